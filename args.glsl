@@ -0,0 +1,14 @@
+// Laid out as `mesh::UniformArgs`.
+layout(set = 0, binding = 0) uniform Args {
+    mat4 proj;
+    mat4 view;
+    float ambient_power;
+    float time;
+    float wetness;
+    float fog_density;
+    vec4 fog_color;
+    vec4 sun_direction;
+    // Fog of war mask, see `vision::uniform`.
+    vec4 vision_area;
+    vec4 vision_size;
+};
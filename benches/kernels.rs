@@ -0,0 +1,71 @@
+//! Timings of the CPU meshing and culling kernels.
+//!
+//! Run with `cargo bench`, add `--features simd` to compare both paths.
+
+use std::time::Instant;
+
+use avenir::chunk::{face_masks, Axis, Chunk, MeshScratch};
+use avenir::culling::{Aabb, Frustum};
+use nalgebra::{Perspective3, Point3};
+use rand::Rng;
+
+fn bench<F: FnMut()>(name: &str, iterations: u32, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    println!("{}: {:?} per iteration", name, start.elapsed() / iterations);
+}
+
+fn main() {
+    let mut rng = rand::thread_rng();
+
+    let mut chunk = Chunk::new();
+    for z in 0..32 {
+        for y in 0..32 {
+            for x in 0..32 {
+                if rng.gen_bool(0.5) {
+                    chunk.set_voxel(x, y, z, 1);
+                }
+            }
+        }
+    }
+    let columns: Vec<_> = Axis::ALL.iter().map(|&axis| chunk.columns(axis)).collect();
+
+    bench("chunk columns", 100, || {
+        for &axis in &Axis::ALL {
+            std::hint::black_box(chunk.columns(axis));
+        }
+    });
+    bench("face masks", 10_000, || {
+        for columns in &columns {
+            std::hint::black_box(face_masks(columns));
+        }
+    });
+
+    let mut scratch = MeshScratch::default();
+    bench("visible faces with scratch", 100, || {
+        std::hint::black_box(scratch.visible_faces(&chunk));
+    });
+
+    let frustum = Frustum::from_matrix(
+        &Perspective3::new(16.0 / 9.0, std::f32::consts::FRAC_PI_3, 0.1, 1000.0).to_homogeneous(),
+    );
+    let aabbs: Vec<_> = (0..100_000)
+        .map(|_| {
+            let min = Point3::new(
+                rng.gen_range(-500.0, 500.0),
+                rng.gen_range(-500.0, 500.0),
+                rng.gen_range(-1000.0, 0.0),
+            );
+            Aabb::new(min, min + nalgebra::Vector3::repeat(1.0))
+        })
+        .collect();
+    let mut visible = Vec::with_capacity(aabbs.len());
+
+    bench("frustum cull 100k boxes", 100, || {
+        visible.clear();
+        frustum.cull_aabbs(&aabbs, &mut visible);
+        std::hint::black_box(&visible);
+    });
+}
@@ -0,0 +1,103 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 ndc;
+layout(location = 0) out vec4 color;
+
+layout(push_constant) uniform Clouds {
+    mat4 inv_view_proj;
+    // Camera position in render space.
+    vec4 eye;
+    // Direction toward the sun, w is its intensity.
+    vec4 sun;
+    float time;
+    float height;
+    float coverage;
+    // 1 for the flat layer, 2 for the raymarched volume.
+    uint mode;
+};
+
+const float THICKNESS = 60.0;
+const float SCALE = 0.004;
+const vec2 WIND = vec2(0.02, 0.008);
+// Samples along the view ray, see `config::ShaderTuning::cloud_steps`.
+layout(constant_id = 0) const int STEPS = 24;
+
+#include "color.glsl"
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+float noise(vec2 p) {
+    vec2 i = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f * f * (3.0 - 2.0 * f);
+    return mix(
+        mix(hash(i), hash(i + vec2(1.0, 0.0)), u.x),
+        mix(hash(i + vec2(0.0, 1.0)), hash(i + vec2(1.0, 1.0)), u.x),
+        u.y
+    );
+}
+
+float fbm(vec2 p) {
+    float value = 0.0;
+    float amplitude = 0.5;
+    for (int i = 0; i < 5; i++) {
+        value += amplitude * noise(p);
+        p *= 2.03;
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+// Cloud density at a point, the volume is thickest in the middle of the slab.
+float density(vec3 p) {
+    float base = smoothstep(1.0 - coverage, 1.0, fbm(p.xz * SCALE + WIND * time));
+    float y = clamp((p.y - height) / THICKNESS, 0.0, 1.0);
+    return base * 4.0 * y * (1.0 - y);
+}
+
+void main() {
+    vec4 far = inv_view_proj * vec4(ndc, 1.0, 1.0);
+    vec3 dir = normalize(far.xyz / far.w - eye.xyz);
+    if (abs(dir.y) < 0.001) {
+        discard;
+    }
+    // The volume is entered from its top when seen from above.
+    float plane = mode == 2 && eye.y > height ? height + THICKNESS : height;
+    float t = (plane - eye.y) / dir.y;
+    if (t <= 0.0) {
+        discard;
+    }
+    vec3 hit = eye.xyz + dir * t;
+    float sun_light = sun.w * clamp(sun.y * 2.0 + 0.2, 0.0, 1.0);
+    vec3 lit = mix(vec3(0.45, 0.47, 0.52), vec3(1.0, 0.98, 0.95), sun_light);
+
+    float alpha;
+    if (mode == 1) {
+        alpha = smoothstep(1.0 - coverage, 1.0, fbm(hit.xz * SCALE + WIND * time));
+        // Lighter on the side facing the sun.
+        lit *= 0.85 + 0.15 * max(dot(normalize(sun.xyz), -dir), 0.0);
+    } else {
+        float step_length = THICKNESS / max(abs(dir.y), 0.2) / float(STEPS);
+        float transmittance = 1.0;
+        float shade = 0.0;
+        for (int i = 0; i < STEPS && transmittance > 0.05; i++) {
+            vec3 p = hit + dir * step_length * (float(i) + 0.5);
+            float d = density(p);
+            if (d > 0.0) {
+                // Density toward the sun darkens the sample.
+                float occlusion = density(p + normalize(sun.xyz) * THICKNESS * 0.25);
+                float absorbed = transmittance * (1.0 - exp(-d * step_length * 0.05));
+                shade += absorbed * exp(-occlusion * 2.0);
+                transmittance -= absorbed;
+            }
+        }
+        alpha = 1.0 - transmittance;
+        lit *= alpha > 0.0 ? 0.5 + 0.5 * shade / alpha : 1.0;
+    }
+    // Fade toward the horizon.
+    alpha *= exp(-t * 0.0004);
+    color = vec4(encode_output(lit), alpha);
+}
@@ -0,0 +1,38 @@
+// Colors are linear from the vertices to the blending, as in `color.rs`.
+// The surface encodes them when it has an sRGB format, otherwise
+// `encode_output` does before they are written.
+
+// Encoding of the surface, see `color::OutputEncoding::constant`: zero
+// leaves the colors linear, a negative value encodes them with the sRGB
+// curve and a positive one with that gamma.
+layout(constant_id = 15) const float OUTPUT_GAMMA = 0.0;
+
+vec3 srgb_to_linear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), greaterThan(c, vec3(0.04045)));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, greaterThan(c, vec3(0.0031308)));
+}
+
+// Linear `c` as written to the surface. Blending then happens on encoded
+// colors, close enough for the surfaces without an sRGB format.
+vec3 encode_output(vec3 c) {
+    c = max(c, vec3(0.0));
+    if (OUTPUT_GAMMA < 0.0) {
+        return linear_to_srgb(c);
+    } else if (OUTPUT_GAMMA > 0.0) {
+        return pow(c, vec3(1.0 / OUTPUT_GAMMA));
+    }
+    return c;
+}
+
+// Linear color of `c` read back from the surface, undoing `encode_output`.
+vec3 decode_output(vec3 c) {
+    if (OUTPUT_GAMMA < 0.0) {
+        return srgb_to_linear(c);
+    } else if (OUTPUT_GAMMA > 0.0) {
+        return pow(c, vec3(OUTPUT_GAMMA));
+    }
+    return c;
+}
@@ -0,0 +1,40 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec4 color;
+
+// Texels of the retained frame, 8 bits per channel.
+layout(std430, set = 0, binding = 0) readonly buffer Retained {
+    uint texels[];
+};
+
+layout(push_constant) uniform Fade {
+    // Retained frame size over the framebuffer size.
+    vec2 scale;
+    uint width;
+    uint height;
+    // Opacity of the retained frame.
+    float alpha;
+    // Channels stored blue first.
+    uint bgra;
+    // Channels stored sRGB encoded.
+    uint srgb;
+};
+
+#include "color.glsl"
+
+void main() {
+    ivec2 texel = ivec2(gl_FragCoord.xy * scale);
+    texel = clamp(texel, ivec2(0), ivec2(width, height) - 1);
+    vec4 retained = unpackUnorm4x8(texels[uint(texel.y) * width + uint(texel.x)]);
+    if (bgra != 0) {
+        retained = retained.bgra;
+    }
+    // Channels stored by an sRGB surface or encoded by the shaders.
+    if (srgb != 0) {
+        retained.rgb = srgb_to_linear(retained.rgb);
+    } else {
+        retained.rgb = decode_output(retained.rgb);
+    }
+    color = vec4(encode_output(retained.rgb), alpha);
+}
@@ -41,7 +41,15 @@ use rendy::{
 #[macro_use]
 extern crate log;
 
-use avenir::{camera::Camera, graph, Inputs};
+use avenir::{
+    camera::Camera,
+    coords::Location,
+    input::{ActionMap, InputState},
+    plugin::PluginHost,
+    renderer::RendererBuilder,
+    scene::Scene,
+    transform::Transform,
+};
 use env_logger;
 use nalgebra::Point3;
 
@@ -57,39 +65,55 @@ type Backend = rendy::dx12::Backend;
 #[cfg(feature = "empty")]
 type Backend = rendy::empty::Backend;
 
+#[cfg(feature = "gl")]
+type Backend = rendy::gl::Backend;
+
 const WIDTH: u32 = 3840;
 const HEIGHT: u32 = 2160;
 
 #[allow(dead_code)] // Bug in rust-analyzer.
 fn run<B: hal::Backend>(
     event_loop: EventLoop<()>,
-    mut factory: Factory<B>,
-    mut families: Families<B>,
+    factory: Factory<B>,
+    families: Families<B>,
     surface: Surface<B>,
     window: Window,
 ) {
     let mut frame = 0u64;
-    let mut cam = Camera::look_at(
+    let mut scene = Scene::new(Camera::look_at(
         10.0,
         Point3::new(0.0, 0.0, -10.0),
         Point3::new(0.0, 0.0, 0.0),
         WIDTH as f32 / HEIGHT as f32,
-    );
-    let mut inputs: Inputs = Inputs::default();
-    let mut graph =
-        Some(graph::build(&mut families, &window, &mut factory, surface, &cam).unwrap());
+    ));
+    scene.add_instance(Location::default(), Transform::identity());
+    let plugins = PluginHost::<B>::load(Vec::new()).unwrap();
+    let actions = ActionMap::default();
+    let mut inputs = InputState::new();
+    let mut renderer = RendererBuilder::new()
+        .with_scene(scene)
+        .build(
+            factory,
+            families,
+            surface,
+            &window,
+            plugins.registry.render_passes(),
+        )
+        .unwrap();
 
     let started = std::time::Instant::now();
     let mut checkpoint = started;
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
+        // Resizes update the camera aspect ratio and rebuild the graph.
+        if let Err(err) = renderer.handle_event(&event, &window, plugins.registry.render_passes()) {
+            error!("{}", err);
+            *control_flow = ControlFlow::Exit;
+        }
         match event {
             Event::DeviceEvent { ref event, .. } => match *event {
-                DeviceEvent::MouseMotion { delta: (x, y) } => {
-                    inputs.mouse_x = x;
-                    inputs.mouse_y = y;
-                }
+                DeviceEvent::MouseMotion { delta: (x, y) } => inputs.mouse_motion(x, y),
                 _ => {}
             },
             Event::WindowEvent { event, .. } => match event {
@@ -97,59 +121,50 @@ fn run<B: hal::Backend>(
                 WindowEvent::Resized(size) => {
                     info!("Window Resized {:?}.", size);
                 }
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(virtual_code),
-                            state,
-                            ..
-                        },
-                    ..
-                } => match (virtual_code, state) {
-                    (VirtualKeyCode::A, ElementState::Pressed) => inputs.left = true,
-                    (VirtualKeyCode::A, ElementState::Released) => inputs.left = false,
-                    (VirtualKeyCode::S, ElementState::Pressed) => inputs.back = true,
-                    (VirtualKeyCode::S, ElementState::Released) => inputs.back = false,
-                    (VirtualKeyCode::D, ElementState::Pressed) => inputs.right = true,
-                    (VirtualKeyCode::D, ElementState::Released) => inputs.right = false,
-                    (VirtualKeyCode::W, ElementState::Pressed) => inputs.front = true,
-                    (VirtualKeyCode::W, ElementState::Released) => inputs.front = false,
-                    (VirtualKeyCode::L, ElementState::Pressed) => cam.ambient_power += 0.1,
-                    (VirtualKeyCode::K, ElementState::Pressed) => cam.ambient_power -= 0.1,
-                    _ => {}
-                },
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if inputs.handle_key(&actions, &input).is_none() {
+                        match (input.virtual_keycode, input.state) {
+                            (Some(VirtualKeyCode::L), ElementState::Pressed) => {
+                                renderer.scene.camera.ambient_power += 0.1
+                            }
+                            (Some(VirtualKeyCode::K), ElementState::Pressed) => {
+                                renderer.scene.camera.ambient_power -= 0.1
+                            }
+                            _ => {}
+                        }
+                    }
+                }
                 _ => {}
             },
             Event::MainEventsCleared => {
-                factory.maintain(&mut families);
-                if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &cam);
-                    frame += 1;
-                }
+                renderer.render();
+                frame += 1;
                 let elapsed = checkpoint.elapsed();
                 // Print fps
                 // let elapsed_ns = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
                 // info!("FPS: {} delta: {}", frame * 1_000_000_000 / elapsed_ns, elapsed.as_secs_f32());
                 frame = 0;
                 checkpoint += elapsed;
-                cam.run(&inputs, elapsed.as_secs_f32());
-                inputs.mouse_x = 0.0;
-                inputs.mouse_y = 0.0;
+                renderer.scene.time = started.elapsed().as_secs_f32();
+                renderer.scene.weather.update(elapsed.as_secs_f32());
+                renderer.scene.camera_effects.update(elapsed.as_secs_f32());
+                renderer
+                    .scene
+                    .camera
+                    .apply_movement(&inputs.movement(), elapsed.as_secs_f32());
+                renderer.scene.update_origin();
+                inputs.clear_motion();
             }
             Event::RedrawRequested(_) => {
-                if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &cam);
-                    frame += 1;
-                }
+                renderer.render();
+                frame += 1;
 
                 info!("Request redraw.");
             }
             _ => {}
         }
         if *control_flow == ControlFlow::Exit {
-            if let Some(graph) = graph.take() {
-                graph.dispose(&mut factory, &cam);
-            }
+            renderer.shutdown();
         }
     });
 }
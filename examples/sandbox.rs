@@ -0,0 +1,677 @@
+//! Interactive voxel sandbox exercising the public API end to end.
+//!
+//! WASD and the mouse fly the camera. Left click breaks the aimed block,
+//! right click places the selected hotbar block and 1 to 5 select it. F5
+//! saves the edited chunks to `sandbox_save/`, they are loaded back on the
+//! next start. Hold T to speed up the day.
+//!
+//! `--benchmark <file>` flies the camera along a fixed path instead, from a
+//! world without the saved edits, and writes the statistics of every frame
+//! to the file, JSON if it ends with `.json` and CSV otherwise.
+//!
+//! `--script <file>` runs the `on_update(dt)` function of a Rhai script
+//! every frame, with the `scripting` feature. The blocks it sets aren't
+//! saved.
+//!
+//! Chunks stream in and out around the camera, generated by the plugin
+//! world generation pass. New and edited chunks are meshed again every
+//! frame into the chunk meshes of the scene. The terrain beyond them is the
+//! horizon mesh, sampled from the same height function. Stone and dirt are
+//! drawn with generated noise textures. The aimed block is marked with an
+//! instance.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rendy::{
+    command::Families,
+    factory::{Config, Factory},
+    hal,
+    init::{
+        winit::{
+            dpi::LogicalSize,
+            event::{
+                DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode,
+                WindowEvent,
+            },
+            event_loop::{ControlFlow, EventLoop},
+            window::{Window, WindowBuilder},
+        },
+        AnyWindowedRendy,
+    },
+    wsi::Surface,
+};
+
+#[macro_use]
+extern crate log;
+
+use avenir::{
+    block_textures::{BlockTextures, LAYER_SIZE},
+    chunk::{Chunk, MeshScratch, VoxelId, AIR},
+    chunk_meshes::ChunkMeshes,
+    color::Color,
+    coords::{ChunkCoord, Location, WorldPos, CHUNK_SIZE},
+    horizon::HorizonSettings,
+    input::{InputEvent, Key},
+    jobs::{JobConfig, JobSystem},
+    material::Texture,
+    plugin::{BlockDesc, BlockRegistry, Plugin, PluginHost, RegisterError, Registry},
+    prelude::*,
+    profiler::Format,
+    storage::{ChunkCodec, RegionCoord, RegionSaver},
+    timestep::FixedTimestep,
+    vertex::AnimFlags,
+};
+use nalgebra::{Point3, Vector3};
+
+#[cfg(feature = "metal")]
+type Backend = rendy::metal::Backend;
+
+#[cfg(feature = "vulkan")]
+type Backend = rendy::vulkan::Backend;
+
+#[cfg(feature = "dx12")]
+type Backend = rendy::dx12::Backend;
+
+#[cfg(feature = "empty")]
+type Backend = rendy::empty::Backend;
+
+#[cfg(feature = "gl")]
+type Backend = rendy::gl::Backend;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+
+const TICK_LENGTH: f32 = 1.0 / 60.0;
+
+/// Chunks loaded around the camera, horizontally.
+const STREAM_RADIUS: i64 = 4;
+
+/// Chunk layers the terrain spans, from y = 0.
+const LAYERS: i64 = 4;
+
+/// Reach of the block picking, in voxels.
+const REACH: f32 = 8.0;
+
+/// In game hours per real second.
+const DAY_SPEED: f32 = 0.02;
+
+const SAVE_DIRECTORY: &str = "sandbox_save";
+
+/// Seconds of simulation the `--benchmark` path lasts.
+const BENCHMARK_SECONDS: f32 = 30.0;
+
+/// Speed of the camera along the benchmark path, in voxels per second.
+const BENCHMARK_SPEED: f32 = 24.0;
+
+/// Height the benchmark path flies at, above the highest hills.
+const BENCHMARK_HEIGHT: i64 = 110;
+
+const HOTBAR: [&str; 5] = [
+    "sandbox:stone",
+    "sandbox:dirt",
+    "sandbox:grass",
+    "sandbox:leaves",
+    "sandbox:glow",
+];
+
+/// Surface height of a column, shared by the world generation and the horizon.
+fn terrain_height(x: i64, z: i64) -> i64 {
+    let (x, z) = (x as f32, z as f32);
+    let hills = (x * 0.021).sin() * (z * 0.017).cos() * 18.0;
+    let ridges = ((x + z) * 0.0043).sin() * 30.0;
+    (48.0 + hills + ridges) as i64
+}
+
+fn terrain_color(height: i64) -> Color {
+    if height > 70 {
+        Color::rgb(0.5, 0.5, 0.52)
+    } else {
+        Color::rgb(0.2, 0.45, 0.15)
+    }
+}
+
+/// Grey noise over the block colors, different for each `seed`.
+fn noise_texture(seed: u32) -> Texture {
+    let texels = (0..LAYER_SIZE * LAYER_SIZE)
+        .map(|i| {
+            let hash = (i ^ seed.wrapping_mul(0x2545_f491)).wrapping_mul(0x9e37_79b9) >> 24;
+            let value = (190 + hash * 65 / 255) as u8;
+            u32::from_le_bytes([value, value, value, 255])
+        })
+        .collect();
+    Texture {
+        width: LAYER_SIZE,
+        height: LAYER_SIZE,
+        texels,
+    }
+}
+
+/// Camera location along the benchmark path after `time` seconds, a
+/// straight flight toward -Z the camera starts looking at.
+fn benchmark_location(time: f32) -> Location {
+    let z = -(time * BENCHMARK_SPEED) as i64;
+    Location::from_voxel(WorldPos::new(0, BENCHMARK_HEIGHT, z))
+}
+
+/// Output file of `--benchmark <file>` and script of `--script <file>`.
+fn parse_args() -> (Option<PathBuf>, Option<PathBuf>) {
+    let mut benchmark = None;
+    let mut script = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--benchmark", Some(path)) => benchmark = Some(PathBuf::from(path)),
+            ("--script", Some(path)) => script = Some(PathBuf::from(path)),
+            (_, value) => warn!("Ignored argument `{}` {:?}.", arg, value),
+        }
+    }
+    (benchmark, script)
+}
+
+/// Script at `path`, with its top level statements run.
+#[cfg(feature = "scripting")]
+fn load_script(path: PathBuf) -> Option<avenir::script::ScriptHost> {
+    let loaded = std::fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| {
+            let mut script = avenir::script::ScriptHost::new();
+            script.load(&source).map(|()| script)
+        });
+    match loaded {
+        Ok(script) => Some(script),
+        Err(err) => {
+            error!("Failed to load the script {}: {}.", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Blocks and world generation of the sandbox.
+struct SandboxPlugin;
+
+impl<B: hal::Backend> Plugin<B> for SandboxPlugin {
+    fn name(&self) -> &str {
+        "sandbox"
+    }
+
+    fn register(&self, registry: &mut Registry<B>) -> Result<(), RegisterError> {
+        let blocks = [
+            (
+                "sandbox:stone",
+                Color::rgb(0.5, 0.5, 0.52),
+                AnimFlags::NONE,
+                Some("sandbox:stone"),
+            ),
+            (
+                "sandbox:dirt",
+                Color::rgb(0.4, 0.28, 0.15),
+                AnimFlags::NONE,
+                Some("sandbox:dirt"),
+            ),
+            (
+                "sandbox:grass",
+                Color::rgb(0.2, 0.45, 0.15),
+                AnimFlags::NONE,
+                None,
+            ),
+            (
+                "sandbox:leaves",
+                Color::rgb(0.15, 0.35, 0.1),
+                AnimFlags::SWAY,
+                None,
+            ),
+            (
+                "sandbox:glow",
+                Color::rgb(1.0, 0.85, 0.4),
+                AnimFlags::NONE,
+                None,
+            ),
+        ];
+        for &(name, color, animation, texture) in &blocks {
+            registry.register_block(BlockDesc {
+                name: name.to_owned(),
+                color,
+                solid: true,
+                texture: texture.map(str::to_owned),
+                animation,
+            })?;
+        }
+        registry.register_worldgen(|coord, chunk, blocks| {
+            let id = |name| blocks.id(name).unwrap_or(AIR);
+            let (stone, dirt, grass) =
+                (id("sandbox:stone"), id("sandbox:dirt"), id("sandbox:grass"));
+            let min = coord.min_voxel();
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let height = terrain_height(min.x + x, min.z + z);
+                    for y in 0..CHUNK_SIZE {
+                        let depth = height - (min.y + y);
+                        let block = match depth {
+                            d if d < 0 => continue,
+                            0 => grass,
+                            1..=3 => dirt,
+                            _ => stone,
+                        };
+                        chunk.set_voxel(x as usize, y as usize, z as usize, block);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Loaded chunks and the edits made to them.
+struct Sandbox {
+    world: World,
+
+    /// Chunks edited by the player, kept loaded and saved by `save`.
+    edited: HashSet<ChunkCoord>,
+    saver: RegionSaver,
+    scratch: MeshScratch,
+}
+
+impl Sandbox {
+    /// Sandbox without edits.
+    fn new(saver: RegionSaver) -> Self {
+        Sandbox {
+            world: World::default(),
+            edited: HashSet::new(),
+            saver,
+            scratch: MeshScratch::default(),
+        }
+    }
+
+    /// Load the chunks saved by a previous session.
+    fn load(saver: RegionSaver) -> Self {
+        let mut sandbox = Sandbox::new(saver);
+        let entries = match std::fs::read_dir(SAVE_DIRECTORY) {
+            Ok(entries) => entries,
+            Err(_) => return sandbox,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let region = match parse_region_name(&name) {
+                Some(region) => region,
+                None => continue,
+            };
+            match sandbox.saver.load(region) {
+                Ok(chunks) => {
+                    for (coord, chunk) in chunks {
+                        sandbox.world.insert_chunk(coord, chunk);
+                        sandbox.edited.insert(coord);
+                    }
+                }
+                Err(err) => error!("Failed to load {}: {}.", name, err),
+            }
+        }
+        info!("Loaded {} edited chunks.", sandbox.edited.len());
+        sandbox
+    }
+
+    /// Generate the missing chunks around `center` and unload the far ones
+    /// along with their meshes.
+    fn stream<B: hal::Backend>(
+        &mut self,
+        center: ChunkCoord,
+        registry: &Registry<B>,
+        meshes: &mut ChunkMeshes,
+    ) {
+        for z in center.z - STREAM_RADIUS..=center.z + STREAM_RADIUS {
+            for x in center.x - STREAM_RADIUS..=center.x + STREAM_RADIUS {
+                for y in 0..LAYERS {
+                    let coord = ChunkCoord::new(x, y, z);
+                    if self.world.chunk(&coord).is_none() {
+                        let mut chunk = Chunk::new();
+                        registry.generate(coord, &mut chunk);
+                        self.world.insert_chunk(coord, chunk);
+                    }
+                }
+            }
+        }
+        // Unedited chunks are generated again identically when needed.
+        let far: Vec<_> = self
+            .world
+            .chunks()
+            .map(|(coord, _)| *coord)
+            .filter(|coord| {
+                (coord.x - center.x).abs() > STREAM_RADIUS + 1
+                    || (coord.z - center.z).abs() > STREAM_RADIUS + 1
+            })
+            .filter(|coord| !self.edited.contains(coord))
+            .collect();
+        for coord in far {
+            self.world.remove_chunk(&coord);
+            meshes.remove(&coord);
+        }
+    }
+
+    /// Mesh the chunks generated, loaded or edited since the last call.
+    fn mesh(
+        &mut self,
+        blocks: &BlockRegistry,
+        textures: &BlockTextures,
+        meshes: &mut ChunkMeshes,
+    ) -> usize {
+        let style = |id| blocks.style(id, textures);
+        self.world
+            .mesh_dirty_chunks(&mut self.scratch, style, meshes)
+    }
+
+    fn set_block(&mut self, pos: &WorldPos, id: VoxelId) {
+        if self.world.set_voxel(pos, id) {
+            self.edited.insert(pos.chunk());
+        }
+    }
+
+    /// Queue the save of every edited chunk, whole regions at once.
+    fn save(&self) {
+        let world = &self.world;
+        self.saver.save(
+            self.edited
+                .iter()
+                .filter_map(|coord| world.chunk(coord).map(|chunk| (*coord, chunk.clone()))),
+        );
+        info!("Saving {} edited chunks.", self.edited.len());
+    }
+}
+
+/// Region of a `RegionCoord::file_name`.
+fn parse_region_name(name: &str) -> Option<RegionCoord> {
+    let parts: Vec<_> = name.split('.').collect();
+    match parts[..] {
+        ["r", x, y, z, "avr"] => Some(RegionCoord {
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+            z: z.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+fn key_of(code: VirtualKeyCode) -> Option<Key> {
+    match code {
+        VirtualKeyCode::A => Some(Key::Left),
+        VirtualKeyCode::S => Some(Key::Back),
+        VirtualKeyCode::D => Some(Key::Right),
+        VirtualKeyCode::W => Some(Key::Front),
+        VirtualKeyCode::Space => Some(Key::Up),
+        VirtualKeyCode::LShift => Some(Key::Down),
+        _ => None,
+    }
+}
+
+/// The window title stands in for a HUD.
+fn hud(window: &Window, selected: usize, time_of_day: f32) {
+    let hotbar: Vec<_> = HOTBAR
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let name = name.trim_start_matches("sandbox:");
+            if i == selected {
+                format!("[{}]", name)
+            } else {
+                name.to_owned()
+            }
+        })
+        .collect();
+    window.set_title(&format!(
+        "Avenir sandbox | {} | {:02}:{:02}",
+        hotbar.join(" "),
+        time_of_day as u32,
+        (time_of_day.fract() * 60.0) as u32
+    ));
+}
+
+fn run<B: hal::Backend>(
+    event_loop: EventLoop<()>,
+    factory: Factory<B>,
+    families: Families<B>,
+    surface: Surface<B>,
+    window: Window,
+) {
+    let plugins: Vec<Box<dyn Plugin<B>>> = vec![Box::new(SandboxPlugin)];
+    let plugins = PluginHost::load(plugins).unwrap_or_else(|(plugin, err)| {
+        error!("Plugin {} failed to register: {}.", plugin, err);
+        std::process::exit(1)
+    });
+    let hotbar: Vec<VoxelId> = HOTBAR
+        .iter()
+        .filter_map(|name| plugins.registry.blocks.id(name))
+        .collect();
+
+    let jobs = Arc::new(JobSystem::new(&JobConfig::default()));
+    if let Err(err) = std::fs::create_dir_all(SAVE_DIRECTORY) {
+        error!("Failed to create {}: {}.", SAVE_DIRECTORY, err);
+    }
+    let (benchmark, script) = parse_args();
+    #[cfg(feature = "scripting")]
+    let mut script = script.and_then(load_script);
+    #[cfg(not(feature = "scripting"))]
+    {
+        if let Some(path) = script {
+            warn!(
+                "Ignored the script {}, scripts need the `scripting` feature.",
+                path.display()
+            );
+        }
+    }
+    let saver = RegionSaver::new(SAVE_DIRECTORY, ChunkCodec::default(), jobs.clone());
+    let mut sandbox = if benchmark.is_some() {
+        Sandbox::new(saver)
+    } else {
+        Sandbox::load(saver)
+    };
+
+    let mut scene = Scene::new(Camera::look_at(
+        10.0,
+        Point3::origin(),
+        Point3::new(0.0, -0.3, -1.0),
+        WIDTH as f32 / HEIGHT as f32,
+    ));
+    if benchmark.is_some() {
+        scene.teleport_camera(&benchmark_location(0.0));
+        scene.profiler.start_recording();
+    } else {
+        scene.teleport_camera(&Location::from_voxel(WorldPos::new(
+            0,
+            terrain_height(0, 0) + 10,
+            0,
+        )));
+    }
+    scene.time_of_day = 8.0;
+    for (seed, name) in ["sandbox:stone", "sandbox:dirt"].iter().enumerate() {
+        let texture = noise_texture(seed as u32);
+        scene.block_textures.insert(name, &texture).unwrap();
+    }
+    // Marks the aimed block.
+    let marker = scene.add_instance(
+        Location::default(),
+        Transform::identity().with_uniform_scale(0.2),
+    );
+
+    let mut renderer = RendererBuilder::new()
+        .with_scene(scene)
+        .with_jobs(jobs)
+        .build(
+            factory,
+            families,
+            surface,
+            &window,
+            plugins.registry.render_passes(),
+        )
+        .unwrap_or_else(|err| {
+            error!("{}", err);
+            std::process::exit(1)
+        });
+
+    let mut inputs = Inputs::default();
+    let mut timestep = FixedTimestep::new(TICK_LENGTH);
+    let mut selected = 0;
+    let mut fast_time = false;
+    let mut checkpoint = std::time::Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        if let Err(err) = renderer.handle_event(&event, &window, plugins.registry.render_passes()) {
+            error!("{}", err);
+            *control_flow = ControlFlow::Exit;
+        }
+        match event {
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (x, y) },
+                ..
+            } => inputs.apply(&InputEvent::MouseMotion { x, y }),
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button,
+                    ..
+                } => {
+                    let scene = &renderer.scene;
+                    let forward = scene.camera.view.rotation * -Vector3::z();
+                    let hit = sandbox
+                        .world
+                        .raycast(&scene.camera_location(), &forward, REACH);
+                    match (button, hit) {
+                        (MouseButton::Left, Some(hit)) => sandbox.set_block(&hit.pos, AIR),
+                        (MouseButton::Right, Some(hit)) => {
+                            if let (Some(pos), Some(&id)) = (hit.adjacent(), hotbar.get(selected)) {
+                                sandbox.set_block(&pos, id);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(code),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    let pressed = state == ElementState::Pressed;
+                    if let Some(key) = key_of(code) {
+                        inputs.apply(&InputEvent::Key { key, pressed });
+                    }
+                    match code {
+                        VirtualKeyCode::Key1 => selected = 0,
+                        VirtualKeyCode::Key2 => selected = 1,
+                        VirtualKeyCode::Key3 => selected = 2,
+                        VirtualKeyCode::Key4 => selected = 3,
+                        VirtualKeyCode::Key5 => selected = 4,
+                        VirtualKeyCode::F5 if pressed => sandbox.save(),
+                        VirtualKeyCode::T => fast_time = pressed,
+                        VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            Event::MainEventsCleared => {
+                let elapsed = checkpoint.elapsed();
+                checkpoint += elapsed;
+                let scene = &mut renderer.scene;
+                for _ in 0..timestep.advance(elapsed.as_secs_f32()) {
+                    scene.begin_tick();
+                    if benchmark.is_some() {
+                        let time = timestep.tick() as f32 * TICK_LENGTH;
+                        let location = benchmark_location(time);
+                        scene.camera.view.translation.vector = scene.origin.to_render(&location);
+                    } else {
+                        scene.camera.run(&inputs, TICK_LENGTH);
+                    }
+                    scene.update_origin();
+                    inputs.clear_motion();
+
+                    let speed = if fast_time { 100.0 } else { 1.0 };
+                    scene.time_of_day =
+                        (scene.time_of_day + TICK_LENGTH * DAY_SPEED * speed) % 24.0;
+                    let sun = scene.sun_direction().y;
+                    scene.camera.ambient_power = 0.25 + 0.75 * sun.max(0.0);
+                    scene.weather.update(TICK_LENGTH);
+                }
+                scene.interpolation = timestep.alpha();
+                scene.time = timestep.tick() as f32 * TICK_LENGTH;
+                #[cfg(feature = "scripting")]
+                {
+                    if let Some(ref mut script) = script {
+                        let dt = elapsed.as_secs_f32();
+                        if let Err(err) = script.update(dt, &mut sandbox.world, scene) {
+                            error!("Script failed: {}.", err);
+                        }
+                    }
+                }
+
+                let eye = scene.camera_location();
+                sandbox.stream(eye.chunk, &plugins.registry, &mut scene.chunk_meshes);
+                let meshed = sandbox.mesh(
+                    &plugins.registry.blocks,
+                    &scene.block_textures,
+                    &mut scene.chunk_meshes,
+                );
+                if meshed > 0 {
+                    debug!("Meshed {} chunks.", meshed);
+                }
+                scene.update_horizon(HorizonSettings::default(), |x, z| {
+                    let height = terrain_height(x, z);
+                    (height as f32, terrain_color(height))
+                });
+
+                let forward = scene.camera.view.rotation * -Vector3::z();
+                if let Some(hit) = sandbox.world.raycast(&eye, &forward, REACH) {
+                    let mut location = Location::from_voxel(hit.pos);
+                    location.translate(&Vector3::new(0.5, 0.5, 0.5));
+                    scene.instances[marker].location = location;
+                }
+                hud(&window, selected, scene.time_of_day);
+                renderer.render();
+
+                let done = timestep.tick() as f32 * TICK_LENGTH >= BENCHMARK_SECONDS;
+                if let (Some(path), true) = (&benchmark, done) {
+                    let profiler = &renderer.scene.profiler;
+                    match profiler.export(path, Format::from_path(path)) {
+                        Ok(()) => info!(
+                            "Benchmark of {} frames written to {}.",
+                            profiler.recorded_frames(),
+                            path.display()
+                        ),
+                        Err(err) => error!("Failed to write {}: {}.", path.display(), err),
+                    }
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            _ => {}
+        }
+        if *control_flow == ControlFlow::Exit {
+            sandbox.save();
+            for err in sandbox.saver.flush() {
+                error!("Save failed: {}.", err);
+            }
+            renderer.shutdown();
+        }
+    });
+}
+
+fn main() {
+    env_logger::init();
+
+    let config: Config = Default::default();
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size(LogicalSize::new(WIDTH, HEIGHT))
+        .with_title("Avenir sandbox");
+
+    let rendy = AnyWindowedRendy::init_auto(&config, window, &event_loop).unwrap();
+    rendy::with_any_windowed_rendy!((rendy)
+    use back;
+    (factory, families, surface, window) => {
+        window.set_cursor_grab(true);
+        run(event_loop, factory, families, surface, window)
+    });
+}
@@ -0,0 +1,110 @@
+//! Trains the dictionary of `ChunkCodec::default` on the sandbox terrain.
+//!
+//! `cargo run --example train_dictionary [file]` writes it to `file`,
+//! `data/chunks.dict` by default. Region files saved with the previous
+//! dictionary can't be loaded with the new one.
+//!
+//! Only the chunks crossing the surface are sampled, the empty and full
+//! ones compress to a few bytes anyway.
+
+use avenir::{
+    chunk::{Chunk, VoxelId, AIR},
+    coords::{ChunkCoord, CHUNK_SIZE},
+    storage::{train_dictionary, ChunkCodec, DEFAULT_LEVEL},
+};
+
+#[macro_use]
+extern crate log;
+
+/// Largest dictionary.
+const MAX_SIZE: usize = 64 * 1024;
+
+/// Chunks sampled around the origin, horizontally.
+const RADIUS: i64 = 12;
+
+/// Chunk layers of the sandbox terrain, from y = 0.
+const LAYERS: i64 = 4;
+
+/// Ids the sandbox registers its blocks with.
+const STONE: VoxelId = 1;
+const DIRT: VoxelId = 2;
+const GRASS: VoxelId = 3;
+
+/// Surface height of a column, as in the sandbox.
+fn terrain_height(x: i64, z: i64) -> i64 {
+    let (x, z) = (x as f32, z as f32);
+    let hills = (x * 0.021).sin() * (z * 0.017).cos() * 18.0;
+    let ridges = ((x + z) * 0.0043).sin() * 30.0;
+    (48.0 + hills + ridges) as i64
+}
+
+/// Chunk at `coord` as the sandbox world generation fills it.
+fn generate(coord: ChunkCoord) -> Chunk {
+    let mut chunk = Chunk::new();
+    let min = coord.min_voxel();
+    for z in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            let height = terrain_height(min.x + x, min.z + z);
+            for y in 0..CHUNK_SIZE {
+                let block = match height - (min.y + y) {
+                    d if d < 0 => continue,
+                    0 => GRASS,
+                    1..=3 => DIRT,
+                    _ => STONE,
+                };
+                chunk.set_voxel(x as usize, y as usize, z as usize, block);
+            }
+        }
+    }
+    chunk
+}
+
+fn main() {
+    env_logger::init();
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "data/chunks.dict".to_owned());
+
+    let mut chunks = Vec::new();
+    for z in -RADIUS..RADIUS {
+        for x in -RADIUS..RADIUS {
+            for y in 0..LAYERS {
+                let chunk = generate(ChunkCoord::new(x, y, z));
+                let voxels = chunk.voxels();
+                if voxels.contains(&AIR) && voxels.iter().any(|&id| id != AIR) {
+                    chunks.push(chunk);
+                }
+            }
+        }
+    }
+    let dictionary = train_dictionary(&chunks, MAX_SIZE).unwrap_or_else(|err| {
+        error!("Training failed: {}.", err);
+        std::process::exit(1)
+    });
+
+    let codec = ChunkCodec::new(DEFAULT_LEVEL).with_dictionary(dictionary.clone());
+    let plain = ChunkCodec::new(DEFAULT_LEVEL);
+    let size = |codec: &ChunkCodec| -> usize {
+        let mut encoder = codec.encoder();
+        chunks
+            .iter()
+            .map(|chunk| encoder.encode(chunk).map_or(0, |data| data.len()))
+            .sum()
+    };
+    info!(
+        "{} chunks take {} bytes with the dictionary, {} without.",
+        chunks.len(),
+        size(&codec),
+        size(&plain)
+    );
+
+    if let Err(err) = std::fs::write(&path, &dictionary) {
+        error!("Failed to write {}: {}.", path, err);
+        std::process::exit(1);
+    }
+    info!(
+        "Dictionary of {} bytes written to {}.",
+        dictionary.len(),
+        path
+    );
+}
@@ -0,0 +1,5 @@
+// Blend `color` toward the `fog` color with the `distance` to the eye, for
+// an exponential fog of `density`.
+vec3 apply_fog(vec3 color, vec3 fog, float density, float distance) {
+    return mix(color, fog, 1.0 - exp(-density * distance));
+}
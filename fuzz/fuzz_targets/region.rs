@@ -0,0 +1,10 @@
+//! Region files must be rejected with an error, never panic or allocate
+//! more than a full region.
+#![no_main]
+
+use avenir::storage::{parse_region, ChunkCodec};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_region(data, &ChunkCodec::default());
+});
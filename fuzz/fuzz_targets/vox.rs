@@ -0,0 +1,10 @@
+//! `.vox` files must be rejected with an error, never panic or allocate
+//! more than their size allows.
+#![no_main]
+
+use avenir::vox_loader::parse_vox_models;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_vox_models(data);
+});
@@ -0,0 +1,30 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+layout(location = 2) in vec3 normal;
+layout(location = 0) out vec4 frag_color;
+
+layout(push_constant) uniform Horizon {
+    // Projection extended to the horizon, times the view rotation.
+    mat4 view_proj;
+    // Mesh center relative to the eye, w is the ambient power.
+    vec4 offset;
+    // Direction toward the sun, w is the fog density.
+    vec4 sun;
+    vec4 fog_color;
+};
+
+#include "fog.glsl"
+
+void main() {
+    vec3 pos = position + offset.xyz;
+    gl_Position = view_proj * vec4(pos, 1.0);
+    // On the far plane, behind everything the scene drew.
+    gl_Position.z = gl_Position.w;
+
+    float light = 0.4 + 0.6 * max(dot(normal, normalize(sun.xyz)), 0.0);
+    vec3 lit = color.rgb * light * offset.w;
+    frag_color = vec4(apply_fog(lit, fog_color.rgb, sun.w, length(pos)), 1.0);
+}
@@ -0,0 +1,33 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Camera {
+    mat4 view_proj;
+    vec4 right;
+    vec4 up;
+    // Ambient power, view resolution and number of views.
+    vec4 params;
+};
+
+// RGBA8 texels, sRGB encoded, the views side by side, rows from the top.
+layout(std430, set = 0, binding = 0) readonly buffer Atlas {
+    uint texels[];
+};
+
+#include "color.glsl"
+
+layout(location = 0) in vec2 frag_uv;
+layout(location = 1) flat in uint frag_view;
+layout(location = 0) out vec4 color;
+
+void main() {
+    uint resolution = uint(params.y);
+    uint views = uint(params.z);
+    uvec2 texel = min(uvec2(frag_uv * float(resolution)), uvec2(resolution - 1));
+    uint row = resolution - 1 - texel.y;
+    vec4 texel_color = unpackUnorm4x8(texels[(row * views + frag_view) * resolution + texel.x]);
+    if (texel_color.a < 0.5) {
+        discard;
+    }
+    color = vec4(encode_output(srgb_to_linear(texel_color.rgb) * params.x), 1.0);
+}
@@ -0,0 +1,39 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Camera {
+    mat4 view_proj;
+    // Camera axes in render space, the quads face the camera.
+    vec4 right;
+    vec4 up;
+    // Ambient power, view resolution and number of views.
+    vec4 params;
+};
+
+struct Draw {
+    // Center in render space and radius.
+    vec4 sphere;
+    // Captured view in x.
+    uvec4 view;
+};
+
+layout(std430, set = 0, binding = 1) readonly buffer Draws {
+    Draw draws[];
+};
+
+layout(location = 0) out vec2 frag_uv;
+layout(location = 1) flat out uint frag_view;
+
+const vec2 CORNERS[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+void main() {
+    Draw draw = draws[gl_InstanceIndex];
+    vec2 corner = CORNERS[gl_VertexIndex];
+    vec3 pos = draw.sphere.xyz + (right.xyz * corner.x + up.xyz * corner.y) * draw.sphere.w;
+    frag_uv = corner * 0.5 + 0.5;
+    frag_view = draw.view.x;
+    gl_Position = view_proj * vec4(pos, 1.0);
+}
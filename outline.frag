@@ -0,0 +1,25 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 frag_color;
+layout(location = 1) in float frag_distance;
+layout(location = 2) in float frag_facing;
+layout(location = 0) out vec4 color;
+
+layout(push_constant) uniform Constants {
+    mat4 view_proj;
+    // Camera position, the fog density in w.
+    vec4 eye;
+    vec4 fog_color;
+};
+
+#include "color.glsl"
+#include "fog.glsl"
+
+void main() {
+    // The front of the hull would hide the object, only its back is kept.
+    if (frag_facing > 0.0) {
+        discard;
+    }
+    color = vec4(encode_output(apply_fog(frag_color, fog_color.rgb, eye.w, frag_distance)), 1.0);
+}
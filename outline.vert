@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+layout(location = 2) in vec3 normal;
+layout(location = 3) in uint anim_flags;
+// vec4[4] is used instead of mat4 due to spirv-cross bug for dx12 backend
+layout(location = 4) in vec4 model[4]; // per-instance.
+// Outline color, the width in w.
+layout(location = 8) in vec4 outline; // per-instance.
+
+layout(push_constant) uniform Constants {
+    mat4 view_proj;
+    // Camera position, the fog density in w.
+    vec4 eye;
+    vec4 fog_color;
+};
+
+layout(location = 0) out vec3 frag_color;
+layout(location = 1) out float frag_distance;
+// Positive on the hull faces turned towards the camera.
+layout(location = 2) out float frag_facing;
+
+void main() {
+    mat4 model_mat = mat4(model[0], model[1], model[2], model[3]);
+    vec3 world_normal = normalize(mat3(model_mat) * normal);
+    vec4 pos = model_mat * vec4(position * 100, 1.0);
+    pos.xyz += world_normal * outline.w;
+    vec3 to_eye = eye.xyz - pos.xyz;
+    frag_color = outline.rgb;
+    frag_distance = length(to_eye);
+    frag_facing = dot(world_normal, to_eye);
+    gl_Position = view_proj * pos;
+}
@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec4 color;
+
+layout(push_constant) uniform Precipitation {
+    float time;
+    float rain;
+    float snow;
+    float overcast;
+};
+
+#include "color.glsl"
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+// One drop per grid cell of `cell_size` pixels, falling at `speed` cells
+// per second. `density` is the fraction of cells with a drop.
+float drops(vec2 cell_size, float speed, float density) {
+    vec2 p = gl_FragCoord.xy / cell_size;
+    p.y -= time * speed;
+    vec2 cell = floor(p);
+    float h = hash(cell);
+    if (h > density) {
+        return 0.0;
+    }
+    vec2 center = vec2(0.2 + 0.6 * hash(cell + 17.0), fract(h * 7.0));
+    return smoothstep(0.25, 0.0, length((fract(p) - center) * cell_size) / cell_size.x);
+}
+
+void main() {
+    float streaks = drops(vec2(6.0, 48.0), 3.0, rain) * rain;
+    float flakes = drops(vec2(14.0, 14.0), 0.4, snow) * snow;
+    float haze = overcast * 0.25;
+
+    vec3 haze_color = vec3(0.35, 0.37, 0.4);
+    vec3 drop_color = streaks > flakes ? vec3(0.7, 0.75, 0.8) : vec3(1.0);
+    float drop = max(streaks * 0.5, flakes * 0.9);
+    vec3 rgb = mix(haze_color, drop_color, drop / max(max(drop, haze), 0.001));
+    color = vec4(encode_output(rgb), max(drop, haze));
+}
@@ -6,14 +6,147 @@ layout(early_fragment_tests) in;
 layout(location = 0) in vec4 in_pos;
 layout(location = 1) in vec3 frag_norm;
 layout(location = 2) in vec4 frag_color;
+layout(location = 3) in vec4 frag_emissive;
+layout(location = 4) flat in uint frag_damage;
+layout(location = 5) in vec2 frag_face;
+layout(location = 6) flat in uint frag_texture;
+layout(location = 7) in vec2 frag_uv;
 layout(location = 0) out vec4 color;
 
-layout(set = 0, binding = 0) uniform Args {
-    mat4 proj;
-    mat4 view;
-    float ambient_power;
+#include "args.glsl"
+#include "color.glsl"
+#include "fog.glsl"
+
+// Visibility of the mask cells, a byte each.
+layout(set = 0, binding = 1) readonly buffer Vision {
+    uint vision_cells[];
+};
+
+// Texels of the block textures, `LAYER_SIZE` squared per layer, 8 bits
+// per channel, sRGB encoded. See `block_textures::BlockTextures`.
+layout(set = 0, binding = 2) readonly buffer BlockTextures {
+    uint block_texels[];
 };
 
+const uint LAYER_SIZE = 16;
+
+// Shading model and toon ramp bands, see `material::ShadingModel`.
+layout(constant_id = 0) const uint SHADING_MODEL = 0;
+layout(constant_id = 1) const uint TOON_BANDS = 0;
+
+const uint SHADING_LIT = 0;
+const uint SHADING_LAMBERT = 1;
+const uint SHADING_UNLIT = 2;
+const uint SHADING_TOON = 3;
+
+// Light of the sky reaching surfaces facing away from the sun, see
+// `config::ShaderTuning::ambient`.
+layout(constant_id = 2) const float AMBIENT = 0.35;
+
+// Highest damage stage, see `chunk::DAMAGE_STAGES`.
+const float MAX_DAMAGE = 9.0;
+
+vec2 hash(vec2 p) {
+    return fract(sin(vec2(dot(p, vec2(127.1, 311.7)), dot(p, vec2(269.5, 183.3)))) * 43758.5453);
+}
+
+// Distance to the nearest border between the cells of a jittered grid.
+float cell_border(vec2 p) {
+    vec2 cell = floor(p);
+    float first = 8.0;
+    float second = 8.0;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            vec2 neighbour = cell + vec2(x, y);
+            float d = length(neighbour + hash(neighbour) - p);
+            second = max(first, min(second, d));
+            first = min(first, d);
+        }
+    }
+    return second - first;
+}
+
+// Darkening of the cracks of a damaged voxel, wider at each stage.
+float cracks(vec2 face, uint damage) {
+    if (damage == 0u) {
+        return 0.0;
+    }
+    float stage = float(damage) / MAX_DAMAGE;
+    float border = cell_border(face * 3.0);
+    return (1.0 - smoothstep(0.0, 0.02 + 0.1 * stage, border)) * (0.4 + 0.4 * stage);
+}
+
+// Linear color of the block texture `layer` plus one at `uv`, repeated
+// every voxel, white without a texture.
+vec4 block_texture(vec2 uv, uint layer) {
+    if (layer == 0u) {
+        return vec4(1.0);
+    }
+    // Rows of the layers go down.
+    vec2 tile = vec2(fract(uv.x), 1.0 - fract(uv.y));
+    uvec2 texel = min(uvec2(tile * float(LAYER_SIZE)), uvec2(LAYER_SIZE - 1u));
+    uint i = (layer - 1u) * LAYER_SIZE * LAYER_SIZE + texel.y * LAYER_SIZE + texel.x;
+    vec4 texel_color = unpackUnorm4x8(block_texels[i]);
+    return vec4(srgb_to_linear(texel_color.rgb), texel_color.a);
+}
+
+// Light reaching the surface, before the color.
+vec3 shade(vec3 normal, float roughness) {
+    if (SHADING_MODEL == SHADING_UNLIT) {
+        return vec3(1.0);
+    }
+    float diffuse = max(dot(normal, sun_direction.xyz), 0.0);
+    if (SHADING_MODEL == SHADING_TOON) {
+        float bands = float(max(TOON_BANDS, 1u));
+        diffuse = min(floor(diffuse * bands + 0.5), bands) / bands;
+    }
+    float light = AMBIENT + (1.0 - AMBIENT) * diffuse;
+    if (SHADING_MODEL != SHADING_LIT) {
+        return vec3(light * ambient_power);
+    }
+    // Blinn-Phong highlight, sharper and brighter on smooth surfaces. In
+    // view space, where the eye is at the origin.
+    vec3 to_eye = -normalize((view * in_pos).xyz);
+    vec3 half_way = normalize(to_eye + mat3(view) * sun_direction.xyz);
+    float smoothness = 1.0 - clamp(roughness, 0.0, 1.0);
+    float shininess = exp2(1.0 + 10.0 * smoothness);
+    float specular = pow(max(dot(mat3(view) * normal, half_way), 0.0), shininess) * smoothness;
+    return vec3(light + specular * step(0.0, diffuse)) * ambient_power;
+}
+
+// Visibility of a mask cell, unexplored outside the mask.
+float vision_cell(ivec2 cell) {
+    ivec2 size = ivec2(vision_size.xy);
+    if (any(lessThan(cell, ivec2(0))) || any(greaterThanEqual(cell, size))) {
+        return 0.0;
+    }
+    uint i = uint(cell.y * size.x + cell.x);
+    uint byte = (vision_cells[i >> 2] >> ((i & 3u) * 8u)) & 0xffu;
+    return float(byte) / 255.0;
+}
+
+// Visibility at a position, filtered between the cell centers.
+float vision(vec3 pos) {
+    if (vision_size.z == 0.0) {
+        return 1.0;
+    }
+    vec2 p = (pos.xz - vision_area.xy) / vision_area.z - 0.5;
+    ivec2 cell = ivec2(floor(p));
+    vec2 t = p - floor(p);
+    float low = mix(vision_cell(cell), vision_cell(cell + ivec2(1, 0)), t.x);
+    float high = mix(vision_cell(cell + ivec2(0, 1)), vision_cell(cell + ivec2(1, 1)), t.x);
+    return mix(low, high, t.y);
+}
+
 void main() {
-    color = frag_color * vec4(frag_norm * ambient_power, 1.0);
+    color = frag_color * block_texture(frag_uv, frag_texture);
+    color.rgb *= shade(normalize(frag_norm), frag_emissive.w);
+    color.rgb *= 1.0 - cracks(frag_face, frag_damage);
+    // Wet surfaces are darker.
+    color.rgb *= 1.0 - 0.35 * wetness;
+    color.rgb += frag_emissive.rgb;
+    float dist = length((view * in_pos).xyz);
+    color.rgb = apply_fog(color.rgb, fog_color.rgb, fog_density, dist);
+    color.rgb *= vision(in_pos.xyz);
+    color.rgb = encode_output(color.rgb);
 }
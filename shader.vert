@@ -4,23 +4,60 @@
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec4 color;
 layout(location = 2) in vec3 normal;
+layout(location = 3) in uint anim_flags;
 // vec4[4] is used instead of mat4 due to spirv-cross bug for dx12 backend
-layout(location = 3) in vec4 model[4]; // per-instance.
+layout(location = 4) in vec4 model[4]; // per-instance.
+layout(location = 8) in vec4 tint; // per-instance.
+// Emissive color, the roughness multiplier in w.
+layout(location = 9) in vec4 emissive; // per-instance.
 
-layout(set = 0, binding = 0) uniform Args {
-    mat4 proj;
-    mat4 view;
-    float ambient_power;
-};
+#include "args.glsl"
+
+const uint ANIM_SWAY = 1;
+// Damage stage of the voxel, see `meshing::DAMAGE_SHIFT`.
+const uint DAMAGE_SHIFT = 8;
+const uint DAMAGE_MASK = 0xf;
+// Block texture layer plus one, see `meshing::TEXTURE_SHIFT`.
+const uint TEXTURE_SHIFT = 16;
+const uint TEXTURE_MASK = 0x1ff;
+
+// Horizontal wind offset, in phase for vertices close to each other.
+vec3 sway(vec3 pos) {
+    float phase = time * 2.0 + pos.x * 0.7 + pos.z * 0.3;
+    return vec3(sin(phase), 0.0, cos(phase * 0.8)) * 0.08;
+}
 
 layout(location = 0) out vec4 frag_pos;
 layout(location = 1) out vec3 frag_norm;
 layout(location = 2) out vec4 frag_color;
+// Emissive color, the roughness in w.
+layout(location = 3) out vec4 frag_emissive;
+layout(location = 4) flat out uint frag_damage;
+// Position on the face, in voxels, for the cracks.
+layout(location = 5) out vec2 frag_face;
+layout(location = 6) flat out uint frag_texture;
+// Texture coordinates, in voxels, up along the sides.
+layout(location = 7) out vec2 frag_uv;
 
 void main() {
     mat4 model_mat = mat4(model[0], model[1], model[2], model[3]);
-    frag_color = color;
+    frag_color = color * tint;
+    frag_emissive = emissive;
+    frag_damage = (anim_flags >> DAMAGE_SHIFT) & DAMAGE_MASK;
+    vec3 axis = abs(normal);
+    frag_face = axis.x > 0.5 ? position.yz : axis.y > 0.5 ? position.xz : position.xy;
+    frag_texture = (anim_flags >> TEXTURE_SHIFT) & TEXTURE_MASK;
+    frag_uv = axis.x > 0.5 ? position.zy : axis.y > 0.5 ? position.xz : position.xy;
     frag_norm = normalize((vec4(normal, 1.0) * model_mat).xyz);
-    frag_pos = model_mat * vec4(position * 100, 1.0);
+#ifdef SKINNED
+    // Skinned by the compute pre-pass, already at model scale.
+    vec3 pos = position;
+#else
+    vec3 pos = position * 100;
+#endif
+    if ((anim_flags & ANIM_SWAY) != 0) {
+        pos += sway(pos);
+    }
+    frag_pos = model_mat * vec4(pos, 1.0);
     gl_Position = proj * view * frag_pos;
 }
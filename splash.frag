@@ -0,0 +1,52 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec4 color;
+
+// Texels of the logo, 8 bits per channel, sRGB encoded.
+layout(std430, set = 0, binding = 0) readonly buffer Logo {
+    uint texels[];
+};
+
+layout(push_constant) uniform Splash {
+    // Filled and unfilled parts of the progress bar.
+    vec4 bar;
+    vec4 track;
+    // Framebuffer size.
+    vec2 size;
+    // Logo size in texels, zero without a logo.
+    uint width;
+    uint height;
+    // Loaded fraction, from 0 to 1.
+    float progress;
+};
+
+#include "color.glsl"
+
+void main() {
+    vec2 pos = gl_FragCoord.xy;
+
+    // Progress bar, centered at three quarters of the height.
+    vec2 bar_size = vec2(size.x * 0.4, max(4.0, size.y * 0.01));
+    vec2 bar_min = vec2(size.x * 0.5, size.y * 0.75) - bar_size * 0.5;
+    vec2 local = (pos - bar_min) / bar_size;
+    if (all(greaterThanEqual(local, vec2(0.0))) && all(lessThan(local, vec2(1.0)))) {
+        color = local.x < progress ? bar : track;
+        color.rgb = encode_output(color.rgb);
+        return;
+    }
+
+    // Logo, centered above the bar and scaled down to fit.
+    if (width == 0 || height == 0) {
+        discard;
+    }
+    vec2 logo = vec2(width, height);
+    float scale = min(1.0, min(size.x * 0.8 / logo.x, size.y * 0.4 / logo.y));
+    vec2 logo_min = vec2(size.x * 0.5, size.y * 0.4) - logo * scale * 0.5;
+    ivec2 texel = ivec2(floor((pos - logo_min) / scale));
+    if (any(lessThan(texel, ivec2(0))) || any(greaterThanEqual(texel, ivec2(width, height)))) {
+        discard;
+    }
+    vec4 texel_color = unpackUnorm4x8(texels[uint(texel.y) * width + uint(texel.x)]);
+    color = vec4(encode_output(srgb_to_linear(texel_color.rgb)), texel_color.a);
+}
@@ -0,0 +1,221 @@
+//! Quality settings adjusted to hold a frame rate.
+//!
+//! `AdaptiveQuality` is given the duration of each frame. While frames are
+//! slower than the target it lowers the view distance, then the resolution
+//! scale, then the shadow resolution, and raises them back in the reverse
+//! order once frames have time to spare. Each setting stays within the
+//! `QualityBounds` chosen by the user.
+//!
+//! The frame times are smoothed and judged only some frames after the last
+//! change, longer after the changes rebuilding the graph, so the cost of
+//! the rebuild and the frames drawn at the old quality don't count.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::config::QualitySettings;
+
+/// Weight of each new frame time in the smoothed one.
+const SMOOTHING: f32 = 0.1;
+
+/// Frames measured after a change before judging the next one.
+const SETTLE_FRAMES: u32 = 30;
+
+/// Seconds ignored after a change rebuilding the graph.
+const REBUILD_SETTLE: f32 = 2.0;
+
+/// Factor of the view distance for each change.
+const VIEW_DISTANCE_STEP: f32 = 0.8;
+
+/// Resolution scale added or removed by each change.
+const RESOLUTION_STEP: f32 = 0.125;
+
+/// Range each adjusted setting is kept in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QualityBounds {
+    pub min_view_distance: f32,
+    pub max_view_distance: f32,
+    pub min_resolution_scale: f32,
+    pub max_resolution_scale: f32,
+    pub min_shadow_resolution: u32,
+    pub max_shadow_resolution: u32,
+}
+
+impl Default for QualityBounds {
+    fn default() -> Self {
+        QualityBounds {
+            min_view_distance: 200.0,
+            max_view_distance: 800.0,
+            min_resolution_scale: 0.5,
+            max_resolution_scale: 1.0,
+            min_shadow_resolution: 1024,
+            max_shadow_resolution: 2048,
+        }
+    }
+}
+
+/// Setting changed by `AdaptiveQuality::update`, with its new value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QualityChange {
+    ViewDistance(f32),
+    ResolutionScale(f32),
+    ShadowResolution(u32),
+}
+
+impl QualityChange {
+    /// Whether the renderer rebuilds its graph for the change, see
+    /// `RendererConfig::needs_rebuild`.
+    pub fn needs_rebuild(&self) -> bool {
+        match self {
+            QualityChange::ViewDistance(_) => false,
+            QualityChange::ResolutionScale(_) | QualityChange::ShadowResolution(_) => true,
+        }
+    }
+}
+
+impl fmt::Display for QualityChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QualityChange::ViewDistance(distance) => write!(f, "view distance {}", distance),
+            QualityChange::ResolutionScale(scale) => write!(f, "resolution scale {}", scale),
+            QualityChange::ShadowResolution(size) => write!(f, "shadow resolution {}", size),
+        }
+    }
+}
+
+/// Adjusts the quality settings to frame times.
+#[derive(Debug, Clone)]
+pub struct AdaptiveQuality {
+    /// Frame time to hold.
+    pub target: Duration,
+
+    pub bounds: QualityBounds,
+
+    /// Part of the target frames may take in excess before the quality is
+    /// lowered.
+    pub tolerance: f32,
+
+    /// Part of the target frames must take less to raise the quality,
+    /// larger than `tolerance` so a raise isn't undone right away.
+    pub headroom: f32,
+
+    smoothed: f32,
+    frames: u32,
+
+    /// Seconds left to ignore after a rebuild.
+    wait: f32,
+}
+
+impl AdaptiveQuality {
+    /// Controller holding `target` frame time within the default bounds.
+    pub fn new(target: Duration) -> Self {
+        AdaptiveQuality {
+            target,
+            bounds: QualityBounds::default(),
+            tolerance: 0.1,
+            headroom: 0.3,
+            smoothed: 0.0,
+            frames: 0,
+            wait: 0.0,
+        }
+    }
+
+    /// Controller holding `fps` frames per second.
+    pub fn with_frame_rate(fps: f32) -> Self {
+        AdaptiveQuality::new(Duration::from_secs_f32(1.0 / fps))
+    }
+
+    pub fn with_bounds(mut self, bounds: QualityBounds) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    /// Smoothed frame time, in seconds, zero right after a change.
+    pub fn frame_time(&self) -> f32 {
+        self.smoothed
+    }
+
+    /// Measure a frame which took `frame_time`, changing one setting of
+    /// `quality` if it's time to. Apply the returned change with
+    /// `RendererConfig::set_quality`.
+    pub fn update(
+        &mut self,
+        frame_time: Duration,
+        quality: &mut QualitySettings,
+    ) -> Option<QualityChange> {
+        let seconds = frame_time.as_secs_f32();
+        if self.wait > 0.0 {
+            self.wait -= seconds;
+            return None;
+        }
+        self.smoothed = if self.frames == 0 {
+            seconds
+        } else {
+            self.smoothed + (seconds - self.smoothed) * SMOOTHING
+        };
+        self.frames += 1;
+        if self.frames < SETTLE_FRAMES {
+            return None;
+        }
+
+        let target = self.target.as_secs_f32();
+        let change = if self.smoothed > target * (1.0 + self.tolerance) {
+            self.lower(quality)
+        } else if self.smoothed < target * (1.0 - self.headroom) {
+            self.raise(quality)
+        } else {
+            None
+        };
+        if let Some(change) = change {
+            self.frames = 0;
+            self.smoothed = 0.0;
+            if change.needs_rebuild() {
+                self.wait = REBUILD_SETTLE;
+            }
+        }
+        change
+    }
+
+    fn lower(&self, quality: &mut QualitySettings) -> Option<QualityChange> {
+        let bounds = &self.bounds;
+        let distance = (quality.view_distance * VIEW_DISTANCE_STEP).max(bounds.min_view_distance);
+        if distance < quality.view_distance {
+            quality.view_distance = distance;
+            return Some(QualityChange::ViewDistance(distance));
+        }
+        let scale = (quality.resolution_scale - RESOLUTION_STEP).max(bounds.min_resolution_scale);
+        if scale < quality.resolution_scale {
+            quality.resolution_scale = scale;
+            return Some(QualityChange::ResolutionScale(scale));
+        }
+        let size = (quality.shadow_resolution / 2).max(bounds.min_shadow_resolution);
+        if size < quality.shadow_resolution {
+            quality.shadow_resolution = size;
+            return Some(QualityChange::ShadowResolution(size));
+        }
+        None
+    }
+
+    fn raise(&self, quality: &mut QualitySettings) -> Option<QualityChange> {
+        let bounds = &self.bounds;
+        let size = quality
+            .shadow_resolution
+            .saturating_mul(2)
+            .min(bounds.max_shadow_resolution);
+        if size > quality.shadow_resolution {
+            quality.shadow_resolution = size;
+            return Some(QualityChange::ShadowResolution(size));
+        }
+        let scale = (quality.resolution_scale + RESOLUTION_STEP).min(bounds.max_resolution_scale);
+        if scale > quality.resolution_scale {
+            quality.resolution_scale = scale;
+            return Some(QualityChange::ResolutionScale(scale));
+        }
+        let distance = (quality.view_distance / VIEW_DISTANCE_STEP).min(bounds.max_view_distance);
+        if distance > quality.view_distance {
+            quality.view_distance = distance;
+            return Some(QualityChange::ViewDistance(distance));
+        }
+        None
+    }
+}
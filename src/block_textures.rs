@@ -0,0 +1,210 @@
+//! Textures of the blocks, drawn over the vertex colors of the chunk meshes.
+//!
+//! gfx-hal 0.4 has no descriptor indexing, the block textures can't be
+//! bound as one array of images indexed per vertex. Each texture is
+//! resampled to `LAYER_SIZE` texels square instead, a layer of one storage
+//! buffer shared by every block. The blocky mesher writes the layer of each
+//! face in the vertex flags, see `meshing::texture_flags`, so a chunk stays
+//! a single draw whatever the number of textures of its blocks. Faces
+//! repeat the texture once per voxel.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rendy::factory::Factory;
+use rendy::hal::{self, adapter::PhysicalDevice};
+use rendy::resource::BufferInfo;
+
+use crate::mapped::MappedBuffer;
+use crate::material::Texture;
+use crate::scene::Scene;
+
+/// Side of the layers, in texels.
+pub const LAYER_SIZE: u32 = 16;
+
+/// Texels of a layer.
+pub const LAYER_TEXELS: usize = (LAYER_SIZE * LAYER_SIZE) as usize;
+
+/// Most layers, the size of the buffer on the device.
+pub const MAX_BLOCK_TEXTURES: usize = 256;
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Error returned when a texture is refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockTextureError {
+    /// The texture has no texels, or fewer than its size.
+    Empty,
+
+    /// Every layer is taken.
+    Full,
+}
+
+impl fmt::Display for BlockTextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockTextureError::Empty => write!(f, "the texture has no texels"),
+            BlockTextureError::Full => {
+                write!(f, "more than {} block textures", MAX_BLOCK_TEXTURES)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockTextureError {}
+
+/// Layers of the block textures by name.
+#[derive(Debug, Clone)]
+pub struct BlockTextures {
+    /// `LAYER_TEXELS` sRGB encoded texels per layer, as in `Texture`.
+    texels: Vec<u32>,
+    layers: HashMap<String, u32>,
+    generation: u64,
+}
+
+impl BlockTextures {
+    pub fn new() -> Self {
+        BlockTextures {
+            texels: Vec::new(),
+            layers: HashMap::new(),
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Resample `texture` to a layer named `name` and return the layer,
+    /// replacing the texture of a layer of that name.
+    pub fn insert(&mut self, name: &str, texture: &Texture) -> Result<u32, BlockTextureError> {
+        let texels = resample(texture).ok_or(BlockTextureError::Empty)?;
+        let layer = match self.layers.get(name) {
+            Some(&layer) => layer,
+            None if self.layers.len() == MAX_BLOCK_TEXTURES => {
+                return Err(BlockTextureError::Full);
+            }
+            None => {
+                let layer = self.layers.len() as u32;
+                self.texels.resize(self.texels.len() + LAYER_TEXELS, 0);
+                self.layers.insert(name.to_owned(), layer);
+                layer
+            }
+        };
+        let start = layer as usize * LAYER_TEXELS;
+        self.texels[start..start + LAYER_TEXELS].copy_from_slice(&texels);
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        Ok(layer)
+    }
+
+    /// Layer of the texture named `name`.
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.layers.get(name).cloned()
+    }
+
+    /// Texels of every layer, one after the other.
+    pub fn texels(&self) -> &[u32] {
+        &self.texels
+    }
+
+    /// Different after every change, tells the pipelines to upload them.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of layers.
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl Default for BlockTextures {
+    fn default() -> Self {
+        BlockTextures::new()
+    }
+}
+
+/// Texels of `texture` resized to a layer with the nearest texel, `None`
+/// if it has none.
+pub fn resample(texture: &Texture) -> Option<Vec<u32>> {
+    let (width, height) = (texture.width as usize, texture.height as usize);
+    if width == 0 || height == 0 || texture.texels.len() < width * height {
+        return None;
+    }
+    let size = LAYER_SIZE as usize;
+    let texels = (0..LAYER_TEXELS)
+        .map(|i| {
+            let (x, y) = (i % size, i / size);
+            // Texel of the texture under the center of the layer texel.
+            let u = (2 * x + 1) * width / (2 * size);
+            let v = (2 * y + 1) * height / (2 * size);
+            texture.texels[v * width + u]
+        })
+        .collect();
+    Some(texels)
+}
+
+const BUFFER_SIZE: u64 = (LAYER_TEXELS * MAX_BLOCK_TEXTURES * 4) as u64;
+
+pub(crate) fn binding(binding: u32) -> hal::pso::DescriptorSetLayoutBinding {
+    hal::pso::DescriptorSetLayoutBinding {
+        binding,
+        ty: hal::pso::DescriptorType::StorageBuffer,
+        count: 1,
+        stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+        immutable_samplers: false,
+    }
+}
+
+/// Block textures of the scene on the device, a copy per frame in flight.
+pub(crate) struct BlockTextureBuffer<B: hal::Backend> {
+    buffer: MappedBuffer<B>,
+    align: u64,
+    /// Generation of the textures copied to each frame.
+    uploaded: Vec<Option<u64>>,
+}
+
+impl<B: hal::Backend> BlockTextureBuffer<B> {
+    pub fn new(factory: &Factory<B>, frames: usize) -> Self {
+        let align = factory
+            .physical()
+            .limits()
+            .min_storage_buffer_offset_alignment;
+        let buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: crate::mesh::iceil(BUFFER_SIZE, align) * frames as u64,
+                usage: hal::buffer::Usage::STORAGE,
+            },
+        );
+        BlockTextureBuffer {
+            buffer,
+            align,
+            uploaded: vec![None; frames],
+        }
+    }
+
+    fn offset(&self, index: usize) -> u64 {
+        crate::mesh::iceil(BUFFER_SIZE, self.align) * index as u64
+    }
+
+    pub fn descriptor(&self, index: usize) -> hal::pso::Descriptor<B> {
+        let offset = self.offset(index);
+        hal::pso::Descriptor::Buffer(self.buffer.raw(), Some(offset)..Some(offset + BUFFER_SIZE))
+    }
+
+    /// Copy the textures of the scene in the region of frame `index`, once
+    /// they changed since the last copy to it.
+    pub fn upload(&mut self, factory: &Factory<B>, index: usize, aux: &Scene) {
+        let textures = &aux.block_textures;
+        if self.uploaded[index] == Some(textures.generation()) {
+            return;
+        }
+        let offset = self.offset(index);
+        unsafe {
+            self.buffer.write(factory, offset, textures.texels());
+        }
+        self.uploaded[index] = Some(textures.generation());
+    }
+}
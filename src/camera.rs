@@ -1,5 +1,41 @@
-use nalgebra::{Isometry3, Perspective3, UnitQuaternion, Vector3};
+use std::fmt;
+
 use crate::Inputs;
+use nalgebra::{Isometry3, Perspective3, UnitQuaternion, Vector3};
+
+/// Projection parameter refused by the setters of `Camera`, which keep the
+/// previous value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CameraError {
+    /// Vertical field of view outside `0.0..PI` radians.
+    InvalidFov(f32),
+    /// Aspect ratio not positive or not finite.
+    InvalidAspect(f32),
+    /// Near plane not positive or far plane not finite and past the near one.
+    InvalidNearFar(f32, f32),
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CameraError::InvalidFov(fovy) => write!(f, "invalid field of view {}", fovy),
+            CameraError::InvalidAspect(aspect) => write!(f, "invalid aspect ratio {}", aspect),
+            CameraError::InvalidNearFar(near, far) => {
+                write!(f, "invalid clip planes {}..{}", near, far)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CameraError {}
+
+fn check_fov(fovy: f32) -> Result<(), CameraError> {
+    if fovy > 0.0 && fovy < std::f32::consts::PI {
+        Ok(())
+    } else {
+        Err(CameraError::InvalidFov(fovy))
+    }
+}
 
 /// Represent a configurable camera in 3D.
 pub struct Camera {
@@ -9,6 +45,9 @@ pub struct Camera {
     /// The rotation sensitivity, often linked to mouse movement.
     pub sensitivity: f64,
 
+    /// Rotation speed of `CameraMovement::yaw` and `pitch`, in radians per second.
+    pub turn_speed: f32,
+
     /// View matrix, represent Camera position and rotation.
     pub view: Isometry3<f32>,
 
@@ -17,6 +56,26 @@ pub struct Camera {
 
     /// Test TODO: Remove
     pub ambient_power: f32,
+
+    /// Field of view change in progress, see `transition_fov`.
+    fov_transition: Option<FovTransition>,
+}
+
+/// Eased change of the field of view, for sprint and zoom effects.
+#[derive(Debug, Copy, Clone)]
+struct FovTransition {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl FovTransition {
+    fn current(&self) -> f32 {
+        let t = (self.elapsed / self.duration).min(1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+        self.from + (self.to - self.from) * eased
+    }
 }
 
 impl Camera {
@@ -30,57 +89,158 @@ impl Camera {
         Camera {
             speed,
             sensitivity: 0.01,
+            turn_speed: 2.0,
             view: nalgebra::Isometry3::look_at_rh(&eye, &target, &Vector3::y()),
             proj: Perspective3::new(aspect, std::f32::consts::FRAC_PI_3, 1.0, 400.0),
             ambient_power: 1.0,
+            fov_transition: None,
+        }
+    }
+
+    /// Vertical field of view, in radians.
+    pub fn fov(&self) -> f32 {
+        self.proj.fovy()
+    }
+
+    /// Set the vertical field of view, in radians, stopping any transition.
+    pub fn set_fov(&mut self, fovy: f32) -> Result<(), CameraError> {
+        check_fov(fovy)?;
+        self.fov_transition = None;
+        self.proj.set_fovy(fovy);
+        Ok(())
+    }
+
+    /// Move the field of view to `fovy` over `duration` seconds, eased in
+    /// and out. Advanced by `update_fov`.
+    pub fn transition_fov(&mut self, fovy: f32, duration: f32) -> Result<(), CameraError> {
+        if duration <= 0.0 {
+            return self.set_fov(fovy);
+        }
+        check_fov(fovy)?;
+        self.fov_transition = Some(FovTransition {
+            from: self.fov(),
+            to: fovy,
+            duration,
+            elapsed: 0.0,
+        });
+        Ok(())
+    }
+
+    /// Whether a `transition_fov` hasn't reached its target yet.
+    pub fn is_fov_transitioning(&self) -> bool {
+        self.fov_transition.is_some()
+    }
+
+    /// Advance the field of view transition, called by `run`.
+    pub fn update_fov(&mut self, delta_sec: f32) {
+        if let Some(mut transition) = self.fov_transition.take() {
+            transition.elapsed += delta_sec;
+            self.proj.set_fovy(transition.current());
+            if transition.elapsed < transition.duration {
+                self.fov_transition = Some(transition);
+            }
+        }
+    }
+
+    /// Width over height of the view.
+    pub fn aspect(&self) -> f32 {
+        self.proj.aspect()
+    }
+
+    /// Set the width over height of the view, after the window is resized.
+    ///
+    /// `Scene::update_aspect` sets it from the viewport of the scene.
+    pub fn set_aspect(&mut self, aspect: f32) -> Result<(), CameraError> {
+        if !(aspect > 0.0 && aspect.is_finite()) {
+            return Err(CameraError::InvalidAspect(aspect));
         }
+        self.proj.set_aspect(aspect);
+        Ok(())
     }
 
-    /// Provide input to update camera. TODO: Decouple inputs and Camera.
+    /// Distance of the near plane.
+    pub fn near(&self) -> f32 {
+        self.proj.znear()
+    }
+
+    /// Distance of the far plane.
+    pub fn far(&self) -> f32 {
+        self.proj.zfar()
+    }
+
+    /// Set the distances of the near and far planes.
+    ///
+    /// The far plane is also set from the view distance by
+    /// `RendererConfig::apply`.
+    pub fn set_near_far(&mut self, near: f32, far: f32) -> Result<(), CameraError> {
+        if !(near > 0.0 && far > near && far.is_finite()) {
+            return Err(CameraError::InvalidNearFar(near, far));
+        }
+        self.proj.set_znear_and_zfar(near, far);
+        Ok(())
+    }
+
+    /// Move the camera with the keys of `inputs`, see `apply_movement`.
     pub fn run(&mut self, inputs: &Inputs, delta_sec: f32) {
-        let x = if inputs.right && inputs.left {
-            0.0
-        } else if inputs.right {
-            1.0
-        } else if inputs.left {
-            -1.0
-        } else {
-            0.0
-        };
-        let y = if inputs.up && inputs.down {
-            0.0
-        } else if inputs.up {
-            1.0
-        } else if inputs.down {
-            -1.0
-        } else {
-            0.0
-        };
-        let z = if inputs.front && inputs.back {
-            0.0
-        } else if inputs.front {
-            -1.0
-        } else if inputs.back {
-            1.0
-        } else {
-            0.0
-        };
-
-        self.view.rotation *= UnitQuaternion::from_axis_angle(
-            &Vector3::x_axis(),
-            (inputs.mouse_y * self.sensitivity) as f32,
-        );
+        self.apply_movement(&CameraMovement::from(inputs), delta_sec);
+    }
 
-        let q = UnitQuaternion::from_axis_angle(
-            &Vector3::y_axis(),
-            (-inputs.mouse_x * self.sensitivity) as f32,
-        );
-        self.view.rotation = q * self.view.rotation;
+    /// Move and turn the camera for `delta_sec` seconds, advancing the
+    /// field of view transition.
+    pub fn apply_movement(&mut self, movement: &CameraMovement, delta_sec: f32) {
+        self.update_fov(delta_sec);
+        let axis = |value: f32| value.max(-1.0).min(1.0);
+
+        let turn = f64::from(self.turn_speed * delta_sec);
+        let pitch = movement.mouse_y * self.sensitivity + f64::from(axis(movement.pitch)) * turn;
+        self.view.rotation *= UnitQuaternion::from_axis_angle(&Vector3::x_axis(), pitch as f32);
 
-        let translation = Vector3::new(x, y, z);
+        let yaw = movement.mouse_x * self.sensitivity + f64::from(axis(movement.yaw)) * turn;
+        let q = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -yaw as f32);
+        self.view.rotation = q * self.view.rotation;
 
-        let rotation_translation =
-            self.view.rotation * translation * (delta_sec as f32 * self.speed);
+        let translation = Vector3::new(
+            axis(movement.right),
+            axis(movement.up),
+            -axis(movement.forward),
+        );
+        let rotation_translation = self.view.rotation * translation * (delta_sec * self.speed);
         self.view.translation.vector += rotation_translation;
     }
 }
+
+/// Movement of the camera during a frame, from keys, a gamepad or a script.
+///
+/// Axes go from -1.0 to 1.0, they are clamped.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct CameraMovement {
+    /// Toward the right of the view, scaled by `Camera::speed`.
+    pub right: f32,
+    pub up: f32,
+    pub forward: f32,
+
+    /// Turn to the right, scaled by `Camera::turn_speed`.
+    pub yaw: f32,
+
+    /// Turn up, scaled by `Camera::turn_speed`.
+    pub pitch: f32,
+
+    /// Mouse motion, scaled by `Camera::sensitivity`, not clamped.
+    pub mouse_x: f64,
+    pub mouse_y: f64,
+}
+
+impl From<&Inputs> for CameraMovement {
+    fn from(inputs: &Inputs) -> Self {
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        CameraMovement {
+            right: axis(inputs.right, inputs.left),
+            up: axis(inputs.up, inputs.down),
+            forward: axis(inputs.front, inputs.back),
+            yaw: 0.0,
+            pitch: 0.0,
+            mouse_x: inputs.mouse_x,
+            mouse_y: inputs.mouse_y,
+        }
+    }
+}
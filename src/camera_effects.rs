@@ -0,0 +1,160 @@
+//! Procedural shake and recoil drawn on top of the camera.
+//!
+//! Effects only offset the view the frame is drawn from, `Camera::view`
+//! stays where the controller put it so movement, picking and the floating
+//! origin never see them.
+
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector2, Vector3};
+
+/// Strength of the shake at full trauma.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShakeSettings {
+    /// Largest offset of the camera position, in voxels.
+    pub translation: f32,
+
+    /// Largest pitch, yaw and roll, in radians.
+    pub rotation: f32,
+
+    /// Speed of the noise, in cycles per second.
+    pub frequency: f32,
+
+    /// Trauma lost per second.
+    pub decay: f32,
+}
+
+impl Default for ShakeSettings {
+    fn default() -> Self {
+        ShakeSettings {
+            translation: 0.1,
+            rotation: 0.05,
+            frequency: 12.0,
+            decay: 1.2,
+        }
+    }
+}
+
+/// Spring pulling the recoil back to rest.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RecoilSettings {
+    pub stiffness: f32,
+
+    /// 1 returns to rest as fast as possible without overshooting, lower
+    /// values bounce.
+    pub damping_ratio: f32,
+}
+
+impl Default for RecoilSettings {
+    fn default() -> Self {
+        RecoilSettings {
+            stiffness: 150.0,
+            damping_ratio: 0.8,
+        }
+    }
+}
+
+/// Shake and recoil offsets of the drawn view, advanced with `update`.
+#[derive(Debug, Clone)]
+pub struct CameraEffects {
+    pub shake: ShakeSettings,
+    pub recoil: RecoilSettings,
+
+    /// Shake intensity in `0.0..=1.0`, the offsets grow with its square.
+    trauma: f32,
+    time: f32,
+
+    /// Pitch and yaw of the recoil, in radians.
+    recoil_angles: Vector2<f32>,
+    recoil_velocity: Vector2<f32>,
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        CameraEffects {
+            shake: ShakeSettings::default(),
+            recoil: RecoilSettings::default(),
+            trauma: 0.0,
+            time: 0.0,
+            recoil_angles: Vector2::zeros(),
+            recoil_velocity: Vector2::zeros(),
+        }
+    }
+}
+
+/// Longest integration step of the recoil spring.
+const MAX_STEP: f32 = 1.0 / 240.0;
+
+impl CameraEffects {
+    /// Add to the shake intensity, explosions close by add more.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).max(0.0).min(1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Kick the view up by `pitch` and sideways by `yaw` radians per second,
+    /// the spring brings it back.
+    pub fn kick(&mut self, pitch: f32, yaw: f32) {
+        self.recoil_velocity += Vector2::new(pitch, yaw);
+    }
+
+    /// Whether no effect moves the view.
+    pub fn is_idle(&self) -> bool {
+        self.trauma == 0.0
+            && self.recoil_angles.norm_squared() < 1e-10
+            && self.recoil_velocity.norm_squared() < 1e-10
+    }
+
+    pub fn update(&mut self, delta_sec: f32) {
+        self.time += delta_sec;
+        self.trauma = (self.trauma - self.shake.decay * delta_sec).max(0.0);
+
+        let stiffness = self.recoil.stiffness;
+        let damping = 2.0 * self.recoil.damping_ratio * stiffness.sqrt();
+        let steps = (delta_sec / MAX_STEP).ceil().max(1.0);
+        let step = delta_sec / steps;
+        for _ in 0..steps as usize {
+            let acceleration = -self.recoil_angles * stiffness - self.recoil_velocity * damping;
+            self.recoil_velocity += acceleration * step;
+            self.recoil_angles += self.recoil_velocity * step;
+        }
+        if self.is_idle() {
+            self.recoil_angles = Vector2::zeros();
+            self.recoil_velocity = Vector2::zeros();
+        }
+    }
+
+    /// Transform of the drawn view relative to the camera view.
+    pub fn offset(&self) -> Isometry3<f32> {
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.shake.frequency;
+        let translation =
+            Vector3::new(noise(0, t), noise(1, t), noise(2, t)) * (shake * self.shake.translation);
+        let angles =
+            Vector3::new(noise(3, t), noise(4, t), noise(5, t)) * (shake * self.shake.rotation);
+        let rotation = UnitQuaternion::from_euler_angles(
+            angles.x + self.recoil_angles.x,
+            angles.y + self.recoil_angles.y,
+            angles.z,
+        );
+        Isometry3::from_parts(Translation3::from(translation), rotation)
+    }
+}
+
+/// Smooth value noise in `-1.0..=1.0`, one independent curve per `channel`.
+fn noise(channel: u32, t: f32) -> f32 {
+    let cell = t.floor();
+    let f = t - cell;
+    let a = lattice(channel, cell as i32);
+    let b = lattice(channel, cell as i32 + 1);
+    a + (b - a) * f * f * (3.0 - 2.0 * f)
+}
+
+fn lattice(channel: u32, i: i32) -> f32 {
+    let mut h = (i as u32).wrapping_mul(0x9E37_79B1) ^ channel.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
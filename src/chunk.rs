@@ -0,0 +1,419 @@
+//! Voxel storage of one chunk and the bitmask kernels used to mesh it.
+//!
+//! Solid voxels are packed in 64 bit columns, one bit per voxel along an
+//! axis with one padding bit on each side for the neighbouring chunks. Face
+//! visibility of a whole column is then a couple of shifts and masks, done
+//! four columns at a time with the `simd` feature.
+//!
+//! Voxel arrays and mesher buffers are large, a `VoxelPool` and pooled
+//! `MeshScratch` keep them from being reallocated for every chunk.
+//!
+//! Faces can be painted a color of their own and voxels can be damaged,
+//! both kept in sparse overlays next to the voxels since few ever are.
+//!
+//! A chunk remembers whether it changed since `generate_mesh` last meshed
+//! it, see `need_mesh_update`.
+
+use std::collections::BTreeMap;
+
+use crate::color::Color;
+use crate::coords::CHUNK_SIZE;
+use crate::meshing::{mesh_chunk, MeshData, VoxelStyle};
+use crate::pool::Pool;
+
+/// Identifier of a voxel type, `AIR` is empty space.
+pub type VoxelId = u16;
+
+pub const AIR: VoxelId = 0;
+
+const SIZE: usize = CHUNK_SIZE as usize;
+
+/// Number of damage stages, a voxel taking more is destroyed.
+pub const DAMAGE_STAGES: u8 = 10;
+
+/// Number of voxels in a chunk.
+pub const VOLUME: usize = SIZE * SIZE * SIZE;
+
+/// Number of columns along one axis.
+pub const COLUMNS: usize = SIZE * SIZE;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    /// Position of the voxel at `along` in the column `(a, b)` of this axis.
+    ///
+    /// Columns along X are indexed by `(y, z)`, along Y by `(x, z)` and along
+    /// Z by `(x, y)`.
+    pub fn voxel(self, a: usize, b: usize, along: usize) -> (usize, usize, usize) {
+        match self {
+            Axis::X => (along, a, b),
+            Axis::Y => (a, along, b),
+            Axis::Z => (a, b, along),
+        }
+    }
+}
+
+/// Face of a voxel, looking along `axis` toward the positive or the
+/// negative coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Side {
+    pub axis: Axis,
+    pub positive: bool,
+}
+
+impl Side {
+    pub const ALL: [Side; 6] = [
+        Side::new(Axis::X, true),
+        Side::new(Axis::X, false),
+        Side::new(Axis::Y, true),
+        Side::new(Axis::Y, false),
+        Side::new(Axis::Z, true),
+        Side::new(Axis::Z, false),
+    ];
+
+    pub const fn new(axis: Axis, positive: bool) -> Self {
+        Side { axis, positive }
+    }
+
+    /// Position in `ALL`.
+    pub fn index(self) -> usize {
+        self.axis as usize * 2 + !self.positive as usize
+    }
+}
+
+/// Idle voxel arrays of dropped chunks.
+pub type VoxelPool = Pool<Box<[VoxelId]>>;
+
+/// Cubic block of `CHUNK_SIZE` voxels per side.
+#[derive(Clone)]
+pub struct Chunk {
+    voxels: Box<[VoxelId]>,
+
+    /// Painted faces by `voxel index * 6 + Side::index`.
+    paint: BTreeMap<u32, Color>,
+
+    /// Damage stage of the damaged voxels, by index.
+    damage: BTreeMap<u32, u8>,
+
+    /// Changed since the last `generate_mesh`.
+    dirty: bool,
+}
+
+impl Chunk {
+    /// Chunk filled with air.
+    pub fn new() -> Self {
+        Chunk {
+            voxels: vec![AIR; VOLUME].into_boxed_slice(),
+            paint: BTreeMap::new(),
+            damage: BTreeMap::new(),
+            dirty: true,
+        }
+    }
+
+    /// Chunk filled with air, reusing a voxel array from `pool`.
+    pub fn new_in(pool: &VoxelPool) -> Self {
+        let mut voxels = pool.take_or_else(|| vec![AIR; VOLUME].into_boxed_slice());
+        for voxel in voxels.iter_mut() {
+            *voxel = AIR;
+        }
+        Chunk {
+            voxels,
+            paint: BTreeMap::new(),
+            damage: BTreeMap::new(),
+            dirty: true,
+        }
+    }
+
+    /// Give the voxel array back to `pool`.
+    pub fn recycle(self, pool: &VoxelPool) {
+        pool.give(self.voxels);
+    }
+
+    /// Chunk from voxels in `voxels()` order, `None` if the length isn't `VOLUME`.
+    pub fn from_voxels(voxels: Vec<VoxelId>) -> Option<Self> {
+        if voxels.len() == VOLUME {
+            Some(Chunk {
+                voxels: voxels.into_boxed_slice(),
+                paint: BTreeMap::new(),
+                damage: BTreeMap::new(),
+                dirty: true,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Index of a voxel in `voxels()`. Panics out of the chunk, where the
+    /// index would wrap to another voxel instead of failing the slice access.
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        assert!(
+            x < SIZE && y < SIZE && z < SIZE,
+            "voxel ({}, {}, {}) out of the chunk",
+            x,
+            y,
+            z
+        );
+        x + SIZE * (y + SIZE * z)
+    }
+
+    pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> VoxelId {
+        self.voxels[Self::index(x, y, z)]
+    }
+
+    /// Set a voxel, replacing it with another type wipes the paint of its
+    /// faces and its damage.
+    pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, id: VoxelId) {
+        let index = Self::index(x, y, z);
+        if self.voxels[index] != id {
+            if !self.paint.is_empty() {
+                let first = (index * 6) as u32;
+                let faces: Vec<_> = self
+                    .paint
+                    .range(first..first + 6)
+                    .map(|(&key, _)| key)
+                    .collect();
+                for key in faces {
+                    self.paint.remove(&key);
+                }
+            }
+            self.damage.remove(&(index as u32));
+            self.dirty = true;
+        }
+        self.voxels[index] = id;
+    }
+
+    /// Set the damage stage of a voxel, 0 repairs it. Stages are clamped
+    /// below `DAMAGE_STAGES`.
+    pub fn set_damage(&mut self, x: usize, y: usize, z: usize, stage: u8) {
+        let index = Self::index(x, y, z) as u32;
+        self.dirty = true;
+        if stage == 0 {
+            self.damage.remove(&index);
+        } else {
+            self.damage.insert(index, stage.min(DAMAGE_STAGES - 1));
+        }
+    }
+
+    pub fn damage(&self, x: usize, y: usize, z: usize) -> u8 {
+        let index = Self::index(x, y, z) as u32;
+        self.damage.get(&index).copied().unwrap_or(0)
+    }
+
+    /// Damaged voxels in voxel order, with their stage.
+    pub fn damaged_voxels(&self) -> impl Iterator<Item = ((usize, usize, usize), u8)> + '_ {
+        self.damage.iter().map(|(&index, &stage)| {
+            let index = index as usize;
+            ((index % SIZE, index / SIZE % SIZE, index / COLUMNS), stage)
+        })
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        !self.damage.is_empty()
+    }
+
+    fn paint_key(x: usize, y: usize, z: usize, side: Side) -> u32 {
+        (Self::index(x, y, z) * 6 + side.index()) as u32
+    }
+
+    /// Paint a face of a voxel, the color is blended over the voxel color by
+    /// its alpha when meshed.
+    pub fn paint_face(&mut self, x: usize, y: usize, z: usize, side: Side, color: Color) {
+        self.dirty = true;
+        self.paint.insert(Self::paint_key(x, y, z, side), color);
+    }
+
+    /// Remove the paint of a face, returns its color.
+    pub fn clear_face(&mut self, x: usize, y: usize, z: usize, side: Side) -> Option<Color> {
+        let color = self.paint.remove(&Self::paint_key(x, y, z, side));
+        self.dirty |= color.is_some();
+        color
+    }
+
+    pub fn face_paint(&self, x: usize, y: usize, z: usize, side: Side) -> Option<Color> {
+        self.paint.get(&Self::paint_key(x, y, z, side)).copied()
+    }
+
+    /// Painted faces in voxel order, with the position of their voxel.
+    pub fn painted_faces(&self) -> impl Iterator<Item = ((usize, usize, usize), Side, Color)> + '_ {
+        self.paint.iter().map(|(&key, &color)| {
+            let index = key as usize / 6;
+            let position = (index % SIZE, index / SIZE % SIZE, index / COLUMNS);
+            (position, Side::ALL[key as usize % 6], color)
+        })
+    }
+
+    pub fn is_painted(&self) -> bool {
+        !self.paint.is_empty()
+    }
+
+    /// Voxels in x, then y, then z order.
+    pub fn voxels(&self) -> &[VoxelId] {
+        &self.voxels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.iter().all(|&id| id == AIR)
+    }
+
+    /// Whether the voxels, paint or damage changed since the last
+    /// `generate_mesh`, true for a new chunk.
+    pub fn need_mesh_update(&self) -> bool {
+        self.dirty
+    }
+
+    /// Blocky mesh of the chunk into `out`, see `mesh_chunk`, clearing
+    /// `need_mesh_update`. `VoxelVertex::from_mesh_data` turns it into
+    /// vertices for `Mesh::builder`.
+    pub fn generate_mesh<F>(&mut self, scratch: &mut MeshScratch, style: F, out: &mut MeshData)
+    where
+        F: Fn(VoxelId) -> VoxelStyle,
+    {
+        mesh_chunk(self, scratch, style, out);
+        self.dirty = false;
+    }
+
+    /// Clear `need_mesh_update` once meshed by other means than
+    /// `generate_mesh`.
+    pub(crate) fn mark_meshed(&mut self) {
+        self.dirty = false;
+    }
+
+    /// Solid voxels packed in columns along `axis`, indexed by `a + b * CHUNK_SIZE`.
+    ///
+    /// Bit `i + 1` is set when the voxel `i` of the column is solid, bits 0
+    /// and `CHUNK_SIZE + 1` are left for the neighbouring chunks.
+    pub fn columns(&self, axis: Axis) -> Vec<u64> {
+        let mut columns = Vec::new();
+        self.columns_into(axis, &mut columns);
+        columns
+    }
+
+    /// Like `columns` but reuses the allocation of `columns`.
+    pub fn columns_into(&self, axis: Axis, columns: &mut Vec<u64>) {
+        columns.clear();
+        columns.resize(COLUMNS, 0);
+        for b in 0..SIZE {
+            for a in 0..SIZE {
+                let mut column = 0;
+                for along in 0..SIZE {
+                    let (x, y, z) = axis.voxel(a, b, along);
+                    if self.get_voxel(x, y, z) != AIR {
+                        column |= 1 << (along + 1);
+                    }
+                }
+                columns[a + b * SIZE] = column;
+            }
+        }
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::new()
+    }
+}
+
+impl std::fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Chunk(empty: {}, painted faces: {}, damaged voxels: {})",
+            self.is_empty(),
+            self.paint.len(),
+            self.damage.len()
+        )
+    }
+}
+
+/// Visible faces of each column, bit `i` set when voxel `i` has a face.
+#[derive(Debug, Default, Clone)]
+pub struct FaceMasks {
+    /// Faces looking toward the positive side of the axis.
+    pub positive: Vec<u32>,
+
+    /// Faces looking toward the negative side of the axis.
+    pub negative: Vec<u32>,
+}
+
+impl FaceMasks {
+    /// Recompute the masks of `columns`, reusing the allocations.
+    pub fn compute(&mut self, columns: &[u64]) {
+        self.positive.clear();
+        self.positive.resize(columns.len(), 0);
+        self.negative.clear();
+        self.negative.resize(columns.len(), 0);
+        face_masks_into(columns, &mut self.positive, &mut self.negative);
+    }
+}
+
+/// Faces of the solid voxels which aren't covered by a solid neighbour.
+pub fn face_masks(columns: &[u64]) -> FaceMasks {
+    let mut masks = FaceMasks::default();
+    masks.compute(columns);
+    masks
+}
+
+/// Intermediate buffers of the mesher, meant to be kept in a `Pool`.
+#[derive(Debug, Default)]
+pub struct MeshScratch {
+    columns: Vec<u64>,
+
+    /// Face masks along X, Y and Z.
+    pub masks: [FaceMasks; 3],
+}
+
+impl MeshScratch {
+    /// Fill `masks` with the visible faces of `chunk` along each axis.
+    pub fn visible_faces(&mut self, chunk: &Chunk) -> &[FaceMasks; 3] {
+        for (i, &axis) in Axis::ALL.iter().enumerate() {
+            chunk.columns_into(axis, &mut self.columns);
+            self.masks[i].compute(&self.columns);
+        }
+        &self.masks
+    }
+}
+
+fn column_faces(column: u64) -> (u32, u32) {
+    let positive = column & !(column >> 1);
+    let negative = column & !(column << 1);
+    ((positive >> 1) as u32, (negative >> 1) as u32)
+}
+
+#[cfg(not(feature = "simd"))]
+fn face_masks_into(columns: &[u64], positive: &mut [u32], negative: &mut [u32]) {
+    for (i, &column) in columns.iter().enumerate() {
+        let (pos, neg) = column_faces(column);
+        positive[i] = pos;
+        negative[i] = neg;
+    }
+}
+
+#[cfg(feature = "simd")]
+fn face_masks_into(columns: &[u64], positive: &mut [u32], negative: &mut [u32]) {
+    use wide::u64x4;
+
+    let lanes = columns.len() / 4 * 4;
+    for start in (0..lanes).step_by(4) {
+        let mut lane = [0u64; 4];
+        lane.copy_from_slice(&columns[start..start + 4]);
+        let column = u64x4::from(lane);
+        let pos = (column & !(column >> 1)) >> 1;
+        let neg = (column & !(column << 1)) >> 1;
+        for (i, (pos, neg)) in pos.to_array().iter().zip(neg.to_array().iter()).enumerate() {
+            positive[start + i] = *pos as u32;
+            negative[start + i] = *neg as u32;
+        }
+    }
+    for i in lanes..columns.len() {
+        let (pos, neg) = column_faces(columns[i]);
+        positive[i] = pos;
+        negative[i] = neg;
+    }
+}
@@ -0,0 +1,161 @@
+//! Meshes of the world chunks drawn by the mesh pipeline.
+//!
+//! `World::mesh_dirty_chunks` meshes the chunks changed since their last
+//! mesh into `Scene::chunk_meshes`. Every new mesh gets a new generation,
+//! the mesh pipeline uploads a mesh when its generation changes and draws
+//! the chunks in view with one instance each, up to `MAX_CHUNK_MESHES` per
+//! frame, the largest on screen first.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nalgebra::{Point3, Transform3, Translation3, Vector3};
+
+use crate::coords::{ChunkCoord, FloatingOrigin, Location};
+use crate::culling::{apply_budget, Aabb, CullingStats};
+use crate::material::MaterialOverride;
+use crate::meshing::MeshData;
+use crate::vertex::InstanceData;
+
+/// Chunk meshes drawn per frame.
+pub const MAX_CHUNK_MESHES: usize = 1024;
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Mesh of a chunk, in chunk local coordinates.
+#[derive(Debug, Clone)]
+pub struct ChunkMesh {
+    mesh: MeshData,
+    bounds: Aabb,
+    generation: u64,
+}
+
+impl ChunkMesh {
+    pub fn mesh(&self) -> &MeshData {
+        &self.mesh
+    }
+
+    /// Bounds of the mesh, in chunk local coordinates.
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    /// Different for every mesh set, tells the mesh pipeline to upload it.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// Meshes of the chunks by coordinates, chunks without a mesh aren't drawn.
+#[derive(Debug, Default)]
+pub struct ChunkMeshes {
+    meshes: HashMap<ChunkCoord, ChunkMesh>,
+}
+
+impl ChunkMeshes {
+    pub fn new() -> Self {
+        ChunkMeshes::default()
+    }
+
+    /// Replace the mesh of the chunk at `coord`, an empty mesh removes it.
+    pub fn set(&mut self, coord: ChunkCoord, mesh: MeshData) {
+        let bounds = match Aabb::from_points(mesh.positions.iter().map(|&p| Point3::from(p))) {
+            Some(bounds) => bounds,
+            None => {
+                self.meshes.remove(&coord);
+                return;
+            }
+        };
+        let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        self.meshes.insert(
+            coord,
+            ChunkMesh {
+                mesh,
+                bounds,
+                generation,
+            },
+        );
+    }
+
+    /// Stop drawing the chunk at `coord`, such as once unloaded.
+    pub fn remove(&mut self, coord: &ChunkCoord) -> bool {
+        self.meshes.remove(coord).is_some()
+    }
+
+    pub fn get(&self, coord: &ChunkCoord) -> Option<&ChunkMesh> {
+        self.meshes.get(coord)
+    }
+
+    pub fn contains(&self, coord: &ChunkCoord) -> bool {
+        self.meshes.contains_key(coord)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ChunkCoord, &ChunkMesh)> {
+        self.meshes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+}
+
+/// Draw of a chunk mesh, with the instance at the same index of the
+/// instance data given by `chunk_draws`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkDraw {
+    pub coord: ChunkCoord,
+    pub generation: u64,
+}
+
+/// Instance data of the chunk `meshes` relative to `origin`, replacing the
+/// contents of `instances`, and the draw of each, in coordinates order.
+///
+/// Meshes whose bounds `visible` rejects are skipped, past
+/// `MAX_CHUNK_MESHES` the smallest seen from `eye` are left out.
+pub fn chunk_draws<F>(
+    meshes: &ChunkMeshes,
+    origin: &FloatingOrigin,
+    eye: &Point3<f32>,
+    visible: F,
+    instances: &mut Vec<InstanceData>,
+) -> (Vec<ChunkDraw>, CullingStats)
+where
+    F: Fn(&Aabb) -> bool,
+{
+    let mut chunks = Vec::new();
+    let mut aabbs = Vec::new();
+    for (coord, mesh) in meshes.iter() {
+        let min = origin.to_render(&Location::new(*coord, Vector3::zeros()));
+        let aabb = Aabb::new(mesh.bounds.min + min, mesh.bounds.max + min);
+        chunks.push((*coord, mesh.generation, min));
+        aabbs.push(aabb);
+    }
+    let mut drawn: Vec<_> = (0..aabbs.len()).filter(|&i| visible(&aabbs[i])).collect();
+    let mut stats = CullingStats {
+        tested: aabbs.len(),
+        frustum_culled: aabbs.len() - drawn.len(),
+        ..CullingStats::default()
+    };
+    stats.budget_culled = apply_budget(&mut drawn, &aabbs, eye, MAX_CHUNK_MESHES);
+    stats.drawn = drawn.len();
+    // Same chunks, same draws: the mesh pipeline records them again only
+    // once they change.
+    drawn.sort_unstable_by_key(|&i| chunks[i].0);
+
+    instances.clear();
+    let overrides = MaterialOverride::default();
+    let draws = drawn
+        .iter()
+        .map(|&i| {
+            let (coord, generation, min) = chunks[i];
+            let model = Transform3::from_matrix_unchecked(Translation3::from(min).to_homogeneous());
+            instances.push(InstanceData::new(&model, &overrides));
+            ChunkDraw { coord, generation }
+        })
+        .collect();
+    (draws, stats)
+}
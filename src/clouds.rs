@@ -0,0 +1,229 @@
+//! Cloud layer drawn over the sky.
+//!
+//! The clouds are a noise pattern scrolled by the wind on a horizontal
+//! plane, or raymarched through a slab above that plane. They are drawn on
+//! the far plane so the depth test keeps them behind the terrain.
+
+use nalgebra::Matrix4;
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    Layout, PrepareResult, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{GraphContext, NodeBuffer, NodeImage};
+use rendy::hal;
+use rendy::resource::{DescriptorSetLayout, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::color::OutputEncoding;
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::scene::Scene;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../clouds.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/clouds.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../clouds.frag"));
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/clouds.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+}
+
+/// How the clouds are drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CloudMode {
+    Off,
+
+    /// Translucent plane, cheap.
+    Flat,
+
+    /// Raymarched volume with self shadowing.
+    Volumetric,
+}
+
+/// Settings of the cloud layer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CloudLayer {
+    pub mode: CloudMode,
+
+    /// Altitude of the cloud base in render space.
+    pub height: f32,
+
+    /// Fraction of the sky covered, in `0.0..=1.0`.
+    pub coverage: f32,
+}
+
+impl Default for CloudLayer {
+    fn default() -> Self {
+        CloudLayer {
+            mode: CloudMode::Flat,
+            height: 200.0,
+            coverage: 0.4,
+        }
+    }
+}
+
+/// Size of the push constants, in `u32`s.
+const CONSTANTS: usize = 28;
+
+#[derive(Debug, Default)]
+pub(crate) struct CloudsDesc {
+    /// See `ShaderTuning::cloud_steps`.
+    pub steps: u32,
+
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
+
+#[derive(Debug)]
+pub(crate) struct Clouds {
+    constants: [u32; CONSTANTS],
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for CloudsDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Clouds;
+
+    fn colors(&self) -> Vec<hal::pso::ColorBlendDesc> {
+        vec![hal::pso::ColorBlendDesc {
+            mask: hal::pso::ColorMask::ALL,
+            blend: Some(hal::pso::BlendState::ALPHA),
+        }]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        Some(hal::pso::DepthStencilDesc {
+            depth: Some(hal::pso::DepthTest {
+                fun: hal::pso::Comparison::LessEqual,
+                write: false,
+            }),
+            depth_bounds: false,
+            stencil: None,
+        })
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        let constants = SpecConstants::new()
+            .with_u32(0, self.steps)
+            .with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: Vec::new(),
+            push_constants: vec![(
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0..(CONSTANTS * 4) as u32,
+            )],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &Scene,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        Ok(Clouds {
+            constants: [0; CONSTANTS],
+        })
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Clouds
+where
+    B: hal::Backend,
+{
+    type Desc = CloudsDesc;
+
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        let inverse = aux
+            .view_proj()
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let eye = aux.render_view().translation.vector;
+        let sun = aux.sun_direction();
+        let layer = &aux.clouds;
+        let floats = inverse.iter().cloned().chain(vec![
+            eye.x,
+            eye.y,
+            eye.z,
+            0.0,
+            sun.x,
+            sun.y,
+            sun.z,
+            1.0 - aux.weather.params().overcast * 0.5,
+            aux.time,
+            layer.height,
+            layer.coverage,
+        ]);
+        for (constant, value) in self.constants.iter_mut().zip(floats) {
+            *constant = value.to_bits();
+        }
+        self.constants[CONSTANTS - 1] = match layer.mode {
+            CloudMode::Volumetric => 2,
+            _ => 1,
+        };
+        // Constants change every frame.
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        aux: &Scene,
+    ) {
+        if aux.clouds.mode == CloudMode::Off {
+            return;
+        }
+        unsafe {
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.constants,
+            );
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
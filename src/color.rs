@@ -0,0 +1,204 @@
+//! Colors.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rendy::hal;
+
+/// Linear RGBA color with straight alpha.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+    pub const TRANSPARENT: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+
+    /// Color from linear components.
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color { r, g, b, a }
+    }
+
+    /// Opaque color from linear components.
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Color::new(r, g, b, 1.0)
+    }
+
+    /// Color from sRGB encoded components, alpha is always linear.
+    pub fn from_srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a)
+    }
+
+    /// sRGB encoded components.
+    pub fn to_srgb(self) -> [f32; 4] {
+        [
+            linear_to_srgb(self.r),
+            linear_to_srgb(self.g),
+            linear_to_srgb(self.b),
+            self.a,
+        ]
+    }
+
+    /// Parse a sRGB hex color, `#rgb`, `#rrggbb` or `#rrggbbaa`, `#` is optional.
+    pub fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        let digits = hex.trim_start_matches('#');
+        if !digits.is_ascii() {
+            return Err(ParseColorError(hex.to_owned()));
+        }
+        let channel = |range: std::ops::Range<usize>| {
+            u8::from_str_radix(&digits[range], 16).map_err(|_| ParseColorError(hex.to_owned()))
+        };
+        let (r, g, b, a) = match digits.len() {
+            3 => (
+                channel(0..1)? * 17,
+                channel(1..2)? * 17,
+                channel(2..3)? * 17,
+                255,
+            ),
+            6 => (channel(0..2)?, channel(2..4)?, channel(4..6)?, 255),
+            8 => (
+                channel(0..2)?,
+                channel(2..4)?,
+                channel(4..6)?,
+                channel(6..8)?,
+            ),
+            _ => return Err(ParseColorError(hex.to_owned())),
+        };
+        Ok(Color::from_srgb(
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        ))
+    }
+
+    /// Linear components.
+    pub fn to_array(self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// Same color with alpha `a`.
+    pub fn with_alpha(self, a: f32) -> Self {
+        Color { a, ..self }
+    }
+
+    /// Linear interpolation, `t = 0.0` is `self`.
+    pub fn lerp(self, other: Color, t: f32) -> Self {
+        Color::new(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+            self.a + (other.a - self.a) * t,
+        )
+    }
+}
+
+impl From<[f32; 4]> for Color {
+    fn from([r, g, b, a]: [f32; 4]) -> Self {
+        Color::new(r, g, b, a)
+    }
+}
+
+impl From<Color> for [f32; 4] {
+    fn from(color: Color) -> Self {
+        color.to_array()
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::from_hex(s)
+    }
+}
+
+/// How the colors written to the surface are encoded, the `OUTPUT_GAMMA`
+/// constant of `color.glsl`. The shaders work on linear colors until then.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputEncoding {
+    /// Written linear, for a surface with an sRGB format encoding them.
+    Linear,
+
+    /// Encoded with the sRGB curve by the shaders.
+    Srgb,
+
+    /// Encoded with the power `1 / gamma` by the shaders.
+    Gamma(f32),
+}
+
+impl OutputEncoding {
+    /// Encoding for a surface of `format`. Surfaces with an sRGB format
+    /// encode the colors themselves, the shaders encode them with `gamma`
+    /// for the others, the sRGB curve if it's `None`.
+    pub fn for_format(format: hal::format::Format, gamma: Option<f32>) -> Self {
+        let srgb = format.base_format().1 == hal::format::ChannelType::Srgb;
+        match gamma {
+            _ if srgb => OutputEncoding::Linear,
+            Some(gamma) => OutputEncoding::Gamma(gamma),
+            None => OutputEncoding::Srgb,
+        }
+    }
+
+    /// Value of the `OUTPUT_GAMMA` constant: zero for `Linear`, negative
+    /// for `Srgb` and the gamma otherwise.
+    pub fn constant(self) -> f32 {
+        match self {
+            OutputEncoding::Linear => 0.0,
+            OutputEncoding::Srgb => -1.0,
+            OutputEncoding::Gamma(gamma) => gamma,
+        }
+    }
+
+    /// Components of `color` as written to the surface, alpha is always
+    /// linear.
+    pub fn encode(self, color: Color) -> [f32; 4] {
+        match self {
+            OutputEncoding::Linear => color.to_array(),
+            OutputEncoding::Srgb => color.to_srgb(),
+            OutputEncoding::Gamma(gamma) => {
+                let encode = |c: f32| c.max(0.0).powf(1.0 / gamma);
+                [encode(color.r), encode(color.g), encode(color.b), color.a]
+            }
+        }
+    }
+}
+
+impl Default for OutputEncoding {
+    fn default() -> Self {
+        OutputEncoding::Linear
+    }
+}
+
+/// Error returned by `Color::from_hex`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseColorError(String);
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid hex color `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,661 @@
+//! Renderer configuration.
+
+use std::fmt;
+
+use nalgebra::Vector3;
+use rendy::hal;
+
+use crate::camera::Camera;
+use crate::color::{Color, OutputEncoding};
+use crate::culling::{shadow_cascades, ShadowCascade};
+use crate::material::ShadingModel;
+use crate::scene::Scene;
+use crate::tonemap::HdrSettings;
+use crate::validation::ValidationLayer;
+
+/// Named sets of quality settings.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    High,
+    Ultra,
+
+    /// Settings changed one by one, not matching any preset.
+    Custom,
+}
+
+impl QualityPreset {
+    /// Settings of the preset, `None` for `Custom`.
+    pub fn settings(self) -> Option<QualitySettings> {
+        let settings = match self {
+            QualityPreset::Low => QualitySettings {
+                view_distance: 200.0,
+                shadow_resolution: 1024,
+                shadow_cascades: 2,
+                post_effects: false,
+                msaa_samples: 1,
+                lod_bias: 1.0,
+                draw_budget: Some(256),
+                resolution_scale: 1.0,
+            },
+            QualityPreset::Medium => QualitySettings {
+                view_distance: 400.0,
+                shadow_resolution: 2048,
+                shadow_cascades: 3,
+                post_effects: true,
+                msaa_samples: 1,
+                lod_bias: 0.5,
+                draw_budget: None,
+                resolution_scale: 1.0,
+            },
+            QualityPreset::High => QualitySettings {
+                view_distance: 800.0,
+                shadow_resolution: 2048,
+                shadow_cascades: 4,
+                post_effects: true,
+                msaa_samples: 4,
+                lod_bias: 0.0,
+                draw_budget: None,
+                resolution_scale: 1.0,
+            },
+            QualityPreset::Ultra => QualitySettings {
+                view_distance: 1600.0,
+                shadow_resolution: 4096,
+                shadow_cascades: 4,
+                post_effects: true,
+                msaa_samples: 8,
+                lod_bias: -0.5,
+                draw_budget: None,
+                resolution_scale: 1.0,
+            },
+            QualityPreset::Custom => return None,
+        };
+        Some(settings)
+    }
+}
+
+/// Settings tuned together by a `QualityPreset`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QualitySettings {
+    /// Distance of the camera far plane.
+    pub view_distance: f32,
+
+    /// Size of each shadow cascade map.
+    pub shadow_resolution: u32,
+
+    pub shadow_cascades: usize,
+
+    /// Post-process passes after the scene pass, and the outlines of
+    /// `MaterialOverride::outline`.
+    pub post_effects: bool,
+
+    pub msaa_samples: u8,
+
+    /// Added to the LOD level chosen from the distance, positive is coarser.
+    pub lod_bias: f32,
+
+    /// See `Scene::draw_budget`.
+    pub draw_budget: Option<usize>,
+
+    /// Size of the scene images relative to the window, from
+    /// `MIN_RESOLUTION_SCALE` to 1. Smaller frames are stretched to the
+    /// window when presented.
+    pub resolution_scale: f32,
+}
+
+impl QualitySettings {
+    /// Size of the scene images for a `width` by `height` window.
+    pub fn render_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |len: u32| ((len as f32 * self.resolution_scale).round() as u32).max(1);
+        (scale(width), scale(height))
+    }
+
+    /// Extents of the images drawn to for a `width` by `height` window.
+    pub fn target_extents(&self, width: u32, height: u32) -> TargetExtents {
+        let (render_width, render_height) = self.render_size(width, height);
+        TargetExtents {
+            render: hal::image::Kind::D2(render_width, render_height, 1, 1),
+            surface: hal::window::Extent2D { width, height },
+        }
+    }
+}
+
+/// Extents of the images a graph built for a window draws to, they change
+/// with the window size.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TargetExtents {
+    /// Scene images, the depth image included.
+    pub render: hal::image::Kind,
+
+    /// Swapchain images, the size of the window.
+    pub surface: hal::window::Extent2D,
+}
+
+impl TargetExtents {
+    /// Whether the scene is drawn at another size than the window, and
+    /// stretched to it.
+    pub fn is_scaled(&self) -> bool {
+        let extent = self.render.extent();
+        (extent.width, extent.height) != (self.surface.width, self.surface.height)
+    }
+}
+
+/// Smallest `QualitySettings::resolution_scale`.
+pub const MIN_RESOLUTION_SCALE: f32 = 0.25;
+
+/// Most shadow cascades the shaders support.
+pub const MAX_SHADOW_CASCADES: usize = 4;
+
+/// Vulkan layer checking the use of the API.
+pub const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// How the sun shadow volumes follow the camera.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShadowProjection {
+    /// `QualitySettings::shadow_cascades` cascades split along the view,
+    /// for cameras looking towards the horizon.
+    Cascades,
+
+    /// A single stable cascade over the view up to `distance`, for top-down
+    /// and isometric cameras seeing the ground at about the same distance.
+    TopDown { distance: f32 },
+}
+
+/// When the renderer draws frames.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RedrawMode {
+    /// Every iteration of the event loop, for games.
+    Continuous,
+
+    /// Only once the camera moved or `Renderer::request_redraw` was called,
+    /// for editors and tools idle most of the time. Animations of the
+    /// shaders stop in between.
+    OnDemand,
+}
+
+/// How a pass starts: from cleared targets or from what they already hold.
+///
+/// Store ops aren't set here, the graph picks them from the later users of
+/// each target.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PassDesc {
+    clear: Option<Color>,
+}
+
+impl PassDesc {
+    /// Clear the color target to `color` and the depth to the far plane.
+    pub fn clear(color: Color) -> Self {
+        PassDesc { clear: Some(color) }
+    }
+
+    /// Keep what the targets hold, to draw over the image of an earlier
+    /// pass or frame. The surface images rotate through the swapchain, so
+    /// without the crossfade image the one loaded is from frames ago.
+    pub fn load_existing() -> Self {
+        PassDesc { clear: None }
+    }
+
+    /// Color the targets are cleared to, `None` if they're loaded.
+    pub fn clear_color(&self) -> Option<Color> {
+        self.clear
+    }
+
+    /// Clear value of a color target written with `output`, for
+    /// `GraphBuilder::create_image`.
+    pub fn color_clear_value(&self, output: OutputEncoding) -> Option<hal::command::ClearValue> {
+        self.clear.map(|color| hal::command::ClearValue {
+            color: hal::command::ClearColor {
+                float32: output.encode(color),
+            },
+        })
+    }
+
+    /// Clear value of a depth target.
+    pub fn depth_clear_value(&self) -> Option<hal::command::ClearValue> {
+        self.clear.map(|_| hal::command::ClearValue {
+            depth_stencil: hal::command::ClearDepthStencil {
+                depth: 1.0,
+                stencil: 0,
+            },
+        })
+    }
+}
+
+/// Tuning values of the shaders, specialization constants of the pipelines
+/// built with them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShaderTuning {
+    /// Light of the sky reaching lit surfaces facing away from the sun,
+    /// from 0 to 1.
+    pub ambient: f32,
+
+    /// Samples along each view ray through the raymarched clouds.
+    pub cloud_steps: u32,
+
+    /// Gamma the shaders encode the colors with on surfaces without an
+    /// sRGB format, the sRGB curve if `None`. See `OutputEncoding`.
+    pub output_gamma: Option<f32>,
+}
+
+impl Default for ShaderTuning {
+    fn default() -> Self {
+        ShaderTuning {
+            ambient: 0.35,
+            cloud_steps: 24,
+            output_gamma: None,
+        }
+    }
+}
+
+/// Invalid setting or combination of settings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigProblem {
+    /// `occlusion_culling` is set without `gpu_culling`.
+    OcclusionWithoutGpuCulling,
+
+    /// MSAA sample count which is zero or not a power of two.
+    InvalidMsaa(u8),
+
+    /// MSAA sample count the device can't render to.
+    UnsupportedMsaa { requested: u8, supported: u8 },
+
+    /// Shadow maps larger than the biggest image of the device.
+    ShadowResolutionTooLarge { requested: u32, max: u32 },
+
+    /// Zero cascades or more than `MAX_SHADOW_CASCADES`.
+    InvalidShadowCascades(usize),
+
+    /// Far plane at or before the near plane.
+    ViewDistanceTooShort { view_distance: f32, znear: f32 },
+
+    /// `ShadowProjection::TopDown` distance which isn't positive.
+    InvalidShadowDistance(f32),
+
+    /// `ShadingModel::Toon` ramp without any band.
+    NoToonBands,
+
+    /// `ShaderTuning::ambient` outside of 0 to 1.
+    InvalidAmbient(f32),
+
+    /// `ShaderTuning::cloud_steps` is zero.
+    NoCloudSteps,
+
+    /// `ShaderTuning::output_gamma` which isn't positive.
+    InvalidOutputGamma(f32),
+
+    /// `QualitySettings::resolution_scale` outside of
+    /// `MIN_RESOLUTION_SCALE` to 1.
+    InvalidResolutionScale(f32),
+
+    /// `HdrSettings::exposure` which isn't positive.
+    InvalidExposure(f32),
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigProblem::OcclusionWithoutGpuCulling => {
+                write!(f, "occlusion culling needs gpu culling")
+            }
+            ConfigProblem::InvalidMsaa(samples) => {
+                write!(f, "{} MSAA samples is not a power of two", samples)
+            }
+            ConfigProblem::UnsupportedMsaa {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "{} MSAA samples not supported, supported counts mask is {:#010b}",
+                requested, supported
+            ),
+            ConfigProblem::ShadowResolutionTooLarge { requested, max } => write!(
+                f,
+                "shadow resolution {} is above the device limit of {}",
+                requested, max
+            ),
+            ConfigProblem::InvalidShadowCascades(cascades) => write!(
+                f,
+                "{} shadow cascades, expected 1 to {}",
+                cascades, MAX_SHADOW_CASCADES
+            ),
+            ConfigProblem::ViewDistanceTooShort {
+                view_distance,
+                znear,
+            } => write!(
+                f,
+                "view distance {} is not beyond the near plane at {}",
+                view_distance, znear
+            ),
+            ConfigProblem::InvalidShadowDistance(distance) => {
+                write!(f, "top-down shadow distance {} is not positive", distance)
+            }
+            ConfigProblem::NoToonBands => write!(f, "toon shading needs at least one band"),
+            ConfigProblem::InvalidAmbient(ambient) => {
+                write!(f, "ambient light {} is not between 0 and 1", ambient)
+            }
+            ConfigProblem::NoCloudSteps => write!(f, "clouds need at least one step"),
+            ConfigProblem::InvalidOutputGamma(gamma) => {
+                write!(f, "output gamma {} is not positive", gamma)
+            }
+            ConfigProblem::InvalidResolutionScale(scale) => write!(
+                f,
+                "resolution scale {} is not between {} and 1",
+                scale, MIN_RESOLUTION_SCALE
+            ),
+            ConfigProblem::InvalidExposure(exposure) => {
+                write!(f, "exposure {} is not positive", exposure)
+            }
+        }
+    }
+}
+
+/// Every problem found in a configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid renderer configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n- {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Options used when building the render graph.
+#[derive(Debug, Clone)]
+pub struct RendererConfig {
+    /// Cull instances in a compute pre-pass instead of on the CPU.
+    pub gpu_culling: bool,
+
+    /// Also cull instances hidden in the previous frame, needs `gpu_culling`.
+    pub occlusion_culling: bool,
+
+    /// Skin the skeletal meshes in a compute pre-pass instead of on the CPU.
+    pub gpu_skinning: bool,
+
+    /// Preset `quality` comes from, `Custom` once changed by hand.
+    pub preset: QualityPreset,
+
+    pub quality: QualitySettings,
+
+    /// Start of the scene pass, cleared to the color of the background
+    /// behind every drawn voxel by default.
+    pub scene_pass: PassDesc,
+
+    /// Seconds the last frame of a rebuilt graph takes to fade out, zero
+    /// cuts to the new graph.
+    pub crossfade: f32,
+
+    /// Shading of the voxels and skinned meshes of the world.
+    pub shading: ShadingModel,
+
+    pub shadow_projection: ShadowProjection,
+
+    /// Changing them rebuilds the graph but never recompiles the shaders.
+    pub tuning: ShaderTuning,
+
+    /// Draw the scene to a half float image tonemapped to the surface, see
+    /// `tonemap`. Off by default.
+    pub hdr: Option<HdrSettings>,
+
+    pub redraw: RedrawMode,
+
+    /// Add the node copying the intermediate targets for
+    /// `Scene::request_frame_dump`, on by default in debug builds.
+    pub frame_dumps: bool,
+
+    /// Enable the validation layer, see `enable_gpu_validation`.
+    pub gpu_validation: bool,
+}
+
+impl RendererConfig {
+    pub fn with_preset(mut self, preset: QualityPreset) -> Self {
+        self.set_preset(preset);
+        self
+    }
+
+    pub fn gpu_validation(mut self, enabled: bool) -> Self {
+        self.gpu_validation = enabled;
+        self
+    }
+
+    /// With `gpu_validation`, enable the validation layer for the instance
+    /// `AnyWindowedRendy::init` creates, until `ValidationLayer::finish`.
+    /// Errors are logged as errors, warnings as warnings, information as
+    /// info and verbose messages as trace. Other backends ignore the layer.
+    pub fn enable_gpu_validation(&self) -> Option<ValidationLayer> {
+        if self.gpu_validation {
+            Some(ValidationLayer::enable())
+        } else {
+            None
+        }
+    }
+
+    /// Switch to the settings of `preset`, `Custom` keeps the current ones.
+    pub fn set_preset(&mut self, preset: QualityPreset) {
+        if let Some(quality) = preset.settings() {
+            self.quality = quality;
+        }
+        self.preset = preset;
+    }
+
+    /// Replace the quality settings, the preset becomes `Custom`.
+    pub fn set_quality(&mut self, quality: QualitySettings) {
+        self.quality = quality;
+        self.preset = QualityPreset::Custom;
+    }
+
+    /// Apply the settings which don't need a graph rebuild.
+    pub fn apply(&self, scene: &mut Scene) {
+        scene.camera.proj.set_zfar(self.quality.view_distance);
+        scene.draw_budget = self.quality.draw_budget;
+        scene.lod_bias = self.quality.lod_bias;
+    }
+
+    /// Shadow volumes of the sun shining along `light_dir` for `camera`,
+    /// laid out by `shadow_projection`.
+    pub fn shadow_cascades(&self, camera: &Camera, light_dir: &Vector3<f32>) -> Vec<ShadowCascade> {
+        let quality = &self.quality;
+        match self.shadow_projection {
+            ShadowProjection::Cascades => shadow_cascades(
+                camera,
+                light_dir,
+                quality.shadow_cascades,
+                quality.view_distance,
+            ),
+            ShadowProjection::TopDown { distance } => vec![ShadowCascade::stable(
+                camera,
+                light_dir,
+                camera.proj.znear(),
+                distance.min(quality.view_distance),
+                quality.shadow_resolution,
+            )],
+        }
+    }
+
+    /// Turn off what the device can't run instead of failing, for backends
+    /// like GL on older GPUs. Returns a message for each change.
+    ///
+    /// Culling and skinning fall back to the CPU without a compute queue and
+    /// MSAA to the highest supported sample count. Draws are single indirect
+    /// draws so multi-draw support is not needed.
+    pub fn degrade(&mut self, limits: &hal::Limits, compute: bool) -> Vec<String> {
+        let mut changes = Vec::new();
+        if !compute && self.gpu_culling {
+            self.gpu_culling = false;
+            self.occlusion_culling = false;
+            changes.push("no compute queue, culling on the CPU".to_owned());
+        }
+        if !compute && self.gpu_skinning {
+            self.gpu_skinning = false;
+            changes.push("no compute queue, skinning on the CPU".to_owned());
+        }
+
+        let samples = self.quality.msaa_samples;
+        let supported = limits.framebuffer_color_sample_counts;
+        if samples.is_power_of_two() && supported & samples == 0 {
+            let fallback = (0..8)
+                .map(|bit| 1u8 << bit)
+                .filter(|&count| count < samples && supported & count != 0)
+                .last()
+                .unwrap_or(1);
+            self.quality.msaa_samples = fallback;
+            self.preset = QualityPreset::Custom;
+            changes.push(format!(
+                "{} MSAA samples not supported, using {}",
+                samples, fallback
+            ));
+        }
+        changes
+    }
+
+    /// This configuration degraded to a device with `limits`, and a compute
+    /// queue if `compute`, or every problem left with it for a camera whose
+    /// near plane is at `znear`. The degradations are logged.
+    pub fn check(
+        mut self,
+        limits: &hal::Limits,
+        compute: bool,
+        znear: f32,
+    ) -> Result<RendererConfig, ConfigError> {
+        for change in self.degrade(limits, compute) {
+            warn!("Config degraded: {}.", change);
+        }
+        let mut problems = self.problems(limits);
+        if self.quality.view_distance <= znear {
+            problems.push(ConfigProblem::ViewDistanceTooShort {
+                view_distance: self.quality.view_distance,
+                znear,
+            });
+        }
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+        Ok(self)
+    }
+
+    /// Replace this configuration with `config` checked as by `check`, and
+    /// return the replaced one. A failed check leaves it unchanged.
+    pub fn replace_checked(
+        &mut self,
+        config: RendererConfig,
+        limits: &hal::Limits,
+        compute: bool,
+        znear: f32,
+    ) -> Result<RendererConfig, ConfigError> {
+        let config = config.check(limits, compute, znear)?;
+        Ok(std::mem::replace(self, config))
+    }
+
+    /// Problems of this configuration on a device with `limits`.
+    pub fn problems(&self, limits: &hal::Limits) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        if self.occlusion_culling && !self.gpu_culling {
+            problems.push(ConfigProblem::OcclusionWithoutGpuCulling);
+        }
+
+        let samples = self.quality.msaa_samples;
+        let supported = limits.framebuffer_color_sample_counts;
+        if !samples.is_power_of_two() {
+            problems.push(ConfigProblem::InvalidMsaa(samples));
+        } else if supported & samples == 0 {
+            problems.push(ConfigProblem::UnsupportedMsaa {
+                requested: samples,
+                supported,
+            });
+        }
+
+        if self.quality.shadow_resolution > limits.max_image_2d_size {
+            problems.push(ConfigProblem::ShadowResolutionTooLarge {
+                requested: self.quality.shadow_resolution,
+                max: limits.max_image_2d_size,
+            });
+        }
+        let cascades = self.quality.shadow_cascades;
+        if cascades == 0 || cascades > MAX_SHADOW_CASCADES {
+            problems.push(ConfigProblem::InvalidShadowCascades(cascades));
+        }
+        if let ShadowProjection::TopDown { distance } = self.shadow_projection {
+            let valid = distance > 0.0;
+            if !valid {
+                problems.push(ConfigProblem::InvalidShadowDistance(distance));
+            }
+        }
+        if self.shading == (ShadingModel::Toon { bands: 0 }) {
+            problems.push(ConfigProblem::NoToonBands);
+        }
+        let ambient = self.tuning.ambient;
+        let valid = (0.0..=1.0).contains(&ambient);
+        if !valid {
+            problems.push(ConfigProblem::InvalidAmbient(ambient));
+        }
+        if self.tuning.cloud_steps == 0 {
+            problems.push(ConfigProblem::NoCloudSteps);
+        }
+        if let Some(gamma) = self.tuning.output_gamma {
+            let valid = gamma > 0.0;
+            if !valid {
+                problems.push(ConfigProblem::InvalidOutputGamma(gamma));
+            }
+        }
+        let scale = self.quality.resolution_scale;
+        let valid = (MIN_RESOLUTION_SCALE..=1.0).contains(&scale);
+        if !valid {
+            problems.push(ConfigProblem::InvalidResolutionScale(scale));
+        }
+        if let Some(hdr) = self.hdr {
+            let valid = hdr.exposure > 0.0 && hdr.exposure.is_finite();
+            if !valid {
+                problems.push(ConfigProblem::InvalidExposure(hdr.exposure));
+            }
+        }
+        problems
+    }
+
+    /// Whether going from `previous` to `self` needs the graph to be rebuilt.
+    pub fn needs_rebuild(&self, previous: &RendererConfig) -> bool {
+        self.gpu_culling != previous.gpu_culling
+            || self.occlusion_culling != previous.occlusion_culling
+            || self.gpu_skinning != previous.gpu_skinning
+            || self.quality.shadow_resolution != previous.quality.shadow_resolution
+            || self.quality.shadow_cascades != previous.quality.shadow_cascades
+            || self.quality.post_effects != previous.quality.post_effects
+            || self.quality.msaa_samples != previous.quality.msaa_samples
+            || self.quality.resolution_scale != previous.quality.resolution_scale
+            || self.scene_pass != previous.scene_pass
+            || self.crossfade != previous.crossfade
+            || self.shading != previous.shading
+            || self.shadow_projection != previous.shadow_projection
+            || self.tuning != previous.tuning
+            || self.hdr != previous.hdr
+            || self.frame_dumps != previous.frame_dumps
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            gpu_culling: false,
+            occlusion_culling: false,
+            gpu_skinning: true,
+            preset: QualityPreset::Medium,
+            quality: QualityPreset::Medium.settings().unwrap(),
+            scene_pass: PassDesc::clear(Color::rgb(0.8, 0.8, 0.8)),
+            crossfade: 0.25,
+            shading: ShadingModel::default(),
+            shadow_projection: ShadowProjection::Cascades,
+            tuning: ShaderTuning::default(),
+            hdr: None,
+            redraw: RedrawMode::Continuous,
+            frame_dumps: cfg!(debug_assertions),
+            gpu_validation: false,
+        }
+    }
+}
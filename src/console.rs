@@ -0,0 +1,381 @@
+//! In-game command console.
+//!
+//! Commands are registered by name with a handler receiving the scene, the
+//! renderer config and the arguments of the line. The console keeps the
+//! line being typed, the history of submitted lines and the output shown
+//! when it is open.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::clouds::CloudMode;
+use crate::config::{QualityPreset, RendererConfig};
+use crate::console_overlay::ConsoleOverlay;
+use crate::coords::{Location, WorldPos};
+use crate::debug_palette::DebugPalette;
+use crate::frame_dump::timestamped_folder;
+use crate::scene::Scene;
+use crate::weather::Weather;
+
+/// State a command can change.
+pub struct CommandContext<'a> {
+    pub scene: &'a mut Scene,
+    pub config: &'a mut RendererConfig,
+}
+
+/// Handler of a command, returns the line to print or an error message.
+pub type CommandHandler = Box<dyn Fn(&mut CommandContext, &[&str]) -> Result<String, String>>;
+
+struct Command {
+    help: String,
+    handler: CommandHandler,
+}
+
+/// Maximum number of output lines kept.
+const OUTPUT_LINES: usize = 64;
+
+/// Command registry, input line, history and output of the console.
+pub struct Console {
+    commands: BTreeMap<String, Command>,
+    pub open: bool,
+    input: String,
+    history: Vec<String>,
+    /// Entry of `history` shown in the input, `None` when typing a new line.
+    cursor: Option<usize>,
+    output: Vec<String>,
+}
+
+impl Console {
+    /// Console with the built-in commands.
+    pub fn new() -> Self {
+        let mut console = Console::empty();
+        console.register_builtins();
+        console
+    }
+
+    /// Console without any command.
+    pub fn empty() -> Self {
+        Console {
+            commands: BTreeMap::new(),
+            open: false,
+            input: String::new(),
+            history: Vec::new(),
+            cursor: None,
+            output: Vec::new(),
+        }
+    }
+
+    /// Register `handler` under `name`, replacing any command of that name.
+    pub fn register_command<F>(&mut self, name: &str, help: &str, handler: F)
+    where
+        F: Fn(&mut CommandContext, &[&str]) -> Result<String, String> + 'static,
+    {
+        self.commands.insert(
+            name.to_owned(),
+            Command {
+                help: help.to_owned(),
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Lines to draw while open, see `Scene::console`.
+    pub fn overlay(&self) -> Option<ConsoleOverlay> {
+        if !self.open {
+            return None;
+        }
+        Some(ConsoleOverlay {
+            output: self.output.clone(),
+            input: self.input.clone(),
+        })
+    }
+
+    /// Feed a typed character, control characters are ignored.
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_control() {
+            self.input.push(c);
+            self.cursor = None;
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+        self.cursor = None;
+    }
+
+    /// Show the previous history entry in the input.
+    pub fn history_prev(&mut self) {
+        let index = match self.cursor {
+            _ if self.history.is_empty() => return,
+            Some(0) => 0,
+            Some(index) => index - 1,
+            None => self.history.len() - 1,
+        };
+        self.cursor = Some(index);
+        self.input = self.history[index].clone();
+    }
+
+    /// Show the next history entry, or an empty line after the last one.
+    pub fn history_next(&mut self) {
+        match self.cursor {
+            Some(index) if index + 1 < self.history.len() => {
+                self.cursor = Some(index + 1);
+                self.input = self.history[index + 1].clone();
+            }
+            Some(_) => {
+                self.cursor = None;
+                self.input.clear();
+            }
+            None => {}
+        }
+    }
+
+    /// Run the input line and clear it.
+    pub fn submit(&mut self, context: &mut CommandContext) {
+        let line = std::mem::replace(&mut self.input, String::new());
+        self.cursor = None;
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        self.print(format!("> {}", line));
+        let result = self.execute(context, &line);
+        match result {
+            Ok(message) if message.is_empty() => {}
+            Ok(message) => self.print(message),
+            Err(message) => self.print(format!("error: {}", message)),
+        }
+    }
+
+    /// Run one command line without touching the history.
+    pub fn execute(&self, context: &mut CommandContext, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or_else(|| "empty command".to_owned())?;
+        let args: Vec<_> = words.collect();
+        if name == "help" {
+            return Ok(self
+                .commands
+                .iter()
+                .map(|(name, command)| format!("{}: {}", name, command.help))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        let command = self
+            .commands
+            .get(name)
+            .ok_or_else(|| format!("unknown command `{}`", name))?;
+        (command.handler)(context, &args)
+    }
+
+    pub fn print(&mut self, message: String) {
+        info!("Console: {}", message);
+        self.output.extend(message.lines().map(str::to_owned));
+        if self.output.len() > OUTPUT_LINES {
+            let extra = self.output.len() - OUTPUT_LINES;
+            self.output.drain(..extra);
+        }
+    }
+
+    fn register_builtins(&mut self) {
+        self.register_command(
+            "debug",
+            "debug <view> [on|off], toggle a debug view",
+            |ctx, args| {
+                let view = *args.first().ok_or("missing view name")?;
+                let enabled = match args.get(1) {
+                    Some(&"on") => true,
+                    Some(&"off") => false,
+                    None => !ctx.scene.debug_views.contains(view),
+                    Some(other) => return Err(format!("expected on or off, got `{}`", other)),
+                };
+                if enabled {
+                    ctx.scene.debug_views.insert(view.to_owned());
+                } else {
+                    ctx.scene.debug_views.remove(view);
+                }
+                Ok(format!("{} {}", view, if enabled { "on" } else { "off" }))
+            },
+        );
+
+        self.register_command(
+            "debug_palette",
+            "debug_palette <rainbow|colorblind>, colors of the debug views",
+            |ctx, args| {
+                let palette = match args.first().map(|arg| arg.to_lowercase()).as_deref() {
+                    Some("rainbow") => DebugPalette::Rainbow,
+                    Some("colorblind") => DebugPalette::ColorblindSafe,
+                    _ => return Err("expected rainbow or colorblind".to_owned()),
+                };
+                ctx.scene.debug_palette = palette;
+                Ok(String::new())
+            },
+        );
+
+        self.register_command(
+            "freeze_culling",
+            "freeze_culling [on|off], lock the culling camera in place",
+            |ctx, args| {
+                let frozen = match args.first() {
+                    Some(&"on") => true,
+                    Some(&"off") => false,
+                    None => !ctx.scene.is_culling_frozen(),
+                    Some(other) => return Err(format!("expected on or off, got `{}`", other)),
+                };
+                ctx.scene.freeze_culling(frozen);
+                Ok(format!(
+                    "culling {}",
+                    if frozen { "frozen" } else { "unfrozen" }
+                ))
+            },
+        );
+
+        self.register_command("time", "time <hour>, set the time of day", |ctx, args| {
+            let hour: f32 = parse(args.first())?;
+            ctx.scene.time_of_day = hour.rem_euclid(24.0);
+            Ok(String::new())
+        });
+
+        self.register_command(
+            "quality",
+            "quality <low|medium|high|ultra>, apply a quality preset",
+            |ctx, args| {
+                let preset = match args.first().map(|arg| arg.to_lowercase()).as_deref() {
+                    Some("low") => QualityPreset::Low,
+                    Some("medium") => QualityPreset::Medium,
+                    Some("high") => QualityPreset::High,
+                    Some("ultra") => QualityPreset::Ultra,
+                    _ => return Err("expected low, medium, high or ultra".to_owned()),
+                };
+                let previous = ctx.config.clone();
+                ctx.config.set_preset(preset);
+                ctx.config.apply(ctx.scene);
+                if ctx.config.needs_rebuild(&previous) {
+                    Ok("some settings apply once the graph is rebuilt".to_owned())
+                } else {
+                    Ok(String::new())
+                }
+            },
+        );
+
+        self.register_command(
+            "budget",
+            "budget <count|off>, set the draw budget",
+            |ctx, args| {
+                ctx.scene.draw_budget = match args.first() {
+                    Some(&"off") => None,
+                    arg => Some(parse(arg)?),
+                };
+                Ok(String::new())
+            },
+        );
+
+        self.register_command(
+            "teleport",
+            "teleport <x> <y> <z>, move the camera to a voxel",
+            |ctx, args| {
+                if args.len() != 3 {
+                    return Err("expected 3 coordinates".to_owned());
+                }
+                let pos = WorldPos::new(
+                    parse(args.get(0))?,
+                    parse(args.get(1))?,
+                    parse(args.get(2))?,
+                );
+                ctx.scene.teleport_camera(&Location::from_voxel(pos));
+                Ok(String::new())
+            },
+        );
+
+        self.register_command(
+            "weather",
+            "weather <clear|cloudy|rain|storm|snow>, change the weather",
+            |ctx, args| {
+                let weather = match args.first().map(|arg| arg.to_lowercase()).as_deref() {
+                    Some("clear") => Weather::Clear,
+                    Some("cloudy") => Weather::Cloudy,
+                    Some("rain") => Weather::Rain,
+                    Some("storm") => Weather::Storm,
+                    Some("snow") => Weather::Snow,
+                    _ => return Err("expected clear, cloudy, rain, storm or snow".to_owned()),
+                };
+                ctx.scene.set_weather(weather);
+                Ok(String::new())
+            },
+        );
+
+        self.register_command(
+            "clouds",
+            "clouds <off|flat|volumetric> [coverage], change the cloud layer",
+            |ctx, args| {
+                let mode = match args.first().map(|arg| arg.to_lowercase()).as_deref() {
+                    Some("off") => CloudMode::Off,
+                    Some("flat") => CloudMode::Flat,
+                    Some("volumetric") => CloudMode::Volumetric,
+                    _ => return Err("expected off, flat or volumetric".to_owned()),
+                };
+                if let Some(coverage) = args.get(1) {
+                    let coverage: f32 = coverage
+                        .parse()
+                        .map_err(|_| format!("invalid coverage `{}`", coverage))?;
+                    ctx.scene.clouds.coverage = coverage.max(0.0).min(1.0);
+                }
+                ctx.scene.clouds.mode = mode;
+                Ok(String::new())
+            },
+        );
+
+        self.register_command(
+            "dump_frame",
+            "dump_frame [folder], write the render targets of the next frame as PNG files",
+            |ctx, args| {
+                if !ctx.config.frame_dumps {
+                    return Err("frame dumps are off in the renderer config".to_owned());
+                }
+                let base = Path::new(args.first().cloned().unwrap_or("frame_dumps"));
+                let folder = timestamped_folder(base);
+                ctx.scene.request_frame_dump(folder.clone());
+                Ok(format!("dumping the next frame to {}", folder.display()))
+            },
+        );
+
+        self.register_command("stats", "stats, print the culling counts", |ctx, _| {
+            Ok(format!("{:?}", ctx.scene.culling_stats()))
+        });
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console::new()
+    }
+}
+
+impl std::fmt::Debug for Console {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Console({} commands)", self.commands.len())
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&&str>) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| "missing argument".to_owned())?;
+    arg.parse()
+        .map_err(|_| format!("invalid argument `{}`", arg))
+}
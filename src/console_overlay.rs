@@ -0,0 +1,303 @@
+//! Drop-down console drawn over the scene.
+//!
+//! `Console::overlay` gives the lines to show while the console is open,
+//! kept in `Scene::console`. The last group of the scene pass, or of the
+//! tonemap pass with `RendererConfig::hdr`, draws them at the top of the
+//! viewport: a panel, then the text from a built-in 5 by 7 bitmap font, both
+//! cleared like the letterbox bars. Letters are drawn in upper case,
+//! characters the font lacks as `?`.
+
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{PrepareResult, RenderGroup, RenderGroupDesc};
+use rendy::graph::{GraphContext, NodeBuffer, NodeBuildError, NodeImage};
+use rendy::hal;
+
+use crate::color::{Color, OutputEncoding};
+use crate::scene::Scene;
+
+/// Lines of an open console.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsoleOverlay {
+    /// Output, oldest first, the last ones fitting the panel are drawn.
+    pub output: Vec<String>,
+    /// Line being typed, drawn last after a prompt.
+    pub input: String,
+}
+
+/// Glyph size in font pixels.
+pub const GLYPH_WIDTH: i16 = 5;
+pub const GLYPH_HEIGHT: i16 = 7;
+
+/// Space taken by a glyph and the gap to the next, in font pixels.
+pub const CELL_WIDTH: i16 = GLYPH_WIDTH + 1;
+pub const CELL_HEIGHT: i16 = GLYPH_HEIGHT + 2;
+
+/// Part of the viewport height the panel covers.
+const PANEL_HEIGHT: f32 = 0.5;
+
+/// Target height drawn with one target pixel per font pixel.
+const BASE_HEIGHT: i16 = 360;
+
+const PANEL_COLOR: Color = Color::rgb(0.02, 0.02, 0.03);
+const TEXT_COLOR: Color = Color::rgb(0.85, 0.85, 0.8);
+
+/// Rows of the glyphs from ' ' to '_', the leftmost pixel in bit 4.
+const FONT: [[u8; 7]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // '!'
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A], // '#'
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], // '&'
+    [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // '0'
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // '1'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // '2'
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // '3'
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // '4'
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // '5'
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // '6'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // '8'
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], // '@'
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11], // 'A'
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // 'B'
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // 'C'
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // 'D'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // 'E'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // 'F'
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // 'G'
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // 'H'
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // 'L'
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // 'O'
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // 'P'
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // 'Q'
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // 'R'
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // 'S'
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // 'W'
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // 'X'
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // 'Y'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // 'Z'
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\\'
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], // ']'
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // '_'
+];
+
+/// Rows of the glyph of `c`, see `FONT`.
+pub fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        c @ ' '..='_' => FONT[c as usize - ' ' as usize],
+        '`' => [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00],
+        '{' => [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02],
+        '|' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        '}' => [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08],
+        '~' => [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00],
+        _ => glyph('?'),
+    }
+}
+
+/// Target pixels per font pixel in a `height` high target, at least 1.
+pub fn text_scale(height: i16) -> i16 {
+    (height / BASE_HEIGHT).max(1)
+}
+
+/// Rectangles of the overlay, `text` to draw over `panel`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayLayout {
+    pub panel: hal::pso::Rect,
+    pub text: Vec<hal::pso::Rect>,
+}
+
+/// Layout of `overlay` at the top of `area`, with `scale` target pixels
+/// per font pixel.
+///
+/// The input line is drawn at the bottom of the panel with the output
+/// lines fitting above it. Lines are cut at the width of the panel, the
+/// input keeping its end in view.
+pub fn layout(overlay: &ConsoleOverlay, area: hal::pso::Rect, scale: i16) -> OverlayLayout {
+    let panel = hal::pso::Rect {
+        h: (f32::from(area.h) * PANEL_HEIGHT).round() as i16,
+        ..area
+    };
+    let (cell_w, cell_h) = (CELL_WIDTH * scale, CELL_HEIGHT * scale);
+    let columns = ((panel.w - 2 * cell_w) / cell_w).max(0) as usize;
+    let rows = ((panel.h - cell_h) / cell_h).max(0) as usize;
+    let mut text = Vec::new();
+    if rows == 0 || columns == 0 {
+        return OverlayLayout { panel, text };
+    }
+
+    let input: Vec<char> = format!("> {}_", overlay.input).chars().collect();
+    let input: String = input[input.len().saturating_sub(columns)..]
+        .iter()
+        .collect();
+    let shown = overlay.output.len().min(rows - 1);
+    let lines = overlay.output[overlay.output.len() - shown..]
+        .iter()
+        .map(String::as_str)
+        .chain(Some(input.as_str()));
+    let top = panel.y + panel.h - cell_h * (shown as i16 + 1);
+    for (row, line) in lines.enumerate() {
+        let y = top + cell_h * row as i16;
+        for (column, c) in line.chars().take(columns).enumerate() {
+            let x = panel.x + cell_w + cell_w * column as i16;
+            glyph_rects(glyph(c), x, y, scale, &mut text);
+        }
+    }
+    OverlayLayout { panel, text }
+}
+
+/// Push the rectangles covering the pixels of `rows` drawn at `x`, `y`,
+/// one per run of pixels in a row.
+fn glyph_rects(rows: [u8; 7], x: i16, y: i16, scale: i16, rects: &mut Vec<hal::pso::Rect>) {
+    for (row, &bits) in rows.iter().enumerate() {
+        let lit = |column: i16| bits & (0x10 >> column) != 0;
+        let mut column = 0;
+        while column < GLYPH_WIDTH {
+            if !lit(column) {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < GLYPH_WIDTH && lit(column) {
+                column += 1;
+            }
+            rects.push(hal::pso::Rect {
+                x: x + start * scale,
+                y: y + row as i16 * scale,
+                w: (column - start) * scale,
+                h: scale,
+            });
+        }
+    }
+}
+
+/// Render group drawing `Scene::console`, added last to the pass drawing
+/// the final target.
+#[derive(Debug, Default)]
+pub(crate) struct ConsoleOverlayDesc {
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+
+    /// Whether the pass it's added to has a depth attachment.
+    pub depth: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct ConsoleOverlayGroup {
+    framebuffer: hal::pso::Rect,
+    output: OutputEncoding,
+}
+
+impl<B: hal::Backend> RenderGroupDesc<B, Scene> for ConsoleOverlayDesc {
+    fn colors(&self) -> usize {
+        1
+    }
+
+    fn depth(&self) -> bool {
+        self.depth
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &Scene,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        _subpass: hal::pass::Subpass<'_, B>,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, Scene>>, NodeBuildError> {
+        Ok(Box::new(ConsoleOverlayGroup {
+            framebuffer: hal::pso::Rect {
+                x: 0,
+                y: 0,
+                w: framebuffer_width as i16,
+                h: framebuffer_height as i16,
+            },
+            output: self.output,
+        }))
+    }
+}
+
+impl<B: hal::Backend> RenderGroup<B, Scene> for ConsoleOverlayGroup {
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        _aux: &Scene,
+    ) -> PrepareResult {
+        PrepareResult::DrawRecord
+    }
+
+    fn draw_inline(
+        &mut self,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        aux: &Scene,
+    ) {
+        let overlay = match aux.console {
+            Some(ref overlay) => overlay,
+            None => return,
+        };
+        let area = aux.viewport_in(self.framebuffer);
+        let layout = layout(overlay, area, text_scale(self.framebuffer.h));
+        if layout.panel.w <= 0 || layout.panel.h <= 0 {
+            return;
+        }
+        // Cleared in order, the text over the panel.
+        let clears = [(PANEL_COLOR, vec![layout.panel]), (TEXT_COLOR, layout.text)];
+        for (color, rects) in clears.iter() {
+            if rects.is_empty() {
+                continue;
+            }
+            unsafe {
+                encoder.clear_attachments(
+                    Some(hal::command::AttachmentClear::Color {
+                        index: 0,
+                        value: hal::command::ClearColor {
+                            float32: self.output.encode(*color),
+                        },
+                    }),
+                    rects
+                        .iter()
+                        .map(|&rect| hal::pso::ClearRect { rect, layers: 0..1 }),
+                );
+            }
+        }
+    }
+
+    fn dispose(self: Box<Self>, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
@@ -0,0 +1,282 @@
+//! World coordinates.
+//!
+//! Voxels are addressed with `i64` coordinates so worlds can grow far beyond
+//! what an `f32` can represent precisely. Rendering happens relative to a
+//! `FloatingOrigin` that follows the camera chunk by chunk.
+
+use nalgebra::{Transform3, Translation3, Vector3};
+
+/// Size of a chunk along each axis, in voxels.
+pub const CHUNK_SIZE: i64 = 32;
+
+/// Absolute position of a voxel in the world.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WorldPos {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl WorldPos {
+    pub fn new(x: i64, y: i64, z: i64) -> Self {
+        WorldPos { x, y, z }
+    }
+
+    /// Chunk containing this voxel, negative coordinates included.
+    pub fn chunk(&self) -> ChunkCoord {
+        ChunkCoord::new(
+            self.x.div_euclid(CHUNK_SIZE),
+            self.y.div_euclid(CHUNK_SIZE),
+            self.z.div_euclid(CHUNK_SIZE),
+        )
+    }
+
+    /// Position of this voxel inside its chunk.
+    pub fn local(&self) -> (usize, usize, usize) {
+        (
+            self.x.rem_euclid(CHUNK_SIZE) as usize,
+            self.y.rem_euclid(CHUNK_SIZE) as usize,
+            self.z.rem_euclid(CHUNK_SIZE) as usize,
+        )
+    }
+
+    /// Offset this position, returns `None` on overflow.
+    pub fn checked_offset(&self, dx: i64, dy: i64, dz: i64) -> Option<Self> {
+        Some(WorldPos {
+            x: self.x.checked_add(dx)?,
+            y: self.y.checked_add(dy)?,
+            z: self.z.checked_add(dz)?,
+        })
+    }
+}
+
+/// Position of a chunk in the world grid.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl ChunkCoord {
+    pub fn new(x: i64, y: i64, z: i64) -> Self {
+        ChunkCoord { x, y, z }
+    }
+
+    /// First voxel of the chunk.
+    pub fn min_voxel(&self) -> WorldPos {
+        WorldPos::new(
+            self.x.saturating_mul(CHUNK_SIZE),
+            self.y.saturating_mul(CHUNK_SIZE),
+            self.z.saturating_mul(CHUNK_SIZE),
+        )
+    }
+
+    /// Distance in voxels from `self` to `other`.
+    ///
+    /// The difference is computed in integers before the conversion so the
+    /// result stays precise as long as both chunks are close to each other.
+    pub fn offset_to(&self, other: &ChunkCoord) -> Vector3<f32> {
+        Vector3::new(
+            other.x.wrapping_sub(self.x) as f32,
+            other.y.wrapping_sub(self.y) as f32,
+            other.z.wrapping_sub(self.z) as f32,
+        ) * CHUNK_SIZE as f32
+    }
+}
+
+/// Precise position in the world: a chunk and an offset from its first voxel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Location {
+    pub chunk: ChunkCoord,
+    pub offset: Vector3<f32>,
+}
+
+impl Location {
+    pub fn new(chunk: ChunkCoord, offset: Vector3<f32>) -> Self {
+        let mut location = Location { chunk, offset };
+        location.normalize();
+        location
+    }
+
+    /// Location of the first corner of a voxel.
+    pub fn from_voxel(pos: WorldPos) -> Self {
+        let (x, y, z) = pos.local();
+        Location {
+            chunk: pos.chunk(),
+            offset: Vector3::new(x as f32, y as f32, z as f32),
+        }
+    }
+
+    /// Voxel containing this location.
+    pub fn voxel(&self) -> WorldPos {
+        let min = self.chunk.min_voxel();
+        WorldPos::new(
+            min.x.saturating_add(self.offset.x.floor() as i64),
+            min.y.saturating_add(self.offset.y.floor() as i64),
+            min.z.saturating_add(self.offset.z.floor() as i64),
+        )
+    }
+
+    /// Move the location, keeping the offset inside its chunk.
+    pub fn translate(&mut self, translation: &Vector3<f32>) {
+        self.offset += translation;
+        self.normalize();
+    }
+
+    /// Blend from `self` at `t = 0.0` to `other` at `t = 1.0`.
+    pub fn lerp(&self, other: &Location, t: f32) -> Self {
+        let delta = other.relative_to(&self.chunk) - self.offset;
+        Location::new(self.chunk, self.offset + delta * t)
+    }
+
+    /// Position relative to the first voxel of `origin`.
+    pub fn relative_to(&self, origin: &ChunkCoord) -> Vector3<f32> {
+        origin.offset_to(&self.chunk) + self.offset
+    }
+
+    /// Transfer whole chunks from the offset to the chunk coordinate.
+    fn normalize(&mut self) {
+        let size = CHUNK_SIZE as f32;
+        let shift = (self.offset / size).map(f32::floor);
+        self.chunk.x = self.chunk.x.saturating_add(shift.x as i64);
+        self.chunk.y = self.chunk.y.saturating_add(shift.y as i64);
+        self.chunk.z = self.chunk.z.saturating_add(shift.z as i64);
+        self.offset -= shift * size;
+    }
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Location {
+            chunk: ChunkCoord::default(),
+            offset: Vector3::zeros(),
+        }
+    }
+}
+
+/// Voxel range a world is allowed to span.
+///
+/// Worlds are sparse, bounds only restrict where chunks may exist.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WorldBounds {
+    pub min: WorldPos,
+    pub max: WorldPos,
+}
+
+impl WorldBounds {
+    pub fn new(min: WorldPos, max: WorldPos) -> Self {
+        WorldBounds { min, max }
+    }
+
+    /// Bounds covering every representable voxel.
+    pub fn infinite() -> Self {
+        WorldBounds {
+            min: WorldPos::new(i64::MIN, i64::MIN, i64::MIN),
+            max: WorldPos::new(i64::MAX, i64::MAX, i64::MAX),
+        }
+    }
+
+    pub fn contains(&self, pos: &WorldPos) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x)
+            && (self.min.y..=self.max.y).contains(&pos.y)
+            && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    /// Whether any voxel of `chunk` lies inside the bounds.
+    pub fn intersects_chunk(&self, chunk: &ChunkCoord) -> bool {
+        let min = chunk.min_voxel();
+        let max = WorldPos::new(
+            min.x.saturating_add(CHUNK_SIZE - 1),
+            min.y.saturating_add(CHUNK_SIZE - 1),
+            min.z.saturating_add(CHUNK_SIZE - 1),
+        );
+        min.x <= self.max.x
+            && max.x >= self.min.x
+            && min.y <= self.max.y
+            && max.y >= self.min.y
+            && min.z <= self.max.z
+            && max.z >= self.min.z
+    }
+
+    pub fn clamp(&self, pos: &WorldPos) -> WorldPos {
+        WorldPos::new(
+            pos.x.max(self.min.x).min(self.max.x),
+            pos.y.max(self.min.y).min(self.max.y),
+            pos.z.max(self.min.z).min(self.max.z),
+        )
+    }
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        WorldBounds::infinite()
+    }
+}
+
+/// Origin used to convert world locations into render space.
+///
+/// The origin jumps from chunk to chunk to stay close to the camera, keeping
+/// render space coordinates small enough for `f32`.
+#[derive(Debug, Copy, Clone)]
+pub struct FloatingOrigin {
+    origin: ChunkCoord,
+
+    /// Distance in voxels the focus may move away before the origin follows.
+    pub threshold: f32,
+}
+
+impl FloatingOrigin {
+    pub fn new(threshold: f32) -> Self {
+        FloatingOrigin {
+            origin: ChunkCoord::default(),
+            threshold,
+        }
+    }
+
+    pub fn origin(&self) -> ChunkCoord {
+        self.origin
+    }
+
+    /// Render space position of a world location.
+    pub fn to_render(&self, location: &Location) -> Vector3<f32> {
+        location.relative_to(&self.origin)
+    }
+
+    /// Move the origin to the chunk of `focus` if it went too far.
+    ///
+    /// Returns the translation to apply to everything already expressed in
+    /// render space, see `rebase`.
+    pub fn update(&mut self, focus: &Location) -> Option<Vector3<f32>> {
+        if self.to_render(focus).norm() <= self.threshold {
+            return None;
+        }
+        debug!("Floating origin moved to {:?}.", focus.chunk);
+        Some(self.move_to(focus.chunk))
+    }
+
+    /// Move the origin to `chunk`, however far it is, returns the
+    /// translation like `update`.
+    ///
+    /// Locations of `chunk` are then exact in render space, whereas
+    /// converting a distant location to render space first loses precision.
+    pub fn move_to(&mut self, chunk: ChunkCoord) -> Vector3<f32> {
+        let shift = chunk.offset_to(&self.origin);
+        self.origin = chunk;
+        shift
+    }
+
+    /// Apply the translation returned by `update` to render space transforms.
+    pub fn rebase(shift: &Vector3<f32>, transforms: &mut [Transform3<f32>]) {
+        let translation = Translation3::from(*shift).to_homogeneous();
+        for transform in transforms.iter_mut() {
+            *transform = Transform3::from_matrix_unchecked(translation * transform.matrix());
+        }
+    }
+}
+
+impl Default for FloatingOrigin {
+    fn default() -> Self {
+        FloatingOrigin::new(CHUNK_SIZE as f32 * 4.0)
+    }
+}
@@ -0,0 +1,496 @@
+//! Fade from the last frame of a render graph to the first frames of the
+//! graph replacing it.
+//!
+//! With `RendererConfig::crossfade` set the scene pass draws into a final
+//! image blitted to the surface. `Renderer::rebuild` draws one more frame
+//! with that image copied to the host before disposing the graph, the copy
+//! lands once the device is idle. The last group of the new scene pass
+//! draws the retained frame over the new one, fading it out.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use rendy::command::{
+    CommandBuffer, CommandPool, ExecutableState, Family, Fence, MultiShot, PendingState, Queue,
+    QueueId, RenderPassEncoder, SimultaneousUse, Submission, Submit, Transfer,
+};
+use rendy::factory::Factory;
+use rendy::frame::Frames;
+use rendy::graph::render::{
+    Layout, PrepareResult, SetLayout, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{
+    gfx_acquire_barriers, gfx_release_barriers, GraphContext, ImageAccess, Node, NodeBuffer,
+    NodeBuildError, NodeDesc, NodeImage,
+};
+use rendy::hal;
+use rendy::resource::{BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::color::OutputEncoding;
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::mapped::MappedBuffer;
+use crate::readback::Readback;
+use crate::scene::Scene;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../crossfade.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/crossfade.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../crossfade.frag"));
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/crossfade.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+}
+
+/// Last frame of a disposed graph.
+#[derive(Clone)]
+pub struct RetainedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub format: hal::format::Format,
+
+    /// One texel per `u32`, rows tightly packed.
+    pub texels: Vec<u32>,
+}
+
+impl std::fmt::Debug for RetainedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "RetainedFrame({}x{}, {:?})",
+            self.width, self.height, self.format
+        )
+    }
+}
+
+/// Channel order and encoding of the formats a frame can be retained in,
+/// blue first and sRGB.
+pub fn texel_layout(format: hal::format::Format) -> Option<(bool, bool)> {
+    use hal::format::Format;
+    match format {
+        Format::Rgba8Unorm => Some((false, false)),
+        Format::Rgba8Srgb => Some((false, true)),
+        Format::Bgra8Unorm => Some((true, false)),
+        Format::Bgra8Srgb => Some((true, true)),
+        _ => None,
+    }
+}
+
+/// Capture request and retained frame, kept by the scene across rebuilds.
+#[derive(Debug, Default)]
+pub(crate) struct Retained {
+    capture: AtomicBool,
+    frame: Arc<Mutex<Option<RetainedFrame>>>,
+}
+
+impl Retained {
+    /// Copy the final image of the next frame.
+    pub fn request_capture(&self) {
+        self.capture.store(true, Ordering::Relaxed);
+    }
+
+    fn take_request(&self) -> bool {
+        self.capture.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn take(&self) -> Option<RetainedFrame> {
+        self.frame.lock().unwrap().take()
+    }
+}
+
+/// Copies the final image to the host when a capture is requested,
+/// expects the final image.
+#[derive(Debug, Default)]
+pub(crate) struct CaptureNodeDesc;
+
+pub(crate) struct CaptureNode<B: hal::Backend> {
+    readback: Readback<B>,
+    extent: hal::image::Extent,
+    format: hal::format::Format,
+    command_pool: CommandPool<B, Transfer>,
+    command_buffers:
+        Vec<CommandBuffer<B, Transfer, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>>,
+    /// Submits of each frame, without and with the copy.
+    submits: Vec<(Submit<B, SimultaneousUse>, Submit<B, SimultaneousUse>)>,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for CaptureNode<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Capture Node")
+    }
+}
+
+impl<B> NodeDesc<B, Scene> for CaptureNodeDesc
+where
+    B: hal::Backend,
+{
+    type Node = CaptureNode<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::TRANSFER_READ,
+            usage: hal::image::Usage::TRANSFER_SRC,
+            layout: hal::image::Layout::TransferSrcOptimal,
+            stages: hal::pso::PipelineStage::TRANSFER,
+        }]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        aux: &Scene,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 1);
+
+        // A request made for a graph without capture is stale by now.
+        aux.retained().take_request();
+
+        let image = ctx.get_image(images[0].id).unwrap();
+        let extent = image.kind().extent();
+        let format = image.format();
+        let frames = ctx.frames_in_flight as usize;
+        let size = extent.width as u64 * extent.height as u64 * 4;
+        let readback = Readback::new(factory, size, frames);
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Transfer>()
+            .expect("Graph builder must provide family with Transfer capability");
+
+        let mut command_buffers = Vec::new();
+        let mut submits = Vec::new();
+        let mut initials = command_pool.allocate_buffers(frames * 2).into_iter();
+        for index in 0..frames {
+            let mut pair = Vec::with_capacity(2);
+            for copy in &[false, true] {
+                let initial = initials.next().unwrap();
+                let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+                let mut encoder = recording.encoder();
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, &buffers, &images);
+                    encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                }
+                if *copy {
+                    unsafe {
+                        readback.record_image_copy(
+                            &mut encoder,
+                            image.raw(),
+                            hal::image::Layout::TransferSrcOptimal,
+                            extent,
+                            4,
+                            readback.slot(index as u64),
+                        );
+                    }
+                }
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, &buffers, &images);
+                    encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                }
+                let (submit, command_buffer) = recording.finish().submit();
+                pair.push(submit);
+                command_buffers.push(command_buffer);
+            }
+            let copy = pair.pop().unwrap();
+            let plain = pair.pop().unwrap();
+            submits.push((plain, copy));
+        }
+
+        Ok(CaptureNode {
+            readback,
+            extent,
+            format,
+            command_pool,
+            command_buffers,
+            submits,
+        })
+    }
+}
+
+impl<B> Node<B, Scene> for CaptureNode<B>
+where
+    B: hal::Backend,
+{
+    type Capability = Transfer;
+
+    fn run<'a>(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        queue: &mut Queue<B>,
+        aux: &Scene,
+        frames: &Frames<B>,
+        waits: &[(&'a B::Semaphore, hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        let index = frames.next().index() as usize % self.submits.len();
+        let slot = self.readback.slot(index as u64);
+        self.readback.complete(factory, slot);
+
+        let (ref plain, ref copy) = self.submits[index];
+        let submit = if aux.retained().take_request() {
+            let (width, height, format) = (self.extent.width, self.extent.height, self.format);
+            let frame = aux.retained().frame.clone();
+            let size = self.readback.slot_size();
+            self.readback.request(slot, size, move |data| {
+                let texels = data
+                    .chunks_exact(4)
+                    .map(|texel| u32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+                    .collect();
+                *frame.lock().unwrap() = Some(RetainedFrame {
+                    width,
+                    height,
+                    format,
+                    texels,
+                });
+            });
+            copy
+        } else {
+            plain
+        };
+
+        unsafe {
+            queue.submit(
+                Some(
+                    Submission::new()
+                        .submits(Some(submit))
+                        .wait(waits.iter().cloned())
+                        .signal(signals.iter()),
+                ),
+                fence,
+            );
+        }
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &Scene) {
+        info!("Disposing Capture Node.");
+        // The device is idle, deliver the copies still pending.
+        for slot in 0..self.submits.len() {
+            self.readback.complete(factory, slot);
+        }
+        drop(self.submits);
+        self.command_pool.free_buffers(
+            self.command_buffers
+                .into_iter()
+                .map(|command_buffer| command_buffer.mark_complete()),
+        );
+        factory.destroy_command_pool(self.command_pool);
+    }
+}
+
+/// Size of the push constants, in `u32`s.
+const CONSTANTS: usize = 7;
+
+/// Draws the retained frame over the scene, fading out over `duration`
+/// seconds. Added to the pass drawing the final image, before the console.
+#[derive(Debug, Default)]
+pub(crate) struct CrossfadeDesc {
+    pub duration: f32,
+
+    /// Size of the framebuffer drawn to.
+    pub width: u32,
+    pub height: u32,
+
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
+
+pub(crate) struct Crossfade<B: hal::Backend> {
+    duration: f32,
+    retained: Option<(MappedBuffer<B>, Escape<DescriptorSet<B>>)>,
+    started: Option<Instant>,
+    constants: [u32; CONSTANTS],
+}
+
+impl<B: hal::Backend> std::fmt::Debug for Crossfade<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Crossfade({}s)", self.duration)
+    }
+}
+
+impl<B: hal::Backend> Crossfade<B> {
+    /// Opacity of the retained frame, zero once faded out.
+    fn alpha(&self) -> f32 {
+        let elapsed = self
+            .started
+            .map_or(0.0, |started| started.elapsed().as_secs_f32());
+        let t = (elapsed / self.duration).min(1.0);
+        1.0 - t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for CrossfadeDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Crossfade<B>;
+
+    fn colors(&self) -> Vec<hal::pso::ColorBlendDesc> {
+        vec![hal::pso::ColorBlendDesc {
+            mask: hal::pso::ColorMask::ALL,
+            blend: Some(hal::pso::BlendState::ALPHA),
+        }]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        None
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        let constants = SpecConstants::new().with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: vec![SetLayout {
+                bindings: vec![hal::pso::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: hal::pso::DescriptorType::StorageBuffer,
+                    count: 1,
+                    stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                }],
+            }],
+            push_constants: vec![(
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0..(CONSTANTS * 4) as u32,
+            )],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        aux: &Scene,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        let mut constants = [0; CONSTANTS];
+        let retained = aux.retained().take().and_then(|frame| {
+            let (bgra, srgb) = texel_layout(frame.format)?;
+            let values = [
+                (frame.width as f32 / self.width as f32).to_bits(),
+                (frame.height as f32 / self.height as f32).to_bits(),
+                frame.width,
+                frame.height,
+                1.0f32.to_bits(),
+                bgra as u32,
+                srgb as u32,
+            ];
+            constants.copy_from_slice(&values);
+
+            let mut buffer = MappedBuffer::new(
+                factory,
+                BufferInfo {
+                    size: (frame.texels.len() * 4) as u64,
+                    usage: hal::buffer::Usage::STORAGE,
+                },
+            );
+            unsafe { buffer.write(factory, 0, &frame.texels) };
+            let set = factory
+                .create_descriptor_set(set_layouts[0].clone())
+                .unwrap();
+            unsafe {
+                factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Buffer(buffer.raw(), None..None)),
+                }));
+            }
+            Some((buffer, set))
+        });
+
+        Ok(Crossfade {
+            duration: self.duration,
+            retained,
+            started: None,
+            constants,
+        })
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Crossfade<B>
+where
+    B: hal::Backend,
+{
+    type Desc = CrossfadeDesc;
+
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        _aux: &Scene,
+    ) -> PrepareResult {
+        if self.retained.is_some() {
+            // The fade starts with the first frame of the graph.
+            self.started.get_or_insert_with(Instant::now);
+            self.constants[4] = self.alpha().to_bits();
+        }
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        _aux: &Scene,
+    ) {
+        // Frames still in flight may read the retained texels, they are
+        // kept until the graph is disposed.
+        let set = match self.retained {
+            Some((_, ref set)) if self.alpha() > 0.0 => set,
+            _ => return,
+        };
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(layout, 0, Some(set.raw()), std::iter::empty());
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.constants,
+            );
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
@@ -0,0 +1,647 @@
+//! Visibility tests shared by the render passes.
+
+use nalgebra::{Isometry3, Matrix4, Orthographic3, Perspective3, Point3, Vector3, Vector4};
+
+use crate::camera::Camera;
+
+/// Plane where `normal.dot(p) + d >= 0` is the inner side.
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub d: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = Vector3::new(row.x, row.y, row.z);
+        let length = normal.norm();
+        Plane {
+            normal: normal / length,
+            d: row.w / length,
+        }
+    }
+
+    /// Signed distance from the plane, positive on the inner side.
+    pub fn distance(&self, point: &Point3<f32>) -> f32 {
+        self.normal.dot(&point.coords) + self.d
+    }
+}
+
+/// Axis aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Aabb { min, max }
+    }
+
+    /// Smallest box containing every point, `None` without points.
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Aabb::new(first, first), |aabb, point| {
+            aabb.union(&Aabb::new(point, point))
+        }))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::from(self.min.coords.zip_map(&other.min.coords, f32::min)),
+            max: Point3::from(self.max.coords.zip_map(&other.max.coords, f32::max)),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        nalgebra::center(&self.min, &self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point3::new(a.x, a.y, a.z),
+            Point3::new(b.x, a.y, a.z),
+            Point3::new(a.x, b.y, a.z),
+            Point3::new(b.x, b.y, a.z),
+            Point3::new(a.x, a.y, b.z),
+            Point3::new(b.x, a.y, b.z),
+            Point3::new(a.x, b.y, b.z),
+            Point3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    /// Bounding box of this box once transformed.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Aabb {
+        Aabb::from_points(
+            self.corners()
+                .iter()
+                .map(|corner| Point3::from_homogeneous(matrix * corner.to_homogeneous()).unwrap()),
+        )
+        .unwrap()
+    }
+
+    /// Center and radius of a sphere containing the box.
+    pub fn bounding_sphere(&self) -> (Point3<f32>, f32) {
+        (self.center(), self.half_extents().norm())
+    }
+
+    /// Box covering every position of this box moved along `offset`.
+    pub fn sweep(&self, offset: &Vector3<f32>) -> Aabb {
+        self.union(&Aabb::new(self.min + offset, self.max + offset))
+    }
+}
+
+/// View volume described by six planes.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near, far.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extract the planes of a view-projection matrix.
+    pub fn from_matrix(view_proj: &Matrix4<f32>) -> Self {
+        let row = |i: usize| view_proj.row(i).transpose();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                Plane::from_row(r3 + r0),
+                Plane::from_row(r3 - r0),
+                Plane::from_row(r3 + r1),
+                Plane::from_row(r3 - r1),
+                Plane::from_row(r3 + r2),
+                Plane::from_row(r3 - r2),
+            ],
+        }
+    }
+
+    /// Frustum seen by a camera.
+    pub fn from_camera(camera: &Camera) -> Self {
+        Frustum::from_matrix(
+            &(camera.proj.to_homogeneous() * camera.view.inverse().to_homogeneous()),
+        )
+    }
+
+    pub fn contains_sphere(&self, center: &Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(center) >= -radius)
+    }
+
+    /// Conservative box test, boxes near the frustum corners may pass.
+    pub fn contains_aabb(&self, min: &Point3<f32>, max: &Point3<f32>) -> bool {
+        let aabb = Aabb::new(*min, *max);
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(&positive_vertex(&aabb, plane)) >= 0.0)
+    }
+
+    /// Push the indices of the boxes passing `contains_aabb` to `out`.
+    ///
+    /// With the `simd` feature the boxes are tested eight at a time.
+    pub fn cull_aabbs(&self, aabbs: &[Aabb], out: &mut Vec<usize>) {
+        #[cfg(feature = "simd")]
+        let start = self.cull_aabbs_simd(aabbs, out);
+        #[cfg(not(feature = "simd"))]
+        let start = 0;
+
+        for (i, aabb) in aabbs.iter().enumerate().skip(start) {
+            if self.contains_aabb(&aabb.min, &aabb.max) {
+                out.push(i);
+            }
+        }
+    }
+
+    /// Test whole groups of eight boxes, return the index of the first untested box.
+    #[cfg(feature = "simd")]
+    fn cull_aabbs_simd(&self, aabbs: &[Aabb], out: &mut Vec<usize>) -> usize {
+        use wide::f32x8;
+
+        let lanes = aabbs.len() / 8 * 8;
+        for start in (0..lanes).step_by(8) {
+            let mut center = [[0.0f32; 8]; 3];
+            let mut extent = [[0.0f32; 8]; 3];
+            for (lane, aabb) in aabbs[start..start + 8].iter().enumerate() {
+                let (c, e) = (aabb.center(), aabb.half_extents());
+                for axis in 0..3 {
+                    center[axis][lane] = c[axis];
+                    extent[axis][lane] = e[axis];
+                }
+            }
+            let center = [
+                f32x8::from(center[0]),
+                f32x8::from(center[1]),
+                f32x8::from(center[2]),
+            ];
+            let extent = [
+                f32x8::from(extent[0]),
+                f32x8::from(extent[1]),
+                f32x8::from(extent[2]),
+            ];
+
+            // Distance of the positive vertex, like `contains_aabb`.
+            let mut distance = f32x8::splat(std::f32::MAX);
+            for plane in &self.planes {
+                let mut d = f32x8::splat(plane.d);
+                for axis in 0..3 {
+                    let n = plane.normal[axis];
+                    d = center[axis].mul_add(f32x8::splat(n), d);
+                    d = extent[axis].mul_add(f32x8::splat(n.abs()), d);
+                }
+                distance = distance.min(d);
+            }
+
+            let mask = distance.cmp_ge(f32x8::ZERO).move_mask();
+            out.extend(
+                (0..8)
+                    .filter(|lane| mask & (1 << lane) != 0)
+                    .map(|lane| start + lane),
+            );
+        }
+        lanes
+    }
+}
+
+/// Counts of one frame of culling.
+///
+/// The GPU culling pre-pass isn't read back, with it only `tested` is known.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CullingStats {
+    pub tested: usize,
+    pub frustum_culled: usize,
+    pub occlusion_culled: usize,
+
+    /// Further than the maximum distance of their class.
+    pub distance_culled: usize,
+
+    /// Visible but dropped to respect the draw budget.
+    pub budget_culled: usize,
+
+    /// Drawn as billboards instead of meshes, see `impostor`.
+    pub impostors: usize,
+
+    pub drawn: usize,
+}
+
+impl CullingStats {
+    /// Add the counts of `other`, for things culled separately.
+    pub fn add(&mut self, other: &CullingStats) {
+        self.tested += other.tested;
+        self.frustum_culled += other.frustum_culled;
+        self.occlusion_culled += other.occlusion_culled;
+        self.distance_culled += other.distance_culled;
+        self.budget_culled += other.budget_culled;
+        self.impostors += other.impostors;
+        self.drawn += other.drawn;
+    }
+
+    /// Names and values, as recorded in the profiler counters.
+    pub fn counters(&self) -> [(&'static str, u64); 7] {
+        [
+            ("culling.tested", self.tested as u64),
+            ("culling.frustum", self.frustum_culled as u64),
+            ("culling.occlusion", self.occlusion_culled as u64),
+            ("culling.distance", self.distance_culled as u64),
+            ("culling.budget", self.budget_culled as u64),
+            ("culling.impostors", self.impostors as u64),
+            ("culling.drawn", self.drawn as u64),
+        ]
+    }
+}
+
+/// Drop the `visible` boxes whose bounding sphere is further from `eye`
+/// than `max_distance` of their index, return the number dropped.
+pub fn cull_distance<F>(
+    visible: &mut Vec<usize>,
+    aabbs: &[Aabb],
+    eye: &Point3<f32>,
+    max_distance: F,
+) -> usize
+where
+    F: Fn(usize) -> Option<f32>,
+{
+    let before = visible.len();
+    visible.retain(|&i| {
+        max_distance(i).map_or(true, |max| {
+            let (center, radius) = aabbs[i].bounding_sphere();
+            (center - eye).norm() - radius <= max
+        })
+    });
+    before - visible.len()
+}
+
+/// Rough screen size of a box seen from `eye`, used to rank draws.
+pub fn importance(aabb: &Aabb, eye: &Point3<f32>) -> f32 {
+    let radius = aabb.half_extents().norm();
+    let distance = (aabb.center() - eye).norm();
+    radius / distance.max(radius).max(std::f32::EPSILON)
+}
+
+/// Keep the `budget` most important of the `visible` boxes, return the number dropped.
+///
+/// The order of the kept indices is not preserved.
+pub fn apply_budget(
+    visible: &mut Vec<usize>,
+    aabbs: &[Aabb],
+    eye: &Point3<f32>,
+    budget: usize,
+) -> usize {
+    if visible.len() <= budget {
+        return 0;
+    }
+    let dropped = visible.len() - budget;
+    if budget > 0 {
+        visible.select_nth_unstable_by(budget - 1, |&a, &b| {
+            importance(&aabbs[b], eye)
+                .partial_cmp(&importance(&aabbs[a], eye))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+    visible.truncate(budget);
+    dropped
+}
+
+const BVH_LEAF_SIZE: usize = 4;
+
+#[derive(Debug)]
+enum BvhNode {
+    Leaf {
+        aabb: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Inner {
+        aabb: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+/// Bounding volume hierarchy over a list of boxes.
+///
+/// Queries return indices into the slice used to build it.
+#[derive(Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    items: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(aabbs: &[Aabb]) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(aabbs.len() / BVH_LEAF_SIZE * 2 + 1),
+            items: (0..aabbs.len()).collect(),
+        };
+        if !aabbs.is_empty() {
+            bvh.build_node(aabbs, 0, aabbs.len());
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, aabbs: &[Aabb], start: usize, end: usize) -> usize {
+        let aabb = self.items[start + 1..end]
+            .iter()
+            .fold(aabbs[self.items[start]], |acc, &item| {
+                acc.union(&aabbs[item])
+            });
+        let index = self.nodes.len();
+        if end - start <= BVH_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf { aabb, start, end });
+            return index;
+        }
+
+        // Median split along the largest axis.
+        let extents = aabb.half_extents();
+        let axis = extents.imax();
+        self.items[start..end].sort_by(|&a, &b| {
+            aabbs[a].center()[axis]
+                .partial_cmp(&aabbs[b].center()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let middle = (start + end) / 2;
+
+        self.nodes.push(BvhNode::Inner {
+            aabb,
+            left: 0,
+            right: 0,
+        });
+        let left_node = self.build_node(aabbs, start, middle);
+        let right_node = self.build_node(aabbs, middle, end);
+        if let BvhNode::Inner { left, right, .. } = &mut self.nodes[index] {
+            *left = left_node;
+            *right = right_node;
+        }
+        index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Push every item whose box passes `test`, subtrees failing it are skipped.
+    pub fn query<F>(&self, mut test: F, out: &mut Vec<usize>)
+    where
+        F: FnMut(&Aabb) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            match self.nodes[node] {
+                BvhNode::Leaf {
+                    ref aabb,
+                    start,
+                    end,
+                } => {
+                    if test(aabb) {
+                        out.extend_from_slice(&self.items[start..end]);
+                    }
+                }
+                BvhNode::Inner {
+                    ref aabb,
+                    left,
+                    right,
+                } => {
+                    if test(aabb) {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Items potentially inside the frustum.
+    pub fn cull(&self, frustum: &Frustum, out: &mut Vec<usize>) {
+        self.query(|aabb| frustum.contains_aabb(&aabb.min, &aabb.max), out)
+    }
+}
+
+/// Split distances of `count` cascades between `near` and `far`.
+///
+/// `lambda` blends between uniform (0.0) and logarithmic (1.0) splits.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (0..=count)
+        .map(|i| {
+            let ratio = i as f32 / count as f32;
+            let log = near * (far / near).powf(ratio);
+            let uniform = near + (far - near) * ratio;
+            lambda * log + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// One slice of the camera frustum and the light volume covering it.
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowCascade {
+    pub near: f32,
+    pub far: f32,
+
+    /// View-projection matrix of the light for this cascade.
+    pub light_view_proj: Matrix4<f32>,
+
+    /// Volume rendered in the cascade shadow map.
+    pub light_frustum: Frustum,
+
+    /// Slice of the camera frustum receiving the shadows.
+    pub receiver_frustum: Frustum,
+
+    /// Depth of the light volume, casters are swept this far along the light.
+    pub depth: f32,
+}
+
+impl ShadowCascade {
+    /// Fit an orthographic light volume around a slice of the camera frustum.
+    pub fn new(camera: &Camera, light_dir: &Vector3<f32>, near: f32, far: f32) -> Self {
+        let (receivers, corners) = frustum_slice(camera, near, far);
+        let center = corners
+            .iter()
+            .fold(Vector3::zeros(), |acc, corner| acc + corner.coords)
+            / corners.len() as f32;
+        let direction = light_dir.normalize();
+        let light_view = Isometry3::look_at_rh(
+            &Point3::from(center - direction),
+            &Point3::from(center),
+            &light_up(&direction),
+        )
+        .to_homogeneous();
+
+        let bounds = Aabb::from_points(
+            corners
+                .iter()
+                .map(|corner| light_view.transform_point(corner)),
+        )
+        .unwrap();
+        // Light space looks down -z, the near side of the volume is bounds.max.z.
+        let depth = bounds.max.z - bounds.min.z;
+        let proj = Orthographic3::new(
+            bounds.min.x,
+            bounds.max.x,
+            bounds.min.y,
+            bounds.max.y,
+            -bounds.max.z,
+            -bounds.min.z,
+        );
+        let light_view_proj = proj.to_homogeneous() * light_view;
+
+        ShadowCascade {
+            near,
+            far,
+            light_view_proj,
+            light_frustum: Frustum::from_matrix(&light_view_proj),
+            receiver_frustum: Frustum::from_matrix(&receivers),
+            depth,
+        }
+    }
+
+    /// Fit a light volume of fixed size around the bounding sphere of a
+    /// slice of the camera frustum, moved by whole texels of a shadow map
+    /// `resolution` texels wide.
+    ///
+    /// Looser than `new` but the shadow edges don't shimmer as the camera
+    /// moves or turns. Suits a single cascade over the ground seen by a
+    /// top-down or isometric camera.
+    pub fn stable(
+        camera: &Camera,
+        light_dir: &Vector3<f32>,
+        near: f32,
+        far: f32,
+        resolution: u32,
+    ) -> Self {
+        let (receivers, corners) = frustum_slice(camera, near, far);
+        let center = corners
+            .iter()
+            .fold(Vector3::zeros(), |acc, corner| acc + corner.coords)
+            / corners.len() as f32;
+        let radius = corners
+            .iter()
+            .map(|corner| (corner.coords - center).norm())
+            .fold(0.0, f32::max);
+        // Rounded up so float noise in the corners doesn't resize it.
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        // Rotation only, the volume moves in light space.
+        let direction = light_dir.normalize();
+        let light_view = Isometry3::look_at_rh(
+            &Point3::origin(),
+            &Point3::from(direction),
+            &light_up(&direction),
+        )
+        .to_homogeneous();
+        let texel = radius * 2.0 / resolution.max(1) as f32;
+        let center = light_view.transform_point(&Point3::from(center));
+        let (x, y) = (
+            (center.x / texel).floor() * texel,
+            (center.y / texel).floor() * texel,
+        );
+        let proj = Orthographic3::new(
+            x - radius,
+            x + radius,
+            y - radius,
+            y + radius,
+            -center.z - radius,
+            -center.z + radius,
+        );
+        let light_view_proj = proj.to_homogeneous() * light_view;
+
+        ShadowCascade {
+            near,
+            far,
+            light_view_proj,
+            light_frustum: Frustum::from_matrix(&light_view_proj),
+            receiver_frustum: Frustum::from_matrix(&receivers),
+            depth: radius * 2.0,
+        }
+    }
+
+    /// Indices of the boxes casting a shadow into this cascade.
+    ///
+    /// A caster must lie in the light volume, or between it and the light,
+    /// and its shadow, swept along `light_dir`, must reach the receivers.
+    pub fn cull_casters(&self, bvh: &Bvh, light_dir: &Vector3<f32>, out: &mut Vec<usize>) {
+        let sweep = light_dir.normalize() * self.depth;
+        let light = self.light_frustum.planes;
+        // Casters between the light and the volume still cast shadows into it.
+        let side_planes = &light[..4];
+        let far_plane = &light[5];
+        bvh.query(
+            |aabb| {
+                let in_light = side_planes
+                    .iter()
+                    .chain(std::iter::once(far_plane))
+                    .all(|plane| plane.distance(&positive_vertex(aabb, plane)) >= 0.0);
+                let shadow = aabb.sweep(&sweep);
+                in_light
+                    && self
+                        .receiver_frustum
+                        .contains_aabb(&shadow.min, &shadow.max)
+            },
+            out,
+        );
+    }
+}
+
+/// Build cascades for a camera, see `cascade_splits`.
+pub fn shadow_cascades(
+    camera: &Camera,
+    light_dir: &Vector3<f32>,
+    count: usize,
+    distance: f32,
+) -> Vec<ShadowCascade> {
+    let splits = cascade_splits(camera.proj.znear(), distance, count, 0.75);
+    splits
+        .windows(2)
+        .map(|split| ShadowCascade::new(camera, light_dir, split[0], split[1]))
+        .collect()
+}
+
+/// View-projection matrix of the camera frustum between `near` and `far`,
+/// and the corners of that slice.
+fn frustum_slice(camera: &Camera, near: f32, far: f32) -> (Matrix4<f32>, Vec<Point3<f32>>) {
+    let slice = Perspective3::new(camera.proj.aspect(), camera.proj.fovy(), near, far);
+    let view_proj = slice.to_homogeneous() * camera.view.inverse().to_homogeneous();
+    let inverse = view_proj.try_inverse().unwrap_or_else(Matrix4::identity);
+    let corners = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+        .corners()
+        .iter()
+        .map(|corner| Point3::from_homogeneous(inverse * corner.to_homogeneous()).unwrap())
+        .collect();
+    (view_proj, corners)
+}
+
+/// Up vector of a view looking along `direction`.
+fn light_up(direction: &Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        Vector3::z()
+    } else {
+        Vector3::y()
+    }
+}
+
+fn positive_vertex(aabb: &Aabb, plane: &Plane) -> Point3<f32> {
+    Point3::new(
+        if plane.normal.x >= 0.0 {
+            aabb.max.x
+        } else {
+            aabb.min.x
+        },
+        if plane.normal.y >= 0.0 {
+            aabb.max.y
+        } else {
+            aabb.min.y
+        },
+        if plane.normal.z >= 0.0 {
+            aabb.max.z
+        } else {
+            aabb.min.z
+        },
+    )
+}
@@ -0,0 +1,105 @@
+//! Colors of the debug views.
+//!
+//! Debug views color voxels by a value, such as the LOD level or the light
+//! level of their chunk, or the chunk itself. `Rainbow` spreads the values
+//! over the hues, which red-green color blindness makes unreadable.
+//! `ColorblindSafe` keeps them apart with deuteranopia and protanopia: the
+//! Okabe-Ito colors for categories and the cividis ramp, dark blue to
+//! yellow with a steadily rising lightness, for levels.
+
+use crate::color::Color;
+use crate::coords::ChunkCoord;
+use crate::lighting::MAX_LIGHT;
+
+/// Okabe-Ito colors, sRGB, with dark grey in place of black so shading
+/// still shows.
+const OKABE_ITO: [u32; 8] = [
+    0xe69f00, 0x56b4e9, 0x009e73, 0xf0e442, 0x0072b2, 0xd55e00, 0xcc79a7, 0x404040,
+];
+
+/// Cividis at 0, 1/4, 1/2, 3/4 and 1, sRGB.
+const CIVIDIS: [u32; 5] = [0x00204d, 0x414d6b, 0x7c7b78, 0xbcaf6f, 0xffea46];
+
+/// Categories `Rainbow` cycles through before repeating a hue.
+const RAINBOW_CATEGORIES: usize = 8;
+
+/// Colors of the debug views, see `Scene::debug_palette`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugPalette {
+    Rainbow,
+
+    /// Readable with deuteranopia and protanopia.
+    ColorblindSafe,
+}
+
+impl Default for DebugPalette {
+    fn default() -> Self {
+        DebugPalette::Rainbow
+    }
+}
+
+impl DebugPalette {
+    /// Color of a category, cycling after 8 categories.
+    pub fn category(self, index: usize) -> Color {
+        match self {
+            DebugPalette::Rainbow => {
+                let index = index % RAINBOW_CATEGORIES;
+                hue(index as f32 / RAINBOW_CATEGORIES as f32)
+            }
+            DebugPalette::ColorblindSafe => hex(OKABE_ITO[index % OKABE_ITO.len()]),
+        }
+    }
+
+    /// Color of a level in `0.0..=1.0`, clamped.
+    pub fn ramp(self, t: f32) -> Color {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            // Blue for the lowest levels to red for the highest.
+            DebugPalette::Rainbow => hue((1.0 - t) * 2.0 / 3.0),
+            DebugPalette::ColorblindSafe => {
+                let position = t * (CIVIDIS.len() - 1) as f32;
+                let index = (position as usize).min(CIVIDIS.len() - 2);
+                let (low, high) = (srgb(CIVIDIS[index]), srgb(CIVIDIS[index + 1]));
+                let f = position - index as f32;
+                let mix = |i: usize| low[i] + (high[i] - low[i]) * f;
+                Color::from_srgb(mix(0), mix(1), mix(2), 1.0)
+            }
+        }
+    }
+
+    /// Color of a LOD level, level 0 being the full detail.
+    pub fn lod(self, level: u32) -> Color {
+        self.category(level as usize)
+    }
+
+    /// Color of a light level, up to `MAX_LIGHT`.
+    pub fn light(self, level: u8) -> Color {
+        self.ramp(f32::from(level) / f32::from(MAX_LIGHT))
+    }
+
+    /// Color of a chunk, different from the chunks sharing a face with it.
+    pub fn chunk(self, coord: &ChunkCoord) -> Color {
+        // Neighbors differ by 1, 3 or 5 modulo 8.
+        let index = coord.x + 3 * coord.y + 5 * coord.z;
+        self.category(index.rem_euclid(8) as usize)
+    }
+}
+
+fn srgb(rgb: u32) -> [f32; 3] {
+    let channel = |shift: u32| ((rgb >> shift) & 0xff) as f32 / 255.0;
+    [channel(16), channel(8), channel(0)]
+}
+
+fn hex(rgb: u32) -> Color {
+    let [r, g, b] = srgb(rgb);
+    Color::from_srgb(r, g, b, 1.0)
+}
+
+/// Fully saturated color of `hue` in turns, red at 0.
+fn hue(hue: f32) -> Color {
+    let channel = |offset: f32| {
+        let k = (offset + hue * 6.0) % 6.0;
+        1.0 - (k.min(4.0 - k).min(1.0)).max(0.0)
+    };
+    Color::from_srgb(channel(5.0), channel(3.0), channel(1.0), 1.0)
+}
@@ -0,0 +1,178 @@
+//! Dual contouring of signed distance fields.
+//!
+//! Like surface nets, each cell the surface crosses gets one vertex and each
+//! crossed edge a quad joining the cells around it. The vertex is placed
+//! where the planes through the edge crossings, oriented by the field
+//! normals, best intersect: the minimum of their quadratic error function.
+//! Where the planes meet at an angle the vertex lands on the edge or corner
+//! between them, so the mesh keeps the sharp features of the field.
+
+use nalgebra::{Matrix3, Vector3};
+
+use crate::coords::CHUNK_SIZE;
+use crate::meshing::{MeshData, VoxelStyle};
+use crate::smooth::{corner_position, crossing, DensityGrid, EDGES};
+
+const SIZE: usize = CHUNK_SIZE as usize;
+
+/// Singular values of the error function below this are ignored, the
+/// vertex stays at the mass point along the flat directions.
+const SINGULAR_THRESHOLD: f32 = 0.1;
+
+/// Sum of the squared distances to a set of planes.
+#[derive(Debug, Clone)]
+pub struct Qef {
+    ata: Matrix3<f32>,
+    atb: Vector3<f32>,
+
+    /// Sum of the plane points, their average is the mass point.
+    points: Vector3<f32>,
+    count: u32,
+}
+
+impl Qef {
+    pub fn new() -> Self {
+        Qef {
+            ata: Matrix3::zeros(),
+            atb: Vector3::zeros(),
+            points: Vector3::zeros(),
+            count: 0,
+        }
+    }
+
+    /// Add the plane through `point` facing `normal`.
+    pub fn add(&mut self, point: Vector3<f32>, normal: Vector3<f32>) {
+        self.ata += normal * normal.transpose();
+        self.atb += normal * normal.dot(&point);
+        self.points += point;
+        self.count += 1;
+    }
+
+    pub fn mass_point(&self) -> Vector3<f32> {
+        self.points / self.count.max(1) as f32
+    }
+
+    /// Point closest to every plane.
+    ///
+    /// Solved around the mass point with a truncated pseudo inverse, so
+    /// parallel planes leave the point near the mass point instead of
+    /// sending it away.
+    pub fn solve(&self) -> Vector3<f32> {
+        let mass = self.mass_point();
+        let max = self.ata.abs().max();
+        let inverse = self
+            .ata
+            .svd(true, true)
+            .pseudo_inverse(max * SINGULAR_THRESHOLD)
+            .unwrap_or_else(|_| Matrix3::zeros());
+        mass + inverse * (self.atb - self.ata * mass)
+    }
+}
+
+impl Default for Qef {
+    fn default() -> Self {
+        Qef::new()
+    }
+}
+
+/// Mesh the surface of `grid` with dual contouring into `out`.
+///
+/// `normal` is the gradient of the field the grid was sampled from, exact
+/// normals keep the features sharper than the ones interpolated from the
+/// grid. `style` gives the appearance of the vertex of the cell whose
+/// lowest corner is `(x, y, z)`.
+///
+/// With a grid from `DensityGrid::from_fn_with_apron`, the quads across the
+/// high faces of the chunk are emitted too. The neighbour chunk computes
+/// the same vertices from the same samples, so the chunks join exactly.
+pub fn dual_contouring<N, F>(grid: &DensityGrid, normal: N, style: F, out: &mut MeshData)
+where
+    N: Fn([f32; 3]) -> [f32; 3],
+    F: Fn(usize, usize, usize) -> VoxelStyle,
+{
+    out.clear();
+    // Cells past the high faces exist only with the apron.
+    let cells = grid.points() - 1;
+    let cell_index = |x: usize, y: usize, z: usize| x + (y + z * cells) * cells;
+    let mut vertices = vec![u32::max_value(); cells * cells * cells];
+
+    for z in 0..cells {
+        for y in 0..cells {
+            for x in 0..cells {
+                let densities = grid.cell(x, y, z);
+                let mut qef = Qef::new();
+                let mut normals = Vector3::zeros();
+                for &(a, b) in &EDGES {
+                    if (densities[a] < 0.0) == (densities[b] < 0.0) {
+                        continue;
+                    }
+                    let point = |i| corner_position(x, y, z, i);
+                    let p = crossing(point(a), densities[a], point(b), densities[b]);
+                    let n = Vector3::from(normal(p));
+                    qef.add(Vector3::from(p), n);
+                    normals += n;
+                }
+                if qef.count == 0 {
+                    continue;
+                }
+                // A vertex outside its cell folds the mesh, the mass point
+                // is always inside.
+                let min = Vector3::new(x as f32, y as f32, z as f32);
+                let max = min + Vector3::repeat(1.0);
+                let mut position = qef.solve();
+                if (0..3).any(|i| position[i] < min[i] || position[i] > max[i]) {
+                    position = qef.mass_point();
+                }
+                let n = normals
+                    .try_normalize(std::f32::EPSILON)
+                    .unwrap_or_else(Vector3::y);
+                let cell_style = style(x, y, z);
+                let flags = if n.y > 0.0 { cell_style.flags } else { 0 };
+                out.push_vertex(position.into(), n.into(), cell_style.color, flags);
+                vertices[cell_index(x, y, z)] = out.vertex_count() as u32 - 1;
+            }
+        }
+    }
+
+    // Quads across the edges of this chunk, those on its low faces belong
+    // to the neighbours which have the cells on both sides.
+    for z in 0..=SIZE {
+        for y in 0..=SIZE {
+            for x in 0..=SIZE {
+                let p = [x, y, z];
+                for axis in 0..3 {
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                    let inner = |c: usize| c > 0 && c < cells;
+                    if p[axis] >= SIZE || !inner(p[u]) || !inner(p[v]) {
+                        continue;
+                    }
+                    let mut q = p;
+                    q[axis] += 1;
+                    let inside = grid.solid(x, y, z);
+                    if inside == grid.solid(q[0], q[1], q[2]) {
+                        continue;
+                    }
+                    let cell = |du: usize, dv: usize| {
+                        let mut c = p;
+                        c[u] -= du;
+                        c[v] -= dv;
+                        vertices[cell_index(c[0], c[1], c[2])]
+                    };
+                    let quad = [cell(1, 1), cell(0, 1), cell(0, 0), cell(1, 0)];
+                    if quad.contains(&u32::max_value()) {
+                        continue;
+                    }
+                    if inside {
+                        out.indices.extend_from_slice(&[
+                            quad[0], quad[1], quad[2], quad[0], quad[2], quad[3],
+                        ]);
+                    } else {
+                        out.indices.extend_from_slice(&[
+                            quad[0], quad[2], quad[1], quad[0], quad[3], quad[2],
+                        ]);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Events of the renderer, collected with `Renderer::drain_events`.
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::config::RendererConfig;
+
+/// Something which happened inside the renderer since the last drain.
+#[derive(Debug, Clone)]
+pub enum RendererEvent {
+    GraphRebuilt(RebuildEvent),
+}
+
+/// Why the render graph was built again.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RebuildCause {
+    /// First build, by `RendererBuilder::build`.
+    Initial,
+
+    /// New surface after `Renderer::suspend`.
+    Resume,
+
+    Resize,
+    PresentModeChange,
+    MsaaChange,
+
+    /// Other settings of `RendererConfig::needs_rebuild`.
+    ConfigChange,
+
+    DeviceLost,
+}
+
+impl RebuildCause {
+    /// Cause of the rebuild going from `previous` to `config`.
+    pub fn of_config_change(previous: &RendererConfig, config: &RendererConfig) -> Self {
+        if previous.quality.msaa_samples != config.quality.msaa_samples {
+            RebuildCause::MsaaChange
+        } else {
+            RebuildCause::ConfigChange
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RebuildCause::Initial => "initial",
+            RebuildCause::Resume => "resume",
+            RebuildCause::Resize => "resize",
+            RebuildCause::PresentModeChange => "present mode change",
+            RebuildCause::MsaaChange => "msaa change",
+            RebuildCause::ConfigChange => "config change",
+            RebuildCause::DeviceLost => "device lost",
+        }
+    }
+}
+
+impl fmt::Display for RebuildCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A graph rebuild, to find what causes hitches.
+#[derive(Debug, Clone)]
+pub struct RebuildEvent {
+    pub cause: RebuildCause,
+
+    /// When the rebuild started.
+    pub started: SystemTime,
+
+    /// Time spent disposing the old graph and building the new one.
+    pub duration: Duration,
+
+    /// Names of the graph resources created again.
+    pub resources: Vec<&'static str>,
+
+    /// Whether the new graph was built, the renderer is suspended otherwise.
+    pub succeeded: bool,
+}
+
+impl fmt::Display for RebuildEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let since_epoch = self
+            .started
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "graph rebuild at {}.{:03} ({}) took {:?}{}, recreated {}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_millis(),
+            self.cause,
+            self.duration,
+            if self.succeeded { "" } else { " and failed" },
+            self.resources.join(", ")
+        )
+    }
+}
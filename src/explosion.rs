@@ -0,0 +1,132 @@
+//! Explosions carving the terrain.
+//!
+//! Rays leave the center in every direction with full strength, fading
+//! with distance by the falloff. Each solid voxel a ray reaches with some
+//! strength left is destroyed and absorbs part of it, a voxel reached with
+//! none shields the voxels behind it. Voxels behind an obstacle take a
+//! weaker blast, they survive closer to the center than in the open.
+
+use std::collections::{BTreeSet, HashSet};
+
+use nalgebra::Vector3;
+
+use crate::chunk::{VoxelId, AIR};
+use crate::coords::{ChunkCoord, Location, WorldPos, CHUNK_SIZE};
+use crate::world::World;
+
+/// Largest radius, larger explosions are shrunk to it.
+pub const MAX_EXPLOSION_RADIUS: f32 = 64.0;
+
+/// Strength a destroyed voxel takes from the ray crossing it.
+const ABSORPTION: f32 = 0.25;
+
+/// Distance between the samples of a ray, in voxels.
+const RAY_STEP: f32 = 0.3;
+
+/// Voxels removed by an explosion.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Explosion {
+    /// Destroyed voxels and their former type, for particles and drops.
+    pub destroyed: Vec<(WorldPos, VoxelId)>,
+
+    /// Chunks to mesh and light again, once each. Chunks next to a
+    /// destroyed border voxel are included, their faces uncovered.
+    pub chunks: Vec<ChunkCoord>,
+}
+
+impl Explosion {
+    pub fn positions<'a>(&'a self) -> impl Iterator<Item = WorldPos> + 'a {
+        self.destroyed.iter().map(|(pos, _)| *pos)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.destroyed.is_empty()
+    }
+}
+
+/// Blow up the terrain around `center`, see `World::explode`.
+pub fn explode(world: &mut World, center: &Location, radius: f32, falloff: f32) -> Explosion {
+    let valid = radius > 0.0 && center.offset.iter().all(|x| x.is_finite());
+    if !valid {
+        return Explosion::default();
+    }
+    let radius = radius.min(MAX_EXPLOSION_RADIUS);
+    let center = Location::new(center.chunk, center.offset);
+    let origin = center.chunk.min_voxel();
+    let strength = |distance: f32| (1.0 - distance / radius).max(0.0).powf(falloff.max(0.0));
+
+    let mut destroyed = HashSet::new();
+    let mut explosion = Explosion::default();
+    for direction in directions(radius) {
+        let mut absorbed = 0.0;
+        let mut last = None;
+        for step in 0.. {
+            let distance = step as f32 * RAY_STEP;
+            if distance > radius {
+                break;
+            }
+            let point = center.offset + direction * distance;
+            let pos = match origin.checked_offset(
+                point.x.floor() as i64,
+                point.y.floor() as i64,
+                point.z.floor() as i64,
+            ) {
+                Some(pos) => pos,
+                None => break,
+            };
+            if last == Some(pos) {
+                continue;
+            }
+            last = Some(pos);
+            // Voxels are removed once every ray is cast, those destroyed
+            // by previous rays are already gone.
+            let id = world.get_voxel(&pos);
+            if id == AIR || destroyed.contains(&pos) {
+                continue;
+            }
+            if strength(distance) - absorbed <= 0.0 {
+                break;
+            }
+            absorbed += ABSORPTION;
+            destroyed.insert(pos);
+            explosion.destroyed.push((pos, id));
+        }
+    }
+
+    let mut chunks = BTreeSet::new();
+    for (pos, _) in &explosion.destroyed {
+        world.set_voxel(pos, AIR);
+        let coord = pos.chunk();
+        let (x, y, z) = pos.local();
+        let last = CHUNK_SIZE as usize - 1;
+        chunks.insert(coord);
+        let border = |v: usize| match v {
+            0 => -1,
+            v if v == last => 1,
+            _ => 0,
+        };
+        let (dx, dy, dz) = (border(x), border(y), border(z));
+        for &(ox, oy, oz) in &[(dx, 0, 0), (0, dy, 0), (0, 0, dz)] {
+            if (ox, oy, oz) != (0, 0, 0) {
+                chunks.insert(ChunkCoord::new(coord.x + ox, coord.y + oy, coord.z + oz));
+            }
+        }
+    }
+    explosion.chunks = chunks.into_iter().collect();
+    explosion
+}
+
+/// Directions spread evenly over the sphere, enough for neighbouring rays
+/// to stay about a voxel apart at `radius`.
+fn directions(radius: f32) -> impl Iterator<Item = Vector3<f32>> {
+    let area = 4.0 * std::f32::consts::PI * radius * radius;
+    let count = (area * 2.0).max(64.0) as usize;
+    // Golden angle spiral.
+    let turn = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    (0..count).map(move |i| {
+        let y = 1.0 - (i as f32 + 0.5) / count as f32 * 2.0;
+        let ring = (1.0 - y * y).sqrt();
+        let angle = turn * i as f32;
+        Vector3::new(angle.cos() * ring, y, angle.sin() * ring)
+    })
+}
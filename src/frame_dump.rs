@@ -0,0 +1,443 @@
+//! Dumps of the intermediate render targets of a frame, for offline
+//! inspection.
+//!
+//! With `RendererConfig::frame_dumps` set the graph ends with a node copying
+//! every target it reads to the host when a dump is requested: the depth
+//! image, the final color image and each level of the Hi-Z pyramid when
+//! occlusion culling is on. `Scene::request_frame_dump` asks for the next
+//! frame, its targets are written as PNG files into the given folder once
+//! the frame completed, by an IO job of the renderer's `JobSystem`.
+//!
+//! Color targets are written as they are, depth and other single float
+//! channels as grey levels stretched over the range of the frame.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rendy::command::{
+    CommandBuffer, CommandPool, ExecutableState, Family, Fence, MultiShot, PendingState, Queue,
+    SimultaneousUse, Submission, Submit, Transfer,
+};
+use rendy::factory::Factory;
+use rendy::frame::Frames;
+use rendy::graph::{
+    gfx_acquire_barriers, gfx_release_barriers, GraphContext, ImageAccess, Node, NodeBuffer,
+    NodeBuildError, NodeDesc, NodeImage,
+};
+use rendy::hal;
+
+use crate::jobs::{JobCategory, JobSystem};
+use crate::readback::Readback;
+use crate::scene::Scene;
+
+/// Folder of a dump made now under `base`, named after the time in
+/// milliseconds since the Unix epoch.
+pub fn timestamped_folder(base: &Path) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    base.join(format!("frame-{}", millis))
+}
+
+/// Texels of a target turned to 8 bit RGBA, `None` for formats without a
+/// conversion.
+///
+/// `data` holds the texels of `format` with rows tightly packed.
+pub fn to_rgba8(format: hal::format::Format, data: &[u8]) -> Option<Vec<u8>> {
+    use hal::format::Format;
+    match format {
+        Format::Rgba8Unorm | Format::Rgba8Srgb => Some(data.to_vec()),
+        Format::Bgra8Unorm | Format::Bgra8Srgb => Some(
+            data.chunks_exact(4)
+                .flat_map(|texel| vec![texel[2], texel[1], texel[0], texel[3]])
+                .collect(),
+        ),
+        Format::D32Sfloat | Format::R32Sfloat => {
+            let values: Vec<f32> = data
+                .chunks_exact(4)
+                .map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+                .collect();
+            let finite = values.iter().cloned().filter(|value| value.is_finite());
+            let min = finite.clone().fold(std::f32::INFINITY, f32::min);
+            let max = finite.fold(std::f32::NEG_INFINITY, f32::max);
+            let range = if max > min { max - min } else { 1.0 };
+            Some(
+                values
+                    .iter()
+                    .flat_map(|&value| {
+                        let level = if value.is_finite() {
+                            ((value - min) / range * 255.0).round() as u8
+                        } else {
+                            0
+                        };
+                        vec![level, level, level, 255]
+                    })
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// PNG file of an 8 bit RGBA image, rows tightly packed.
+///
+/// The image data is stored without compression, the files are only meant
+/// to be opened by other tools.
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width as usize * height as usize * 4);
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    if width > 0 {
+        for row in rgba.chunks_exact(width as usize * 4) {
+            // No filter.
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+    }
+
+    // Zlib stream of stored deflate blocks.
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, no filter, not interlaced.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(id);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Dump request, kept by the scene across rebuilds so a dump asked for
+/// while the graph is rebuilt is made by the new one.
+#[derive(Debug, Default)]
+pub(crate) struct FrameDumps {
+    folder: Mutex<Option<PathBuf>>,
+}
+
+impl FrameDumps {
+    /// Dump the targets of the next frame into `folder`, replacing a request
+    /// not made yet.
+    pub fn request(&self, folder: PathBuf) {
+        *self.folder.lock().unwrap() = Some(folder);
+    }
+
+    fn take_request(&self) -> Option<PathBuf> {
+        self.folder.lock().unwrap().take()
+    }
+}
+
+/// Level of a target and where its texels go in a readback slot.
+#[derive(Debug, Clone)]
+struct Region {
+    file: String,
+    image: usize,
+    level: u8,
+    extent: hal::image::Extent,
+    offset: u64,
+    size: u64,
+}
+
+/// Copies its images to the host when a dump is requested, one name for
+/// each image, in the order they are added.
+#[derive(Debug)]
+pub(crate) struct FrameDumpNodeDesc {
+    pub names: Vec<&'static str>,
+
+    /// Runs the writes of the files.
+    pub jobs: Arc<JobSystem>,
+}
+
+pub(crate) struct FrameDumpNode<B: hal::Backend> {
+    readback: Readback<B>,
+    jobs: Arc<JobSystem>,
+    regions: Vec<Region>,
+    formats: Vec<hal::format::Format>,
+    command_pool: CommandPool<B, Transfer>,
+    command_buffers:
+        Vec<CommandBuffer<B, Transfer, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>>,
+    /// Submits of each frame, without and with the copies.
+    submits: Vec<(Submit<B, SimultaneousUse>, Submit<B, SimultaneousUse>)>,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for FrameDumpNode<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Frame Dump Node({} regions)", self.regions.len())
+    }
+}
+
+impl<B> NodeDesc<B, Scene> for FrameDumpNodeDesc
+where
+    B: hal::Backend,
+{
+    type Node = FrameDumpNode<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.names
+            .iter()
+            .map(|_| ImageAccess {
+                access: hal::image::Access::TRANSFER_READ,
+                usage: hal::image::Usage::TRANSFER_SRC,
+                layout: hal::image::Layout::TransferSrcOptimal,
+                stages: hal::pso::PipelineStage::TRANSFER,
+            })
+            .collect()
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &Scene,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), self.names.len());
+
+        let mut regions = Vec::new();
+        let mut formats = Vec::new();
+        let mut offset = 0;
+        for (index, (name, node_image)) in self.names.iter().zip(&images).enumerate() {
+            let image = ctx.get_image(node_image.id).unwrap();
+            let texel = image.format().surface_desc().bits as u64 / 8;
+            formats.push(image.format());
+            for level in 0..image.levels() {
+                let extent = image.kind().extent().at_level(level);
+                let size = extent.width as u64 * extent.height as u64 * texel;
+                let file = if image.levels() > 1 {
+                    format!("{}_mip{}.png", name, level)
+                } else {
+                    format!("{}.png", name)
+                };
+                regions.push(Region {
+                    file,
+                    image: index,
+                    level,
+                    extent,
+                    offset,
+                    size,
+                });
+                // Buffer offsets of copies are aligned to the texel size.
+                offset += (size + 15) & !15;
+            }
+        }
+        let frames = ctx.frames_in_flight as usize;
+        let readback = Readback::new(factory, offset.max(16), frames);
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Transfer>()
+            .expect("Graph builder must provide family with Transfer capability");
+
+        let mut command_buffers = Vec::new();
+        let mut submits = Vec::new();
+        let mut initials = command_pool.allocate_buffers(frames * 2).into_iter();
+        for index in 0..frames {
+            let start = readback.slot_range(readback.slot(index as u64)).start;
+            let mut pair = Vec::with_capacity(2);
+            for copy in &[false, true] {
+                let initial = initials.next().unwrap();
+                let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+                let mut encoder = recording.encoder();
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, &buffers, &images);
+                    encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                }
+                if *copy {
+                    for region in &regions {
+                        let image = ctx.get_image(images[region.image].id).unwrap();
+                        unsafe {
+                            encoder.copy_image_to_buffer(
+                                image.raw(),
+                                hal::image::Layout::TransferSrcOptimal,
+                                readback.raw(),
+                                Some(hal::command::BufferImageCopy {
+                                    buffer_offset: start + region.offset,
+                                    buffer_width: region.extent.width,
+                                    buffer_height: region.extent.height,
+                                    image_layers: hal::image::SubresourceLayers {
+                                        aspects: image.format().surface_desc().aspects,
+                                        level: region.level,
+                                        layers: 0..1,
+                                    },
+                                    image_offset: hal::image::Offset::ZERO,
+                                    image_extent: region.extent,
+                                }),
+                            );
+                        }
+                    }
+                }
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, &buffers, &images);
+                    encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                }
+                let (submit, command_buffer) = recording.finish().submit();
+                pair.push(submit);
+                command_buffers.push(command_buffer);
+            }
+            let copy = pair.pop().unwrap();
+            let plain = pair.pop().unwrap();
+            submits.push((plain, copy));
+        }
+
+        Ok(FrameDumpNode {
+            readback,
+            jobs: self.jobs,
+            regions,
+            formats,
+            command_pool,
+            command_buffers,
+            submits,
+        })
+    }
+}
+
+impl<B> Node<B, Scene> for FrameDumpNode<B>
+where
+    B: hal::Backend,
+{
+    type Capability = Transfer;
+
+    fn run<'a>(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        queue: &mut Queue<B>,
+        aux: &Scene,
+        frames: &Frames<B>,
+        waits: &[(&'a B::Semaphore, hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        let index = frames.next().index() as usize % self.submits.len();
+        let slot = self.readback.slot(index as u64);
+        self.readback.complete(factory, slot);
+
+        let (ref plain, ref copy) = self.submits[index];
+        let submit = match aux.frame_dumps().take_request() {
+            Some(folder) => {
+                let regions = self.regions.clone();
+                let formats = self.formats.clone();
+                let size = self.readback.slot_size();
+                let jobs = self.jobs.clone();
+                self.readback.request(slot, size, move |data| {
+                    let data = data.to_vec();
+                    jobs.spawn(JobCategory::Io, move || {
+                        if let Err(err) = write_dump(&folder, &regions, &formats, &data) {
+                            warn!("Can't write the frame dump to {:?}: {}.", folder, err);
+                        }
+                    });
+                });
+                copy
+            }
+            None => plain,
+        };
+
+        unsafe {
+            queue.submit(
+                Some(
+                    Submission::new()
+                        .submits(Some(submit))
+                        .wait(waits.iter().cloned())
+                        .signal(signals.iter()),
+                ),
+                fence,
+            );
+        }
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &Scene) {
+        info!("Disposing Frame Dump Node.");
+        // The device is idle, write the dumps still pending.
+        for slot in 0..self.submits.len() {
+            self.readback.complete(factory, slot);
+        }
+        drop(self.submits);
+        self.command_pool.free_buffers(
+            self.command_buffers
+                .into_iter()
+                .map(|command_buffer| command_buffer.mark_complete()),
+        );
+        factory.destroy_command_pool(self.command_pool);
+    }
+}
+
+fn write_dump(
+    folder: &Path,
+    regions: &[Region],
+    formats: &[hal::format::Format],
+    data: &[u8],
+) -> io::Result<()> {
+    fs::create_dir_all(folder)?;
+    for region in regions {
+        let format = formats[region.image];
+        let texels = &data[region.offset as usize..(region.offset + region.size) as usize];
+        let rgba = match to_rgba8(format, texels) {
+            Some(rgba) => rgba,
+            None => {
+                warn!("Can't dump {}, {:?} isn't supported.", region.file, format);
+                continue;
+            }
+        };
+        let png = encode_png(region.extent.width, region.extent.height, &rgba);
+        fs::write(folder.join(&region.file), png)?;
+    }
+    info!("Frame dumped to {:?}.", folder);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! `#include` directives of the GLSL sources, expanded before compilation.
+//!
+//! Shaders share code through snippets like `fog.glsl`, included with
+//! `#include "fog.glsl"` on a line of its own. Each snippet is included
+//! once per shader, later includes of it are dropped. A `#line` directive
+//! follows the included code so compile errors still point at the line of
+//! the shader.
+//!
+//! The crate's snippets are embedded in the binary, `expand` resolves them.
+//! Plugins shipping their own shaders resolve theirs with `preprocess`.
+
+use std::collections::HashSet;
+use std::fmt;
+
+/// Snippets of the crate by name, as written in the includes.
+const SNIPPETS: &[(&str, &str)] = &[
+    ("args.glsl", include_str!("../args.glsl")),
+    ("color.glsl", include_str!("../color.glsl")),
+    ("fog.glsl", include_str!("../fog.glsl")),
+];
+
+/// Snippet of the crate named `name`.
+pub fn snippet(name: &str) -> Option<&'static str> {
+    SNIPPETS
+        .iter()
+        .find(|&&(snippet, _)| snippet == name)
+        .map(|&(_, source)| source)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncludeError {
+    /// No snippet of that name.
+    Unknown(String),
+
+    /// The snippet includes itself, through the others listed.
+    Cycle(Vec<String>),
+
+    /// An `#include` without a quoted name, at that line of its source.
+    Malformed(usize),
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IncludeError::Unknown(name) => write!(f, "unknown shader include \"{}\"", name),
+            IncludeError::Cycle(names) => {
+                write!(f, "shader includes form a cycle: {}", names.join(" -> "))
+            }
+            IncludeError::Malformed(line) => write!(f, "malformed #include on line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+/// Name included by `line`, `None` if it isn't an include.
+fn include_name(line: &str) -> Option<Result<&str, ()>> {
+    let rest = line.trim_start().strip_prefix("#include")?;
+    let rest = rest.trim();
+    let name = rest
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .filter(|name| !name.is_empty() && !name.contains('"'));
+    Some(name.ok_or(()))
+}
+
+/// Expand the includes of `source`, snippets resolved by `resolve`.
+pub fn preprocess<'a, F>(source: &str, resolve: F) -> Result<String, IncludeError>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    let mut output = String::with_capacity(source.len());
+    let mut included = HashSet::new();
+    let mut stack = Vec::new();
+    expand_into(source, &resolve, &mut included, &mut stack, &mut output)?;
+    Ok(output)
+}
+
+fn expand_into<'a, F>(
+    source: &str,
+    resolve: &F,
+    included: &mut HashSet<String>,
+    stack: &mut Vec<String>,
+    output: &mut String,
+) -> Result<(), IncludeError>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    for (number, line) in source.lines().enumerate() {
+        let name = match include_name(line) {
+            None => {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+            Some(Err(())) => return Err(IncludeError::Malformed(number + 1)),
+            Some(Ok(name)) => name,
+        };
+        if stack.iter().any(|parent| parent == name) {
+            let mut cycle = stack.clone();
+            cycle.push(name.to_owned());
+            return Err(IncludeError::Cycle(cycle));
+        }
+        if !included.insert(name.to_owned()) {
+            continue;
+        }
+        let snippet = resolve(name).ok_or_else(|| IncludeError::Unknown(name.to_owned()))?;
+        stack.push(name.to_owned());
+        expand_into(snippet, resolve, included, stack, output)?;
+        stack.pop();
+        output.push_str(&format!("#line {}\n", number + 2));
+    }
+    Ok(())
+}
+
+/// Expand the includes of a shader of the crate.
+///
+/// # Panics
+///
+/// If an include can't be expanded, the sources are part of the crate.
+pub(crate) fn expand(source: &str) -> String {
+    preprocess(source, snippet).unwrap_or_else(|err| panic!("{}", err))
+}
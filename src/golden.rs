@@ -0,0 +1,267 @@
+//! Comparison of rendered frames against reference images.
+//!
+//! References are binary PPM files checked in next to the tests. A frame
+//! matches when few enough pixels differ perceptibly, the color distance
+//! is measured in YIQ which weighs luma over chroma like the eye does. On a
+//! mismatch the frame and a diff image, differing pixels in red over a
+//! faded copy of the reference, are written next to the reference.
+//!
+//! Set `AVENIR_BLESS=1` to write missing or mismatching references instead
+//! of failing.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// 8 bit sRGB image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+
+    /// Rows top to bottom.
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    /// Image filled with `color`.
+    pub fn new(width: u32, height: u32, color: [u8; 3]) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![color; (width * height) as usize],
+        }
+    }
+
+    /// Image from RGBA rows such as a readback of the swapchain, alpha is dropped.
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8]) -> Option<Self> {
+        if rgba.len() != (width * height * 4) as usize {
+            return None;
+        }
+        Some(Image {
+            width,
+            height,
+            pixels: rgba.chunks(4).map(|p| [p[0], p[1], p[2]]).collect(),
+        })
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> [u8; 3] {
+        self.pixels[(x + y * self.width) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: [u8; 3]) {
+        self.pixels[(x + y * self.width) as usize] = color;
+    }
+
+    pub fn read_ppm(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a binary PPM file");
+        let mut header = Vec::new();
+        // Magic, width, height and maximum value, comments skipped.
+        while header.len() < 4 {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid());
+            }
+            let line = line.split('#').next().unwrap_or("");
+            header.extend(line.split_whitespace().map(str::to_owned));
+        }
+        if header[0] != "P6" || header[3] != "255" {
+            return Err(invalid());
+        }
+        let width: u32 = header[1].parse().map_err(|_| invalid())?;
+        let height: u32 = header[2].parse().map_err(|_| invalid())?;
+        let mut data = vec![0; (width as usize) * (height as usize) * 3];
+        reader.read_exact(&mut data)?;
+        Ok(Image {
+            width,
+            height,
+            pixels: data.chunks(3).map(|p| [p[0], p[1], p[2]]).collect(),
+        })
+    }
+
+    pub fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            file.write_all(pixel)?;
+        }
+        file.flush()
+    }
+}
+
+/// How far a frame may be from its reference.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tolerance {
+    /// Color distance from which a pixel differs, in `0.0..=1.0`.
+    pub threshold: f32,
+
+    /// Fraction of pixels allowed to differ.
+    pub max_differing: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            threshold: 0.1,
+            max_differing: 0.001,
+        }
+    }
+}
+
+/// Result of `compare`.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub differing: usize,
+    pub total: usize,
+    pub diff: Image,
+}
+
+impl Comparison {
+    pub fn matches(&self, tolerance: &Tolerance) -> bool {
+        self.differing as f32 <= self.total as f32 * tolerance.max_differing
+    }
+}
+
+/// Largest YIQ distance, between black and white.
+const MAX_DELTA: f32 = 35215.0;
+
+/// Squared YIQ distance between two colors, normalized to `0.0..=1.0`.
+pub fn color_delta(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let yiq = |c: [u8; 3]| {
+        let (r, g, b) = (c[0] as f32, c[1] as f32, c[2] as f32);
+        (
+            r * 0.298_895_31 + g * 0.586_622_47 + b * 0.114_482_23,
+            r * 0.595_977_99 - g * 0.274_176_10 - b * 0.321_801_89,
+            r * 0.211_470_17 - g * 0.522_617_42 + b * 0.311_147_25,
+        )
+    };
+    let (y1, i1, q1) = yiq(a);
+    let (y2, i2, q2) = yiq(b);
+    let (y, i, q) = (y1 - y2, i1 - i2, q1 - q2);
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_DELTA
+}
+
+/// Compare two images of the same size pixel by pixel.
+pub fn compare(reference: &Image, actual: &Image, tolerance: &Tolerance) -> Comparison {
+    debug_assert_eq!(
+        (reference.width, reference.height),
+        (actual.width, actual.height)
+    );
+    let mut diff = reference.clone();
+    let mut differing = 0;
+    let threshold = tolerance.threshold * tolerance.threshold;
+    for (out, (&a, &b)) in diff
+        .pixels
+        .iter_mut()
+        .zip(reference.pixels.iter().zip(&actual.pixels))
+    {
+        if color_delta(a, b) > threshold {
+            differing += 1;
+            *out = [255, 0, 0];
+        } else {
+            // Faded reference so the differences stand out.
+            let luma = (a[0] as u32 * 3 + a[1] as u32 * 6 + a[2] as u32) / 10;
+            let faded = (255 - (255 - luma) / 4) as u8;
+            *out = [faded, faded, faded];
+        }
+    }
+    Comparison {
+        differing,
+        total: reference.pixels.len(),
+        diff,
+    }
+}
+
+/// Error returned by `check`.
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(PathBuf, io::Error),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Mismatch {
+        differing: usize,
+        total: usize,
+        diff: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            GoldenError::SizeMismatch { expected, actual } => write!(
+                f,
+                "expected a {}x{} image, got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            GoldenError::Mismatch {
+                differing,
+                total,
+                diff,
+            } => write!(
+                f,
+                "{} of {} pixels differ, see {}",
+                differing,
+                total,
+                diff.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+fn blessing() -> bool {
+    std::env::var("AVENIR_BLESS").map_or(false, |value| value == "1")
+}
+
+/// Compare `actual` against the reference image at `reference`.
+///
+/// On a mismatch `<name>.actual.ppm` and `<name>.diff.ppm` are written
+/// next to the reference.
+pub fn check(reference: &Path, actual: &Image, tolerance: &Tolerance) -> Result<(), GoldenError> {
+    let io_error = |path: &Path| {
+        let path = path.to_owned();
+        move |err| GoldenError::Io(path, err)
+    };
+    let bless = || actual.write_ppm(reference).map_err(io_error(reference));
+    let expected = match Image::read_ppm(reference) {
+        Ok(expected) => expected,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound && blessing() => return bless(),
+        Err(err) => return Err(GoldenError::Io(reference.to_owned(), err)),
+    };
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        if blessing() {
+            return bless();
+        }
+        return Err(GoldenError::SizeMismatch {
+            expected: (expected.width, expected.height),
+            actual: (actual.width, actual.height),
+        });
+    }
+    let comparison = compare(&expected, actual, tolerance);
+    if comparison.matches(tolerance) {
+        return Ok(());
+    }
+    if blessing() {
+        return bless();
+    }
+    let actual_path = reference.with_extension("actual.ppm");
+    let diff_path = reference.with_extension("diff.ppm");
+    actual
+        .write_ppm(&actual_path)
+        .map_err(io_error(&actual_path))?;
+    comparison
+        .diff
+        .write_ppm(&diff_path)
+        .map_err(io_error(&diff_path))?;
+    Err(GoldenError::Mismatch {
+        differing: comparison.differing,
+        total: comparison.total,
+        diff: diff_path,
+    })
+}
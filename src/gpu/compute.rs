@@ -0,0 +1,556 @@
+//! Compute reductions reused by the passes.
+//!
+//! `ImageReduce` computes the minimum, maximum and average of the first
+//! channel of an image, for auto-exposure and the like. `PrefixSum` turns a
+//! buffer of `u32` into its exclusive prefix sums, the offsets at which to
+//! compact the kept elements of a culling or meshing pass.
+//!
+//! Both are built once along with the node using them, bound to its image
+//! or buffer, and recorded in its command buffer. Results stay on the
+//! device, a `Readback` brings them to the host. The CPU versions below
+//! give the same results, for tests and hosts without compute.
+
+use std::mem::size_of;
+
+use rendy::command::{Compute, Encoder, Supports};
+use rendy::factory::Factory;
+use rendy::graph::NodeBuildError;
+use rendy::hal::{self, adapter::PhysicalDevice, device::Device};
+use rendy::memory::Data;
+use rendy::resource::{
+    Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Filter, Handle, ImageView,
+    SamplerDesc, WrapMode,
+};
+use rendy::shader::{Shader, ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader};
+
+use crate::mesh::iceil;
+
+lazy_static::lazy_static! {
+    static ref REDUCE: SpirvShader = SourceShaderInfo::new(
+        include_str!("../../reduce.comp"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/reduce.comp").into(),
+        ShaderKind::Compute,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SCAN: SpirvShader = SourceShaderInfo::new(
+        include_str!("../../scan.comp"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/scan.comp").into(),
+        ShaderKind::Compute,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+}
+
+/// Compile the reduction shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&REDUCE);
+    lazy_static::initialize(&SCAN);
+}
+
+/// Elements combined by each group of the shaders.
+pub const GROUP_SIZE: u32 = 256;
+
+/// Size of the result of an `ImageReduce`, four `f32`: minimum, maximum,
+/// sum and count.
+pub const REDUCED_SIZE: u64 = 16;
+
+/// Minimum, maximum and average of values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reduced {
+    pub min: f32,
+    pub max: f32,
+    pub average: f32,
+}
+
+impl Reduced {
+    /// Read the result of an `ImageReduce`, `None` if it covered no value.
+    pub fn from_raw(raw: [f32; 4]) -> Option<Self> {
+        let [min, max, sum, count] = raw;
+        if count < 1.0 {
+            return None;
+        }
+        Some(Reduced {
+            min,
+            max,
+            average: sum / count,
+        })
+    }
+
+    /// Read the result of an `ImageReduce` from the bytes of a readback.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < REDUCED_SIZE as usize {
+            return None;
+        }
+        let mut raw = [0.0; 4];
+        for (value, chunk) in raw.iter_mut().zip(bytes.chunks_exact(4)) {
+            *value = f32::from_bits(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        }
+        Self::from_raw(raw)
+    }
+}
+
+/// Minimum, maximum and average of `values` on the CPU, `None` if empty.
+pub fn reduce(values: &[f32]) -> Option<Reduced> {
+    let mut raw = [std::f32::INFINITY, std::f32::NEG_INFINITY, 0.0, 0.0];
+    for &value in values {
+        raw[0] = raw[0].min(value);
+        raw[1] = raw[1].max(value);
+        raw[2] += value;
+        raw[3] += 1.0;
+    }
+    Reduced::from_raw(raw)
+}
+
+/// Exclusive prefix sums of `values` on the CPU, in place, returning the
+/// total. Wraps around on overflow like the shader.
+pub fn exclusive_scan(values: &mut [u32]) -> u32 {
+    let mut total = 0u32;
+    for value in values {
+        let next = total.wrapping_add(*value);
+        *value = total;
+        total = next;
+    }
+    total
+}
+
+/// Values left by each pass reducing `count` values, down to the last
+/// pass leaving one.
+pub fn reduce_passes(count: u32) -> Vec<u32> {
+    let mut passes = Vec::new();
+    let mut count = count.max(1);
+    loop {
+        count = (count + GROUP_SIZE - 1) / GROUP_SIZE;
+        passes.push(count);
+        if count == 1 {
+            return passes;
+        }
+    }
+}
+
+/// Length of each level of the prefix sums of `count` values: the values,
+/// the totals of their groups, the totals of those, up to the single total.
+pub fn scan_levels(count: u32) -> Vec<u32> {
+    let mut levels = vec![count.max(1)];
+    loop {
+        let count = (levels[levels.len() - 1] + GROUP_SIZE - 1) / GROUP_SIZE;
+        levels.push(count);
+        if count == 1 {
+            return levels;
+        }
+    }
+}
+
+fn layout_binding(
+    binding: u32,
+    ty: hal::pso::DescriptorType,
+) -> hal::pso::DescriptorSetLayoutBinding {
+    hal::pso::DescriptorSetLayoutBinding {
+        binding,
+        ty,
+        count: 1,
+        stage_flags: hal::pso::ShaderStageFlags::COMPUTE,
+        immutable_samplers: false,
+    }
+}
+
+fn storage<B: hal::Backend>(
+    buffer: &B::Buffer,
+    offset: u64,
+    size: u64,
+) -> hal::pso::Descriptor<'_, B> {
+    hal::pso::Descriptor::Buffer(buffer, Some(offset)..Some(offset + size))
+}
+
+/// Pipeline of `shader` with a `Pass` block of two `u32` push constants.
+fn create_pipeline<B: hal::Backend>(
+    factory: &Factory<B>,
+    shader: &SpirvShader,
+    set_layout: &DescriptorSetLayout<B>,
+) -> Result<(B::PipelineLayout, B::ComputePipeline), NodeBuildError> {
+    let pipeline_layout = unsafe {
+        factory.device().create_pipeline_layout(
+            Some(set_layout.raw()),
+            Some((hal::pso::ShaderStageFlags::COMPUTE, 0..8)),
+        )
+    }
+    .map_err(NodeBuildError::OutOfMemory)?;
+
+    let module = unsafe { shader.module(factory) }.unwrap();
+    let pipeline = unsafe {
+        factory.device().create_compute_pipeline(
+            &hal::pso::ComputePipelineDesc {
+                shader: hal::pso::EntryPoint {
+                    entry: "main",
+                    module: &module,
+                    specialization: hal::pso::Specialization::default(),
+                },
+                layout: &pipeline_layout,
+                flags: hal::pso::PipelineCreationFlags::empty(),
+                parent: hal::pso::BasePipeline::None,
+            },
+            None,
+        )
+    };
+    unsafe { factory.destroy_shader_module(module) };
+    match pipeline {
+        Ok(pipeline) => Ok((pipeline_layout, pipeline)),
+        Err(err) => {
+            unsafe { factory.device().destroy_pipeline_layout(pipeline_layout) };
+            Err(NodeBuildError::Pipeline(err))
+        }
+    }
+}
+
+/// Wait for the previous dispatch to be written before the next one reads.
+unsafe fn dispatch_barrier<B, C, L>(encoder: &mut Encoder<'_, B, C, L>)
+where
+    B: hal::Backend,
+{
+    encoder.pipeline_barrier(
+        hal::pso::PipelineStage::COMPUTE_SHADER..hal::pso::PipelineStage::COMPUTE_SHADER,
+        hal::memory::Dependencies::empty(),
+        Some(hal::memory::Barrier::AllBuffers(
+            hal::buffer::Access::SHADER_WRITE
+                ..hal::buffer::Access::SHADER_READ | hal::buffer::Access::SHADER_WRITE,
+        )),
+    );
+}
+
+/// Offsets of arrays of `lengths` elements of `size` bytes packed in one
+/// buffer, and the size of the buffer.
+fn pack(lengths: &[u32], size: u64, align: u64) -> (Vec<u64>, u64) {
+    let mut offsets = Vec::with_capacity(lengths.len());
+    let mut end = 0;
+    for &length in lengths {
+        offsets.push(end);
+        end += iceil(length as u64 * size, align);
+    }
+    (offsets, end)
+}
+
+/// Minimum, maximum and average of the first channel of an image.
+pub struct ImageReduce<B: hal::Backend> {
+    texels: u32,
+    passes: Vec<u32>,
+    offsets: Vec<u64>,
+    scratch: Escape<Buffer<B>>,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for ImageReduce<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ImageReduce({} texels)", self.texels)
+    }
+}
+
+impl<B: hal::Backend> ImageReduce<B> {
+    /// Reduction of the first level of `view`, of `width` by `height`
+    /// texels, sampled in `layout` when recorded.
+    pub fn new(
+        factory: &Factory<B>,
+        view: &ImageView<B>,
+        layout: hal::image::Layout,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, NodeBuildError> {
+        let texels = width * height;
+        let passes = reduce_passes(texels);
+        let align = factory
+            .physical()
+            .limits()
+            .min_storage_buffer_offset_alignment;
+        let (offsets, size) = pack(&passes, REDUCED_SIZE, align);
+        let scratch = factory
+            .create_buffer(
+                BufferInfo {
+                    size,
+                    usage: hal::buffer::Usage::STORAGE | hal::buffer::Usage::TRANSFER_SRC,
+                },
+                Data,
+            )
+            .unwrap();
+
+        let set_layout: Handle<DescriptorSetLayout<B>> = factory
+            .create_descriptor_set_layout(vec![
+                layout_binding(0, hal::pso::DescriptorType::CombinedImageSampler),
+                layout_binding(1, hal::pso::DescriptorType::StorageBuffer),
+                layout_binding(2, hal::pso::DescriptorType::StorageBuffer),
+            ])
+            .map_err(NodeBuildError::OutOfMemory)?
+            .into();
+        let sampler = factory
+            .get_sampler(SamplerDesc::new(Filter::Nearest, WrapMode::Clamp))
+            .map_err(NodeBuildError::OutOfMemory)?;
+
+        // One set per pass, reading the output of the previous one. The
+        // first pass reads the image, its input is only there to be bound.
+        let sets: Vec<_> = (0..passes.len())
+            .map(|_| factory.create_descriptor_set(set_layout.clone()).unwrap())
+            .collect();
+        let mut writes = Vec::new();
+        for (pass, set) in sets.iter().enumerate() {
+            let src = pass.saturating_sub(1);
+            writes.push(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 0,
+                array_offset: 0,
+                descriptors: Some(hal::pso::Descriptor::CombinedImageSampler(
+                    view.raw(),
+                    layout,
+                    sampler.raw(),
+                )),
+            });
+            writes.push(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 1,
+                array_offset: 0,
+                descriptors: Some(storage::<B>(
+                    scratch.raw(),
+                    offsets[src],
+                    passes[src] as u64 * REDUCED_SIZE,
+                )),
+            });
+            writes.push(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 2,
+                array_offset: 0,
+                descriptors: Some(storage::<B>(
+                    scratch.raw(),
+                    offsets[pass],
+                    passes[pass] as u64 * REDUCED_SIZE,
+                )),
+            });
+        }
+        unsafe { factory.write_descriptor_sets(writes) };
+
+        let (pipeline_layout, pipeline) = create_pipeline(factory, &REDUCE, &set_layout)?;
+        Ok(ImageReduce {
+            texels,
+            passes,
+            offsets,
+            scratch,
+            sets,
+            pipeline_layout,
+            pipeline,
+        })
+    }
+
+    /// Buffer and offset of the result, `REDUCED_SIZE` bytes read with
+    /// `Reduced::from_raw`.
+    pub fn result(&self) -> (&B::Buffer, u64) {
+        (self.scratch.raw(), self.offsets[self.offsets.len() - 1])
+    }
+
+    /// Record the passes, the result is written once they're done.
+    ///
+    /// # Safety
+    ///
+    /// The image must be in the layout given at creation and readable by
+    /// compute shaders at this point of the commands. A barrier is needed
+    /// before reading the result.
+    pub unsafe fn record<C, L>(&self, encoder: &mut Encoder<'_, B, C, L>)
+    where
+        C: Supports<Compute>,
+    {
+        encoder.bind_compute_pipeline(&self.pipeline);
+        let mut count = self.texels;
+        for (pass, &groups) in self.passes.iter().enumerate() {
+            if pass > 0 {
+                dispatch_barrier(encoder);
+            }
+            encoder.bind_compute_descriptor_sets(
+                &self.pipeline_layout,
+                0,
+                Some(self.sets[pass].raw()),
+                std::iter::empty(),
+            );
+            encoder.push_constants(
+                &self.pipeline_layout,
+                hal::pso::ShaderStageFlags::COMPUTE,
+                0,
+                &[(pass == 0) as u32, count],
+            );
+            encoder.dispatch(groups, 1, 1);
+            count = groups;
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The commands recorded with it must be complete.
+    pub unsafe fn dispose(self, factory: &Factory<B>) {
+        drop(self.sets);
+        drop(self.scratch);
+        factory.device().destroy_compute_pipeline(self.pipeline);
+        factory
+            .device()
+            .destroy_pipeline_layout(self.pipeline_layout);
+    }
+}
+
+/// Exclusive prefix sums of a range of `u32`, in place.
+///
+/// The sums of the groups are scanned in turn, level after level, then
+/// added back to the groups under them.
+pub struct PrefixSum<B: hal::Backend> {
+    levels: Vec<u32>,
+    offsets: Vec<u64>,
+    scratch: Escape<Buffer<B>>,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for PrefixSum<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PrefixSum({} values)", self.levels[0])
+    }
+}
+
+impl<B: hal::Backend> PrefixSum<B> {
+    /// Prefix sums of the `count` values of `data` from `offset`, a storage
+    /// buffer.
+    pub fn new(
+        factory: &Factory<B>,
+        data: &B::Buffer,
+        offset: u64,
+        count: u32,
+    ) -> Result<Self, NodeBuildError> {
+        const VALUE_SIZE: u64 = size_of::<u32>() as u64;
+
+        let levels = scan_levels(count);
+        let align = factory
+            .physical()
+            .limits()
+            .min_storage_buffer_offset_alignment;
+        // The first level is `data`, the others are kept here.
+        let (mut offsets, size) = pack(&levels[1..], VALUE_SIZE, align);
+        let scratch = factory
+            .create_buffer(
+                BufferInfo {
+                    size,
+                    usage: hal::buffer::Usage::STORAGE | hal::buffer::Usage::TRANSFER_SRC,
+                },
+                Data,
+            )
+            .unwrap();
+        offsets.insert(0, offset);
+
+        let set_layout: Handle<DescriptorSetLayout<B>> = factory
+            .create_descriptor_set_layout(vec![
+                layout_binding(0, hal::pso::DescriptorType::StorageBuffer),
+                layout_binding(1, hal::pso::DescriptorType::StorageBuffer),
+            ])
+            .map_err(NodeBuildError::OutOfMemory)?
+            .into();
+
+        // One set per level below the total, with the level above it. Used
+        // both to scan the level and to add the scanned level above back.
+        let sets: Vec<_> = (0..levels.len() - 1)
+            .map(|_| factory.create_descriptor_set(set_layout.clone()).unwrap())
+            .collect();
+        let mut writes = Vec::new();
+        for (level, set) in sets.iter().enumerate() {
+            let buffer = if level == 0 { data } else { scratch.raw() };
+            writes.push(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 0,
+                array_offset: 0,
+                descriptors: Some(storage::<B>(
+                    buffer,
+                    offsets[level],
+                    levels[level] as u64 * VALUE_SIZE,
+                )),
+            });
+            writes.push(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 1,
+                array_offset: 0,
+                descriptors: Some(storage::<B>(
+                    scratch.raw(),
+                    offsets[level + 1],
+                    levels[level + 1] as u64 * VALUE_SIZE,
+                )),
+            });
+        }
+        unsafe { factory.write_descriptor_sets(writes) };
+
+        let (pipeline_layout, pipeline) = create_pipeline(factory, &SCAN, &set_layout)?;
+        Ok(PrefixSum {
+            levels,
+            offsets,
+            scratch,
+            sets,
+            pipeline_layout,
+            pipeline,
+        })
+    }
+
+    /// Buffer and offset of the total of the values, a `u32`, the count of
+    /// elements kept when compacting.
+    pub fn total(&self) -> (&B::Buffer, u64) {
+        (self.scratch.raw(), self.offsets[self.offsets.len() - 1])
+    }
+
+    /// Record the scans and additions, `data` holds the prefix sums once
+    /// they're done.
+    ///
+    /// # Safety
+    ///
+    /// The range of `data` must be readable and writable by compute
+    /// shaders at this point of the commands. A barrier is needed before
+    /// reading the sums or the total.
+    pub unsafe fn record<C, L>(&self, encoder: &mut Encoder<'_, B, C, L>)
+    where
+        C: Supports<Compute>,
+    {
+        encoder.bind_compute_pipeline(&self.pipeline);
+        let below_total = self.levels.len() - 1;
+        for level in 0..below_total {
+            if level > 0 {
+                dispatch_barrier(encoder);
+            }
+            self.dispatch(encoder, level, false);
+        }
+        // The top level below the total is complete once scanned.
+        for level in (0..below_total - 1).rev() {
+            dispatch_barrier(encoder);
+            self.dispatch(encoder, level, true);
+        }
+    }
+
+    unsafe fn dispatch<C, L>(&self, encoder: &mut Encoder<'_, B, C, L>, level: usize, add: bool)
+    where
+        C: Supports<Compute>,
+    {
+        encoder.bind_compute_descriptor_sets(
+            &self.pipeline_layout,
+            0,
+            Some(self.sets[level].raw()),
+            std::iter::empty(),
+        );
+        encoder.push_constants(
+            &self.pipeline_layout,
+            hal::pso::ShaderStageFlags::COMPUTE,
+            0,
+            &[add as u32, self.levels[level]],
+        );
+        encoder.dispatch(self.levels[level + 1], 1, 1);
+    }
+
+    /// # Safety
+    ///
+    /// The commands recorded with it must be complete.
+    pub unsafe fn dispose(self, factory: &Factory<B>) {
+        drop(self.sets);
+        drop(self.scratch);
+        factory.device().destroy_compute_pipeline(self.pipeline);
+        factory
+            .device()
+            .destroy_pipeline_layout(self.pipeline_layout);
+    }
+}
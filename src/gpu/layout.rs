@@ -0,0 +1,221 @@
+//! Descriptor set layouts of the pipelines, taken from the shaders.
+//!
+//! Each pipeline declares the layout it expects with `binding` and the
+//! `LayoutDesc` builder. With the `experimental-spirv-reflection` feature,
+//! `resolve` reflects the layout from the compiled shaders instead and logs
+//! where the declared one disagrees, so a binding changed in the GLSL and
+//! not in the Rust code shows up when the graph is built rather than as a
+//! device error. Without the feature the declared layout is used as is.
+
+use std::fmt;
+use std::ops::Range;
+
+use rendy::graph::render::{Layout, SetLayout};
+use rendy::hal;
+use rendy::shader::ShaderSetBuilder;
+
+/// Binding of a single descriptor of `ty` in the `stages`.
+pub fn binding(
+    binding: u32,
+    ty: hal::pso::DescriptorType,
+    stages: hal::pso::ShaderStageFlags,
+) -> hal::pso::DescriptorSetLayoutBinding {
+    hal::pso::DescriptorSetLayoutBinding {
+        binding,
+        ty,
+        count: 1,
+        stage_flags: stages,
+        immutable_samplers: false,
+    }
+}
+
+/// Layout of a pipeline, set after set.
+#[derive(Debug, Default)]
+pub struct LayoutDesc {
+    sets: Vec<SetLayout>,
+    push_constants: Vec<(hal::pso::ShaderStageFlags, Range<u32>)>,
+}
+
+impl LayoutDesc {
+    pub fn new() -> Self {
+        LayoutDesc::default()
+    }
+
+    /// Add the next set, made of `bindings`.
+    pub fn with_set<I>(mut self, bindings: I) -> Self
+    where
+        I: IntoIterator<Item = hal::pso::DescriptorSetLayoutBinding>,
+    {
+        self.sets.push(SetLayout {
+            bindings: bindings.into_iter().collect(),
+        });
+        self
+    }
+
+    /// Add push constants, `range` in bytes.
+    pub fn with_push_constants(
+        mut self,
+        stages: hal::pso::ShaderStageFlags,
+        range: Range<u32>,
+    ) -> Self {
+        self.push_constants.push((stages, range));
+        self
+    }
+
+    pub fn build(self) -> Layout {
+        Layout {
+            sets: self.sets,
+            push_constants: self.push_constants,
+        }
+    }
+}
+
+/// Difference between a declared layout and the one of the shaders.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutMismatch {
+    /// Binding used by the shaders and not declared.
+    Missing { set: usize, binding: u32 },
+
+    /// Declared binding of another type than in the shaders.
+    Type {
+        set: usize,
+        binding: u32,
+        declared: hal::pso::DescriptorType,
+        reflected: hal::pso::DescriptorType,
+    },
+
+    /// Declared binding visible to fewer stages than use it.
+    Stages {
+        set: usize,
+        binding: u32,
+        missing: hal::pso::ShaderStageFlags,
+    },
+
+    /// Push constants of the shaders not covered by the declared ones.
+    PushConstants {
+        stages: hal::pso::ShaderStageFlags,
+        range: Range<u32>,
+    },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutMismatch::Missing { set, binding } => {
+                write!(f, "set {} binding {} is not declared", set, binding)
+            }
+            LayoutMismatch::Type {
+                set,
+                binding,
+                declared,
+                reflected,
+            } => write!(
+                f,
+                "set {} binding {} is declared {:?} but is {:?} in the shaders",
+                set, binding, declared, reflected
+            ),
+            LayoutMismatch::Stages {
+                set,
+                binding,
+                missing,
+            } => write!(
+                f,
+                "set {} binding {} is not visible to {:?}",
+                set, binding, missing
+            ),
+            LayoutMismatch::PushConstants { stages, range } => write!(
+                f,
+                "push constants {:?} of {:?} are not declared",
+                range, stages
+            ),
+        }
+    }
+}
+
+/// Where `declared` doesn't provide what the `reflected` layout of the
+/// shaders needs. Declared bindings the shaders don't use are fine.
+pub fn mismatches(declared: &Layout, reflected: &Layout) -> Vec<LayoutMismatch> {
+    let mut mismatches = Vec::new();
+    for (set, reflected_set) in reflected.sets.iter().enumerate() {
+        let declared_set = declared.sets.get(set);
+        for used in &reflected_set.bindings {
+            let found = declared_set.and_then(|declared_set| {
+                declared_set
+                    .bindings
+                    .iter()
+                    .find(|binding| binding.binding == used.binding)
+            });
+            let binding = match found {
+                Some(binding) => binding,
+                None => {
+                    mismatches.push(LayoutMismatch::Missing {
+                        set,
+                        binding: used.binding,
+                    });
+                    continue;
+                }
+            };
+            if binding.ty != used.ty {
+                mismatches.push(LayoutMismatch::Type {
+                    set,
+                    binding: used.binding,
+                    declared: binding.ty,
+                    reflected: used.ty,
+                });
+            }
+            let missing = used.stage_flags - binding.stage_flags;
+            if !missing.is_empty() {
+                mismatches.push(LayoutMismatch::Stages {
+                    set,
+                    binding: used.binding,
+                    missing,
+                });
+            }
+        }
+    }
+    for (stages, range) in &reflected.push_constants {
+        let covered = declared
+            .push_constants
+            .iter()
+            .any(|(declared, declared_range)| {
+                declared.contains(*stages)
+                    && declared_range.start <= range.start
+                    && declared_range.end >= range.end
+            });
+        if !covered {
+            mismatches.push(LayoutMismatch::PushConstants {
+                stages: *stages,
+                range: range.clone(),
+            });
+        }
+    }
+    mismatches
+}
+
+/// Layout of the pipeline `name` built from `shaders`, reflected with the
+/// `experimental-spirv-reflection` feature, otherwise `declared`.
+#[cfg(feature = "experimental-spirv-reflection")]
+pub(crate) fn resolve(name: &str, declared: Layout, shaders: &ShaderSetBuilder) -> Layout {
+    let reflected = shaders
+        .reflect()
+        .map_err(|err| format!("{:?}", err))
+        .and_then(|reflection| reflection.layout().map_err(|err| format!("{:?}", err)));
+    let reflected = match reflected {
+        Ok(reflected) => reflected,
+        Err(err) => {
+            warn!("Can't reflect the layout of {}: {}.", name, err);
+            return declared;
+        }
+    };
+    for mismatch in mismatches(&declared, &reflected) {
+        warn!("Layout of {}: {}.", name, mismatch);
+    }
+    reflected
+}
+
+/// Layout of the pipeline `name` built from `shaders`, reflected with the
+/// `experimental-spirv-reflection` feature, otherwise `declared`.
+#[cfg(not(feature = "experimental-spirv-reflection"))]
+pub(crate) fn resolve(_name: &str, declared: Layout, _shaders: &ShaderSetBuilder) -> Layout {
+    declared
+}
@@ -0,0 +1,5 @@
+//! Building blocks shared by the GPU passes.
+
+pub mod compute;
+pub mod layout;
+pub mod specialization;
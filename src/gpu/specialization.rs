@@ -0,0 +1,83 @@
+//! Specialization constants, values of the SPIR-V set when a pipeline is
+//! created instead of written in the shader source.
+//!
+//! A shader declares `layout(constant_id = N) const ...` with a default and
+//! the pipeline overrides it, without compiling the shader again.
+
+use rendy::hal;
+use rendy::shader::SpecConstantSet;
+
+use crate::color::OutputEncoding;
+
+/// Id of the `OUTPUT_GAMMA` constant of `color.glsl`.
+const OUTPUT_GAMMA: u32 = 15;
+
+/// Constants of a shader stage, added one by one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpecConstants {
+    constants: Vec<hal::pso::SpecializationConstant>,
+    data: Vec<u8>,
+}
+
+impl SpecConstants {
+    pub fn new() -> Self {
+        SpecConstants::default()
+    }
+
+    fn with_bytes(mut self, id: u32, bytes: [u8; 4]) -> Self {
+        let start = self.data.len() as u16;
+        self.constants.push(hal::pso::SpecializationConstant {
+            id,
+            range: start..start + 4,
+        });
+        self.data.extend_from_slice(&bytes);
+        self
+    }
+
+    /// Set the `uint` or `int` constant `id`, the bits of `value`.
+    pub fn with_u32(self, id: u32, value: u32) -> Self {
+        self.with_bytes(id, value.to_ne_bytes())
+    }
+
+    /// Set the `float` constant `id`.
+    pub fn with_f32(self, id: u32, value: f32) -> Self {
+        self.with_bytes(id, value.to_bits().to_ne_bytes())
+    }
+
+    /// Set the `bool` constant `id`, 32 bits wide in SPIR-V.
+    pub fn with_bool(self, id: u32, value: bool) -> Self {
+        self.with_u32(id, value as u32)
+    }
+
+    /// Set the `OUTPUT_GAMMA` constant of a shader including `color.glsl`.
+    pub fn with_output(self, output: OutputEncoding) -> Self {
+        self.with_f32(OUTPUT_GAMMA, output.constant())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+
+    pub fn specialization(&self) -> hal::pso::Specialization<'static> {
+        hal::pso::Specialization {
+            constants: self.constants.clone().into(),
+            data: self.data.clone().into(),
+        }
+    }
+
+    /// Constants of the vertex stage of a shader set.
+    pub fn vertex(&self) -> SpecConstantSet {
+        SpecConstantSet {
+            vertex: Some(self.specialization()),
+            ..SpecConstantSet::default()
+        }
+    }
+
+    /// Constants of the fragment stage of a shader set.
+    pub fn fragment(&self) -> SpecConstantSet {
+        SpecConstantSet {
+            fragment: Some(self.specialization()),
+            ..SpecConstantSet::default()
+        }
+    }
+}
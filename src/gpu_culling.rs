@@ -0,0 +1,510 @@
+//! Compute pre-pass culling the scene instances on the GPU.
+//!
+//! The node writes a `DrawIndexedCommand` followed by the visible model
+//! matrices into a graph buffer, read back by the mesh pipeline.
+//!
+//! With occlusion culling, instances hidden in the previous frame's depth
+//! pyramid built by `hiz` are dropped as well.
+
+use std::mem::size_of;
+
+use rendy::command::{
+    CommandBuffer, CommandPool, Compute, DrawIndexedCommand, ExecutableState, Family, Fence,
+    MultiShot, PendingState, Queue, SimultaneousUse, Submission, Submit,
+};
+use rendy::factory::Factory;
+use rendy::frame::Frames;
+use rendy::graph::{
+    gfx_acquire_barriers, gfx_release_barriers, BufferAccess, GraphContext, ImageAccess, Node,
+    NodeBuffer, NodeBuildError, NodeDesc, NodeImage,
+};
+use rendy::hal::{self, adapter::PhysicalDevice, device::Device};
+use rendy::resource::{
+    Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Filter, Handle, ImageView,
+    ImageViewInfo, SamplerDesc, ViewKind, WrapMode,
+};
+use rendy::shader::{Shader, ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader};
+
+use crate::coords::ChunkCoord;
+use crate::culling::Frustum;
+use crate::hiz;
+use crate::mapped::MappedBuffer;
+use crate::mesh::{self, iceil, MAX_OBJECTS};
+use crate::scene::Scene;
+use crate::vertex::InstanceData;
+
+lazy_static::lazy_static! {
+    static ref COMPUTE: SpirvShader = SourceShaderInfo::new(
+        include_str!("../cull.comp"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/cull.comp").into(),
+        ShaderKind::Compute,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref OCCLUSION_SOURCE: String =
+        include_str!("../cull.comp").replacen('\n', "\n#define OCCLUSION\n", 1);
+
+    static ref OCCLUSION_COMPUTE: SpirvShader = SourceShaderInfo::new(
+        &OCCLUSION_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/cull.comp").into(),
+        ShaderKind::Compute,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+}
+
+/// Compile the culling shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&COMPUTE);
+    lazy_static::initialize(&OCCLUSION_COMPUTE);
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct CullArgs {
+    planes: [[f32; 4]; 6],
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    prev_view_proj: [[f32; 4]; 4],
+    hiz_size: [f32; 2],
+    count: u32,
+    hiz_levels: u32,
+    occlusion: u32,
+}
+
+const GROUP_SIZE: u32 = 64;
+const ARGS_SIZE: u64 = size_of::<CullArgs>() as u64;
+const COMMAND_SIZE: u64 = size_of::<DrawIndexedCommand>() as u64;
+const MODELS_SIZE: u64 = size_of::<InstanceData>() as u64 * MAX_OBJECTS as u64;
+
+/// Offset of the models in the output buffer, after the draw command.
+pub const OUTPUT_MODELS_OFFSET: u64 = 32;
+
+/// Size of the graph buffer written by the pre-pass.
+pub const OUTPUT_SIZE: u64 = OUTPUT_MODELS_OFFSET + MODELS_SIZE;
+
+fn args_offset(index: usize, align: u64) -> u64 {
+    frame_size(align) * index as u64
+}
+
+fn command_offset(index: usize, align: u64) -> u64 {
+    args_offset(index, align) + iceil(ARGS_SIZE, align)
+}
+
+fn models_offset(index: usize, align: u64) -> u64 {
+    command_offset(index, align) + iceil(COMMAND_SIZE, align)
+}
+
+fn frame_size(align: u64) -> u64 {
+    iceil(ARGS_SIZE, align) + iceil(COMMAND_SIZE, align) + iceil(MODELS_SIZE, align)
+}
+
+fn layout_binding(
+    binding: u32,
+    ty: hal::pso::DescriptorType,
+) -> hal::pso::DescriptorSetLayoutBinding {
+    hal::pso::DescriptorSetLayoutBinding {
+        binding,
+        ty,
+        count: 1,
+        stage_flags: hal::pso::ShaderStageFlags::COMPUTE,
+        immutable_samplers: false,
+    }
+}
+
+/// Culling pre-pass, expects the output buffer and, with `occlusion`, the
+/// depth pyramid.
+#[derive(Debug, Default)]
+pub struct CullNodeDesc {
+    pub occlusion: bool,
+}
+
+pub struct CullNode<B: hal::Backend> {
+    align: u64,
+    buffer: MappedBuffer<B>,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+    command_pool: CommandPool<B, Compute>,
+    command_buffers:
+        Vec<CommandBuffer<B, Compute, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>>,
+    submits: Vec<Submit<B, SimultaneousUse>>,
+    models: Vec<InstanceData>,
+    hiz_view: Option<Escape<ImageView<B>>>,
+    hiz_size: [f32; 2],
+    hiz_levels: u32,
+    /// Camera of the frame the pyramid was built from.
+    history: Option<(nalgebra::Matrix4<f32>, ChunkCoord)>,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for CullNode<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Cull Node")
+    }
+}
+
+impl<B> NodeDesc<B, Scene> for CullNodeDesc
+where
+    B: hal::Backend,
+{
+    type Node = CullNode<B>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        vec![BufferAccess {
+            access: hal::buffer::Access::TRANSFER_WRITE
+                | hal::buffer::Access::SHADER_READ
+                | hal::buffer::Access::SHADER_WRITE,
+            stages: hal::pso::PipelineStage::TRANSFER | hal::pso::PipelineStage::COMPUTE_SHADER,
+            usage: hal::buffer::Usage::TRANSFER_DST | hal::buffer::Usage::STORAGE,
+        }]
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        if self.occlusion {
+            vec![hiz::sampled_access()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        aux: &Scene,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert_eq!(images.len(), self.occlusion as usize);
+        assert_eq!(buffers.len(), 1);
+
+        let output = ctx.get_buffer(buffers[0].id).unwrap();
+        let frames = ctx.frames_in_flight as usize;
+        let limits = factory.physical().limits();
+        let align = limits
+            .min_uniform_buffer_offset_alignment
+            .max(limits.min_storage_buffer_offset_alignment);
+
+        let mut buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: frame_size(align) * frames as u64,
+                usage: hal::buffer::Usage::UNIFORM
+                    | hal::buffer::Usage::STORAGE
+                    | hal::buffer::Usage::TRANSFER_SRC,
+            },
+        );
+
+        // Copied over the output each frame to reset the instance count.
+        let command = DrawIndexedCommand {
+            index_count: mesh::model_index_count(),
+            instance_count: 0,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+        };
+
+        let mut bindings = vec![
+            layout_binding(0, hal::pso::DescriptorType::UniformBuffer),
+            layout_binding(1, hal::pso::DescriptorType::StorageBuffer),
+            layout_binding(2, hal::pso::DescriptorType::StorageBuffer),
+        ];
+        if self.occlusion {
+            bindings.push(layout_binding(
+                3,
+                hal::pso::DescriptorType::CombinedImageSampler,
+            ));
+        }
+        let set_layout: Handle<DescriptorSetLayout<B>> = factory
+            .create_descriptor_set_layout(bindings)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .into();
+
+        let sampler = factory
+            .get_sampler(SamplerDesc::new(Filter::Nearest, WrapMode::Clamp))
+            .map_err(NodeBuildError::OutOfMemory)?;
+
+        let (hiz_view, hiz_size, hiz_levels) = match images.first() {
+            Some(image) => {
+                let pyramid = ctx.get_image(image.id).unwrap();
+                let extent = pyramid.kind().extent();
+                let view = factory
+                    .create_image_view(
+                        pyramid.clone(),
+                        ImageViewInfo {
+                            view_kind: ViewKind::D2,
+                            format: hiz::FORMAT,
+                            swizzle: hal::format::Swizzle::NO,
+                            range: hal::image::SubresourceRange {
+                                aspects: hal::format::Aspects::COLOR,
+                                levels: 0..pyramid.levels(),
+                                layers: 0..1,
+                            },
+                        },
+                    )
+                    .unwrap();
+                (
+                    Some(view),
+                    [extent.width as f32, extent.height as f32],
+                    pyramid.levels() as u32,
+                )
+            }
+            None => (None, [0.0; 2], 0),
+        };
+
+        for index in 0..frames {
+            unsafe { buffer.write(factory, command_offset(index, align), &[command]) };
+        }
+
+        let sets = {
+            let _scope = aux.profiler.scope("gpu_culling.descriptors");
+            let sets: Vec<_> = (0..frames)
+                .map(|_| factory.create_descriptor_set(set_layout.clone()).unwrap())
+                .collect();
+
+            // Every binding of every frame written in a single call.
+            let mut writes = Vec::new();
+            for (index, set) in sets.iter().enumerate() {
+                writes.push(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Buffer(
+                        buffer.raw(),
+                        Some(args_offset(index, align))
+                            ..Some(args_offset(index, align) + ARGS_SIZE),
+                    )),
+                });
+                writes.push(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 1,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Buffer(
+                        buffer.raw(),
+                        Some(models_offset(index, align))
+                            ..Some(models_offset(index, align) + MODELS_SIZE),
+                    )),
+                });
+                writes.push(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 2,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Buffer(
+                        output.raw(),
+                        Some(0)..Some(OUTPUT_SIZE),
+                    )),
+                });
+                if let Some(ref view) = hiz_view {
+                    writes.push(hal::pso::DescriptorSetWrite {
+                        set: set.raw(),
+                        binding: 3,
+                        array_offset: 0,
+                        descriptors: Some(hal::pso::Descriptor::CombinedImageSampler(
+                            view.raw(),
+                            hal::image::Layout::General,
+                            sampler.raw(),
+                        )),
+                    });
+                }
+            }
+            unsafe { factory.write_descriptor_sets(writes) };
+            sets
+        };
+
+        let pipeline_layout = unsafe {
+            factory.device().create_pipeline_layout(
+                Some(set_layout.raw()),
+                std::iter::empty::<(hal::pso::ShaderStageFlags, std::ops::Range<u32>)>(),
+            )
+        }
+        .map_err(NodeBuildError::OutOfMemory)?;
+
+        let shader = if self.occlusion {
+            &*OCCLUSION_COMPUTE
+        } else {
+            &*COMPUTE
+        };
+        let module = unsafe { shader.module(factory) }.unwrap();
+        let pipeline = unsafe {
+            factory.device().create_compute_pipeline(
+                &hal::pso::ComputePipelineDesc {
+                    shader: hal::pso::EntryPoint {
+                        entry: "main",
+                        module: &module,
+                        specialization: hal::pso::Specialization::default(),
+                    },
+                    layout: &pipeline_layout,
+                    flags: hal::pso::PipelineCreationFlags::empty(),
+                    parent: hal::pso::BasePipeline::None,
+                },
+                None,
+            )
+        };
+        unsafe { factory.destroy_shader_module(module) };
+        let pipeline = pipeline.map_err(NodeBuildError::Pipeline)?;
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Compute>()
+            .expect("Graph builder must provide family with Compute capability");
+
+        let mut command_buffers = Vec::new();
+        let mut submits = Vec::new();
+        for (index, initial) in command_pool
+            .allocate_buffers(frames)
+            .into_iter()
+            .enumerate()
+        {
+            let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+            let mut encoder = recording.encoder();
+            {
+                let (stages, barriers) = gfx_acquire_barriers(ctx, &buffers, &images);
+                encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+            unsafe {
+                encoder.copy_buffer(
+                    buffer.raw(),
+                    output.raw(),
+                    Some(hal::command::BufferCopy {
+                        src: command_offset(index, align),
+                        dst: 0,
+                        size: COMMAND_SIZE,
+                    }),
+                );
+                encoder.pipeline_barrier(
+                    hal::pso::PipelineStage::TRANSFER..hal::pso::PipelineStage::COMPUTE_SHADER,
+                    hal::memory::Dependencies::empty(),
+                    Some(hal::memory::Barrier::Buffer {
+                        states: hal::buffer::Access::TRANSFER_WRITE
+                            ..hal::buffer::Access::SHADER_READ | hal::buffer::Access::SHADER_WRITE,
+                        target: output.raw(),
+                        families: None,
+                        range: None..None,
+                    }),
+                );
+                encoder.bind_compute_pipeline(&pipeline);
+                encoder.bind_compute_descriptor_sets(
+                    &pipeline_layout,
+                    0,
+                    Some(sets[index].raw()),
+                    std::iter::empty(),
+                );
+                encoder.dispatch((MAX_OBJECTS as u32 + GROUP_SIZE - 1) / GROUP_SIZE, 1, 1);
+            }
+            {
+                let (stages, barriers) = gfx_release_barriers(ctx, &buffers, &images);
+                encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+            let (submit, command_buffer) = recording.finish().submit();
+            submits.push(submit);
+            command_buffers.push(command_buffer);
+        }
+
+        Ok(CullNode {
+            align,
+            buffer,
+            sets,
+            pipeline_layout,
+            pipeline,
+            command_pool,
+            command_buffers,
+            submits,
+            models: Vec::with_capacity(MAX_OBJECTS),
+            hiz_view,
+            hiz_size,
+            hiz_levels,
+            history: None,
+        })
+    }
+}
+
+impl<B> Node<B, Scene> for CullNode<B>
+where
+    B: hal::Backend,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        queue: &mut Queue<B>,
+        aux: &Scene,
+        frames: &Frames<B>,
+        waits: &[(&'a B::Semaphore, hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        let index = frames.next().index() as usize % self.submits.len();
+        debug!("Cull Node, Running {}.", index);
+
+        self.models.clear();
+        self.models.extend(aux.instance_data().take(MAX_OBJECTS));
+
+        let view_proj = aux.view_proj();
+        let frustum = Frustum::from_matrix(&aux.culling_view_proj());
+        let mut planes = [[0.0; 4]; 6];
+        for (dst, plane) in planes.iter_mut().zip(frustum.planes.iter()) {
+            *dst = [plane.normal.x, plane.normal.y, plane.normal.z, plane.d];
+        }
+        // The pyramid is stale after the floating origin moved, and doesn't
+        // match a frozen culling view.
+        let history = self.history.filter(|&(_, origin)| {
+            self.hiz_view.is_some() && origin == aux.origin.origin() && !aux.is_culling_frozen()
+        });
+        let prev_view_proj = history.map(|(matrix, _)| matrix).unwrap_or(view_proj);
+        self.history = Some((view_proj, aux.origin.origin()));
+
+        let bounds = mesh::model_bounds();
+        let args = CullArgs {
+            planes,
+            bounds_min: [bounds.min.x, bounds.min.y, bounds.min.z, 1.0],
+            bounds_max: [bounds.max.x, bounds.max.y, bounds.max.z, 1.0],
+            prev_view_proj: prev_view_proj.into(),
+            hiz_size: self.hiz_size,
+            count: self.models.len() as u32,
+            hiz_levels: self.hiz_levels,
+            occlusion: history.is_some() as u32,
+        };
+
+        unsafe {
+            self.buffer
+                .write(factory, args_offset(index, self.align), &[args]);
+            if !self.models.is_empty() {
+                self.buffer
+                    .write(factory, models_offset(index, self.align), &self.models[..]);
+            }
+
+            queue.submit(
+                Some(
+                    Submission::new()
+                        .submits(Some(&self.submits[index]))
+                        .wait(waits.iter().cloned())
+                        .signal(signals.iter()),
+                ),
+                fence,
+            );
+        }
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &Scene) {
+        info!("Disposing Cull Node.");
+        drop(self.submits);
+        drop(self.sets);
+        drop(self.hiz_view);
+        self.command_pool.free_buffers(
+            self.command_buffers
+                .into_iter()
+                .map(|command_buffer| command_buffer.mark_complete()),
+        );
+        factory.destroy_command_pool(self.command_pool);
+        factory.device().destroy_compute_pipeline(self.pipeline);
+        factory
+            .device()
+            .destroy_pipeline_layout(self.pipeline_layout);
+    }
+}
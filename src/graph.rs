@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use rendy::{
     command::{
         CommandBuffer, CommandPool, Compute, DrawCommand, DrawIndexedCommand, ExecutableState,
@@ -33,56 +35,293 @@ use rendy::{
     wsi::Surface,
 };
 
-use crate::camera::Camera;
+use crate::clouds::{self, CloudsDesc};
+use crate::color::OutputEncoding;
+use crate::config::RendererConfig;
+use crate::console_overlay::ConsoleOverlayDesc;
+use crate::crossfade::{self, CaptureNodeDesc, CrossfadeDesc};
+use crate::frame_dump::FrameDumpNodeDesc;
+use crate::gpu_culling::{self, CullNodeDesc, OUTPUT_SIZE};
+use crate::hiz::{self, HiZNodeDesc};
+use crate::horizon::{self, HorizonDesc};
+use crate::impostor::{self, ImpostorDesc};
+use crate::jobs::JobSystem;
+use crate::letterbox::LetterboxDesc;
+use crate::outline::{self, OutlineDesc};
+use crate::plugin::RenderPassHook;
+use crate::precipitation::{self, PrecipitationDesc};
+use crate::scene::Scene;
+use crate::skinning::{self, SkinNodeDesc, SkinnedDesc};
+use crate::tonemap::{self, TonemapDesc};
+use crate::transient::{TransientImage, TransientPlanner};
+use crate::viewport::DynamicViewportDesc;
+
+/// Frames the graph records ahead of the device.
+pub(crate) const FRAMES_IN_FLIGHT: u32 = 3;
+
+/// Compile the shaders and load the assets of every group and node, so
+/// `build` doesn't. Runs on any thread, later calls return immediately.
+pub fn precompile() {
+    crate::mesh::precompile();
+    gpu_culling::precompile();
+    hiz::precompile();
+    crate::gpu::compute::precompile();
+    skinning::precompile();
+    impostor::precompile();
+    horizon::precompile();
+    clouds::precompile();
+    precipitation::precompile();
+    crossfade::precompile();
+    outline::precompile();
+    tonemap::precompile();
+}
+
+/// Resources `build` creates with `config`, for the rebuild events.
+pub fn resources(config: &RendererConfig, plugin_passes: bool) -> Vec<&'static str> {
+    let mut resources = vec!["surface", "swapchain", "depth", "scene pass"];
+    if config.gpu_culling {
+        resources.push("culling buffer");
+    }
+    if config.gpu_culling && config.occlusion_culling {
+        resources.push("depth pyramid");
+    }
+    if config.gpu_skinning {
+        resources.push("skinning buffer");
+    }
+    if config.hdr.is_some() {
+        resources.push("hdr image");
+    }
+    if config.crossfade > 0.0 || config.quality.resolution_scale != 1.0 || config.frame_dumps {
+        resources.push("final image");
+    }
+    if config.frame_dumps {
+        resources.push("frame dump buffer");
+    }
+    if plugin_passes {
+        resources.push("plugin passes");
+    }
+    resources
+}
 
+/// Build the render graph of `config` drawing to `surface`.
+///
+/// Descriptor set layouts only change here, when the graph is rebuilt.
+/// Each node writes all of its sets in a single `write_descriptor_sets`
+/// call from its `build`. The writes of several nodes can't share a call:
+/// rendy builds the nodes one after the other and moves each into the
+/// graph, and the writes borrow the resources the node owns.
 pub fn build<B>(
     mut families: &mut Families<B>,
     window: &Window,
     mut factory: &mut Factory<B>,
     surface: Surface<B>,
-    cam: &Camera,
-) -> Result<Graph<B, Camera>, GraphBuildError>
+    scene: &Scene,
+    config: &RendererConfig,
+    jobs: &Arc<JobSystem>,
+    render_passes: &[RenderPassHook<B>],
+) -> Result<Graph<B, Scene>, GraphBuildError>
 where
     B: hal::Backend,
 {
-    let mut graph_builder = GraphBuilder::<B, Camera>::new();
+    let _scope = scene.profiler.scope("graph.build");
+    let mut graph_builder = GraphBuilder::<B, Scene>::new().with_frames_in_flight(FRAMES_IN_FLIGHT);
 
     let size = window.inner_size();
+    let extents = config.quality.target_extents(size.width, size.height);
+    let scaled = extents.is_scaled();
+    let render_kind = extents.render;
+    let (width, height) = (render_kind.extent().width, render_kind.extent().height);
+
+    // Passes in submission order, used for the lifetime of transient targets.
+    const SCENE_PASS: usize = 1;
+    const HIZ_PASS: usize = 2;
+    const PLUGIN_PASSES: usize = 3;
+    const FRAME_DUMP_PASS: usize = 4;
 
-    let window_kind = hal::image::Kind::D2(size.width as u32, size.height as u32, 1, 1);
+    let occlusion = config.gpu_culling && config.occlusion_culling;
+    let mut transient = TransientPlanner::new();
 
     // Create the depth stencil image.
-    let depth = graph_builder.create_image(
-        window_kind,
-        1,
-        hal::format::Format::D32Sfloat,
-        Some(hal::command::ClearValue {
-            depth_stencil: hal::command::ClearDepthStencil {
-                depth: 1.,
-                stencil: 0,
-            },
-        }),
-    );
+    let depth = transient.request(TransientImage {
+        kind: render_kind,
+        levels: 1,
+        format: hal::format::Format::D32Sfloat,
+        clear: config.scene_pass.depth_clear_value(),
+        first: SCENE_PASS,
+        last: if config.frame_dumps {
+            FRAME_DUMP_PASS
+        } else if !render_passes.is_empty() {
+            PLUGIN_PASSES
+        } else if occlusion {
+            HIZ_PASS
+        } else {
+            SCENE_PASS
+        },
+    });
+    let transient = transient.build(&mut graph_builder);
+    let depth = transient[depth];
+
+    // Colors are encoded by the surface if it has an sRGB format, by the
+    // shaders otherwise. With HDR the scene stays linear until the tonemap
+    // pass encodes it.
+    let format = factory.get_surface_format(&surface);
+    let surface_output = OutputEncoding::for_format(format, config.tuning.output_gamma);
+    let output = if config.hdr.is_some() {
+        OutputEncoding::Linear
+    } else {
+        surface_output
+    };
 
-    let _meshpass = graph_builder.add_node(
-        crate::mesh::Pipeline::builder()
+    let mut pipeline = DynamicViewportDesc::new(crate::mesh::PipelineDesc {
+        gpu_culling: config.gpu_culling,
+        shading: config.shading,
+        tuning: config.tuning,
+        output,
+    })
+    .builder();
+
+    let pyramid = if occlusion {
+        let (kind, levels) = hiz::pyramid_kind(width, height);
+        Some(graph_builder.create_image(kind, levels, hiz::FORMAT, None))
+    } else {
+        None
+    };
+
+    if config.gpu_culling {
+        let culled = graph_builder.create_buffer(OUTPUT_SIZE);
+        let mut cull = CullNodeDesc { occlusion }.builder().with_buffer(culled);
+        if let Some(pyramid) = pyramid {
+            cull.add_image(pyramid);
+        }
+        graph_builder.add_node(cull);
+        pipeline.add_buffer(culled);
+    }
+
+    let mut skinned = DynamicViewportDesc::new(SkinnedDesc {
+        gpu_skinning: config.gpu_skinning,
+        shading: config.shading,
+        tuning: config.tuning,
+        output,
+    })
+    .builder();
+    if config.gpu_skinning {
+        let vertices = graph_builder.create_buffer(skinning::OUTPUT_SIZE);
+        graph_builder.add_node(SkinNodeDesc.builder().with_buffer(vertices));
+        skinned.add_buffer(vertices);
+    }
+
+    let mut subpass = pipeline
+        .into_subpass()
+        .with_group(skinned)
+        .with_group(DynamicViewportDesc::new(ImpostorDesc { output }).builder());
+    if config.quality.post_effects {
+        subpass.add_group(
+            DynamicViewportDesc::new(OutlineDesc {
+                gpu_culling: config.gpu_culling,
+                output,
+            })
+            .builder(),
+        );
+    }
+    let mut subpass = subpass
+        .with_group(DynamicViewportDesc::new(HorizonDesc { output }).builder())
+        .with_group(
+            DynamicViewportDesc::new(CloudsDesc {
+                steps: config.tuning.cloud_steps,
+                output,
+            })
+            .builder(),
+        )
+        .with_group(DynamicViewportDesc::new(PrecipitationDesc { output }).builder())
+        .with_group(LetterboxDesc { output }.builder())
+        .with_depth_stencil(depth);
+    let clear = config.scene_pass.color_clear_value(output);
+
+    // With HDR the scene pass draws to a half float image, drawn to the
+    // final target by the tonemap pass. The groups over the frame go in the
+    // last pass, on the encoded colors.
+    let (mut subpass, clear) = match config.hdr {
+        Some(settings) => {
+            let hdr = graph_builder.create_image(render_kind, 1, tonemap::FORMAT, clear);
+            let scene = graph_builder.add_node(subpass.with_color(hdr).into_pass());
+            let tonemap = TonemapDesc {
+                settings,
+                output: surface_output,
+            }
+            .builder()
+            .with_image(hdr)
             .into_subpass()
-            .with_depth_stencil(depth)
-            .with_color_surface()
-            .into_pass()
-            .with_surface(
-                surface,
-                hal::window::Extent2D {
-                    width: size.width as _,
-                    height: size.height as _,
-                },
-                Some(hal::command::ClearValue {
-                    color: hal::command::ClearColor {
-                        float32: [0.8, 0.8, 0.8, 1.0],
-                    },
-                }),
-            ),
+            .with_dependency(scene);
+            (tonemap, None)
+        }
+        None => (subpass, clear),
+    };
+
+    // The crossfade and the frame dumps need the frame in an image they can
+    // copy, blitted to the surface afterwards. The blit also stretches
+    // scaled frames.
+    let crossfade = config.crossfade > 0.0 && crossfade::texel_layout(format).is_some();
+    if crossfade {
+        subpass.add_group(
+            CrossfadeDesc {
+                duration: config.crossfade,
+                width,
+                height,
+                output: surface_output,
+            }
+            .builder(),
+        );
+    }
+    // Over the crossfade, the console doesn't fade with the frame.
+    subpass.add_group(
+        ConsoleOverlayDesc {
+            output: surface_output,
+            depth: config.hdr.is_none(),
+        }
+        .builder(),
     );
+    let color = if crossfade || scaled || config.frame_dumps {
+        let color = graph_builder.create_image(render_kind, 1, format, clear);
+        let last = graph_builder.add_node(subpass.with_color(color).into_pass());
+        if crossfade {
+            graph_builder.add_node(CaptureNodeDesc.builder().with_image(color));
+        }
+        graph_builder
+            .add_node(PresentNode::builder(&factory, surface, color).with_dependency(last));
+        Some(color)
+    } else {
+        graph_builder.add_node(subpass.with_color_surface().into_pass().with_surface(
+            surface,
+            extents.surface,
+            clear,
+        ));
+        None
+    };
+
+    // Built after the scene pass, read by the culling of the next frame.
+    if let Some(pyramid) = pyramid {
+        graph_builder.add_node(HiZNodeDesc.builder().with_image(depth).with_image(pyramid));
+    }
+
+    for hook in render_passes {
+        hook(&mut graph_builder, depth);
+    }
+
+    // Last, so the targets are dumped once every pass is done with them.
+    if config.frame_dumps {
+        let mut targets = vec![("depth", depth)];
+        targets.extend(color.map(|color| ("color", color)));
+        targets.extend(pyramid.map(|pyramid| ("hiz", pyramid)));
+        let mut dump = FrameDumpNodeDesc {
+            names: targets.iter().map(|&(name, _)| name).collect(),
+            jobs: jobs.clone(),
+        }
+        .builder();
+        for (_, image) in targets {
+            dump.add_image(image);
+        }
+        graph_builder.add_node(dump);
+    }
 
-    graph_builder.build(&mut factory, &mut families, &cam)
+    graph_builder.build(&mut factory, &mut families, scene)
 }
@@ -0,0 +1,216 @@
+//! Generational handles to the resources owned by the scene.
+//!
+//! A handle is an index into a `HandleMap` and the generation of its slot.
+//! Removing a value bumps the generation, so handles kept past the removal
+//! resolve to nothing instead of to the value reusing the slot.
+//!
+//! Values the frames in flight may still use are released rather than
+//! removed: their handles stop resolving right away but the value and its
+//! slot are only freed by `collect`, once those frames completed.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use crate::material::{Material, Texture};
+use crate::objects::SceneObject;
+use crate::skinning::SkinnedMesh;
+
+pub type SkinnedMeshHandle = Handle<SkinnedMesh>;
+pub type TextureHandle = Handle<Texture>;
+pub type MaterialHandle = Handle<Material>;
+pub type ObjectHandle = Handle<SceneObject>;
+
+/// Reference to a value of a `HandleMap<T>`.
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+}
+
+// Implemented by hand, deriving would require `T` to implement them.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// Values addressed by generational handles.
+#[derive(Debug, Clone)]
+pub struct HandleMap<T> {
+    slots: Vec<Slot<T>>,
+
+    /// Indices of the empty slots.
+    free: Vec<u32>,
+
+    /// Released values with their index and the frame they were released in.
+    released: Vec<(u64, u32, T)>,
+}
+
+impl<T> Default for HandleMap<T> {
+    fn default() -> Self {
+        HandleMap {
+            slots: Vec::new(),
+            free: Vec::new(),
+            released: Vec::new(),
+        }
+    }
+}
+
+impl<T> HandleMap<T> {
+    pub fn new() -> Self {
+        HandleMap::default()
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: None,
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        slot.value = Some(value);
+        Handle {
+            index,
+            generation: slot.generation,
+            marker: PhantomData,
+        }
+    }
+
+    /// Value of `handle`, `None` once removed.
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        self.slots
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.value.as_mut())
+    }
+
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// Remove the value of `handle`, every copy of the handle is invalid
+    /// afterwards.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        let value = self.invalidate(handle)?;
+        self.free.push(handle.index);
+        Some(value)
+    }
+
+    /// Invalidate `handle` and keep its value until `collect` is called
+    /// with `frame` complete. Returns whether the handle was valid.
+    pub fn release(&mut self, handle: Handle<T>, frame: u64) -> bool {
+        match self.invalidate(handle) {
+            Some(value) => {
+                self.released.push((frame, handle.index, value));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop the values released in frames up to `completed` and reuse their
+    /// slots. Returns the number of values dropped.
+    pub fn collect(&mut self, completed: u64) -> usize {
+        let before = self.released.len();
+        let free = &mut self.free;
+        self.released.retain(|&(frame, index, _)| {
+            if frame <= completed {
+                free.push(index);
+                false
+            } else {
+                true
+            }
+        });
+        before - self.released.len()
+    }
+
+    /// Values released and not collected yet.
+    pub fn released(&self) -> usize {
+        self.released.len()
+    }
+
+    fn invalidate(&mut self, handle: Handle<T>) -> Option<T> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len() - self.released.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value.as_ref().map(|value| {
+                (
+                    Handle {
+                        index: index as u32,
+                        generation: slot.generation,
+                        marker: PhantomData,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+}
@@ -0,0 +1,381 @@
+//! Hierarchical depth pyramid used for occlusion culling.
+//!
+//! Each level stores the farthest depth of the texels it covers, built from
+//! the depth buffer once the scene has been drawn. The pyramid is read by the
+//! `gpu_culling` pre-pass of the next frame.
+
+use rendy::command::{
+    CommandBuffer, CommandPool, Compute, ExecutableState, Family, Fence, MultiShot, PendingState,
+    Queue, SimultaneousUse, Submission, Submit,
+};
+use rendy::factory::Factory;
+use rendy::frame::Frames;
+use rendy::graph::{
+    gfx_acquire_barriers, gfx_release_barriers, GraphContext, ImageAccess, Node, NodeBuffer,
+    NodeBuildError, NodeDesc, NodeImage,
+};
+use rendy::hal::{self, device::Device};
+use rendy::resource::{
+    DescriptorSet, DescriptorSetLayout, Escape, Filter, Handle, ImageView, ImageViewInfo,
+    SamplerDesc, ViewKind, WrapMode,
+};
+use rendy::shader::{Shader, ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader};
+
+use crate::scene::Scene;
+
+lazy_static::lazy_static! {
+    static ref COMPUTE: SpirvShader = SourceShaderInfo::new(
+        include_str!("../hiz.comp"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/hiz.comp").into(),
+        ShaderKind::Compute,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+}
+
+/// Compile the pyramid shader ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&COMPUTE);
+}
+
+/// Format of the pyramid levels.
+pub const FORMAT: hal::format::Format = hal::format::Format::R32Sfloat;
+
+const GROUP_SIZE: u32 = 8;
+
+/// Kind and level count of the pyramid for a depth buffer of `width` by `height`.
+///
+/// The first level is half the depth buffer resolution.
+pub fn pyramid_kind(width: u32, height: u32) -> (hal::image::Kind, u8) {
+    let width = ((width + 1) / 2).max(1);
+    let height = ((height + 1) / 2).max(1);
+    let levels = 32 - width.max(height).leading_zeros();
+    (hal::image::Kind::D2(width, height, 1, 1), levels as u8)
+}
+
+/// Access of the culling pass to the pyramid.
+pub fn sampled_access() -> ImageAccess {
+    ImageAccess {
+        access: hal::image::Access::SHADER_READ,
+        usage: hal::image::Usage::SAMPLED,
+        layout: hal::image::Layout::General,
+        stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+    }
+}
+
+fn layout_binding(
+    binding: u32,
+    ty: hal::pso::DescriptorType,
+) -> hal::pso::DescriptorSetLayoutBinding {
+    hal::pso::DescriptorSetLayoutBinding {
+        binding,
+        ty,
+        count: 1,
+        stage_flags: hal::pso::ShaderStageFlags::COMPUTE,
+        immutable_samplers: false,
+    }
+}
+
+/// Node building the pyramid, expects the depth image then the pyramid image.
+#[derive(Debug, Default)]
+pub struct HiZNodeDesc;
+
+pub struct HiZNode<B: hal::Backend> {
+    views: Vec<Escape<ImageView<B>>>,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+    command_pool: CommandPool<B, Compute>,
+    command_buffer:
+        CommandBuffer<B, Compute, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>,
+    submit: Submit<B, SimultaneousUse>,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for HiZNode<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "HiZ Node")
+    }
+}
+
+impl<B> NodeDesc<B, Scene> for HiZNodeDesc
+where
+    B: hal::Backend,
+{
+    type Node = HiZNode<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![
+            ImageAccess {
+                access: hal::image::Access::SHADER_READ,
+                usage: hal::image::Usage::SAMPLED,
+                layout: hal::image::Layout::ShaderReadOnlyOptimal,
+                stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+            },
+            ImageAccess {
+                access: hal::image::Access::SHADER_READ | hal::image::Access::SHADER_WRITE,
+                usage: hal::image::Usage::STORAGE,
+                layout: hal::image::Layout::General,
+                stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+            },
+        ]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        aux: &Scene,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 2);
+
+        let depth = ctx.get_image(images[0].id).unwrap();
+        let pyramid = ctx.get_image(images[1].id).unwrap();
+        let levels = pyramid.levels();
+        let extent = pyramid.kind().extent();
+
+        let sampler = factory
+            .get_sampler(SamplerDesc::new(Filter::Nearest, WrapMode::Clamp))
+            .map_err(NodeBuildError::OutOfMemory)?;
+
+        let depth_view = factory
+            .create_image_view(
+                depth.clone(),
+                ImageViewInfo {
+                    view_kind: ViewKind::D2,
+                    format: depth.format(),
+                    swizzle: hal::format::Swizzle::NO,
+                    range: hal::image::SubresourceRange {
+                        aspects: hal::format::Aspects::DEPTH,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                },
+            )
+            .unwrap();
+
+        let mut views = Vec::new();
+        for level in 0..levels {
+            let view = factory
+                .create_image_view(
+                    pyramid.clone(),
+                    ImageViewInfo {
+                        view_kind: ViewKind::D2,
+                        format: FORMAT,
+                        swizzle: hal::format::Swizzle::NO,
+                        range: hal::image::SubresourceRange {
+                            aspects: hal::format::Aspects::COLOR,
+                            levels: level..level + 1,
+                            layers: 0..1,
+                        },
+                    },
+                )
+                .unwrap();
+            views.push(view);
+        }
+
+        let set_layout: Handle<DescriptorSetLayout<B>> = factory
+            .create_descriptor_set_layout(vec![
+                layout_binding(0, hal::pso::DescriptorType::CombinedImageSampler),
+                layout_binding(1, hal::pso::DescriptorType::StorageImage),
+                layout_binding(2, hal::pso::DescriptorType::StorageImage),
+            ])
+            .map_err(NodeBuildError::OutOfMemory)?
+            .into();
+
+        // One set per level, the first one reads the depth buffer.
+        let sets = {
+            let _scope = aux.profiler.scope("hiz.descriptors");
+            let sets: Vec<_> = (0..levels)
+                .map(|_| factory.create_descriptor_set(set_layout.clone()).unwrap())
+                .collect();
+
+            let mut writes = Vec::new();
+            for (level, set) in sets.iter().enumerate() {
+                let src = &views[level.saturating_sub(1)];
+                writes.push(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::CombinedImageSampler(
+                        depth_view.raw(),
+                        hal::image::Layout::ShaderReadOnlyOptimal,
+                        sampler.raw(),
+                    )),
+                });
+                writes.push(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 1,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Image(
+                        src.raw(),
+                        hal::image::Layout::General,
+                    )),
+                });
+                writes.push(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 2,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Image(
+                        views[level].raw(),
+                        hal::image::Layout::General,
+                    )),
+                });
+            }
+            unsafe { factory.write_descriptor_sets(writes) };
+            sets
+        };
+        views.push(depth_view);
+
+        let pipeline_layout = unsafe {
+            factory.device().create_pipeline_layout(
+                Some(set_layout.raw()),
+                Some((hal::pso::ShaderStageFlags::COMPUTE, 0..4)),
+            )
+        }
+        .map_err(NodeBuildError::OutOfMemory)?;
+
+        let module = unsafe { COMPUTE.module(factory) }.unwrap();
+        let pipeline = unsafe {
+            factory.device().create_compute_pipeline(
+                &hal::pso::ComputePipelineDesc {
+                    shader: hal::pso::EntryPoint {
+                        entry: "main",
+                        module: &module,
+                        specialization: hal::pso::Specialization::default(),
+                    },
+                    layout: &pipeline_layout,
+                    flags: hal::pso::PipelineCreationFlags::empty(),
+                    parent: hal::pso::BasePipeline::None,
+                },
+                None,
+            )
+        };
+        unsafe { factory.destroy_shader_module(module) };
+        let pipeline = pipeline.map_err(NodeBuildError::Pipeline)?;
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Compute>()
+            .expect("Graph builder must provide family with Compute capability");
+
+        let initial = command_pool.allocate_buffers(1).pop().unwrap();
+        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+        let mut encoder = recording.encoder();
+        {
+            let (stages, barriers) = gfx_acquire_barriers(ctx, None, &images);
+            encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+        }
+        unsafe {
+            encoder.bind_compute_pipeline(&pipeline);
+            for level in 0..levels {
+                if level > 0 {
+                    // Wait for the previous level to be written.
+                    encoder.pipeline_barrier(
+                        hal::pso::PipelineStage::COMPUTE_SHADER
+                            ..hal::pso::PipelineStage::COMPUTE_SHADER,
+                        hal::memory::Dependencies::empty(),
+                        Some(hal::memory::Barrier::Image {
+                            states: (
+                                hal::image::Access::SHADER_WRITE,
+                                hal::image::Layout::General,
+                            )
+                                ..(hal::image::Access::SHADER_READ, hal::image::Layout::General),
+                            target: pyramid.raw(),
+                            families: None,
+                            range: hal::image::SubresourceRange {
+                                aspects: hal::format::Aspects::COLOR,
+                                levels: level - 1..level,
+                                layers: 0..1,
+                            },
+                        }),
+                    );
+                }
+                encoder.bind_compute_descriptor_sets(
+                    &pipeline_layout,
+                    0,
+                    Some(sets[level as usize].raw()),
+                    std::iter::empty(),
+                );
+                encoder.push_constants(
+                    &pipeline_layout,
+                    hal::pso::ShaderStageFlags::COMPUTE,
+                    0,
+                    &[(level == 0) as u32],
+                );
+                let width = (extent.width >> level).max(1);
+                let height = (extent.height >> level).max(1);
+                encoder.dispatch(
+                    (width + GROUP_SIZE - 1) / GROUP_SIZE,
+                    (height + GROUP_SIZE - 1) / GROUP_SIZE,
+                    1,
+                );
+            }
+        }
+        {
+            let (stages, barriers) = gfx_release_barriers(ctx, None, &images);
+            encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+        }
+        let (submit, command_buffer) = recording.finish().submit();
+
+        Ok(HiZNode {
+            views,
+            sets,
+            pipeline_layout,
+            pipeline,
+            command_pool,
+            command_buffer,
+            submit,
+        })
+    }
+}
+
+impl<B> Node<B, Scene> for HiZNode<B>
+where
+    B: hal::Backend,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &Factory<B>,
+        queue: &mut Queue<B>,
+        _aux: &Scene,
+        _frames: &Frames<B>,
+        waits: &[(&'a B::Semaphore, hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        unsafe {
+            queue.submit(
+                Some(
+                    Submission::new()
+                        .submits(Some(&self.submit))
+                        .wait(waits.iter().cloned())
+                        .signal(signals.iter()),
+                ),
+                fence,
+            );
+        }
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &Scene) {
+        info!("Disposing HiZ Node.");
+        drop(self.submit);
+        drop(self.sets);
+        drop(self.views);
+        self.command_pool
+            .free_buffers(Some(self.command_buffer.mark_complete()));
+        factory.destroy_command_pool(self.command_pool);
+        factory.device().destroy_compute_pipeline(self.pipeline);
+        factory
+            .device()
+            .destroy_pipeline_layout(self.pipeline_layout);
+    }
+}
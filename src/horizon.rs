@@ -0,0 +1,375 @@
+//! Distant terrain drawn beyond the view distance.
+//!
+//! The horizon is a polar heightmap mesh around the camera, starting where
+//! the loaded chunks end. Its cells grow with the distance, so a few thousand
+//! vertices cover several kilometres. Heights come from a sample function,
+//! usually the one the world generator uses, as the chunks out there are not
+//! loaded.
+//!
+//! The mesh is drawn on the far plane behind the scene, its rings ordered far
+//! to near so nearer hills cover farther ones without a depth buffer of their
+//! own.
+
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nalgebra::{Perspective3, Vector3};
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    Layout, PrepareResult, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{GraphContext, NodeBuffer, NodeImage};
+use rendy::hal;
+use rendy::mesh::{AsVertex, Mesh};
+use rendy::resource::{DescriptorSetLayout, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::color::{Color, OutputEncoding};
+use crate::coords::{Location, WorldPos};
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::meshing::MeshData;
+use crate::scene::Scene;
+use crate::vertex::VoxelVertex;
+
+lazy_static::lazy_static! {
+    static ref VERTEX_SOURCE: String = glsl::expand(include_str!("../horizon.vert"));
+
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        &VERTEX_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/horizon.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../horizon.frag"));
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/horizon.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+}
+
+/// Resolution and extent of the horizon mesh.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HorizonSettings {
+    /// Distance where the horizon ends, in voxels.
+    pub outer_radius: f32,
+
+    /// Vertices around each ring.
+    pub segments: u32,
+
+    /// Rings between the inner and outer radius.
+    pub rings: u32,
+
+    /// Distance the camera moves before the mesh is generated again.
+    pub rebuild_distance: f32,
+}
+
+impl Default for HorizonSettings {
+    fn default() -> Self {
+        HorizonSettings {
+            outer_radius: 8000.0,
+            segments: 128,
+            rings: 32,
+            rebuild_distance: 256.0,
+        }
+    }
+}
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Heightmap mesh of the terrain around a center column.
+#[derive(Debug, Clone)]
+pub struct HorizonMesh {
+    center: WorldPos,
+    settings: HorizonSettings,
+    mesh: MeshData,
+
+    /// Changes every time a mesh is generated, tells the pass to upload it.
+    generation: u64,
+}
+
+impl HorizonMesh {
+    /// Generate the mesh around the column of `center`, from `inner_radius`
+    /// to `settings.outer_radius`.
+    ///
+    /// `sample(x, z)` returns the surface height and color of a column.
+    /// `inner_radius` is normally the view distance.
+    pub fn generate<F>(
+        center: WorldPos,
+        inner_radius: f32,
+        settings: HorizonSettings,
+        sample: F,
+    ) -> Self
+    where
+        F: Fn(i64, i64) -> (f32, Color),
+    {
+        let center = WorldPos::new(center.x, 0, center.z);
+        let segments = settings.segments.max(3) as usize;
+        let rings = settings.rings.max(1) as usize;
+        let inner = inner_radius.max(1.0);
+        let outer = settings.outer_radius.max(inner * 1.01);
+
+        // Rings are spaced geometrically, cells stay roughly square.
+        let mut mesh = MeshData::new();
+        for ring in 0..=rings {
+            let radius = inner * (outer / inner).powf(ring as f32 / rings as f32);
+            for segment in 0..segments {
+                let angle = segment as f32 / segments as f32 * 2.0 * PI;
+                let (x, z) = (radius * angle.cos(), radius * angle.sin());
+                let (height, color) =
+                    sample(center.x + x.round() as i64, center.z + z.round() as i64);
+                mesh.push_vertex([x, height, z], [0.0, 1.0, 0.0], color, 0);
+            }
+        }
+
+        // Farthest ring first, see the module documentation.
+        let index = |ring: usize, segment: usize| (ring * segments + segment % segments) as u32;
+        for ring in (0..rings).rev() {
+            for segment in 0..segments {
+                let (a, b) = (index(ring, segment), index(ring, segment + 1));
+                let (c, d) = (index(ring + 1, segment), index(ring + 1, segment + 1));
+                mesh.indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        // Smooth normals, the sum of the adjacent face normals.
+        let mut normals = vec![Vector3::zeros(); mesh.vertex_count()];
+        for triangle in mesh.indices.chunks(3) {
+            let position = |i: u32| Vector3::from(mesh.positions[i as usize]);
+            let (a, b, c) = (
+                position(triangle[0]),
+                position(triangle[1]),
+                position(triangle[2]),
+            );
+            let normal = (b - a).cross(&(c - a));
+            for &i in triangle {
+                normals[i as usize] += normal;
+            }
+        }
+        for (dst, normal) in mesh.normals.iter_mut().zip(normals) {
+            let normal = normal.try_normalize(1.0e-6).unwrap_or_else(Vector3::y);
+            *dst = [normal.x, normal.y, normal.z];
+        }
+
+        HorizonMesh {
+            center,
+            settings,
+            mesh,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Column the mesh is centered on, at height 0.
+    pub fn center(&self) -> WorldPos {
+        self.center
+    }
+
+    pub fn settings(&self) -> &HorizonSettings {
+        &self.settings
+    }
+
+    /// Whether `eye` moved far enough from the center to generate the mesh again.
+    pub fn needs_rebuild(&self, eye: &WorldPos) -> bool {
+        let dx = (eye.x - self.center.x) as f32;
+        let dz = (eye.z - self.center.z) as f32;
+        dx * dx + dz * dz > self.settings.rebuild_distance * self.settings.rebuild_distance
+    }
+
+    pub fn mesh(&self) -> &MeshData {
+        &self.mesh
+    }
+}
+
+/// Size of the push constants, in `u32`s.
+const CONSTANTS: usize = 28;
+
+#[derive(Debug, Default)]
+pub(crate) struct HorizonDesc {
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
+
+#[derive(Debug)]
+pub(crate) struct Horizon<B: hal::Backend> {
+    mesh: Option<Mesh<B>>,
+    generation: u64,
+    constants: [u32; CONSTANTS],
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for HorizonDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Horizon<B>;
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<hal::pso::Element<hal::format::Format>>,
+        hal::pso::ElemStride,
+        hal::pso::VertexInputRate,
+    )> {
+        vec![VoxelVertex::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex)]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        // Only where the scene drew nothing, the far plane is cleared to 1.
+        Some(hal::pso::DepthStencilDesc {
+            depth: Some(hal::pso::DepthTest {
+                fun: hal::pso::Comparison::LessEqual,
+                write: false,
+            }),
+            depth_bounds: false,
+            stencil: None,
+        })
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        let constants = SpecConstants::new().with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: Vec::new(),
+            push_constants: vec![(
+                hal::pso::ShaderStageFlags::VERTEX,
+                0..(CONSTANTS * 4) as u32,
+            )],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &Scene,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        Ok(Horizon {
+            mesh: None,
+            generation: 0,
+            constants: [0; CONSTANTS],
+        })
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Horizon<B>
+where
+    B: hal::Backend,
+{
+    type Desc = HorizonDesc;
+
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        let horizon = match aux.horizon {
+            Some(ref horizon) => horizon,
+            None => return PrepareResult::DrawRecord,
+        };
+        if horizon.generation != self.generation {
+            let _scope = aux.profiler.scope("horizon.upload");
+            self.generation = horizon.generation;
+            self.mesh = if horizon.mesh.is_empty() {
+                None
+            } else {
+                Mesh::<B>::builder()
+                    .with_vertices(&VoxelVertex::from_mesh_data(&horizon.mesh)[..])
+                    .with_indices(&horizon.mesh.indices[..])
+                    .build(queue, factory)
+                    .map_err(|err| error!("Horizon upload failed: {:?}.", err))
+                    .ok()
+            };
+        }
+
+        // The scene projection ends at the view distance, the horizon
+        // extends it to its outer radius.
+        let proj = &aux.camera.proj;
+        let far = Perspective3::new(
+            proj.aspect(),
+            proj.fovy(),
+            proj.znear(),
+            horizon.settings.outer_radius.max(proj.zfar()),
+        );
+        // Positions are relative to the eye for precision, the view is
+        // only rotated.
+        let view = aux.render_view();
+        let rotation = view.rotation.inverse().to_homogeneous();
+        let view_proj = far.to_homogeneous() * rotation;
+        let center = aux.origin.to_render(&Location::from_voxel(horizon.center));
+        let offset = center - view.translation.vector;
+        let sun = aux.sun_direction();
+        let weather = aux.weather.params();
+        let floats = view_proj
+            .iter()
+            .cloned()
+            .chain(vec![
+                offset.x,
+                offset.y,
+                offset.z,
+                aux.camera.ambient_power,
+                sun.x,
+                sun.y,
+                sun.z,
+                weather.fog_density,
+            ])
+            .chain(weather.fog_color.to_array().iter().cloned());
+        for (constant, value) in self.constants.iter_mut().zip(floats) {
+            *constant = value.to_bits();
+        }
+        // Constants change every frame.
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        aux: &Scene,
+    ) {
+        let mesh = match (&aux.horizon, &self.mesh) {
+            (Some(_), Some(mesh)) => mesh,
+            _ => return,
+        };
+        unsafe {
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::VERTEX,
+                0,
+                &self.constants,
+            );
+            mesh.bind(0, &[VoxelVertex::vertex()], &mut encoder)
+                .unwrap();
+            encoder.draw_indexed(0..mesh.len(), 0, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
@@ -0,0 +1,515 @@
+//! Billboards drawn in place of distant instances.
+//!
+//! The instance mesh is captured once from `VIEWS` angles around its
+//! vertical axis into a small atlas. Visible instances whose screen size is
+//! under `ImpostorSettings::threshold` are drawn as a camera facing quad
+//! showing the captured view closest to the direction they are seen from.
+//!
+//! The atlas is tiny and sampled without filtering, it's kept in a storage
+//! buffer next to the per frame billboards rather than in an image.
+
+use nalgebra::{Matrix4, Point3, Transform3, Vector3};
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    Layout, PrepareResult, SetLayout, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{GraphContext, NodeBuffer, NodeImage};
+use rendy::hal::{self, adapter::PhysicalDevice};
+use rendy::resource::{BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::color::{Color, OutputEncoding};
+use crate::culling::{importance, Aabb};
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::mapped::MappedBuffer;
+use crate::mesh::iceil;
+use crate::scene::Scene;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../impostor.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/impostor.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../impostor.frag"));
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/impostor.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+}
+
+/// Number of captured angles around the vertical axis.
+pub const VIEWS: usize = 8;
+
+/// Width and height of one captured view, in texels.
+pub const RESOLUTION: usize = 32;
+
+/// Most billboards drawn in a frame.
+pub(crate) const MAX_IMPOSTORS: usize = 4096;
+
+/// When instances are drawn as billboards.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImpostorSettings {
+    pub enabled: bool,
+
+    /// Screen size, as given by `culling::importance`, under which an
+    /// instance is drawn as a billboard.
+    pub threshold: f32,
+}
+
+impl Default for ImpostorSettings {
+    fn default() -> Self {
+        ImpostorSettings {
+            enabled: true,
+            threshold: 0.02,
+        }
+    }
+}
+
+/// Vertex of a mesh to capture.
+#[derive(Debug, Copy, Clone)]
+pub struct CaptureVertex {
+    pub position: Point3<f32>,
+    pub color: [f32; 4],
+    pub normal: Vector3<f32>,
+}
+
+/// Views of a mesh side by side, `VIEWS` cells of `RESOLUTION` texels
+/// squared, rows from the top.
+#[derive(Debug, Clone)]
+pub struct ImpostorAtlas {
+    /// RGBA8 texels, sRGB encoded, red in the low byte. Empty texels have
+    /// zero alpha.
+    texels: Vec<u32>,
+
+    /// Sphere around the mesh each view is framed on, in model space.
+    center: Point3<f32>,
+    radius: f32,
+}
+
+impl ImpostorAtlas {
+    /// Rasterize the triangles of `indices` from every view.
+    ///
+    /// View `k` looks at the mesh from the direction at `k / VIEWS` of a
+    /// turn around +Y starting at +Z, lit from the viewer.
+    pub fn capture(vertices: &[CaptureVertex], indices: &[u32]) -> Self {
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| vertex.position))
+            .unwrap_or_else(|| Aabb::new(Point3::origin(), Point3::origin()));
+        let center = bounds.center();
+        let radius = vertices
+            .iter()
+            .map(|vertex| (vertex.position - center).norm())
+            .fold(std::f32::EPSILON, f32::max);
+        let mut atlas = ImpostorAtlas {
+            texels: vec![0; VIEWS * RESOLUTION * RESOLUTION],
+            center,
+            radius,
+        };
+        for view in 0..VIEWS {
+            atlas.capture_view(view, vertices, indices);
+        }
+        atlas
+    }
+
+    fn capture_view(&mut self, view: usize, vertices: &[CaptureVertex], indices: &[u32]) {
+        let back = view_direction(view);
+        let right = Vector3::y().cross(&back);
+        let size = RESOLUTION as f32;
+        let mut depths = vec![std::f32::NEG_INFINITY; RESOLUTION * RESOLUTION];
+
+        // Pixel coordinates and depth, larger is closer.
+        let (center, radius) = (self.center, self.radius);
+        let project = |vertex: &CaptureVertex| {
+            let offset = (vertex.position - center) / radius;
+            let x = (offset.dot(&right) + 1.0) * 0.5 * size;
+            let y = (1.0 - offset.y) * 0.5 * size;
+            (x, y, offset.dot(&back))
+        };
+        for triangle in indices.chunks_exact(3) {
+            let corners = [
+                &vertices[triangle[0] as usize],
+                &vertices[triangle[1] as usize],
+                &vertices[triangle[2] as usize],
+            ];
+            let p = [
+                project(corners[0]),
+                project(corners[1]),
+                project(corners[2]),
+            ];
+            let area = edge(p[0], p[1], p[2].0, p[2].1);
+            if area.abs() < std::f32::EPSILON {
+                continue;
+            }
+            let min_x = p.iter().map(|p| p.0).fold(size, f32::min).max(0.0) as usize;
+            let max_x = p.iter().map(|p| p.0).fold(0.0, f32::max).min(size - 1.0) as usize;
+            let min_y = p.iter().map(|p| p.1).fold(size, f32::min).max(0.0) as usize;
+            let max_y = p.iter().map(|p| p.1).fold(0.0, f32::max).min(size - 1.0) as usize;
+            for y in min_y..=max_y {
+                for x in min_x..=max_x {
+                    let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                    let w = [
+                        edge(p[1], p[2], px, py) / area,
+                        edge(p[2], p[0], px, py) / area,
+                        edge(p[0], p[1], px, py) / area,
+                    ];
+                    if w.iter().any(|&w| w < 0.0) {
+                        continue;
+                    }
+                    let depth = w[0] * p[0].2 + w[1] * p[1].2 + w[2] * p[2].2;
+                    let pixel = y * RESOLUTION + x;
+                    if depth <= depths[pixel] {
+                        continue;
+                    }
+                    depths[pixel] = depth;
+
+                    let normal = corners
+                        .iter()
+                        .zip(&w)
+                        .map(|(corner, w)| corner.normal * *w)
+                        .sum::<Vector3<f32>>()
+                        .try_normalize(std::f32::EPSILON)
+                        .unwrap_or(back);
+                    let light = 0.35 + 0.65 * normal.dot(&back).max(0.0);
+                    let mut color = [0.0; 4];
+                    for (corner, w) in corners.iter().zip(&w) {
+                        for (channel, value) in color.iter_mut().zip(&corner.color) {
+                            *channel += value * w;
+                        }
+                    }
+                    let color = Color::rgb(color[0] * light, color[1] * light, color[2] * light);
+                    self.texels[y * RESOLUTION * VIEWS + view * RESOLUTION + x] =
+                        pack(color.to_srgb());
+                }
+            }
+        }
+    }
+
+    /// sRGB encoded RGBA8 texels, rows of all the views from the top.
+    pub fn texels(&self) -> &[u32] {
+        &self.texels
+    }
+
+    /// Texel of `view` at `x`, `y` from its top left corner.
+    pub fn texel(&self, view: usize, x: usize, y: usize) -> u32 {
+        self.texels[y * RESOLUTION * VIEWS + view * RESOLUTION + x]
+    }
+
+    /// Billboard of an instance drawn with `model`, seen from `eye`.
+    pub fn billboard(&self, model: &Transform3<f32>, eye: &Point3<f32>) -> ImpostorDraw {
+        let matrix = model.matrix();
+        let center = model * self.center;
+        let scale = matrix
+            .fixed_slice::<nalgebra::U3, nalgebra::U1>(0, 0)
+            .norm();
+        let local = model
+            .try_inverse()
+            .map_or_else(Vector3::z, |inverse| inverse * eye - self.center);
+        ImpostorDraw {
+            sphere: [center.x, center.y, center.z, self.radius * scale],
+            view: [closest_view(&local), 0, 0, 0],
+        }
+    }
+}
+
+/// Direction from the mesh towards the viewer of `view`.
+pub fn view_direction(view: usize) -> Vector3<f32> {
+    let angle = view as f32 / VIEWS as f32 * std::f32::consts::PI * 2.0;
+    Vector3::new(angle.sin(), 0.0, angle.cos())
+}
+
+/// View captured closest to the direction `local`, in model space, from
+/// the mesh towards the viewer.
+pub fn closest_view(local: &Vector3<f32>) -> u32 {
+    let turn = local.x.atan2(local.z) / (std::f32::consts::PI * 2.0);
+    ((turn * VIEWS as f32).round() as i32).rem_euclid(VIEWS as i32) as u32
+}
+
+fn edge(a: (f32, f32, f32), b: (f32, f32, f32), x: f32, y: f32) -> f32 {
+    (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0)
+}
+
+fn pack(color: [f32; 4]) -> u32 {
+    color
+        .iter()
+        .enumerate()
+        .map(|(i, c)| ((c.max(0.0).min(1.0) * 255.0).round() as u32) << (i * 8))
+        .fold(0, |packed, channel| packed | channel)
+}
+
+/// One billboard, as read by `impostor.vert`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct ImpostorDraw {
+    /// Center in render space and radius.
+    pub sphere: [f32; 4],
+
+    /// Captured view in `x`.
+    pub view: [u32; 4],
+}
+
+/// Move the `visible` instances under the screen size threshold to
+/// billboards in `out`, return how many moved.
+pub(crate) fn split(
+    visible: &mut Vec<usize>,
+    aabbs: &[Aabb],
+    transforms: &[Transform3<f32>],
+    eye: &Point3<f32>,
+    settings: &ImpostorSettings,
+    atlas: &ImpostorAtlas,
+    out: &mut Vec<ImpostorDraw>,
+) -> usize {
+    if !settings.enabled {
+        return 0;
+    }
+    let before = out.len();
+    visible.retain(|&i| {
+        if importance(&aabbs[i], eye) >= settings.threshold || out.len() >= MAX_IMPOSTORS {
+            return true;
+        }
+        out.push(atlas.billboard(&transforms[i], eye));
+        false
+    });
+    out.len() - before
+}
+
+/// Size of the push constants, in `u32`s.
+const CONSTANTS: usize = 28;
+
+const ATLAS_SIZE: u64 = (VIEWS * RESOLUTION * RESOLUTION * 4) as u64;
+const DRAWS_SIZE: u64 = (MAX_IMPOSTORS * std::mem::size_of::<ImpostorDraw>()) as u64;
+
+#[derive(Debug, Default)]
+pub(crate) struct ImpostorDesc {
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
+
+pub(crate) struct Impostors<B: hal::Backend> {
+    buffer: MappedBuffer<B>,
+    align: u64,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    constants: [u32; CONSTANTS],
+    count: u32,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for Impostors<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Impostors({} drawn)", self.count)
+    }
+}
+
+fn draws_offset(index: usize, align: u64) -> u64 {
+    iceil(ATLAS_SIZE, align) + iceil(DRAWS_SIZE, align) * index as u64
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for ImpostorDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Impostors<B>;
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        Some(hal::pso::DepthStencilDesc {
+            depth: Some(hal::pso::DepthTest {
+                fun: hal::pso::Comparison::Less,
+                write: true,
+            }),
+            depth_bounds: false,
+            stencil: None,
+        })
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        let constants = SpecConstants::new().with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        let binding = |binding, stage_flags| hal::pso::DescriptorSetLayoutBinding {
+            binding,
+            ty: hal::pso::DescriptorType::StorageBuffer,
+            count: 1,
+            stage_flags,
+            immutable_samplers: false,
+        };
+        Layout {
+            sets: vec![SetLayout {
+                bindings: vec![
+                    binding(0, hal::pso::ShaderStageFlags::FRAGMENT),
+                    binding(1, hal::pso::ShaderStageFlags::VERTEX),
+                ],
+            }],
+            push_constants: vec![(
+                hal::pso::ShaderStageFlags::VERTEX | hal::pso::ShaderStageFlags::FRAGMENT,
+                0..(CONSTANTS * 4) as u32,
+            )],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        aux: &Scene,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        let frames = ctx.frames_in_flight as usize;
+        let align = factory
+            .physical()
+            .limits()
+            .min_storage_buffer_offset_alignment;
+        let mut buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: draws_offset(frames, align),
+                usage: hal::buffer::Usage::STORAGE,
+            },
+        );
+        unsafe {
+            buffer.write(factory, 0, crate::mesh::impostor_atlas().texels());
+        }
+
+        let _scope = aux.profiler.scope("impostor.descriptors");
+        let sets: Vec<_> = (0..frames)
+            .map(|_| {
+                factory
+                    .create_descriptor_set(set_layouts[0].clone())
+                    .unwrap()
+            })
+            .collect();
+        unsafe {
+            factory.write_descriptor_sets(sets.iter().enumerate().flat_map(|(index, set)| {
+                let draws = draws_offset(index, align);
+                vec![
+                    hal::pso::DescriptorSetWrite {
+                        set: set.raw(),
+                        binding: 0,
+                        array_offset: 0,
+                        descriptors: Some(hal::pso::Descriptor::Buffer(
+                            buffer.raw(),
+                            Some(0)..Some(ATLAS_SIZE),
+                        )),
+                    },
+                    hal::pso::DescriptorSetWrite {
+                        set: set.raw(),
+                        binding: 1,
+                        array_offset: 0,
+                        descriptors: Some(hal::pso::Descriptor::Buffer(
+                            buffer.raw(),
+                            Some(draws)..Some(draws + DRAWS_SIZE),
+                        )),
+                    },
+                ]
+            }));
+        }
+
+        Ok(Impostors {
+            buffer,
+            align,
+            sets,
+            constants: [0; CONSTANTS],
+            count: 0,
+        })
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Impostors<B>
+where
+    B: hal::Backend,
+{
+    type Desc = ImpostorDesc;
+
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        // Filled by the mesh pipeline, prepared first in the same subpass.
+        let draws = aux.take_impostors();
+        self.count = draws.len() as u32;
+        if !draws.is_empty() {
+            unsafe {
+                self.buffer
+                    .write(factory, draws_offset(index, self.align), &draws);
+            }
+        }
+
+        let view = aux.render_view();
+        let right = view.rotation * Vector3::x();
+        let up = view.rotation * Vector3::y();
+        let view_proj: Matrix4<f32> = aux.view_proj();
+        let floats = view_proj.iter().cloned().chain(vec![
+            right.x,
+            right.y,
+            right.z,
+            0.0,
+            up.x,
+            up.y,
+            up.z,
+            0.0,
+            aux.camera.ambient_power,
+            RESOLUTION as f32,
+            VIEWS as f32,
+            0.0,
+        ]);
+        for (constant, value) in self.constants.iter_mut().zip(floats) {
+            *constant = value.to_bits();
+        }
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        index: usize,
+        _aux: &Scene,
+    ) {
+        if self.count == 0 {
+            return;
+        }
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(
+                layout,
+                0,
+                Some(self.sets[index].raw()),
+                std::iter::empty(),
+            );
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::VERTEX | hal::pso::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.constants,
+            );
+            encoder.draw(0..6, 0..self.count);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
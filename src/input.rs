@@ -0,0 +1,424 @@
+//! Player inputs and their recording.
+//!
+//! An `InputRecording` stores input events stamped with the simulation tick
+//! they were applied on. Replayed with the same `FixedTimestep`, it gives
+//! every tick the exact inputs of the recorded session, so a bug report or
+//! a test can reproduce a run frame for frame.
+//!
+//! An `ActionMap` binds window keys to logical `Action`s, rebindable at run
+//! time, and an `InputState` tracks the actions held and the mouse motion
+//! of a frame. Its `movement` drives the camera with
+//! `Camera::apply_movement`, as gamepads or scripts can.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use rendy::init::winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+use crate::camera::CameraMovement;
+
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+/// Temporary struct representing user's inputs.
+pub struct Inputs {
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    pub front: bool,
+    pub back: bool,
+    pub mouse_x: f64,
+    pub mouse_y: f64,
+}
+
+impl Inputs {
+    /// Update the inputs with `event`, mouse motions add up until
+    /// `clear_motion`.
+    pub fn apply(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::Key { key, pressed } => *self.key_mut(key) = pressed,
+            InputEvent::MouseMotion { x, y } => {
+                self.mouse_x += x;
+                self.mouse_y += y;
+            }
+        }
+    }
+
+    /// Reset the mouse motion, call it after each tick.
+    pub fn clear_motion(&mut self) {
+        self.mouse_x = 0.0;
+        self.mouse_y = 0.0;
+    }
+
+    fn key_mut(&mut self, key: Key) -> &mut bool {
+        match key {
+            Key::Left => &mut self.left,
+            Key::Right => &mut self.right,
+            Key::Up => &mut self.up,
+            Key::Down => &mut self.down,
+            Key::Front => &mut self.front,
+            Key::Back => &mut self.back,
+        }
+    }
+}
+
+/// Movement keys of `Inputs`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Left,
+    Right,
+    Up,
+    Down,
+    Front,
+    Back,
+}
+
+impl Key {
+    pub const ALL: [Key; 6] = [
+        Key::Left,
+        Key::Right,
+        Key::Up,
+        Key::Down,
+        Key::Front,
+        Key::Back,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::Left => "left",
+            Key::Right => "right",
+            Key::Up => "up",
+            Key::Down => "down",
+            Key::Front => "front",
+            Key::Back => "back",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Key> {
+        Key::ALL.iter().cloned().find(|key| key.name() == name)
+    }
+}
+
+/// Change of the inputs, as received from the window.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputEvent {
+    Key { key: Key, pressed: bool },
+    MouseMotion { x: f64, y: f64 },
+}
+
+const HEADER: &str = "avenir-input 1";
+
+/// Input events by simulation tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputRecording {
+    /// Seconds per tick of the recorded session, replays must use the same.
+    pub tick_length: f32,
+
+    /// Events in tick order.
+    events: Vec<(u64, InputEvent)>,
+}
+
+impl InputRecording {
+    pub fn new(tick_length: f32) -> Self {
+        InputRecording {
+            tick_length,
+            events: Vec::new(),
+        }
+    }
+
+    /// Store `event`, applied on `tick`.
+    ///
+    /// Ticks must not decrease between calls, events of an earlier tick
+    /// are stored on the last recorded one.
+    pub fn record(&mut self, tick: u64, event: InputEvent) {
+        let last = self.events.last().map_or(0, |&(last, _)| last);
+        self.events.push((tick.max(last), event));
+    }
+
+    /// Inputs of each tick in order, starting from tick 0.
+    pub fn replay(&self) -> InputReplay<'_> {
+        InputReplay {
+            recording: self,
+            next: 0,
+            tick: 0,
+            inputs: Inputs::default(),
+        }
+    }
+
+    pub fn events(&self) -> &[(u64, InputEvent)] {
+        &self.events
+    }
+
+    /// Number of ticks up to the last event.
+    pub fn len_ticks(&self) -> u64 {
+        self.events.last().map_or(0, |&(tick, _)| tick + 1)
+    }
+
+    /// Write the recording in a line based text format.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{} {}", HEADER, self.tick_length)?;
+        for (tick, event) in &self.events {
+            match event {
+                InputEvent::Key { key, pressed } => {
+                    writeln!(writer, "{} key {} {}", tick, key.name(), *pressed as u8)?
+                }
+                InputEvent::MouseMotion { x, y } => writeln!(writer, "{} mouse {} {}", tick, x, y)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a recording written by `write_to`.
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Self> {
+        let invalid = |line: usize| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid input recording at line {}", line + 1),
+            )
+        };
+        let mut lines = reader.lines();
+        let header = lines.next().ok_or_else(|| invalid(0))??;
+        let tick_length = header
+            .strip_prefix(HEADER)
+            .and_then(|length| length.trim().parse().ok())
+            .ok_or_else(|| invalid(0))?;
+        let mut recording = InputRecording::new(tick_length);
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let event = match fields[..] {
+                [_, "key", key, pressed] => InputEvent::Key {
+                    key: Key::from_name(key).ok_or_else(|| invalid(i + 1))?,
+                    pressed: pressed == "1",
+                },
+                [_, "mouse", x, y] => InputEvent::MouseMotion {
+                    x: x.parse().map_err(|_| invalid(i + 1))?,
+                    y: y.parse().map_err(|_| invalid(i + 1))?,
+                },
+                [] => continue,
+                _ => return Err(invalid(i + 1)),
+            };
+            let tick = fields[0].parse().map_err(|_| invalid(i + 1))?;
+            recording.record(tick, event);
+        }
+        Ok(recording)
+    }
+}
+
+/// Iterator over the inputs of each recorded tick, see `InputRecording::replay`.
+#[derive(Debug)]
+pub struct InputReplay<'a> {
+    recording: &'a InputRecording,
+    next: usize,
+    tick: u64,
+    inputs: Inputs,
+}
+
+impl<'a> Iterator for InputReplay<'a> {
+    type Item = Inputs;
+
+    fn next(&mut self) -> Option<Inputs> {
+        if self.tick >= self.recording.len_ticks() {
+            return None;
+        }
+        self.inputs.clear_motion();
+        let events = &self.recording.events;
+        while let Some(&(tick, ref event)) = events.get(self.next) {
+            if tick != self.tick {
+                break;
+            }
+            self.inputs.apply(event);
+            self.next += 1;
+        }
+        self.tick += 1;
+        Some(self.inputs)
+    }
+}
+
+/// Logical input bound to keys by an `ActionMap`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    RotateUp,
+    RotateDown,
+    RotateLeft,
+    RotateRight,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::MoveForward,
+        Action::MoveBack,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::RotateUp,
+        Action::RotateDown,
+        Action::RotateLeft,
+        Action::RotateRight,
+    ];
+
+    /// Key of `Inputs` the action moves with, for the input recordings.
+    pub fn key(self) -> Option<Key> {
+        match self {
+            Action::MoveForward => Some(Key::Front),
+            Action::MoveBack => Some(Key::Back),
+            Action::MoveLeft => Some(Key::Left),
+            Action::MoveRight => Some(Key::Right),
+            Action::MoveUp => Some(Key::Up),
+            Action::MoveDown => Some(Key::Down),
+            _ => None,
+        }
+    }
+}
+
+/// Keys bound to the actions, a key triggers at most one action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionMap {
+    bindings: HashMap<VirtualKeyCode, Action>,
+}
+
+impl Default for ActionMap {
+    /// WASD to move, space and left shift up and down, the arrows to turn.
+    fn default() -> Self {
+        let mut map = ActionMap::empty();
+        map.bind(VirtualKeyCode::W, Action::MoveForward);
+        map.bind(VirtualKeyCode::S, Action::MoveBack);
+        map.bind(VirtualKeyCode::A, Action::MoveLeft);
+        map.bind(VirtualKeyCode::D, Action::MoveRight);
+        map.bind(VirtualKeyCode::Space, Action::MoveUp);
+        map.bind(VirtualKeyCode::LShift, Action::MoveDown);
+        map.bind(VirtualKeyCode::Up, Action::RotateUp);
+        map.bind(VirtualKeyCode::Down, Action::RotateDown);
+        map.bind(VirtualKeyCode::Left, Action::RotateLeft);
+        map.bind(VirtualKeyCode::Right, Action::RotateRight);
+        map
+    }
+}
+
+impl ActionMap {
+    /// Map without bindings.
+    pub fn empty() -> Self {
+        ActionMap {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Bind `key` to `action`, alongside the other keys of `action`.
+    /// Returns the action `key` was bound to.
+    pub fn bind(&mut self, key: VirtualKeyCode, action: Action) -> Option<Action> {
+        self.bindings.insert(key, action)
+    }
+
+    /// Bind `key` to `action` in place of the keys bound to it.
+    pub fn rebind(&mut self, action: Action, key: VirtualKeyCode) {
+        self.bindings.retain(|_, bound| *bound != action);
+        self.bind(key, action);
+    }
+
+    pub fn unbind(&mut self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings.remove(&key)
+    }
+
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+
+    /// Keys bound to `action`, sorted.
+    pub fn keys(&self, action: Action) -> Vec<VirtualKeyCode> {
+        let mut keys: Vec<_> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| *key)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Action of a key event and whether it's pressed.
+    pub fn translate(&self, input: &KeyboardInput) -> Option<(Action, bool)> {
+        let action = self.action(input.virtual_keycode?)?;
+        Some((action, input.state == ElementState::Pressed))
+    }
+}
+
+/// Keys held and mouse motion of a frame, translated by an `ActionMap`.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    /// Keys held with the action they were pressed for.
+    held: Vec<(VirtualKeyCode, Action)>,
+    pub mouse_x: f64,
+    pub mouse_y: f64,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        InputState::default()
+    }
+
+    /// Apply a key event of the window, returns its action.
+    pub fn handle_key(&mut self, map: &ActionMap, input: &KeyboardInput) -> Option<Action> {
+        let key = input.virtual_keycode?;
+        self.key(map, key, input.state == ElementState::Pressed)
+    }
+
+    /// Press or release `key`, returns its action.
+    ///
+    /// A key keeps the action it was pressed for until released, even if
+    /// rebound meanwhile. Repeated presses count once.
+    pub fn key(&mut self, map: &ActionMap, key: VirtualKeyCode, pressed: bool) -> Option<Action> {
+        if !pressed {
+            let index = self.held.iter().position(|(held, _)| *held == key)?;
+            return Some(self.held.remove(index).1);
+        }
+        let action = map.action(key)?;
+        if !self.held.iter().any(|(held, _)| *held == key) {
+            self.held.push((key, action));
+        }
+        Some(action)
+    }
+
+    /// Whether a key bound to `action` is held.
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.iter().any(|(_, held)| *held == action)
+    }
+
+    /// Add mouse motion, it adds up until `clear_motion`.
+    pub fn mouse_motion(&mut self, x: f64, y: f64) {
+        self.mouse_x += x;
+        self.mouse_y += y;
+    }
+
+    /// Reset the mouse motion, call it after each frame or tick.
+    pub fn clear_motion(&mut self) {
+        self.mouse_x = 0.0;
+        self.mouse_y = 0.0;
+    }
+
+    /// Release every key, such as when the window loses the focus.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    /// Camera movement of the held actions, opposite actions cancel out.
+    pub fn movement(&self) -> CameraMovement {
+        let axis = |positive: Action, negative: Action| {
+            self.is_held(positive) as i32 as f32 - self.is_held(negative) as i32 as f32
+        };
+        CameraMovement {
+            right: axis(Action::MoveRight, Action::MoveLeft),
+            up: axis(Action::MoveUp, Action::MoveDown),
+            forward: axis(Action::MoveForward, Action::MoveBack),
+            yaw: axis(Action::RotateRight, Action::RotateLeft),
+            pitch: axis(Action::RotateUp, Action::RotateDown),
+            mouse_x: self.mouse_x,
+            mouse_y: self.mouse_y,
+        }
+    }
+}
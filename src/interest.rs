@@ -0,0 +1,309 @@
+//! Which chunks and entities each viewer of a server should receive.
+//!
+//! The server registers the camera of every connected viewer and calls
+//! `InterestManager::update` once per tick, it returns what each viewer
+//! gained and lost since the previous update so the server sends the
+//! chunks and entity transforms in, and tells the viewers to drop the rest.
+//! Nearby things are always of interest, further ones only within the
+//! frustum of the viewer, tested with the same math as the render culling.
+//!
+//! Leaving takes `margin` more than entering, so walking back and forth over
+//! the edge doesn't send the same chunk again. Chunks stay of interest as
+//! long as they're close enough, whichever way the viewer looks, their data
+//! is large and rarely changes. Entities also leave the frustum, their
+//! transforms are steady traffic.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use nalgebra::{Isometry3, Perspective3, Point3, Translation3, UnitQuaternion, Vector3};
+
+use crate::coords::{ChunkCoord, Location, CHUNK_SIZE};
+use crate::culling::{Aabb, Frustum};
+use crate::world::World;
+
+/// Identifier of a viewer, chosen by the server.
+pub type ViewerId = u64;
+
+/// Camera of a viewer, as reported by its client.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewerCamera {
+    pub eye: Location,
+
+    /// Orientation of the camera, looking along its -Z axis.
+    pub rotation: UnitQuaternion<f32>,
+
+    pub proj: Perspective3<f32>,
+}
+
+impl ViewerCamera {
+    /// Frustum relative to the first voxel of the chunk of the eye.
+    fn frustum(&self) -> Frustum {
+        let view = Isometry3::from_parts(Translation3::from(self.eye.offset), self.rotation);
+        Frustum::from_matrix(&(self.proj.to_homogeneous() * view.inverse().to_homogeneous()))
+    }
+}
+
+/// Distances of interest, in voxels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InterestSettings {
+    /// Things further away are never of interest.
+    pub radius: f32,
+
+    /// Things closer are of interest even outside the frustum, so turning
+    /// around doesn't show missing chunks while they arrive.
+    pub near_radius: f32,
+
+    /// Extra distance before things stop being of interest.
+    pub margin: f32,
+}
+
+impl Default for InterestSettings {
+    fn default() -> Self {
+        InterestSettings {
+            radius: 256.0,
+            near_radius: 64.0,
+            margin: 16.0,
+        }
+    }
+}
+
+/// Entity as seen by the interest system.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EntityBounds {
+    pub entity: u64,
+    pub location: Location,
+
+    /// Radius of a sphere around the location containing the entity.
+    pub radius: f32,
+}
+
+/// Change of what a viewer should receive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterestEvent {
+    ChunkAdded { viewer: ViewerId, coord: ChunkCoord },
+    ChunkRemoved { viewer: ViewerId, coord: ChunkCoord },
+    EntityAdded { viewer: ViewerId, entity: u64 },
+    EntityRemoved { viewer: ViewerId, entity: u64 },
+}
+
+#[derive(Debug)]
+struct Viewer {
+    camera: ViewerCamera,
+    chunks: HashSet<ChunkCoord>,
+    entities: HashSet<u64>,
+}
+
+/// Tracks what each viewer has received.
+#[derive(Debug, Default)]
+pub struct InterestManager {
+    pub settings: InterestSettings,
+    viewers: BTreeMap<ViewerId, Viewer>,
+}
+
+impl InterestManager {
+    pub fn new(settings: InterestSettings) -> Self {
+        InterestManager {
+            settings,
+            viewers: BTreeMap::new(),
+        }
+    }
+
+    /// Start tracking a viewer, which has received nothing yet. A viewer
+    /// already tracked only has its camera replaced.
+    pub fn add_viewer(&mut self, viewer: ViewerId, camera: ViewerCamera) {
+        self.viewers
+            .entry(viewer)
+            .or_insert_with(|| Viewer {
+                camera,
+                chunks: HashSet::new(),
+                entities: HashSet::new(),
+            })
+            .camera = camera;
+    }
+
+    /// Stop tracking a viewer, such as when it disconnects. Returns whether
+    /// it was tracked.
+    pub fn remove_viewer(&mut self, viewer: ViewerId) -> bool {
+        self.viewers.remove(&viewer).is_some()
+    }
+
+    /// Move the camera of a viewer, returns whether it is tracked. Takes
+    /// effect on the next `update`.
+    pub fn move_viewer(&mut self, viewer: ViewerId, camera: ViewerCamera) -> bool {
+        match self.viewers.get_mut(&viewer) {
+            Some(state) => {
+                state.camera = camera;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of viewers tracked.
+    pub fn len(&self) -> usize {
+        self.viewers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.viewers.is_empty()
+    }
+
+    /// Whether `viewer` has received the chunk at `coord`.
+    pub fn sees_chunk(&self, viewer: ViewerId, coord: &ChunkCoord) -> bool {
+        self.viewers
+            .get(&viewer)
+            .map_or(false, |state| state.chunks.contains(coord))
+    }
+
+    pub fn sees_entity(&self, viewer: ViewerId, entity: u64) -> bool {
+        self.viewers
+            .get(&viewer)
+            .map_or(false, |state| state.entities.contains(&entity))
+    }
+
+    /// Viewers which have received the chunk at `coord`, to send its edits.
+    pub fn chunk_viewers<'a>(
+        &'a self,
+        coord: &'a ChunkCoord,
+    ) -> impl Iterator<Item = ViewerId> + 'a {
+        self.viewers
+            .iter()
+            .filter(move |(_, state)| state.chunks.contains(coord))
+            .map(|(&viewer, _)| viewer)
+    }
+
+    /// Viewers which have received `entity`, to send its transforms.
+    pub fn entity_viewers(&self, entity: u64) -> impl Iterator<Item = ViewerId> + '_ {
+        self.viewers
+            .iter()
+            .filter(move |(_, state)| state.entities.contains(&entity))
+            .map(|(&viewer, _)| viewer)
+    }
+
+    /// Update what each viewer should receive from the chunks loaded in
+    /// `world` and the `entities`.
+    ///
+    /// Events are grouped by viewer, removals first. Chunks unloaded from
+    /// the world and entities no longer listed are removed whatever the
+    /// distance.
+    pub fn update(&mut self, world: &World, entities: &[EntityBounds]) -> Vec<InterestEvent> {
+        let settings = self.settings;
+        let by_id: HashMap<_, _> = entities
+            .iter()
+            .map(|bounds| (bounds.entity, bounds))
+            .collect();
+        let mut events = Vec::new();
+        for (&viewer, state) in &mut self.viewers {
+            let eye = Point3::from(state.camera.eye.offset);
+            let origin = state.camera.eye.chunk;
+            let frustum = state.camera.frustum();
+            let chunk_distance = |coord: &ChunkCoord| {
+                let min = Point3::from(origin.offset_to(coord));
+                let aabb = Aabb::new(min, min + Vector3::repeat(CHUNK_SIZE as f32));
+                let (center, radius) = aabb.bounding_sphere();
+                ((center - eye).norm() - radius, aabb)
+            };
+
+            let mut removed: Vec<_> = state
+                .chunks
+                .iter()
+                .filter(|coord| {
+                    world.chunk(coord).is_none()
+                        || chunk_distance(coord).0 > settings.radius + settings.margin
+                })
+                .cloned()
+                .collect();
+            removed.sort();
+            for coord in &removed {
+                state.chunks.remove(coord);
+            }
+            events.extend(
+                removed
+                    .into_iter()
+                    .map(|coord| InterestEvent::ChunkRemoved { viewer, coord }),
+            );
+
+            let mut removed: Vec<_> = state
+                .entities
+                .iter()
+                .filter(|&&entity| {
+                    !by_id.get(&entity).map_or(false, |bounds| {
+                        entity_of_interest(
+                            &settings,
+                            &frustum,
+                            &origin,
+                            &eye,
+                            bounds,
+                            settings.margin,
+                        )
+                    })
+                })
+                .cloned()
+                .collect();
+            removed.sort();
+            for entity in &removed {
+                state.entities.remove(entity);
+            }
+            events.extend(
+                removed
+                    .into_iter()
+                    .map(|entity| InterestEvent::EntityRemoved { viewer, entity }),
+            );
+
+            let mut added: Vec<_> = world
+                .chunks()
+                .map(|(coord, _)| coord)
+                .filter(|coord| !state.chunks.contains(coord))
+                .filter(|coord| {
+                    let (distance, aabb) = chunk_distance(coord);
+                    distance <= settings.near_radius
+                        || (distance <= settings.radius
+                            && frustum.contains_aabb(&aabb.min, &aabb.max))
+                })
+                .cloned()
+                .collect();
+            added.sort();
+            state.chunks.extend(added.iter().cloned());
+            events.extend(
+                added
+                    .into_iter()
+                    .map(|coord| InterestEvent::ChunkAdded { viewer, coord }),
+            );
+
+            let mut added: Vec<_> = entities
+                .iter()
+                .filter(|bounds| !state.entities.contains(&bounds.entity))
+                .filter(|bounds| {
+                    entity_of_interest(&settings, &frustum, &origin, &eye, bounds, 0.0)
+                })
+                .map(|bounds| bounds.entity)
+                .collect();
+            added.sort();
+            added.dedup();
+            state.entities.extend(added.iter().cloned());
+            events.extend(
+                added
+                    .into_iter()
+                    .map(|entity| InterestEvent::EntityAdded { viewer, entity }),
+            );
+        }
+        events
+    }
+}
+
+/// Whether the entity is of interest to a viewer with its eye at `eye`
+/// from `origin`, with the distances extended by `margin`.
+fn entity_of_interest(
+    settings: &InterestSettings,
+    frustum: &Frustum,
+    origin: &ChunkCoord,
+    eye: &Point3<f32>,
+    bounds: &EntityBounds,
+    margin: f32,
+) -> bool {
+    let center = Point3::from(bounds.location.relative_to(origin));
+    let distance = (center - eye).norm() - bounds.radius;
+    distance <= settings.near_radius + margin
+        || (distance <= settings.radius + margin
+            && frustum.contains_sphere(&center, bounds.radius + margin))
+}
@@ -0,0 +1,221 @@
+//! Worker threads shared by the background work of the crate.
+//!
+//! Jobs are tagged with a `JobCategory`, each category can be limited to a
+//! number of concurrently running jobs so long IO or worldgen jobs can't
+//! starve meshing. Workers take the next job from the categories in turn,
+//! so a category with queued jobs never waits on the others for long.
+//!
+//! A job which panics only loses its own work, the panic is logged and the
+//! worker carries on.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum JobCategory {
+    Meshing,
+    WorldGen,
+    Lighting,
+    Io,
+}
+
+impl JobCategory {
+    pub const ALL: [JobCategory; 4] = [
+        JobCategory::Meshing,
+        JobCategory::WorldGen,
+        JobCategory::Lighting,
+        JobCategory::Io,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            JobCategory::Meshing => "meshing",
+            JobCategory::WorldGen => "worldgen",
+            JobCategory::Lighting => "lighting",
+            JobCategory::Io => "io",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobConfig {
+    /// Number of worker threads.
+    pub threads: usize,
+
+    /// Maximum running jobs per category, indexed like `JobCategory::ALL`,
+    /// at least one so queued jobs always run.
+    limits: [usize; 4],
+}
+
+impl JobConfig {
+    /// Maximum running jobs of `category`.
+    pub fn limit(&self, category: JobCategory) -> usize {
+        self.limits[category.index()]
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_limit(mut self, category: JobCategory, limit: usize) -> Self {
+        self.limits[category.index()] = limit.max(1);
+        self
+    }
+}
+
+impl Default for JobConfig {
+    /// One thread per core minus the main thread, two IO jobs at a time.
+    fn default() -> Self {
+        let threads = std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(2)
+            .saturating_sub(1)
+            .max(1);
+        JobConfig {
+            threads,
+            limits: [threads; 4],
+        }
+        .with_limit(JobCategory::Io, 2)
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct State {
+    queues: [VecDeque<Job>; 4],
+    running: [usize; 4],
+
+    /// Category the next free worker looks at first.
+    turn: usize,
+    shutdown: bool,
+}
+
+impl State {
+    fn pending(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum::<usize>() + self.running.iter().sum::<usize>()
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    limits: [usize; 4],
+    changed: Condvar,
+}
+
+/// Pool of worker threads running jobs by category.
+pub struct JobSystem {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl JobSystem {
+    pub fn new(config: &JobConfig) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            limits: config.limits,
+            changed: Condvar::new(),
+        });
+        let threads = (0..config.threads.max(1))
+            .map(|i| {
+                let shared = shared.clone();
+                std::thread::Builder::new()
+                    .name(format!("avenir-worker-{}", i))
+                    .spawn(move || worker(&shared))
+                    .unwrap()
+            })
+            .collect();
+        info!("Started {} worker threads.", config.threads.max(1));
+
+        JobSystem { shared, threads }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, category: JobCategory, job: F) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.queues[category.index()].push_back(Box::new(job));
+        self.shared.changed.notify_all();
+    }
+
+    /// Queued and running jobs of `category`.
+    pub fn pending(&self, category: JobCategory) -> usize {
+        let state = self.shared.state.lock().unwrap();
+        state.queues[category.index()].len() + state.running[category.index()]
+    }
+
+    /// Block until every queued job has run.
+    pub fn wait_idle(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.pending() > 0 {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+}
+
+fn worker(shared: &Shared) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        let count = JobCategory::ALL.len();
+        let next = (0..count)
+            .map(|offset| (state.turn + offset) % count)
+            .find(|&i| !state.queues[i].is_empty() && state.running[i] < shared.limits[i]);
+        match next {
+            Some(i) => {
+                let job = state.queues[i].pop_front().unwrap();
+                state.running[i] += 1;
+                state.turn = (i + 1) % count;
+                drop(state);
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    error!(
+                        "A {} job panicked: {}",
+                        JobCategory::ALL[i].name(),
+                        panic_message(&*payload)
+                    );
+                }
+                state = shared.state.lock().unwrap();
+                state.running[i] -= 1;
+                shared.changed.notify_all();
+            }
+            None if state.shutdown => return,
+            None => state = shared.changed.wait(state).unwrap(),
+        }
+    }
+}
+
+/// Message given to `panic!`, if it's a string.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "no message"
+    }
+}
+
+impl Drop for JobSystem {
+    /// Run the remaining jobs then join the workers.
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.changed.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for JobSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "JobSystem({} threads)", self.threads.len())
+    }
+}
@@ -0,0 +1,196 @@
+//! Fixed aspect ratio rendering with letterbox and pillarbox bars.
+//!
+//! With `Scene::letterbox` set the scene is drawn in the largest centered
+//! area of the wanted aspect ratio, the bars around it are cleared by the
+//! last group of the scene pass. Groups drawn with `DynamicViewportDesc`
+//! follow the letterboxed viewport on their own.
+
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{PrepareResult, RenderGroup, RenderGroupDesc};
+use rendy::graph::{GraphContext, NodeBuffer, NodeBuildError, NodeImage};
+use rendy::hal;
+
+use crate::color::{Color, OutputEncoding};
+use crate::scene::Scene;
+
+/// Aspect ratio the scene is drawn at, and the color of the bars.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Letterbox {
+    /// Width over height.
+    pub aspect: f32,
+    pub color: Color,
+}
+
+impl Letterbox {
+    /// Anamorphic widescreen, for cutscenes.
+    pub const CINEMA: f32 = 2.39;
+
+    /// Black bars around an `aspect` wide view.
+    pub fn new(aspect: f32) -> Self {
+        Letterbox {
+            aspect,
+            color: Color::BLACK,
+        }
+    }
+}
+
+/// Largest area of `aspect` centered in `area`.
+pub fn fit(area: hal::pso::Rect, aspect: f32) -> hal::pso::Rect {
+    let (w, h) = (f32::from(area.w), f32::from(area.h));
+    if w <= 0.0 || h <= 0.0 || aspect <= 0.0 {
+        return area;
+    }
+    if w / h > aspect {
+        // Pillarbox, bars left and right.
+        let inner = (h * aspect).round() as i16;
+        hal::pso::Rect {
+            x: area.x + (area.w - inner) / 2,
+            w: inner,
+            ..area
+        }
+    } else {
+        // Letterbox, bars above and below.
+        let inner = (w / aspect).round() as i16;
+        hal::pso::Rect {
+            y: area.y + (area.h - inner) / 2,
+            h: inner,
+            ..area
+        }
+    }
+}
+
+/// Parts of `area` outside `inner`, which must be inside it.
+pub fn bars(area: hal::pso::Rect, inner: hal::pso::Rect) -> Vec<hal::pso::Rect> {
+    let bars = [
+        // Above and below, full width.
+        hal::pso::Rect {
+            h: inner.y - area.y,
+            ..area
+        },
+        hal::pso::Rect {
+            y: inner.y + inner.h,
+            h: area.y + area.h - (inner.y + inner.h),
+            ..area
+        },
+        // Left and right, between the others.
+        hal::pso::Rect {
+            x: area.x,
+            w: inner.x - area.x,
+            ..inner
+        },
+        hal::pso::Rect {
+            x: inner.x + inner.w,
+            w: area.x + area.w - (inner.x + inner.w),
+            ..inner
+        },
+    ];
+    bars.iter()
+        .filter(|bar| bar.w > 0 && bar.h > 0)
+        .cloned()
+        .collect()
+}
+
+/// `area` shrunk by `margin`, a fraction of its size on each side.
+pub fn inset(area: hal::pso::Rect, margin: f32) -> hal::pso::Rect {
+    let dx = (f32::from(area.w) * margin).round() as i16;
+    let dy = (f32::from(area.h) * margin).round() as i16;
+    hal::pso::Rect {
+        x: area.x + dx,
+        y: area.y + dy,
+        w: (area.w - 2 * dx).max(0),
+        h: (area.h - 2 * dy).max(0),
+    }
+}
+
+/// Render group clearing the letterbox bars, added last to the scene pass.
+#[derive(Debug, Default)]
+pub(crate) struct LetterboxDesc {
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
+
+#[derive(Debug)]
+pub(crate) struct LetterboxGroup {
+    framebuffer: hal::pso::Rect,
+    output: OutputEncoding,
+}
+
+impl<B: hal::Backend> RenderGroupDesc<B, Scene> for LetterboxDesc {
+    fn colors(&self) -> usize {
+        1
+    }
+
+    fn depth(&self) -> bool {
+        true
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &Scene,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        _subpass: hal::pass::Subpass<'_, B>,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, Scene>>, NodeBuildError> {
+        Ok(Box::new(LetterboxGroup {
+            framebuffer: hal::pso::Rect {
+                x: 0,
+                y: 0,
+                w: framebuffer_width as i16,
+                h: framebuffer_height as i16,
+            },
+            output: self.output,
+        }))
+    }
+}
+
+impl<B: hal::Backend> RenderGroup<B, Scene> for LetterboxGroup {
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        _aux: &Scene,
+    ) -> PrepareResult {
+        PrepareResult::DrawRecord
+    }
+
+    fn draw_inline(
+        &mut self,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        aux: &Scene,
+    ) {
+        let letterbox = match aux.letterbox {
+            Some(letterbox) => letterbox,
+            None => return,
+        };
+        let area = aux.viewport.unwrap_or(self.framebuffer);
+        let rects = bars(area, aux.viewport_in(self.framebuffer));
+        if rects.is_empty() {
+            return;
+        }
+        unsafe {
+            encoder.clear_attachments(
+                Some(hal::command::AttachmentClear::Color {
+                    index: 0,
+                    value: hal::command::ClearColor {
+                        float32: self.output.encode(letterbox.color),
+                    },
+                }),
+                rects
+                    .into_iter()
+                    .map(|rect| hal::pso::ClearRect { rect, layers: 0..1 }),
+            );
+        }
+    }
+
+    fn dispose(self: Box<Self>, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
@@ -1,22 +1,83 @@
 /// Avenir
 /// Voxel rendering crate early stage.
 
+pub mod adaptive;
+pub mod block_textures;
 pub mod camera;
-pub mod mesh;
-pub mod graph;
+pub mod camera_effects;
+pub mod chunk;
+pub mod chunk_meshes;
+pub mod clouds;
+pub mod color;
+pub mod config;
+pub mod console;
+pub mod console_overlay;
+pub mod coords;
+pub(crate) mod crossfade;
+pub mod culling;
+pub mod debug_palette;
+pub mod dual_contouring;
+pub mod events;
+pub mod explosion;
+pub mod frame_dump;
+pub(crate) mod mesh;
+pub mod pool;
+pub mod glsl;
+pub mod golden;
+pub mod gpu;
+pub mod handle;
+pub(crate) mod gpu_culling;
+pub(crate) mod graph;
+pub(crate) mod hiz;
+pub mod horizon;
+pub mod impostor;
+pub mod input;
+pub mod interest;
+pub mod jobs;
+pub mod letterbox;
+pub mod lighting;
+pub mod lod;
+pub mod material;
+pub(crate) mod mapped;
+pub mod meshing;
+pub mod nav;
+pub mod net;
+pub mod objects;
+pub(crate) mod outline;
+pub mod plugin;
+pub(crate) mod precipitation;
+pub mod prelude;
+pub mod profiler;
+pub mod raycast;
+pub mod readback;
+pub mod renderer;
+pub mod rewind;
+pub mod scene;
+pub mod sdf;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod skinning;
+pub mod smooth;
+pub mod splash;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod storage;
+pub mod streamer;
+pub mod third_person;
+pub mod timestep;
+pub mod tonemap;
+pub mod transform;
+pub(crate) mod transient;
+pub mod validation;
+pub mod vertex;
+pub mod vision;
+pub(crate) mod viewport;
+pub mod vox_loader;
+pub mod weather;
+pub mod world;
+#[cfg(feature = "openxr")]
+pub mod xr;
 
 #[macro_use]
 extern crate log;
 
-#[derive(Default, Copy, Clone, Debug)]
-/// Temporary struct representing user's inputs.
-pub struct Inputs {
-    pub left: bool,
-    pub right: bool,
-    pub up: bool,
-    pub down: bool,
-    pub front: bool,
-    pub back: bool,
-    pub mouse_x: f64,
-    pub mouse_y: f64,
-}
+pub use input::Inputs;
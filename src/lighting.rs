@@ -0,0 +1,285 @@
+//! Block light propagation inside a chunk.
+//!
+//! Emissive voxels spread light to their neighbours, losing one level per
+//! voxel, and every non air voxel blocks it. Light doesn't cross chunk
+//! borders yet.
+//!
+//! A `LightEngine` keeps the light of the world chunks and updates it around
+//! each changed voxel, which gives the same levels as lighting the chunk
+//! again at a fraction of the cost.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::chunk::{Chunk, VoxelId, AIR, VOLUME};
+use crate::coords::{ChunkCoord, WorldPos, CHUNK_SIZE};
+use crate::world::World;
+
+const SIZE: usize = CHUNK_SIZE as usize;
+
+/// Brightest light level.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Most changes in a chunk updated voxel by voxel by
+/// `LightEngine::on_blocks_changed`, past it lighting the chunk is faster.
+const INCREMENTAL_CHANGES: usize = 64;
+
+/// Light level of every voxel of a chunk.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LightMap {
+    levels: Box<[u8]>,
+}
+
+impl LightMap {
+    /// Unlit chunk.
+    pub fn new() -> Self {
+        LightMap {
+            levels: vec![0; VOLUME].into_boxed_slice(),
+        }
+    }
+
+    /// Light `chunk` from its emissive voxels.
+    ///
+    /// `emission` returns the light level a voxel type emits, 0 for most.
+    pub fn compute<F>(chunk: &Chunk, emission: F) -> Self
+    where
+        F: Fn(VoxelId) -> u8,
+    {
+        let mut light = LightMap::new();
+        let mut queue = VecDeque::new();
+        for z in 0..SIZE {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let level = emission(chunk.get_voxel(x, y, z)).min(MAX_LIGHT);
+                    if level > 0 {
+                        light.levels[index(x, y, z)] = level;
+                        queue.push_back((x, y, z));
+                    }
+                }
+            }
+        }
+        light.spread(chunk, queue);
+        light
+    }
+
+    /// Update the light after the voxel at `(x, y, z)` of `chunk` changed,
+    /// the result is the same as `compute` on the changed chunk.
+    ///
+    /// The light the voxel spread is removed first, as far as it reaches,
+    /// then spread again from the voxels lit by other sources at the edge
+    /// of the removed area and from the voxel itself.
+    pub fn update<F>(&mut self, chunk: &Chunk, x: usize, y: usize, z: usize, emission: F)
+    where
+        F: Fn(VoxelId) -> u8,
+    {
+        let mut removed = Vec::new();
+        let mut darken = VecDeque::new();
+        let mut queue = VecDeque::new();
+        let level = self.levels[index(x, y, z)];
+        if level > 0 {
+            self.levels[index(x, y, z)] = 0;
+            removed.push((x, y, z));
+            darken.push_back((x, y, z, level));
+        }
+        // A voxel lit by the removed light is darker than its source, a
+        // brighter or equal one has another source and lights the area back.
+        while let Some((x, y, z, level)) = darken.pop_front() {
+            for (nx, ny, nz) in neighbours(x, y, z) {
+                let i = index(nx, ny, nz);
+                let neighbour = self.levels[i];
+                if neighbour == 0 {
+                    continue;
+                }
+                if neighbour < level {
+                    self.levels[i] = 0;
+                    removed.push((nx, ny, nz));
+                    darken.push_back((nx, ny, nz, neighbour));
+                } else {
+                    queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+
+        // Emitters in the removed area, the changed voxel included, shine
+        // again. A voxel turned to air lets its neighbours light it.
+        removed.push((x, y, z));
+        for (x, y, z) in removed {
+            let level = emission(chunk.get_voxel(x, y, z)).min(MAX_LIGHT);
+            if level > self.levels[index(x, y, z)] {
+                self.levels[index(x, y, z)] = level;
+                queue.push_back((x, y, z));
+            }
+        }
+        if chunk.get_voxel(x, y, z) == AIR {
+            queue.extend(neighbours(x, y, z).filter(|&(x, y, z)| self.get(x, y, z) > 1));
+        }
+        self.spread(chunk, queue);
+    }
+
+    /// Spread the light of the voxels of `queue` through the air.
+    fn spread(&mut self, chunk: &Chunk, mut queue: VecDeque<(usize, usize, usize)>) {
+        // Breadth first, each voxel is set once to its final level.
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = self.levels[index(x, y, z)];
+            if level <= 1 {
+                continue;
+            }
+            for (nx, ny, nz) in neighbours(x, y, z) {
+                let i = index(nx, ny, nz);
+                if chunk.get_voxel(nx, ny, nz) == AIR && self.levels[i] < level - 1 {
+                    self.levels[i] = level - 1;
+                    queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.levels[index(x, y, z)]
+    }
+
+    /// Light level as a brightness factor in `0.0..=1.0`.
+    pub fn brightness(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.get(x, y, z) as f32 / MAX_LIGHT as f32
+    }
+}
+
+impl Default for LightMap {
+    fn default() -> Self {
+        LightMap::new()
+    }
+}
+
+impl std::fmt::Debug for LightMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let lit = self.levels.iter().filter(|&&level| level > 0).count();
+        write!(f, "LightMap({} lit voxels)", lit)
+    }
+}
+
+/// Light of the loaded chunks of a world, kept up to date voxel by voxel.
+pub struct LightEngine {
+    emission: Box<dyn Fn(VoxelId) -> u8 + Send + Sync>,
+    maps: HashMap<ChunkCoord, LightMap>,
+}
+
+impl LightEngine {
+    /// `emission` returns the light level a voxel type emits, 0 for most.
+    pub fn new<F>(emission: F) -> Self
+    where
+        F: Fn(VoxelId) -> u8 + Send + Sync + 'static,
+    {
+        LightEngine {
+            emission: Box::new(emission),
+            maps: HashMap::new(),
+        }
+    }
+
+    /// Light a chunk from scratch, such as a chunk just loaded. A chunk
+    /// missing from `world` is forgotten.
+    pub fn light_chunk(&mut self, world: &World, coord: ChunkCoord) {
+        match world.chunk(&coord) {
+            Some(chunk) => {
+                let light = LightMap::compute(chunk, &*self.emission);
+                self.maps.insert(coord, light);
+            }
+            None => {
+                self.maps.remove(&coord);
+            }
+        }
+    }
+
+    /// Update the light after the voxel at `pos` changed in `world`.
+    ///
+    /// Call it once per changed voxel, after `World::set_voxel`. Only the
+    /// area the voxel lit or now lights is visited, the levels are the same
+    /// as `light_chunk` would compute. A chunk never lit is lit whole.
+    pub fn on_block_changed(&mut self, world: &World, pos: &WorldPos) {
+        let coord = pos.chunk();
+        let chunk = match world.chunk(&coord) {
+            Some(chunk) => chunk,
+            None => {
+                self.maps.remove(&coord);
+                return;
+            }
+        };
+        match self.maps.get_mut(&coord) {
+            Some(light) => {
+                let (x, y, z) = pos.local();
+                light.update(chunk, x, y, z, &*self.emission);
+            }
+            None => self.light_chunk(world, coord),
+        }
+    }
+
+    /// Update the light after many voxels changed, such as by an explosion.
+    ///
+    /// Chunks with few changes are updated voxel by voxel, the others are
+    /// lit again whole, once.
+    pub fn on_blocks_changed<I>(&mut self, world: &World, positions: I)
+    where
+        I: IntoIterator<Item = WorldPos>,
+    {
+        let mut chunks: HashMap<ChunkCoord, Vec<WorldPos>> = HashMap::new();
+        for pos in positions {
+            chunks.entry(pos.chunk()).or_default().push(pos);
+        }
+        for (coord, positions) in chunks {
+            if positions.len() > INCREMENTAL_CHANGES || !self.maps.contains_key(&coord) {
+                self.light_chunk(world, coord);
+            } else {
+                for pos in &positions {
+                    self.on_block_changed(world, pos);
+                }
+            }
+        }
+    }
+
+    pub fn forget_chunk(&mut self, coord: &ChunkCoord) -> Option<LightMap> {
+        self.maps.remove(coord)
+    }
+
+    pub fn chunk(&self, coord: &ChunkCoord) -> Option<&LightMap> {
+        self.maps.get(coord)
+    }
+
+    /// Light level at `pos`, 0 in unlit chunks.
+    pub fn light(&self, pos: &WorldPos) -> u8 {
+        let (x, y, z) = pos.local();
+        self.maps
+            .get(&pos.chunk())
+            .map_or(0, |light| light.get(x, y, z))
+    }
+}
+
+impl std::fmt::Debug for LightEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LightEngine({} chunks)", self.maps.len())
+    }
+}
+
+const NEIGHBOURS: [(isize, isize, isize); 6] = [
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, -1, 0),
+    (0, 1, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+];
+
+/// Same layout as the chunk voxels.
+fn index(x: usize, y: usize, z: usize) -> usize {
+    x + SIZE * (y + SIZE * z)
+}
+
+/// Face neighbours of a voxel inside the chunk.
+fn neighbours(x: usize, y: usize, z: usize) -> impl Iterator<Item = (usize, usize, usize)> {
+    let (x, y, z) = (x as isize, y as isize, z as isize);
+    NEIGHBOURS
+        .iter()
+        .map(move |&(dx, dy, dz)| (x + dx, y + dy, z + dz))
+        .filter(|&(x, y, z)| {
+            let inside = |v: isize| v >= 0 && v < SIZE as isize;
+            inside(x) && inside(y) && inside(z)
+        })
+        .map(|(x, y, z)| (x as usize, y as usize, z as usize))
+}
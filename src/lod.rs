@@ -0,0 +1,148 @@
+//! Chunk levels of detail and the blending between them.
+//!
+//! Level `n` meshes a chunk from cells of `2^n` voxels, each level covering
+//! twice the distance of the previous one. Switching level at a fixed
+//! distance makes the terrain pop, so over the last `morph_band` of a level
+//! the vertices are moved towards where the next level puts them. When the
+//! chunk switches, its vertices are already in place.
+
+use std::collections::HashMap;
+
+use crate::chunk::{Chunk, VoxelId, AIR};
+use crate::coords::CHUNK_SIZE;
+use crate::meshing::MeshData;
+
+const SIZE: usize = CHUNK_SIZE as usize;
+
+/// Distances of the levels of detail.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LodSettings {
+    /// Distance where level 0 ends, in voxels.
+    pub base_distance: f32,
+
+    /// Number of levels, the last one is used up to any distance.
+    pub levels: u32,
+
+    /// Fraction of each level, at its far end, over which it morphs into
+    /// the next one.
+    pub morph_band: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            base_distance: 64.0,
+            levels: 4,
+            morph_band: 0.25,
+        }
+    }
+}
+
+/// Level of detail of a chunk and how far it is morphed into the next one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LodSelection {
+    pub level: u32,
+
+    /// From 0 at the level positions to 1 at the next level positions.
+    pub morph: f32,
+}
+
+impl LodSettings {
+    /// Level of detail of a chunk at `distance` from the camera.
+    ///
+    /// `bias` is `QualitySettings::lod_bias`, added to the level before it
+    /// is rounded down.
+    pub fn select(&self, distance: f32, bias: f32) -> LodSelection {
+        let last = self.levels.saturating_sub(1);
+        // Level 0 below the base distance, then one level per doubling.
+        let continuous = if distance < self.base_distance {
+            distance / self.base_distance
+        } else {
+            (distance / self.base_distance).log2() + 1.0
+        };
+        let continuous = (continuous + bias).max(0.0);
+        let level = (continuous.floor() as u32).min(last);
+        if level == last {
+            return LodSelection { level, morph: 0.0 };
+        }
+        let band = self.morph_band.max(std::f32::EPSILON).min(1.0);
+        let morph = ((continuous.fract() - (1.0 - band)) / band)
+            .max(0.0)
+            .min(1.0);
+        LodSelection { level, morph }
+    }
+}
+
+/// Copy of `chunk` made of cells of `2^level` voxels.
+///
+/// Each cell takes the most common voxel type of its solid voxels, or air
+/// when less than half of it is solid.
+pub fn downsample(chunk: &Chunk, level: u32) -> Chunk {
+    let step = cell_size(level);
+    if step == 1 {
+        return chunk.clone();
+    }
+    let mut out = Chunk::new();
+    let mut counts = HashMap::new();
+    for cz in (0..SIZE).step_by(step) {
+        for cy in (0..SIZE).step_by(step) {
+            for cx in (0..SIZE).step_by(step) {
+                counts.clear();
+                for z in cz..cz + step {
+                    for y in cy..cy + step {
+                        for x in cx..cx + step {
+                            let id = chunk.get_voxel(x, y, z);
+                            if id != AIR {
+                                *counts.entry(id).or_insert(0usize) += 1;
+                            }
+                        }
+                    }
+                }
+                let solid: usize = counts.values().sum();
+                if solid * 2 < step * step * step {
+                    continue;
+                }
+                // Ties go to the smallest id so the result is deterministic.
+                let id: VoxelId = counts
+                    .iter()
+                    .max_by_key(|&(&id, &count)| (count, std::cmp::Reverse(id)))
+                    .map_or(AIR, |(&id, _)| id);
+                for z in cz..cz + step {
+                    for y in cy..cy + step {
+                        for x in cx..cx + step {
+                            out.set_voxel(x, y, z, id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Positions of the `mesh` vertices at the next level, for `geomorph`.
+///
+/// `mesh` is the mesh of level `level`. The surfaces of the next level lie
+/// on its cell grid, so each vertex is moved to the nearest grid point.
+pub fn morph_targets(mesh: &MeshData, level: u32) -> Vec<[f32; 3]> {
+    let step = cell_size(level + 1) as f32;
+    let snap = |v: f32| (v / step).round() * step;
+    mesh.positions
+        .iter()
+        .map(|p| [snap(p[0]), snap(p[1]), snap(p[2])])
+        .collect()
+}
+
+/// Blend the vertex positions of `mesh` towards `targets` by `morph`.
+pub fn geomorph(mesh: &mut MeshData, original: &[[f32; 3]], targets: &[[f32; 3]], morph: f32) {
+    debug_assert_eq!(original.len(), targets.len());
+    for (out, (from, to)) in mesh.positions.iter_mut().zip(original.iter().zip(targets)) {
+        let blend = |i: usize| from[i] + (to[i] - from[i]) * morph;
+        *out = [blend(0), blend(1), blend(2)];
+    }
+}
+
+/// Edge of the cells of `level`, in voxels, at most the chunk size.
+pub fn cell_size(level: u32) -> usize {
+    1usize.checked_shl(level).unwrap_or(SIZE).min(SIZE)
+}
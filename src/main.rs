@@ -41,14 +41,24 @@ use rendy::{
 #[macro_use]
 extern crate log;
 
-use camera::Camera;
+use avenir::{
+    adaptive::AdaptiveQuality,
+    camera::{Camera, CameraMovement},
+    config::RendererConfig,
+    console::{CommandContext, Console},
+    coords::Location,
+    input::{Action, ActionMap, InputEvent, InputRecording, Key},
+    plugin::PluginHost,
+    renderer::RendererBuilder,
+    rewind::{CameraSnapshot, RewindBuffer},
+    scene::Scene,
+    timestep::FixedTimestep,
+    transform::Transform,
+    Inputs,
+};
 use env_logger;
 use nalgebra::{Point3, Vector3};
 
-pub mod camera;
-pub mod graph;
-pub mod mesh;
-
 #[cfg(feature = "metal")]
 type Backend = rendy::metal::Backend;
 
@@ -61,50 +71,127 @@ type Backend = rendy::dx12::Backend;
 #[cfg(feature = "empty")]
 type Backend = rendy::empty::Backend;
 
+#[cfg(feature = "gl")]
+type Backend = rendy::gl::Backend;
+
 const WIDTH: u32 = 3840;
 const HEIGHT: u32 = 2160;
 
-#[derive(Default, Copy, Clone, Debug)]
-pub struct Inputs {
-    left: bool,
-    right: bool,
-    up: bool,
-    down: bool,
-    front: bool,
-    back: bool,
-    mouse_x: f64,
-    mouse_y: f64,
+/// Seconds per simulation tick.
+const TICK_LENGTH: f32 = 1.0 / 60.0;
+
+/// Seconds F9 rewinds the camera by.
+const REWIND_SECONDS: f32 = 5.0;
+
+/// Apply a live input event, stored in `recording` if there is one.
+fn input(
+    event: InputEvent,
+    inputs: &mut Inputs,
+    recording: &mut Option<InputRecording>,
+    tick: u64,
+) {
+    inputs.apply(&event);
+    if let Some(ref mut recording) = recording {
+        recording.record(tick, event);
+    }
+}
+
+/// `--record <path>` saves the inputs on exit, `--replay <path>` drives
+/// the camera with saved inputs instead of the live ones, `--target-fps
+/// <fps>` adjusts the quality to hold that frame rate.
+fn parse_args() -> (Option<String>, Option<InputRecording>, Option<f32>) {
+    let mut record = None;
+    let mut replay = None;
+    let mut target_fps = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--record", Some(path)) => record = Some(path),
+            ("--replay", Some(path)) => {
+                let recording = std::fs::File::open(&path)
+                    .and_then(|file| InputRecording::read_from(std::io::BufReader::new(file)));
+                match recording {
+                    Ok(recording) => replay = Some(recording),
+                    Err(err) => error!("Failed to load the replay {}: {}.", path, err),
+                }
+            }
+            ("--target-fps", Some(fps)) => match fps.parse::<f32>() {
+                Ok(fps) if fps > 0.0 => target_fps = Some(fps),
+                _ => error!("Invalid target frame rate {}.", fps),
+            },
+            (_, value) => warn!("Ignored argument `{}` {:?}.", arg, value),
+        }
+    }
+    (record, replay, target_fps)
 }
 
 fn run<B: hal::Backend>(
     event_loop: EventLoop<()>,
-    mut factory: Factory<B>,
-    mut families: Families<B>,
+    factory: Factory<B>,
+    families: Families<B>,
     surface: Surface<B>,
     window: Window,
+    config: RendererConfig,
 ) {
     let mut frame = 0u64;
-    let mut cam = Camera::look_at(
+    let mut scene = Scene::new(Camera::look_at(
         10.0,
         Point3::new(0.0, 0.0, -10.0),
         Point3::new(0.0, 0.0, 0.0),
         WIDTH as f32 / HEIGHT as f32,
-    );
+    ));
+    scene.add_instance(Location::default(), Transform::identity());
+    let mut console = Console::new();
+    let mut plugins = PluginHost::<B>::load(Vec::new()).unwrap();
+    plugins.install_commands(&mut console);
     let mut inputs: Inputs = Inputs::default();
-    let mut graph =
-        Some(graph::build(&mut families, &window, &mut factory, surface, &cam).unwrap());
+    let actions = ActionMap::default();
+    let (record_path, replay, target_fps) = parse_args();
+    let mut adaptive = target_fps.map(AdaptiveQuality::with_frame_rate);
+    let tick_length = replay
+        .as_ref()
+        .map_or(TICK_LENGTH, |replay| replay.tick_length);
+    let mut recording = record_path
+        .as_ref()
+        .map(|_| InputRecording::new(tick_length));
+    let mut replay = replay.map(|replay| replay.replay().collect::<Vec<_>>().into_iter());
+    let mut timestep = FixedTimestep::new(tick_length);
+    let mut rewind = RewindBuffer::new(REWIND_SECONDS, tick_length);
+    let mut renderer = RendererBuilder::new()
+        .with_scene(scene)
+        .with_config(config)
+        .build(
+            factory,
+            families,
+            surface,
+            &window,
+            plugins.registry.render_passes(),
+        )
+        .unwrap_or_else(|err| {
+            error!("{}", err);
+            std::process::exit(1)
+        });
+    for (name, timing) in renderer.scene.profiler.timings() {
+        info!("{}: {:?}.", name, timing.last);
+    }
 
     let started = std::time::Instant::now();
     let mut checkpoint = started;
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
+        *control_flow = renderer.control_flow();
+        if let Err(err) = renderer.handle_event(&event, &window, plugins.registry.render_passes()) {
+            error!("{}", err);
+            *control_flow = ControlFlow::Exit;
+        }
         match event {
             Event::DeviceEvent { ref event, .. } => match *event {
-                DeviceEvent::MouseMotion { delta: (x, y) } => {
-                    inputs.mouse_x = x;
-                    inputs.mouse_y = y;
-                }
+                DeviceEvent::MouseMotion { delta: (x, y) } if !console.open => input(
+                    InputEvent::MouseMotion { x, y },
+                    &mut inputs,
+                    &mut recording,
+                    timestep.tick(),
+                ),
                 _ => {}
             },
             Event::WindowEvent { event, .. } => match event {
@@ -112,6 +199,9 @@ fn run<B: hal::Backend>(
                 WindowEvent::Resized(size) => {
                     info!("Window Resized {:?}.", size);
                 }
+                WindowEvent::ReceivedCharacter(c) if console.open && c != '`' => {
+                    console.type_char(c)
+                }
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
@@ -121,47 +211,129 @@ fn run<B: hal::Backend>(
                         },
                     ..
                 } => match (virtual_code, state) {
-                    (VirtualKeyCode::A, ElementState::Pressed) => inputs.left = true,
-                    (VirtualKeyCode::A, ElementState::Released) => inputs.left = false,
-                    (VirtualKeyCode::S, ElementState::Pressed) => inputs.back = true,
-                    (VirtualKeyCode::S, ElementState::Released) => inputs.back = false,
-                    (VirtualKeyCode::D, ElementState::Pressed) => inputs.right = true,
-                    (VirtualKeyCode::D, ElementState::Released) => inputs.right = false,
-                    (VirtualKeyCode::W, ElementState::Pressed) => inputs.front = true,
-                    (VirtualKeyCode::W, ElementState::Released) => inputs.front = false,
-                    _ => {}
+                    (VirtualKeyCode::Grave, ElementState::Pressed) => {
+                        console.toggle();
+                        for &key in &Key::ALL {
+                            let event = InputEvent::Key {
+                                key,
+                                pressed: false,
+                            };
+                            input(event, &mut inputs, &mut recording, timestep.tick());
+                        }
+                    }
+                    (_, ElementState::Pressed) if console.open => match virtual_code {
+                        VirtualKeyCode::Back => console.backspace(),
+                        VirtualKeyCode::Up => console.history_prev(),
+                        VirtualKeyCode::Down => console.history_next(),
+                        VirtualKeyCode::Return => console.submit(&mut CommandContext {
+                            scene: &mut renderer.scene,
+                            config: &mut renderer.config,
+                        }),
+                        _ => {}
+                    },
+                    _ if console.open => {}
+                    (VirtualKeyCode::F9, ElementState::Pressed) => {
+                        let scene = &mut renderer.scene;
+                        if let Some(rewound) = rewind.rewind_seconds(REWIND_SECONDS) {
+                            if let Some(camera) = rewound.camera {
+                                match camera.restore(&mut scene.camera, &scene.origin) {
+                                    Ok(()) => {
+                                        scene.update_origin();
+                                        scene.begin_tick();
+                                    }
+                                    Err(err) => warn!("Failed to rewind the camera: {}.", err),
+                                }
+                            }
+                            info!("Rewound to tick {}.", rewound.tick);
+                        }
+                    }
+                    (code, state) => {
+                        if let Some(key) = actions.action(code).and_then(Action::key) {
+                            let event = InputEvent::Key {
+                                key,
+                                pressed: state == ElementState::Pressed,
+                            };
+                            input(event, &mut inputs, &mut recording, timestep.tick());
+                        }
+                    }
                 },
                 _ => {}
             },
             Event::MainEventsCleared => {
-                factory.maintain(&mut families);
-                if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &cam);
-                    frame += 1;
-                }
+                frame += 1;
                 let elapsed = checkpoint.elapsed();
                 // Print fps
                 // let elapsed_ns = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
                 // info!("FPS: {} delta: {}", frame * 1_000_000_000 / elapsed_ns, elapsed.as_secs_f32());
                 frame = 0;
                 checkpoint += elapsed;
-                cam.run(&inputs, elapsed.as_secs_f32());
-                inputs.mouse_x = 0.0;
-                inputs.mouse_y = 0.0;
+                renderer.scene.time = started.elapsed().as_secs_f32();
+                renderer.scene.camera_effects.update(elapsed.as_secs_f32());
+                let ticks = timestep.advance(elapsed.as_secs_f32());
+                let first = timestep.tick() - ticks as u64;
+                for tick in first..first + ticks as u64 {
+                    // Replays ignore the live inputs.
+                    let tick_inputs = match replay {
+                        Some(ref mut replay) => match replay.next() {
+                            Some(tick_inputs) => tick_inputs,
+                            None => {
+                                info!("Replay finished.");
+                                *control_flow = ControlFlow::Exit;
+                                break;
+                            }
+                        },
+                        None => inputs,
+                    };
+                    renderer.scene.begin_tick();
+                    let camera = CameraSnapshot::of(&renderer.scene.camera, &renderer.scene.origin);
+                    rewind.record_camera(tick, camera);
+                    renderer.scene.weather.update(timestep.tick_length());
+                    renderer.scene.camera.apply_movement(
+                        &CameraMovement::from(&tick_inputs),
+                        timestep.tick_length(),
+                    );
+                    renderer.scene.update_origin();
+                    inputs.clear_motion();
+                }
+                renderer.scene.interpolation = timestep.alpha();
+                let overlay = console.overlay();
+                if overlay != renderer.scene.console {
+                    renderer.scene.console = overlay;
+                    renderer.request_redraw();
+                }
+                let drawn = renderer.render_if_needed();
+                let mut quality = renderer.config.quality;
+                let change = match adaptive {
+                    Some(ref mut adaptive) if drawn => adaptive.update(elapsed, &mut quality),
+                    _ => None,
+                };
+                if let Some(change) = change {
+                    info!("Adaptive quality: {}.", change);
+                    let mut config = renderer.config.clone();
+                    config.set_quality(quality);
+                    let passes = plugins.registry.render_passes();
+                    if let Err(err) = renderer.set_config(config, &window, passes) {
+                        error!("{}", err);
+                    }
+                }
             }
             Event::RedrawRequested(_) => {
-                if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &cam);
-                    frame += 1;
-                }
+                renderer.render();
+                frame += 1;
 
                 info!("Request redraw.");
             }
             _ => {}
         }
         if *control_flow == ControlFlow::Exit {
-            if let Some(graph) = graph.take() {
-                graph.dispose(&mut factory, &cam);
+            renderer.shutdown();
+            if let (Some(recording), Some(path)) = (recording.take(), record_path.as_ref()) {
+                let saved = std::fs::File::create(path)
+                    .and_then(|file| recording.write_to(std::io::BufWriter::new(file)));
+                match saved {
+                    Ok(()) => info!("Inputs recorded to {}.", path),
+                    Err(err) => error!("Failed to save the inputs to {}: {}.", path, err),
+                }
             }
         }
     });
@@ -172,6 +344,8 @@ fn main() {
     info!("Starting Avenir");
 
     let config: Config = Default::default();
+    let renderer_config = RendererConfig::default().gpu_validation(cfg!(debug_assertions));
+    let validation = renderer_config.enable_gpu_validation();
     let event_loop = EventLoop::new();
 
     info!("Creating Window of {} by {} pixels.", WIDTH, HEIGHT);
@@ -180,10 +354,12 @@ fn main() {
         .with_title("Avenir");
 
     let rendy = AnyWindowedRendy::init_auto(&config, window, &event_loop).unwrap();
+    // Forwards the validation messages for as long as the event loop runs.
+    let _validation_log = validation.and_then(|layer| layer.finish());
     rendy::with_any_windowed_rendy!((rendy)
     use back;
     (factory, families, surface, window) => {
         window.set_cursor_grab(true);
-        run(event_loop, factory, families, surface, window)
+        run(event_loop, factory, families, surface, window, renderer_config)
     });
 }
@@ -0,0 +1,90 @@
+//! Persistently mapped buffers for data written every frame.
+
+use std::ptr::NonNull;
+
+use rendy::factory::Factory;
+use rendy::hal;
+use rendy::memory::{Block, Dynamic};
+use rendy::resource::{Buffer, BufferInfo, Escape};
+
+/// Host visible buffer mapped once for its whole lifetime.
+///
+/// Writes go straight to the mapping when the memory is coherent, otherwise
+/// they fall back to `Factory::upload_visible_buffer` which maps and flushes
+/// on each call.
+///
+/// The buffer is usually split in one region per frame in flight, the graph
+/// waits for the fence of a frame before reusing its index, so writing the
+/// region of the frame being prepared is safe.
+pub struct MappedBuffer<B: hal::Backend> {
+    buffer: Escape<Buffer<B>>,
+    ptr: Option<NonNull<u8>>,
+}
+
+// The mapping is owned by the buffer and only written through `&mut self`.
+unsafe impl<B: hal::Backend> Send for MappedBuffer<B> {}
+unsafe impl<B: hal::Backend> Sync for MappedBuffer<B> {}
+
+impl<B: hal::Backend> MappedBuffer<B> {
+    pub fn new(factory: &Factory<B>, info: BufferInfo) -> Self {
+        let size = info.size;
+        let mut buffer = factory.create_buffer(info, Dynamic).unwrap();
+
+        let coherent = buffer
+            .block()
+            .properties()
+            .contains(hal::memory::Properties::COHERENT);
+        let ptr = if coherent {
+            buffer
+                .map(factory.device(), 0..size)
+                .ok()
+                .map(|mapped| mapped.ptr())
+        } else {
+            None
+        };
+        if ptr.is_none() {
+            debug!("Buffer memory is not coherent, falling back to uploads.");
+        }
+
+        MappedBuffer { buffer, ptr }
+    }
+
+    pub fn raw(&self) -> &B::Buffer {
+        self.buffer.raw()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    /// Copy `data` at `offset` in the buffer.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must not be reading the written range anymore.
+    pub unsafe fn write<T: Copy>(&mut self, factory: &Factory<B>, offset: u64, data: &[T]) {
+        let len = std::mem::size_of_val(data);
+        assert!(offset + len as u64 <= self.size());
+        match self.ptr {
+            Some(ptr) => std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                ptr.as_ptr().add(offset as usize),
+                len,
+            ),
+            None => factory
+                .upload_visible_buffer(&mut self.buffer, offset, data)
+                .unwrap(),
+        }
+    }
+}
+
+impl<B: hal::Backend> std::fmt::Debug for MappedBuffer<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MappedBuffer({}, mapped: {})",
+            self.size(),
+            self.ptr.is_some()
+        )
+    }
+}
@@ -0,0 +1,179 @@
+//! Textures and materials registered in the scene.
+//!
+//! Both live in `HandleMap`s of the scene and refer to each other through
+//! handles, never through backend resources. The voxel pipeline doesn't
+//! sample them yet, they describe surfaces for the passes that will.
+//! Per-object `MaterialOverride`s are drawn, uploaded with the models.
+
+use crate::color::Color;
+use crate::handle::TextureHandle;
+
+/// Image in host memory.
+#[derive(Clone)]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+
+    /// One sRGB encoded RGBA texel per `u32`, red in the low byte, rows
+    /// tightly packed from the top.
+    pub texels: Vec<u32>,
+}
+
+impl Texture {
+    /// Texture from RGBA bytes, `None` if their length doesn't match the size.
+    pub fn from_rgba8(width: u32, height: u32, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != width as usize * height as usize * 4 {
+            return None;
+        }
+        let texels = bytes
+            .chunks_exact(4)
+            .map(|texel| u32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+            .collect();
+        Some(Texture {
+            width,
+            height,
+            texels,
+        })
+    }
+}
+
+impl std::fmt::Debug for Texture {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Texture({}x{})", self.width, self.height)
+    }
+}
+
+/// How lit surfaces are shaded, a variant of the fragment shader chosen
+/// when the pipelines are built.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadingModel {
+    /// Diffuse and ambient light with a specular highlight shaped by the
+    /// roughness.
+    Lit,
+
+    /// Diffuse and ambient light only.
+    Lambert,
+
+    /// Colors as they are, ignoring the lights. Fog still applies.
+    Unlit,
+
+    /// Diffuse light snapped to `bands` flat steps, for a cartoon look.
+    Toon { bands: u32 },
+}
+
+impl ShadingModel {
+    /// Id of the variant in the shaders.
+    pub(crate) fn id(self) -> u32 {
+        match self {
+            ShadingModel::Lit => 0,
+            ShadingModel::Lambert => 1,
+            ShadingModel::Unlit => 2,
+            ShadingModel::Toon { .. } => 3,
+        }
+    }
+
+    /// Steps of the toon ramp, `0` for the other models.
+    pub(crate) fn bands(self) -> u32 {
+        match self {
+            ShadingModel::Toon { bands } => bands,
+            _ => 0,
+        }
+    }
+}
+
+impl Default for ShadingModel {
+    fn default() -> Self {
+        ShadingModel::Lit
+    }
+}
+
+/// Line drawn around the silhouette of an object.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Outline {
+    pub color: Color,
+
+    /// Thickness of the line, in world units.
+    pub width: f32,
+}
+
+impl Outline {
+    pub fn new(color: Color, width: f32) -> Self {
+        Outline { color, width }
+    }
+}
+
+/// Surface parameters shared by the objects using them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    /// Multiplies the vertex colors and the texture.
+    pub base_color: Color,
+
+    /// Light emitted whatever the lighting.
+    pub emissive: Color,
+
+    /// From mirror-like at `0.0` to fully diffuse at `1.0`.
+    pub roughness: f32,
+
+    pub texture: Option<TextureHandle>,
+
+    /// Shading of the surfaces, `None` for `RendererConfig::shading`.
+    pub shading: Option<ShadingModel>,
+
+    pub outline: Option<Outline>,
+}
+
+/// Changes to the material of a single object, so placed models can vary
+/// without registering a material each.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MaterialOverride {
+    /// Multiplies the color of the object.
+    pub tint: Color,
+
+    /// Added to the lit color, glows through the shading.
+    pub emissive: Color,
+
+    /// Multiplies the roughness, shaping the highlight of the
+    /// `ShadingModel::Lit` surfaces.
+    pub roughness: f32,
+
+    /// Drawn by the outline pass, when the post effects are on.
+    pub outline: Option<Outline>,
+}
+
+impl MaterialOverride {
+    pub fn tinted(tint: Color) -> Self {
+        MaterialOverride {
+            tint,
+            ..MaterialOverride::default()
+        }
+    }
+
+    pub fn with_outline(mut self, outline: Outline) -> Self {
+        self.outline = Some(outline);
+        self
+    }
+}
+
+impl Default for MaterialOverride {
+    fn default() -> Self {
+        MaterialOverride {
+            tint: Color::WHITE,
+            emissive: Color::BLACK,
+            roughness: 1.0,
+            outline: None,
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            base_color: Color::WHITE,
+            emissive: Color::BLACK,
+            roughness: 1.0,
+            texture: None,
+            shading: None,
+            outline: None,
+        }
+    }
+}
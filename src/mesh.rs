@@ -1,8 +1,8 @@
-use genmesh::generators::{IndexedPolygon, SharedVertex};
 use nalgebra::{Matrix3, Matrix4, Perspective3, Point3, Projective3, Translation3, Vector3};
 use rendy::command::{DrawIndexedCommand, QueueId, RenderPassEncoder};
 use rendy::factory::Factory;
 use rendy::graph::render::*;
+use rendy::graph::BufferAccess;
 use rendy::graph::{
     render::{Layout, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc},
     GraphContext, NodeBuffer, NodeImage,
@@ -10,19 +10,43 @@ use rendy::graph::{
 use rendy::hal;
 use rendy::hal::{adapter::PhysicalDevice, device::Device};
 
-use crate::camera::Camera;
+use crate::block_textures::{self, BlockTextureBuffer};
+use crate::chunk_meshes::{chunk_draws, ChunkDraw, MAX_CHUNK_MESHES};
+use crate::color::OutputEncoding;
+use crate::config::ShaderTuning;
+use crate::coords::{ChunkCoord, Location, CHUNK_SIZE};
+use crate::culling::{apply_budget, cull_distance, Aabb, CullingStats, Frustum};
+use crate::glsl;
+use crate::gpu::layout::{self, LayoutDesc};
+use crate::gpu::specialization::SpecConstants;
+use crate::gpu_culling::OUTPUT_MODELS_OFFSET;
+use crate::handle::ObjectHandle;
+use crate::impostor::{self, CaptureVertex, ImpostorAtlas};
+use crate::mapped::MappedBuffer;
+use crate::material::ShadingModel;
+use crate::meshing::MeshData;
+use crate::objects::{ObjectDraw, ObjectUploadKey, MAX_OBJECT_INSTANCES, MAX_SCENE_OBJECTS};
+use crate::scene::Scene;
+use crate::vertex::{AnimFlags, InstanceData, VoxelVertex};
+use crate::vision::{self, VisionBuffer};
 use generic_octree::{render, Octree};
 use rand::Rng;
-use rendy::mesh::{AsVertex, Mesh, Model, PosColorNorm};
+use rendy::mesh::{AsVertex, Mesh};
 use rendy::resource::{Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle};
 use rendy::shader::{
-    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo,
+    SpecConstantSet, SpirvShader,
 };
+use std::collections::HashMap;
 use std::mem::size_of;
 
 lazy_static::lazy_static! {
+    static ref VERTEX_SOURCE: String = glsl::expand(include_str!("../shader.vert"));
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../shader.frag"));
+
     static ref VERTEX: SpirvShader = SourceShaderInfo::new(
-        include_str!("../shader.vert"),
+        &VERTEX_SOURCE,
         concat!(env!("CARGO_MANIFEST_DIR"), "/shader.vert").into(),
         ShaderKind::Vertex,
         SourceLanguage::GLSL,
@@ -30,7 +54,7 @@ lazy_static::lazy_static! {
     ).precompile().unwrap();
 
     static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
-            include_str!("../shader.frag"),
+            &FRAGMENT_SOURCE,
             concat!(env!("CARGO_MANIFEST_DIR"), "/shader.frag").into(),
             ShaderKind::Fragment,
             SourceLanguage::GLSL,
@@ -45,25 +69,29 @@ lazy_static::lazy_static! {
 
     static ref OCTREE_MODEL: render::Model = render::Model::from(&*OCTREE_TREE);
 
-    static ref CUBE: genmesh::generators::Cone = genmesh::generators::Cone::new(30);
-
-    static ref CUBE_INDICES: Vec<u32> = genmesh::Vertices::vertices(CUBE.indexed_polygon_iter())
-        .map(|i| i as u32)
-        .collect();
+    static ref IMPOSTOR_ATLAS: ImpostorAtlas = {
+        let vertices: Vec<_> = OCTREE_MODEL
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let [x, y, z] = vertex.position.0;
+                let [nx, ny, nz] = vertex.normal.0;
+                CaptureVertex {
+                    position: Point3::new(x, y, z) * MESH_SCALE,
+                    color: vertex.color.0,
+                    normal: Vector3::new(nx, ny, nz),
+                }
+            })
+            .collect();
+        ImpostorAtlas::capture(&vertices, &OCTREE_MODEL.indices)
+    };
+}
 
-    static ref CUBE_VERTICES: Vec<PosColorNorm> = CUBE.shared_vertex_iter()
-                .map(|v| PosColorNorm {
-                    position: v.pos.into(),
-                    color: [
-                        (v.pos.x + 1.0) / 2.0,
-                        (v.pos.y + 1.0) / 2.0,
-                        (v.pos.z + 1.0) / 2.0,
-                        1.0,
-                    ]
-                    .into(),
-                    normal: v.normal.into(),
-                })
-                .collect();
+/// Compile the shaders, load the octree model and capture its impostors
+/// ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+    lazy_static::initialize(&IMPOSTOR_ATLAS);
 }
 
 #[derive(Clone, Copy)]
@@ -72,30 +100,150 @@ pub struct UniformArgs {
     pub proj: Matrix4<f32>,
     pub view: Matrix4<f32>,
     pub ambient_power: f32,
+
+    /// Seconds driving the vertex animations.
+    pub time: f32,
+
+    /// Darkening of wet surfaces in `0.0..=1.0`.
+    pub wetness: f32,
+
+    pub fog_density: f32,
+    pub fog_color: [f32; 4],
+
+    /// Direction towards the sun, `w` unused.
+    pub sun_direction: [f32; 4],
+
+    /// See `vision::uniform`.
+    pub vision_area: [f32; 4],
+    pub vision_size: [f32; 4],
+}
+
+/// Specialization of `shader.frag`: the model and toon bands of `shading`
+/// as constants 0 and 1, the ambient light of `tuning` as constant 2 and
+/// the `output` encoding.
+pub(crate) fn fragment_constants(
+    shading: ShadingModel,
+    tuning: &ShaderTuning,
+    output: OutputEncoding,
+) -> SpecConstantSet {
+    SpecConstants::new()
+        .with_u32(0, shading.id())
+        .with_u32(1, shading.bands())
+        .with_f32(2, tuning.ambient)
+        .with_output(output)
+        .fragment()
+}
+
+/// Layout of `shader.vert` and `shader.frag`: the frame uniforms, the fog
+/// of war mask and the block textures.
+pub(crate) fn scene_layout() -> Layout {
+    LayoutDesc::new()
+        .with_set(vec![
+            layout::binding(
+                0,
+                hal::pso::DescriptorType::UniformBuffer,
+                hal::pso::ShaderStageFlags::GRAPHICS,
+            ),
+            vision::binding(1),
+            block_textures::binding(2),
+        ])
+        .build()
+}
+
+/// Uniforms of the frame, shared by the voxel and skinned pipelines.
+pub(crate) fn uniform_args(aux: &Scene) -> UniformArgs {
+    let weather = aux.weather.params();
+    let sun = aux.sun_direction();
+    let (vision_area, vision_size) = vision::uniform(aux);
+    UniformArgs {
+        proj: aux.camera.proj.to_homogeneous(),
+        view: aux.render_view().inverse().to_homogeneous(),
+        ambient_power: aux.camera.ambient_power,
+        time: aux.time,
+        wetness: aux.weather.wetness(),
+        fog_density: weather.fog_density,
+        fog_color: weather.fog_color.to_array(),
+        sun_direction: [sun.x, sun.y, sun.z, 0.0],
+        vision_area,
+        vision_size,
+    }
 }
 
 #[derive(Debug, Default)]
-pub struct PipelineDesc;
+pub struct PipelineDesc {
+    /// Read the draw command and models from the `gpu_culling` output buffer.
+    pub gpu_culling: bool,
+
+    pub shading: ShadingModel,
+
+    pub tuning: ShaderTuning,
+
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
 
 pub struct Pipeline<B: hal::Backend> {
     align: u64,
-    buffer: Escape<Buffer<B>>,
+    buffer: MappedBuffer<B>,
     sets: Vec<Escape<DescriptorSet<B>>>,
     mesh: Mesh<B>,
-    positions: Vec<nalgebra::Transform3<f32>>,
+    bounds: Aabb,
+    positions: Vec<InstanceData>,
+    transforms: Vec<nalgebra::Transform3<f32>>,
+    aabbs: Vec<Aabb>,
+    visible: Vec<usize>,
+    culled: Option<Handle<Buffer<B>>>,
+    vision: VisionBuffer<B>,
+    block_textures: BlockTextureBuffer<B>,
+
+    /// Meshes of the scene objects, uploaded on first draw.
+    object_meshes: HashMap<ObjectHandle, Mesh<B>>,
+    object_data: Vec<InstanceData>,
+
+    /// Object draws of each frame, recorded again when they change.
+    object_draws: Vec<Vec<ObjectDraw>>,
+
+    /// What the object instances of each frame were last uploaded from.
+    object_uploads: Vec<Option<ObjectUploadKey>>,
+
+    /// Culling of the object instances of the frame being prepared.
+    object_stats: CullingStats,
+
+    /// Meshes of the chunks and the generation they were uploaded from.
+    chunk_meshes: HashMap<ChunkCoord, (u64, Mesh<B>)>,
+    chunk_data: Vec<InstanceData>,
+
+    /// Chunk draws of each frame, recorded again when they change.
+    chunk_draws: Vec<Vec<ChunkDraw>>,
+
+    /// Culling of the chunk meshes of the frame being prepared.
+    chunk_stats: CullingStats,
 }
 
-const MAX_OBJECTS: usize = 1;
+pub(crate) const MAX_OBJECTS: usize = 1024;
+/// Scale applied to vertex positions in `shader.vert`.
+const MESH_SCALE: f32 = 100.0;
 const UNIFORM_SIZE: u64 = size_of::<UniformArgs>() as u64;
-const MODELS_SIZE: u64 = size_of::<Model>() as u64 * MAX_OBJECTS as u64;
+const MODELS_SIZE: u64 = size_of::<InstanceData>() as u64 * MAX_OBJECTS as u64;
 const INDIRECT_SIZE: u64 = size_of::<DrawIndexedCommand>() as u64;
+const OBJECT_MODELS_SIZE: u64 = size_of::<InstanceData>() as u64 * MAX_OBJECT_INSTANCES as u64;
+const OBJECT_INDIRECT_SIZE: u64 = INDIRECT_SIZE * MAX_SCENE_OBJECTS as u64;
+const CHUNK_MODELS_SIZE: u64 = size_of::<InstanceData>() as u64 * MAX_CHUNK_MESHES as u64;
 
-fn iceil(value: u64, scale: u64) -> u64 {
+pub(crate) fn iceil(value: u64, scale: u64) -> u64 {
     ((value - 1) / scale + 1) * scale
 }
 
 fn buffer_frame_size(align: u64) -> u64 {
-    iceil(UNIFORM_SIZE + MODELS_SIZE + INDIRECT_SIZE, align)
+    iceil(
+        UNIFORM_SIZE
+            + MODELS_SIZE
+            + INDIRECT_SIZE
+            + OBJECT_MODELS_SIZE
+            + OBJECT_INDIRECT_SIZE
+            + CHUNK_MODELS_SIZE,
+        align,
+    )
 }
 
 fn uniform_offset(index: usize, align: u64) -> u64 {
@@ -110,13 +258,72 @@ fn indirect_offset(index: usize, align: u64) -> u64 {
     models_offset(index, align) + MODELS_SIZE
 }
 
+fn object_models_offset(index: usize, align: u64) -> u64 {
+    indirect_offset(index, align) + INDIRECT_SIZE
+}
+
+fn object_indirect_offset(index: usize, align: u64) -> u64 {
+    object_models_offset(index, align) + OBJECT_MODELS_SIZE
+}
+
+fn chunk_models_offset(index: usize, align: u64) -> u64 {
+    object_indirect_offset(index, align) + OBJECT_INDIRECT_SIZE
+}
+
+/// Bounds of the drawn model, scale included.
+pub(crate) fn model_bounds() -> Aabb {
+    Aabb::from_points(OCTREE_MODEL.vertices.iter().map(|vertex| {
+        let [x, y, z] = vertex.position.0;
+        Point3::new(x, y, z) * MESH_SCALE
+    }))
+    .unwrap_or_else(|| Aabb::new(Point3::origin(), Point3::origin()))
+}
+
+/// Drawn model uploaded to the device.
+pub(crate) fn model_mesh<B: hal::Backend>(queue: QueueId, factory: &Factory<B>) -> Mesh<B> {
+    let vertices: Vec<_> = OCTREE_MODEL
+        .vertices
+        .iter()
+        .map(|&vertex| VoxelVertex::new(vertex, AnimFlags::NONE))
+        .collect();
+    Mesh::<B>::builder()
+        .with_vertices(&vertices[..])
+        .with_indices(&(*OCTREE_MODEL.indices)[..])
+        .build(queue, factory)
+        .unwrap()
+}
+
+/// Mesh of a scene object or chunk uploaded to the device, at the scale of
+/// the instances once scaled by the vertex shader.
+fn object_mesh<B: hal::Backend>(queue: QueueId, factory: &Factory<B>, data: &MeshData) -> Mesh<B> {
+    let mut vertices = VoxelVertex::from_mesh_data(data);
+    for vertex in &mut vertices {
+        let [x, y, z] = vertex.position.0;
+        vertex.position = [x / MESH_SCALE, y / MESH_SCALE, z / MESH_SCALE].into();
+    }
+    Mesh::<B>::builder()
+        .with_vertices(&vertices[..])
+        .with_indices(&data.indices[..])
+        .build(queue, factory)
+        .unwrap()
+}
+
+/// Views of the drawn model for the impostors, captured on first use.
+pub(crate) fn impostor_atlas() -> &'static ImpostorAtlas {
+    &IMPOSTOR_ATLAS
+}
+
+pub(crate) fn model_index_count() -> u32 {
+    OCTREE_MODEL.indices.len() as u32
+}
+
 impl<B: hal::Backend> std::fmt::Debug for Pipeline<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "Pipeline Test")
     }
 }
 
-impl<B> SimpleGraphicsPipelineDesc<B, Camera> for PipelineDesc
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for PipelineDesc
 where
     B: hal::Backend,
 {
@@ -131,32 +338,38 @@ where
     )> {
         // Set the vertices for the vertex shader.
         return vec![
-            PosColorNorm::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex),
-            Model::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Instance(1)),
+            VoxelVertex::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex),
+            InstanceData::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Instance(1)),
         ];
     }
 
+    fn buffers(&self) -> Vec<BufferAccess> {
+        if !self.gpu_culling {
+            return Vec::new();
+        }
+        vec![BufferAccess {
+            access: hal::buffer::Access::INDIRECT_COMMAND_READ
+                | hal::buffer::Access::VERTEX_BUFFER_READ,
+            stages: hal::pso::PipelineStage::DRAW_INDIRECT | hal::pso::PipelineStage::VERTEX_INPUT,
+            usage: hal::buffer::Usage::INDIRECT | hal::buffer::Usage::VERTEX,
+        }]
+    }
+
     fn load_shader_set(
         &self,
         factory: &mut Factory<B>,
-        _aux: &Camera,
+        _aux: &Scene,
     ) -> rendy::shader::ShaderSet<B> {
-        SHADERS.build(factory, Default::default()).unwrap()
+        SHADERS
+            .build(
+                factory,
+                fragment_constants(self.shading, &self.tuning, self.output),
+            )
+            .unwrap()
     }
 
     fn layout(&self) -> Layout {
-        return Layout {
-            sets: vec![SetLayout {
-                bindings: vec![hal::pso::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    ty: hal::pso::DescriptorType::UniformBuffer,
-                    count: 1,
-                    stage_flags: hal::pso::ShaderStageFlags::GRAPHICS,
-                    immutable_samplers: false,
-                }],
-            }],
-            push_constants: Vec::new(),
-        };
+        layout::resolve("mesh", scene_layout(), &SHADERS)
     }
 
     fn build<'a>(
@@ -164,8 +377,8 @@ where
         ctx: &GraphContext<B>,
         factory: &mut Factory<B>,
         queue: QueueId,
-        _aux: &Camera,
-        _buffers: Vec<NodeBuffer>,
+        aux: &Scene,
+        buffers: Vec<NodeBuffer>,
         _images: Vec<NodeImage>,
         set_layouts: &[Handle<DescriptorSetLayout<B>>],
     ) -> Result<Self::Pipeline, hal::pso::CreationError> {
@@ -175,60 +388,240 @@ where
             .limits()
             .min_uniform_buffer_offset_alignment;
 
-        let buffer = factory
-            .create_buffer(
-                BufferInfo {
-                    size: buffer_frame_size(align) * frames as u64,
-                    usage: hal::buffer::Usage::UNIFORM
-                        | hal::buffer::Usage::INDIRECT
-                        | hal::buffer::Usage::VERTEX,
-                },
-                rendy::memory::Dynamic,
-            )
-            .unwrap();
-
-        let mut sets = Vec::new();
+        let buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: buffer_frame_size(align) * frames as u64,
+                usage: hal::buffer::Usage::UNIFORM
+                    | hal::buffer::Usage::INDIRECT
+                    | hal::buffer::Usage::VERTEX,
+            },
+        );
+
+        let vision = VisionBuffer::new(factory, frames);
+        let block_textures = BlockTextureBuffer::new(factory, frames);
+
+        let sets = {
+            let _scope = aux.profiler.scope("mesh.descriptors");
+            let sets: Vec<_> = (0..frames)
+                .map(|_| {
+                    factory
+                        .create_descriptor_set(set_layouts[0].clone())
+                        .unwrap()
+                })
+                .collect();
 
-        for index in 0..frames {
+            // Sets are written once, all frames in a single call.
             unsafe {
-                let set = factory
-                    .create_descriptor_set(set_layouts[0].clone())
-                    .unwrap();
-                factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
-                    set: set.raw(),
-                    binding: 0,
-                    array_offset: 0,
-                    descriptors: Some(hal::pso::Descriptor::Buffer(
-                        buffer.raw(),
-                        Some(uniform_offset(index, align))
-                            ..Some(uniform_offset(index, align) + UNIFORM_SIZE),
-                    )),
+                factory.write_descriptor_sets(sets.iter().enumerate().flat_map(|(index, set)| {
+                    vec![
+                        hal::pso::DescriptorSetWrite {
+                            set: set.raw(),
+                            binding: 0,
+                            array_offset: 0,
+                            descriptors: Some(hal::pso::Descriptor::Buffer(
+                                buffer.raw(),
+                                Some(uniform_offset(index, align))
+                                    ..Some(uniform_offset(index, align) + UNIFORM_SIZE),
+                            )),
+                        },
+                        hal::pso::DescriptorSetWrite {
+                            set: set.raw(),
+                            binding: 1,
+                            array_offset: 0,
+                            descriptors: Some(vision.descriptor(index)),
+                        },
+                        hal::pso::DescriptorSetWrite {
+                            set: set.raw(),
+                            binding: 2,
+                            array_offset: 0,
+                            descriptors: Some(block_textures.descriptor(index)),
+                        },
+                    ]
                 }));
-                sets.push(set);
             }
-        }
+            sets
+        };
 
-        let mesh = Mesh::<B>::builder()
-            .with_vertices(&(*OCTREE_MODEL.vertices)[..])
-            .with_indices(&(*OCTREE_MODEL.indices)[..])
-            .build(queue, &factory)
-            .unwrap();
+        let mesh = model_mesh(queue, factory);
 
-        let positions: Vec<nalgebra::Transform3<f32>> = (0..MAX_OBJECTS)
-            .map(|_| nalgebra::Transform3::identity() * nalgebra::Translation3::new(0.0, 0.0, 0.0))
-            .collect();
+        let bounds = model_bounds();
+
+        let culled = buffers
+            .first()
+            .map(|buffer| ctx.get_buffer(buffer.id).unwrap().clone());
+
+        let positions = Vec::with_capacity(MAX_OBJECTS);
 
         Ok(Pipeline {
             align,
             buffer,
             sets,
             mesh,
+            bounds,
             positions,
+            transforms: Vec::new(),
+            aabbs: Vec::new(),
+            visible: Vec::new(),
+            culled,
+            vision,
+            block_textures,
+            object_meshes: HashMap::new(),
+            object_data: Vec::with_capacity(MAX_OBJECT_INSTANCES),
+            object_draws: vec![Vec::new(); frames],
+            object_uploads: vec![None; frames],
+            object_stats: CullingStats::default(),
+            chunk_meshes: HashMap::new(),
+            chunk_data: Vec::with_capacity(MAX_CHUNK_MESHES),
+            chunk_draws: vec![Vec::new(); frames],
+            chunk_stats: CullingStats::default(),
         })
     }
 }
 
-impl<B> SimpleGraphicsPipeline<B, Camera> for Pipeline<B>
+/// Culling of the object instances given by `draws`.
+fn object_stats(draws: &[ObjectDraw]) -> CullingStats {
+    let mut stats = CullingStats::default();
+    for draw in draws {
+        let (drawn, culled) = (draw.instance_count as usize, draw.culled as usize);
+        stats.tested += drawn + culled;
+        stats.frustum_culled += culled;
+        stats.drawn += drawn;
+    }
+    stats
+}
+
+impl<B: hal::Backend> Pipeline<B> {
+    /// Upload the instances and draw commands of the scene objects for the
+    /// frame `index`, and the meshes of the new objects. Nothing is uploaded
+    /// while the objects and the view are those of the last upload to the
+    /// frame. The draws are recorded again when objects were added or
+    /// removed, the instance counts are read from the indirect buffer.
+    fn prepare_objects(
+        &mut self,
+        factory: &Factory<B>,
+        queue: QueueId,
+        index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        let view_proj = aux.culling_view_proj();
+        let key = ObjectUploadKey::new(aux.objects(), &aux.origin, &view_proj);
+        if self.object_uploads[index].as_ref() == Some(&key) {
+            self.object_stats = object_stats(&self.object_draws[index]);
+            return PrepareResult::DrawReuse;
+        }
+
+        let frustum = Frustum::from_matrix(&view_proj);
+        let draws = aux.object_draws(
+            |aabb| frustum.contains_aabb(&aabb.min, &aabb.max),
+            &mut self.object_data,
+        );
+        self.object_stats = object_stats(&draws);
+
+        // Frames in flight keep the buffers of dropped meshes alive.
+        self.object_meshes
+            .retain(|&handle, _| aux.objects().contains(handle));
+        for draw in &draws {
+            if !self.object_meshes.contains_key(&draw.object) {
+                let object = aux.objects().get(draw.object).unwrap();
+                let mesh = object_mesh(queue, factory, object.mesh());
+                self.object_meshes.insert(draw.object, mesh);
+            }
+        }
+
+        let commands: Vec<_> = draws
+            .iter()
+            .map(|draw| DrawIndexedCommand {
+                index_count: self.object_meshes[&draw.object].len(),
+                instance_count: draw.instance_count,
+                first_index: 0,
+                vertex_offset: 0,
+                first_instance: 0,
+            })
+            .collect();
+        unsafe {
+            self.buffer.write(
+                factory,
+                object_models_offset(index, self.align),
+                &self.object_data[..],
+            );
+            self.buffer.write(
+                factory,
+                object_indirect_offset(index, self.align),
+                &commands[..],
+            );
+        }
+
+        let changed = draws.len() != self.object_draws[index].len()
+            || draws
+                .iter()
+                .zip(&self.object_draws[index])
+                .any(|(draw, recorded)| draw.object != recorded.object);
+        self.object_draws[index] = draws;
+        self.object_uploads[index] = Some(key);
+        if changed {
+            PrepareResult::DrawRecord
+        } else {
+            PrepareResult::DrawReuse
+        }
+    }
+
+    /// Upload the instances of the chunk meshes in view for the frame
+    /// `index`, and the meshes which changed since their last upload. The
+    /// draws are recorded again when the chunks in view or their meshes
+    /// changed.
+    fn prepare_chunks(
+        &mut self,
+        factory: &Factory<B>,
+        queue: QueueId,
+        index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        let frustum = Frustum::from_matrix(&aux.culling_view_proj());
+        let eye = Point3::from(aux.culling_view().translation.vector);
+        let (draws, stats) = chunk_draws(
+            &aux.chunk_meshes,
+            &aux.origin,
+            &eye,
+            |aabb| frustum.contains_aabb(&aabb.min, &aabb.max),
+            &mut self.chunk_data,
+        );
+        self.chunk_stats = stats;
+
+        // Frames in flight keep the buffers of dropped meshes alive, their
+        // draws are recorded again as the generations differ.
+        let meshes = &aux.chunk_meshes;
+        self.chunk_meshes.retain(|coord, (generation, _)| {
+            meshes
+                .get(coord)
+                .map_or(false, |mesh| mesh.generation() == *generation)
+        });
+        for draw in &draws {
+            if !self.chunk_meshes.contains_key(&draw.coord) {
+                let mesh = object_mesh(queue, factory, meshes.get(&draw.coord).unwrap().mesh());
+                self.chunk_meshes
+                    .insert(draw.coord, (draw.generation, mesh));
+            }
+        }
+        unsafe {
+            self.buffer.write(
+                factory,
+                chunk_models_offset(index, self.align),
+                &self.chunk_data[..],
+            );
+        }
+
+        let changed = draws != self.chunk_draws[index];
+        self.chunk_draws[index] = draws;
+        if changed {
+            PrepareResult::DrawRecord
+        } else {
+            PrepareResult::DrawReuse
+        }
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Pipeline<B>
 where
     B: hal::Backend,
 {
@@ -237,28 +630,115 @@ where
     fn prepare(
         &mut self,
         factory: &Factory<B>,
-        _queue: QueueId,
+        queue: QueueId,
         _set_layouts: &[Handle<DescriptorSetLayout<B>>],
         index: usize,
-        aux: &Camera,
+        aux: &Scene,
     ) -> PrepareResult {
         debug!("Pipeline Mesh, Preparing {}.", index);
 
         unsafe {
             // Upload Uniform Parameters
-            factory
-                .upload_visible_buffer(
-                    &mut self.buffer,
-                    uniform_offset(index, self.align) as u64,
-                    &[UniformArgs {
-                        proj: aux.proj.to_homogeneous(),
-                        view: aux.view.inverse().to_homogeneous(),
-                        ambient_power: aux.ambient_power,
-                    }],
-                )
-                .unwrap();
+            self.buffer.write(
+                factory,
+                uniform_offset(index, self.align) as u64,
+                &[uniform_args(aux)],
+            );
+        };
+        self.vision.upload(factory, index, aux);
+        self.block_textures.upload(factory, index, aux);
+
+        let objects = self.prepare_objects(factory, queue, index, aux);
+        let chunks = self.prepare_chunks(factory, queue, index, aux);
+        let result = match (objects, chunks) {
+            (PrepareResult::DrawReuse, PrepareResult::DrawReuse) => PrepareResult::DrawReuse,
+            _ => PrepareResult::DrawRecord,
         };
 
+        if self.culled.is_some() {
+            // Culling and models upload happen in the compute pre-pass.
+            let mut stats = CullingStats {
+                tested: aux.instances.len(),
+                ..CullingStats::default()
+            };
+            stats.add(&self.object_stats);
+            stats.add(&self.chunk_stats);
+            aux.record_culling(stats);
+            // Nothing is read back, chunks are tested whole instead.
+            let frustum = Frustum::from_matrix(&aux.culling_view_proj());
+            let size = Vector3::repeat(CHUNK_SIZE as f32);
+            aux.record_visible_chunks(
+                aux.instances
+                    .iter()
+                    .map(|instance| instance.location.chunk)
+                    .filter(|chunk| {
+                        let min = aux
+                            .origin
+                            .to_render(&Location::new(*chunk, Vector3::zeros()));
+                        frustum.contains_aabb(&Point3::from(min), &Point3::from(min + size))
+                    }),
+            );
+            return result;
+        }
+
+        let scope = aux.profiler.scope("mesh.culling");
+        // Model matrices are relative to the floating origin, like the camera.
+        let frustum = Frustum::from_matrix(&aux.culling_view_proj());
+        let bounds = &self.bounds;
+        self.transforms.clear();
+        self.transforms.extend(aux.model_transforms());
+        self.aabbs.clear();
+        self.aabbs.extend(
+            self.transforms
+                .iter()
+                .map(|transform| bounds.transform(transform.matrix())),
+        );
+        self.visible.clear();
+        frustum.cull_aabbs(&self.aabbs, &mut self.visible);
+
+        let mut stats = CullingStats {
+            tested: self.aabbs.len(),
+            frustum_culled: self.aabbs.len() - self.visible.len(),
+            ..CullingStats::default()
+        };
+        let eye = Point3::from(aux.culling_view().translation.vector);
+        stats.distance_culled = cull_distance(&mut self.visible, &self.aabbs, &eye, |i| {
+            aux.max_distance(i)
+        });
+        // Billboards and instances over the budget are still in view.
+        aux.record_visible_chunks(
+            self.visible
+                .iter()
+                .map(|&i| aux.instances[i].location.chunk),
+        );
+        let mut impostors = Vec::new();
+        stats.impostors = impostor::split(
+            &mut self.visible,
+            &self.aabbs,
+            &self.transforms,
+            &eye,
+            &aux.impostors,
+            impostor_atlas(),
+            &mut impostors,
+        );
+        aux.record_impostors(impostors);
+        let budget = aux.draw_budget.unwrap_or(MAX_OBJECTS).min(MAX_OBJECTS);
+        stats.budget_culled = apply_budget(&mut self.visible, &self.aabbs, &eye, budget);
+        stats.drawn = self.visible.len();
+        stats.add(&self.object_stats);
+        stats.add(&self.chunk_stats);
+        aux.record_drawn(&self.visible);
+        aux.record_culling(stats);
+        drop(scope);
+
+        let transforms = &self.transforms;
+        self.positions.clear();
+        self.positions.extend(
+            self.visible
+                .iter()
+                .map(|&i| InstanceData::new(&transforms[i], &aux.instances[i].overrides)),
+        );
+
         let command = DrawIndexedCommand {
             index_count: self.mesh.len(),
             instance_count: self.positions.len() as u32,
@@ -269,27 +749,20 @@ where
 
         unsafe {
             // Upload Index Command
-            factory
-                .upload_visible_buffer(
-                    &mut self.buffer,
-                    indirect_offset(index, self.align),
-                    &[command],
-                )
-                .unwrap()
+            self.buffer
+                .write(factory, indirect_offset(index, self.align), &[command]);
         }
 
         unsafe {
             // Upload positions
-            factory
-                .upload_visible_buffer(
-                    &mut self.buffer,
-                    models_offset(index, self.align),
-                    &self.positions[..],
-                )
-                .unwrap()
+            self.buffer.write(
+                factory,
+                models_offset(index, self.align),
+                &self.positions[..],
+            );
         }
 
-        PrepareResult::DrawReuse
+        result
     }
 
     fn draw(
@@ -297,7 +770,7 @@ where
         layout: &B::PipelineLayout,
         mut encoder: RenderPassEncoder<'_, B>,
         index: usize,
-        _aux: &Camera,
+        _aux: &Scene,
     ) {
         debug!("Pipeline Mesh, Drawing index: {}.", index);
 
@@ -309,24 +782,51 @@ where
                 std::iter::empty(),
             );
 
-            let vertex = [PosColorNorm::vertex()];
+            let vertex = [VoxelVertex::vertex()];
 
             self.mesh.bind(0, &vertex, &mut encoder).unwrap();
 
-            encoder.bind_vertex_buffers(
-                1,
-                std::iter::once((self.buffer.raw(), models_offset(index, self.align))),
-            );
-            encoder.draw_indexed_indirect(
-                self.buffer.raw(),
-                indirect_offset(index, self.align),
-                1,
-                INDIRECT_SIZE as u32,
-            );
+            let (buffer, models, indirect) = match self.culled {
+                Some(ref culled) => (culled.raw(), OUTPUT_MODELS_OFFSET, 0),
+                None => (
+                    self.buffer.raw(),
+                    models_offset(index, self.align),
+                    indirect_offset(index, self.align),
+                ),
+            };
+
+            encoder.bind_vertex_buffers(1, std::iter::once((buffer, models)));
+            // A single draw, the culling pass sets its instance count on the
+            // GPU. gfx-hal 0.4 has no indirect count draws to go further.
+            encoder.draw_indexed_indirect(buffer, indirect, 1, INDIRECT_SIZE as u32);
+
+            let buffer = self.buffer.raw();
+            for (slot, draw) in self.object_draws[index].iter().enumerate() {
+                let mesh = &self.object_meshes[&draw.object];
+                mesh.bind(0, &vertex, &mut encoder).unwrap();
+                let models = object_models_offset(index, self.align)
+                    + draw.first_instance as u64 * size_of::<InstanceData>() as u64;
+                encoder.bind_vertex_buffers(1, std::iter::once((buffer, models)));
+                encoder.draw_indexed_indirect(
+                    buffer,
+                    object_indirect_offset(index, self.align) + slot as u64 * INDIRECT_SIZE,
+                    1,
+                    INDIRECT_SIZE as u32,
+                );
+            }
+
+            let models = chunk_models_offset(index, self.align);
+            encoder.bind_vertex_buffers(1, std::iter::once((buffer, models)));
+            for (slot, draw) in self.chunk_draws[index].iter().enumerate() {
+                let (_, mesh) = &self.chunk_meshes[&draw.coord];
+                mesh.bind(0, &vertex, &mut encoder).unwrap();
+                let slot = slot as u32;
+                encoder.draw_indexed(0..mesh.len(), 0, slot..slot + 1);
+            }
         }
     }
 
-    fn dispose(self, _factory: &mut Factory<B>, _aux: &Camera) {
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {
         info!("Disposing Pipeline Mesh.");
     }
 }
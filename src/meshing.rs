@@ -0,0 +1,458 @@
+//! Greedy meshing of chunks into plain vertex arrays.
+//!
+//! Nothing here depends on the GPU, the output `MeshData` is converted to
+//! vertices by `VoxelVertex::from_mesh_data` when it is uploaded. Smooth
+//! surfaces are extracted by the `smooth` module, see `MeshingMode`.
+//!
+//! Painted faces are blended into the vertex colors by the blocky mesher
+//! and damaged voxels get their stage in the vertex flags, for the shader
+//! to draw cracks, as do textured voxels their block texture layer. The
+//! smooth surfaces don't follow the voxel faces and ignore all three.
+
+use crate::chunk::{Axis, Chunk, MeshScratch, Side, VoxelId, AIR};
+use std::collections::HashMap;
+
+use crate::color::Color;
+use crate::coords::CHUNK_SIZE;
+use crate::smooth::{self, DensityGrid};
+
+const SIZE: usize = CHUNK_SIZE as usize;
+
+/// First bit of the damage stage in the vertex flags, below are the
+/// `AnimFlags`.
+pub const DAMAGE_SHIFT: u32 = 8;
+
+/// Bits of the damage stage in the vertex flags.
+pub const DAMAGE_MASK: u32 = 0xf << DAMAGE_SHIFT;
+
+/// First bit of the block texture layer in the vertex flags, see
+/// `texture_flags`.
+pub const TEXTURE_SHIFT: u32 = 16;
+
+/// Bits of the block texture layer in the vertex flags.
+pub const TEXTURE_MASK: u32 = 0x1ff << TEXTURE_SHIFT;
+
+/// Vertex flags of the faces drawn with the block texture `layer`, see
+/// `BlockTextures`. Stored plus one, zero is untextured.
+pub fn texture_flags(layer: Option<u32>) -> u32 {
+    layer.map_or(0, |layer| ((layer + 1) << TEXTURE_SHIFT) & TEXTURE_MASK)
+}
+
+/// Block texture layer of the vertex `flags`, see `texture_flags`.
+pub fn texture_layer(flags: u32) -> Option<u32> {
+    match (flags & TEXTURE_MASK) >> TEXTURE_SHIFT {
+        0 => None,
+        layer => Some(layer - 1),
+    }
+}
+
+/// Triangle mesh with one attribute array per vertex attribute.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 4]>,
+
+    /// `AnimFlags` bits of each vertex, with the damage stage of its voxel
+    /// in `DAMAGE_MASK` and its block texture in `TEXTURE_MASK`.
+    pub flags: Vec<u32>,
+
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn new() -> Self {
+        MeshData::default()
+    }
+
+    /// Empty the arrays, keeping their allocations.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.normals.clear();
+        self.colors.clear();
+        self.flags.clear();
+        self.indices.clear();
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn push_vertex(&mut self, position: [f32; 3], normal: [f32; 3], color: Color, flags: u32) {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.colors.push(color.to_array());
+        self.flags.push(flags);
+    }
+}
+
+/// Appearance of a voxel type in the mesh.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoxelStyle {
+    pub color: Color,
+
+    /// `AnimFlags` bits set on the top vertices of the faces.
+    pub flags: u32,
+
+    /// Layer of the block texture drawn over the color, see
+    /// `BlockTextures`. Blocky meshes only.
+    pub texture: Option<u32>,
+}
+
+/// Surface extracted from the voxels of a chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// Cube faces, see `mesh_chunk`.
+    Blocky,
+
+    /// One vertex per surface cell, rounded terrain with few triangles.
+    SurfaceNets,
+
+    /// Triangles inside each surface cell, closer to the voxels.
+    MarchingCubes,
+}
+
+impl Default for MeshingMode {
+    fn default() -> Self {
+        MeshingMode::Blocky
+    }
+}
+
+/// Skirts hanging from the open borders of chunk meshes.
+///
+/// Neighbour chunks meshed at different levels of detail don't meet
+/// exactly along their border. A skirt is a strip pushed into the terrain
+/// from each open edge, which fills the crack from behind.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkirtSettings {
+    /// Length of the skirts, in voxels.
+    pub depth: f32,
+
+    /// Blocky meshes always close their borders, they rarely need skirts.
+    pub blocky: bool,
+
+    pub surface_nets: bool,
+    pub marching_cubes: bool,
+}
+
+impl SkirtSettings {
+    pub fn enabled(&self, mode: MeshingMode) -> bool {
+        match mode {
+            MeshingMode::Blocky => self.blocky,
+            MeshingMode::SurfaceNets => self.surface_nets,
+            MeshingMode::MarchingCubes => self.marching_cubes,
+        }
+    }
+}
+
+impl Default for SkirtSettings {
+    fn default() -> Self {
+        SkirtSettings {
+            depth: 2.0,
+            blocky: false,
+            surface_nets: true,
+            marching_cubes: true,
+        }
+    }
+}
+
+/// Mesh `chunk` with `mode` into `out`, in chunk local coordinates, with
+/// skirts if `skirts` enables them for `mode`.
+///
+/// The smooth modes treat every non air voxel as solid and pass between
+/// the voxel centers, so they don't reach the chunk faces at its lowest
+/// corner.
+pub fn mesh<F>(
+    mode: MeshingMode,
+    skirts: &SkirtSettings,
+    chunk: &Chunk,
+    scratch: &mut MeshScratch,
+    style: F,
+    out: &mut MeshData,
+) where
+    F: Fn(VoxelId) -> VoxelStyle,
+{
+    let grid = match mode {
+        MeshingMode::Blocky => {
+            mesh_chunk(chunk, scratch, style, out);
+            if skirts.enabled(mode) {
+                add_skirts(out, skirts.depth);
+            }
+            return;
+        }
+        MeshingMode::SurfaceNets | MeshingMode::MarchingCubes => DensityGrid::from_chunk(chunk),
+    };
+    // A cell takes the style of the first solid voxel at its corners.
+    let cell_style = |x: usize, y: usize, z: usize| {
+        let last = SIZE - 1;
+        let id = (0..8)
+            .map(|i| {
+                chunk.get_voxel(
+                    (x + (i & 1)).min(last),
+                    (y + ((i >> 1) & 1)).min(last),
+                    (z + ((i >> 2) & 1)).min(last),
+                )
+            })
+            .find(|&id| id != AIR)
+            .unwrap_or(AIR);
+        style(id)
+    };
+    if mode == MeshingMode::SurfaceNets {
+        smooth::surface_nets(&grid, cell_style, out);
+    } else {
+        smooth::marching_cubes(&grid, cell_style, out);
+    }
+    // The grid samples voxel centers.
+    for position in &mut out.positions {
+        for v in position.iter_mut() {
+            *v += 0.5;
+        }
+    }
+    if skirts.enabled(mode) {
+        add_skirts(out, skirts.depth);
+    }
+}
+
+/// Extrude the open edges of `mesh`, used by a single triangle, `depth`
+/// voxels against their vertex normals.
+///
+/// Vertices are matched by position, meshers which don't share vertices
+/// between cells get skirts only on their actual border.
+pub fn add_skirts(mesh: &mut MeshData, depth: f32) {
+    let key = |i: u32| {
+        let p = mesh.positions[i as usize];
+        [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]
+    };
+    // Directed edges of the triangles, an inner edge is also used reversed.
+    let mut edges = HashMap::new();
+    for triangle in mesh.indices.chunks(3) {
+        for k in 0..3 {
+            let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+            *edges.entry((key(a), key(b))).or_insert(0i32) += 1;
+            *edges.entry((key(b), key(a))).or_insert(0i32) -= 1;
+        }
+    }
+    let mut open = Vec::new();
+    for triangle in mesh.indices.chunks(3) {
+        for k in 0..3 {
+            let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+            if edges[&(key(a), key(b))] > 0 {
+                open.push((a, b));
+            }
+        }
+    }
+
+    for (a, b) in open {
+        let first = mesh.vertex_count() as u32;
+        for &i in &[a, b] {
+            let i = i as usize;
+            let (p, n) = (mesh.positions[i], mesh.normals[i]);
+            let lowered = [
+                p[0] - n[0] * depth,
+                p[1] - n[1] * depth,
+                p[2] - n[2] * depth,
+            ];
+            let color = Color::from(mesh.colors[i]);
+            mesh.push_vertex(lowered, n, color, 0);
+        }
+        // Walked b to a like the neighbouring triangle would.
+        mesh.indices
+            .extend_from_slice(&[b, a, first, b, first, first + 1]);
+    }
+}
+
+/// Mesh the visible faces of `chunk` into `out`, in chunk local coordinates.
+///
+/// Coplanar faces of the same voxel type, paint and damage are merged
+/// into rectangles. Faces on the chunk border are always emitted as the
+/// neighbours are unknown.
+pub fn mesh_chunk<F>(chunk: &Chunk, scratch: &mut MeshScratch, style: F, out: &mut MeshData)
+where
+    F: Fn(VoxelId) -> VoxelStyle,
+{
+    out.clear();
+    scratch.visible_faces(chunk);
+    let (painted, damaged) = (chunk.is_painted(), chunk.is_damaged());
+    let mut slice = vec![(AIR, None, 0); SIZE * SIZE];
+    for (i, &axis) in Axis::ALL.iter().enumerate() {
+        for &positive in &[true, false] {
+            let masks = if positive {
+                &scratch.masks[i].positive
+            } else {
+                &scratch.masks[i].negative
+            };
+            for along in 0..SIZE {
+                for b in 0..SIZE {
+                    for a in 0..SIZE {
+                        let column = a + b * SIZE;
+                        slice[column] = if masks[column] & (1 << along) != 0 {
+                            let (x, y, z) = axis.voxel(a, b, along);
+                            let paint = if painted {
+                                chunk.face_paint(x, y, z, Side::new(axis, positive))
+                            } else {
+                                None
+                            };
+                            let damage = if damaged { chunk.damage(x, y, z) } else { 0 };
+                            (chunk.get_voxel(x, y, z), paint, damage)
+                        } else {
+                            (AIR, None, 0)
+                        };
+                    }
+                }
+                let face = Face {
+                    axis,
+                    positive,
+                    along,
+                };
+                merge_slice(&mut slice, |(id, paint, damage), rect| {
+                    let style = paint_style(style(id), paint);
+                    let flags = (damage as u32) << DAMAGE_SHIFT | texture_flags(style.texture);
+                    face.emit(rect, style, flags, out)
+                });
+            }
+        }
+    }
+}
+
+/// Paint blended over the color of a voxel by its alpha.
+fn paint_style(style: VoxelStyle, paint: Option<Color>) -> VoxelStyle {
+    match paint {
+        Some(paint) => VoxelStyle {
+            color: style.color.lerp(paint, paint.a).with_alpha(style.color.a),
+            ..style
+        },
+        None => style,
+    }
+}
+
+/// Face of a slice: the voxel it belongs to, the paint of the face and the
+/// damage of the voxel.
+type Cell = (VoxelId, Option<Color>, u8);
+
+/// Rectangle of merged faces in slice coordinates, ends excluded.
+#[derive(Debug, Copy, Clone)]
+struct Rect {
+    a: (usize, usize),
+    b: (usize, usize),
+}
+
+/// Greedily cover the non air cells of `slice` with rectangles of equal
+/// cells.
+///
+/// The slice is consumed, merged cells are set to air.
+fn merge_slice<F>(slice: &mut [Cell], mut emit: F)
+where
+    F: FnMut(Cell, Rect),
+{
+    for b in 0..SIZE {
+        let mut a = 0;
+        while a < SIZE {
+            let cell = slice[a + b * SIZE];
+            if cell.0 == AIR {
+                a += 1;
+                continue;
+            }
+            let mut width = 1;
+            while a + width < SIZE && slice[a + width + b * SIZE] == cell {
+                width += 1;
+            }
+            let mut height = 1;
+            while b + height < SIZE
+                && (a..a + width).all(|x| slice[x + (b + height) * SIZE] == cell)
+            {
+                height += 1;
+            }
+            for y in b..b + height {
+                for x in a..a + width {
+                    slice[x + y * SIZE] = (AIR, None, 0);
+                }
+            }
+            emit(
+                cell,
+                Rect {
+                    a: (a, a + width),
+                    b: (b, b + height),
+                },
+            );
+            a += width;
+        }
+    }
+}
+
+/// Orientation of the faces of one slice.
+struct Face {
+    axis: Axis,
+    positive: bool,
+    along: usize,
+}
+
+impl Face {
+    /// Emit a rectangle, `flags` set on every vertex and `style.flags` on
+    /// the top ones.
+    fn emit(&self, rect: Rect, style: VoxelStyle, flags: u32, out: &mut MeshData) {
+        let plane = if self.positive {
+            self.along + 1
+        } else {
+            self.along
+        } as f32;
+        let point = |a: usize, b: usize| {
+            let (x, y, z) = match self.axis {
+                Axis::X => (plane, a as f32, b as f32),
+                Axis::Y => (a as f32, plane, b as f32),
+                Axis::Z => (a as f32, b as f32, plane),
+            };
+            [x, y, z]
+        };
+        let corners = [
+            point(rect.a.0, rect.b.0),
+            point(rect.a.1, rect.b.0),
+            point(rect.a.1, rect.b.1),
+            point(rect.a.0, rect.b.1),
+        ];
+        let sign = if self.positive { 1.0 } else { -1.0 };
+        let normal = match self.axis {
+            Axis::X => [sign, 0.0, 0.0],
+            Axis::Y => [0.0, sign, 0.0],
+            Axis::Z => [0.0, 0.0, sign],
+        };
+        let top = corners
+            .iter()
+            .map(|corner| corner[1])
+            .fold(std::f32::MIN, f32::max);
+
+        let first = out.vertex_count() as u32;
+        for corner in &corners {
+            let flags = if corner[1] == top {
+                style.flags | flags
+            } else {
+                flags
+            };
+            out.push_vertex(*corner, normal, style.color, flags);
+        }
+        // The corners turn around +X and +Z but around -Y, see `Axis::voxel`.
+        let counter_clockwise = self.positive != (self.axis == Axis::Y);
+        if counter_clockwise {
+            out.indices.extend_from_slice(&[
+                first,
+                first + 1,
+                first + 2,
+                first,
+                first + 2,
+                first + 3,
+            ]);
+        } else {
+            out.indices.extend_from_slice(&[
+                first,
+                first + 2,
+                first + 1,
+                first,
+                first + 3,
+                first + 2,
+            ]);
+        }
+    }
+}
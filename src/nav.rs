@@ -0,0 +1,261 @@
+//! Path finding for characters walking on the voxel terrain.
+//!
+//! Positions are the voxels a character stands in: air with a solid voxel
+//! below and enough air above for its height. `find_path` runs A* from
+//! column to column, climbing, jumping and dropping within the limits of
+//! `NavSettings`, then drops the waypoints a straight walk can skip.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::chunk::AIR;
+use crate::coords::WorldPos;
+use crate::world::World;
+
+/// Movement abilities of the walking character.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NavSettings {
+    /// Air voxels needed above the floor.
+    pub height: i64,
+
+    /// Highest climb walked up like flat ground.
+    pub step_height: i64,
+
+    /// Highest climb, past `step_height` it takes a jump.
+    pub jump_height: i64,
+
+    /// Added to the cost of a move which takes a jump.
+    pub jump_cost: f32,
+
+    /// Deepest drop walked off.
+    pub max_drop: i64,
+
+    /// Most positions visited before giving up.
+    pub max_nodes: usize,
+}
+
+impl Default for NavSettings {
+    fn default() -> Self {
+        NavSettings {
+            height: 2,
+            step_height: 0,
+            jump_height: 1,
+            jump_cost: 1.0,
+            max_drop: 3,
+            max_nodes: 4096,
+        }
+    }
+}
+
+/// Walkable route between two positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavPath {
+    /// Positions to walk straight between, from the start to the goal.
+    pub waypoints: Vec<WorldPos>,
+
+    /// Length of the route, jumps included.
+    pub cost: f32,
+}
+
+/// Whether a character can stand in `pos`.
+pub fn is_walkable(world: &World, pos: &WorldPos, settings: &NavSettings) -> bool {
+    solid(world, pos.x, pos.y - 1, pos.z)
+        && clear(world, pos.x, pos.y, pos.y + settings.height, pos.z)
+}
+
+/// Shortest walk from `start` to `goal`, `None` when either isn't walkable
+/// or the goal is out of reach within `settings.max_nodes` positions.
+pub fn find_path(
+    world: &World,
+    start: &WorldPos,
+    goal: &WorldPos,
+    settings: &NavSettings,
+) -> Option<NavPath> {
+    if !is_walkable(world, start, settings) || !is_walkable(world, goal, settings) {
+        return None;
+    }
+    let heuristic = |pos: &WorldPos| ((pos.x - goal.x).abs() + (pos.z - goal.z).abs()) as f32;
+
+    let mut open = BinaryHeap::new();
+    // Best known cost of each position and the position it was reached from.
+    let mut visited: HashMap<WorldPos, (f32, Option<WorldPos>)> = HashMap::new();
+    visited.insert(*start, (0.0, None));
+    open.push(Open {
+        estimate: heuristic(start),
+        cost: 0.0,
+        pos: *start,
+    });
+    let mut expanded = 0;
+    while let Some(Open { cost, pos, .. }) = open.pop() {
+        if pos == *goal {
+            let mut path = vec![pos];
+            while let Some((_, Some(previous))) = visited.get(path.last().unwrap()) {
+                path.push(*previous);
+            }
+            path.reverse();
+            return Some(NavPath {
+                waypoints: smooth_path(world, &path, settings),
+                cost,
+            });
+        }
+        if cost > visited[&pos].0 {
+            continue;
+        }
+        expanded += 1;
+        if expanded > settings.max_nodes {
+            return None;
+        }
+        for &(dx, dz) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (next, step) = match neighbour(world, &pos, dx, dz, settings) {
+                Some(neighbour) => neighbour,
+                None => continue,
+            };
+            let cost = cost + step;
+            if visited.get(&next).map_or(true, |&(known, _)| cost < known) {
+                visited.insert(next, (cost, Some(pos)));
+                open.push(Open {
+                    estimate: cost + heuristic(&next),
+                    cost,
+                    pos: next,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Drop the waypoints of `path` which can be skipped by walking straight
+/// on flat ground. Changes of height are kept as waypoints.
+pub fn smooth_path(world: &World, path: &[WorldPos], settings: &NavSettings) -> Vec<WorldPos> {
+    let mut smoothed: Vec<WorldPos> = path.iter().take(1).cloned().collect();
+    let mut i = 0;
+    while i + 1 < path.len() {
+        let mut next = i + 1;
+        while next + 1 < path.len() && straight_walk(world, &path[i], &path[next + 1], settings) {
+            next += 1;
+        }
+        smoothed.push(path[next]);
+        i = next;
+    }
+    smoothed
+}
+
+/// Position reached by moving one column along `(dx, dz)` and the cost of
+/// the move.
+fn neighbour(
+    world: &World,
+    from: &WorldPos,
+    dx: i64,
+    dz: i64,
+    settings: &NavSettings,
+) -> Option<(WorldPos, f32)> {
+    let (x, z) = (from.x + dx, from.z + dz);
+    // Highest first, a floor above blocks the positions below it.
+    for dy in (-settings.max_drop..=settings.jump_height).rev() {
+        let to = WorldPos::new(x, from.y + dy, z);
+        if !is_walkable(world, &to, settings) {
+            continue;
+        }
+        // Room to rise above the start, or to fall down the target column.
+        let head = from.y + settings.height;
+        let reachable = if dy > 0 {
+            clear(world, from.x, head, head + dy, from.z)
+        } else {
+            clear(world, x, to.y + settings.height, head, z)
+        };
+        if !reachable {
+            return None;
+        }
+        let jump = if dy > settings.step_height {
+            settings.jump_cost
+        } else {
+            0.0
+        };
+        return Some((to, 1.0 + jump));
+    }
+    None
+}
+
+/// Whether the flat ground between `from` and `to` can be walked in a
+/// straight line, through every column the line touches.
+fn straight_walk(world: &World, from: &WorldPos, to: &WorldPos, settings: &NavSettings) -> bool {
+    if from.y != to.y {
+        return false;
+    }
+    let (dx, dz) = (to.x - from.x, to.z - from.z);
+    let (nx, nz) = (dx.abs(), dz.abs());
+    let (sx, sz) = (dx.signum(), dz.signum());
+    let walkable = |x: i64, z: i64| is_walkable(world, &WorldPos::new(x, from.y, z), settings);
+    let (mut x, mut z) = (from.x, from.z);
+    let (mut ix, mut iz) = (0, 0);
+    while ix < nx || iz < nz {
+        // Compare where the line between the centers crosses the next
+        // column border along each axis.
+        match ((1 + 2 * ix) * nz).cmp(&((1 + 2 * iz) * nx)) {
+            Ordering::Less => {
+                x += sx;
+                ix += 1;
+            }
+            Ordering::Greater => {
+                z += sz;
+                iz += 1;
+            }
+            Ordering::Equal => {
+                // Through a corner, both columns beside it are touched.
+                if !walkable(x + sx, z) || !walkable(x, z + sz) {
+                    return false;
+                }
+                x += sx;
+                z += sz;
+                ix += 1;
+                iz += 1;
+            }
+        }
+        if !walkable(x, z) {
+            return false;
+        }
+    }
+    true
+}
+
+fn solid(world: &World, x: i64, y: i64, z: i64) -> bool {
+    world.get_voxel(&WorldPos::new(x, y, z)) != AIR
+}
+
+/// Whether the voxels from `low` to `high`, excluded, are air.
+fn clear(world: &World, x: i64, low: i64, high: i64, z: i64) -> bool {
+    (low..high).all(|y| !solid(world, x, y, z))
+}
+
+/// Position waiting in the open set.
+#[derive(Debug, Copy, Clone)]
+struct Open {
+    /// Cost so far plus the heuristic.
+    estimate: f32,
+    cost: f32,
+    pos: WorldPos,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    /// Reversed, the heap pops the lowest estimate first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
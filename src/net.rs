@@ -0,0 +1,388 @@
+//! Wire format of the world for multiplayer experiments.
+//!
+//! Whole chunks, voxel changes within a chunk and entity transforms are
+//! encoded as `Message`s, each sent in a frame prefixed with its length in
+//! bytes. Nothing here opens a connection: frames go over any reliable
+//! ordered byte stream, TCP or a QUIC stream alike. Blocking streams use
+//! `write_message` and `read_message`, event loops feed the bytes they
+//! receive to a `FrameDecoder`.
+//!
+//! Integers are little-endian. Chunk voxels are run-length encoded, which
+//! keeps the mostly air or solid chunks small without a compression
+//! library, their paint and damage follow.
+
+use std::io::{self, Read, Write};
+
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+
+use crate::chunk::{Chunk, Side, VoxelId, DAMAGE_STAGES, VOLUME};
+use crate::color::Color;
+use crate::coords::{ChunkCoord, Location, CHUNK_SIZE};
+
+/// Largest frame accepted, a chunk with every face painted fits.
+pub const MAX_FRAME_SIZE: usize = 8 << 20;
+
+/// Bytes of the length before each frame.
+const LENGTH_BYTES: usize = 4;
+
+const CHUNK_TAG: u8 = 1;
+const DIFF_TAG: u8 = 2;
+const TRANSFORM_TAG: u8 = 3;
+
+/// Bytes of a painted face: its voxel, its side and its color.
+const PAINT_BYTES: usize = 3 + 1 + 16;
+
+/// Voxels set in a chunk since the peer last had it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkDiff {
+    pub coord: ChunkCoord,
+
+    /// New voxels by position in the chunk.
+    pub voxels: Vec<((usize, usize, usize), VoxelId)>,
+}
+
+impl ChunkDiff {
+    /// Voxels of `new` that differ from `old`.
+    pub fn between(coord: ChunkCoord, old: &Chunk, new: &Chunk) -> Self {
+        let size = CHUNK_SIZE as usize;
+        let voxels = old
+            .voxels()
+            .iter()
+            .zip(new.voxels())
+            .enumerate()
+            .filter(|&(_, (old, new))| old != new)
+            .map(|(index, (_, &new))| {
+                let voxel = (index % size, index / size % size, index / (size * size));
+                (voxel, new)
+            })
+            .collect();
+        ChunkDiff { coord, voxels }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    /// Set the voxels of the diff in `chunk`.
+    pub fn apply(&self, chunk: &mut Chunk) {
+        for &((x, y, z), id) in &self.voxels {
+            chunk.set_voxel(x, y, z, id);
+        }
+    }
+}
+
+/// Placement of an entity, identified by the game.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EntityTransform {
+    pub entity: u64,
+    pub location: Location,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+/// What peers send each other.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Every voxel of a chunk, with its paint and damage.
+    Chunk {
+        coord: ChunkCoord,
+        chunk: Chunk,
+    },
+
+    ChunkDiff(ChunkDiff),
+
+    EntityTransform(EntityTransform),
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Frame of `message`, its length then its bytes.
+pub fn encode(message: &Message) -> Vec<u8> {
+    let mut frame = vec![0; LENGTH_BYTES];
+    match message {
+        Message::Chunk { coord, chunk } => {
+            frame.push(CHUNK_TAG);
+            write_coord(&mut frame, coord);
+            write_chunk(&mut frame, chunk);
+        }
+        Message::ChunkDiff(diff) => {
+            frame.push(DIFF_TAG);
+            write_coord(&mut frame, &diff.coord);
+            frame.extend_from_slice(&(diff.voxels.len() as u32).to_le_bytes());
+            for &((x, y, z), id) in &diff.voxels {
+                frame.extend_from_slice(&[x as u8, y as u8, z as u8]);
+                frame.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        Message::EntityTransform(transform) => {
+            frame.push(TRANSFORM_TAG);
+            frame.extend_from_slice(&transform.entity.to_le_bytes());
+            write_coord(&mut frame, &transform.location.chunk);
+            let rotation = transform.rotation.coords;
+            let floats = transform
+                .location
+                .offset
+                .iter()
+                .chain(rotation.iter())
+                .chain(transform.scale.iter());
+            for float in floats {
+                frame.extend_from_slice(&float.to_le_bytes());
+            }
+        }
+    }
+    let len = (frame.len() - LENGTH_BYTES) as u32;
+    frame[..LENGTH_BYTES].copy_from_slice(&len.to_le_bytes());
+    frame
+}
+
+/// Message of the bytes of a frame, without its length.
+///
+/// Malformed data returns an `InvalidData` error, allocations are bounded by
+/// the size of the frame whatever it claims.
+pub fn decode(payload: &[u8]) -> io::Result<Message> {
+    let mut reader = Reader(payload);
+    let message = match reader.u8()? {
+        CHUNK_TAG => {
+            let coord = reader.coord()?;
+            let chunk = read_chunk(&mut reader)?;
+            Message::Chunk { coord, chunk }
+        }
+        DIFF_TAG => {
+            let coord = reader.coord()?;
+            let count = reader.u32()? as usize;
+            if count > VOLUME {
+                return Err(invalid("too many voxels in chunk diff"));
+            }
+            let mut voxels = Vec::with_capacity(count);
+            for bytes in reader.take(count * 5)?.chunks_exact(5) {
+                let voxel = voxel_at(bytes)?;
+                voxels.push((voxel, VoxelId::from_le_bytes([bytes[3], bytes[4]])));
+            }
+            Message::ChunkDiff(ChunkDiff { coord, voxels })
+        }
+        TRANSFORM_TAG => {
+            let entity = reader.u64()?;
+            let chunk = reader.coord()?;
+            let mut floats = [0.0; 10];
+            for float in floats.iter_mut() {
+                *float = reader.f32()?;
+            }
+            let valid = floats.iter().all(|float| float.is_finite());
+            if !valid {
+                return Err(invalid("invalid entity transform"));
+            }
+            let offset = Vector3::new(floats[0], floats[1], floats[2]);
+            let rotation = Quaternion::new(floats[6], floats[3], floats[4], floats[5]);
+            let rotation = UnitQuaternion::try_new(rotation, f32::EPSILON)
+                .ok_or_else(|| invalid("invalid entity rotation"))?;
+            Message::EntityTransform(EntityTransform {
+                entity,
+                location: Location { chunk, offset },
+                rotation,
+                scale: Vector3::new(floats[7], floats[8], floats[9]),
+            })
+        }
+        _ => return Err(invalid("unknown message")),
+    };
+    if !reader.0.is_empty() {
+        return Err(invalid("trailing bytes after message"));
+    }
+    Ok(message)
+}
+
+/// Send `message` in a single write of its frame.
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> io::Result<()> {
+    writer.write_all(&encode(message))
+}
+
+/// Wait for the next frame of `reader`.
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<Message> {
+    let mut len = [0; LENGTH_BYTES];
+    reader.read_exact(&mut len)?;
+    let len = frame_len(len)?;
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    decode(&payload)
+}
+
+fn frame_len(bytes: [u8; LENGTH_BYTES]) -> io::Result<usize> {
+    let len = u32::from_le_bytes(bytes) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(invalid("frame too large"));
+    }
+    Ok(len)
+}
+
+/// Splits the bytes of a stream in messages as they arrive.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        FrameDecoder::default()
+    }
+
+    /// Append bytes received from the stream.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Bytes received and not decoded yet.
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Next complete message, `None` until all its bytes are pushed.
+    ///
+    /// After an error the stream is out of sync, the connection should be
+    /// dropped.
+    pub fn next_message(&mut self) -> io::Result<Option<Message>> {
+        if self.buffer.len() < LENGTH_BYTES {
+            return Ok(None);
+        }
+        let mut len = [0; LENGTH_BYTES];
+        len.copy_from_slice(&self.buffer[..LENGTH_BYTES]);
+        let end = LENGTH_BYTES + frame_len(len)?;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+        let message = decode(&self.buffer[LENGTH_BYTES..end]);
+        self.buffer.drain(..end);
+        message.map(Some)
+    }
+}
+
+fn write_coord(data: &mut Vec<u8>, coord: &ChunkCoord) {
+    data.extend_from_slice(&coord.x.to_le_bytes());
+    data.extend_from_slice(&coord.y.to_le_bytes());
+    data.extend_from_slice(&coord.z.to_le_bytes());
+}
+
+fn write_chunk(data: &mut Vec<u8>, chunk: &Chunk) {
+    let mut runs = Vec::new();
+    for &id in chunk.voxels() {
+        match runs.last_mut() {
+            Some((len, run)) if *run == id => *len += 1,
+            _ => runs.push((1u16, id)),
+        }
+    }
+    data.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (len, id) in runs {
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&id.to_le_bytes());
+    }
+
+    data.extend_from_slice(&(chunk.painted_faces().count() as u32).to_le_bytes());
+    for ((x, y, z), side, color) in chunk.painted_faces() {
+        data.extend_from_slice(&[x as u8, y as u8, z as u8, side.index() as u8]);
+        for channel in &color.to_array() {
+            data.extend_from_slice(&channel.to_le_bytes());
+        }
+    }
+
+    data.extend_from_slice(&(chunk.damaged_voxels().count() as u32).to_le_bytes());
+    for ((x, y, z), stage) in chunk.damaged_voxels() {
+        data.extend_from_slice(&[x as u8, y as u8, z as u8, stage]);
+    }
+}
+
+fn read_chunk(reader: &mut Reader) -> io::Result<Chunk> {
+    let count = reader.u32()? as usize;
+    if count > VOLUME {
+        return Err(invalid("too many voxel runs in chunk"));
+    }
+    let mut voxels = Vec::with_capacity(VOLUME);
+    for run in reader.take(count * 4)?.chunks_exact(4) {
+        let len = u16::from_le_bytes([run[0], run[1]]) as usize;
+        let id = VoxelId::from_le_bytes([run[2], run[3]]);
+        if len == 0 || voxels.len() + len > VOLUME {
+            return Err(invalid("invalid voxel run in chunk"));
+        }
+        voxels.extend(std::iter::repeat(id).take(len));
+    }
+    let mut chunk = Chunk::from_voxels(voxels).ok_or_else(|| invalid("wrong chunk size"))?;
+
+    let count = reader.u32()? as usize;
+    if count > VOLUME * Side::ALL.len() {
+        return Err(invalid("too many painted faces in chunk"));
+    }
+    for face in reader.take(count * PAINT_BYTES)?.chunks_exact(PAINT_BYTES) {
+        let (x, y, z) = voxel_at(face)?;
+        let side = *Side::ALL
+            .get(face[3] as usize)
+            .ok_or_else(|| invalid("invalid painted face"))?;
+        let mut channels = [0.0; 4];
+        for (channel, bytes) in channels.iter_mut().zip(face[4..].chunks_exact(4)) {
+            *channel = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        chunk.paint_face(x, y, z, side, Color::from(channels));
+    }
+
+    let count = reader.u32()? as usize;
+    if count > VOLUME {
+        return Err(invalid("too many damaged voxels in chunk"));
+    }
+    for voxel in reader.take(count * 4)?.chunks_exact(4) {
+        let (x, y, z) = voxel_at(voxel)?;
+        if voxel[3] >= DAMAGE_STAGES {
+            return Err(invalid("invalid damage stage"));
+        }
+        chunk.set_damage(x, y, z, voxel[3]);
+    }
+    Ok(chunk)
+}
+
+/// Position in a chunk of the first three `bytes`.
+fn voxel_at(bytes: &[u8]) -> io::Result<(usize, usize, usize)> {
+    let size = CHUNK_SIZE as usize;
+    let (x, y, z) = (bytes[0] as usize, bytes[1] as usize, bytes[2] as usize);
+    if x >= size || y >= size || z >= size {
+        return Err(invalid("voxel outside of the chunk"));
+    }
+    Ok((x, y, z))
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid("truncated message"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn coord(&mut self) -> io::Result<ChunkCoord> {
+        let mut coord = [0; 3];
+        for axis in coord.iter_mut() {
+            *axis = self.u64()? as i64;
+        }
+        Ok(ChunkCoord::new(coord[0], coord[1], coord[2]))
+    }
+}
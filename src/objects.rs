@@ -0,0 +1,349 @@
+//! Meshes added to the scene at run time.
+//!
+//! A `SceneObject` owns its mesh, the places it is drawn at and an optional
+//! tint. The mesh pipeline uploads the mesh of an object the first time it
+//! draws it and issues one indexed indirect draw per object, after the
+//! instances of the scene model. Objects are kept in a `HandleMap`, removing
+//! one leaves the handles of the others valid.
+//!
+//! Instances can be moved at any time. The instance data of a frame in
+//! flight is only uploaded again when an object, the floating origin or the
+//! culling frustum changed since, see `ObjectUploadKey`. The buffers hold
+//! `MAX_SCENE_OBJECTS` objects and `MAX_OBJECT_INSTANCES` instances, changes
+//! going past them are refused with an `ObjectError`.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use genmesh::generators::{Cube, IndexedPolygon, SharedVertex, SphereUv};
+use genmesh::{EmitTriangles, Triangulate, Vertex, Vertices};
+use nalgebra::{Matrix4, Point3, Transform3, Translation3};
+
+use crate::color::Color;
+use crate::coords::{ChunkCoord, FloatingOrigin, Location};
+use crate::culling::Aabb;
+use crate::handle::{HandleMap, ObjectHandle};
+use crate::material::MaterialOverride;
+use crate::meshing::MeshData;
+use crate::transform::Transform;
+use crate::vertex::{AnimFlags, InstanceData};
+
+/// Objects drawn per frame.
+pub const MAX_SCENE_OBJECTS: usize = 64;
+
+/// Instances of all the objects drawn per frame.
+pub const MAX_OBJECT_INSTANCES: usize = 1024;
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Change of the objects which wouldn't fit in the buffers of the mesh
+/// pipeline, or of an object which doesn't exist.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObjectError {
+    /// There are already `MAX_SCENE_OBJECTS` objects.
+    TooManyObjects,
+
+    /// The objects would have that many instances, more than
+    /// `MAX_OBJECT_INSTANCES`.
+    TooManyInstances(usize),
+
+    /// The handle or instance index isn't valid.
+    NotFound,
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectError::TooManyObjects => {
+                write!(f, "scene objects are limited to {}", MAX_SCENE_OBJECTS)
+            }
+            ObjectError::TooManyInstances(count) => write!(
+                f,
+                "{} object instances, more than the {} drawn per frame",
+                count, MAX_OBJECT_INSTANCES
+            ),
+            ObjectError::NotFound => write!(f, "no such object or instance"),
+        }
+    }
+}
+
+impl std::error::Error for ObjectError {}
+
+/// `Err` if `count` instances don't fit in a frame.
+fn check_instances(count: usize) -> Result<(), ObjectError> {
+    if count > MAX_OBJECT_INSTANCES {
+        Err(ObjectError::TooManyInstances(count))
+    } else {
+        Ok(())
+    }
+}
+
+/// Mesh drawn at each of its instances.
+#[derive(Debug, Clone)]
+pub struct SceneObject {
+    mesh: MeshData,
+    bounds: Aabb,
+
+    /// Position and transform of each instance, like `Instance`.
+    instances: Vec<(Location, Transform)>,
+
+    /// Color multiplied with the vertex colors of every instance.
+    tint: Option<Color>,
+
+    /// Changes every time the instances or the tint change, tells the mesh
+    /// pipeline to upload them.
+    generation: u64,
+}
+
+impl SceneObject {
+    /// Object of `mesh`, without instances.
+    pub fn new(mesh: MeshData) -> Self {
+        let bounds = Aabb::from_points(mesh.positions.iter().map(|&p| Point3::from(p)))
+            .unwrap_or_else(|| Aabb::new(Point3::origin(), Point3::origin()));
+        SceneObject {
+            mesh,
+            bounds,
+            instances: Vec::new(),
+            tint: None,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Cube of `size` centered on the origin.
+    pub fn cube(size: f32, color: Color) -> Self {
+        SceneObject::new(generated(&Cube::new(), size / 2.0, color))
+    }
+
+    /// Sphere of `radius` centered on the origin.
+    pub fn sphere(radius: f32, color: Color) -> Self {
+        SceneObject::new(generated(&SphereUv::new(24, 16), radius, color))
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.set_tint(Some(tint));
+        self
+    }
+
+    pub fn tint(&self) -> Option<Color> {
+        self.tint
+    }
+
+    pub fn set_tint(&mut self, tint: Option<Color>) {
+        self.tint = tint;
+        self.changed();
+    }
+
+    pub fn mesh(&self) -> &MeshData {
+        &self.mesh
+    }
+
+    /// Bounds of the mesh, before the transform of an instance.
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    /// Position and transform of each instance.
+    pub fn instances(&self) -> &[(Location, Transform)] {
+        &self.instances
+    }
+
+    /// Add an instance and return its index. `Scene::add_object_instance`
+    /// also checks the instances of the other objects.
+    pub fn add_instance(
+        &mut self,
+        location: Location,
+        transform: Transform,
+    ) -> Result<usize, ObjectError> {
+        check_instances(self.instances.len() + 1)?;
+        self.instances.push((location, transform));
+        self.changed();
+        Ok(self.instances.len() - 1)
+    }
+
+    /// Move the instance at `index`.
+    pub fn set_instance(
+        &mut self,
+        index: usize,
+        location: Location,
+        transform: Transform,
+    ) -> Result<(), ObjectError> {
+        let instance = self.instances.get_mut(index).ok_or(ObjectError::NotFound)?;
+        *instance = (location, transform);
+        self.changed();
+        Ok(())
+    }
+
+    /// Replace all the instances, to animate them. `Scene::set_object_instances`
+    /// also checks the instances of the other objects.
+    pub fn set_instances(
+        &mut self,
+        instances: Vec<(Location, Transform)>,
+    ) -> Result<(), ObjectError> {
+        check_instances(instances.len())?;
+        self.instances = instances;
+        self.changed();
+        Ok(())
+    }
+
+    /// Remove the instance at `index`, the later ones move down by one.
+    pub fn remove_instance(&mut self, index: usize) -> Option<(Location, Transform)> {
+        if index >= self.instances.len() {
+            return None;
+        }
+        self.changed();
+        Some(self.instances.remove(index))
+    }
+
+    fn changed(&mut self) {
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn overrides(&self) -> MaterialOverride {
+        self.tint
+            .map_or_else(MaterialOverride::default, MaterialOverride::tinted)
+    }
+}
+
+/// Flat shaded mesh of a `genmesh` shape, scaled by `scale`.
+fn generated<G, P>(shape: &G, scale: f32, color: Color) -> MeshData
+where
+    G: SharedVertex<Vertex> + IndexedPolygon<P>,
+    P: EmitTriangles<Vertex = usize>,
+{
+    let mut mesh = MeshData::new();
+    for index in shape.indexed_polygon_iter().triangulate().vertices() {
+        let vertex = shape.shared_vertex(index);
+        mesh.positions.push([
+            vertex.pos.x * scale,
+            vertex.pos.y * scale,
+            vertex.pos.z * scale,
+        ]);
+        mesh.normals
+            .push([vertex.normal.x, vertex.normal.y, vertex.normal.z]);
+        mesh.colors.push(color.to_array());
+        mesh.flags.push(AnimFlags::NONE.0);
+        mesh.indices.push(mesh.indices.len() as u32);
+    }
+    mesh
+}
+
+/// Draw of the instances of an object, `first_instance..` of the instance
+/// data given by `object_draws`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ObjectDraw {
+    pub object: ObjectHandle,
+    pub first_instance: u32,
+    pub instance_count: u32,
+
+    /// Instances of the object `visible` rejected.
+    pub culled: u32,
+}
+
+/// Instance data of `objects` relative to `origin`, replacing the contents
+/// of `instances`, and the draw of each object in handle order.
+///
+/// Every object gets a draw, even without an instance drawn, so the draws
+/// only change when objects are added or removed. Instances whose bounds
+/// `visible` rejects are skipped. `objects` must be within the limits
+/// `check_new_object` and `check_instance_count` enforce, as the objects of
+/// a `Scene` are, the buffers have no room for more.
+pub fn object_draws<F>(
+    objects: &HandleMap<SceneObject>,
+    origin: &FloatingOrigin,
+    visible: F,
+    instances: &mut Vec<InstanceData>,
+) -> Vec<ObjectDraw>
+where
+    F: Fn(&Aabb) -> bool,
+{
+    debug_assert!(objects.len() <= MAX_SCENE_OBJECTS, "too many objects");
+    debug_assert!(
+        instance_count(objects) <= MAX_OBJECT_INSTANCES,
+        "too many object instances"
+    );
+    instances.clear();
+    let mut draws = Vec::new();
+    for (handle, object) in objects.iter() {
+        let first = instances.len();
+        let mut culled = 0;
+        let overrides = object.overrides();
+        for (location, transform) in &object.instances {
+            let translation = Translation3::from(origin.to_render(location));
+            let model = Transform3::from_matrix_unchecked(
+                translation.to_homogeneous() * transform.to_matrix(),
+            );
+            if visible(&object.bounds.transform(model.matrix())) {
+                instances.push(InstanceData::new(&model, &overrides));
+            } else {
+                culled += 1;
+            }
+        }
+        draws.push(ObjectDraw {
+            object: handle,
+            first_instance: first as u32,
+            instance_count: (instances.len() - first) as u32,
+            culled,
+        });
+    }
+    draws
+}
+
+/// Instances of all the `objects`.
+pub fn instance_count(objects: &HandleMap<SceneObject>) -> usize {
+    objects
+        .iter()
+        .map(|(_, object)| object.instances.len())
+        .sum()
+}
+
+/// `Err` if `object` doesn't fit in the buffers along with `objects`.
+pub fn check_new_object(
+    objects: &HandleMap<SceneObject>,
+    object: &SceneObject,
+) -> Result<(), ObjectError> {
+    if objects.len() >= MAX_SCENE_OBJECTS {
+        return Err(ObjectError::TooManyObjects);
+    }
+    check_instances(instance_count(objects) + object.instances.len())
+}
+
+/// `Err` if `object` can't have `count` instances along with the other
+/// `objects`.
+pub fn check_instance_count(
+    objects: &HandleMap<SceneObject>,
+    object: ObjectHandle,
+    count: usize,
+) -> Result<(), ObjectError> {
+    let current = objects.get(object).ok_or(ObjectError::NotFound)?;
+    check_instances(instance_count(objects) - current.instances.len() + count)
+}
+
+/// What the instance data of the objects is computed from. The mesh
+/// pipeline keeps the key of the last upload to each frame in flight and
+/// uploads again only once it differs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectUploadKey {
+    /// Generations of the objects, in handle order.
+    generations: Vec<u64>,
+    origin: ChunkCoord,
+    view_proj: Matrix4<f32>,
+}
+
+impl ObjectUploadKey {
+    /// Key of `objects` placed relative to `origin` and culled with
+    /// `view_proj`.
+    pub fn new(
+        objects: &HandleMap<SceneObject>,
+        origin: &FloatingOrigin,
+        view_proj: &Matrix4<f32>,
+    ) -> Self {
+        ObjectUploadKey {
+            generations: objects
+                .iter()
+                .map(|(_, object)| object.generation)
+                .collect(),
+            origin: origin.origin(),
+            view_proj: *view_proj,
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Outlines around the silhouettes of instances, for stylized rendering.
+//!
+//! Inverted hull: the model is drawn again with its vertices pushed out
+//! along their normals by the outline width, keeping only the faces turned
+//! away from the camera. The object covers the inside of the hull and the
+//! rim left around it is the outline. Faces are flat so the hull splits
+//! open at sharp edges, thin outlines hide the gaps.
+//!
+//! Only instances whose `MaterialOverride::outline` is set are outlined,
+//! among those the mesh pass draws. With GPU culling the instances drawn
+//! aren't known on the CPU, the outlines are culled against the frustum
+//! like the compute pre-pass does.
+
+use nalgebra::{Transform3, Vector3};
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    Layout, PrepareResult, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{GraphContext, NodeBuffer, NodeImage};
+use rendy::hal;
+use rendy::mesh::{AsVertex, Mesh, VertexFormat};
+use rendy::resource::{BufferInfo, DescriptorSetLayout, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::color::OutputEncoding;
+use crate::culling::{Aabb, Frustum};
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::mapped::MappedBuffer;
+use crate::material::Outline;
+use crate::mesh::{model_bounds, model_mesh, MAX_OBJECTS};
+use crate::scene::Scene;
+use crate::vertex::VoxelVertex;
+
+lazy_static::lazy_static! {
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../outline.frag"));
+
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../outline.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/outline.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/outline.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+}
+
+/// Per-instance data of the outlined models.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct OutlineInstance {
+    model: [[f32; 4]; 4],
+
+    /// Outline color, the width in `w`.
+    outline: [f32; 4],
+}
+
+impl OutlineInstance {
+    fn new(model: &Transform3<f32>, outline: &Outline) -> Self {
+        let color = outline.color;
+        OutlineInstance {
+            model: (*model.matrix()).into(),
+            outline: [color.r, color.g, color.b, outline.width],
+        }
+    }
+}
+
+impl AsVertex for OutlineInstance {
+    fn vertex() -> VertexFormat {
+        VertexFormat::new((
+            (hal::format::Format::Rgba32Sfloat, "model"),
+            (hal::format::Format::Rgba32Sfloat, "model"),
+            (hal::format::Format::Rgba32Sfloat, "model"),
+            (hal::format::Format::Rgba32Sfloat, "model"),
+            (hal::format::Format::Rgba32Sfloat, "outline"),
+        ))
+    }
+}
+
+/// Size of the push constants, in `u32`s.
+const CONSTANTS: usize = 24;
+
+const INSTANCES_SIZE: u64 = (MAX_OBJECTS * std::mem::size_of::<OutlineInstance>()) as u64;
+
+#[derive(Debug, Default)]
+pub(crate) struct OutlineDesc {
+    /// See `mesh::PipelineDesc::gpu_culling`.
+    pub gpu_culling: bool,
+
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
+
+pub(crate) struct Outlines<B: hal::Backend> {
+    gpu_culling: bool,
+    drawn: Vec<usize>,
+    buffer: MappedBuffer<B>,
+    mesh: Mesh<B>,
+    bounds: Aabb,
+    transforms: Vec<Transform3<f32>>,
+    instances: Vec<OutlineInstance>,
+    constants: [u32; CONSTANTS],
+}
+
+impl<B: hal::Backend> std::fmt::Debug for Outlines<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Outlines({} drawn)", self.instances.len())
+    }
+}
+
+fn instances_offset(index: usize) -> u64 {
+    INSTANCES_SIZE * index as u64
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for OutlineDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Outlines<B>;
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<hal::pso::Element<hal::format::Format>>,
+        hal::pso::ElemStride,
+        hal::pso::VertexInputRate,
+    )> {
+        vec![
+            VoxelVertex::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex),
+            OutlineInstance::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Instance(1)),
+        ]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        Some(hal::pso::DepthStencilDesc {
+            depth: Some(hal::pso::DepthTest {
+                fun: hal::pso::Comparison::Less,
+                write: true,
+            }),
+            depth_bounds: false,
+            stencil: None,
+        })
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        let constants = SpecConstants::new().with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: Vec::new(),
+            push_constants: vec![(
+                hal::pso::ShaderStageFlags::VERTEX | hal::pso::ShaderStageFlags::FRAGMENT,
+                0..(CONSTANTS * 4) as u32,
+            )],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        _aux: &Scene,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        let frames = ctx.frames_in_flight as usize;
+        let buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: instances_offset(frames),
+                usage: hal::buffer::Usage::VERTEX,
+            },
+        );
+        Ok(Outlines {
+            gpu_culling: self.gpu_culling,
+            drawn: Vec::with_capacity(MAX_OBJECTS),
+            buffer,
+            mesh: model_mesh(queue, factory),
+            bounds: model_bounds(),
+            transforms: Vec::new(),
+            instances: Vec::with_capacity(MAX_OBJECTS),
+            constants: [0; CONSTANTS],
+        })
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Outlines<B>
+where
+    B: hal::Backend,
+{
+    type Desc = OutlineDesc;
+
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        // Filled by the mesh pipeline, prepared first in the same subpass.
+        if !self.gpu_culling {
+            aux.swap_drawn(&mut self.drawn);
+        }
+        let frustum = Frustum::from_matrix(&aux.culling_view_proj());
+        self.transforms.clear();
+        self.transforms.extend(aux.model_transforms());
+        let count = if self.gpu_culling {
+            self.transforms.len()
+        } else {
+            self.drawn.len()
+        };
+        self.instances.clear();
+        for k in 0..count {
+            let i = if self.gpu_culling { k } else { self.drawn[k] };
+            let outline = match aux.instances[i].overrides.outline {
+                Some(outline) if outline.width > 0.0 => outline,
+                _ => continue,
+            };
+            let model = &self.transforms[i];
+            if self.gpu_culling {
+                // Pushed out of the bounds by the width.
+                let aabb = self.bounds.transform(model.matrix());
+                let margin = Vector3::repeat(outline.width);
+                if !frustum.contains_aabb(&(aabb.min - margin), &(aabb.max + margin)) {
+                    continue;
+                }
+            }
+            self.instances.push(OutlineInstance::new(model, &outline));
+            if self.instances.len() == MAX_OBJECTS {
+                break;
+            }
+        }
+        if !self.instances.is_empty() {
+            unsafe {
+                self.buffer
+                    .write(factory, instances_offset(index), &self.instances);
+            }
+        }
+
+        let weather = aux.weather.params();
+        let eye = aux.render_view().translation.vector;
+        let fog = weather.fog_color;
+        let floats = aux.view_proj().iter().cloned().chain(vec![
+            eye.x,
+            eye.y,
+            eye.z,
+            weather.fog_density,
+            fog.r,
+            fog.g,
+            fog.b,
+            fog.a,
+        ]);
+        for (constant, value) in self.constants.iter_mut().zip(floats) {
+            *constant = value.to_bits();
+        }
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        index: usize,
+        _aux: &Scene,
+    ) {
+        if self.instances.is_empty() {
+            return;
+        }
+        unsafe {
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::VERTEX | hal::pso::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.constants,
+            );
+            self.mesh
+                .bind(0, &[VoxelVertex::vertex()], &mut encoder)
+                .unwrap();
+            encoder.bind_vertex_buffers(
+                1,
+                std::iter::once((self.buffer.raw(), instances_offset(index))),
+            );
+            encoder.draw_indexed(0..self.mesh.len(), 0, 0..self.instances.len() as u32);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
@@ -0,0 +1,309 @@
+//! Plugin API.
+//!
+//! A `Plugin` adds blocks, textures, world generation passes, render passes
+//! and console commands to a `Registry`. The `PluginHost` registers every
+//! plugin once at startup, in the order they are given, so later plugins can
+//! look up what earlier ones registered.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use rendy::graph::{GraphBuilder, ImageId};
+use rendy::hal;
+
+use crate::block_textures::BlockTextures;
+use crate::chunk::{Chunk, VoxelId, AIR};
+use crate::color::Color;
+use crate::console::{CommandContext, Console};
+use crate::coords::ChunkCoord;
+use crate::meshing::VoxelStyle;
+use crate::scene::Scene;
+use crate::vertex::AnimFlags;
+
+/// Error returned when a registration is refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterError {
+    /// A block or texture of that name is already registered.
+    Duplicate(String),
+
+    /// Every voxel id is taken.
+    OutOfIds,
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterError::Duplicate(name) => write!(f, "`{}` is already registered", name),
+            RegisterError::OutOfIds => write!(f, "no voxel id left"),
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
+
+/// Properties of a block type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDesc {
+    /// Unique name, prefixed by the plugin name by convention.
+    pub name: String,
+
+    /// Vertex color of the block faces.
+    pub color: Color,
+
+    /// Whether the block hides the faces of its neighbours.
+    pub solid: bool,
+
+    /// Name of a registered texture, drawn over the color once loaded in
+    /// `Scene::block_textures` under that name.
+    pub texture: Option<String>,
+
+    /// Vertex animation of the block faces.
+    pub animation: AnimFlags,
+}
+
+/// Block types by voxel id, id `AIR` is reserved.
+#[derive(Debug)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockDesc>,
+    ids: HashMap<String, VoxelId>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        let air = BlockDesc {
+            name: "air".to_owned(),
+            color: Color::TRANSPARENT,
+            solid: false,
+            texture: None,
+            animation: AnimFlags::NONE,
+        };
+        let mut ids = HashMap::new();
+        ids.insert(air.name.clone(), AIR);
+        BlockRegistry {
+            blocks: vec![air],
+            ids,
+        }
+    }
+
+    /// Register a block type and return its voxel id.
+    pub fn register(&mut self, desc: BlockDesc) -> Result<VoxelId, RegisterError> {
+        if self.ids.contains_key(&desc.name) {
+            return Err(RegisterError::Duplicate(desc.name));
+        }
+        if self.blocks.len() > VoxelId::max_value() as usize {
+            return Err(RegisterError::OutOfIds);
+        }
+        let id = self.blocks.len() as VoxelId;
+        self.ids.insert(desc.name.clone(), id);
+        self.blocks.push(desc);
+        Ok(id)
+    }
+
+    pub fn id(&self, name: &str) -> Option<VoxelId> {
+        self.ids.get(name).cloned()
+    }
+
+    pub fn get(&self, id: VoxelId) -> Option<&BlockDesc> {
+        self.blocks.get(id as usize)
+    }
+
+    /// Appearance of the block `id` in the chunk meshes, with its layer of
+    /// `textures`. Unknown blocks are drawn as air, blocks whose texture
+    /// isn't loaded untextured.
+    pub fn style(&self, id: VoxelId, textures: &BlockTextures) -> VoxelStyle {
+        let desc = self.get(id).unwrap_or(&self.blocks[AIR as usize]);
+        VoxelStyle {
+            color: desc.color,
+            flags: desc.animation.0,
+            texture: desc.texture.as_ref().and_then(|name| textures.layer(name)),
+        }
+    }
+
+    /// Number of block types, air included.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        BlockRegistry::new()
+    }
+}
+
+/// Texture files by index, in registration order.
+#[derive(Debug, Default)]
+pub struct TextureRegistry {
+    paths: Vec<PathBuf>,
+    ids: HashMap<String, u32>,
+}
+
+impl TextureRegistry {
+    pub fn new() -> Self {
+        TextureRegistry::default()
+    }
+
+    /// Register the texture at `path` and return its index.
+    pub fn register(&mut self, name: &str, path: PathBuf) -> Result<u32, RegisterError> {
+        if self.ids.contains_key(name) {
+            return Err(RegisterError::Duplicate(name.to_owned()));
+        }
+        let id = self.paths.len() as u32;
+        self.ids.insert(name.to_owned(), id);
+        self.paths.push(path);
+        Ok(id)
+    }
+
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).cloned()
+    }
+
+    pub fn path(&self, id: u32) -> Option<&PathBuf> {
+        self.paths.get(id as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+/// Fills or edits a newly created chunk, passes run in registration order.
+pub type WorldGenPass = Box<dyn Fn(ChunkCoord, &mut Chunk, &BlockRegistry) + Send + Sync>;
+
+/// Adds nodes to the render graph after the scene pass, given the depth image.
+pub type RenderPassHook<B> = Box<dyn Fn(&mut GraphBuilder<B, Scene>, ImageId)>;
+
+struct PluginCommand {
+    name: String,
+    help: String,
+    handler: Box<dyn Fn(&mut CommandContext, &[&str]) -> Result<String, String>>,
+}
+
+/// Everything registered by the plugins.
+pub struct Registry<B: hal::Backend> {
+    pub blocks: BlockRegistry,
+    pub textures: TextureRegistry,
+    worldgen: Vec<WorldGenPass>,
+    render_passes: Vec<RenderPassHook<B>>,
+    commands: Vec<PluginCommand>,
+}
+
+impl<B: hal::Backend> Registry<B> {
+    pub fn new() -> Self {
+        Registry {
+            blocks: BlockRegistry::new(),
+            textures: TextureRegistry::new(),
+            worldgen: Vec::new(),
+            render_passes: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn register_block(&mut self, desc: BlockDesc) -> Result<VoxelId, RegisterError> {
+        self.blocks.register(desc)
+    }
+
+    pub fn register_texture(&mut self, name: &str, path: PathBuf) -> Result<u32, RegisterError> {
+        self.textures.register(name, path)
+    }
+
+    pub fn register_worldgen<F>(&mut self, pass: F)
+    where
+        F: Fn(ChunkCoord, &mut Chunk, &BlockRegistry) + Send + Sync + 'static,
+    {
+        self.worldgen.push(Box::new(pass));
+    }
+
+    pub fn register_render_pass<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut GraphBuilder<B, Scene>, ImageId) + 'static,
+    {
+        self.render_passes.push(Box::new(hook));
+    }
+
+    /// See `Console::register_command`.
+    pub fn register_command<F>(&mut self, name: &str, help: &str, handler: F)
+    where
+        F: Fn(&mut CommandContext, &[&str]) -> Result<String, String> + 'static,
+    {
+        self.commands.push(PluginCommand {
+            name: name.to_owned(),
+            help: help.to_owned(),
+            handler: Box::new(handler),
+        });
+    }
+
+    /// Run the world generation passes on a new chunk.
+    pub fn generate(&self, coord: ChunkCoord, chunk: &mut Chunk) {
+        for pass in &self.worldgen {
+            pass(coord, chunk, &self.blocks);
+        }
+    }
+
+    pub fn render_passes(&self) -> &[RenderPassHook<B>] {
+        &self.render_passes
+    }
+}
+
+impl<B: hal::Backend> Default for Registry<B> {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+/// Extension of the engine loaded at startup.
+pub trait Plugin<B: hal::Backend> {
+    /// Name used in logs and errors.
+    fn name(&self) -> &str;
+
+    /// Add the plugin content to `registry`.
+    fn register(&self, registry: &mut Registry<B>) -> Result<(), RegisterError>;
+}
+
+/// Loaded plugins and their registrations.
+pub struct PluginHost<B: hal::Backend> {
+    plugins: Vec<Box<dyn Plugin<B>>>,
+    pub registry: Registry<B>,
+}
+
+impl<B: hal::Backend> PluginHost<B> {
+    /// Register `plugins` in order, stopping at the first refused registration.
+    pub fn load(plugins: Vec<Box<dyn Plugin<B>>>) -> Result<Self, (String, RegisterError)> {
+        let mut registry = Registry::new();
+        for plugin in &plugins {
+            plugin
+                .register(&mut registry)
+                .map_err(|err| (plugin.name().to_owned(), err))?;
+            info!("Loaded plugin {}.", plugin.name());
+        }
+        Ok(PluginHost { plugins, registry })
+    }
+
+    /// Move the registered commands to `console`.
+    ///
+    /// Plugin commands replace the built-in commands of the same name.
+    pub fn install_commands(&mut self, console: &mut Console) {
+        for command in self.registry.commands.drain(..) {
+            console.register_command(&command.name, &command.help, command.handler);
+        }
+    }
+
+    pub fn plugin_names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|plugin| plugin.name())
+    }
+}
+
+impl<B: hal::Backend> fmt::Debug for PluginHost<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.plugin_names()).finish()
+    }
+}
@@ -0,0 +1,85 @@
+//! Free lists of large CPU allocations reused across chunks.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// Thread safe free list, keeps at most `capacity` idle values.
+#[derive(Debug)]
+pub struct Pool<T> {
+    free: Mutex<Vec<T>>,
+    capacity: usize,
+}
+
+impl<T> Pool<T> {
+    pub fn new(capacity: usize) -> Self {
+        Pool {
+            free: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Reuse an idle value or create one with `create`.
+    ///
+    /// Reused values are returned as they were given back, callers reset them.
+    pub fn take_or_else<F: FnOnce() -> T>(&self, create: F) -> T {
+        let value = self.free.lock().unwrap().pop();
+        value.unwrap_or_else(create)
+    }
+
+    /// Give a value back, it is dropped when the pool is full.
+    pub fn give(&self, value: T) {
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.capacity {
+            free.push(value);
+        }
+    }
+
+    /// Like `take_or_else` but gives the value back when the guard is dropped.
+    pub fn get<F: FnOnce() -> T>(&self, create: F) -> Pooled<'_, T> {
+        Pooled {
+            pool: self,
+            value: Some(self.take_or_else(create)),
+        }
+    }
+
+    /// Number of idle values.
+    pub fn idle(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+}
+
+/// Value borrowed from a `Pool`.
+#[derive(Debug)]
+pub struct Pooled<'a, T> {
+    pool: &'a Pool<T>,
+    value: Option<T>,
+}
+
+impl<T> Pooled<'_, T> {
+    /// Keep the value instead of giving it back.
+    pub fn detach(mut self) -> T {
+        self.value.take().unwrap()
+    }
+}
+
+impl<T> Deref for Pooled<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Pooled<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for Pooled<'_, T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            self.pool.give(value);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Full screen overlay drawing rain, snow and the overcast haze.
+
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    Layout, PrepareResult, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{GraphContext, NodeBuffer, NodeImage};
+use rendy::hal;
+use rendy::resource::{DescriptorSetLayout, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::color::OutputEncoding;
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::scene::Scene;
+use crate::weather::WeatherParams;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../precipitation.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/precipitation.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../precipitation.frag"));
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/precipitation.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+}
+
+/// Size of the push constants, in `u32`s.
+const CONSTANTS: usize = 4;
+
+#[derive(Debug, Default)]
+pub struct PrecipitationDesc {
+    /// Encoding of the colors written, see `OutputEncoding`.
+    pub output: OutputEncoding,
+}
+
+#[derive(Debug)]
+pub struct Precipitation {
+    constants: [u32; CONSTANTS],
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for PrecipitationDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Precipitation;
+
+    fn colors(&self) -> Vec<hal::pso::ColorBlendDesc> {
+        vec![hal::pso::ColorBlendDesc {
+            mask: hal::pso::ColorMask::ALL,
+            blend: Some(hal::pso::BlendState::ALPHA),
+        }]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        None
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        let constants = SpecConstants::new().with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: Vec::new(),
+            push_constants: vec![(
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0..(CONSTANTS * 4) as u32,
+            )],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &Scene,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        Ok(Precipitation {
+            constants: [0; CONSTANTS],
+        })
+    }
+}
+
+impl Precipitation {
+    fn visible(params: &WeatherParams) -> bool {
+        params.rain > 0.0 || params.snow > 0.0 || params.overcast > 0.0
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Precipitation
+where
+    B: hal::Backend,
+{
+    type Desc = PrecipitationDesc;
+
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        let params = aux.weather.params();
+        self.constants = [
+            aux.time.to_bits(),
+            params.rain.to_bits(),
+            params.snow.to_bits(),
+            params.overcast.to_bits(),
+        ];
+        // Constants change every frame.
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        aux: &Scene,
+    ) {
+        if !Precipitation::visible(&aux.weather.params()) {
+            return;
+        }
+        unsafe {
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.constants,
+            );
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
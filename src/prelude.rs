@@ -0,0 +1,9 @@
+//! Types most applications need, `use avenir::prelude::*;`.
+
+pub use crate::camera::Camera;
+pub use crate::color::Color;
+pub use crate::renderer::{Renderer, RendererBuilder};
+pub use crate::scene::Scene;
+pub use crate::transform::Transform;
+pub use crate::world::World;
+pub use crate::Inputs;
@@ -0,0 +1,283 @@
+//! CPU timings of named sections of the renderer.
+//!
+//! While recording, `end_frame` keeps the time of each frame with the
+//! sections timed and the counters set during it. `export` writes them to
+//! a CSV or JSON file, one row or object per frame, to compare benchmark
+//! runs across commits.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Accumulated durations of one section.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Timing {
+    /// Duration of the last run.
+    pub last: Duration,
+
+    /// Sum of all the runs.
+    pub total: Duration,
+
+    /// Number of runs.
+    pub count: u32,
+}
+
+impl Timing {
+    pub fn average(&self) -> Duration {
+        if self.count == 0 {
+            Duration::default()
+        } else {
+            self.total / self.count
+        }
+    }
+}
+
+/// File format of `Profiler::export`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// One row per frame, a column per section and counter.
+    Csv,
+
+    /// An array of one object per frame.
+    Json,
+}
+
+impl Format {
+    /// Format of a file by its extension, CSV unless it's `.json`.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Csv,
+        }
+    }
+}
+
+/// Statistics of one recorded frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameRecord {
+    /// Time since the previous frame.
+    pub frame_time: Duration,
+
+    /// Total duration of each section timed during the frame, by name.
+    pub timings: Vec<(&'static str, Duration)>,
+
+    /// Counters at the end of the frame, by name.
+    pub counters: Vec<(&'static str, u64)>,
+}
+
+/// Collects timings and counters, usable from graph nodes which only get
+/// `&Scene`.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    timings: Mutex<HashMap<&'static str, Timing>>,
+    counters: Mutex<HashMap<&'static str, u64>>,
+
+    /// Durations recorded since the last `end_frame`.
+    frame: Mutex<HashMap<&'static str, Duration>>,
+
+    /// Frames recorded, `None` when not recording.
+    frames: Mutex<Option<Vec<FrameRecord>>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Time the section until the returned guard is dropped.
+    pub fn scope(&self, name: &'static str) -> Scope<'_> {
+        Scope {
+            profiler: self,
+            name,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record(&self, name: &'static str, duration: Duration) {
+        let mut timings = self.timings.lock().unwrap();
+        let timing = timings.entry(name).or_default();
+        timing.last = duration;
+        timing.total += duration;
+        timing.count += 1;
+        *self.frame.lock().unwrap().entry(name).or_default() += duration;
+    }
+
+    pub fn timing(&self, name: &str) -> Option<Timing> {
+        self.timings.lock().unwrap().get(name).copied()
+    }
+
+    /// All the timings sorted by name.
+    pub fn timings(&self) -> Vec<(&'static str, Timing)> {
+        let mut timings: Vec<_> = self
+            .timings
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, timing)| (*name, *timing))
+            .collect();
+        timings.sort_by_key(|(name, _)| *name);
+        timings
+    }
+
+    /// Set a counter to its value for the last frame.
+    pub fn set_counter(&self, name: &'static str, value: u64) {
+        self.counters.lock().unwrap().insert(name, value);
+    }
+
+    pub fn counter(&self, name: &str) -> Option<u64> {
+        self.counters.lock().unwrap().get(name).copied()
+    }
+
+    /// All the counters sorted by name.
+    pub fn counters(&self) -> Vec<(&'static str, u64)> {
+        let mut counters: Vec<_> = self
+            .counters
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, value)| (*name, *value))
+            .collect();
+        counters.sort_by_key(|(name, _)| *name);
+        counters
+    }
+
+    pub fn reset(&self) {
+        self.timings.lock().unwrap().clear();
+        self.counters.lock().unwrap().clear();
+        self.frame.lock().unwrap().clear();
+    }
+
+    /// Keep the statistics of each frame from now on, dropping those
+    /// recorded before.
+    pub fn start_recording(&self) {
+        *self.frames.lock().unwrap() = Some(Vec::new());
+    }
+
+    /// Stop recording and return the frames recorded.
+    pub fn stop_recording(&self) -> Vec<FrameRecord> {
+        self.frames.lock().unwrap().take().unwrap_or_default()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.frames.lock().unwrap().is_some()
+    }
+
+    /// Number of frames recorded so far.
+    pub fn recorded_frames(&self) -> usize {
+        self.frames.lock().unwrap().as_ref().map_or(0, Vec::len)
+    }
+
+    /// Close a frame which took `frame_time`, recording it if recording.
+    pub fn end_frame(&self, frame_time: Duration) {
+        let mut timings: Vec<_> = self.frame.lock().unwrap().drain().collect();
+        if let Some(frames) = self.frames.lock().unwrap().as_mut() {
+            timings.sort_by_key(|(name, _)| *name);
+            frames.push(FrameRecord {
+                frame_time,
+                timings,
+                counters: self.counters(),
+            });
+        }
+    }
+
+    /// Write the frames recorded so far to the file at `path`.
+    pub fn export(&self, path: &Path, format: Format) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out, format)?;
+        out.flush()
+    }
+
+    /// Write the frames recorded so far to `out`.
+    ///
+    /// Durations are in milliseconds. A section not timed during a frame
+    /// is left empty in CSV and missing in JSON.
+    pub fn write<W: Write>(&self, out: &mut W, format: Format) -> io::Result<()> {
+        let frames = self.frames.lock().unwrap();
+        let frames = frames.as_ref().map_or(&[][..], |frames| &frames[..]);
+        match format {
+            Format::Csv => write_csv(out, frames),
+            Format::Json => write_json(out, frames),
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn write_csv<W: Write>(out: &mut W, frames: &[FrameRecord]) -> io::Result<()> {
+    let sections: BTreeSet<_> = frames
+        .iter()
+        .flat_map(|frame| frame.timings.iter().map(|(name, _)| *name))
+        .collect();
+    let counters: BTreeSet<_> = frames
+        .iter()
+        .flat_map(|frame| frame.counters.iter().map(|(name, _)| *name))
+        .collect();
+    write!(out, "frame,frame_ms")?;
+    for name in &sections {
+        write!(out, ",{}_ms", name)?;
+    }
+    for name in &counters {
+        write!(out, ",{}", name)?;
+    }
+    writeln!(out)?;
+    for (index, frame) in frames.iter().enumerate() {
+        write!(out, "{},{:.3}", index, millis(frame.frame_time))?;
+        for name in &sections {
+            match frame.timings.iter().find(|(section, _)| section == name) {
+                Some((_, duration)) => write!(out, ",{:.3}", millis(*duration))?,
+                None => write!(out, ",")?,
+            }
+        }
+        for name in &counters {
+            match frame.counters.iter().find(|(counter, _)| counter == name) {
+                Some((_, value)) => write!(out, ",{}", value)?,
+                None => write!(out, ",")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Section and counter names are plain identifiers, written unescaped.
+fn write_json<W: Write>(out: &mut W, frames: &[FrameRecord]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (index, frame) in frames.iter().enumerate() {
+        write!(
+            out,
+            "  {{\"frame\": {}, \"frame_ms\": {:.3}, \"timings_ms\": {{",
+            index,
+            millis(frame.frame_time)
+        )?;
+        for (i, (name, duration)) in frame.timings.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(out, "{}\"{}\": {:.3}", separator, name, millis(*duration))?;
+        }
+        write!(out, "}}, \"counters\": {{")?;
+        for (i, (name, value)) in frame.counters.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(out, "{}\"{}\": {}", separator, name, value)?;
+        }
+        let separator = if index + 1 == frames.len() { "" } else { "," };
+        writeln!(out, "}}}}{}", separator)?;
+    }
+    writeln!(out, "]")
+}
+
+/// Guard returned by `Profiler::scope`.
+pub struct Scope<'a> {
+    profiler: &'a Profiler,
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        self.profiler.record(self.name, self.start.elapsed());
+    }
+}
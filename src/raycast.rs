@@ -0,0 +1,255 @@
+//! Voxel raycasting by grid traversal.
+//!
+//! The ray steps from voxel to voxel along its direction (Amanatides and
+//! Woo), so no voxel it crosses is skipped however thin the crossing. It only
+//! needs a predicate telling which voxels are solid, `World::raycast` queries
+//! the loaded chunks. `spherecast` sweeps a sphere instead of a point, for
+//! probes which must keep clear of the terrain.
+//!
+//! A ray running exactly along a voxel boundary tests the voxels on the
+//! positive side of the boundary, and a ray through an edge or a corner
+//! steps through one of the voxels touching it.
+
+use nalgebra::Vector3;
+
+use crate::coords::{Location, WorldPos};
+
+/// Longest ray traversed, in voxels. Longer rays are cut there, so a ray
+/// missing everything in an unbounded world still ends.
+pub const MAX_RAY_DISTANCE: f32 = 65536.0;
+
+/// Ray with its own length, for casting many at once.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Location,
+    pub direction: Vector3<f32>,
+    pub max_distance: f32,
+}
+
+impl Ray {
+    pub fn new(origin: Location, direction: Vector3<f32>, max_distance: f32) -> Self {
+        Ray {
+            origin,
+            direction,
+            max_distance,
+        }
+    }
+
+    /// Ray from `from` ending at `to`, for line of sight checks.
+    pub fn between(from: &Location, to: &Location) -> Self {
+        let direction = to.relative_to(&from.chunk) - from.offset;
+        Ray::new(*from, direction, direction.norm())
+    }
+}
+
+/// Solid voxel hit by a ray.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RaycastHit {
+    pub pos: WorldPos,
+
+    /// Normal of the face the ray entered through, zero when the ray
+    /// started inside the voxel.
+    pub normal: Vector3<i64>,
+
+    /// Distance from the ray origin to the entry point.
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    /// Voxel in front of the hit face, where a block would be placed.
+    pub fn adjacent(&self) -> Option<WorldPos> {
+        self.pos
+            .checked_offset(self.normal.x, self.normal.y, self.normal.z)
+    }
+}
+
+/// First voxel for which `solid` returns true along the ray, within
+/// `max_distance`.
+///
+/// `direction` doesn't need to be normalized, a zero direction only tests
+/// the voxel of `origin`. A ray starting inside a solid voxel hits it at
+/// distance zero. Non finite inputs and negative distances hit nothing.
+pub fn raycast<F>(
+    origin: &Location,
+    direction: &Vector3<f32>,
+    max_distance: f32,
+    solid: F,
+) -> Option<RaycastHit>
+where
+    F: Fn(&WorldPos) -> bool,
+{
+    let finite = |v: &Vector3<f32>| v.iter().all(|x| x.is_finite());
+    let valid = finite(&origin.offset) && finite(direction) && max_distance >= 0.0;
+    if !valid {
+        return None;
+    }
+    let max_distance = max_distance.min(MAX_RAY_DISTANCE);
+    // Offsets far outside their chunk lose precision, bring them back in.
+    let origin = Location::new(origin.chunk, origin.offset);
+
+    let mut pos = origin.voxel();
+    if solid(&pos) {
+        return Some(RaycastHit {
+            pos,
+            normal: Vector3::zeros(),
+            distance: 0.0,
+        });
+    }
+    let direction = direction.try_normalize(std::f32::EPSILON)?;
+
+    // Position of the origin inside its voxel, in `0.0..1.0`.
+    let fraction = origin.offset.map(|x| x - x.floor());
+    let mut step = [0i64; 3];
+    let mut next = [std::f32::INFINITY; 3];
+    let mut delta = [std::f32::INFINITY; 3];
+    for axis in 0..3 {
+        let d = direction[axis];
+        if d > 0.0 {
+            step[axis] = 1;
+            delta[axis] = 1.0 / d;
+            next[axis] = (1.0 - fraction[axis]) / d;
+        } else if d < 0.0 {
+            step[axis] = -1;
+            delta[axis] = -1.0 / d;
+            next[axis] = fraction[axis] / -d;
+        }
+    }
+
+    loop {
+        let axis = if next[0] < next[1] {
+            if next[0] < next[2] {
+                0
+            } else {
+                2
+            }
+        } else if next[1] < next[2] {
+            1
+        } else {
+            2
+        };
+        let distance = next[axis];
+        if distance > max_distance {
+            return None;
+        }
+        let mut offset = [0i64; 3];
+        offset[axis] = step[axis];
+        pos = pos.checked_offset(offset[0], offset[1], offset[2])?;
+        next[axis] += delta[axis];
+        if solid(&pos) {
+            let mut normal = Vector3::zeros();
+            normal[axis] = -step[axis];
+            return Some(RaycastHit {
+                pos,
+                normal,
+                distance,
+            });
+        }
+    }
+}
+
+/// First voxel for which `solid` returns true touched by a sphere of
+/// `radius` swept along the ray, within `max_distance`.
+///
+/// `distance` of the hit is how far the sphere center moved before
+/// touching. Voxels are tested as boxes grown by `radius`, so the sphere
+/// stops slightly early near voxel edges and corners. Every voxel of the
+/// swept bounds is tested, meant for short sweeps such as camera probes.
+/// Non finite inputs, negative radii and distances, and zero directions
+/// hit nothing.
+pub fn spherecast<F>(
+    origin: &Location,
+    direction: &Vector3<f32>,
+    radius: f32,
+    max_distance: f32,
+    solid: F,
+) -> Option<RaycastHit>
+where
+    F: Fn(&WorldPos) -> bool,
+{
+    let finite = |v: &Vector3<f32>| v.iter().all(|x| x.is_finite());
+    let valid = finite(&origin.offset)
+        && finite(direction)
+        && radius.is_finite()
+        && radius >= 0.0
+        && max_distance.is_finite()
+        && max_distance >= 0.0;
+    if !valid {
+        return None;
+    }
+    let direction = direction.try_normalize(std::f32::EPSILON)?;
+    // Offsets far outside their chunk lose precision, bring them back in.
+    let origin = Location::new(origin.chunk, origin.offset);
+    let end = origin.offset + direction * max_distance;
+    let reach = |a: f32, b: f32| {
+        let min = (a.min(b) - radius).floor() as i64;
+        let max = (a.max(b) + radius).floor() as i64;
+        min..=max
+    };
+    let base = origin.chunk.min_voxel();
+    let mut best: Option<RaycastHit> = None;
+    for z in reach(origin.offset.z, end.z) {
+        for y in reach(origin.offset.y, end.y) {
+            for x in reach(origin.offset.x, end.x) {
+                let pos = match base.checked_offset(x, y, z) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
+                let min = Vector3::new(x as f32, y as f32, z as f32).add_scalar(-radius);
+                let max = min.add_scalar(1.0 + 2.0 * radius);
+                let entry = match sweep_box(&origin.offset, &direction, &min, &max) {
+                    Some(entry) if entry.0 <= max_distance => entry,
+                    _ => continue,
+                };
+                if best.map_or(false, |best| best.distance <= entry.0) || !solid(&pos) {
+                    continue;
+                }
+                best = Some(RaycastHit {
+                    pos,
+                    normal: entry.1,
+                    distance: entry.0,
+                });
+            }
+        }
+    }
+    best
+}
+
+/// Distance along the ray where it enters the box and the normal of the
+/// entered face, zero distance and normal when it starts inside.
+fn sweep_box(
+    origin: &Vector3<f32>,
+    direction: &Vector3<f32>,
+    min: &Vector3<f32>,
+    max: &Vector3<f32>,
+) -> Option<(f32, Vector3<i64>)> {
+    let mut enter = 0.0f32;
+    let mut exit = std::f32::INFINITY;
+    let mut normal = Vector3::zeros();
+    for axis in 0..3 {
+        let d = direction[axis];
+        if d == 0.0 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let (near, far, side) = if d > 0.0 {
+            (min[axis], max[axis], -1)
+        } else {
+            (max[axis], min[axis], 1)
+        };
+        let t_near = (near - origin[axis]) / d;
+        let t_far = (far - origin[axis]) / d;
+        if t_near > enter {
+            enter = t_near;
+            normal = Vector3::zeros();
+            normal[axis] = side;
+        }
+        exit = exit.min(t_far);
+    }
+    if enter <= exit {
+        Some((enter, normal))
+    } else {
+        None
+    }
+}
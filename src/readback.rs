@@ -0,0 +1,285 @@
+//! GPU to host copies delivered frames later.
+//!
+//! A `Readback` owns a host buffer split in one slot per frame in flight.
+//! A node records a copy into the slot of the frame it builds, requests the
+//! result when it runs that frame, and collects the previous result of the
+//! slot at the start of each run. The graph waits for the fence of a frame
+//! before running its index again, so by then the copy has landed and no
+//! feature needs fences of its own. Results arrive `frames_in_flight` frames
+//! after they were requested.
+
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use rendy::command::{Encoder, Supports, Transfer};
+use rendy::factory::Factory;
+use rendy::hal;
+use rendy::memory::Download;
+use rendy::resource::{Buffer, BufferInfo, Escape};
+
+/// Result of a request, filled once its frame completed.
+///
+/// Cloned handles share the result, the first `take` gets it.
+#[derive(Debug, Clone, Default)]
+pub struct ReadbackHandle {
+    state: Arc<Mutex<HandleState>>,
+}
+
+#[derive(Debug)]
+enum HandleState {
+    Pending,
+    Ready(Vec<u8>),
+    Taken,
+
+    /// The readback was dropped or rebuilt before the frame completed.
+    Cancelled,
+}
+
+impl Default for HandleState {
+    fn default() -> Self {
+        HandleState::Pending
+    }
+}
+
+impl ReadbackHandle {
+    pub fn is_ready(&self) -> bool {
+        match *self.state.lock().unwrap() {
+            HandleState::Ready(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        match *self.state.lock().unwrap() {
+            HandleState::Cancelled => true,
+            _ => false,
+        }
+    }
+
+    /// The result if it arrived and wasn't taken yet.
+    pub fn take(&self) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        match std::mem::replace(&mut *state, HandleState::Taken) {
+            HandleState::Ready(data) => Some(data),
+            other => {
+                *state = other;
+                None
+            }
+        }
+    }
+
+    fn set(&self, state: HandleState) {
+        *self.state.lock().unwrap() = state;
+    }
+}
+
+type Callback = Box<dyn FnOnce(&[u8]) + Send>;
+
+/// Receiver of a requested result.
+enum Request {
+    Callback(Callback),
+    Handle(ReadbackHandle),
+}
+
+struct Slot {
+    /// Bytes of the slot to deliver and who to deliver them to.
+    pending: Option<(u64, Request)>,
+}
+
+/// Host buffer receiving copies from the GPU, one slot per frame in flight.
+pub struct Readback<B: hal::Backend> {
+    buffer: Escape<Buffer<B>>,
+    slot_size: u64,
+    slots: Vec<Slot>,
+}
+
+impl<B: hal::Backend> Readback<B> {
+    /// Readback of up to `slot_size` bytes per frame, over `frames` frames
+    /// in flight.
+    pub fn new(factory: &Factory<B>, slot_size: u64, frames: usize) -> Self {
+        let buffer = factory
+            .create_buffer(
+                BufferInfo {
+                    size: slot_size * frames as u64,
+                    usage: hal::buffer::Usage::TRANSFER_DST,
+                },
+                Download,
+            )
+            .unwrap();
+        Readback {
+            buffer,
+            slot_size,
+            slots: (0..frames).map(|_| Slot { pending: None }).collect(),
+        }
+    }
+
+    pub fn raw(&self) -> &B::Buffer {
+        self.buffer.raw()
+    }
+
+    pub fn slot_size(&self) -> u64 {
+        self.slot_size
+    }
+
+    /// Slot of the frame of `index`, as given by `Frames::next`.
+    pub fn slot(&self, index: u64) -> usize {
+        index as usize % self.slots.len()
+    }
+
+    /// Range of `slot` in the buffer.
+    pub fn slot_range(&self, slot: usize) -> Range<u64> {
+        let start = slot as u64 * self.slot_size;
+        start..start + self.slot_size
+    }
+
+    /// Record a copy of `size` bytes of `src` at `offset` into `slot`.
+    ///
+    /// # Safety
+    ///
+    /// `src` must be readable by transfers at this point of the commands.
+    pub unsafe fn record_buffer_copy<C, L>(
+        &self,
+        encoder: &mut Encoder<'_, B, C, L>,
+        src: &B::Buffer,
+        offset: u64,
+        size: u64,
+        slot: usize,
+    ) where
+        C: Supports<Transfer>,
+    {
+        assert!(size <= self.slot_size);
+        encoder.copy_buffer(
+            src,
+            self.buffer.raw(),
+            Some(hal::command::BufferCopy {
+                src: offset,
+                dst: self.slot_range(slot).start,
+                size,
+            }),
+        );
+    }
+
+    /// Record a copy of a region of the first layer of a color image into
+    /// `slot`, rows tightly packed.
+    ///
+    /// # Safety
+    ///
+    /// `image` must be in `layout` and readable by transfers at this point
+    /// of the commands.
+    pub unsafe fn record_image_copy<C, L>(
+        &self,
+        encoder: &mut Encoder<'_, B, C, L>,
+        image: &B::Image,
+        layout: hal::image::Layout,
+        region: hal::image::Extent,
+        texel_size: u64,
+        slot: usize,
+    ) where
+        C: Supports<Transfer>,
+    {
+        let size = region.width as u64 * region.height as u64 * region.depth as u64 * texel_size;
+        assert!(size <= self.slot_size);
+        encoder.copy_image_to_buffer(
+            image,
+            layout,
+            self.buffer.raw(),
+            Some(hal::command::BufferImageCopy {
+                buffer_offset: self.slot_range(slot).start,
+                buffer_width: region.width,
+                buffer_height: region.height,
+                image_layers: hal::image::SubresourceLayers {
+                    aspects: hal::format::Aspects::COLOR,
+                    level: 0,
+                    layers: 0..1,
+                },
+                image_offset: hal::image::Offset::ZERO,
+                image_extent: region,
+            }),
+        );
+    }
+
+    /// Deliver `size` bytes of `slot` to `callback` once the frame now
+    /// running completed.
+    ///
+    /// An earlier request on the slot still pending is cancelled.
+    pub fn request<F>(&mut self, slot: usize, size: u64, callback: F)
+    where
+        F: FnOnce(&[u8]) + Send + 'static,
+    {
+        self.arm(slot, size, Request::Callback(Box::new(callback)));
+    }
+
+    /// Like `request`, with the result polled through a handle.
+    pub fn request_handle(&mut self, slot: usize, size: u64) -> ReadbackHandle {
+        let handle = ReadbackHandle::default();
+        self.arm(slot, size, Request::Handle(handle.clone()));
+        handle
+    }
+
+    fn arm(&mut self, slot: usize, size: u64, request: Request) {
+        let size = size.min(self.slot_size);
+        if let Some((_, Request::Handle(old))) = self.slots[slot].pending.replace((size, request)) {
+            old.set(HandleState::Cancelled);
+        }
+    }
+
+    /// Deliver the result of the previous frame of `slot`.
+    ///
+    /// Call it when running a frame, before recording or requesting anything
+    /// for its slot: the graph waited for the frame which last used it.
+    pub fn complete(&mut self, factory: &Factory<B>, slot: usize) {
+        let (size, request) = match self.slots[slot].pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let start = self.slot_range(slot).start;
+        let range = start..start + size;
+        let data = unsafe {
+            self.buffer
+                .map(factory.device(), range.clone())
+                .and_then(|mut mapped| {
+                    mapped
+                        .read::<u8>(factory.device(), range)
+                        .map(<[u8]>::to_vec)
+                })
+        };
+        match (data, request) {
+            (Ok(data), Request::Callback(callback)) => callback(&data),
+            (Ok(data), Request::Handle(handle)) => handle.set(HandleState::Ready(data)),
+            (Err(err), request) => {
+                error!("Readback of {} bytes failed: {:?}.", size, err);
+                if let Request::Handle(handle) = request {
+                    handle.set(HandleState::Cancelled);
+                }
+            }
+        }
+    }
+}
+
+impl<B: hal::Backend> Drop for Readback<B> {
+    fn drop(&mut self) {
+        for slot in &mut self.slots {
+            if let Some((_, Request::Handle(handle))) = slot.pending.take() {
+                handle.set(HandleState::Cancelled);
+            }
+        }
+    }
+}
+
+impl<B: hal::Backend> fmt::Debug for Readback<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pending = self
+            .slots
+            .iter()
+            .filter(|slot| slot.pending.is_some())
+            .count();
+        write!(
+            f,
+            "Readback({} slots of {} bytes, {} pending)",
+            self.slots.len(),
+            self.slot_size,
+            pending
+        )
+    }
+}
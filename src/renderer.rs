@@ -0,0 +1,723 @@
+//! Entry point of the crate, owns the device, the render graph and the scene.
+
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime};
+
+use nalgebra::{Matrix4, Point3};
+use rendy::{
+    command::Families,
+    factory::Factory,
+    graph::{Graph, GraphBuildError},
+    hal::{self, adapter::PhysicalDevice},
+    init::winit::{
+        event::{Event, WindowEvent},
+        event_loop::ControlFlow,
+        window::Window,
+    },
+    wsi::Surface,
+};
+
+use crate::camera::Camera;
+use crate::config::{ConfigError, RedrawMode, RendererConfig};
+use crate::coords::ChunkCoord;
+use crate::events::{RebuildCause, RebuildEvent, RendererEvent};
+use crate::graph;
+use crate::jobs::{JobCategory, JobConfig, JobSystem};
+use crate::plugin::RenderPassHook;
+use crate::scene::Scene;
+use crate::splash::{SplashProgress, SplashScreen, SplashSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::storage::RegionSaver;
+
+/// Renders `scene` to a window surface.
+///
+/// Fields drop in declaration order, the factory goes last as everything
+/// else was created from it.
+pub struct Renderer<B: hal::Backend> {
+    graph: Option<Graph<B, Scene>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    saver: Option<RegionSaver>,
+    shut_down: bool,
+    jobs: Arc<JobSystem>,
+    families: Families<B>,
+    factory: Factory<B>,
+    pub scene: Scene,
+
+    /// Settings the graph was built with, see `RendererConfig::apply`.
+    pub config: RendererConfig,
+
+    events: Vec<RendererEvent>,
+
+    /// See `visible_chunks`.
+    visible_chunks: Vec<ChunkCoord>,
+
+    /// See `request_redraw`.
+    redraw_requested: bool,
+
+    /// Frames keep being drawn until then, for the crossfade of a rebuild.
+    redraw_until: Option<Instant>,
+
+    /// Camera of the last frame, a frame is due once it moved.
+    drawn_view_proj: Option<Matrix4<f32>>,
+
+    /// When the last frame was drawn, for `Profiler::end_frame`.
+    last_frame: Option<Instant>,
+
+    /// Window size the graph was built at, `None` if it wasn't.
+    built_size: Option<(u32, u32)>,
+}
+
+impl<B: hal::Backend> Renderer<B> {
+    /// Draw a frame.
+    pub fn render(&mut self) {
+        self.factory.maintain(&mut self.families);
+        if let Some(ref mut graph) = self.graph {
+            graph.run(&mut self.factory, &mut self.families, &self.scene);
+            self.scene.swap_visible_chunks(&mut self.visible_chunks);
+            self.scene.end_frame();
+            self.end_profiler_frame();
+        }
+        self.redraw_requested = false;
+        self.drawn_view_proj = Some(self.scene.view_proj());
+    }
+
+    /// Set the counters of the frame and close it in the profiler.
+    fn end_profiler_frame(&mut self) {
+        let profiler = &self.scene.profiler;
+        profiler.set_counter("chunks.visible", self.visible_chunks.len() as u64);
+        let memory = self.factory.memory_utilization();
+        let (used, effective) = memory.heaps.iter().fold((0, 0), |(used, effective), heap| {
+            (
+                used + heap.utilization.used,
+                effective + heap.utilization.effective,
+            )
+        });
+        profiler.set_counter("memory.used", used);
+        profiler.set_counter("memory.effective", effective);
+        let now = Instant::now();
+        let frame_time = self
+            .last_frame
+            .map_or(Duration::default(), |last| now - last);
+        self.last_frame = Some(now);
+        profiler.end_frame(frame_time);
+    }
+
+    /// Draw a frame if `needs_redraw`, returns whether one was drawn.
+    pub fn render_if_needed(&mut self) -> bool {
+        let needed = self.needs_redraw();
+        if needed {
+            self.render();
+        }
+        needed
+    }
+
+    /// Draw the next frame with `RedrawMode::OnDemand`, once the scene or
+    /// the UI drawn over it changed.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    /// Whether a frame is due: always with `RedrawMode::Continuous`,
+    /// otherwise once requested, the camera moved or during a crossfade.
+    pub fn needs_redraw(&self) -> bool {
+        if self.config.redraw == RedrawMode::Continuous || self.redraw_requested {
+            return true;
+        }
+        let fading = self
+            .redraw_until
+            .map_or(false, |until| Instant::now() < until);
+        fading || self.drawn_view_proj != Some(self.scene.view_proj())
+    }
+
+    /// How the event loop should wait for the next frame: polling with
+    /// `RedrawMode::Continuous`, otherwise waiting for events unless a
+    /// frame is due.
+    pub fn control_flow(&self) -> ControlFlow {
+        if self.needs_redraw() {
+            ControlFlow::Poll
+        } else {
+            ControlFlow::Wait
+        }
+    }
+
+    /// Chunks holding instances in view in the last frame, sorted, for
+    /// games to update what the player sees first.
+    ///
+    /// Instances are in view once past the frustum and distance culling,
+    /// drawn as billboards or dropped by the draw budget included. With
+    /// GPU culling the chunks are tested whole against the frustum.
+    pub fn visible_chunks(&self) -> &[ChunkCoord] {
+        &self.visible_chunks
+    }
+
+    /// See `Scene::freeze_culling`.
+    pub fn freeze_culling(&mut self, frozen: bool) {
+        self.scene.freeze_culling(frozen);
+        info!("Culling {}.", if frozen { "frozen" } else { "unfrozen" });
+    }
+
+    /// Events since the last call, oldest first.
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, RendererEvent> {
+        self.events.drain(..)
+    }
+
+    pub fn factory(&self) -> &Factory<B> {
+        &self.factory
+    }
+
+    /// Saver flushed by `shutdown`, replacing the previous one.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_saver(&mut self, saver: RegionSaver) -> Option<RegionSaver> {
+        self.saver.replace(saver)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn saver(&self) -> Option<&RegionSaver> {
+        self.saver.as_ref()
+    }
+
+    /// Job system of the background work of the renderer, see
+    /// `RendererBuilder::with_jobs`.
+    pub fn jobs(&self) -> &Arc<JobSystem> {
+        &self.jobs
+    }
+
+    /// Whether `shutdown` was called, nothing is drawn afterwards.
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    /// Whether the graph was disposed by `suspend` and not rebuilt yet.
+    pub fn is_suspended(&self) -> bool {
+        self.graph.is_none() && !self.shut_down
+    }
+
+    /// Dispose the graph and the surface it presents to.
+    ///
+    /// Android and iOS destroy the window surface when the application goes
+    /// to the background, call it on `Event::Suspended`. The scene and the
+    /// config are kept, nothing is drawn until `resume`.
+    pub fn suspend(&mut self) {
+        if let Some(graph) = self.graph.take() {
+            self.factory.maintain(&mut self.families);
+            if let Err(err) = self.factory.wait_idle() {
+                error!("Failed to wait for the device: {:?}.", err);
+            }
+            graph.dispose(&mut self.factory, &self.scene);
+            self.scene.collect_released(u64::MAX);
+        }
+        self.built_size = None;
+    }
+
+    /// Rebuild the graph on a new surface of `window` after `suspend`.
+    pub fn resume(
+        &mut self,
+        window: &Window,
+        render_passes: &[RenderPassHook<B>],
+    ) -> Result<(), BuildError> {
+        self.rebuild(window, render_passes, RebuildCause::Resume)
+    }
+
+    /// Dispose the graph and build it again on a new surface of `window`.
+    ///
+    /// Emits a `RendererEvent::GraphRebuilt` with `cause`, whether the build
+    /// succeeded or not. With `RendererConfig::crossfade` the last frame of
+    /// the old graph fades out over the first frames of the new one.
+    pub fn rebuild(
+        &mut self,
+        window: &Window,
+        render_passes: &[RenderPassHook<B>],
+        cause: RebuildCause,
+    ) -> Result<(), BuildError> {
+        if self.shut_down {
+            return Ok(());
+        }
+        let started = SystemTime::now();
+        let start = Instant::now();
+        if self.config.crossfade > 0.0 && self.graph.is_some() {
+            // Drawn once more to keep the frame for the crossfade, it is
+            // delivered when the graph is disposed.
+            self.scene.retained().request_capture();
+            self.render();
+        }
+        let result = {
+            let _scope = self.scene.profiler.scope("graph.rebuild");
+            self.suspend();
+            self.factory
+                .create_surface(window)
+                .map_err(BuildError::Surface)
+                .and_then(|surface| {
+                    graph::build(
+                        &mut self.families,
+                        window,
+                        &mut self.factory,
+                        surface,
+                        &self.scene,
+                        &self.config,
+                        &self.jobs,
+                        render_passes,
+                    )
+                    .map_err(BuildError::from)
+                })
+        };
+        self.record_rebuild(RebuildEvent {
+            cause,
+            started,
+            duration: start.elapsed(),
+            resources: graph::resources(&self.config, !render_passes.is_empty()),
+            succeeded: result.is_ok(),
+        });
+        self.graph = Some(result?);
+        self.built_size = Some(window_size(window));
+        self.request_redraw();
+        self.redraw_until =
+            Some(Instant::now() + Duration::from_secs_f32(self.config.crossfade.max(0.0)));
+        Ok(())
+    }
+
+    /// Update the renderer with a window event, for applications running
+    /// their own event loop. Call `render` when a frame is wanted.
+    ///
+    /// Resizing `window` rebuilds the graph at the new size, unless it
+    /// already has it, and updates the camera aspect ratio. `Suspended` and
+    /// `Resumed` call `suspend` and `resume`. Other events and other
+    /// windows are ignored.
+    pub fn handle_event<T>(
+        &mut self,
+        event: &Event<T>,
+        window: &Window,
+        render_passes: &[RenderPassHook<B>],
+    ) -> Result<(), BuildError> {
+        match *event {
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::Resized(size),
+            } if window_id == window.id() => {
+                let size = (size.width, size.height);
+                match resize_action(size, self.built_size, self.is_suspended()) {
+                    ResizeAction::Ignore => Ok(()),
+                    ResizeAction::UpdateAspect => {
+                        self.scene.update_aspect(size.0, size.1);
+                        Ok(())
+                    }
+                    ResizeAction::Rebuild => {
+                        self.scene.update_aspect(size.0, size.1);
+                        self.rebuild(window, render_passes, RebuildCause::Resize)
+                    }
+                }
+            }
+            Event::Suspended => {
+                self.suspend();
+                Ok(())
+            }
+            Event::Resumed if self.is_suspended() => self.resume(window, render_passes),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replace the config, rebuilding the graph if a changed setting needs it.
+    ///
+    /// The config is degraded and checked against the device like by
+    /// `RendererBuilder::build`, a `ConfigError` leaves the renderer as it
+    /// was. If the rebuild fails the previous config is put back and the
+    /// graph rebuilt with it.
+    pub fn set_config(
+        &mut self,
+        config: RendererConfig,
+        window: &Window,
+        render_passes: &[RenderPassHook<B>],
+    ) -> Result<(), BuildError> {
+        let (limits, compute) = device_support(&self.factory, &self.families);
+        let znear = self.scene.camera.near();
+        let previous = self
+            .config
+            .replace_checked(config, &limits, compute, znear)?;
+        self.config.apply(&mut self.scene);
+        if self.config.needs_rebuild(&previous) {
+            let cause = RebuildCause::of_config_change(&previous, &self.config);
+            if let Err(err) = self.rebuild(window, render_passes, cause) {
+                self.config = previous;
+                self.config.apply(&mut self.scene);
+                if let Err(err) = self.rebuild(window, render_passes, RebuildCause::ConfigChange) {
+                    error!("Failed to rebuild with the previous config: {}.", err);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn record_rebuild(&mut self, event: RebuildEvent) {
+        if event.succeeded {
+            info!("{}.", event);
+        } else {
+            error!("{}.", event);
+        }
+        self.events.push(RendererEvent::GraphRebuilt(event));
+    }
+
+    /// Release the renderer resources in order.
+    ///
+    /// Pending region saves are written first, then queued uploads are
+    /// flushed and the device is waited idle so no frame is in flight when
+    /// the graph is disposed. Errors are logged, teardown always completes.
+    ///
+    /// Called on drop, call it explicitly where drop doesn't run such as
+    /// at the end of `EventLoop::run`. Later calls do nothing.
+    pub fn shutdown(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(saver) = self.saver.take() {
+            for err in saver.flush() {
+                error!("Region save failed on shutdown: {}.", err);
+            }
+        }
+        if self.shut_down {
+            return;
+        }
+        self.suspend();
+        self.shut_down = true;
+        info!("Renderer shut down.");
+    }
+}
+
+impl<B: hal::Backend> Drop for Renderer<B> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl<B: hal::Backend> std::fmt::Debug for Renderer<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Renderer({:?})", self.config)
+    }
+}
+
+/// Error returned by `RendererBuilder::build`.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The configuration was rejected before creating any resource.
+    Config(ConfigError),
+
+    Graph(GraphBuildError),
+
+    /// A new surface couldn't be created by `Renderer::resume`.
+    Surface(hal::window::InitError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Config(err) => write!(f, "{}", err),
+            BuildError::Graph(err) => write!(f, "render graph build failed: {:?}", err),
+            BuildError::Surface(err) => write!(f, "surface creation failed: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<ConfigError> for BuildError {
+    fn from(err: ConfigError) -> Self {
+        BuildError::Config(err)
+    }
+}
+
+impl From<GraphBuildError> for BuildError {
+    fn from(err: GraphBuildError) -> Self {
+        BuildError::Graph(err)
+    }
+}
+
+/// Settings of a `Renderer` before its graph is built.
+#[derive(Default)]
+pub struct RendererBuilder {
+    scene: Option<Scene>,
+    config: RendererConfig,
+    jobs: Option<Arc<JobSystem>>,
+}
+
+impl RendererBuilder {
+    pub fn new() -> Self {
+        RendererBuilder::default()
+    }
+
+    /// Scene drawn by the renderer, by default an empty scene looking at the origin.
+    pub fn with_scene(mut self, scene: Scene) -> Self {
+        self.scene = Some(scene);
+        self
+    }
+
+    pub fn with_config(mut self, config: RendererConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Job system the renderer runs its background work on, by default one
+    /// of its own with `JobConfig::default`.
+    pub fn with_jobs(mut self, jobs: Arc<JobSystem>) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Build the render graph drawing to `surface`.
+    ///
+    /// The configuration is checked against the device limits first, every
+    /// problem found is listed in the returned `ConfigError`.
+    pub fn build<B: hal::Backend>(
+        self,
+        factory: Factory<B>,
+        families: Families<B>,
+        surface: Surface<B>,
+        window: &Window,
+        render_passes: &[RenderPassHook<B>],
+    ) -> Result<Renderer<B>, BuildError> {
+        let jobs = self.jobs();
+        let (scene, config) = self.check(&factory, &families, window)?;
+        finish(
+            factory,
+            families,
+            surface,
+            window,
+            scene,
+            config,
+            jobs,
+            render_passes,
+        )
+    }
+
+    /// Start building the renderer, drawing a loading screen to `surface`
+    /// in the meantime.
+    ///
+    /// The configuration is checked right away. An IO job then compiles the
+    /// shaders, loads the assets of the graph and runs `load` on the scene,
+    /// which can move the progress bar. Call
+    /// `PendingRenderer::poll` on each frame until the renderer is ready,
+    /// the graph is built on the calling thread by the last poll.
+    pub fn build_async<B, F>(
+        self,
+        mut factory: Factory<B>,
+        mut families: Families<B>,
+        surface: Surface<B>,
+        window: &Window,
+        splash: SplashSettings,
+        load: F,
+    ) -> Result<PendingRenderer<B>, BuildError>
+    where
+        B: hal::Backend,
+        F: FnOnce(Scene, &SplashProgress) -> Scene + Send + 'static,
+    {
+        let jobs = self.jobs();
+        let (scene, config) = self.check(&factory, &families, window)?;
+        let splash = SplashScreen::build(&mut factory, &mut families, surface, window, splash)?;
+        let progress = splash.progress();
+        let (sender, receiver) = channel();
+        jobs.spawn(JobCategory::Io, move || {
+            graph::precompile();
+            let _ = sender.send(load(scene, &progress));
+        });
+        Ok(PendingRenderer {
+            splash: Some(splash),
+            device: Some((factory, families)),
+            config,
+            jobs,
+            receiver,
+        })
+    }
+
+    /// Job system given to `with_jobs` or a new one.
+    fn jobs(&self) -> Arc<JobSystem> {
+        self.jobs
+            .clone()
+            .unwrap_or_else(|| Arc::new(JobSystem::new(&JobConfig::default())))
+    }
+
+    /// Scene and config checked against the device.
+    fn check<B: hal::Backend>(
+        self,
+        factory: &Factory<B>,
+        families: &Families<B>,
+        window: &Window,
+    ) -> Result<(Scene, RendererConfig), BuildError> {
+        let scene = self.scene.unwrap_or_else(|| {
+            let size = window.inner_size();
+            Scene::new(Camera::look_at(
+                10.0,
+                Point3::new(0.0, 0.0, -10.0),
+                Point3::origin(),
+                size.width as f32 / size.height as f32,
+            ))
+        });
+        let (limits, compute) = device_support(factory, families);
+        let config = self.config.check(&limits, compute, scene.camera.near())?;
+        Ok((scene, config))
+    }
+}
+
+/// Limits of the device and whether one of its queue families supports
+/// compute, what `RendererConfig::check` checks against.
+fn device_support<B: hal::Backend>(
+    factory: &Factory<B>,
+    families: &Families<B>,
+) -> (hal::Limits, bool) {
+    let compute = families
+        .as_slice()
+        .iter()
+        .any(|family| family.capability().supports_compute());
+    (factory.physical().limits(), compute)
+}
+
+/// What `Renderer::handle_event` does when the window is resized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResizeAction {
+    /// Minimized or suspended, there is nothing to draw to.
+    Ignore,
+
+    /// The graph already has the size, such as when the window is created.
+    UpdateAspect,
+
+    /// Update the camera aspect ratio and rebuild the graph at the size.
+    Rebuild,
+}
+
+/// How to handle a resize of the window to `size`, for a graph built at
+/// `built_size`.
+pub fn resize_action(
+    size: (u32, u32),
+    built_size: Option<(u32, u32)>,
+    suspended: bool,
+) -> ResizeAction {
+    if size.0 == 0 || size.1 == 0 || suspended {
+        ResizeAction::Ignore
+    } else if built_size == Some(size) {
+        ResizeAction::UpdateAspect
+    } else {
+        ResizeAction::Rebuild
+    }
+}
+
+fn window_size(window: &Window) -> (u32, u32) {
+    let size = window.inner_size();
+    (size.width, size.height)
+}
+
+/// Build the graph of a checked config and the renderer owning it.
+fn finish<B: hal::Backend>(
+    mut factory: Factory<B>,
+    mut families: Families<B>,
+    surface: Surface<B>,
+    window: &Window,
+    mut scene: Scene,
+    config: RendererConfig,
+    jobs: Arc<JobSystem>,
+    render_passes: &[RenderPassHook<B>],
+) -> Result<Renderer<B>, BuildError> {
+    config.apply(&mut scene);
+    let started = SystemTime::now();
+    let start = Instant::now();
+    let graph = graph::build(
+        &mut families,
+        window,
+        &mut factory,
+        surface,
+        &scene,
+        &config,
+        &jobs,
+        render_passes,
+    )?;
+    let mut renderer = Renderer {
+        graph: Some(graph),
+        #[cfg(not(target_arch = "wasm32"))]
+        saver: None,
+        shut_down: false,
+        jobs,
+        families,
+        factory,
+        scene,
+        config,
+        events: Vec::new(),
+        visible_chunks: Vec::new(),
+        redraw_requested: true,
+        redraw_until: None,
+        drawn_view_proj: None,
+        last_frame: None,
+        built_size: Some(window_size(window)),
+    };
+    let resources = graph::resources(&renderer.config, !render_passes.is_empty());
+    renderer.record_rebuild(RebuildEvent {
+        cause: RebuildCause::Initial,
+        started,
+        duration: start.elapsed(),
+        resources,
+        succeeded: true,
+    });
+    Ok(renderer)
+}
+
+/// Renderer being built by `RendererBuilder::build_async`.
+pub struct PendingRenderer<B: hal::Backend> {
+    splash: Option<SplashScreen<B>>,
+    device: Option<(Factory<B>, Families<B>)>,
+    config: RendererConfig,
+    jobs: Arc<JobSystem>,
+    receiver: Receiver<Scene>,
+}
+
+impl<B: hal::Backend> PendingRenderer<B> {
+    /// Draw the loading screen while the worker runs, then dispose it and
+    /// build the graph on a new surface of `window`.
+    ///
+    /// Panics if called again after returning `Poll::Ready`, or if the init
+    /// job panicked, its panic is logged by the job system.
+    pub fn poll(
+        &mut self,
+        window: &Window,
+        render_passes: &[RenderPassHook<B>],
+    ) -> Poll<Result<Renderer<B>, BuildError>> {
+        let scene = match self.receiver.try_recv() {
+            Ok(scene) => scene,
+            Err(TryRecvError::Empty) => {
+                let (factory, families) = self.device.as_mut().expect("Renderer already built");
+                if let Some(ref mut splash) = self.splash {
+                    splash.render(factory, families);
+                }
+                return Poll::Pending;
+            }
+            Err(TryRecvError::Disconnected) => {
+                assert!(self.device.is_some(), "Renderer already built");
+                panic!("The renderer init job panicked");
+            }
+        };
+        let (mut factory, families) = self.device.take().expect("Renderer already built");
+        if let Some(splash) = self.splash.take() {
+            splash.dispose(&mut factory);
+        }
+        let result = factory
+            .create_surface(window)
+            .map_err(BuildError::Surface)
+            .and_then(|surface| {
+                finish(
+                    factory,
+                    families,
+                    surface,
+                    window,
+                    scene,
+                    self.config.clone(),
+                    self.jobs.clone(),
+                    render_passes,
+                )
+            });
+        Poll::Ready(result)
+    }
+}
+
+impl<B: hal::Backend> std::fmt::Debug for PendingRenderer<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.splash {
+            Some(ref splash) => write!(f, "PendingRenderer({:?})", splash),
+            None => write!(f, "PendingRenderer(built)"),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Rewinding the last seconds of simulation, for debugging.
+//!
+//! A `RewindBuffer` keeps one entry per tick over a window of seconds,
+//! dropping the oldest as new ticks come. An entry holds the camera at the
+//! start of the tick and the chunks its edits may change, copied before
+//! they are applied. Rewinding takes the newest entries out, the chunks
+//! they saved are then put back in the world with `Rewound::restore` and
+//! the camera with `CameraSnapshot::restore`, so a rendering or lighting
+//! bug can be stepped into again.
+//!
+//! Only the chunks an edit may reach are copied: the chunk of a voxel edit,
+//! the distance field chunks of a brush and the chunks around an explosion.
+
+use std::collections::VecDeque;
+
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+
+use crate::camera::{Camera, CameraError};
+use crate::chunk::Chunk;
+use crate::coords::{ChunkCoord, FloatingOrigin, Location};
+use crate::explosion::MAX_EXPLOSION_RADIUS;
+use crate::sdf::SdfChunk;
+use crate::timestep::WorldEdit;
+use crate::world::World;
+
+/// Camera state kept by the buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraSnapshot {
+    pub location: Location,
+    pub rotation: UnitQuaternion<f32>,
+
+    /// Vertical field of view, in radians.
+    pub fov: f32,
+}
+
+impl CameraSnapshot {
+    /// State of `camera`, whose view is relative to `origin`.
+    pub fn of(camera: &Camera, origin: &FloatingOrigin) -> Self {
+        CameraSnapshot {
+            location: Location::new(origin.origin(), camera.view.translation.vector),
+            rotation: camera.view.rotation,
+            fov: camera.fov(),
+        }
+    }
+
+    /// Put `camera` back in this state, call `Scene::update_origin` after.
+    /// Fails without changing `camera` if the field of view is invalid.
+    pub fn restore(&self, camera: &mut Camera, origin: &FloatingOrigin) -> Result<(), CameraError> {
+        camera.set_fov(self.fov)?;
+        let translation = Translation3::from(origin.to_render(&self.location));
+        camera.view = Isometry3::from_parts(translation, self.rotation);
+        Ok(())
+    }
+}
+
+/// Contents of a tick before its edits.
+#[derive(Debug, Clone, Default)]
+struct Entry {
+    tick: u64,
+    camera: Option<CameraSnapshot>,
+    chunks: Vec<(ChunkCoord, Option<Chunk>)>,
+    sdf: Vec<(ChunkCoord, Option<SdfChunk>)>,
+}
+
+/// Ticks undone by `RewindBuffer::rewind`.
+#[derive(Debug, Clone)]
+pub struct Rewound {
+    /// Oldest tick undone, the state is that of its start.
+    pub tick: u64,
+
+    /// Camera at the start of `tick`, if it was recorded.
+    pub camera: Option<CameraSnapshot>,
+
+    /// Saved chunks, newest tick first so the oldest contents are put
+    /// back last.
+    chunks: Vec<(ChunkCoord, Option<Chunk>)>,
+    sdf: Vec<(ChunkCoord, Option<SdfChunk>)>,
+}
+
+impl Rewound {
+    /// Put the saved chunks back in `world`. Returns them sorted, to be
+    /// meshed again.
+    pub fn restore(self, world: &mut World) -> Vec<ChunkCoord> {
+        let mut changed = Vec::new();
+        for (coord, chunk) in self.chunks {
+            match chunk {
+                Some(chunk) => {
+                    world.insert_chunk(coord, chunk);
+                }
+                None => {
+                    world.remove_chunk(&coord);
+                }
+            }
+            changed.push(coord);
+        }
+        for (coord, chunk) in self.sdf {
+            match chunk {
+                Some(chunk) => {
+                    world.insert_sdf_chunk(coord, chunk);
+                }
+                None => {
+                    world.remove_sdf_chunk(&coord);
+                }
+            }
+            changed.push(coord);
+        }
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+}
+
+/// Ring buffer of the last ticks, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    tick_length: f32,
+}
+
+impl RewindBuffer {
+    /// Buffer keeping the last `seconds` of ticks `tick_length` long.
+    pub fn new(seconds: f32, tick_length: f32) -> Self {
+        let tick_length = tick_length.max(std::f32::EPSILON);
+        RewindBuffer {
+            entries: VecDeque::new(),
+            capacity: ((seconds / tick_length).ceil() as usize).max(1),
+            tick_length,
+        }
+    }
+
+    /// Number of ticks kept.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Seconds that can be rewound.
+    pub fn seconds(&self) -> f32 {
+        self.entries.len() as f32 * self.tick_length
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Keep the camera at the start of `tick`.
+    pub fn record_camera(&mut self, tick: u64, camera: CameraSnapshot) {
+        self.entry(tick).camera = Some(camera);
+    }
+
+    /// Keep the chunks of `world` the `edits` of `tick` may change, call it
+    /// before applying them.
+    pub fn record_edits(&mut self, tick: u64, world: &World, edits: &[WorldEdit]) {
+        let entry = self.entry(tick);
+        for edit in edits {
+            let (chunks, sdf) = reach(edit);
+            for coord in chunks {
+                if !entry.chunks.iter().any(|(saved, _)| *saved == coord) {
+                    entry.chunks.push((coord, world.chunk(&coord).cloned()));
+                }
+            }
+            for coord in sdf {
+                if !entry.sdf.iter().any(|(saved, _)| *saved == coord) {
+                    entry.sdf.push((coord, world.sdf_chunk(&coord).cloned()));
+                }
+            }
+        }
+    }
+
+    /// Entry of `tick`, added after the others if it's a new tick.
+    fn entry(&mut self, tick: u64) -> &mut Entry {
+        let new = self.entries.back().map_or(true, |entry| entry.tick != tick);
+        if new {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(Entry {
+                tick,
+                ..Entry::default()
+            });
+        }
+        self.entries.back_mut().unwrap()
+    }
+
+    /// Take out the last `ticks` ticks kept, `None` if none are.
+    pub fn rewind(&mut self, ticks: usize) -> Option<Rewound> {
+        let mut rewound: Option<Rewound> = None;
+        for _ in 0..ticks {
+            let entry = match self.entries.pop_back() {
+                Some(entry) => entry,
+                None => break,
+            };
+            let rewound = rewound.get_or_insert_with(|| Rewound {
+                tick: entry.tick,
+                camera: None,
+                chunks: Vec::new(),
+                sdf: Vec::new(),
+            });
+            rewound.tick = entry.tick;
+            rewound.camera = entry.camera;
+            rewound.chunks.extend(entry.chunks);
+            rewound.sdf.extend(entry.sdf);
+        }
+        rewound
+    }
+
+    /// Take out the ticks of the last `seconds`, at least one, see `rewind`.
+    pub fn rewind_seconds(&mut self, seconds: f32) -> Option<Rewound> {
+        let ticks = (seconds.max(0.0) / self.tick_length).round() as usize;
+        self.rewind(ticks.max(1))
+    }
+}
+
+/// Voxel and distance field chunks `edit` may change.
+fn reach(edit: &WorldEdit) -> (Vec<ChunkCoord>, Vec<ChunkCoord>) {
+    match edit {
+        WorldEdit::SetVoxel { pos, .. }
+        | WorldEdit::Damage { pos, .. }
+        | WorldEdit::Paint { pos, .. } => (vec![pos.chunk()], Vec::new()),
+        WorldEdit::Sdf(edit) => (Vec::new(), edit.brush.chunks().collect()),
+        WorldEdit::Explode { center, radius, .. } => {
+            let reach = Vector3::repeat(radius.max(0.0).min(MAX_EXPLOSION_RADIUS) + 1.0);
+            let corner = |offset: Vector3<f32>| Location::new(center.chunk, offset).voxel().chunk();
+            let (min, max) = (corner(center.offset - reach), corner(center.offset + reach));
+            let mut chunks = Vec::new();
+            for z in min.z..=max.z {
+                for y in min.y..=max.y {
+                    for x in min.x..=max.x {
+                        chunks.push(ChunkCoord::new(x, y, z));
+                    }
+                }
+            }
+            (chunks, Vec::new())
+        }
+    }
+}
@@ -0,0 +1,691 @@
+//! Scene given to the render graph as auxiliary data.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use nalgebra::{Isometry3, Matrix4, Transform3, Translation3, Vector3};
+use rendy::hal;
+
+use crate::block_textures::BlockTextures;
+use crate::camera::Camera;
+use crate::camera_effects::CameraEffects;
+use crate::chunk_meshes::ChunkMeshes;
+use crate::clouds::CloudLayer;
+use crate::color::Color;
+use crate::console_overlay::ConsoleOverlay;
+use crate::coords::{ChunkCoord, FloatingOrigin, Location, CHUNK_SIZE};
+use crate::crossfade::Retained;
+use crate::culling::{Aabb, CullingStats};
+use crate::debug_palette::DebugPalette;
+use crate::frame_dump::FrameDumps;
+use crate::graph::FRAMES_IN_FLIGHT;
+use crate::handle::{HandleMap, MaterialHandle, ObjectHandle, SkinnedMeshHandle, TextureHandle};
+use crate::horizon::{HorizonMesh, HorizonSettings};
+use crate::impostor::{ImpostorDraw, ImpostorSettings};
+use crate::letterbox::{self, Letterbox};
+use crate::lod::{LodSelection, LodSettings};
+use crate::material::{Material, MaterialOverride, Texture};
+use crate::objects::{self, ObjectDraw, ObjectError, SceneObject};
+use crate::profiler::Profiler;
+use crate::skinning::{SkinnedInstance, SkinnedMesh};
+use crate::transform::Transform;
+use crate::vertex::InstanceData;
+use crate::vision::VisionMask;
+use crate::weather::{Weather, WeatherState, TRANSITION_TIME};
+
+/// One drawn instance of the scene mesh.
+#[derive(Debug, Copy, Clone)]
+pub struct Instance {
+    /// Position of the instance in the world.
+    pub location: Location,
+
+    /// Rotation and scale applied around the instance position.
+    pub transform: Transform,
+
+    /// Location and transform at the previous simulation tick, set by
+    /// `Scene::begin_tick`. Drawn as is when `None`.
+    pub previous: Option<(Location, Transform)>,
+
+    /// Index of the class of the instance in `Scene::classes`.
+    pub class: usize,
+
+    pub overrides: MaterialOverride,
+}
+
+/// Distances shared by a kind of instances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceClass {
+    pub name: String,
+
+    /// Instances further than this from the camera are not drawn.
+    pub max_distance: Option<f32>,
+
+    /// Instances further than this are removed by `Scene::despawn_distant`.
+    pub despawn_distance: Option<f32>,
+}
+
+impl InstanceClass {
+    /// Class drawn and kept at any distance.
+    pub fn new(name: &str) -> Self {
+        InstanceClass {
+            name: name.to_string(),
+            max_distance: None,
+            despawn_distance: None,
+        }
+    }
+
+    pub fn with_max_distance(mut self, distance: f32) -> Self {
+        self.max_distance = Some(distance);
+        self
+    }
+
+    pub fn with_despawn_distance(mut self, distance: f32) -> Self {
+        self.despawn_distance = Some(distance);
+        self
+    }
+}
+
+impl Instance {
+    /// Location and transform drawn at `alpha` between the previous tick
+    /// and the current one.
+    pub fn interpolated(&self, alpha: f32) -> (Location, Transform) {
+        match self.previous {
+            Some((location, transform)) => (
+                location.lerp(&self.location, alpha),
+                transform.interpolate(&self.transform, alpha),
+            ),
+            None => (self.location, self.transform),
+        }
+    }
+}
+
+/// Everything the render graph needs to draw a frame.
+pub struct Scene {
+    pub camera: Camera,
+
+    /// Shake and recoil added to the drawn view, advanced with
+    /// `CameraEffects::update`.
+    pub camera_effects: CameraEffects,
+
+    /// Render space origin, `camera.view` is expressed relative to it.
+    pub origin: FloatingOrigin,
+
+    pub instances: Vec<Instance>,
+
+    /// Classes of the instances, the first one is the default class.
+    pub classes: Vec<InstanceClass>,
+
+    /// Skeletal meshes, drawn after the instances.
+    pub skinned: Vec<SkinnedInstance>,
+
+    /// Meshes of the skinned instances, see `release_mesh`.
+    pub meshes: HandleMap<SkinnedMesh>,
+
+    pub textures: HandleMap<Texture>,
+    pub materials: HandleMap<Material>,
+
+    /// Meshes drawn at their own instances, see `add_object`. Private so
+    /// the object and instance limits can't be bypassed.
+    objects: HandleMap<SceneObject>,
+
+    /// CPU timings recorded by the graph nodes.
+    pub profiler: Profiler,
+
+    /// Area of the framebuffer drawn to, the whole framebuffer when `None`.
+    pub viewport: Option<hal::pso::Rect>,
+
+    /// Fixed aspect ratio the scene is drawn at inside the viewport, with
+    /// bars around it, see `viewport_in`.
+    pub letterbox: Option<Letterbox>,
+
+    /// Maximum number of drawn instances, the least important are dropped first.
+    pub draw_budget: Option<usize>,
+
+    /// When distant instances are drawn as billboards.
+    pub impostors: ImpostorSettings,
+
+    /// Names of the enabled debug views.
+    pub debug_views: BTreeSet<String>,
+
+    /// Console drawn over the scene, `None` while closed.
+    pub console: Option<ConsoleOverlay>,
+
+    /// Colors of the debug views coloring voxels by a value.
+    pub debug_palette: DebugPalette,
+
+    /// Hour of the day in `0.0..24.0`.
+    pub time_of_day: f32,
+
+    /// Weather drawn over the scene, advanced with `WeatherState::update`.
+    pub weather: WeatherState,
+
+    pub clouds: CloudLayer,
+
+    /// Textures of the blocks, see `BlockRegistry::style`.
+    pub block_textures: BlockTextures,
+
+    /// Meshes of the loaded chunks, see `World::mesh_dirty_chunks`.
+    pub chunk_meshes: ChunkMeshes,
+
+    /// Terrain drawn beyond the view distance, see `update_horizon`.
+    pub horizon: Option<HorizonMesh>,
+
+    /// Fog of war over the voxels, none when everything is visible.
+    pub vision: Option<VisionMask>,
+
+    /// Distances of the chunk levels of detail, see `chunk_lod`.
+    pub lod: LodSettings,
+
+    /// See `QualitySettings::lod_bias`.
+    pub lod_bias: f32,
+
+    /// Seconds since the start, drives the vertex animations.
+    pub time: f32,
+
+    /// Time elapsed since the last simulation tick, as a fraction of the
+    /// tick length. Frames are drawn at this point between the previous
+    /// and current state of the camera and instances.
+    pub interpolation: f32,
+
+    /// `camera.view` at the previous tick.
+    previous_view: Option<Isometry3<f32>>,
+
+    /// View culled against while culling is frozen.
+    frozen_view: Option<Isometry3<f32>>,
+
+    stats: Mutex<CullingStats>,
+
+    /// Billboards of the frame being prepared, from the mesh pass culling.
+    impostor_draws: Mutex<Vec<ImpostorDraw>>,
+
+    /// Chunks of the instances in view, from the mesh pass culling.
+    visible_chunks: Mutex<Vec<ChunkCoord>>,
+
+    /// Instances drawn by the mesh pass when culled on the CPU.
+    drawn_instances: Mutex<Vec<usize>>,
+
+    /// Last frame of the previous graph, for the crossfade.
+    retained: Retained,
+
+    /// Pending request of `request_frame_dump`.
+    frame_dumps: FrameDumps,
+
+    /// Frames drawn so far, released resources are freed after the frames
+    /// in flight when they were released.
+    frame: u64,
+}
+
+impl Scene {
+    pub fn new(camera: Camera) -> Self {
+        Scene {
+            camera,
+            camera_effects: CameraEffects::default(),
+            origin: FloatingOrigin::default(),
+            instances: Vec::new(),
+            classes: vec![InstanceClass::new("default")],
+            skinned: Vec::new(),
+            meshes: HandleMap::new(),
+            textures: HandleMap::new(),
+            materials: HandleMap::new(),
+            objects: HandleMap::new(),
+            profiler: Profiler::new(),
+            viewport: None,
+            letterbox: None,
+            draw_budget: None,
+            impostors: ImpostorSettings::default(),
+            debug_views: BTreeSet::new(),
+            console: None,
+            debug_palette: DebugPalette::default(),
+            time_of_day: 12.0,
+            weather: WeatherState::default(),
+            clouds: CloudLayer::default(),
+            block_textures: BlockTextures::new(),
+            chunk_meshes: ChunkMeshes::new(),
+            horizon: None,
+            vision: None,
+            lod: LodSettings::default(),
+            lod_bias: 0.0,
+            time: 0.0,
+            interpolation: 1.0,
+            previous_view: None,
+            frozen_view: None,
+            stats: Mutex::new(CullingStats::default()),
+            impostor_draws: Mutex::new(Vec::new()),
+            visible_chunks: Mutex::new(Vec::new()),
+            drawn_instances: Mutex::new(Vec::new()),
+            retained: Retained::default(),
+            frame_dumps: FrameDumps::default(),
+            frame: 0,
+        }
+    }
+
+    /// Add an instance of the default class and return its index.
+    pub fn add_instance(&mut self, location: Location, transform: Transform) -> usize {
+        self.add_instance_of(0, location, transform)
+    }
+
+    /// Add an instance of `class` and return its index.
+    pub fn add_instance_of(
+        &mut self,
+        class: usize,
+        location: Location,
+        transform: Transform,
+    ) -> usize {
+        assert!(
+            class < self.classes.len(),
+            "unknown instance class {}",
+            class
+        );
+        self.instances.push(Instance {
+            location,
+            transform,
+            previous: None,
+            class,
+            overrides: MaterialOverride::default(),
+        });
+        self.instances.len() - 1
+    }
+
+    /// Add an instance class and return its index.
+    pub fn add_class(&mut self, class: InstanceClass) -> usize {
+        self.classes.push(class);
+        self.classes.len() - 1
+    }
+
+    /// Add an object and return its handle, see `add_object_instance`.
+    /// Fails past `MAX_SCENE_OBJECTS` objects or `MAX_OBJECT_INSTANCES`
+    /// instances.
+    pub fn add_object(&mut self, object: SceneObject) -> Result<ObjectHandle, ObjectError> {
+        objects::check_new_object(&self.objects, &object)?;
+        Ok(self.objects.insert(object))
+    }
+
+    /// Add a cube of `size` centered on the origin of its instances.
+    pub fn add_cube(&mut self, size: f32, color: Color) -> Result<ObjectHandle, ObjectError> {
+        self.add_object(SceneObject::cube(size, color))
+    }
+
+    /// Add a sphere of `radius` centered on the origin of its instances.
+    pub fn add_sphere(&mut self, radius: f32, color: Color) -> Result<ObjectHandle, ObjectError> {
+        self.add_object(SceneObject::sphere(radius, color))
+    }
+
+    /// Add an instance of `object` and return its index among the instances
+    /// of the object. Fails past `MAX_OBJECT_INSTANCES` instances in all
+    /// the objects.
+    pub fn add_object_instance(
+        &mut self,
+        object: ObjectHandle,
+        location: Location,
+        transform: Transform,
+    ) -> Result<usize, ObjectError> {
+        let current = self.objects.get(object).ok_or(ObjectError::NotFound)?;
+        let count = current.instances().len() + 1;
+        objects::check_instance_count(&self.objects, object, count)?;
+        self.objects
+            .get_mut(object)
+            .unwrap()
+            .add_instance(location, transform)
+    }
+
+    /// Replace the instances of `object`, to animate it. Fails past
+    /// `MAX_OBJECT_INSTANCES` instances in all the objects.
+    pub fn set_object_instances(
+        &mut self,
+        object: ObjectHandle,
+        instances: Vec<(Location, Transform)>,
+    ) -> Result<(), ObjectError> {
+        objects::check_instance_count(&self.objects, object, instances.len())?;
+        self.objects
+            .get_mut(object)
+            .unwrap()
+            .set_instances(instances)
+    }
+
+    /// Remove `object` and its instances, its mesh is freed once the frames
+    /// in flight are done with it. Returns whether the handle was valid.
+    pub fn remove_object(&mut self, object: ObjectHandle) -> bool {
+        self.objects.release(object, self.frame)
+    }
+
+    /// Objects added with `add_object`.
+    pub fn objects(&self) -> &HandleMap<SceneObject> {
+        &self.objects
+    }
+
+    /// Instance data and draws of the objects, see `objects::object_draws`.
+    pub fn object_draws<F>(&self, visible: F, instances: &mut Vec<InstanceData>) -> Vec<ObjectDraw>
+    where
+        F: Fn(&Aabb) -> bool,
+    {
+        objects::object_draws(&self.objects, &self.origin, visible, instances)
+    }
+
+    /// Maximum draw distance of the instance at `index`.
+    pub fn max_distance(&self, index: usize) -> Option<f32> {
+        self.classes[self.instances[index].class].max_distance
+    }
+
+    /// Remove the instances further from the camera than the despawn
+    /// distance of their class, return how many were removed.
+    ///
+    /// The indices of the instances after a removed one shift down.
+    pub fn despawn_distant(&mut self) -> usize {
+        let eye = self.camera_location();
+        let classes = &self.classes;
+        let before = self.instances.len();
+        self.instances.retain(|instance| {
+            classes[instance.class]
+                .despawn_distance
+                .map_or(true, |max| {
+                    let offset = instance.location.relative_to(&eye.chunk) - eye.offset;
+                    offset.norm() <= max
+                })
+        });
+        before - self.instances.len()
+    }
+
+    /// World location of the camera.
+    pub fn camera_location(&self) -> Location {
+        Location::new(self.origin.origin(), self.camera.view.translation.vector)
+    }
+
+    /// Follow the camera with the floating origin, call it after moving the camera.
+    ///
+    /// The camera is moved by the same amount as the origin so the rendered
+    /// image doesn't change.
+    pub fn update_origin(&mut self) {
+        let focus = self.camera_location();
+        if let Some(shift) = self.origin.update(&focus) {
+            self.camera.view.translation.vector += shift;
+            for view in self.previous_view.iter_mut().chain(&mut self.frozen_view) {
+                view.translation.vector += shift;
+            }
+        }
+    }
+
+    /// Move the camera to `location`, keeping its rotation.
+    ///
+    /// The origin moves to the chunk of `location` first, so the camera
+    /// lands exactly there however far it is.
+    pub fn teleport_camera(&mut self, location: &Location) {
+        let shift = self.origin.move_to(location.chunk);
+        self.camera.view.translation.vector = location.offset;
+        self.previous_view = None;
+        for view in self.frozen_view.iter_mut() {
+            view.translation.vector += shift;
+        }
+    }
+
+    /// Save the camera and instances as the previous tick state, call it
+    /// before each simulation tick moves them.
+    pub fn begin_tick(&mut self) {
+        self.previous_view = Some(self.camera.view);
+        for instance in &mut self.instances {
+            instance.previous = Some((instance.location, instance.transform));
+        }
+    }
+
+    /// Camera view drawn this frame, see `interpolation`, with the camera
+    /// effects applied.
+    pub fn render_view(&self) -> Isometry3<f32> {
+        self.interpolated_view() * self.camera_effects.offset()
+    }
+
+    fn interpolated_view(&self) -> Isometry3<f32> {
+        match self.previous_view {
+            Some(previous) => {
+                let alpha = self.alpha();
+                let view = &self.camera.view;
+                Isometry3::from_parts(
+                    previous
+                        .translation
+                        .vector
+                        .lerp(&view.translation.vector, alpha)
+                        .into(),
+                    previous.rotation.slerp(&view.rotation, alpha),
+                )
+            }
+            None => self.camera.view,
+        }
+    }
+
+    /// Projection times the inverse of `render_view`.
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.camera.proj.to_homogeneous() * self.render_view().inverse().to_homogeneous()
+    }
+
+    /// Lock the view used for culling where it is, or release it.
+    ///
+    /// While frozen the camera keeps moving but instances are culled as if
+    /// it stayed in place, so what the culling drops can be seen from
+    /// outside. Occlusion culling is off while frozen, the depth pyramid
+    /// is rendered from the moving camera.
+    pub fn freeze_culling(&mut self, frozen: bool) {
+        self.frozen_view = if frozen {
+            self.frozen_view.or_else(|| Some(self.render_view()))
+        } else {
+            None
+        };
+    }
+
+    pub fn is_culling_frozen(&self) -> bool {
+        self.frozen_view.is_some()
+    }
+
+    /// View the instances are culled against, `render_view` unless frozen.
+    pub fn culling_view(&self) -> Isometry3<f32> {
+        self.frozen_view.unwrap_or_else(|| self.render_view())
+    }
+
+    /// Projection times the inverse of `culling_view`.
+    pub fn culling_view_proj(&self) -> Matrix4<f32> {
+        self.camera.proj.to_homogeneous() * self.culling_view().inverse().to_homogeneous()
+    }
+
+    fn alpha(&self) -> f32 {
+        self.interpolation.max(0.0).min(1.0)
+    }
+
+    /// Direction towards the sun, rising in +X at 6h and setting in -X at 18h.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.time_of_day - 6.0) / 12.0 * std::f32::consts::PI;
+        Vector3::new(angle.cos(), angle.sin(), 0.3).normalize()
+    }
+
+    /// Generate the horizon around the camera if there is none or the camera
+    /// moved past its rebuild distance.
+    ///
+    /// The horizon starts at the far plane, where the drawn chunks end.
+    pub fn update_horizon<F>(&mut self, settings: HorizonSettings, sample: F)
+    where
+        F: Fn(i64, i64) -> (f32, Color),
+    {
+        let eye = self.camera_location().voxel();
+        let stale = self.horizon.as_ref().map_or(true, |horizon| {
+            horizon.settings() != &settings || horizon.needs_rebuild(&eye)
+        });
+        if stale {
+            let inner_radius = self.camera.proj.zfar();
+            self.horizon = Some(HorizonMesh::generate(eye, inner_radius, settings, sample));
+        }
+    }
+
+    /// Transition to `weather` over `TRANSITION_TIME` seconds.
+    pub fn set_weather(&mut self, weather: Weather) {
+        self.weather.transition(weather, TRANSITION_TIME);
+    }
+
+    /// Area the scene is drawn to in a `framebuffer` sized target: the
+    /// viewport, or the framebuffer, fitted to the letterbox.
+    pub fn viewport_in(&self, framebuffer: hal::pso::Rect) -> hal::pso::Rect {
+        let area = self.viewport.unwrap_or(framebuffer);
+        match self.letterbox {
+            Some(letterbox) => letterbox::fit(area, letterbox.aspect),
+            None => area,
+        }
+    }
+
+    /// Area of a `width` by `height` target where the HUD stays visible:
+    /// inside the letterbox bars, shrunk by `margin` of its size on each
+    /// side for displays cropping their edges.
+    pub fn safe_area(&self, width: u32, height: u32, margin: f32) -> hal::pso::Rect {
+        letterbox::inset(self.viewport_in(full_rect(width, height)), margin)
+    }
+
+    /// Match the camera projection to the area drawn in a `width` by
+    /// `height` target, call it after resizing or changing the letterbox.
+    pub fn update_aspect(&mut self, width: u32, height: u32) {
+        let area = self.viewport_in(full_rect(width, height));
+        // The aspect of an empty area, while minimized, is refused and the
+        // previous one kept.
+        let _ = self
+            .camera
+            .set_aspect(f32::from(area.w) / f32::from(area.h));
+    }
+
+    pub fn debug_view(&self, name: &str) -> bool {
+        self.debug_views.contains(name)
+    }
+
+    /// Level of detail of a chunk, from the distance of its center to the
+    /// culling camera.
+    pub fn chunk_lod(&self, chunk: &ChunkCoord) -> LodSelection {
+        let half = CHUNK_SIZE as f32 / 2.0;
+        let center = Location::new(*chunk, Vector3::new(half, half, half));
+        let eye = self.culling_view().translation.vector;
+        let distance = (self.origin.to_render(&center) - eye).norm();
+        self.lod.select(distance, self.lod_bias)
+    }
+
+    /// Culling counts of the last prepared frame.
+    pub fn culling_stats(&self) -> CullingStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub(crate) fn record_culling(&self, stats: CullingStats) {
+        for &(name, value) in stats.counters().iter() {
+            self.profiler.set_counter(name, value);
+        }
+        *self.stats.lock().unwrap() = stats;
+    }
+
+    pub(crate) fn record_impostors(&self, draws: Vec<ImpostorDraw>) {
+        *self.impostor_draws.lock().unwrap() = draws;
+    }
+
+    pub(crate) fn take_impostors(&self) -> Vec<ImpostorDraw> {
+        std::mem::replace(&mut *self.impostor_draws.lock().unwrap(), Vec::new())
+    }
+
+    /// Record the chunks of the instances in view, sorted and deduplicated.
+    pub(crate) fn record_visible_chunks<I>(&self, chunks: I)
+    where
+        I: IntoIterator<Item = ChunkCoord>,
+    {
+        let mut visible = self.visible_chunks.lock().unwrap();
+        visible.clear();
+        visible.extend(chunks);
+        visible.sort();
+        visible.dedup();
+    }
+
+    /// Move the recorded visible chunks into `chunks`, reusing its
+    /// allocation for the next frame.
+    pub(crate) fn swap_visible_chunks(&self, chunks: &mut Vec<ChunkCoord>) {
+        let mut visible = self.visible_chunks.lock().unwrap();
+        std::mem::swap(&mut *visible, chunks);
+        visible.clear();
+    }
+
+    /// Record the instances the mesh pass draws this frame.
+    pub(crate) fn record_drawn(&self, instances: &[usize]) {
+        let mut drawn = self.drawn_instances.lock().unwrap();
+        drawn.clear();
+        drawn.extend_from_slice(instances);
+    }
+
+    /// Swap the recorded drawn instances with `instances`.
+    pub(crate) fn swap_drawn(&self, instances: &mut Vec<usize>) {
+        std::mem::swap(&mut *self.drawn_instances.lock().unwrap(), instances);
+    }
+
+    pub(crate) fn retained(&self) -> &Retained {
+        &self.retained
+    }
+
+    /// Write the intermediate targets of the next frame as PNG files into
+    /// `folder`, created if needed. Only graphs built with
+    /// `RendererConfig::frame_dumps` make dumps, a request waits for one.
+    pub fn request_frame_dump(&self, folder: PathBuf) {
+        self.frame_dumps.request(folder);
+    }
+
+    pub(crate) fn frame_dumps(&self) -> &FrameDumps {
+        &self.frame_dumps
+    }
+
+    /// Free `mesh` once the frames in flight are done with it, the handle
+    /// is invalid right away. Returns whether it was valid.
+    pub fn release_mesh(&mut self, mesh: SkinnedMeshHandle) -> bool {
+        self.meshes.release(mesh, self.frame)
+    }
+
+    /// See `release_mesh`.
+    pub fn release_texture(&mut self, texture: TextureHandle) -> bool {
+        self.textures.release(texture, self.frame)
+    }
+
+    /// See `release_mesh`.
+    pub fn release_material(&mut self, material: MaterialHandle) -> bool {
+        self.materials.release(material, self.frame)
+    }
+
+    /// Count a submitted frame and free the resources released before the
+    /// oldest frame still in flight.
+    pub(crate) fn end_frame(&mut self) {
+        self.frame += 1;
+        if let Some(completed) = self.frame.checked_sub(u64::from(FRAMES_IN_FLIGHT) + 1) {
+            self.collect_released(completed);
+        }
+    }
+
+    /// Free the released resources of the frames up to `completed`, every
+    /// frame once the device is idle.
+    pub(crate) fn collect_released(&mut self, completed: u64) {
+        let freed = self.meshes.collect(completed)
+            + self.textures.collect(completed)
+            + self.materials.collect(completed)
+            + self.objects.collect(completed);
+        if freed > 0 {
+            debug!("Freed {} released resources.", freed);
+        }
+    }
+
+    /// Model matrices of the instances, relative to the floating origin.
+    pub fn model_transforms(&self) -> impl Iterator<Item = Transform3<f32>> + '_ {
+        let alpha = self.alpha();
+        self.instances.iter().map(move |instance| {
+            let (location, transform) = instance.interpolated(alpha);
+            let translation = Translation3::from(self.origin.to_render(&location));
+            Transform3::from_matrix_unchecked(translation.to_homogeneous() * transform.to_matrix())
+        })
+    }
+
+    /// Model matrices and material overrides of the instances, as uploaded.
+    pub fn instance_data(&self) -> impl Iterator<Item = InstanceData> + '_ {
+        self.model_transforms()
+            .zip(self.instances.iter())
+            .map(|(model, instance)| InstanceData::new(&model, &instance.overrides))
+    }
+}
+
+fn full_rect(width: u32, height: u32) -> hal::pso::Rect {
+    hal::pso::Rect {
+        x: 0,
+        y: 0,
+        w: width as i16,
+        h: height as i16,
+    }
+}
@@ -0,0 +1,154 @@
+//! Rhai scripting, enabled with the `scripting` feature.
+//!
+//! Scripts define an `on_update(dt)` function called once per frame. The
+//! functions exposed to them queue commands which are applied to the world
+//! and the scene when the callback returns, scripts never hold references to
+//! engine state.
+//!
+//! ```rhai
+//! fn on_update(dt) {
+//!     set_block(0, 10, 0, 1);
+//!     move_camera(0, 20, -10);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use rhai::{Engine, ImmutableString, RegisterFn, Scope, AST};
+
+use crate::chunk::VoxelId;
+use crate::coords::{Location, WorldPos};
+use crate::scene::Scene;
+use crate::transform::Transform;
+use crate::world::World;
+
+/// Change requested by a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    SetBlock(WorldPos, VoxelId),
+    SpawnPrefab(String, WorldPos),
+    MoveCamera(WorldPos),
+    SetAmbient(f32),
+}
+
+/// Interpreter and compiled script.
+pub struct ScriptHost {
+    engine: Engine,
+    scope: Scope<'static>,
+    ast: Option<AST>,
+    commands: Rc<RefCell<Vec<ScriptCommand>>>,
+    prefabs: HashMap<String, Transform>,
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        let mut engine = Engine::new();
+        let commands = Rc::new(RefCell::new(Vec::new()));
+
+        let queue = commands.clone();
+        engine.register_fn("set_block", move |x: i64, y: i64, z: i64, id: i64| {
+            match VoxelId::try_from(id) {
+                Ok(id) => queue
+                    .borrow_mut()
+                    .push(ScriptCommand::SetBlock(WorldPos::new(x, y, z), id)),
+                Err(_) => warn!(
+                    "Script set the invalid block id {}, ids go up to {}.",
+                    id,
+                    VoxelId::max_value()
+                ),
+            }
+        });
+        let queue = commands.clone();
+        engine.register_fn(
+            "spawn_prefab",
+            move |name: ImmutableString, x: i64, y: i64, z: i64| {
+                queue.borrow_mut().push(ScriptCommand::SpawnPrefab(
+                    name.to_string(),
+                    WorldPos::new(x, y, z),
+                ));
+            },
+        );
+        let queue = commands.clone();
+        engine.register_fn("move_camera", move |x: i64, y: i64, z: i64| {
+            queue
+                .borrow_mut()
+                .push(ScriptCommand::MoveCamera(WorldPos::new(x, y, z)));
+        });
+        let queue = commands.clone();
+        engine.register_fn("set_ambient", move |power: f64| {
+            queue
+                .borrow_mut()
+                .push(ScriptCommand::SetAmbient(power as f32));
+        });
+
+        ScriptHost {
+            engine,
+            scope: Scope::new(),
+            ast: None,
+            commands,
+            prefabs: HashMap::new(),
+        }
+    }
+
+    /// Transform of the instances spawned by `spawn_prefab(name, ...)`.
+    pub fn register_prefab(&mut self, name: &str, transform: Transform) {
+        self.prefabs.insert(name.to_owned(), transform);
+    }
+
+    /// Compile `source` and run its top level statements.
+    pub fn load(&mut self, source: &str) -> Result<(), String> {
+        let ast = self.engine.compile(source).map_err(|err| err.to_string())?;
+        self.engine
+            .consume_ast_with_scope(&mut self.scope, &ast)
+            .map_err(|err| err.to_string())?;
+        self.ast = Some(ast);
+        Ok(())
+    }
+
+    /// Call `on_update(dt)` then apply the queued commands.
+    pub fn update(&mut self, dt: f32, world: &mut World, scene: &mut Scene) -> Result<(), String> {
+        if let Some(ref ast) = self.ast {
+            self.engine
+                .call_fn::<_, ()>(&mut self.scope, ast, "on_update", (dt as f64,))
+                .map_err(|err| err.to_string())?;
+        }
+        let commands = std::mem::replace(&mut *self.commands.borrow_mut(), Vec::new());
+        for command in commands {
+            self.apply(command, world, scene);
+        }
+        Ok(())
+    }
+
+    fn apply(&self, command: ScriptCommand, world: &mut World, scene: &mut Scene) {
+        match command {
+            ScriptCommand::SetBlock(pos, id) => {
+                if !world.set_voxel(&pos, id) {
+                    warn!("Script set a block outside the world at {:?}.", pos);
+                }
+            }
+            ScriptCommand::SpawnPrefab(name, pos) => match self.prefabs.get(&name) {
+                Some(transform) => {
+                    scene.add_instance(Location::from_voxel(pos), *transform);
+                }
+                None => warn!("Script spawned unknown prefab `{}`.", name),
+            },
+            ScriptCommand::MoveCamera(pos) => scene.teleport_camera(&Location::from_voxel(pos)),
+            ScriptCommand::SetAmbient(power) => scene.camera.ambient_power = power,
+        }
+    }
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        ScriptHost::new()
+    }
+}
+
+impl std::fmt::Debug for ScriptHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ScriptHost(loaded: {})", self.ast.is_some())
+    }
+}
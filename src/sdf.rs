@@ -0,0 +1,212 @@
+//! Signed distance field voxel layer.
+//!
+//! Smooth terrain stores the distance to its surface at every voxel corner
+//! instead of block ids, negative inside. Distances are kept as `f16` and
+//! clamped to a few voxels around the surface, which is all the meshers
+//! look at. Edits are CSG operations of brushes on the field.
+
+use half::f16;
+use nalgebra::Vector3;
+
+use crate::coords::{ChunkCoord, Location, CHUNK_SIZE};
+use crate::smooth::{DensityGrid, POINTS};
+
+/// Distances are clamped to this many voxels.
+pub const MAX_DISTANCE: f32 = 4.0;
+
+/// Distances at the corners of a chunk, from `(0, 0, 0)` to
+/// `(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE)`.
+///
+/// The corners on the faces are shared with the neighbour chunks, edits
+/// update both copies.
+#[derive(Clone, PartialEq)]
+pub struct SdfChunk {
+    distances: Box<[f16]>,
+}
+
+impl SdfChunk {
+    /// Chunk outside the terrain.
+    pub fn new() -> Self {
+        SdfChunk::filled(MAX_DISTANCE)
+    }
+
+    /// Chunk with every corner at `distance`, clamped.
+    pub fn filled(distance: f32) -> Self {
+        let distance = f16::from_f32(clamp(distance));
+        SdfChunk {
+            distances: vec![distance; POINTS * POINTS * POINTS].into_boxed_slice(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.distances[index(x, y, z)].to_f32()
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, distance: f32) {
+        self.distances[index(x, y, z)] = f16::from_f32(clamp(distance));
+    }
+
+    /// Whether no corner is inside the terrain.
+    pub fn is_empty(&self) -> bool {
+        self.distances.iter().all(|d| d.to_f32() >= 0.0)
+    }
+
+    /// Apply `edit` to the corners it reaches, the chunk is at `coord`.
+    ///
+    /// Returns whether a distance changed.
+    pub fn apply(&mut self, coord: &ChunkCoord, edit: &CsgEdit) -> bool {
+        let center = edit.brush.center().relative_to(coord);
+        let reach = edit.brush.radius() + MAX_DISTANCE;
+        let range = |c: f32| {
+            let min = (c - reach).floor().max(0.0) as usize;
+            let max = ((c + reach).ceil().max(-1.0) + 1.0).min(POINTS as f32) as usize;
+            min..max
+        };
+        let mut changed = false;
+        for z in range(center.z) {
+            for y in range(center.y) {
+                for x in range(center.x) {
+                    let corner = Vector3::new(x as f32, y as f32, z as f32);
+                    let brush = edit.brush.distance(&(corner - center));
+                    let old = self.get(x, y, z);
+                    let new = match edit.op {
+                        CsgOp::Union => old.min(brush),
+                        CsgOp::Subtract => old.max(-brush),
+                    };
+                    let new = f16::from_f32(clamp(new));
+                    let i = index(x, y, z);
+                    if self.distances[i] != new {
+                        self.distances[i] = new;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    /// Distances for the smooth meshers.
+    pub fn density_grid(&self) -> DensityGrid {
+        DensityGrid::from_fn(|x, y, z| self.get(x, y, z))
+    }
+}
+
+impl Default for SdfChunk {
+    fn default() -> Self {
+        SdfChunk::new()
+    }
+}
+
+impl std::fmt::Debug for SdfChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SdfChunk({} corners)", self.distances.len())
+    }
+}
+
+fn index(x: usize, y: usize, z: usize) -> usize {
+    x + (y + z * POINTS) * POINTS
+}
+
+fn clamp(distance: f32) -> f32 {
+    distance.max(-MAX_DISTANCE).min(MAX_DISTANCE)
+}
+
+/// Shape of a CSG edit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Brush {
+    Sphere {
+        center: Location,
+        radius: f32,
+    },
+
+    /// Axis aligned box.
+    Box {
+        center: Location,
+        half_extents: Vector3<f32>,
+    },
+}
+
+impl Brush {
+    pub fn center(&self) -> Location {
+        match *self {
+            Brush::Sphere { center, .. } | Brush::Box { center, .. } => center,
+        }
+    }
+
+    /// Radius of the sphere containing the brush.
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Brush::Sphere { radius, .. } => radius,
+            Brush::Box { half_extents, .. } => half_extents.norm(),
+        }
+    }
+
+    /// Signed distance to the brush surface from `offset`, relative to its
+    /// center.
+    pub fn distance(&self, offset: &Vector3<f32>) -> f32 {
+        match *self {
+            Brush::Sphere { radius, .. } => offset.norm() - radius,
+            Brush::Box { half_extents, .. } => {
+                let q = offset.abs() - half_extents;
+                let outside = q.map(|v| v.max(0.0)).norm();
+                let inside = q.max().min(0.0);
+                outside + inside
+            }
+        }
+    }
+
+    /// Chunks whose corners the brush may change, including the clamped
+    /// band around it.
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkCoord> {
+        let center = self.center();
+        let reach = self.radius() + MAX_DISTANCE;
+        let size = CHUNK_SIZE as f32;
+        // Chunks sharing corners on their faces are included.
+        let range = move |chunk: i64, offset: f32| {
+            let min = ((offset - reach) / size).floor() as i64 - 1;
+            let max = ((offset + reach) / size).floor() as i64;
+            chunk + min..=chunk + max
+        };
+        let xs = range(center.chunk.x, center.offset.x);
+        let ys = range(center.chunk.y, center.offset.y);
+        let zs = range(center.chunk.z, center.offset.z);
+        zs.flat_map(move |z| {
+            let xs = xs.clone();
+            ys.clone()
+                .flat_map(move |y| xs.clone().map(move |x| ChunkCoord::new(x, y, z)))
+        })
+    }
+}
+
+/// How a brush combines with the field.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CsgOp {
+    /// Add the brush volume to the terrain.
+    Union,
+
+    /// Carve the brush volume out of the terrain.
+    Subtract,
+}
+
+/// One edit of the distance field.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CsgEdit {
+    pub brush: Brush,
+    pub op: CsgOp,
+}
+
+impl CsgEdit {
+    pub fn union(brush: Brush) -> Self {
+        CsgEdit {
+            brush,
+            op: CsgOp::Union,
+        }
+    }
+
+    pub fn subtract(brush: Brush) -> Self {
+        CsgEdit {
+            brush,
+            op: CsgOp::Subtract,
+        }
+    }
+}
@@ -0,0 +1,915 @@
+//! Skeletal meshes, skinned once per frame before being drawn.
+//!
+//! With `RendererConfig::gpu_skinning` a compute pre-pass blends the joint
+//! matrices of every vertex and writes the result as `VoxelVertex` into a
+//! graph buffer. Passes drawing skinned meshes bind that buffer as plain
+//! vertices, the skinning cost is paid once however many times they are
+//! drawn. Without it the same vertices are skinned on the CPU.
+
+use std::mem::size_of;
+
+use nalgebra::{Matrix4, Point3, Transform3, Translation3, Vector3};
+use rendy::command::{
+    CommandBuffer, CommandPool, Compute, ExecutableState, Family, Fence, MultiShot, PendingState,
+    Queue, QueueId, RenderPassEncoder, SimultaneousUse, Submission, Submit,
+};
+use rendy::factory::Factory;
+use rendy::frame::Frames;
+use rendy::graph::render::{
+    Layout, PrepareResult, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{
+    gfx_acquire_barriers, gfx_release_barriers, BufferAccess, GraphContext, Node, NodeBuffer,
+    NodeBuildError, NodeDesc, NodeImage,
+};
+use rendy::hal::{self, adapter::PhysicalDevice, device::Device};
+use rendy::mesh::AsVertex;
+use rendy::resource::{Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::block_textures::BlockTextureBuffer;
+use crate::color::OutputEncoding;
+use crate::config::ShaderTuning;
+use crate::coords::Location;
+use crate::glsl;
+use crate::gpu::layout;
+use crate::handle::SkinnedMeshHandle;
+use crate::mapped::MappedBuffer;
+use crate::material::MaterialOverride;
+use crate::material::ShadingModel;
+use crate::mesh::{fragment_constants, iceil, scene_layout, uniform_args, UniformArgs};
+use crate::scene::Scene;
+use crate::transform::Transform;
+use crate::vertex::{AnimFlags, InstanceData, VoxelVertex};
+use crate::vision::{self, VisionBuffer};
+
+lazy_static::lazy_static! {
+    static ref COMPUTE: SpirvShader = SourceShaderInfo::new(
+        include_str!("../skin.comp"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/skin.comp").into(),
+        ShaderKind::Compute,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref VERTEX_SOURCE: String =
+        glsl::expand(include_str!("../shader.vert")).replacen('\n', "\n#define SKINNED\n", 1);
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../shader.frag"));
+
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        &VERTEX_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/shader.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/shader.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the skinning and drawing shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&COMPUTE);
+    lazy_static::initialize(&SHADERS);
+}
+
+/// Most skinned vertices, all instances together.
+pub const MAX_SKINNED_VERTICES: usize = 32768;
+
+/// Most indices of the skinned instances, all instances together.
+pub const MAX_SKINNED_INDICES: usize = 3 * 65536;
+
+/// Most joint matrices, all instances together.
+pub const MAX_JOINTS: usize = 1024;
+
+/// Most skinned instances drawn, later ones are skipped.
+pub const MAX_SKINNED_INSTANCES: usize = 256;
+
+/// Vertex bound to up to four joints.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkinVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 4],
+
+    /// Indices into the pose of the instance.
+    pub joints: [u32; 4],
+
+    /// Influence of each joint, summing to one.
+    pub weights: [f32; 4],
+}
+
+/// Mesh deformed by a skeleton, in its bind pose.
+#[derive(Debug, Clone)]
+pub struct SkinnedMesh {
+    pub vertices: Vec<SkinVertex>,
+    pub indices: Vec<u32>,
+    pub joint_count: usize,
+}
+
+/// Posed instance of a skinned mesh.
+#[derive(Debug, Clone)]
+pub struct SkinnedInstance {
+    /// Mesh in `Scene::meshes`, shared between the instances. The vertices
+    /// are uploaded once per mesh order change, instances of a removed mesh
+    /// are not drawn.
+    pub mesh: SkinnedMeshHandle,
+
+    pub location: Location,
+    pub transform: Transform,
+    pub overrides: MaterialOverride,
+
+    /// Matrix of each joint, from the bind pose to the current pose in mesh
+    /// space. Missing joints keep the bind pose.
+    pub pose: Vec<Matrix4<f32>>,
+}
+
+impl SkinnedInstance {
+    /// Instance of `mesh` in its bind pose.
+    pub fn new(mesh: SkinnedMeshHandle, location: Location, transform: Transform) -> Self {
+        SkinnedInstance {
+            mesh,
+            location,
+            transform,
+            overrides: MaterialOverride::default(),
+            pose: Vec::new(),
+        }
+    }
+
+    fn joint(&self, joint: u32) -> Matrix4<f32> {
+        self.pose
+            .get(joint as usize)
+            .cloned()
+            .unwrap_or_else(Matrix4::identity)
+    }
+}
+
+/// Position and normal of `vertex` in `pose`, as computed by `skin.comp`.
+pub fn skin(vertex: &SkinVertex, pose: &[Matrix4<f32>]) -> (Point3<f32>, Vector3<f32>) {
+    let mut matrix = Matrix4::zeros();
+    for (&joint, &weight) in vertex.joints.iter().zip(vertex.weights.iter()) {
+        let joint = pose
+            .get(joint as usize)
+            .cloned()
+            .unwrap_or_else(Matrix4::identity);
+        matrix += joint * weight;
+    }
+    let position = matrix.transform_point(&Point3::from(vertex.position));
+    let normal = matrix.transform_vector(&Vector3::from(vertex.normal));
+    (position, normal.normalize())
+}
+
+/// Range of the skinning buffers used by one instance.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Batch {
+    pub first_vertex: u32,
+    pub first_joint: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+/// Skinned instance with its mesh.
+pub(crate) type Resolved<'a> = (&'a SkinnedInstance, &'a SkinnedMesh);
+
+/// Instances of the scene with a mesh, in order.
+pub(crate) fn resolve(scene: &Scene) -> Vec<Resolved<'_>> {
+    scene
+        .skinned
+        .iter()
+        .filter_map(|instance| Some((instance, scene.meshes.get(instance.mesh)?)))
+        .collect()
+}
+
+/// Batches of the instances fitting in the buffers, in order.
+pub(crate) fn batches(instances: &[Resolved]) -> Vec<Batch> {
+    let mut batches = Vec::new();
+    let (mut vertices, mut joints, mut indices) = (0, 0, 0);
+    for &(_, mesh) in instances.iter().take(MAX_SKINNED_INSTANCES) {
+        if vertices + mesh.vertices.len() > MAX_SKINNED_VERTICES
+            || joints + mesh.joint_count > MAX_JOINTS
+            || indices + mesh.indices.len() > MAX_SKINNED_INDICES
+        {
+            break;
+        }
+        batches.push(Batch {
+            first_vertex: vertices as u32,
+            first_joint: joints as u32,
+            first_index: indices as u32,
+            index_count: mesh.indices.len() as u32,
+        });
+        vertices += mesh.vertices.len();
+        joints += mesh.joint_count;
+        indices += mesh.indices.len();
+    }
+    batches
+}
+
+/// Identifies the meshes of the batched instances, their vertices and
+/// indices are uploaded again when it changes.
+fn meshes_key(instances: &[Resolved], batches: &[Batch]) -> Vec<SkinnedMeshHandle> {
+    instances
+        .iter()
+        .take(batches.len())
+        .map(|(instance, _)| instance.mesh)
+        .collect()
+}
+
+fn batch_indices(instances: &[Resolved], batches: &[Batch]) -> Vec<u32> {
+    instances
+        .iter()
+        .take(batches.len())
+        .flat_map(|(_, mesh)| mesh.indices.iter().cloned())
+        .collect()
+}
+
+fn batch_models(scene: &Scene, instances: &[Resolved], batches: &[Batch]) -> Vec<InstanceData> {
+    instances
+        .iter()
+        .take(batches.len())
+        .map(|(instance, _)| {
+            let translation = Translation3::from(scene.origin.to_render(&instance.location));
+            let model = Transform3::from_matrix_unchecked(
+                translation.to_homogeneous() * instance.transform.to_matrix(),
+            );
+            InstanceData::new(&model, &instance.overrides)
+        })
+        .collect()
+}
+
+/// Vertex as read by `skin.comp`, with the joints offset to the instance.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct SkinSource {
+    position: [f32; 4],
+    normal: [f32; 4],
+    color: [f32; 4],
+    joints: [u32; 4],
+    weights: [f32; 4],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct SkinArgs {
+    count: u32,
+}
+
+const GROUP_SIZE: u32 = 64;
+const ARGS_SIZE: u64 = size_of::<SkinArgs>() as u64;
+const SOURCES_SIZE: u64 = (size_of::<SkinSource>() * MAX_SKINNED_VERTICES) as u64;
+const JOINTS_SIZE: u64 = (size_of::<Matrix4<f32>>() * MAX_JOINTS) as u64;
+
+/// Size of the graph buffer written by the pre-pass.
+pub(crate) const OUTPUT_SIZE: u64 = (size_of::<VoxelVertex>() * MAX_SKINNED_VERTICES) as u64;
+
+fn args_offset(index: usize, align: u64) -> u64 {
+    frame_size(align) * index as u64
+}
+
+fn sources_offset(index: usize, align: u64) -> u64 {
+    args_offset(index, align) + iceil(ARGS_SIZE, align)
+}
+
+fn joints_offset(index: usize, align: u64) -> u64 {
+    sources_offset(index, align) + iceil(SOURCES_SIZE, align)
+}
+
+fn frame_size(align: u64) -> u64 {
+    iceil(ARGS_SIZE, align) + iceil(SOURCES_SIZE, align) + iceil(JOINTS_SIZE, align)
+}
+
+fn layout_binding(
+    binding: u32,
+    ty: hal::pso::DescriptorType,
+) -> hal::pso::DescriptorSetLayoutBinding {
+    hal::pso::DescriptorSetLayoutBinding {
+        binding,
+        ty,
+        count: 1,
+        stage_flags: hal::pso::ShaderStageFlags::COMPUTE,
+        immutable_samplers: false,
+    }
+}
+
+/// Skinning pre-pass, expects the output buffer.
+#[derive(Debug, Default)]
+pub(crate) struct SkinNodeDesc;
+
+pub(crate) struct SkinNode<B: hal::Backend> {
+    align: u64,
+    buffer: MappedBuffer<B>,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+    command_pool: CommandPool<B, Compute>,
+    command_buffers:
+        Vec<CommandBuffer<B, Compute, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>>,
+    submits: Vec<Submit<B, SimultaneousUse>>,
+    /// Meshes whose vertices are in the sources of each frame.
+    uploaded: Vec<Vec<SkinnedMeshHandle>>,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for SkinNode<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Skin Node")
+    }
+}
+
+impl<B> NodeDesc<B, Scene> for SkinNodeDesc
+where
+    B: hal::Backend,
+{
+    type Node = SkinNode<B>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        vec![BufferAccess {
+            access: hal::buffer::Access::SHADER_WRITE,
+            stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+            usage: hal::buffer::Usage::STORAGE,
+        }]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        aux: &Scene,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(images.is_empty());
+        assert_eq!(buffers.len(), 1);
+
+        let output = ctx.get_buffer(buffers[0].id).unwrap();
+        let frames = ctx.frames_in_flight as usize;
+        let limits = factory.physical().limits();
+        let align = limits
+            .min_uniform_buffer_offset_alignment
+            .max(limits.min_storage_buffer_offset_alignment);
+
+        let buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: frame_size(align) * frames as u64,
+                usage: hal::buffer::Usage::UNIFORM | hal::buffer::Usage::STORAGE,
+            },
+        );
+
+        let set_layout: Handle<DescriptorSetLayout<B>> = factory
+            .create_descriptor_set_layout(vec![
+                layout_binding(0, hal::pso::DescriptorType::UniformBuffer),
+                layout_binding(1, hal::pso::DescriptorType::StorageBuffer),
+                layout_binding(2, hal::pso::DescriptorType::StorageBuffer),
+                layout_binding(3, hal::pso::DescriptorType::StorageBuffer),
+            ])
+            .map_err(NodeBuildError::OutOfMemory)?
+            .into();
+
+        let sets = {
+            let _scope = aux.profiler.scope("skinning.descriptors");
+            let sets: Vec<_> = (0..frames)
+                .map(|_| factory.create_descriptor_set(set_layout.clone()).unwrap())
+                .collect();
+
+            // Every binding of every frame written in a single call.
+            let mut writes = Vec::new();
+            for (index, set) in sets.iter().enumerate() {
+                let ranges = [
+                    (args_offset(index, align), ARGS_SIZE),
+                    (sources_offset(index, align), SOURCES_SIZE),
+                    (joints_offset(index, align), JOINTS_SIZE),
+                ];
+                for (binding, &(offset, size)) in ranges.iter().enumerate() {
+                    writes.push(hal::pso::DescriptorSetWrite {
+                        set: set.raw(),
+                        binding: binding as u32,
+                        array_offset: 0,
+                        descriptors: Some(hal::pso::Descriptor::Buffer(
+                            buffer.raw(),
+                            Some(offset)..Some(offset + size),
+                        )),
+                    });
+                }
+                writes.push(hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 3,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Buffer(
+                        output.raw(),
+                        Some(0)..Some(OUTPUT_SIZE),
+                    )),
+                });
+            }
+            unsafe { factory.write_descriptor_sets(writes) };
+            sets
+        };
+
+        let pipeline_layout = unsafe {
+            factory.device().create_pipeline_layout(
+                Some(set_layout.raw()),
+                std::iter::empty::<(hal::pso::ShaderStageFlags, std::ops::Range<u32>)>(),
+            )
+        }
+        .map_err(NodeBuildError::OutOfMemory)?;
+
+        let module = unsafe { COMPUTE.module(factory) }.unwrap();
+        let pipeline = unsafe {
+            factory.device().create_compute_pipeline(
+                &hal::pso::ComputePipelineDesc {
+                    shader: hal::pso::EntryPoint {
+                        entry: "main",
+                        module: &module,
+                        specialization: hal::pso::Specialization::default(),
+                    },
+                    layout: &pipeline_layout,
+                    flags: hal::pso::PipelineCreationFlags::empty(),
+                    parent: hal::pso::BasePipeline::None,
+                },
+                None,
+            )
+        };
+        unsafe { factory.destroy_shader_module(module) };
+        let pipeline = pipeline.map_err(NodeBuildError::Pipeline)?;
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Compute>()
+            .expect("Graph builder must provide family with Compute capability");
+
+        let mut command_buffers = Vec::new();
+        let mut submits = Vec::new();
+        for (index, initial) in command_pool
+            .allocate_buffers(frames)
+            .into_iter()
+            .enumerate()
+        {
+            let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+            let mut encoder = recording.encoder();
+            {
+                let (stages, barriers) = gfx_acquire_barriers(ctx, &buffers, &images);
+                encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+            unsafe {
+                encoder.bind_compute_pipeline(&pipeline);
+                encoder.bind_compute_descriptor_sets(
+                    &pipeline_layout,
+                    0,
+                    Some(sets[index].raw()),
+                    std::iter::empty(),
+                );
+                encoder.dispatch(
+                    (MAX_SKINNED_VERTICES as u32 + GROUP_SIZE - 1) / GROUP_SIZE,
+                    1,
+                    1,
+                );
+            }
+            {
+                let (stages, barriers) = gfx_release_barriers(ctx, &buffers, &images);
+                encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+            let (submit, command_buffer) = recording.finish().submit();
+            submits.push(submit);
+            command_buffers.push(command_buffer);
+        }
+
+        Ok(SkinNode {
+            align,
+            buffer,
+            sets,
+            pipeline_layout,
+            pipeline,
+            command_pool,
+            command_buffers,
+            submits,
+            uploaded: vec![Vec::new(); frames],
+        })
+    }
+}
+
+impl<B> Node<B, Scene> for SkinNode<B>
+where
+    B: hal::Backend,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        queue: &mut Queue<B>,
+        aux: &Scene,
+        frames: &Frames<B>,
+        waits: &[(&'a B::Semaphore, hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        let index = frames.next().index() as usize % self.submits.len();
+        debug!("Skin Node, Running {}.", index);
+
+        let _scope = aux.profiler.scope("skinning.upload");
+        let instances = resolve(aux);
+        let batches = batches(&instances);
+        let count: usize = instances
+            .iter()
+            .take(batches.len())
+            .map(|(_, mesh)| mesh.vertices.len())
+            .sum();
+
+        let key = meshes_key(&instances, &batches);
+        if self.uploaded[index] != key {
+            let sources: Vec<_> = instances
+                .iter()
+                .zip(batches.iter())
+                .flat_map(|((_, mesh), batch)| {
+                    let first_joint = batch.first_joint;
+                    mesh.vertices.iter().map(move |vertex| {
+                        let [x, y, z] = vertex.position;
+                        let [nx, ny, nz] = vertex.normal;
+                        let mut joints = vertex.joints;
+                        for joint in joints.iter_mut() {
+                            *joint += first_joint;
+                        }
+                        SkinSource {
+                            position: [x, y, z, 1.0],
+                            normal: [nx, ny, nz, 0.0],
+                            color: vertex.color,
+                            joints,
+                            weights: vertex.weights,
+                        }
+                    })
+                })
+                .collect();
+            if !sources.is_empty() {
+                unsafe {
+                    self.buffer
+                        .write(factory, sources_offset(index, self.align), &sources);
+                }
+            }
+            self.uploaded[index] = key;
+        }
+
+        let joints: Vec<Matrix4<f32>> = instances
+            .iter()
+            .take(batches.len())
+            .flat_map(|(instance, mesh)| {
+                (0..mesh.joint_count as u32).map(move |j| instance.joint(j))
+            })
+            .collect();
+
+        unsafe {
+            self.buffer.write(
+                factory,
+                args_offset(index, self.align),
+                &[SkinArgs {
+                    count: count as u32,
+                }],
+            );
+            if !joints.is_empty() {
+                self.buffer
+                    .write(factory, joints_offset(index, self.align), &joints);
+            }
+
+            queue.submit(
+                Some(
+                    Submission::new()
+                        .submits(Some(&self.submits[index]))
+                        .wait(waits.iter().cloned())
+                        .signal(signals.iter()),
+                ),
+                fence,
+            );
+        }
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &Scene) {
+        info!("Disposing Skin Node.");
+        drop(self.submits);
+        drop(self.sets);
+        self.command_pool.free_buffers(
+            self.command_buffers
+                .into_iter()
+                .map(|command_buffer| command_buffer.mark_complete()),
+        );
+        factory.destroy_command_pool(self.command_pool);
+        factory.device().destroy_compute_pipeline(self.pipeline);
+        factory
+            .device()
+            .destroy_pipeline_layout(self.pipeline_layout);
+    }
+}
+
+const UNIFORM_SIZE: u64 = size_of::<UniformArgs>() as u64;
+const MODELS_SIZE: u64 = (size_of::<InstanceData>() * MAX_SKINNED_INSTANCES) as u64;
+const INDICES_SIZE: u64 = (size_of::<u32>() * MAX_SKINNED_INDICES) as u64;
+
+/// Draws the skinned instances in the scene pass.
+///
+/// With `gpu_skinning` the vertices come from the pre-pass output buffer,
+/// otherwise they are skinned in `prepare` and uploaded with the models.
+#[derive(Debug, Default)]
+pub(crate) struct SkinnedDesc {
+    pub gpu_skinning: bool,
+    pub shading: ShadingModel,
+    pub tuning: ShaderTuning,
+    pub output: OutputEncoding,
+}
+
+pub(crate) struct Skinned<B: hal::Backend> {
+    align: u64,
+    buffer: MappedBuffer<B>,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    skinned: Option<Handle<Buffer<B>>>,
+    batches: Vec<Batch>,
+    /// Meshes whose indices are in the buffer of each frame.
+    uploaded: Vec<Vec<SkinnedMeshHandle>>,
+    vision: VisionBuffer<B>,
+    block_textures: BlockTextureBuffer<B>,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for Skinned<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Skinned({} drawn)", self.batches.len())
+    }
+}
+
+/// Size of the buffer of one frame, vertices included when skinned on the
+/// CPU.
+fn draw_frame_size(align: u64, cpu_skinning: bool) -> u64 {
+    let vertices = if cpu_skinning {
+        iceil(OUTPUT_SIZE, align)
+    } else {
+        0
+    };
+    iceil(UNIFORM_SIZE, align) + iceil(MODELS_SIZE, align) + iceil(INDICES_SIZE, align) + vertices
+}
+
+impl<B: hal::Backend> Skinned<B> {
+    fn uniform_offset(&self, index: usize) -> u64 {
+        draw_frame_size(self.align, self.skinned.is_none()) * index as u64
+    }
+
+    fn models_offset(&self, index: usize) -> u64 {
+        self.uniform_offset(index) + iceil(UNIFORM_SIZE, self.align)
+    }
+
+    fn indices_offset(&self, index: usize) -> u64 {
+        self.models_offset(index) + iceil(MODELS_SIZE, self.align)
+    }
+
+    fn vertices_offset(&self, index: usize) -> u64 {
+        self.indices_offset(index) + iceil(INDICES_SIZE, self.align)
+    }
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for SkinnedDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Skinned<B>;
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<hal::pso::Element<hal::format::Format>>,
+        hal::pso::ElemStride,
+        hal::pso::VertexInputRate,
+    )> {
+        vec![
+            VoxelVertex::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex),
+            InstanceData::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Instance(1)),
+        ]
+    }
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        if !self.gpu_skinning {
+            return Vec::new();
+        }
+        vec![BufferAccess {
+            access: hal::buffer::Access::VERTEX_BUFFER_READ,
+            stages: hal::pso::PipelineStage::VERTEX_INPUT,
+            usage: hal::buffer::Usage::VERTEX,
+        }]
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        SHADERS
+            .build(
+                factory,
+                fragment_constants(self.shading, &self.tuning, self.output),
+            )
+            .unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        layout::resolve("skinned", scene_layout(), &SHADERS)
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        aux: &Scene,
+        buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        let frames = ctx.frames_in_flight as usize;
+        let align = factory
+            .physical()
+            .limits()
+            .min_uniform_buffer_offset_alignment;
+        let skinned = buffers
+            .first()
+            .map(|buffer| ctx.get_buffer(buffer.id).unwrap().clone());
+
+        let buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: draw_frame_size(align, skinned.is_none()) * frames as u64,
+                usage: hal::buffer::Usage::UNIFORM
+                    | hal::buffer::Usage::VERTEX
+                    | hal::buffer::Usage::INDEX,
+            },
+        );
+        let mut pipeline = Skinned {
+            align,
+            buffer,
+            sets: Vec::new(),
+            skinned,
+            batches: Vec::new(),
+            uploaded: vec![Vec::new(); frames],
+            vision: VisionBuffer::new(factory, frames),
+            block_textures: BlockTextureBuffer::new(factory, frames),
+        };
+
+        let _scope = aux.profiler.scope("skinning.descriptors");
+        pipeline.sets = (0..frames)
+            .map(|_| {
+                factory
+                    .create_descriptor_set(set_layouts[0].clone())
+                    .unwrap()
+            })
+            .collect();
+        unsafe {
+            let pipeline = &pipeline;
+            factory.write_descriptor_sets(pipeline.sets.iter().enumerate().flat_map(
+                |(index, set)| {
+                    let offset = pipeline.uniform_offset(index);
+                    vec![
+                        hal::pso::DescriptorSetWrite {
+                            set: set.raw(),
+                            binding: 0,
+                            array_offset: 0,
+                            descriptors: Some(hal::pso::Descriptor::Buffer(
+                                pipeline.buffer.raw(),
+                                Some(offset)..Some(offset + UNIFORM_SIZE),
+                            )),
+                        },
+                        hal::pso::DescriptorSetWrite {
+                            set: set.raw(),
+                            binding: 1,
+                            array_offset: 0,
+                            descriptors: Some(pipeline.vision.descriptor(index)),
+                        },
+                        hal::pso::DescriptorSetWrite {
+                            set: set.raw(),
+                            binding: 2,
+                            array_offset: 0,
+                            descriptors: Some(pipeline.block_textures.descriptor(index)),
+                        },
+                    ]
+                },
+            ));
+        }
+        Ok(pipeline)
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Skinned<B>
+where
+    B: hal::Backend,
+{
+    type Desc = SkinnedDesc;
+
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        index: usize,
+        aux: &Scene,
+    ) -> PrepareResult {
+        let instances = resolve(aux);
+        self.batches = batches(&instances);
+        if self.batches.is_empty() {
+            return PrepareResult::DrawRecord;
+        }
+
+        let models = batch_models(aux, &instances, &self.batches);
+        unsafe {
+            self.buffer
+                .write(factory, self.uniform_offset(index), &[uniform_args(aux)]);
+            self.buffer
+                .write(factory, self.models_offset(index), &models);
+        }
+        self.vision.upload(factory, index, aux);
+        self.block_textures.upload(factory, index, aux);
+
+        let key = meshes_key(&instances, &self.batches);
+        if self.uploaded[index] != key {
+            let indices = batch_indices(&instances, &self.batches);
+            unsafe {
+                self.buffer
+                    .write(factory, self.indices_offset(index), &indices);
+            }
+            self.uploaded[index] = key;
+        }
+
+        if self.skinned.is_none() {
+            let _scope = aux.profiler.scope("skinning.cpu");
+            let vertices: Vec<_> = instances
+                .iter()
+                .take(self.batches.len())
+                .flat_map(|(instance, mesh)| {
+                    mesh.vertices.iter().map(move |vertex| {
+                        let (position, normal) = skin(vertex, &instance.pose);
+                        let position: [f32; 3] = position.coords.into();
+                        let normal: [f32; 3] = normal.into();
+                        VoxelVertex {
+                            position: position.into(),
+                            color: vertex.color.into(),
+                            normal: normal.into(),
+                            flags: AnimFlags::NONE,
+                        }
+                    })
+                })
+                .collect();
+            unsafe {
+                self.buffer
+                    .write(factory, self.vertices_offset(index), &vertices);
+            }
+        }
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        index: usize,
+        _aux: &Scene,
+    ) {
+        if self.batches.is_empty() {
+            return;
+        }
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(
+                layout,
+                0,
+                Some(self.sets[index].raw()),
+                std::iter::empty(),
+            );
+            let vertices = match self.skinned {
+                Some(ref skinned) => (skinned.raw(), 0),
+                None => (self.buffer.raw(), self.vertices_offset(index)),
+            };
+            encoder.bind_vertex_buffers(
+                0,
+                vec![vertices, (self.buffer.raw(), self.models_offset(index))],
+            );
+            encoder.bind_index_buffer(
+                self.buffer.raw(),
+                self.indices_offset(index),
+                hal::IndexType::U32,
+            );
+            for (instance, batch) in self.batches.iter().enumerate() {
+                let instance = instance as u32;
+                encoder.draw_indexed(
+                    batch.first_index..batch.first_index + batch.index_count,
+                    batch.first_vertex as i32,
+                    instance..instance + 1,
+                );
+            }
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {
+        info!("Disposing Skinned.");
+    }
+}
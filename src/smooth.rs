@@ -0,0 +1,424 @@
+//! Smooth meshing of density fields.
+//!
+//! A `DensityGrid` samples a field at the corners of the cells of a chunk,
+//! negative inside the terrain and positive outside. The surface is where
+//! it crosses zero, extracted with surface nets (one vertex per cell, quads
+//! across the crossing edges) or marching cubes (triangles inside each
+//! cell). Normals follow the gradient of the field rather than the
+//! triangles, so shading stays smooth across them.
+
+use crate::chunk::{Chunk, AIR};
+use crate::coords::CHUNK_SIZE;
+use crate::meshing::{MeshData, VoxelStyle};
+
+const SIZE: usize = CHUNK_SIZE as usize;
+
+/// Samples along each axis of a `DensityGrid`, the cell corners of a chunk.
+pub const POINTS: usize = SIZE + 1;
+
+/// Density field sampled at the cell corners of a chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityGrid {
+    /// Samples along each axis, `POINTS` or one more with an apron.
+    points: usize,
+    values: Box<[f32]>,
+}
+
+impl DensityGrid {
+    /// Sample `density` at each corner, from `(0, 0, 0)` to `(SIZE, SIZE, SIZE)`.
+    pub fn from_fn<F>(density: F) -> Self
+    where
+        F: Fn(usize, usize, usize) -> f32,
+    {
+        DensityGrid::sample(POINTS, density)
+    }
+
+    /// Sample `density` like `from_fn` plus one layer past the high faces,
+    /// inside the neighbour chunks.
+    ///
+    /// The apron gives the cells across the high faces, so the meshers
+    /// which support it join the chunk to its neighbours without gaps.
+    pub fn from_fn_with_apron<F>(density: F) -> Self
+    where
+        F: Fn(usize, usize, usize) -> f32,
+    {
+        DensityGrid::sample(POINTS + 1, density)
+    }
+
+    fn sample<F>(points: usize, density: F) -> Self
+    where
+        F: Fn(usize, usize, usize) -> f32,
+    {
+        let mut values = Vec::with_capacity(points * points * points);
+        for z in 0..points {
+            for y in 0..points {
+                for x in 0..points {
+                    values.push(density(x, y, z));
+                }
+            }
+        }
+        DensityGrid {
+            points,
+            values: values.into_boxed_slice(),
+        }
+    }
+
+    /// Samples along each axis.
+    pub fn points(&self) -> usize {
+        self.points
+    }
+
+    pub fn has_apron(&self) -> bool {
+        self.points > POINTS
+    }
+
+    /// Density of the voxels of `chunk`, sampled at their centers.
+    ///
+    /// The samples are half a voxel off the corners, the surface between a
+    /// solid and an air voxel lies on their shared face once `mesh_chunk`
+    /// shifts the vertices back. Samples past the chunk are air.
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        DensityGrid::from_fn(|x, y, z| {
+            if x < SIZE && y < SIZE && z < SIZE && chunk.get_voxel(x, y, z) != AIR {
+                -1.0
+            } else {
+                1.0
+            }
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[x + (y + z * self.points) * self.points]
+    }
+
+    /// Gradient of the field at a corner, one sided on the borders.
+    pub fn corner_gradient(&self, x: usize, y: usize, z: usize) -> [f32; 3] {
+        let diff = |lo: (usize, usize, usize), hi: (usize, usize, usize), span: usize| {
+            (self.get(hi.0, hi.1, hi.2) - self.get(lo.0, lo.1, lo.2)) / span as f32
+        };
+        let range = |v: usize| {
+            let lo = v.saturating_sub(1);
+            let hi = (v + 1).min(self.points - 1);
+            (lo, hi, hi - lo)
+        };
+        let (x0, x1, dx) = range(x);
+        let (y0, y1, dy) = range(y);
+        let (z0, z1, dz) = range(z);
+        [
+            diff((x0, y, z), (x1, y, z), dx),
+            diff((x, y0, z), (x, y1, z), dy),
+            diff((x, y, z0), (x, y, z1), dz),
+        ]
+    }
+
+    /// Gradient at any point of the grid, interpolated between the corners.
+    pub fn gradient(&self, position: [f32; 3]) -> [f32; 3] {
+        let max = (self.points - 1) as f32;
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for ((base, frac), p) in base.iter_mut().zip(&mut frac).zip(&position) {
+            let p = p.max(0.0).min(max);
+            *base = (p.floor() as usize).min(self.points - 2);
+            *frac = p - *base as f32;
+        }
+        let mut gradient = [0.0; 3];
+        for corner in 0..8 {
+            let offset = corner_offset(corner);
+            let weight = (0..3)
+                .map(|axis| {
+                    if offset[axis] == 1 {
+                        frac[axis]
+                    } else {
+                        1.0 - frac[axis]
+                    }
+                })
+                .product::<f32>();
+            let g = self.corner_gradient(
+                base[0] + offset[0],
+                base[1] + offset[1],
+                base[2] + offset[2],
+            );
+            for (sum, g) in gradient.iter_mut().zip(&g) {
+                *sum += g * weight;
+            }
+        }
+        gradient
+    }
+
+    /// Surface normal at `position`, pointing out of the terrain.
+    pub fn normal(&self, position: [f32; 3]) -> [f32; 3] {
+        let [x, y, z] = self.gradient(position);
+        let length = (x * x + y * y + z * z).sqrt();
+        if length > std::f32::EPSILON {
+            [x / length, y / length, z / length]
+        } else {
+            [0.0, 1.0, 0.0]
+        }
+    }
+
+    pub(crate) fn solid(&self, x: usize, y: usize, z: usize) -> bool {
+        self.get(x, y, z) < 0.0
+    }
+
+    /// Densities of the 8 corners of a cell, indexed like `corner_offset`.
+    pub(crate) fn cell(&self, x: usize, y: usize, z: usize) -> [f32; 8] {
+        let mut corners = [0.0; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let o = corner_offset(i);
+            *corner = self.get(x + o[0], y + o[1], z + o[2]);
+        }
+        corners
+    }
+}
+
+/// Position of corner `i` in its cell, bit 0 is X, bit 1 Y and bit 2 Z.
+pub(crate) fn corner_offset(i: usize) -> [usize; 3] {
+    [i & 1, (i >> 1) & 1, (i >> 2) & 1]
+}
+
+pub(crate) fn corner_position(x: usize, y: usize, z: usize, i: usize) -> [f32; 3] {
+    let o = corner_offset(i);
+    [(x + o[0]) as f32, (y + o[1]) as f32, (z + o[2]) as f32]
+}
+
+/// Point of the edge between two corners where the density crosses zero.
+pub(crate) fn crossing(a: [f32; 3], da: f32, b: [f32; 3], db: f32) -> [f32; 3] {
+    let t = if (da - db).abs() > std::f32::EPSILON {
+        (da / (da - db)).max(0.0).min(1.0)
+    } else {
+        0.5
+    };
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn push_vertex(
+    grid: &DensityGrid,
+    position: [f32; 3],
+    style: VoxelStyle,
+    out: &mut MeshData,
+) -> u32 {
+    let normal = grid.normal(position);
+    // Upward facing vertices stand in for the top of the blocky faces.
+    let flags = if normal[1] > 0.0 { style.flags } else { 0 };
+    out.push_vertex(position, normal, style.color, flags);
+    out.vertex_count() as u32 - 1
+}
+
+/// Mesh the surface of `grid` with surface nets into `out`.
+///
+/// `style` gives the appearance of the vertex of the cell whose lowest
+/// corner is `(x, y, z)`.
+pub fn surface_nets<F>(grid: &DensityGrid, style: F, out: &mut MeshData)
+where
+    F: Fn(usize, usize, usize) -> VoxelStyle,
+{
+    out.clear();
+    let cell_index = |x: usize, y: usize, z: usize| x + (y + z * SIZE) * SIZE;
+    let mut vertices = vec![u32::max_value(); SIZE * SIZE * SIZE];
+
+    // One vertex per crossed cell, at the average of its edge crossings.
+    for z in 0..SIZE {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let densities = grid.cell(x, y, z);
+                let mut sum = [0.0; 3];
+                let mut count = 0;
+                for &(a, b) in &EDGES {
+                    if (densities[a] < 0.0) == (densities[b] < 0.0) {
+                        continue;
+                    }
+                    let point = |i| corner_position(x, y, z, i);
+                    let p = crossing(point(a), densities[a], point(b), densities[b]);
+                    for (sum, p) in sum.iter_mut().zip(&p) {
+                        *sum += p;
+                    }
+                    count += 1;
+                }
+                if count == 0 {
+                    continue;
+                }
+                let position = [
+                    sum[0] / count as f32,
+                    sum[1] / count as f32,
+                    sum[2] / count as f32,
+                ];
+                vertices[cell_index(x, y, z)] = push_vertex(grid, position, style(x, y, z), out);
+            }
+        }
+    }
+
+    // A quad across each crossed edge, joining the 4 cells around it.
+    for z in 0..POINTS {
+        for y in 0..POINTS {
+            for x in 0..POINTS {
+                let p = [x, y, z];
+                for axis in 0..3 {
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                    // Edges on the border lack some of their cells.
+                    let inner = |c: usize| c > 0 && c < SIZE;
+                    if p[axis] >= SIZE || !inner(p[u]) || !inner(p[v]) {
+                        continue;
+                    }
+                    let mut q = p;
+                    q[axis] += 1;
+                    let inside = grid.solid(x, y, z);
+                    if inside == grid.solid(q[0], q[1], q[2]) {
+                        continue;
+                    }
+                    let cell = |du: usize, dv: usize| {
+                        let mut c = p;
+                        c[u] -= du;
+                        c[v] -= dv;
+                        vertices[cell_index(c[0], c[1], c[2])]
+                    };
+                    // Around the edge counter clockwise seen from its end.
+                    let quad = [cell(1, 1), cell(0, 1), cell(0, 0), cell(1, 0)];
+                    if quad.contains(&u32::max_value()) {
+                        continue;
+                    }
+                    // The surface faces away from the solid end of the edge.
+                    if inside {
+                        out.indices.extend_from_slice(&[
+                            quad[0], quad[1], quad[2], quad[0], quad[2], quad[3],
+                        ]);
+                    } else {
+                        out.indices.extend_from_slice(&[
+                            quad[0], quad[2], quad[1], quad[0], quad[3], quad[2],
+                        ]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Corners joined by the 12 edges of a cell.
+pub(crate) const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+lazy_static::lazy_static! {
+    /// Triangles of each marching cubes case, as edge indices.
+    static ref TRIANGLES: Vec<Vec<[usize; 3]>> = (0..256).map(cube_case).collect();
+}
+
+fn edge_index(a: usize, b: usize) -> usize {
+    EDGES
+        .iter()
+        .position(|&(x, y)| (x, y) == (a, b) || (x, y) == (b, a))
+        .unwrap()
+}
+
+/// Triangles of the cube whose solid corners are the set bits of `case`.
+///
+/// Walking each face counter clockwise from outside, a segment joins the
+/// edge entering a run of solid corners to the edge leaving it, which keeps
+/// diagonal solid corners apart. Every crossed edge ends a segment on one
+/// face and starts one on the other, so the segments chain into loops,
+/// which are triangulated as fans.
+fn cube_case(case: usize) -> Vec<[usize; 3]> {
+    let solid = |corner: usize| case & (1 << corner) != 0;
+    let mut next = [None; 12];
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for side in 0..2 {
+            let corner = |cu: usize, cv: usize| (side << axis) | (cu << u) | (cv << v);
+            let mut ring = [corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1)];
+            if side == 0 {
+                ring.reverse();
+            }
+            for start in 0..4 {
+                let entering = !solid(ring[start]) && solid(ring[(start + 1) % 4]);
+                if !entering {
+                    continue;
+                }
+                let mut end = (start + 1) % 4;
+                while solid(ring[(end + 1) % 4]) {
+                    end = (end + 1) % 4;
+                }
+                let from = edge_index(ring[start], ring[(start + 1) % 4]);
+                let to = edge_index(ring[end], ring[(end + 1) % 4]);
+                next[from] = Some(to);
+            }
+        }
+    }
+
+    let mut triangles = Vec::new();
+    let mut visited = [false; 12];
+    for first in 0..12 {
+        if visited[first] || next[first].is_none() {
+            continue;
+        }
+        let mut polygon = Vec::new();
+        let mut edge = first;
+        while !visited[edge] {
+            visited[edge] = true;
+            polygon.push(edge);
+            edge = next[edge].expect("marching cubes loops are closed");
+        }
+        for i in 1..polygon.len() - 1 {
+            triangles.push([polygon[0], polygon[i], polygon[i + 1]]);
+        }
+    }
+    triangles
+}
+
+/// Mesh the surface of `grid` with marching cubes into `out`.
+///
+/// `style` gives the appearance of the triangles of the cell whose lowest
+/// corner is `(x, y, z)`.
+pub fn marching_cubes<F>(grid: &DensityGrid, style: F, out: &mut MeshData)
+where
+    F: Fn(usize, usize, usize) -> VoxelStyle,
+{
+    out.clear();
+    for z in 0..SIZE {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let densities = grid.cell(x, y, z);
+                let case = densities
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &d)| d < 0.0)
+                    .fold(0, |case, (i, _)| case | 1 << i);
+                let triangles = &TRIANGLES[case];
+                if triangles.is_empty() {
+                    continue;
+                }
+                let cell_style = style(x, y, z);
+                let mut vertices = [None; 12];
+                for triangle in triangles {
+                    for &edge in triangle {
+                        let index = match vertices[edge] {
+                            Some(index) => index,
+                            None => {
+                                let (a, b) = EDGES[edge];
+                                let point = |i| corner_position(x, y, z, i);
+                                let p = crossing(point(a), densities[a], point(b), densities[b]);
+                                let index = push_vertex(grid, p, cell_style, out);
+                                vertices[edge] = Some(index);
+                                index
+                            }
+                        };
+                        out.indices.push(index);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,362 @@
+//! Loading screen drawn while the renderer and the world are being built.
+//!
+//! A `SplashScreen` owns a small graph of its own presenting to the window
+//! surface, a logo and a progress bar over a plain background. It only
+//! needs the factory, so it can be drawn as soon as the window exists and
+//! disposed once the renderer is ready to take the surface over.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use rendy::command::{Families, QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    Layout, PrepareResult, RenderGroupBuilder, SetLayout, SimpleGraphicsPipeline,
+    SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{Graph, GraphBuildError, GraphBuilder, GraphContext, NodeBuffer, NodeImage};
+use rendy::hal;
+use rendy::init::winit::window::Window;
+use rendy::resource::{BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+use rendy::wsi::Surface;
+
+use crate::color::{Color, OutputEncoding};
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::mapped::MappedBuffer;
+use crate::material::Texture;
+use crate::streamer::LoadProgress;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../crossfade.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/crossfade.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../splash.frag"));
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/splash.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Look of the loading screen.
+#[derive(Debug, Clone)]
+pub struct SplashSettings {
+    /// Drawn centered, scaled down to fit.
+    pub logo: Option<Texture>,
+    pub background: Color,
+
+    /// Filled part of the progress bar.
+    pub bar: Color,
+
+    /// Unfilled part of the progress bar.
+    pub track: Color,
+
+    /// See `ShaderTuning::output_gamma`.
+    pub output_gamma: Option<f32>,
+}
+
+impl Default for SplashSettings {
+    fn default() -> Self {
+        SplashSettings {
+            logo: None,
+            background: Color::BLACK,
+            bar: Color::WHITE,
+            track: Color::rgb(0.05, 0.05, 0.05),
+            output_gamma: None,
+        }
+    }
+}
+
+/// Fraction shown by the progress bar, cloned into the loading code.
+#[derive(Debug, Clone, Default)]
+pub struct SplashProgress(Arc<AtomicU32>);
+
+impl SplashProgress {
+    /// Set the loaded fraction, clamped to `0.0..=1.0`.
+    pub fn set(&self, fraction: f32) {
+        let fraction = fraction.max(0.0).min(1.0);
+        self.0.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Show the progress of a world load.
+    pub fn set_load(&self, progress: &LoadProgress) {
+        self.set(progress.fraction());
+    }
+}
+
+/// Auxiliary data of the loading screen graph.
+#[derive(Debug)]
+struct SplashState {
+    settings: SplashSettings,
+    progress: SplashProgress,
+}
+
+/// Loading screen presenting to a window surface.
+///
+/// Like a render graph it borrows the factory and the families on each
+/// call, so they can be handed to `RendererBuilder::build` once `dispose`
+/// released the surface. The graph is built at the window size, a resized
+/// window stretches it.
+pub struct SplashScreen<B: hal::Backend> {
+    graph: Graph<B, SplashState>,
+    state: SplashState,
+}
+
+impl<B: hal::Backend> SplashScreen<B> {
+    pub fn build(
+        factory: &mut Factory<B>,
+        families: &mut Families<B>,
+        surface: Surface<B>,
+        window: &Window,
+        settings: SplashSettings,
+    ) -> Result<Self, GraphBuildError> {
+        let size = window.inner_size();
+        let output =
+            OutputEncoding::for_format(factory.get_surface_format(&surface), settings.output_gamma);
+        let clear = Some(hal::command::ClearValue {
+            color: hal::command::ClearColor {
+                float32: output.encode(settings.background),
+            },
+        });
+        let state = SplashState {
+            settings,
+            progress: SplashProgress::default(),
+        };
+
+        let mut graph_builder = GraphBuilder::<B, SplashState>::new();
+        graph_builder.add_node(
+            SplashDesc {
+                width: size.width as u32,
+                height: size.height as u32,
+                output,
+            }
+            .builder()
+            .into_subpass()
+            .with_color_surface()
+            .into_pass()
+            .with_surface(
+                surface,
+                hal::window::Extent2D {
+                    width: size.width as _,
+                    height: size.height as _,
+                },
+                clear,
+            ),
+        );
+        let graph = graph_builder.build(factory, families, &state)?;
+        Ok(SplashScreen { graph, state })
+    }
+
+    /// Handle moving the progress bar, can be sent to other threads.
+    pub fn progress(&self) -> SplashProgress {
+        self.state.progress.clone()
+    }
+
+    /// Draw a frame.
+    pub fn render(&mut self, factory: &mut Factory<B>, families: &mut Families<B>) {
+        factory.maintain(families);
+        self.graph.run(factory, families, &self.state);
+    }
+
+    /// Wait for the frames in flight and release the graph and its surface.
+    pub fn dispose(self, factory: &mut Factory<B>) {
+        if let Err(err) = factory.wait_idle() {
+            error!("Failed to wait for the device: {:?}.", err);
+        }
+        self.graph.dispose(factory, &self.state);
+    }
+}
+
+impl<B: hal::Backend> std::fmt::Debug for SplashScreen<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SplashScreen({:.0}%)", self.state.progress.get() * 100.0)
+    }
+}
+
+/// Size of the push constants, in `u32`s.
+const CONSTANTS: usize = 13;
+
+/// Draws the logo and the progress bar over the cleared surface.
+#[derive(Debug, Default)]
+struct SplashDesc {
+    /// Size of the framebuffer drawn to.
+    width: u32,
+    height: u32,
+
+    /// Encoding of the colors written, see `OutputEncoding`.
+    output: OutputEncoding,
+}
+
+struct SplashPipeline<B: hal::Backend> {
+    /// Holds a single transparent texel without a logo.
+    logo: MappedBuffer<B>,
+    set: Escape<DescriptorSet<B>>,
+    constants: [u32; CONSTANTS],
+}
+
+impl<B: hal::Backend> std::fmt::Debug for SplashPipeline<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Splash Pipeline({} logo bytes)", self.logo.size())
+    }
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, SplashState> for SplashDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = SplashPipeline<B>;
+
+    fn colors(&self) -> Vec<hal::pso::ColorBlendDesc> {
+        vec![hal::pso::ColorBlendDesc {
+            mask: hal::pso::ColorMask::ALL,
+            blend: Some(hal::pso::BlendState::ALPHA),
+        }]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        None
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &SplashState) -> ShaderSet<B> {
+        let constants = SpecConstants::new().with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: vec![SetLayout {
+                bindings: vec![hal::pso::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: hal::pso::DescriptorType::StorageBuffer,
+                    count: 1,
+                    stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                }],
+            }],
+            push_constants: vec![(
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0..(CONSTANTS * 4) as u32,
+            )],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        aux: &SplashState,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        let settings = &aux.settings;
+        let (width, height, texels) = match settings.logo {
+            Some(ref logo) if !logo.texels.is_empty() => {
+                (logo.width, logo.height, &logo.texels[..])
+            }
+            _ => (0, 0, &[0u32][..]),
+        };
+        let mut logo = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: (texels.len() * 4) as u64,
+                usage: hal::buffer::Usage::STORAGE,
+            },
+        );
+        unsafe { logo.write(factory, 0, texels) };
+        let set = factory
+            .create_descriptor_set(set_layouts[0].clone())
+            .unwrap();
+        unsafe {
+            factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 0,
+                array_offset: 0,
+                descriptors: Some(hal::pso::Descriptor::Buffer(logo.raw(), None..None)),
+            }));
+        }
+
+        let mut constants = [0; CONSTANTS];
+        let bar = settings.bar.to_array();
+        let track = settings.track.to_array();
+        for (constant, value) in constants.iter_mut().zip(bar.iter().chain(&track)) {
+            *constant = value.to_bits();
+        }
+        constants[8] = (self.width as f32).to_bits();
+        constants[9] = (self.height as f32).to_bits();
+        constants[10] = width;
+        constants[11] = height;
+
+        Ok(SplashPipeline {
+            logo,
+            set,
+            constants,
+        })
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, SplashState> for SplashPipeline<B>
+where
+    B: hal::Backend,
+{
+    type Desc = SplashDesc;
+
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        aux: &SplashState,
+    ) -> PrepareResult {
+        self.constants[12] = aux.progress.get().to_bits();
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        _aux: &SplashState,
+    ) {
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(
+                layout,
+                0,
+                Some(self.set.raw()),
+                std::iter::empty(),
+            );
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.constants,
+            );
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &SplashState) {}
+}
@@ -0,0 +1,551 @@
+//! Chunk persistence in compressed region files.
+//!
+//! Chunks are grouped by `REGION_SIZE` along each axis in one file per
+//! region, each chunk compressed on its own with zstd. A dictionary trained
+//! on typical chunks with `train_dictionary` greatly improves the ratio of
+//! such small inputs, the same dictionary must be used to load them back.
+//! The default codec uses `DEFAULT_DICTIONARY`, trained on the terrain of
+//! the sandbox by the `train_dictionary` example.
+//! Painted faces are few, they are stored uncompressed after their chunk.
+//!
+//! Saves run as IO jobs of the `JobSystem`, the caller only pays for
+//! copying the chunks. A region has at most one save job at a time, saves
+//! queued meanwhile replace each other and the newest is written after it.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use crate::chunk::{Chunk, Side, VoxelId, VOLUME};
+use crate::color::Color;
+use crate::coords::{ChunkCoord, CHUNK_SIZE};
+use crate::jobs::{JobCategory, JobSystem};
+
+/// Number of chunks of a region along each axis.
+pub const REGION_SIZE: i64 = 8;
+
+/// Most chunks a region file can hold.
+const MAX_REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"AVRG";
+/// Version 2 added the painted faces and version 3 the dictionary id,
+/// older files still load.
+const VERSION: u32 = 3;
+
+/// Compression level of `ChunkCodec::default`, the dictionary only pays
+/// off from about this level.
+pub const DEFAULT_LEVEL: i32 = 9;
+
+/// Dictionary of `ChunkCodec::default`.
+pub const DEFAULT_DICTIONARY: &[u8] = include_bytes!("../data/chunks.dict");
+
+/// First bytes of the dictionaries made by zstd, followed by their id.
+const DICTIONARY_MAGIC: u32 = 0xEC30_A437;
+
+/// Suffix of the temporary file of the next write.
+static NEXT_WRITE: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes of a painted face: its voxel, its side and its color.
+const PAINT_BYTES: usize = 3 + 1 + 16;
+
+/// Position of a region in the world grid.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RegionCoord {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl RegionCoord {
+    /// Region containing `chunk`.
+    pub fn of(chunk: &ChunkCoord) -> Self {
+        RegionCoord {
+            x: chunk.x.div_euclid(REGION_SIZE),
+            y: chunk.y.div_euclid(REGION_SIZE),
+            z: chunk.z.div_euclid(REGION_SIZE),
+        }
+    }
+
+    /// File name of the region inside the save directory.
+    pub fn file_name(&self) -> String {
+        format!("r.{}.{}.{}.avr", self.x, self.y, self.z)
+    }
+}
+
+/// Compression of the voxels of single chunks, without their paint.
+#[derive(Debug, Clone)]
+pub struct ChunkCodec {
+    /// zstd compression level.
+    pub level: i32,
+
+    dictionary: Option<Arc<Vec<u8>>>,
+}
+
+impl ChunkCodec {
+    /// Codec without a dictionary.
+    pub fn new(level: i32) -> Self {
+        ChunkCodec {
+            level,
+            dictionary: None,
+        }
+    }
+
+    /// Compress with a dictionary from `train_dictionary`.
+    pub fn with_dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        self.dictionary = Some(Arc::new(dictionary));
+        self
+    }
+
+    /// Id zstd gave the dictionary, zero without one or for raw content.
+    pub fn dictionary_id(&self) -> u32 {
+        match self.dictionary {
+            Some(ref dictionary) if dictionary.len() >= 8 => {
+                let word = |at: usize| {
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&dictionary[at..at + 4]);
+                    u32::from_le_bytes(bytes)
+                };
+                if word(0) == DICTIONARY_MAGIC {
+                    word(4)
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    /// Encoder of many chunks, the dictionary is prepared once for all of
+    /// them.
+    pub fn encoder(&self) -> ChunkEncoder<'_> {
+        ChunkEncoder {
+            level: self.level,
+            context: zstd_safe::create_cctx(),
+            dictionary: self
+                .dictionary
+                .as_ref()
+                .map(|dictionary| EncoderDictionary::new(dictionary, self.level)),
+        }
+    }
+
+    /// Decoder of many chunks, see `encoder`.
+    pub fn decoder(&self) -> ChunkDecoder<'_> {
+        ChunkDecoder {
+            context: zstd_safe::create_dctx(),
+            dictionary: self
+                .dictionary
+                .as_ref()
+                .map(|dictionary| DecoderDictionary::new(dictionary)),
+        }
+    }
+
+    /// Encode a single chunk, use `encoder` for several.
+    pub fn encode(&self, chunk: &Chunk) -> io::Result<Vec<u8>> {
+        self.encoder().encode(chunk)
+    }
+
+    /// Decode a single chunk, use `decoder` for several.
+    pub fn decode(&self, data: &[u8]) -> io::Result<Chunk> {
+        self.decoder().decode(data)
+    }
+}
+
+/// Compression context of a `ChunkCodec` and its prepared dictionary.
+pub struct ChunkEncoder<'a> {
+    level: i32,
+    context: zstd_safe::CCtx<'static>,
+    dictionary: Option<EncoderDictionary<'a>>,
+}
+
+impl<'a> ChunkEncoder<'a> {
+    pub fn encode(&mut self, chunk: &Chunk) -> io::Result<Vec<u8>> {
+        let raw = chunk_bytes(chunk);
+        let mut data = vec![0; zstd_safe::compress_bound(raw.len())];
+        let len = match self.dictionary {
+            Some(ref dictionary) => zstd_safe::compress_using_cdict(
+                &mut self.context,
+                &mut data,
+                &raw,
+                dictionary.as_cdict(),
+            ),
+            None => zstd_safe::compress_cctx(&mut self.context, &mut data, &raw, self.level),
+        }
+        .map_err(zstd_error)?;
+        data.truncate(len);
+        Ok(data)
+    }
+}
+
+/// Decompression context of a `ChunkCodec` and its prepared dictionary.
+pub struct ChunkDecoder<'a> {
+    context: zstd_safe::DCtx<'static>,
+    dictionary: Option<DecoderDictionary<'a>>,
+}
+
+impl<'a> ChunkDecoder<'a> {
+    pub fn decode(&mut self, data: &[u8]) -> io::Result<Chunk> {
+        let mut raw = vec![0; VOLUME * std::mem::size_of::<VoxelId>()];
+        let len = match self.dictionary {
+            Some(ref dictionary) => zstd_safe::decompress_using_ddict(
+                &mut self.context,
+                &mut raw,
+                data,
+                dictionary.as_ddict(),
+            ),
+            None => zstd_safe::decompress_dctx(&mut self.context, &mut raw, data),
+        }
+        .map_err(zstd_error)?;
+        let voxels: Vec<_> = raw[..len]
+            .chunks_exact(2)
+            .map(|bytes| VoxelId::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        Chunk::from_voxels(voxels).ok_or_else(|| invalid("wrong chunk size"))
+    }
+}
+
+impl<'a> std::fmt::Debug for ChunkEncoder<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ChunkEncoder({})", self.level)
+    }
+}
+
+impl<'a> std::fmt::Debug for ChunkDecoder<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ChunkDecoder")
+    }
+}
+
+impl Default for ChunkCodec {
+    fn default() -> Self {
+        ChunkCodec::new(DEFAULT_LEVEL).with_dictionary(DEFAULT_DICTIONARY.to_vec())
+    }
+}
+
+fn chunk_bytes(chunk: &Chunk) -> Vec<u8> {
+    chunk
+        .voxels()
+        .iter()
+        .flat_map(|id| id.to_le_bytes().to_vec())
+        .collect()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Error of a failed zstd call, corrupt data is the likely cause.
+fn zstd_error(code: usize) -> io::Error {
+    invalid(zstd_safe::get_error_name(code))
+}
+
+/// Train a zstd dictionary of at most `max_size` bytes on sample chunks.
+pub fn train_dictionary(chunks: &[Chunk], max_size: usize) -> io::Result<Vec<u8>> {
+    let samples: Vec<_> = chunks.iter().map(chunk_bytes).collect();
+    zstd::dict::from_samples(&samples, max_size)
+}
+
+/// Write the chunks of one region, replacing the previous file atomically.
+pub fn write_region(
+    path: &Path,
+    codec: &ChunkCodec,
+    chunks: &[(ChunkCoord, Chunk)],
+) -> io::Result<()> {
+    let mut encoder = codec.encoder();
+    let mut blobs = Vec::with_capacity(chunks.len());
+    for (_, chunk) in chunks {
+        blobs.push(encoder.encode(chunk)?);
+    }
+
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&codec.dictionary_id().to_le_bytes());
+    data.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+    for ((coord, _), blob) in chunks.iter().zip(&blobs) {
+        data.extend_from_slice(&coord.x.to_le_bytes());
+        data.extend_from_slice(&coord.y.to_le_bytes());
+        data.extend_from_slice(&coord.z.to_le_bytes());
+        data.extend_from_slice(&(blob.len() as u32).to_le_bytes());
+    }
+    for ((_, chunk), blob) in chunks.iter().zip(&blobs) {
+        data.extend_from_slice(blob);
+        data.extend_from_slice(&(chunk.painted_faces().count() as u32).to_le_bytes());
+        for ((x, y, z), side, color) in chunk.painted_faces() {
+            data.extend_from_slice(&[x as u8, y as u8, z as u8, side.index() as u8]);
+            for channel in &color.to_array() {
+                data.extend_from_slice(&channel.to_le_bytes());
+            }
+        }
+    }
+
+    // Named after the write, concurrent writes of a region never share it.
+    let tmp = path.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        NEXT_WRITE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = fs::File::create(&tmp)
+        .and_then(|mut file| file.write_all(&data))
+        .and_then(|()| fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+/// Read all the chunks of a region file.
+pub fn read_region(path: &Path, codec: &ChunkCodec) -> io::Result<Vec<(ChunkCoord, Chunk)>> {
+    let mut data = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut data)?;
+    parse_region(&data, codec)
+}
+
+/// Decode the chunks of region file contents.
+///
+/// Malformed data returns an `InvalidData` error, allocations are bounded by
+/// the size of a region whatever the header claims. Regions written with
+/// another dictionary than the one of `codec` are refused, files older than
+/// version 3 don't say and are read with it.
+pub fn parse_region(data: &[u8], codec: &ChunkCodec) -> io::Result<Vec<(ChunkCoord, Chunk)>> {
+    let mut reader = Reader(data);
+    if reader.take(4)? != MAGIC {
+        return Err(invalid("not a region file"));
+    }
+    let version = reader.u32()?;
+    if version == 0 || version > VERSION {
+        return Err(invalid("unsupported region file version"));
+    }
+    if version >= 3 && reader.u32()? != codec.dictionary_id() {
+        return Err(invalid("region file compressed with another dictionary"));
+    }
+    let count = reader.u32()? as usize;
+    if count > MAX_REGION_CHUNKS {
+        return Err(invalid("too many chunks in region file"));
+    }
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let coord = ChunkCoord::new(reader.i64()?, reader.i64()?, reader.i64()?);
+        entries.push((coord, reader.u32()? as usize));
+    }
+
+    let mut decoder = codec.decoder();
+    let mut chunks = Vec::with_capacity(count);
+    for (coord, len) in entries {
+        let mut chunk = decoder.decode(reader.take(len)?)?;
+        if version >= 2 {
+            read_paint(&mut reader, &mut chunk)?;
+        }
+        chunks.push((coord, chunk));
+    }
+    Ok(chunks)
+}
+
+fn read_paint(reader: &mut Reader, chunk: &mut Chunk) -> io::Result<()> {
+    let count = reader.u32()? as usize;
+    if count > VOLUME * Side::ALL.len() {
+        return Err(invalid("too many painted faces in region file"));
+    }
+    let size = CHUNK_SIZE as usize;
+    for face in reader.take(count * PAINT_BYTES)?.chunks_exact(PAINT_BYTES) {
+        let (x, y, z) = (face[0] as usize, face[1] as usize, face[2] as usize);
+        let side = *Side::ALL
+            .get(face[3] as usize)
+            .ok_or_else(|| invalid("invalid painted face"))?;
+        if x >= size || y >= size || z >= size {
+            return Err(invalid("invalid painted face"));
+        }
+        let mut channels = [0.0; 4];
+        for (channel, bytes) in channels.iter_mut().zip(face[4..].chunks_exact(4)) {
+            *channel = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        chunk.paint_face(x, y, z, side, Color::from(channels));
+    }
+    Ok(())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid("truncated region file"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+}
+
+/// Saves not written yet.
+#[derive(Default)]
+struct Queued {
+    /// Newest chunks of each region waiting for its job.
+    chunks: HashMap<RegionCoord, Vec<(ChunkCoord, Chunk)>>,
+
+    /// Regions with a queued or running save job.
+    scheduled: HashSet<RegionCoord>,
+}
+
+/// Queued saves, `done` is signaled once none are left.
+#[derive(Default)]
+struct Pending {
+    queued: Mutex<Queued>,
+    done: Condvar,
+}
+
+/// Unschedules the region of a save job that panicked, so `flush` doesn't
+/// wait for it forever, and reports the panic as an error. Jobs that return
+/// unschedule their region themselves, along with taking its last chunks.
+struct PanicGuard {
+    region: RegionCoord,
+    pending: Arc<Pending>,
+    errors: Arc<Mutex<Vec<io::Error>>>,
+}
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            return;
+        }
+        let mut queued = self
+            .pending
+            .queued
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        queued.chunks.remove(&self.region);
+        queued.scheduled.remove(&self.region);
+        if queued.scheduled.is_empty() {
+            self.pending.done.notify_all();
+        }
+        let message = format!("save of region {:?} panicked", self.region);
+        self.errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(io::Error::new(io::ErrorKind::Other, message));
+    }
+}
+
+/// Writes regions with `JobCategory::Io` jobs.
+pub struct RegionSaver {
+    directory: PathBuf,
+    codec: ChunkCodec,
+    jobs: Arc<JobSystem>,
+    pending: Arc<Pending>,
+    errors: Arc<Mutex<Vec<io::Error>>>,
+}
+
+impl RegionSaver {
+    pub fn new(directory: impl Into<PathBuf>, codec: ChunkCodec, jobs: Arc<JobSystem>) -> Self {
+        RegionSaver {
+            directory: directory.into(),
+            codec,
+            jobs,
+            pending: Arc::new(Pending::default()),
+            errors: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Queue the save of `chunks`, grouped in one write per region.
+    ///
+    /// Chunks of a region missing from `chunks` are dropped from its file,
+    /// regions must be saved whole. A region whose previous save isn't
+    /// written yet is written once more after it, with the newest chunks.
+    pub fn save<I: IntoIterator<Item = (ChunkCoord, Chunk)>>(&self, chunks: I) {
+        let mut regions: HashMap<RegionCoord, Vec<(ChunkCoord, Chunk)>> = HashMap::new();
+        for (coord, chunk) in chunks {
+            regions
+                .entry(RegionCoord::of(&coord))
+                .or_default()
+                .push((coord, chunk));
+        }
+        let mut queued = self.pending.queued.lock().unwrap();
+        for (region, chunks) in regions {
+            queued.chunks.insert(region, chunks);
+            if !queued.scheduled.insert(region) {
+                continue;
+            }
+            let path = self.directory.join(region.file_name());
+            let codec = self.codec.clone();
+            let pending = self.pending.clone();
+            let errors = self.errors.clone();
+            self.jobs.spawn(JobCategory::Io, move || {
+                let guard = PanicGuard {
+                    region,
+                    pending,
+                    errors,
+                };
+                loop {
+                    let chunks = {
+                        let mut queued = guard.pending.queued.lock().unwrap();
+                        match queued.chunks.remove(&region) {
+                            Some(chunks) => chunks,
+                            None => {
+                                queued.scheduled.remove(&region);
+                                if queued.scheduled.is_empty() {
+                                    guard.pending.done.notify_all();
+                                }
+                                return;
+                            }
+                        }
+                    };
+                    if let Err(err) = write_region(&path, &codec, &chunks) {
+                        error!("Failed to save {}: {}.", path.display(), err);
+                        guard.errors.lock().unwrap().push(err);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Load a region written by this saver, empty if it was never saved.
+    pub fn load(&self, region: RegionCoord) -> io::Result<Vec<(ChunkCoord, Chunk)>> {
+        match read_region(&self.directory.join(region.file_name()), &self.codec) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            result => result,
+        }
+    }
+
+    /// Block until the queued saves are written, return their errors. Save
+    /// jobs that panicked return an error too, their region isn't written.
+    pub fn flush(&self) -> Vec<io::Error> {
+        let mut queued = self.pending.queued.lock().unwrap();
+        while !queued.scheduled.is_empty() {
+            queued = self.pending.done.wait(queued).unwrap();
+        }
+        drop(queued);
+        std::mem::replace(&mut *self.errors.lock().unwrap(), Vec::new())
+    }
+}
+
+impl Drop for RegionSaver {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl std::fmt::Debug for RegionSaver {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RegionSaver({})", self.directory.display())
+    }
+}
@@ -0,0 +1,323 @@
+//! Chunks generated on the worker threads around a moving center.
+//!
+//! Each missing chunk is generated by a `JobCategory::WorldGen` job and
+//! inserted into the world by the next `WorldStreamer::update`. Jobs can't be
+//! removed from the job queues once spawned, cancelled jobs are skipped by
+//! comparing the epoch they were spawned in with the current one.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use crate::chunk::Chunk;
+use crate::coords::ChunkCoord;
+use crate::jobs::{JobCategory, JobSystem};
+use crate::world::World;
+
+/// Fills a new chunk, called on the worker threads.
+pub type ChunkGenerator = Arc<dyn Fn(ChunkCoord, &mut Chunk) + Send + Sync>;
+
+/// Area kept loaded around the center.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StreamSettings {
+    /// Chunks loaded on each side of the center, horizontally.
+    pub radius: i64,
+
+    /// Vertical layers of chunks loaded, from `y = 0` up.
+    pub layers: i64,
+
+    /// Chunks kept past `radius` before being unloaded, so moving back and
+    /// forth over a chunk border doesn't generate the same chunks again.
+    pub unload_margin: i64,
+
+    /// Most generation jobs queued at once.
+    pub max_jobs: usize,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        StreamSettings {
+            radius: 6,
+            layers: 2,
+            unload_margin: 1,
+            max_jobs: 32,
+        }
+    }
+}
+
+/// What `WorldStreamer::flush` does with the outstanding jobs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Wait for the jobs and insert their chunks.
+    Complete,
+
+    /// Skip the jobs not started yet and drop the chunks of the others.
+    Cancel,
+}
+
+/// Stage of the load of the area around the center.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadPhase {
+    Generating,
+
+    /// Chunks are missing but the streamer is paused.
+    Paused,
+
+    /// Every chunk of the area is loaded.
+    Done,
+}
+
+impl LoadPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            LoadPhase::Generating => "generating",
+            LoadPhase::Paused => "paused",
+            LoadPhase::Done => "done",
+        }
+    }
+}
+
+/// How much of the area around the center is loaded, for loading screens.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub chunks_done: usize,
+    pub chunks_total: usize,
+    pub phase: LoadPhase,
+}
+
+impl LoadProgress {
+    /// Loaded part of the area, in `0.0..=1.0`.
+    pub fn fraction(&self) -> f32 {
+        if self.chunks_total == 0 {
+            1.0
+        } else {
+            self.chunks_done as f32 / self.chunks_total as f32
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == LoadPhase::Done
+    }
+}
+
+impl std::fmt::Display for LoadProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}/{} chunks",
+            self.phase.name(),
+            self.chunks_done,
+            self.chunks_total
+        )
+    }
+}
+
+struct Generated {
+    coord: ChunkCoord,
+    epoch: u64,
+    chunk: Option<Chunk>,
+}
+
+/// Loads the chunks around a center and unloads the far ones.
+pub struct WorldStreamer {
+    pub settings: StreamSettings,
+    generator: ChunkGenerator,
+    jobs: Arc<JobSystem>,
+    sender: Sender<Generated>,
+    receiver: Receiver<Generated>,
+
+    /// Chunks with a job spawned and not received yet.
+    pending: HashSet<ChunkCoord>,
+
+    /// Chunks never unloaded, such as those edited by the player.
+    pinned: HashSet<ChunkCoord>,
+
+    /// Incremented to cancel the jobs spawned before.
+    epoch: Arc<AtomicU64>,
+    paused: bool,
+}
+
+impl WorldStreamer {
+    pub fn new(settings: StreamSettings, jobs: Arc<JobSystem>, generator: ChunkGenerator) -> Self {
+        let (sender, receiver) = channel();
+        WorldStreamer {
+            settings,
+            generator,
+            jobs,
+            sender,
+            receiver,
+            pending: HashSet::new(),
+            pinned: HashSet::new(),
+            epoch: Arc::new(AtomicU64::new(0)),
+            paused: false,
+        }
+    }
+
+    /// Keep `coord` loaded wherever the center goes.
+    pub fn pin(&mut self, coord: ChunkCoord) {
+        self.pinned.insert(coord);
+    }
+
+    pub fn unpin(&mut self, coord: &ChunkCoord) {
+        self.pinned.remove(coord);
+    }
+
+    /// Stop spawning jobs and unloading chunks, the jobs already spawned
+    /// still complete and are inserted by `update`.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Chunks being generated.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Progress of the load of the area around `center`, poll it between
+    /// calls to `update` to draw a loading screen.
+    pub fn progress(&self, center: ChunkCoord, world: &World) -> LoadProgress {
+        let coords = self.area(center);
+        let chunks_total = coords.len();
+        let chunks_done = coords
+            .iter()
+            .filter(|coord| world.chunk(coord).is_some())
+            .count();
+        let phase = if chunks_done == chunks_total {
+            LoadPhase::Done
+        } else if self.paused {
+            LoadPhase::Paused
+        } else {
+            LoadPhase::Generating
+        };
+        LoadProgress {
+            chunks_done,
+            chunks_total,
+            phase,
+        }
+    }
+
+    /// Chunks loaded around `center`.
+    fn area(&self, center: ChunkCoord) -> Vec<ChunkCoord> {
+        let radius = self.settings.radius;
+        let mut coords = Vec::new();
+        for z in center.z - radius..=center.z + radius {
+            for x in center.x - radius..=center.x + radius {
+                for y in 0..self.settings.layers {
+                    coords.push(ChunkCoord::new(x, y, z));
+                }
+            }
+        }
+        coords
+    }
+
+    /// Insert the generated chunks, then unless paused spawn the jobs of the
+    /// missing chunks around `center`, closest first, and unload the far
+    /// ones. Returns the number of chunks inserted.
+    pub fn update(&mut self, center: ChunkCoord, world: &mut World) -> usize {
+        let mut inserted = 0;
+        while let Ok(generated) = self.receiver.try_recv() {
+            inserted += self.receive(generated, world) as usize;
+        }
+        if self.paused {
+            return inserted;
+        }
+
+        let pending = &self.pending;
+        let mut missing: Vec<_> = self
+            .area(center)
+            .into_iter()
+            .filter(|coord| world.chunk(coord).is_none() && !pending.contains(coord))
+            .collect();
+        missing.sort_by_key(|coord| {
+            let (dx, dz) = (coord.x - center.x, coord.z - center.z);
+            dx * dx + dz * dz
+        });
+        let free = self.settings.max_jobs.saturating_sub(self.pending.len());
+        for coord in missing.into_iter().take(free) {
+            self.spawn(coord);
+        }
+
+        let keep = self.settings.radius + self.settings.unload_margin;
+        let pinned = &self.pinned;
+        let far: Vec<_> = world
+            .chunks()
+            .map(|(coord, _)| *coord)
+            .filter(|coord| (coord.x - center.x).abs() > keep || (coord.z - center.z).abs() > keep)
+            .filter(|coord| !pinned.contains(coord))
+            .collect();
+        for coord in far {
+            world.remove_chunk(&coord);
+        }
+        inserted
+    }
+
+    /// Block until no job is outstanding, for saving and quitting or
+    /// switching to another world. Returns the number of chunks inserted.
+    pub fn flush(&mut self, world: &mut World, mode: FlushMode) -> usize {
+        if mode == FlushMode::Cancel {
+            self.epoch.fetch_add(1, Ordering::SeqCst);
+        }
+        let mut inserted = 0;
+        while !self.pending.is_empty() {
+            // Every spawned job sends once, cancelled or not.
+            let generated = self.receiver.recv().unwrap();
+            inserted += self.receive(generated, world) as usize;
+        }
+        inserted
+    }
+
+    fn spawn(&mut self, coord: ChunkCoord) {
+        self.pending.insert(coord);
+        let generator = self.generator.clone();
+        let sender = self.sender.clone();
+        let current = self.epoch.clone();
+        let epoch = current.load(Ordering::SeqCst);
+        self.jobs.spawn(JobCategory::WorldGen, move || {
+            let chunk = if current.load(Ordering::SeqCst) == epoch {
+                let mut chunk = Chunk::new();
+                generator(coord, &mut chunk);
+                Some(chunk)
+            } else {
+                None
+            };
+            let _ = sender.send(Generated {
+                coord,
+                epoch,
+                chunk,
+            });
+        });
+    }
+
+    /// Insert a received chunk unless cancelled or loaded in the meantime.
+    fn receive(&mut self, generated: Generated, world: &mut World) -> bool {
+        self.pending.remove(&generated.coord);
+        let current = self.epoch.load(Ordering::SeqCst) == generated.epoch;
+        match generated.chunk {
+            Some(chunk) if current && world.chunk(&generated.coord).is_none() => {
+                world.insert_chunk(generated.coord, chunk);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Debug for WorldStreamer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "WorldStreamer({} pending{})",
+            self.pending.len(),
+            if self.paused { ", paused" } else { "" }
+        )
+    }
+}
@@ -0,0 +1,113 @@
+//! Camera orbiting behind a target, kept out of the terrain.
+
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+
+use crate::coords::Location;
+use crate::scene::Scene;
+use crate::world::World;
+use crate::Inputs;
+
+/// Orbit-follow controller placing `Scene::camera` behind a target.
+///
+/// A sphere is swept from the pivot towards the wanted camera position, the
+/// camera stops where it touches the terrain so no voxel comes between it
+/// and the target. It pulls in at once and eases back out.
+#[derive(Debug, Clone)]
+pub struct ThirdPersonCamera {
+    /// Wanted distance from the pivot, in voxels.
+    pub distance: f32,
+
+    /// Closest the camera comes to the pivot when pulled in.
+    pub min_distance: f32,
+
+    /// Radius of the swept sphere, keeps the near plane out of the voxels.
+    pub radius: f32,
+
+    /// Offset of the pivot from the target, the camera looks at it.
+    pub pivot_offset: Vector3<f32>,
+
+    /// Speed the camera moves back out after being pulled in, in voxels
+    /// per second.
+    pub recover_speed: f32,
+
+    /// The rotation sensitivity, often linked to mouse movement.
+    pub sensitivity: f64,
+
+    /// Rotation around the vertical axis, in radians.
+    pub yaw: f32,
+
+    /// Rotation above the horizon, in radians, negative looks down.
+    pub pitch: f32,
+
+    current_distance: f32,
+}
+
+/// Pitch is kept away from the poles, the view would flip.
+const MAX_PITCH: f32 = 1.4;
+
+impl ThirdPersonCamera {
+    pub fn new(distance: f32) -> Self {
+        ThirdPersonCamera {
+            distance,
+            min_distance: 0.5,
+            radius: 0.3,
+            pivot_offset: Vector3::new(0.0, 1.6, 0.0),
+            recover_speed: 8.0,
+            sensitivity: 0.01,
+            yaw: 0.0,
+            pitch: -0.3,
+            current_distance: distance,
+        }
+    }
+
+    /// Distance from the pivot the camera was placed at by the last update.
+    pub fn current_distance(&self) -> f32 {
+        self.current_distance
+    }
+
+    /// Rotate around the target with the mouse and place the camera.
+    ///
+    /// Call `Scene::update_origin` afterwards, as after `Camera::run`.
+    pub fn run(
+        &mut self,
+        inputs: &Inputs,
+        target: &Location,
+        world: &World,
+        scene: &mut Scene,
+        delta_sec: f32,
+    ) {
+        self.yaw -= (inputs.mouse_x * self.sensitivity) as f32;
+        self.pitch += (inputs.mouse_y * self.sensitivity) as f32;
+        self.pitch = self.pitch.max(-MAX_PITCH).min(MAX_PITCH);
+        self.update(target, world, scene, delta_sec);
+    }
+
+    /// Place the camera behind `target` at the current yaw and pitch.
+    pub fn update(&mut self, target: &Location, world: &World, scene: &mut Scene, delta_sec: f32) {
+        let rotation = self.rotation();
+        let back = rotation * Vector3::z();
+        let mut pivot = *target;
+        pivot.translate(&self.pivot_offset);
+
+        let free = world
+            .spherecast(&pivot, &back, self.radius, self.distance)
+            .map_or(self.distance, |hit| hit.distance)
+            .max(self.min_distance);
+        self.current_distance = if free < self.current_distance {
+            free
+        } else {
+            (self.current_distance + self.recover_speed * delta_sec).min(free)
+        };
+
+        let mut eye = pivot;
+        eye.translate(&(back * self.current_distance));
+        let translation = scene.origin.to_render(&eye);
+        scene.camera.view = Isometry3::from_parts(Translation3::from(translation), rotation);
+    }
+
+    /// Orientation of the camera, looking down -Z like `Camera::run`.
+    pub fn rotation(&self) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.yaw)
+            * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.pitch)
+    }
+}
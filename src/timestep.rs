@@ -0,0 +1,273 @@
+//! Fixed length simulation ticks decoupled from the frame rate.
+//!
+//! Frames add their duration to an accumulator which is spent in whole
+//! ticks. The remainder is the `Scene::interpolation` the frame is drawn at.
+//!
+//! A `TickLoop` runs the callbacks of the game on each tick. They read the
+//! world as it was at the start of the tick and queue their edits, applied
+//! in order once every callback ran, so the result doesn't depend on which
+//! callback sees the edits of the others.
+
+use crate::chunk::{Side, VoxelId};
+use crate::color::Color;
+use crate::coords::{ChunkCoord, Location, WorldPos};
+use crate::rewind::RewindBuffer;
+use crate::sdf::CsgEdit;
+use crate::world::World;
+
+/// Simulation clock stepping in ticks of `tick_length` seconds.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    tick_length: f32,
+    accumulator: f32,
+    tick: u64,
+
+    /// Most ticks run by one `advance`, the rest of a long frame is dropped
+    /// so the simulation doesn't fall further behind.
+    pub max_ticks_per_frame: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_length: f32) -> Self {
+        FixedTimestep {
+            tick_length: tick_length.max(std::f32::EPSILON),
+            accumulator: 0.0,
+            tick: 0,
+            max_ticks_per_frame: 8,
+        }
+    }
+
+    /// Add a frame of `delta_sec` seconds and return the number of ticks to run.
+    pub fn advance(&mut self, delta_sec: f32) -> u32 {
+        self.accumulator += delta_sec.max(0.0);
+        let mut ticks = 0;
+        while self.accumulator >= self.tick_length {
+            self.accumulator -= self.tick_length;
+            ticks += 1;
+            if ticks == self.max_ticks_per_frame {
+                self.accumulator %= self.tick_length;
+                break;
+            }
+        }
+        self.tick += ticks as u64;
+        ticks
+    }
+
+    /// Number of ticks run since the start.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn tick_length(&self) -> f32 {
+        self.tick_length
+    }
+
+    /// Fraction of the next tick already elapsed, for `Scene::interpolation`.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.tick_length
+    }
+}
+
+/// Change of the world queued during a tick.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldEdit {
+    /// See `World::set_voxel`.
+    SetVoxel { pos: WorldPos, id: VoxelId },
+
+    /// See `World::add_damage`.
+    Damage { pos: WorldPos, stages: u8 },
+
+    /// See `World::paint_face`.
+    Paint {
+        pos: WorldPos,
+        side: Side,
+        color: Color,
+    },
+
+    /// See `World::edit_sdf`.
+    Sdf(CsgEdit),
+
+    /// See `World::explode`.
+    Explode {
+        center: Location,
+        radius: f32,
+        falloff: f32,
+    },
+}
+
+/// Edits queued by the callbacks of a tick, applied in order.
+#[derive(Debug, Clone, Default)]
+pub struct WorldCommands {
+    edits: Vec<WorldEdit>,
+}
+
+impl WorldCommands {
+    pub fn new() -> Self {
+        WorldCommands::default()
+    }
+
+    pub fn push(&mut self, edit: WorldEdit) {
+        self.edits.push(edit);
+    }
+
+    pub fn set_voxel(&mut self, pos: WorldPos, id: VoxelId) {
+        self.push(WorldEdit::SetVoxel { pos, id });
+    }
+
+    pub fn damage(&mut self, pos: WorldPos, stages: u8) {
+        self.push(WorldEdit::Damage { pos, stages });
+    }
+
+    pub fn paint(&mut self, pos: WorldPos, side: Side, color: Color) {
+        self.push(WorldEdit::Paint { pos, side, color });
+    }
+
+    pub fn edit_sdf(&mut self, edit: CsgEdit) {
+        self.push(WorldEdit::Sdf(edit));
+    }
+
+    pub fn explode(&mut self, center: Location, radius: f32, falloff: f32) {
+        self.push(WorldEdit::Explode {
+            center,
+            radius,
+            falloff,
+        });
+    }
+
+    /// Edits queued so far, oldest first.
+    pub fn edits(&self) -> &[WorldEdit] {
+        &self.edits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Apply the edits to `world` in order and clear them. Returns the
+    /// chunks changed, sorted, to be meshed again.
+    pub fn apply(&mut self, world: &mut World) -> Vec<ChunkCoord> {
+        let mut changed = Vec::new();
+        for edit in self.edits.drain(..) {
+            match edit {
+                WorldEdit::SetVoxel { pos, id } => {
+                    if world.get_voxel(&pos) != id && world.set_voxel(&pos, id) {
+                        changed.push(pos.chunk());
+                    }
+                }
+                WorldEdit::Damage { pos, stages } => {
+                    let before = world.damage(&pos);
+                    let destroyed = world.add_damage(&pos, stages).is_some();
+                    if destroyed || world.damage(&pos) != before {
+                        changed.push(pos.chunk());
+                    }
+                }
+                WorldEdit::Paint { pos, side, color } => {
+                    if world.paint_face(&pos, side, color) {
+                        changed.push(pos.chunk());
+                    }
+                }
+                WorldEdit::Sdf(edit) => changed.extend(world.edit_sdf(&edit)),
+                WorldEdit::Explode {
+                    center,
+                    radius,
+                    falloff,
+                } => changed.extend(world.explode(&center, radius, falloff).chunks),
+            }
+        }
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+}
+
+/// What a tick callback gets.
+pub struct TickContext<'a> {
+    /// Number of the tick, from 0.
+    pub tick: u64,
+
+    /// Simulated seconds of the tick.
+    pub tick_length: f32,
+
+    /// The world at the start of the tick.
+    pub world: &'a World,
+
+    /// Edits applied once every callback of the tick ran.
+    pub commands: &'a mut WorldCommands,
+}
+
+/// Callback run on each tick, see `TickLoop::on_tick`.
+pub type TickCallback = Box<dyn FnMut(&mut TickContext)>;
+
+/// Fixed timestep running callbacks on each tick, for the simulations of
+/// the world and the logic of the game to share one deterministic cadence.
+pub struct TickLoop {
+    pub timestep: FixedTimestep,
+
+    /// Keeps the chunks each tick changes so they can be put back, see
+    /// `with_rewind`.
+    pub rewind: Option<RewindBuffer>,
+    callbacks: Vec<TickCallback>,
+    commands: WorldCommands,
+}
+
+impl std::fmt::Debug for TickLoop {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "TickLoop({} callbacks)", self.callbacks.len())
+    }
+}
+
+impl TickLoop {
+    pub fn new(tick_length: f32) -> Self {
+        TickLoop {
+            timestep: FixedTimestep::new(tick_length),
+            rewind: None,
+            callbacks: Vec::new(),
+            commands: WorldCommands::new(),
+        }
+    }
+
+    /// Keep the last `seconds` of edits for debugging, see the `rewind`
+    /// module.
+    pub fn with_rewind(mut self, seconds: f32) -> Self {
+        self.rewind = Some(RewindBuffer::new(seconds, self.timestep.tick_length()));
+        self
+    }
+
+    /// Run `callback` on each tick, after those added before it.
+    pub fn on_tick<F>(&mut self, callback: F)
+    where
+        F: FnMut(&mut TickContext) + 'static,
+    {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Add a frame of `delta_sec` seconds and run the ticks it completes.
+    /// Returns the chunks changed by their edits, sorted.
+    pub fn advance(&mut self, delta_sec: f32, world: &mut World) -> Vec<ChunkCoord> {
+        let ticks = self.timestep.advance(delta_sec);
+        let first = self.timestep.tick() - ticks as u64;
+        let mut changed = Vec::new();
+        for tick in first..first + ticks as u64 {
+            changed.extend(self.run_tick(tick, world));
+        }
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+
+    fn run_tick(&mut self, tick: u64, world: &mut World) -> Vec<ChunkCoord> {
+        let mut context = TickContext {
+            tick,
+            tick_length: self.timestep.tick_length(),
+            world,
+            commands: &mut self.commands,
+        };
+        for callback in &mut self.callbacks {
+            callback(&mut context);
+        }
+        if let Some(rewind) = &mut self.rewind {
+            rewind.record_edits(tick, world, self.commands.edits());
+        }
+        self.commands.apply(world)
+    }
+}
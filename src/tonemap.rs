@@ -0,0 +1,283 @@
+//! High dynamic range rendering of the scene.
+//!
+//! With `RendererConfig::hdr` set the scene pass draws linear colors into a
+//! half float image instead of the final target, so lights and sky brighter
+//! than the surface can show aren't clamped while they blend. A pass of its
+//! own then scales them by the exposure, maps them to 0 to 1 with the
+//! tonemap curve and encodes them as the surface expects, see
+//! `OutputEncoding`. The crossfade and the console draw over the
+//! tonemapped frame.
+//!
+//! The surface itself stays SDR. gfx-hal 0.4 can't pick the color space of
+//! a swapchain, HDR10 and scRGB surfaces would show the values as sRGB.
+
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    Layout, PrepareResult, SetLayout, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{GraphContext, ImageAccess, NodeBuffer, NodeImage};
+use rendy::hal;
+use rendy::resource::{
+    DescriptorSet, DescriptorSetLayout, Escape, Filter, Handle, ImageView, ImageViewInfo,
+    SamplerDesc, ViewKind, WrapMode,
+};
+use rendy::shader::{
+    Shader, ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvShader,
+};
+
+use crate::color::{Color, OutputEncoding};
+use crate::glsl;
+use crate::gpu::specialization::SpecConstants;
+use crate::scene::Scene;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../crossfade.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/crossfade.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT_SOURCE: String = glsl::expand(include_str!("../tonemap.frag"));
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        &FRAGMENT_SOURCE,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tonemap.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: ShaderSetBuilder = ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Compile the shaders ahead of the first graph build.
+pub(crate) fn precompile() {
+    lazy_static::initialize(&SHADERS);
+}
+
+/// Format of the image the scene is drawn to.
+pub const FORMAT: hal::format::Format = hal::format::Format::Rgba16Sfloat;
+
+/// Curve mapping the exposed colors to 0 to 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TonemapCurve {
+    /// `c / (1 + c)` per channel, never reaches white.
+    Reinhard,
+
+    /// Fit of the ACES filmic curve by Krzysztof Narkowicz, more contrast
+    /// and white from about 10.
+    Aces,
+}
+
+impl TonemapCurve {
+    /// Id of the curve in the shader.
+    pub(crate) fn id(self) -> u32 {
+        match self {
+            TonemapCurve::Reinhard => 0,
+            TonemapCurve::Aces => 1,
+        }
+    }
+
+    /// Channel `c` mapped by the curve, as `tonemap.frag` does.
+    pub fn map(self, c: f32) -> f32 {
+        let c = c.max(0.0);
+        match self {
+            TonemapCurve::Reinhard => c / (1.0 + c),
+            TonemapCurve::Aces => {
+                let mapped = c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14);
+                mapped.min(1.0).max(0.0)
+            }
+        }
+    }
+}
+
+/// Tonemapping of the HDR scene, see `RendererConfig::hdr`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HdrSettings {
+    /// Scale of the linear colors before the curve, positive.
+    pub exposure: f32,
+
+    pub curve: TonemapCurve,
+}
+
+impl HdrSettings {
+    /// Linear `color` of the scene as tonemapped to the surface, before its
+    /// encoding. Alpha is kept.
+    pub fn apply(self, color: Color) -> Color {
+        let map = |c: f32| self.curve.map(c * self.exposure);
+        Color::new(map(color.r), map(color.g), map(color.b), color.a)
+    }
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        HdrSettings {
+            exposure: 1.0,
+            curve: TonemapCurve::Aces,
+        }
+    }
+}
+
+/// Fullscreen pass drawing the HDR image tonemapped, the first group of the
+/// pass to the final target.
+#[derive(Debug)]
+pub(crate) struct TonemapDesc {
+    pub settings: HdrSettings,
+    pub output: OutputEncoding,
+}
+
+pub(crate) struct Tonemap<B: hal::Backend> {
+    /// Kept alive for the set.
+    _view: Escape<ImageView<B>>,
+    set: Escape<DescriptorSet<B>>,
+}
+
+impl<B: hal::Backend> std::fmt::Debug for Tonemap<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Tonemap")
+    }
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for TonemapDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = Tonemap<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::SHADER_READ,
+            usage: hal::image::Usage::SAMPLED,
+            layout: hal::image::Layout::ShaderReadOnlyOptimal,
+            stages: hal::pso::PipelineStage::FRAGMENT_SHADER,
+        }]
+    }
+
+    fn colors(&self) -> Vec<hal::pso::ColorBlendDesc> {
+        vec![hal::pso::ColorBlendDesc {
+            mask: hal::pso::ColorMask::ALL,
+            blend: None,
+        }]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        None
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Scene) -> ShaderSet<B> {
+        let constants = SpecConstants::new()
+            .with_f32(0, self.settings.exposure)
+            .with_u32(1, self.settings.curve.id())
+            .with_output(self.output);
+        SHADERS.build(factory, constants.fragment()).unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: vec![SetLayout {
+                bindings: vec![hal::pso::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: hal::pso::DescriptorType::CombinedImageSampler,
+                    count: 1,
+                    stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                }],
+            }],
+            push_constants: Vec::new(),
+        }
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &Scene,
+        _buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, hal::pso::CreationError> {
+        let image = ctx.get_image(images[0].id).unwrap();
+        let view = factory
+            .create_image_view(
+                image.clone(),
+                ImageViewInfo {
+                    view_kind: ViewKind::D2,
+                    format: FORMAT,
+                    swizzle: hal::format::Swizzle::NO,
+                    range: hal::image::SubresourceRange {
+                        aspects: hal::format::Aspects::COLOR,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                },
+            )
+            .unwrap();
+        // Read texel for texel, the scene and the target have the same size.
+        let sampler = factory
+            .get_sampler(SamplerDesc::new(Filter::Nearest, WrapMode::Clamp))
+            .unwrap();
+
+        // The image is the same every frame, one set for all of them.
+        let set = factory
+            .create_descriptor_set(set_layouts[0].clone())
+            .unwrap();
+        unsafe {
+            factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 0,
+                array_offset: 0,
+                descriptors: Some(hal::pso::Descriptor::CombinedImageSampler(
+                    view.raw(),
+                    hal::image::Layout::ShaderReadOnlyOptimal,
+                    sampler.raw(),
+                )),
+            }));
+        }
+
+        Ok(Tonemap { _view: view, set })
+    }
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for Tonemap<B>
+where
+    B: hal::Backend,
+{
+    type Desc = TonemapDesc;
+
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        _aux: &Scene,
+    ) -> PrepareResult {
+        PrepareResult::DrawReuse
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        _aux: &Scene,
+    ) {
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(
+                layout,
+                0,
+                Some(self.set.raw()),
+                std::iter::empty(),
+            );
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &Scene) {}
+}
@@ -0,0 +1,74 @@
+//! Object transforms.
+
+use nalgebra::{Matrix4, Transform3, Translation3, UnitQuaternion, Vector3};
+
+/// Translation, rotation and scale, applied in reverse order.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::repeat(1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: UnitQuaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vector3<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_uniform_scale(self, scale: f32) -> Self {
+        self.with_scale(Vector3::repeat(scale))
+    }
+
+    /// Matrix scaling, then rotating, then translating a point.
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Translation3::from(self.translation).to_homogeneous()
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    /// Blend from `self` at `t = 0.0` to `other` at `t = 1.0`.
+    pub fn interpolate(&self, other: &Transform, t: f32) -> Self {
+        Transform {
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: self.scale.lerp(&other.scale, t),
+        }
+    }
+
+    pub fn to_transform3(&self) -> Transform3<f32> {
+        Transform3::from_matrix_unchecked(self.to_matrix())
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl From<Transform> for Transform3<f32> {
+    fn from(transform: Transform) -> Self {
+        transform.to_transform3()
+    }
+}
@@ -0,0 +1,156 @@
+//! Aliasing of transient render targets.
+//!
+//! Targets only used inside a frame are requested with the range of passes
+//! using them. Targets with the same description whose ranges don't overlap
+//! share a single graph image, the graph orders the passes and inserts the
+//! barriers between the users as for any other image.
+//!
+//! Targets read in a later frame, like the Hi-Z pyramid, must not go through
+//! the planner.
+
+use rendy::graph::{GraphBuilder, ImageId};
+use rendy::hal;
+
+/// Description and lifetime of a transient target.
+#[derive(Debug, Copy, Clone)]
+pub struct TransientImage {
+    pub kind: hal::image::Kind,
+    pub levels: u8,
+    pub format: hal::format::Format,
+
+    /// Clear on first use, only the first target of an aliased image can clear.
+    pub clear: Option<hal::command::ClearValue>,
+
+    /// Index of the first pass using the target, in submission order.
+    pub first: usize,
+
+    /// Index of the last pass using the target.
+    pub last: usize,
+}
+
+impl TransientImage {
+    fn compatible(&self, other: &TransientImage) -> bool {
+        self.kind == other.kind && self.levels == other.levels && self.format == other.format
+    }
+
+    /// Approximate memory size of the target in bytes.
+    pub fn bytes(&self) -> u64 {
+        let extent = self.kind.extent();
+        let texel = self.format.surface_desc().bits as u64 / 8;
+        let layers = self.kind.num_layers() as u64;
+        (0..self.levels as u32)
+            .map(|level| {
+                let level = extent.at_level(level as _);
+                level.width as u64 * level.height as u64 * level.depth as u64
+            })
+            .sum::<u64>()
+            * texel
+            * layers
+    }
+}
+
+/// Collects transient targets and assigns them to shared graph images.
+#[derive(Debug, Default)]
+pub struct TransientPlanner {
+    requests: Vec<TransientImage>,
+}
+
+impl TransientPlanner {
+    pub fn new() -> Self {
+        TransientPlanner::default()
+    }
+
+    /// Request a target, returns its index in the ids given by `build`.
+    pub fn request(&mut self, image: TransientImage) -> usize {
+        assert!(image.first <= image.last);
+        self.requests.push(image);
+        self.requests.len() - 1
+    }
+
+    /// Slot of each request, requests sharing a slot share an image.
+    pub fn plan(&self) -> Vec<usize> {
+        let mut order: Vec<_> = (0..self.requests.len()).collect();
+        order.sort_by_key(|&i| self.requests[i].first);
+
+        // Description of the first request and last pass of each slot.
+        let mut slots: Vec<(usize, usize)> = Vec::new();
+        let mut assignment = vec![0; self.requests.len()];
+        for i in order {
+            let request = &self.requests[i];
+            let free = slots.iter().position(|&(owner, last)| {
+                request.clear.is_none()
+                    && last < request.first
+                    && self.requests[owner].compatible(request)
+            });
+            assignment[i] = match free {
+                Some(slot) => {
+                    slots[slot].1 = request.last;
+                    slot
+                }
+                None => {
+                    slots.push((i, request.last));
+                    slots.len() - 1
+                }
+            };
+        }
+        assignment
+    }
+
+    /// Memory of the targets without and with aliasing, in bytes.
+    pub fn memory(&self) -> (u64, u64) {
+        let assignment = self.plan();
+        let total = self.requests.iter().map(TransientImage::bytes).sum();
+        let mut slots = vec![0; assignment.iter().max().map_or(0, |slot| slot + 1)];
+        for (request, &slot) in self.requests.iter().zip(&assignment) {
+            slots[slot] = request.bytes();
+        }
+        (total, slots.iter().sum())
+    }
+
+    /// Create one graph image per slot, return the image of each request.
+    pub fn build<B: hal::Backend, T: ?Sized>(
+        &self,
+        graph_builder: &mut GraphBuilder<B, T>,
+    ) -> Vec<ImageId> {
+        let assignment = self.plan();
+
+        // The earliest request of a slot describes the image and its clear.
+        let mut owners: Vec<Option<usize>> = vec![None; self.requests.len()];
+        for (i, &slot) in assignment.iter().enumerate() {
+            let owner = &mut owners[slot];
+            if owner.map_or(true, |owner| {
+                self.requests[i].first < self.requests[owner].first
+            }) {
+                *owner = Some(i);
+            }
+        }
+        let images: Vec<Option<ImageId>> = owners
+            .iter()
+            .map(|owner| {
+                owner.map(|owner| {
+                    let request = &self.requests[owner];
+                    graph_builder.create_image(
+                        request.kind,
+                        request.levels,
+                        request.format,
+                        request.clear,
+                    )
+                })
+            })
+            .collect();
+        let ids = assignment
+            .iter()
+            .map(|&slot| images[slot].unwrap())
+            .collect();
+
+        let (total, aliased) = self.memory();
+        debug!(
+            "Transient targets: {} images for {} targets, {} MiB instead of {} MiB.",
+            images.iter().filter(|image| image.is_some()).count(),
+            self.requests.len(),
+            aliased >> 20,
+            total >> 20
+        );
+        ids
+    }
+}
@@ -0,0 +1,216 @@
+//! Vulkan validation layer for the instance rendy creates.
+//!
+//! Neither rendy nor gfx-backend-vulkan take the layers of the instance, the
+//! Vulkan loader reads them from `VK_INSTANCE_LAYERS`. `ValidationLayer` sets
+//! the variables only while the instance is created and restores them after,
+//! then `ValidationLog` forwards the messages of the layer to `log`.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::config::VALIDATION_LAYER;
+
+/// Layers the Vulkan loader enables in every instance.
+const INSTANCE_LAYERS_VAR: &str = "VK_INSTANCE_LAYERS";
+
+/// What the layer does with its messages.
+const DEBUG_ACTION_VAR: &str = "VK_KHRONOS_VALIDATION_DEBUG_ACTION";
+
+/// File the layer writes its messages to.
+const LOG_FILENAME_VAR: &str = "VK_KHRONOS_VALIDATION_LOG_FILENAME";
+
+#[cfg(windows)]
+const LAYER_SEPARATOR: char = ';';
+
+#[cfg(not(windows))]
+const LAYER_SEPARATOR: char = ':';
+
+/// Target of the messages forwarded to `log`.
+pub const LOG_TARGET: &str = "vulkan_validation";
+
+/// How long the forwarding thread waits for new messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Environment of the Vulkan loader while the instance is created, restored
+/// by `finish` or on drop.
+///
+/// The variables belong to the whole process: keep the scope around the
+/// creation of the instance only, while no other thread reads them.
+pub struct ValidationLayer {
+    previous: Vec<(&'static str, Option<OsString>)>,
+    log_path: Option<PathBuf>,
+}
+
+impl ValidationLayer {
+    /// Enable the layer in the instances created until `finish`.
+    ///
+    /// Debug builds of gfx-backend-vulkan register a debug messenger which
+    /// logs the messages under the `gfx_backend_vulkan` target, the layer
+    /// keeps quiet then. Release builds have no messenger, the layer writes
+    /// to a file `ValidationLog` forwards.
+    pub fn enable() -> Self {
+        let mut scope = ValidationLayer {
+            previous: Vec::new(),
+            log_path: None,
+        };
+        let layers = env::var(INSTANCE_LAYERS_VAR).unwrap_or_default();
+        scope.set(INSTANCE_LAYERS_VAR, with_layer(&layers, VALIDATION_LAYER));
+        if cfg!(debug_assertions) {
+            scope.set(DEBUG_ACTION_VAR, "VK_DBG_LAYER_ACTION_IGNORE");
+        } else {
+            let path =
+                env::temp_dir().join(format!("avenir-validation-{}.log", std::process::id()));
+            scope.set(DEBUG_ACTION_VAR, "VK_DBG_LAYER_ACTION_LOG_MSG");
+            scope.set(LOG_FILENAME_VAR, &path);
+            scope.log_path = Some(path);
+        }
+        info!("GPU validation enabled with {}.", VALIDATION_LAYER);
+        scope
+    }
+
+    fn set(&mut self, var: &'static str, value: impl AsRef<OsStr>) {
+        self.previous.push((var, env::var_os(var)));
+        env::set_var(var, value);
+    }
+
+    /// Restore the environment once the instance is created, and forward
+    /// what the layer writes to `log`.
+    pub fn finish(mut self) -> Option<ValidationLog> {
+        self.restore();
+        self.log_path.take().and_then(ValidationLog::start)
+    }
+
+    fn restore(&mut self) {
+        for (var, value) in self.previous.drain(..).rev() {
+            match value {
+                Some(value) => env::set_var(var, value),
+                None => env::remove_var(var),
+            }
+        }
+    }
+}
+
+impl Drop for ValidationLayer {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+/// `layers` separated as in `VK_INSTANCE_LAYERS`, with `layer` once.
+pub fn with_layer(layers: &str, layer: &str) -> String {
+    if layers.split(LAYER_SEPARATOR).any(|name| name == layer) {
+        layers.to_owned()
+    } else if layers.is_empty() {
+        layer.to_owned()
+    } else {
+        format!("{}{}{}", layers, LAYER_SEPARATOR, layer)
+    }
+}
+
+/// Level of a line the layer writes, `None` for the following lines of a
+/// message.
+pub fn message_level(line: &str) -> Option<log::Level> {
+    let line = line.trim_start();
+    let prefixes = [
+        ("Validation Error", log::Level::Error),
+        ("ERROR", log::Level::Error),
+        ("Validation Performance Warning", log::Level::Warn),
+        ("Validation Warning", log::Level::Warn),
+        ("WARNING", log::Level::Warn),
+        ("PERF", log::Level::Warn),
+        ("Validation Information", log::Level::Info),
+        ("INFO", log::Level::Info),
+        ("DEBUG", log::Level::Debug),
+        ("Validation Verbose", log::Level::Trace),
+        ("VERBOSE", log::Level::Trace),
+    ];
+    prefixes
+        .iter()
+        .find(|(prefix, _)| line.starts_with(prefix))
+        .map(|&(_, level)| level)
+}
+
+/// Thread forwarding the file of the layer to `log` until dropped.
+pub struct ValidationLog {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    path: PathBuf,
+}
+
+impl ValidationLog {
+    fn start(path: PathBuf) -> Option<Self> {
+        // The layer creates the file with the instance.
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                warn!(
+                    "No messages from {}, is the layer installed? {}.",
+                    VALIDATION_LAYER, err
+                );
+                return None;
+            }
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::Builder::new()
+            .name("validation-log".to_owned())
+            .spawn(move || forward(BufReader::new(file), &stopped))
+            .map_err(|err| error!("Failed to forward the validation messages: {}.", err))
+            .ok()?;
+        Some(ValidationLog {
+            stop,
+            thread: Some(thread),
+            path,
+        })
+    }
+}
+
+impl Drop for ValidationLog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Log the lines of `reader` as they come, the remaining ones once `stop`
+/// is set.
+fn forward(mut reader: impl BufRead, stop: &AtomicBool) {
+    let mut line = String::new();
+    let mut level = log::Level::Warn;
+    loop {
+        // The layer may be in the middle of a line, it is kept until the end.
+        let complete = match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                if stop.load(Ordering::Acquire) {
+                    forward_line(&line, &mut level);
+                    return;
+                }
+                thread::sleep(POLL_INTERVAL);
+                false
+            }
+            Ok(_) => line.ends_with('\n'),
+        };
+        if complete {
+            forward_line(&line, &mut level);
+            line.clear();
+        }
+    }
+}
+
+fn forward_line(line: &str, level: &mut log::Level) {
+    let message = line.trim_end();
+    if !message.is_empty() {
+        *level = message_level(message).unwrap_or(*level);
+        log!(target: LOG_TARGET, *level, "{}", message);
+    }
+}
@@ -0,0 +1,119 @@
+//! Vertex format of the voxel meshes.
+
+use nalgebra::Transform3;
+use rendy::hal::format::Format;
+use rendy::mesh::{AsAttribute, AsVertex, Color, Normal, PosColorNorm, Position, VertexFormat};
+
+use crate::material::MaterialOverride;
+use crate::meshing::MeshData;
+
+/// Animations applied in the vertex shader, combined as bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[repr(transparent)]
+pub struct AnimFlags(pub u32);
+
+impl AnimFlags {
+    pub const NONE: AnimFlags = AnimFlags(0);
+
+    /// Waves with the wind like foliage.
+    ///
+    /// Meshers set it on the top vertices only so the base stays anchored.
+    pub const SWAY: AnimFlags = AnimFlags(1);
+
+    pub fn contains(self, other: AnimFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for AnimFlags {
+    type Output = AnimFlags;
+
+    fn bitor(self, other: AnimFlags) -> AnimFlags {
+        AnimFlags(self.0 | other.0)
+    }
+}
+
+impl AsAttribute for AnimFlags {
+    const NAME: &'static str = "anim_flags";
+    const FORMAT: Format = Format::R32Uint;
+}
+
+/// `PosColorNorm` with the animation flags of its voxel.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct VoxelVertex {
+    pub position: Position,
+    pub color: Color,
+    pub normal: Normal,
+    pub flags: AnimFlags,
+}
+
+impl VoxelVertex {
+    pub fn new(vertex: PosColorNorm, flags: AnimFlags) -> Self {
+        VoxelVertex {
+            position: vertex.position,
+            color: vertex.color,
+            normal: vertex.normal,
+            flags,
+        }
+    }
+
+    /// Interleave the attribute arrays of `data` for upload.
+    pub fn from_mesh_data(data: &MeshData) -> Vec<VoxelVertex> {
+        (0..data.vertex_count())
+            .map(|i| VoxelVertex {
+                position: data.positions[i].into(),
+                color: data.colors[i].into(),
+                normal: data.normals[i].into(),
+                flags: AnimFlags(data.flags[i]),
+            })
+            .collect()
+    }
+}
+
+impl AsVertex for VoxelVertex {
+    fn vertex() -> VertexFormat {
+        VertexFormat::new((
+            Position::vertex(),
+            Color::vertex(),
+            Normal::vertex(),
+            AnimFlags::vertex(),
+        ))
+    }
+}
+
+/// Per-instance data of the drawn models, also the layout of the instances
+/// read and written by `cull.comp`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+    pub tint: [f32; 4],
+
+    /// Emissive color, the roughness multiplier in `w`.
+    pub emissive: [f32; 4],
+}
+
+impl InstanceData {
+    pub fn new(model: &Transform3<f32>, overrides: &MaterialOverride) -> Self {
+        let emissive = overrides.emissive;
+        InstanceData {
+            model: (*model.matrix()).into(),
+            tint: overrides.tint.to_array(),
+            emissive: [emissive.r, emissive.g, emissive.b, overrides.roughness],
+        }
+    }
+}
+
+impl AsVertex for InstanceData {
+    fn vertex() -> VertexFormat {
+        VertexFormat::new((
+            (Format::Rgba32Sfloat, "model"),
+            (Format::Rgba32Sfloat, "model"),
+            (Format::Rgba32Sfloat, "model"),
+            (Format::Rgba32Sfloat, "model"),
+            (Format::Rgba32Sfloat, "tint"),
+            (Format::Rgba32Sfloat, "emissive"),
+        ))
+    }
+}
@@ -0,0 +1,238 @@
+//! Render group with dynamic viewport and scissor.
+//!
+//! Rendy's simple render group bakes the framebuffer size in the pipeline.
+//! This group builds the pipeline of a `SimpleGraphicsPipelineDesc` the same
+//! way but leaves viewport and scissor dynamic, they are set before each
+//! draw from `Scene::viewport_in`, so changing the viewport or the letterbox
+//! never recreates the pipeline.
+
+use rendy::command::{QueueId, RenderPassEncoder};
+use rendy::factory::Factory;
+use rendy::graph::render::{
+    PrepareResult, RenderGroup, RenderGroupDesc, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc,
+};
+use rendy::graph::{
+    BufferAccess, GraphContext, ImageAccess, NodeBuffer, NodeBuildError, NodeImage,
+};
+use rendy::hal::{self, device::Device};
+use rendy::resource::{DescriptorSetLayout, Handle};
+
+use crate::scene::Scene;
+
+/// Wraps a pipeline description to build it with dynamic viewport state.
+#[derive(Debug)]
+pub struct DynamicViewportDesc<P> {
+    pub inner: P,
+}
+
+impl<P> DynamicViewportDesc<P> {
+    pub fn new(inner: P) -> Self {
+        DynamicViewportDesc { inner }
+    }
+}
+
+#[derive(Debug)]
+pub struct DynamicViewportGroup<B: hal::Backend, P> {
+    set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    graphics_pipeline: B::GraphicsPipeline,
+    pipeline: P,
+    framebuffer: hal::pso::Rect,
+}
+
+impl<B, P> RenderGroupDesc<B, Scene> for DynamicViewportDesc<P>
+where
+    B: hal::Backend,
+    P: SimpleGraphicsPipelineDesc<B, Scene>,
+{
+    fn buffers(&self) -> Vec<BufferAccess> {
+        self.inner.buffers()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.inner.images()
+    }
+
+    fn colors(&self) -> usize {
+        self.inner.colors().len()
+    }
+
+    fn depth(&self) -> bool {
+        self.inner.depth_stencil().is_some()
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &Scene,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        subpass: hal::pass::Subpass<'_, B>,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, Scene>>, NodeBuildError> {
+        let mut shader_set = self.inner.load_shader_set(factory, aux);
+        let layout = self.inner.layout();
+
+        let set_layouts = layout
+            .sets
+            .into_iter()
+            .map(|set| {
+                factory
+                    .create_descriptor_set_layout(set.bindings)
+                    .map(Handle::from)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                shader_set.dispose(factory);
+                NodeBuildError::OutOfMemory(err)
+            })?;
+
+        let pipeline_layout = unsafe {
+            factory.device().create_pipeline_layout(
+                set_layouts.iter().map(|layout| layout.raw()),
+                layout.push_constants,
+            )
+        }
+        .map_err(|err| {
+            shader_set.dispose(factory);
+            NodeBuildError::OutOfMemory(err)
+        })?;
+
+        let mut vertex_buffers = Vec::new();
+        let mut attributes = Vec::new();
+        for (binding, (elements, stride, rate)) in self.inner.vertices().into_iter().enumerate() {
+            vertex_buffers.push(hal::pso::VertexBufferDesc {
+                binding: binding as u32,
+                stride,
+                rate,
+            });
+            for element in elements {
+                attributes.push(hal::pso::AttributeDesc {
+                    location: attributes.len() as u32,
+                    binding: binding as u32,
+                    element,
+                });
+            }
+        }
+
+        let graphics_pipeline = match shader_set.raw() {
+            Ok(shaders) => unsafe {
+                factory.device().create_graphics_pipeline(
+                    &hal::pso::GraphicsPipelineDesc {
+                        shaders,
+                        rasterizer: hal::pso::Rasterizer::FILL,
+                        vertex_buffers,
+                        attributes,
+                        input_assembler: hal::pso::InputAssemblerDesc::new(
+                            hal::Primitive::TriangleList,
+                        ),
+                        blender: hal::pso::BlendDesc {
+                            logic_op: None,
+                            targets: self.inner.colors(),
+                        },
+                        depth_stencil: self.inner.depth_stencil().unwrap_or_default(),
+                        multisampling: None,
+                        // Viewport and scissor are dynamic.
+                        baked_states: hal::pso::BakedStates::default(),
+                        layout: &pipeline_layout,
+                        subpass,
+                        flags: hal::pso::PipelineCreationFlags::empty(),
+                        parent: hal::pso::BasePipeline::None,
+                    },
+                    None,
+                )
+            },
+            Err(err) => {
+                warn!("Invalid shader set: {:?}.", err);
+                shader_set.dispose(factory);
+                unsafe { factory.device().destroy_pipeline_layout(pipeline_layout) };
+                return Err(NodeBuildError::Pipeline(hal::pso::CreationError::Other));
+            }
+        };
+        shader_set.dispose(factory);
+
+        let graphics_pipeline = match graphics_pipeline {
+            Ok(graphics_pipeline) => graphics_pipeline,
+            Err(err) => {
+                unsafe { factory.device().destroy_pipeline_layout(pipeline_layout) };
+                return Err(NodeBuildError::Pipeline(err));
+            }
+        };
+
+        let pipeline = self
+            .inner
+            .build(ctx, factory, queue, aux, buffers, images, &set_layouts)
+            .map_err(NodeBuildError::Pipeline)?;
+
+        Ok(Box::new(DynamicViewportGroup {
+            set_layouts,
+            pipeline_layout,
+            graphics_pipeline,
+            pipeline,
+            framebuffer: hal::pso::Rect {
+                x: 0,
+                y: 0,
+                w: framebuffer_width as i16,
+                h: framebuffer_height as i16,
+            },
+        }))
+    }
+}
+
+impl<B, P> RenderGroup<B, Scene> for DynamicViewportGroup<B, P>
+where
+    B: hal::Backend,
+    P: SimpleGraphicsPipeline<B, Scene>,
+{
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        queue: QueueId,
+        index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        aux: &Scene,
+    ) -> PrepareResult {
+        self.pipeline
+            .prepare(factory, queue, &self.set_layouts, index, aux)
+    }
+
+    fn draw_inline(
+        &mut self,
+        mut encoder: RenderPassEncoder<'_, B>,
+        index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        aux: &Scene,
+    ) {
+        let rect = aux.viewport_in(self.framebuffer);
+        unsafe {
+            encoder.set_viewports(
+                0,
+                &[hal::pso::Viewport {
+                    rect,
+                    depth: 0.0..1.0,
+                }],
+            );
+            encoder.set_scissors(0, &[rect]);
+        }
+        encoder.bind_graphics_pipeline(&self.graphics_pipeline);
+        self.pipeline
+            .draw(&self.pipeline_layout, encoder, index, aux);
+    }
+
+    fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &Scene) {
+        let group = *self;
+        group.pipeline.dispose(factory, aux);
+        unsafe {
+            factory
+                .device()
+                .destroy_graphics_pipeline(group.graphics_pipeline);
+            factory
+                .device()
+                .destroy_pipeline_layout(group.pipeline_layout);
+        }
+        drop(group.set_layouts);
+    }
+}
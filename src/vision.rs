@@ -0,0 +1,207 @@
+//! Fog of war darkening the parts of the world the player hasn't seen.
+//!
+//! The game keeps a `VisionMask` on the scene, a grid over the XZ plane with
+//! the visibility of each cell, and stamps reveals around its units. The
+//! voxel and skinned shaders darken the surfaces by the mask, filtered
+//! between the cells, and everything outside the mask as unexplored.
+//!
+//! The mask is a byte per cell, copied to a storage buffer each frame.
+
+use rendy::factory::Factory;
+use rendy::hal::{self, adapter::PhysicalDevice};
+use rendy::resource::BufferInfo;
+
+use crate::coords::Location;
+use crate::mapped::MappedBuffer;
+use crate::scene::Scene;
+
+/// Most cells of a mask, the size of its buffer on the device.
+pub const MAX_VISION_CELLS: usize = 256 * 256;
+
+/// Visibility of a cell in view.
+pub const VISIBLE: u8 = 255;
+
+/// Visibility of a cell never seen.
+pub const UNEXPLORED: u8 = 0;
+
+/// Visibility of the cells over an area of the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisionMask {
+    /// Corner of the first cell, its smallest X and Z.
+    pub origin: Location,
+
+    /// Side of the cells, in voxels.
+    pub cell_size: f32,
+
+    width: usize,
+    height: usize,
+
+    /// Row after row along Z, from `UNEXPLORED` to `VISIBLE`.
+    cells: Vec<u8>,
+}
+
+impl VisionMask {
+    /// Unexplored mask of `width` by `height` cells from `origin` along X
+    /// and Z, `None` if it's empty, larger than `MAX_VISION_CELLS` or the
+    /// cells have no size.
+    pub fn new(origin: Location, width: usize, height: usize, cell_size: f32) -> Option<Self> {
+        let cells = width.checked_mul(height)?;
+        let valid =
+            cells != 0 && cells <= MAX_VISION_CELLS && cell_size > 0.0 && cell_size.is_finite();
+        if !valid {
+            return None;
+        }
+        Some(VisionMask {
+            origin,
+            cell_size,
+            width,
+            height,
+            cells: vec![UNEXPLORED; cells],
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Visibility of every cell, row after row along Z.
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Visibility of every cell, for games computing it themselves.
+    pub fn cells_mut(&mut self) -> &mut [u8] {
+        &mut self.cells
+    }
+
+    /// Visibility of a cell, `UNEXPLORED` outside the mask.
+    pub fn get(&self, x: usize, z: usize) -> u8 {
+        if x < self.width && z < self.height {
+            self.cells[z * self.width + x]
+        } else {
+            UNEXPLORED
+        }
+    }
+
+    /// Set the visibility of a cell, ignored outside the mask.
+    pub fn set(&mut self, x: usize, z: usize, visibility: u8) {
+        if x < self.width && z < self.height {
+            self.cells[z * self.width + x] = visibility;
+        }
+    }
+
+    pub fn fill(&mut self, visibility: u8) {
+        for cell in &mut self.cells {
+            *cell = visibility;
+        }
+    }
+
+    /// Lower the cells above `visibility` to it, for the explored areas
+    /// out of sight once the reveals of the previous update are over.
+    pub fn dim(&mut self, visibility: u8) {
+        for cell in &mut self.cells {
+            *cell = (*cell).min(visibility);
+        }
+    }
+
+    /// Make the cells within `radius` of `center` visible, fading over the
+    /// last cell to the edge. Cells already more visible keep their value.
+    pub fn reveal(&mut self, center: &Location, radius: f32) {
+        let valid = radius > 0.0 && center.offset.iter().all(|x| x.is_finite());
+        if !valid {
+            return;
+        }
+        let local = center.relative_to(&self.origin.chunk) - self.origin.offset;
+        let (cx, cz) = (local.x / self.cell_size, local.z / self.cell_size);
+        let radius = radius / self.cell_size;
+        let range = |center: f32, len: usize| {
+            let low = (center - radius).floor().max(0.0).min(len as f32) as usize;
+            let high = (center + radius).ceil().max(0.0).min(len as f32) as usize;
+            low..high
+        };
+        for z in range(cz, self.height) {
+            for x in range(cx, self.width) {
+                let (dx, dz) = (x as f32 + 0.5 - cx, z as f32 + 0.5 - cz);
+                let distance = (dx * dx + dz * dz).sqrt();
+                let edge = (radius - distance).max(0.0).min(1.0);
+                let visibility = (edge * VISIBLE as f32) as u8;
+                let cell = &mut self.cells[z * self.width + x];
+                *cell = (*cell).max(visibility);
+            }
+        }
+    }
+}
+
+const VISION_SIZE: u64 = MAX_VISION_CELLS as u64;
+
+/// Mask of the scene copied to the device, one region per frame in flight.
+pub(crate) struct VisionBuffer<B: hal::Backend> {
+    buffer: MappedBuffer<B>,
+    align: u64,
+}
+
+impl<B: hal::Backend> VisionBuffer<B> {
+    pub fn new(factory: &Factory<B>, frames: usize) -> Self {
+        let align = factory
+            .physical()
+            .limits()
+            .min_storage_buffer_offset_alignment;
+        let buffer = MappedBuffer::new(
+            factory,
+            BufferInfo {
+                size: crate::mesh::iceil(VISION_SIZE, align) * frames as u64,
+                usage: hal::buffer::Usage::STORAGE,
+            },
+        );
+        VisionBuffer { buffer, align }
+    }
+
+    fn offset(&self, index: usize) -> u64 {
+        crate::mesh::iceil(VISION_SIZE, self.align) * index as u64
+    }
+
+    pub fn descriptor(&self, index: usize) -> hal::pso::Descriptor<B> {
+        let offset = self.offset(index);
+        hal::pso::Descriptor::Buffer(self.buffer.raw(), Some(offset)..Some(offset + VISION_SIZE))
+    }
+
+    /// Copy the mask of the scene in the region of frame `index`.
+    pub fn upload(&mut self, factory: &Factory<B>, index: usize, aux: &Scene) {
+        if let Some(ref mask) = aux.vision {
+            let offset = self.offset(index);
+            unsafe {
+                self.buffer.write(factory, offset, mask.cells());
+            }
+        }
+    }
+}
+
+/// Layout of the mask in the fragment shader.
+pub(crate) fn binding(binding: u32) -> hal::pso::DescriptorSetLayoutBinding {
+    hal::pso::DescriptorSetLayoutBinding {
+        binding,
+        ty: hal::pso::DescriptorType::StorageBuffer,
+        count: 1,
+        stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
+        immutable_samplers: false,
+    }
+}
+
+/// Placement of the mask of the scene for the shaders: its corner in render
+/// space and cell size, then its size in cells and whether there is one.
+pub(crate) fn uniform(aux: &Scene) -> ([f32; 4], [f32; 4]) {
+    match aux.vision {
+        Some(ref mask) => {
+            let corner = aux.origin.to_render(&mask.origin);
+            (
+                [corner.x, corner.z, mask.cell_size, 0.0],
+                [mask.width as f32, mask.height as f32, 1.0, 0.0],
+            )
+        }
+        None => ([0.0; 4], [0.0; 4]),
+    }
+}
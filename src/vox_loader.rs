@@ -0,0 +1,349 @@
+//! Models of MagicaVoxel `.vox` files.
+//!
+//! A file holds a palette of 255 colors and one or more models, each a box
+//! of up to 256 voxels per side listing its solid voxels with their color
+//! index. The scene graph, materials and layers of newer files are skipped.
+//!
+//! MagicaVoxel is Z up, models are turned to the Y up of the engine when
+//! loaded: `y` is the height of the file and `z` its `-y`, so models keep
+//! their handedness and face the same way.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rendy::mesh::PosColorNorm;
+
+use crate::chunk::{Chunk, MeshScratch, VoxelId};
+use crate::color::Color;
+use crate::coords::CHUNK_SIZE;
+use crate::meshing::{mesh_chunk, MeshData, VoxelStyle};
+
+/// Largest side of a model, in voxels.
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+const MAGIC: &[u8; 4] = b"VOX ";
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(io::Error),
+
+    /// The data doesn't start like a `.vox` file.
+    NotVox,
+
+    /// The data ends within a chunk.
+    Truncated,
+
+    /// The file has no model.
+    NoModel,
+
+    /// A chunk of the file is invalid, the reason given.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VoxError::Io(err) => write!(f, "can't read the .vox file: {}", err),
+            VoxError::NotVox => write!(f, "not a .vox file"),
+            VoxError::Truncated => write!(f, "truncated .vox file"),
+            VoxError::NoModel => write!(f, "the .vox file has no model"),
+            VoxError::Malformed(reason) => write!(f, "malformed .vox file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VoxError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for VoxError {
+    fn from(err: io::Error) -> Self {
+        VoxError::Io(err)
+    }
+}
+
+/// Solid voxels of a model and the colors of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxModel {
+    size: (usize, usize, usize),
+
+    /// Position and color index, from 1.
+    voxels: Vec<(u8, u8, u8, u8)>,
+
+    /// Color of each index, index 0 is unused.
+    palette: Vec<Color>,
+}
+
+impl VoxModel {
+    /// Size of the model along X, Y and Z, Y up.
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        self.size
+    }
+
+    /// Number of solid voxels.
+    pub fn len(&self) -> usize {
+        self.voxels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    /// Color of a palette index, linear.
+    pub fn color(&self, index: u8) -> Color {
+        self.palette[index as usize]
+    }
+
+    /// Solid voxels with their color, linear.
+    pub fn voxels(&self) -> impl Iterator<Item = (usize, usize, usize, [f32; 4])> + '_ {
+        self.voxels.iter().map(move |&(x, y, z, index)| {
+            let color = self.palette[index as usize].to_array();
+            (x as usize, y as usize, z as usize, color)
+        })
+    }
+
+    /// Solid voxels with their palette index.
+    pub fn indexed_voxels(&self) -> impl Iterator<Item = ((usize, usize, usize), u8)> + '_ {
+        self.voxels
+            .iter()
+            .map(|&(x, y, z, index)| ((x as usize, y as usize, z as usize), index))
+    }
+
+    /// Blocky mesh of the model, voxel `(x, y, z)` spanning `x..x + 1` and
+    /// so on.
+    ///
+    /// The model is meshed by blocks of a chunk, faces between the blocks
+    /// are kept.
+    pub fn mesh(&self) -> MeshData {
+        let size = CHUNK_SIZE as usize;
+        let blocks = |len: usize| (len + size - 1) / size;
+        let (bx, by, bz) = (
+            blocks(self.size.0),
+            blocks(self.size.1),
+            blocks(self.size.2),
+        );
+        let mut chunks = vec![None; bx * by * bz];
+        for ((x, y, z), index) in self.indexed_voxels() {
+            let block = x / size + bx * (y / size + by * (z / size));
+            chunks[block].get_or_insert_with(Chunk::new).set_voxel(
+                x % size,
+                y % size,
+                z % size,
+                index as VoxelId,
+            );
+        }
+
+        let style = |id: VoxelId| VoxelStyle {
+            color: self.palette[id as usize],
+            flags: 0,
+            texture: None,
+        };
+        let mut scratch = MeshScratch::default();
+        let mut block_mesh = MeshData::new();
+        let mut mesh = MeshData::new();
+        for (block, chunk) in chunks.iter().enumerate() {
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            mesh_chunk(chunk, &mut scratch, style, &mut block_mesh);
+            let origin = [
+                (block % bx * size) as f32,
+                (block / bx % by * size) as f32,
+                (block / (bx * by) * size) as f32,
+            ];
+            let first = mesh.vertex_count() as u32;
+            mesh.positions
+                .extend(block_mesh.positions.iter().map(|position| {
+                    [
+                        position[0] + origin[0],
+                        position[1] + origin[1],
+                        position[2] + origin[2],
+                    ]
+                }));
+            mesh.normals.extend_from_slice(&block_mesh.normals);
+            mesh.colors.extend_from_slice(&block_mesh.colors);
+            mesh.flags.extend_from_slice(&block_mesh.flags);
+            mesh.indices
+                .extend(block_mesh.indices.iter().map(|index| index + first));
+        }
+        mesh
+    }
+
+    /// Vertices and indices of `mesh`, to build a `rendy` mesh like the
+    /// scene models.
+    pub fn vertices(&self) -> (Vec<PosColorNorm>, Vec<u32>) {
+        let mesh = self.mesh();
+        let vertices = (0..mesh.vertex_count())
+            .map(|i| PosColorNorm {
+                position: mesh.positions[i].into(),
+                color: mesh.colors[i].into(),
+                normal: mesh.normals[i].into(),
+            })
+            .collect();
+        (vertices, mesh.indices)
+    }
+}
+
+/// First model of a `.vox` file.
+pub fn load_vox(path: &Path) -> Result<VoxModel, VoxError> {
+    parse_vox(&fs::read(path)?)
+}
+
+/// First model of `.vox` file contents.
+pub fn parse_vox(data: &[u8]) -> Result<VoxModel, VoxError> {
+    parse_vox_models(data)?
+        .into_iter()
+        .next()
+        .ok_or(VoxError::NoModel)
+}
+
+/// Every model of `.vox` file contents, in file order.
+///
+/// Allocations are bounded by the size of the data whatever it claims.
+pub fn parse_vox_models(data: &[u8]) -> Result<Vec<VoxModel>, VoxError> {
+    let mut reader = Reader(data);
+    if data.len() < 8 || reader.take(4)? != MAGIC {
+        return Err(VoxError::NotVox);
+    }
+    let _version = reader.u32()?;
+    let (id, content, mut children) = reader.chunk()?;
+    if id != b"MAIN" {
+        return Err(VoxError::Malformed("first chunk isn't MAIN"));
+    }
+    if !content.is_empty() {
+        return Err(VoxError::Malformed("MAIN chunk has content"));
+    }
+
+    let mut sizes = Vec::new();
+    let mut models = Vec::new();
+    let mut palette = None;
+    while !children.0.is_empty() {
+        let (id, content, _) = children.chunk()?;
+        let mut content = Reader(content);
+        match id {
+            b"SIZE" => {
+                let mut size = [0; 3];
+                for axis in size.iter_mut() {
+                    *axis = content.u32()?;
+                }
+                let valid = size.iter().all(|&axis| axis > 0 && axis <= MAX_MODEL_SIZE);
+                if !valid {
+                    return Err(VoxError::Malformed("invalid model size"));
+                }
+                sizes.push(size);
+            }
+            b"XYZI" => {
+                let size = match sizes.get(models.len()) {
+                    Some(&size) => size,
+                    None => return Err(VoxError::Malformed("XYZI chunk without SIZE")),
+                };
+                let count = content.u32()? as usize;
+                let bytes = content.take(count.checked_mul(4).ok_or(VoxError::Truncated)?)?;
+                let mut voxels = Vec::with_capacity(count);
+                for voxel in bytes.chunks_exact(4) {
+                    let (x, y, z, index) = (voxel[0], voxel[1], voxel[2], voxel[3]);
+                    let inside =
+                        (x as u32) < size[0] && (y as u32) < size[1] && (z as u32) < size[2];
+                    if !inside || index == 0 {
+                        return Err(VoxError::Malformed("invalid voxel"));
+                    }
+                    // Z up to Y up.
+                    voxels.push((x, z, (size[1] - 1) as u8 - y, index));
+                }
+                let size = (size[0] as usize, size[2] as usize, size[1] as usize);
+                models.push((size, voxels));
+            }
+            b"RGBA" => {
+                let bytes = content.take(256 * 4)?;
+                let mut colors = vec![Color::TRANSPARENT];
+                colors.extend(bytes.chunks_exact(4).take(255).map(rgba8));
+                palette = Some(colors);
+            }
+            _ => {}
+        }
+    }
+
+    let palette = palette.unwrap_or_else(default_palette);
+    Ok(models
+        .into_iter()
+        .map(|(size, voxels)| VoxModel {
+            size,
+            voxels,
+            palette: palette.clone(),
+        })
+        .collect())
+}
+
+fn rgba8(bytes: &[u8]) -> Color {
+    let channel = |i: usize| bytes[i] as f32 / 255.0;
+    Color::from_srgb(channel(0), channel(1), channel(2), channel(3))
+}
+
+/// Palette of the files without one, the MagicaVoxel default: a cube of 6
+/// levels per channel, then ramps of blue, green, red and grey.
+fn default_palette() -> Vec<Color> {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut rgba = Vec::with_capacity(256);
+    for &r in &CUBE {
+        for &g in &CUBE {
+            for &b in &CUBE {
+                if (r, g, b) != (0, 0, 0) {
+                    rgba.push([r, g, b, 0xff]);
+                }
+            }
+        }
+    }
+    for &level in &RAMP {
+        rgba.push([0, 0, level, 0xff]);
+    }
+    for &level in &RAMP {
+        rgba.push([0, level, 0, 0xff]);
+    }
+    for &level in &RAMP {
+        rgba.push([level, 0, 0, 0xff]);
+    }
+    for &level in &RAMP {
+        rgba.push([level, level, level, 0xff]);
+    }
+    let mut palette = vec![Color::TRANSPARENT];
+    palette.extend(rgba.iter().map(|color| rgba8(color)));
+    palette
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VoxError> {
+        if self.0.len() < len {
+            return Err(VoxError::Truncated);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, VoxError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Id, content and children of the next chunk.
+    fn chunk(&mut self) -> Result<(&'a [u8], &'a [u8], Reader<'a>), VoxError> {
+        let id = self.take(4)?;
+        let content = self.u32()? as usize;
+        let children = self.u32()? as usize;
+        let content = self.take(content)?;
+        let children = Reader(self.take(children)?);
+        Ok((id, content, children))
+    }
+}
@@ -0,0 +1,185 @@
+//! Weather states and the transitions between them.
+//!
+//! The weather only produces rendering parameters: precipitation drawn by
+//! the overlay pass, fog and surface wetness applied by the scene shaders.
+
+use crate::color::Color;
+
+/// Weather the scene can be set to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Weather {
+    Clear,
+    Cloudy,
+    Rain,
+    Storm,
+    Snow,
+}
+
+impl Weather {
+    pub fn params(self) -> WeatherParams {
+        match self {
+            Weather::Clear => WeatherParams {
+                rain: 0.0,
+                snow: 0.0,
+                overcast: 0.0,
+                fog_density: 0.0,
+                fog_color: Color::rgb(0.8, 0.8, 0.8),
+            },
+            Weather::Cloudy => WeatherParams {
+                rain: 0.0,
+                snow: 0.0,
+                overcast: 0.4,
+                fog_density: 0.002,
+                fog_color: Color::rgb(0.7, 0.72, 0.75),
+            },
+            Weather::Rain => WeatherParams {
+                rain: 0.6,
+                snow: 0.0,
+                overcast: 0.6,
+                fog_density: 0.006,
+                fog_color: Color::rgb(0.55, 0.57, 0.6),
+            },
+            Weather::Storm => WeatherParams {
+                rain: 1.0,
+                snow: 0.0,
+                overcast: 0.85,
+                fog_density: 0.01,
+                fog_color: Color::rgb(0.4, 0.42, 0.45),
+            },
+            Weather::Snow => WeatherParams {
+                rain: 0.0,
+                snow: 0.7,
+                overcast: 0.5,
+                fog_density: 0.008,
+                fog_color: Color::rgb(0.85, 0.87, 0.9),
+            },
+        }
+    }
+}
+
+/// Rendering parameters of a weather, blended during transitions.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WeatherParams {
+    /// Rain intensity in `0.0..=1.0`.
+    pub rain: f32,
+
+    /// Snow intensity in `0.0..=1.0`.
+    pub snow: f32,
+
+    /// Darkening of the sky in `0.0..=1.0`.
+    pub overcast: f32,
+
+    /// Exponential fog density per voxel of distance.
+    pub fog_density: f32,
+
+    pub fog_color: Color,
+}
+
+impl WeatherParams {
+    pub fn lerp(&self, other: &WeatherParams, t: f32) -> Self {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        WeatherParams {
+            rain: mix(self.rain, other.rain),
+            snow: mix(self.snow, other.snow),
+            overcast: mix(self.overcast, other.overcast),
+            fog_density: mix(self.fog_density, other.fog_density),
+            fog_color: self.fog_color.lerp(other.fog_color, t),
+        }
+    }
+}
+
+/// Seconds a transition lasts by default.
+pub const TRANSITION_TIME: f32 = 20.0;
+
+/// Seconds of full rain to soak the surfaces.
+const WETTING_TIME: f32 = 30.0;
+
+/// Seconds for soaked surfaces to dry.
+const DRYING_TIME: f32 = 120.0;
+
+/// Current weather, the one being transitioned to and surface wetness.
+#[derive(Debug, Clone)]
+pub struct WeatherState {
+    from: Weather,
+    to: Weather,
+
+    /// Progress of the transition in `0.0..=1.0`.
+    progress: f32,
+
+    /// Seconds the current transition lasts.
+    duration: f32,
+
+    wetness: f32,
+}
+
+impl WeatherState {
+    pub fn new(weather: Weather) -> Self {
+        WeatherState {
+            from: weather,
+            to: weather,
+            progress: 1.0,
+            duration: TRANSITION_TIME,
+            wetness: 0.0,
+        }
+    }
+
+    /// Start a transition to `weather` lasting `duration` seconds.
+    ///
+    /// A transition in progress restarts from the weather it is closest to.
+    pub fn transition(&mut self, weather: Weather, duration: f32) {
+        if weather == self.to {
+            return;
+        }
+        self.from = if self.progress < 0.5 {
+            self.from
+        } else {
+            self.to
+        };
+        self.to = weather;
+        self.progress = 0.0;
+        self.duration = duration.max(0.0);
+    }
+
+    /// Advance the transition and the wetness by `delta_sec`.
+    pub fn update(&mut self, delta_sec: f32) {
+        if self.progress < 1.0 {
+            self.progress = if self.duration > 0.0 {
+                (self.progress + delta_sec / self.duration).min(1.0)
+            } else {
+                1.0
+            };
+        }
+        let rain = self.params().rain;
+        if rain > self.wetness {
+            self.wetness = (self.wetness + delta_sec / WETTING_TIME).min(rain);
+        } else {
+            self.wetness = (self.wetness - delta_sec / DRYING_TIME).max(rain);
+        }
+    }
+
+    /// Weather being transitioned to, or the current one.
+    pub fn weather(&self) -> Weather {
+        self.to
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.progress < 1.0
+    }
+
+    /// Parameters blended between the two weathers of the transition.
+    pub fn params(&self) -> WeatherParams {
+        let t = self.progress * self.progress * (3.0 - 2.0 * self.progress);
+        self.from.params().lerp(&self.to.params(), t)
+    }
+
+    /// How soaked the surfaces are, in `0.0..=1.0`.
+    pub fn wetness(&self) -> f32 {
+        self.wetness
+    }
+}
+
+impl Default for WeatherState {
+    fn default() -> Self {
+        WeatherState::new(Weather::Clear)
+    }
+}
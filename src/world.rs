@@ -0,0 +1,405 @@
+//! Sparse voxel world made of chunks.
+
+use std::collections::HashMap;
+
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use crate::chunk::{Chunk, MeshScratch, Side, VoxelId, VoxelPool, AIR, DAMAGE_STAGES};
+use crate::chunk_meshes::ChunkMeshes;
+use crate::color::Color;
+use crate::coords::{ChunkCoord, Location, WorldBounds, WorldPos, CHUNK_SIZE};
+use crate::explosion::{self, Explosion};
+use crate::meshing::{self, MeshData, MeshingMode, SkirtSettings, VoxelStyle};
+use crate::raycast::{self, Ray, RaycastHit};
+use crate::sdf::{CsgEdit, SdfChunk, MAX_DISTANCE};
+use crate::smooth::DensityGrid;
+
+/// Number of idle voxel arrays kept for new chunks.
+const POOLED_CHUNKS: usize = 64;
+
+/// Loaded chunks of the world, missing chunks are air.
+pub struct World {
+    chunks: HashMap<ChunkCoord, Chunk>,
+
+    /// Distance field of the smooth terrain, alongside the blocks.
+    sdf: HashMap<ChunkCoord, SdfChunk>,
+    pub bounds: WorldBounds,
+
+    /// Surface the chunks of this world are meshed with.
+    pub meshing: MeshingMode,
+
+    /// Skirts added to the chunk meshes, by meshing mode.
+    pub skirts: SkirtSettings,
+    pool: VoxelPool,
+}
+
+impl World {
+    pub fn new(bounds: WorldBounds) -> Self {
+        World {
+            chunks: HashMap::new(),
+            sdf: HashMap::new(),
+            bounds,
+            meshing: MeshingMode::default(),
+            skirts: SkirtSettings::default(),
+            pool: VoxelPool::new(POOLED_CHUNKS),
+        }
+    }
+
+    pub fn get_voxel(&self, pos: &WorldPos) -> VoxelId {
+        let (x, y, z) = pos.local();
+        self.chunks
+            .get(&pos.chunk())
+            .map_or(AIR, |chunk| chunk.get_voxel(x, y, z))
+    }
+
+    /// Set a voxel, creating its chunk if needed.
+    ///
+    /// Returns `false` when `pos` is outside the world bounds.
+    pub fn set_voxel(&mut self, pos: &WorldPos, id: VoxelId) -> bool {
+        if !self.bounds.contains(pos) {
+            return false;
+        }
+        let (x, y, z) = pos.local();
+        let coord = pos.chunk();
+        if !self.chunks.contains_key(&coord) {
+            if id == AIR {
+                return true;
+            }
+            self.chunks.insert(coord, Chunk::new_in(&self.pool));
+        }
+        self.chunks.get_mut(&coord).unwrap().set_voxel(x, y, z, id);
+        true
+    }
+
+    pub fn damage(&self, pos: &WorldPos) -> u8 {
+        let (x, y, z) = pos.local();
+        self.chunks
+            .get(&pos.chunk())
+            .map_or(0, |chunk| chunk.damage(x, y, z))
+    }
+
+    /// Damage the voxel at `pos` by `stages`, for mining. The voxel is
+    /// destroyed once it takes `DAMAGE_STAGES`, its former type is returned.
+    ///
+    /// Air isn't damaged. Setting the voxel to another type repairs it.
+    pub fn add_damage(&mut self, pos: &WorldPos, stages: u8) -> Option<VoxelId> {
+        let (x, y, z) = pos.local();
+        let chunk = self.chunks.get_mut(&pos.chunk())?;
+        let id = chunk.get_voxel(x, y, z);
+        if id == AIR {
+            return None;
+        }
+        let stage = chunk.damage(x, y, z).saturating_add(stages);
+        if stage >= DAMAGE_STAGES {
+            chunk.set_voxel(x, y, z, AIR);
+            Some(id)
+        } else {
+            chunk.set_damage(x, y, z, stage);
+            None
+        }
+    }
+
+    /// Paint a face of the voxel at `pos`, see `Chunk::paint_face`.
+    ///
+    /// Returns `false` when the chunk of `pos` isn't loaded.
+    pub fn paint_face(&mut self, pos: &WorldPos, side: Side, color: Color) -> bool {
+        let (x, y, z) = pos.local();
+        match self.chunks.get_mut(&pos.chunk()) {
+            Some(chunk) => {
+                chunk.paint_face(x, y, z, side, color);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn chunk(&self, coord: &ChunkCoord) -> Option<&Chunk> {
+        self.chunks.get(coord)
+    }
+
+    pub fn chunk_mut(&mut self, coord: &ChunkCoord) -> Option<&mut Chunk> {
+        self.chunks.get_mut(coord)
+    }
+
+    /// Insert a chunk, returns the chunk it replaces.
+    pub fn insert_chunk(&mut self, coord: ChunkCoord, chunk: Chunk) -> Option<Chunk> {
+        self.chunks.insert(coord, chunk)
+    }
+
+    /// Unload a chunk, its voxel array goes back to the pool.
+    pub fn remove_chunk(&mut self, coord: &ChunkCoord) -> bool {
+        match self.chunks.remove(coord) {
+            Some(chunk) => {
+                chunk.recycle(&self.pool);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// First non air voxel along a ray, see `raycast::raycast`.
+    pub fn raycast(
+        &self,
+        origin: &Location,
+        direction: &Vector3<f32>,
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        raycast::raycast(origin, direction, max_distance, |pos| {
+            self.get_voxel(pos) != AIR
+        })
+    }
+
+    /// First non air voxel along each ray, cast in parallel.
+    ///
+    /// Meant for many rays at once, such as the line of sight checks of the
+    /// AI, hits are returned in the order of `rays`.
+    pub fn raycast_batch(&self, rays: &[Ray]) -> Vec<Option<RaycastHit>> {
+        rays.par_iter()
+            .map(|ray| self.raycast(&ray.origin, &ray.direction, ray.max_distance))
+            .collect()
+    }
+
+    /// First non air voxel touched by a sphere swept along a ray, see
+    /// `raycast::spherecast`.
+    pub fn spherecast(
+        &self,
+        origin: &Location,
+        direction: &Vector3<f32>,
+        radius: f32,
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        raycast::spherecast(origin, direction, radius, max_distance, |pos| {
+            self.get_voxel(pos) != AIR
+        })
+    }
+
+    pub fn sdf_chunk(&self, coord: &ChunkCoord) -> Option<&SdfChunk> {
+        self.sdf.get(coord)
+    }
+
+    /// Insert a distance field chunk, returns the one it replaces.
+    pub fn insert_sdf_chunk(&mut self, coord: ChunkCoord, chunk: SdfChunk) -> Option<SdfChunk> {
+        self.sdf.insert(coord, chunk)
+    }
+
+    pub fn remove_sdf_chunk(&mut self, coord: &ChunkCoord) -> Option<SdfChunk> {
+        self.sdf.remove(coord)
+    }
+
+    /// Apply a CSG edit to the distance field, creating the chunks it adds
+    /// terrain to. Returns the chunks which changed, to be meshed again.
+    pub fn edit_sdf(&mut self, edit: &CsgEdit) -> Vec<ChunkCoord> {
+        let mut changed = Vec::new();
+        for coord in edit.brush.chunks() {
+            if !self.bounds.intersects_chunk(&coord) {
+                continue;
+            }
+            let mut created = false;
+            let chunk = self.sdf.entry(coord).or_insert_with(|| {
+                created = true;
+                SdfChunk::new()
+            });
+            if chunk.apply(&coord, edit) {
+                changed.push(coord);
+            } else if created {
+                self.sdf.remove(&coord);
+            }
+        }
+        changed
+    }
+
+    /// Distances of a chunk for the smooth meshers, with the apron taken
+    /// from its neighbours so the meshes join. `None` without the chunk.
+    pub fn sdf_density(&self, coord: &ChunkCoord) -> Option<DensityGrid> {
+        self.sdf.get(coord)?;
+        let size = CHUNK_SIZE as usize;
+        // Past the high faces, the samples are those of the neighbours.
+        let split = |c: usize| if c > size { (1, c - size) } else { (0, c) };
+        Some(DensityGrid::from_fn_with_apron(|x, y, z| {
+            let ((dx, x), (dy, y), (dz, z)) = (split(x), split(y), split(z));
+            let neighbour = ChunkCoord::new(coord.x + dx, coord.y + dy, coord.z + dz);
+            self.sdf
+                .get(&neighbour)
+                .map_or(MAX_DISTANCE, |chunk| chunk.get(x, y, z))
+        }))
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkCoord, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    /// Mesh the chunks which `need_mesh_update` with `meshing` and `skirts`
+    /// into `meshes`, returns how many were meshed. Empty chunks lose their
+    /// mesh, the meshes of unloaded chunks are left to the caller.
+    pub fn mesh_dirty_chunks<F>(
+        &mut self,
+        scratch: &mut MeshScratch,
+        style: F,
+        meshes: &mut ChunkMeshes,
+    ) -> usize
+    where
+        F: Fn(VoxelId) -> VoxelStyle,
+    {
+        let mut meshed = 0;
+        for (coord, chunk) in &mut self.chunks {
+            if !chunk.need_mesh_update() {
+                continue;
+            }
+            let mut mesh = MeshData::new();
+            meshing::mesh(
+                self.meshing,
+                &self.skirts,
+                chunk,
+                scratch,
+                &style,
+                &mut mesh,
+            );
+            chunk.mark_meshed();
+            meshes.set(*coord, mesh);
+            meshed += 1;
+        }
+        meshed
+    }
+
+    /// Destroy the voxels around `center` within `radius`, see the
+    /// `explosion` module.
+    ///
+    /// The strength of the blast at a distance is `(1 - distance / radius)`
+    /// to the power of `falloff`: 0 keeps it whole up to the radius, above 1
+    /// it fades quickly. Voxels shielded by those which resist the blast
+    /// survive. Remesh and relight the returned chunks once, such as with
+    /// `LightEngine::on_blocks_changed`.
+    pub fn explode(&mut self, center: &Location, radius: f32, falloff: f32) -> Explosion {
+        explosion::explode(self, center, radius, falloff)
+    }
+
+    /// Non air voxels inside `aabb`, bounds included, in no particular
+    /// order. For area effects such as explosions.
+    ///
+    /// Only the loaded chunks overlapping the box are visited, each over
+    /// the part of the box it contains.
+    pub fn query_aabb<'a>(
+        &'a self,
+        aabb: &WorldBounds,
+    ) -> impl Iterator<Item = (WorldPos, VoxelId)> + 'a {
+        let aabb = *aabb;
+        self.chunks_in(&aabb)
+            .into_iter()
+            .flat_map(move |(coord, chunk)| {
+                let min = coord.min_voxel();
+                // Part of the box inside the chunk, empty when reversed.
+                let range = |low: i64, high: i64, base: i64| {
+                    let first = low.saturating_sub(base).max(0);
+                    let last = high.saturating_sub(base).min(CHUNK_SIZE - 1);
+                    first as usize..(last + 1).max(first) as usize
+                };
+                let xs = range(aabb.min.x, aabb.max.x, min.x);
+                let ys = range(aabb.min.y, aabb.max.y, min.y);
+                let zs = range(aabb.min.z, aabb.max.z, min.z);
+                // Skipping an empty chunk costs a scan, worth it only
+                // when the whole chunk would be visited.
+                let whole = [&xs, &ys, &zs]
+                    .iter()
+                    .all(|r| r.len() == CHUNK_SIZE as usize);
+                let zs = if whole && chunk.is_empty() { 0..0 } else { zs };
+                zs.flat_map(move |z| ys.clone().map(move |y| (y, z)))
+                    .flat_map(move |(y, z)| xs.clone().map(move |x| (x, y, z)))
+                    .map(move |(x, y, z)| {
+                        let pos =
+                            WorldPos::new(min.x + x as i64, min.y + y as i64, min.z + z as i64);
+                        (pos, chunk.get_voxel(x, y, z))
+                    })
+                    .filter(|&(_, id)| id != AIR)
+            })
+    }
+
+    /// Non air voxels whose center lies within `radius` of `center`, see
+    /// `query_aabb`.
+    pub fn query_sphere<'a>(
+        &'a self,
+        center: &Location,
+        radius: f32,
+    ) -> impl Iterator<Item = (WorldPos, VoxelId)> + 'a {
+        let center = Location::new(center.chunk, center.offset);
+        let voxel = center.voxel();
+        let reach = if radius.is_finite() {
+            radius.max(0.0).ceil() as i64
+        } else {
+            0
+        };
+        let aabb = WorldBounds::new(
+            WorldPos::new(
+                voxel.x.saturating_sub(reach),
+                voxel.y.saturating_sub(reach),
+                voxel.z.saturating_sub(reach),
+            ),
+            WorldPos::new(
+                voxel.x.saturating_add(reach),
+                voxel.y.saturating_add(reach),
+                voxel.z.saturating_add(reach),
+            ),
+        );
+        let origin = center.chunk.min_voxel();
+        self.query_aabb(&aabb).filter(move |(pos, _)| {
+            // Relative to the chunk of the center, precise far from zero.
+            let position = Vector3::new(
+                pos.x.wrapping_sub(origin.x) as f32,
+                pos.y.wrapping_sub(origin.y) as f32,
+                pos.z.wrapping_sub(origin.z) as f32,
+            )
+            .add_scalar(0.5);
+            (position - center.offset).norm_squared() <= radius * radius
+        })
+    }
+
+    /// Loaded chunks overlapping `aabb`.
+    fn chunks_in(&self, aabb: &WorldBounds) -> Vec<(ChunkCoord, &Chunk)> {
+        let (min, max) = (aabb.min.chunk(), aabb.max.chunk());
+        let span = |a: i64, b: i64| (b as i128 - a as i128 + 1).max(0);
+        let count = span(min.x, max.x)
+            .saturating_mul(span(min.y, max.y))
+            .saturating_mul(span(min.z, max.z));
+        // Large boxes are cheaper to test against the loaded chunks.
+        if count > self.chunks.len() as i128 {
+            return self
+                .chunks
+                .iter()
+                .filter(|(coord, _)| aabb.intersects_chunk(coord))
+                .map(|(coord, chunk)| (*coord, chunk))
+                .collect();
+        }
+        let mut chunks = Vec::new();
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let coord = ChunkCoord::new(x, y, z);
+                    if let Some(chunk) = self.chunks.get(&coord) {
+                        chunks.push((coord, chunk));
+                    }
+                }
+            }
+        }
+        chunks
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new(WorldBounds::infinite())
+    }
+}
+
+impl std::fmt::Debug for World {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "World({} chunks)", self.chunks.len())
+    }
+}
@@ -0,0 +1,136 @@
+//! Stereo views for OpenXR, enabled with the `openxr` feature.
+//!
+//! The runtime locates one view per eye each frame. They are converted to
+//! `EyeView`s, the head pose between them drives the scene camera and each
+//! eye gets its own projection and its half of the render target.
+
+use nalgebra::{Isometry3, Matrix4, Quaternion, Translation3, UnitQuaternion};
+use rendy::hal;
+
+use crate::camera::Camera;
+
+/// Angles of the view frustum sides from the view direction, in radians.
+///
+/// Left and down are negative for a symmetric frustum.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fov {
+    pub left: f32,
+    pub right: f32,
+    pub up: f32,
+    pub down: f32,
+}
+
+impl Fov {
+    /// Off-center perspective projection, same conventions as `Perspective3`.
+    pub fn projection(&self, znear: f32, zfar: f32) -> Matrix4<f32> {
+        let (left, right) = (self.left.tan(), self.right.tan());
+        let (up, down) = (self.up.tan(), self.down.tan());
+        let width = right - left;
+        let height = up - down;
+        let depth = zfar - znear;
+
+        let mut proj = Matrix4::zeros();
+        proj[(0, 0)] = 2.0 / width;
+        proj[(0, 2)] = (right + left) / width;
+        proj[(1, 1)] = 2.0 / height;
+        proj[(1, 2)] = (up + down) / height;
+        proj[(2, 2)] = -(zfar + znear) / depth;
+        proj[(2, 3)] = -2.0 * zfar * znear / depth;
+        proj[(3, 2)] = -1.0;
+        proj
+    }
+}
+
+impl From<openxr::Fovf> for Fov {
+    fn from(fov: openxr::Fovf) -> Self {
+        Fov {
+            left: fov.angle_left,
+            right: fov.angle_right,
+            up: fov.angle_up,
+            down: fov.angle_down,
+        }
+    }
+}
+
+/// Pose and field of view of one eye.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EyeView {
+    /// Eye to world transform, like `Camera::view`.
+    pub pose: Isometry3<f32>,
+    pub fov: Fov,
+}
+
+impl From<&openxr::View> for EyeView {
+    fn from(view: &openxr::View) -> Self {
+        let position = view.pose.position;
+        let orientation = view.pose.orientation;
+        EyeView {
+            pose: Isometry3::from_parts(
+                Translation3::new(position.x, position.y, position.z),
+                UnitQuaternion::from_quaternion(Quaternion::new(
+                    orientation.w,
+                    orientation.x,
+                    orientation.y,
+                    orientation.z,
+                )),
+            ),
+            fov: view.fov.into(),
+        }
+    }
+}
+
+pub const LEFT: usize = 0;
+pub const RIGHT: usize = 1;
+
+/// Views of both eyes for one frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StereoView {
+    pub eyes: [EyeView; 2],
+}
+
+impl StereoView {
+    /// Views as located by the runtime, `None` unless there are two.
+    pub fn from_views(views: &[openxr::View]) -> Option<Self> {
+        match views {
+            [left, right] => Some(StereoView {
+                eyes: [left.into(), right.into()],
+            }),
+            _ => None,
+        }
+    }
+
+    /// Pose halfway between the eyes.
+    pub fn head(&self) -> Isometry3<f32> {
+        let [left, right] = self.eyes;
+        let translation = left
+            .pose
+            .translation
+            .vector
+            .lerp(&right.pose.translation.vector, 0.5);
+        Isometry3::from_parts(
+            translation.into(),
+            left.pose.rotation.slerp(&right.pose.rotation, 0.5),
+        )
+    }
+
+    /// Move `camera` to the head pose, offset by `origin` in render space.
+    pub fn apply_head(&self, camera: &mut Camera, origin: &Isometry3<f32>) {
+        camera.view = origin * self.head();
+    }
+
+    pub fn projection(&self, eye: usize, znear: f32, zfar: f32) -> Matrix4<f32> {
+        self.eyes[eye].fov.projection(znear, zfar)
+    }
+
+    /// Half of a `width` by `height` target drawn by `eye`, for double-pass
+    /// rendering side by side into one swapchain image.
+    pub fn eye_viewport(eye: usize, width: u32, height: u32) -> hal::pso::Rect {
+        let half = (width / 2) as i16;
+        hal::pso::Rect {
+            x: if eye == LEFT { 0 } else { half },
+            y: 0,
+            w: half,
+            h: height as i16,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Checks of the quality adjustments to frame times.
+
+use std::time::Duration;
+
+use avenir::adaptive::{AdaptiveQuality, QualityBounds, QualityChange};
+use avenir::config::QualityPreset;
+
+fn ms(milliseconds: u64) -> Duration {
+    Duration::from_millis(milliseconds)
+}
+
+/// Changes made over `frames` frames of `frame_time`.
+fn run(
+    adaptive: &mut AdaptiveQuality,
+    quality: &mut avenir::config::QualitySettings,
+    frame_time: Duration,
+    frames: usize,
+) -> Vec<QualityChange> {
+    (0..frames)
+        .filter_map(|_| adaptive.update(frame_time, quality))
+        .collect()
+}
+
+#[test]
+fn slow_frames_lower_the_view_distance_first() {
+    let mut quality = QualityPreset::High.settings().unwrap();
+    let mut adaptive = AdaptiveQuality::new(ms(16));
+    let changes = run(&mut adaptive, &mut quality, ms(30), 29);
+    assert!(changes.is_empty());
+    let changes = run(&mut adaptive, &mut quality, ms(30), 1);
+    assert_eq!(changes, vec![QualityChange::ViewDistance(640.0)]);
+    assert_eq!(quality.view_distance, 640.0);
+}
+
+#[test]
+fn settings_stay_within_bounds() {
+    let bounds = QualityBounds {
+        min_view_distance: 300.0,
+        max_view_distance: 400.0,
+        min_resolution_scale: 0.75,
+        max_resolution_scale: 1.0,
+        min_shadow_resolution: 1024,
+        max_shadow_resolution: 2048,
+    };
+    let mut quality = QualityPreset::Medium.settings().unwrap();
+    let mut adaptive = AdaptiveQuality::new(ms(16)).with_bounds(bounds);
+
+    // Rebuilding changes wait two seconds of frames before the next one.
+    let lowered = run(&mut adaptive, &mut quality, ms(50), 2000);
+    assert_eq!(
+        lowered,
+        vec![
+            QualityChange::ViewDistance(320.0),
+            QualityChange::ViewDistance(300.0),
+            QualityChange::ResolutionScale(0.875),
+            QualityChange::ResolutionScale(0.75),
+            QualityChange::ShadowResolution(1024),
+        ]
+    );
+    assert!(lowered[2].needs_rebuild() && !lowered[0].needs_rebuild());
+
+    // Raised back in the reverse order.
+    let raised = run(&mut adaptive, &mut quality, ms(5), 2000);
+    assert_eq!(raised[0], QualityChange::ShadowResolution(2048));
+    assert_eq!(raised[1], QualityChange::ResolutionScale(0.875));
+    assert_eq!(quality.resolution_scale, 1.0);
+    assert_eq!(quality.view_distance, 400.0);
+}
+
+#[test]
+fn frames_on_target_keep_the_settings() {
+    let mut quality = QualityPreset::Medium.settings().unwrap();
+    let before = quality;
+    let mut adaptive = AdaptiveQuality::new(ms(16));
+    assert!(run(&mut adaptive, &mut quality, ms(16), 500).is_empty());
+    assert_eq!(quality, before);
+}
@@ -0,0 +1,156 @@
+//! Layers of the block textures and the layers written by the mesher.
+
+use avenir::block_textures::{
+    resample, BlockTextureError, BlockTextures, LAYER_SIZE, LAYER_TEXELS, MAX_BLOCK_TEXTURES,
+};
+use avenir::chunk::{Chunk, MeshScratch, VoxelId};
+use avenir::color::Color;
+use avenir::material::Texture;
+use avenir::meshing::{
+    mesh_chunk, texture_flags, texture_layer, MeshData, VoxelStyle, DAMAGE_MASK, DAMAGE_SHIFT,
+};
+use avenir::plugin::{BlockDesc, BlockRegistry};
+use avenir::vertex::AnimFlags;
+
+/// Texture whose texels are their index.
+fn numbered(width: u32, height: u32) -> Texture {
+    Texture {
+        width,
+        height,
+        texels: (0..width * height).collect(),
+    }
+}
+
+fn plain(texel: u32) -> Texture {
+    Texture {
+        width: 1,
+        height: 1,
+        texels: vec![texel],
+    }
+}
+
+#[test]
+fn textures_are_resampled_to_a_layer() {
+    let size = LAYER_SIZE as usize;
+    // Same size, unchanged.
+    let same = resample(&numbered(LAYER_SIZE, LAYER_SIZE)).unwrap();
+    assert_eq!(same, (0..LAYER_TEXELS as u32).collect::<Vec<_>>());
+
+    // Smaller, each texel repeated.
+    let small = resample(&numbered(2, 2)).unwrap();
+    assert_eq!(small.len(), LAYER_TEXELS);
+    assert_eq!(small[0], 0);
+    assert_eq!(small[size - 1], 1);
+    assert_eq!(small[LAYER_TEXELS - size], 2);
+    assert_eq!(small[LAYER_TEXELS - 1], 3);
+
+    // Larger, every other texel kept.
+    let large = resample(&numbered(LAYER_SIZE * 2, LAYER_SIZE * 2)).unwrap();
+    assert_eq!(large[0], LAYER_SIZE * 2 + 1);
+    assert_eq!(large[1], LAYER_SIZE * 2 + 3);
+
+    assert_eq!(resample(&numbered(0, 4)), None);
+    let truncated = Texture {
+        texels: vec![0; 3],
+        ..numbered(2, 2)
+    };
+    assert_eq!(resample(&truncated), None);
+}
+
+#[test]
+fn layers_are_found_by_name() {
+    let mut textures = BlockTextures::new();
+    assert_eq!(textures.insert("stone", &plain(1)), Ok(0));
+    assert_eq!(textures.insert("dirt", &plain(2)), Ok(1));
+    assert_eq!(textures.layer("dirt"), Some(1));
+    assert_eq!(textures.layer("sand"), None);
+    assert_eq!(textures.len(), 2);
+    assert_eq!(textures.texels().len(), 2 * LAYER_TEXELS);
+    assert!(textures.texels()[LAYER_TEXELS..].iter().all(|&t| t == 2));
+
+    // Replaced in place, a new generation for the pipelines to upload.
+    let generation = textures.generation();
+    assert_eq!(textures.insert("stone", &plain(3)), Ok(0));
+    assert_ne!(textures.generation(), generation);
+    assert_eq!(textures.len(), 2);
+    assert!(textures.texels()[..LAYER_TEXELS].iter().all(|&t| t == 3));
+}
+
+#[test]
+fn refused_textures_change_nothing() {
+    let mut textures = BlockTextures::new();
+    for i in 0..MAX_BLOCK_TEXTURES {
+        textures.insert(&i.to_string(), &plain(0)).unwrap();
+    }
+    let generation = textures.generation();
+    assert_eq!(
+        textures.insert("one too many", &plain(0)),
+        Err(BlockTextureError::Full)
+    );
+    assert_eq!(
+        textures.insert("0", &numbered(0, 0)),
+        Err(BlockTextureError::Empty)
+    );
+    assert_eq!(textures.len(), MAX_BLOCK_TEXTURES);
+    assert_eq!(textures.generation(), generation);
+}
+
+#[test]
+fn layers_fit_the_vertex_flags() {
+    assert_eq!(texture_flags(None), 0);
+    for &layer in &[0, 1, MAX_BLOCK_TEXTURES as u32 - 1] {
+        let flags = texture_flags(Some(layer));
+        assert_eq!(texture_layer(flags), Some(layer));
+        assert_eq!(flags & (DAMAGE_MASK | AnimFlags::SWAY.0), 0);
+    }
+    assert_eq!(texture_layer(DAMAGE_MASK), None);
+}
+
+#[test]
+fn faces_carry_the_layer_of_their_block() {
+    let mut chunk = Chunk::new();
+    chunk.set_voxel(1, 1, 1, 1);
+    chunk.set_voxel(5, 1, 1, 2);
+    chunk.set_damage(5, 1, 1, 3);
+    let style = |id: VoxelId| VoxelStyle {
+        color: Color::WHITE,
+        flags: 0,
+        texture: if id == 2 { Some(7) } else { None },
+    };
+    let mut mesh = MeshData::new();
+    mesh_chunk(&chunk, &mut MeshScratch::default(), style, &mut mesh);
+
+    // One draw whatever the textures: both blocks in the same mesh.
+    assert_eq!(mesh.vertex_count(), 2 * 6 * 4);
+    for (position, &flags) in mesh.positions.iter().zip(&mesh.flags) {
+        if position[0] <= 2.0 {
+            assert_eq!(texture_layer(flags), None);
+        } else {
+            assert_eq!(texture_layer(flags), Some(7));
+            assert_eq!((flags & DAMAGE_MASK) >> DAMAGE_SHIFT, 3);
+        }
+    }
+}
+
+#[test]
+fn blocks_find_their_texture_by_name() {
+    let mut registry = BlockRegistry::new();
+    let desc = |name: &str, texture: Option<&str>| BlockDesc {
+        name: name.to_owned(),
+        color: Color::WHITE,
+        solid: true,
+        texture: texture.map(str::to_owned),
+        animation: AnimFlags::NONE,
+    };
+    let stone = registry.register(desc("stone", Some("stone"))).unwrap();
+    let sand = registry.register(desc("sand", Some("sand"))).unwrap();
+    let glass = registry.register(desc("glass", None)).unwrap();
+
+    let mut textures = BlockTextures::new();
+    textures.insert("dirt", &plain(0)).unwrap();
+    textures.insert("stone", &plain(0)).unwrap();
+    assert_eq!(registry.style(stone, &textures).texture, Some(1));
+    // Not loaded yet.
+    assert_eq!(registry.style(sand, &textures).texture, None);
+    assert_eq!(registry.style(glass, &textures).texture, None);
+}
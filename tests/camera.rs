@@ -0,0 +1,77 @@
+//! Checks of the camera projection parameters.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use nalgebra::Point3;
+
+use avenir::camera::{Camera, CameraError};
+
+fn camera() -> Camera {
+    Camera::look_at(1.0, Point3::new(0.0, 0.0, 5.0), Point3::origin(), 1.0)
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-5
+}
+
+#[test]
+fn aspect_and_fov_set_the_projection_scale() {
+    let mut camera = camera();
+    camera.set_fov(FRAC_PI_2).unwrap();
+    camera.set_aspect(2.0).unwrap();
+    assert!(close(camera.fov(), FRAC_PI_2));
+    assert!(close(camera.aspect(), 2.0));
+
+    // 1 / tan(fov / 2) is 1 for a right angle, X is divided by the aspect.
+    let proj = camera.proj.to_homogeneous();
+    assert!(close(proj[(0, 0)], 0.5));
+    assert!(close(proj[(1, 1)], 1.0));
+
+    camera.set_aspect(0.5).unwrap();
+    assert!(close(camera.proj.to_homogeneous()[(0, 0)], 2.0));
+}
+
+#[test]
+fn clip_planes_set_the_depth_terms() {
+    let mut camera = camera();
+    camera.set_near_far(1.0, 3.0).unwrap();
+    assert!(close(camera.near(), 1.0));
+    assert!(close(camera.far(), 3.0));
+    let proj = camera.proj.to_homogeneous();
+    assert!(close(proj[(2, 2)], -(3.0 + 1.0) / (3.0 - 1.0)));
+    assert!(close(proj[(2, 3)], -2.0 * 3.0 * 1.0 / (3.0 - 1.0)));
+}
+
+#[test]
+fn invalid_parameters_are_refused_and_keep_the_projection() {
+    let mut camera = camera();
+    let proj = camera.proj;
+    let nan = std::f32::NAN;
+    let infinity = std::f32::INFINITY;
+
+    assert_eq!(camera.set_aspect(0.0), Err(CameraError::InvalidAspect(0.0)));
+    assert_eq!(
+        camera.set_aspect(infinity),
+        Err(CameraError::InvalidAspect(infinity))
+    );
+    assert!(camera.set_aspect(nan).is_err());
+    assert_eq!(camera.set_fov(0.0), Err(CameraError::InvalidFov(0.0)));
+    assert_eq!(camera.set_fov(PI), Err(CameraError::InvalidFov(PI)));
+    assert!(camera.set_fov(nan).is_err());
+    assert_eq!(
+        camera.transition_fov(4.0, 1.0),
+        Err(CameraError::InvalidFov(4.0))
+    );
+    assert!(!camera.is_fov_transitioning());
+    assert_eq!(
+        camera.set_near_far(0.0, 10.0),
+        Err(CameraError::InvalidNearFar(0.0, 10.0))
+    );
+    assert_eq!(
+        camera.set_near_far(2.0, 1.0),
+        Err(CameraError::InvalidNearFar(2.0, 1.0))
+    );
+    assert!(camera.set_near_far(1.0, infinity).is_err());
+    assert!(camera.set_near_far(nan, 10.0).is_err());
+    assert_eq!(camera.proj, proj);
+}
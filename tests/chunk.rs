@@ -0,0 +1,77 @@
+//! Meshes of small chunks and the mesh update flag.
+
+use avenir::chunk::{Chunk, MeshScratch, VoxelId};
+use avenir::color::Color;
+use avenir::meshing::{MeshData, VoxelStyle};
+
+fn style(_: VoxelId) -> VoxelStyle {
+    VoxelStyle {
+        color: Color::new(1.0, 1.0, 1.0, 1.0),
+        flags: 0,
+        texture: None,
+    }
+}
+
+fn generate(chunk: &mut Chunk) -> MeshData {
+    let mut mesh = MeshData::new();
+    chunk.generate_mesh(&mut MeshScratch::default(), style, &mut mesh);
+    mesh
+}
+
+#[test]
+fn single_voxel_has_six_faces() {
+    let mut chunk = Chunk::new();
+    chunk.set_voxel(3, 4, 5, 1);
+    let mesh = generate(&mut chunk);
+    assert_eq!(mesh.vertex_count(), 6 * 4);
+    assert_eq!(mesh.indices.len(), 6 * 6);
+}
+
+#[test]
+fn pair_skips_the_faces_between() {
+    let mut chunk = Chunk::new();
+    chunk.set_voxel(3, 4, 5, 1);
+    chunk.set_voxel(4, 4, 5, 2);
+    // Different types aren't merged, the two inner faces are gone.
+    assert_eq!(generate(&mut chunk).vertex_count(), 10 * 4);
+
+    // The same type merges into the faces of a box.
+    chunk.set_voxel(4, 4, 5, 1);
+    assert_eq!(generate(&mut chunk).vertex_count(), 6 * 4);
+
+    chunk.set_voxel(4, 4, 5, 0);
+    assert_eq!(generate(&mut chunk).vertex_count(), 6 * 4);
+}
+
+#[test]
+fn edits_need_a_mesh_update() {
+    let mut chunk = Chunk::new();
+    assert!(chunk.need_mesh_update());
+    generate(&mut chunk);
+    assert!(!chunk.need_mesh_update());
+
+    chunk.set_voxel(0, 0, 0, 1);
+    assert!(chunk.need_mesh_update());
+    generate(&mut chunk);
+    assert!(!chunk.need_mesh_update());
+
+    // Setting the same voxel again changes nothing.
+    chunk.set_voxel(0, 0, 0, 1);
+    assert!(!chunk.need_mesh_update());
+    chunk.set_damage(0, 0, 0, 2);
+    assert!(chunk.need_mesh_update());
+}
+
+#[test]
+#[should_panic(expected = "out of the chunk")]
+fn voxels_out_of_the_chunk_are_refused() {
+    // x = 32 would otherwise land on (0, 1, 0).
+    let mut chunk = Chunk::new();
+    chunk.set_voxel(32, 0, 0, 1);
+}
+
+#[test]
+#[should_panic(expected = "out of the chunk")]
+fn reads_out_of_the_chunk_are_refused() {
+    Chunk::new().get_voxel(0, 0, 32);
+}
@@ -0,0 +1,143 @@
+//! Chunks meshed again once edited, and the chunk meshes drawn in view.
+
+use nalgebra::Point3;
+
+use avenir::chunk::{MeshScratch, VoxelId, AIR};
+use avenir::chunk_meshes::{chunk_draws, ChunkMeshes, MAX_CHUNK_MESHES};
+use avenir::color::Color;
+use avenir::coords::{ChunkCoord, FloatingOrigin, WorldPos};
+use avenir::meshing::{MeshData, VoxelStyle};
+use avenir::world::World;
+
+fn style(_: VoxelId) -> VoxelStyle {
+    VoxelStyle {
+        color: Color::WHITE,
+        flags: 0,
+        texture: None,
+    }
+}
+
+fn mesh_dirty(world: &mut World, meshes: &mut ChunkMeshes) -> usize {
+    world.mesh_dirty_chunks(&mut MeshScratch::default(), style, meshes)
+}
+
+/// Single quad, for meshes whose content doesn't matter.
+fn quad() -> MeshData {
+    let mut mesh = MeshData::new();
+    for &(x, z) in &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+        mesh.push_vertex([x, 0.0, z], [0.0, 1.0, 0.0], Color::WHITE, 0);
+    }
+    mesh.indices.extend_from_slice(&[0, 1, 2, 0, 2, 3]);
+    mesh
+}
+
+#[test]
+fn placed_voxels_update_the_mesh() {
+    let mut world = World::default();
+    let mut meshes = ChunkMeshes::new();
+    world.set_voxel(&WorldPos::new(4, 4, 4), 1);
+    assert_eq!(mesh_dirty(&mut world, &mut meshes), 1);
+    let coord = ChunkCoord::new(0, 0, 0);
+    let first = meshes.get(&coord).unwrap().generation();
+    let first_vertices = meshes.get(&coord).unwrap().mesh().vertex_count();
+
+    // Nothing changed, nothing is meshed again.
+    assert_eq!(mesh_dirty(&mut world, &mut meshes), 0);
+    assert_eq!(meshes.get(&coord).unwrap().generation(), first);
+
+    // A voxel apart from the first adds its own faces.
+    world.set_voxel(&WorldPos::new(10, 4, 4), 1);
+    assert_eq!(mesh_dirty(&mut world, &mut meshes), 1);
+    let mesh = meshes.get(&coord).unwrap();
+    assert_ne!(mesh.generation(), first);
+    assert_eq!(mesh.mesh().vertex_count(), first_vertices * 2);
+    assert!(mesh.bounds().max.x >= 11.0);
+}
+
+#[test]
+fn only_edited_chunks_are_meshed_again() {
+    let mut world = World::default();
+    let mut meshes = ChunkMeshes::new();
+    world.set_voxel(&WorldPos::new(4, 4, 4), 1);
+    world.set_voxel(&WorldPos::new(40, 4, 4), 1);
+    assert_eq!(mesh_dirty(&mut world, &mut meshes), 2);
+
+    let kept = meshes.get(&ChunkCoord::new(0, 0, 0)).unwrap().generation();
+    world.set_voxel(&WorldPos::new(41, 4, 4), 1);
+    assert_eq!(mesh_dirty(&mut world, &mut meshes), 1);
+    assert_eq!(
+        meshes.get(&ChunkCoord::new(0, 0, 0)).unwrap().generation(),
+        kept
+    );
+}
+
+#[test]
+fn emptied_chunks_lose_their_mesh() {
+    let mut world = World::default();
+    let mut meshes = ChunkMeshes::new();
+    let pos = WorldPos::new(4, 4, 4);
+    world.set_voxel(&pos, 1);
+    mesh_dirty(&mut world, &mut meshes);
+    assert_eq!(meshes.len(), 1);
+
+    world.set_voxel(&pos, AIR);
+    assert_eq!(mesh_dirty(&mut world, &mut meshes), 1);
+    assert!(meshes.is_empty());
+
+    meshes.set(ChunkCoord::new(0, 0, 0), quad());
+    meshes.set(ChunkCoord::new(0, 0, 0), MeshData::new());
+    assert!(!meshes.contains(&ChunkCoord::new(0, 0, 0)));
+}
+
+#[test]
+fn draws_skip_chunks_out_of_view() {
+    let mut meshes = ChunkMeshes::new();
+    for x in 0..3 {
+        meshes.set(ChunkCoord::new(x, 0, 0), quad());
+    }
+    let origin = FloatingOrigin::default();
+    let eye = Point3::origin();
+    let mut instances = Vec::new();
+    // Only the chunks left of x = 40 are in view.
+    let (draws, stats) = chunk_draws(
+        &meshes,
+        &origin,
+        &eye,
+        |aabb| aabb.min.x < 40.0,
+        &mut instances,
+    );
+    let coords: Vec<_> = draws.iter().map(|draw| draw.coord).collect();
+    assert_eq!(
+        coords,
+        vec![ChunkCoord::new(0, 0, 0), ChunkCoord::new(1, 0, 0)]
+    );
+    assert_eq!(stats.tested, 3);
+    assert_eq!(stats.frustum_culled, 1);
+    assert_eq!(stats.drawn, 2);
+
+    // Each instance moves its mesh to the chunk.
+    assert_eq!(instances.len(), 2);
+    assert_eq!(instances[1].model[3], [32.0, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn draws_keep_the_nearest_chunks_past_the_budget() {
+    let mut meshes = ChunkMeshes::new();
+    let count = MAX_CHUNK_MESHES as i64 + 8;
+    for x in 0..count {
+        meshes.set(ChunkCoord::new(x, 0, 0), quad());
+    }
+    let mut instances = Vec::new();
+    let (draws, stats) = chunk_draws(
+        &meshes,
+        &FloatingOrigin::default(),
+        &Point3::origin(),
+        |_| true,
+        &mut instances,
+    );
+    assert_eq!(draws.len(), MAX_CHUNK_MESHES);
+    assert_eq!(stats.budget_culled, 8);
+    assert!(draws
+        .iter()
+        .all(|draw| draw.coord.x < MAX_CHUNK_MESHES as i64));
+}
@@ -0,0 +1,44 @@
+//! Encoding of the colors written to the surface.
+//!
+//! Surfaces with an sRGB format encode the linear colors themselves, the
+//! shaders encode them for the others, with the sRGB curve unless a gamma
+//! is given.
+
+use rendy::hal::format::Format;
+
+use avenir::color::{Color, OutputEncoding};
+
+fn close(a: [f32; 4], b: [f32; 4]) -> bool {
+    a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-5)
+}
+
+#[test]
+fn srgb_surfaces_take_linear_colors() {
+    for &format in &[Format::Bgra8Srgb, Format::Rgba8Srgb] {
+        let output = OutputEncoding::for_format(format, Some(2.2));
+        assert_eq!(output, OutputEncoding::Linear);
+        assert_eq!(output.constant(), 0.0);
+        let color = Color::new(0.2, 0.5, 0.8, 0.5);
+        assert_eq!(output.encode(color), color.to_array());
+    }
+}
+
+#[test]
+fn other_surfaces_are_encoded_by_the_shaders() {
+    let output = OutputEncoding::for_format(Format::Bgra8Unorm, None);
+    assert_eq!(output, OutputEncoding::Srgb);
+    assert!(output.constant() < 0.0);
+
+    let color = Color::from_srgb(0.25, 0.5, 0.75, 0.5);
+    assert!(close(output.encode(color), [0.25, 0.5, 0.75, 0.5]));
+}
+
+#[test]
+fn gamma_override_encodes_with_its_power() {
+    let output = OutputEncoding::for_format(Format::Rgba8Unorm, Some(2.0));
+    assert_eq!(output, OutputEncoding::Gamma(2.0));
+    assert_eq!(output.constant(), 2.0);
+
+    let encoded = output.encode(Color::new(0.25, 0.04, -1.0, 0.3));
+    assert!(close(encoded, [0.5, 0.2, 0.0, 0.3]));
+}
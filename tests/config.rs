@@ -0,0 +1,105 @@
+//! Checks of renderer configurations against the limits of a device.
+
+use rendy::hal;
+
+use avenir::config::{
+    ConfigProblem, QualityPreset, RendererConfig, ShadowProjection, MAX_SHADOW_CASCADES,
+};
+
+const ZNEAR: f32 = 1.0;
+
+/// Device rendering 1, 2 and 4 MSAA samples to images up to 4096 pixels.
+fn limits() -> hal::Limits {
+    hal::Limits {
+        max_image_2d_size: 4096,
+        framebuffer_color_sample_counts: 0b111,
+        ..hal::Limits::default()
+    }
+}
+
+#[test]
+fn presets_pass_on_a_capable_device() {
+    for &preset in &[
+        QualityPreset::Low,
+        QualityPreset::Medium,
+        QualityPreset::High,
+    ] {
+        let config = RendererConfig::default().with_preset(preset);
+        let checked = config.clone().check(&limits(), true, ZNEAR).unwrap();
+        assert_eq!(checked.quality, config.quality);
+    }
+}
+
+#[test]
+fn every_problem_is_listed() {
+    let mut config = RendererConfig::default();
+    config.occlusion_culling = true;
+    config.quality.msaa_samples = 3;
+    config.quality.shadow_resolution = 8192;
+    config.quality.shadow_cascades = MAX_SHADOW_CASCADES + 1;
+    config.quality.view_distance = 0.5;
+    config.shadow_projection = ShadowProjection::TopDown { distance: 0.0 };
+    config.tuning.ambient = 2.0;
+    config.tuning.cloud_steps = 0;
+    config.quality.resolution_scale = 1.5;
+
+    let err = config.check(&limits(), true, ZNEAR).unwrap_err();
+    assert_eq!(
+        err.problems,
+        vec![
+            ConfigProblem::OcclusionWithoutGpuCulling,
+            ConfigProblem::InvalidMsaa(3),
+            ConfigProblem::ShadowResolutionTooLarge {
+                requested: 8192,
+                max: 4096
+            },
+            ConfigProblem::InvalidShadowCascades(MAX_SHADOW_CASCADES + 1),
+            ConfigProblem::InvalidShadowDistance(0.0),
+            ConfigProblem::InvalidAmbient(2.0),
+            ConfigProblem::NoCloudSteps,
+            ConfigProblem::InvalidResolutionScale(1.5),
+            ConfigProblem::ViewDistanceTooShort {
+                view_distance: 0.5,
+                znear: ZNEAR
+            },
+        ]
+    );
+}
+
+#[test]
+fn missing_features_are_degraded_instead_of_failing() {
+    let mut config = RendererConfig::default();
+    config.gpu_culling = true;
+    config.occlusion_culling = true;
+    config.gpu_skinning = true;
+    config.quality.msaa_samples = 8;
+
+    let checked = config.check(&limits(), false, ZNEAR).unwrap();
+    assert!(!checked.gpu_culling);
+    assert!(!checked.occlusion_culling);
+    assert!(!checked.gpu_skinning);
+    assert_eq!(checked.quality.msaa_samples, 4);
+    assert_eq!(checked.preset, QualityPreset::Custom);
+}
+
+#[test]
+fn rejected_configs_leave_the_current_one() {
+    let mut current = RendererConfig::default().with_preset(QualityPreset::Low);
+    let mut rejected = RendererConfig::default().with_preset(QualityPreset::High);
+    rejected.tuning.cloud_steps = 0;
+
+    let err = current
+        .replace_checked(rejected, &limits(), true, ZNEAR)
+        .unwrap_err();
+    assert_eq!(err.problems, vec![ConfigProblem::NoCloudSteps]);
+    assert_eq!(current.preset, QualityPreset::Low);
+    assert_eq!(current.quality, QualityPreset::Low.settings().unwrap());
+    assert_ne!(current.tuning.cloud_steps, 0);
+
+    let accepted = RendererConfig::default().with_preset(QualityPreset::High);
+    let previous = current
+        .replace_checked(accepted, &limits(), true, ZNEAR)
+        .unwrap();
+    assert_eq!(previous.preset, QualityPreset::Low);
+    assert_eq!(current.preset, QualityPreset::High);
+}
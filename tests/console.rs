@@ -0,0 +1,229 @@
+//! Console commands, history, errors and the overlay drawing it.
+
+use nalgebra::Point3;
+use rendy::hal::pso::Rect;
+
+use avenir::camera::Camera;
+use avenir::config::RendererConfig;
+use avenir::console::{CommandContext, Console};
+use avenir::console_overlay::{
+    glyph, layout, ConsoleOverlay, CELL_HEIGHT, CELL_WIDTH, GLYPH_WIDTH,
+};
+use avenir::scene::Scene;
+
+fn scene() -> Scene {
+    Scene::new(Camera::look_at(
+        10.0,
+        Point3::new(0.0, 0.0, -10.0),
+        Point3::origin(),
+        1.0,
+    ))
+}
+
+fn echo_console() -> Console {
+    let mut console = Console::empty();
+    console.register_command("echo", "echo <words>, print the words", |_, args| {
+        Ok(args.join(" "))
+    });
+    console
+}
+
+fn submit(console: &mut Console, scene: &mut Scene, line: &str) {
+    let mut config = RendererConfig::default();
+    for c in line.chars() {
+        console.type_char(c);
+    }
+    console.submit(&mut CommandContext {
+        scene,
+        config: &mut config,
+    });
+}
+
+#[test]
+fn lines_are_split_into_command_and_arguments() {
+    let console = echo_console();
+    let mut scene = scene();
+    let mut config = RendererConfig::default();
+    let mut ctx = CommandContext {
+        scene: &mut scene,
+        config: &mut config,
+    };
+    assert_eq!(
+        console.execute(&mut ctx, "  echo a   b\t"),
+        Ok("a b".to_owned())
+    );
+    assert_eq!(console.execute(&mut ctx, "echo"), Ok(String::new()));
+    assert_eq!(
+        console.execute(&mut ctx, "help"),
+        Ok("echo: echo <words>, print the words".to_owned())
+    );
+}
+
+#[test]
+fn builtins_change_the_scene() {
+    let console = Console::new();
+    let mut scene = scene();
+    let mut config = RendererConfig::default();
+    let mut ctx = CommandContext {
+        scene: &mut scene,
+        config: &mut config,
+    };
+    assert!(console.execute(&mut ctx, "time 25").is_ok());
+    assert!(console.execute(&mut ctx, "debug wireframe on").is_ok());
+    assert!(console.execute(&mut ctx, "budget 100").is_ok());
+    assert_eq!(scene.time_of_day, 1.0);
+    assert!(scene.debug_view("wireframe"));
+    assert_eq!(scene.draw_budget, Some(100));
+}
+
+#[test]
+fn bad_lines_are_errors() {
+    let console = Console::new();
+    let mut scene = scene();
+    let mut config = RendererConfig::default();
+    let mut ctx = CommandContext {
+        scene: &mut scene,
+        config: &mut config,
+    };
+    let error = |ctx: &mut CommandContext, line| console.execute(ctx, line).unwrap_err();
+    assert_eq!(error(&mut ctx, "   "), "empty command");
+    assert_eq!(error(&mut ctx, "fly"), "unknown command `fly`");
+    assert_eq!(error(&mut ctx, "time"), "missing argument");
+    assert_eq!(error(&mut ctx, "time noon"), "invalid argument `noon`");
+    assert_eq!(error(&mut ctx, "teleport 1 2"), "expected 3 coordinates");
+    assert_eq!(
+        error(&mut ctx, "debug wireframe maybe"),
+        "expected on or off, got `maybe`"
+    );
+    assert_eq!(
+        error(&mut ctx, "quality extreme"),
+        "expected low, medium, high or ultra"
+    );
+    // Nothing changed.
+    assert_eq!(scene.time_of_day, 12.0);
+}
+
+#[test]
+fn submitted_errors_are_printed() {
+    let mut console = echo_console();
+    let mut scene = scene();
+    submit(&mut console, &mut scene, "echo hi");
+    submit(&mut console, &mut scene, "fly");
+    assert_eq!(
+        console.output(),
+        &["> echo hi", "hi", "> fly", "error: unknown command `fly`"]
+    );
+    assert_eq!(console.input(), "");
+}
+
+#[test]
+fn history_walks_the_submitted_lines() {
+    let mut console = echo_console();
+    let mut scene = scene();
+    submit(&mut console, &mut scene, "echo a");
+    submit(&mut console, &mut scene, "echo b");
+    // Repeated and blank lines aren't kept.
+    submit(&mut console, &mut scene, "echo b");
+    submit(&mut console, &mut scene, "  ");
+    assert_eq!(console.history(), &["echo a", "echo b"]);
+
+    console.history_prev();
+    assert_eq!(console.input(), "echo b");
+    console.history_prev();
+    console.history_prev();
+    assert_eq!(console.input(), "echo a");
+    console.history_next();
+    assert_eq!(console.input(), "echo b");
+    console.history_next();
+    assert_eq!(console.input(), "");
+
+    // Editing a recalled line starts a new one.
+    console.history_prev();
+    console.type_char('c');
+    console.history_next();
+    assert_eq!(console.input(), "echo bc");
+}
+
+#[test]
+fn the_overlay_shows_the_open_console() {
+    let mut console = echo_console();
+    let mut scene = scene();
+    submit(&mut console, &mut scene, "echo hi");
+    console.type_char('x');
+    assert_eq!(console.overlay(), None);
+    console.toggle();
+    let overlay = console.overlay().unwrap();
+    assert_eq!(overlay.output, vec!["> echo hi", "hi"]);
+    assert_eq!(overlay.input, "x");
+}
+
+#[test]
+fn text_is_drawn_inside_the_panel() {
+    let area = Rect {
+        x: 0,
+        y: 40,
+        w: 640,
+        h: 360,
+    };
+    let overlay = ConsoleOverlay {
+        output: (0..100).map(|i| format!("line {}", i)).collect(),
+        input: "time 12".to_owned(),
+    };
+    let drawn = layout(&overlay, area, 2);
+    assert_eq!(
+        drawn.panel,
+        Rect {
+            x: 0,
+            y: 40,
+            w: 640,
+            h: 180
+        }
+    );
+    assert!(!drawn.text.is_empty());
+    assert!(drawn.text.iter().all(|rect| rect.x >= 0
+        && rect.y >= 40
+        && rect.x + rect.w <= 640
+        && rect.y + rect.h <= 220
+        && rect.w % 2 == 0
+        && rect.h == 2));
+}
+
+#[test]
+fn only_the_last_lines_fit() {
+    let area = Rect {
+        x: 0,
+        y: 0,
+        w: 640,
+        h: 360,
+    };
+    // 180 pixels of panel hold 19 rows, with a row of margin.
+    let rows = (180 - CELL_HEIGHT) / CELL_HEIGHT;
+    let many = ConsoleOverlay {
+        output: (0..100).map(|_| "#".to_owned()).collect(),
+        input: String::new(),
+    };
+    let top = layout(&many, area, 1).text.iter().map(|rect| rect.y).min();
+    assert_eq!(top, Some(180 - rows * CELL_HEIGHT));
+
+    // Long input lines keep their end, with the cursor, in view.
+    let columns = (640 - 2 * CELL_WIDTH) / CELL_WIDTH;
+    let long = ConsoleOverlay {
+        output: Vec::new(),
+        input: "x".repeat(columns as usize * 2),
+    };
+    let right = layout(&long, area, 1)
+        .text
+        .iter()
+        .map(|rect| rect.x + rect.w)
+        .max();
+    assert_eq!(right, Some(CELL_WIDTH * columns + GLYPH_WIDTH));
+}
+
+#[test]
+fn letters_are_drawn_in_upper_case() {
+    assert_eq!(glyph('a'), glyph('A'));
+    assert_ne!(glyph('A'), glyph('B'));
+    assert_eq!(glyph(' '), [0; 7]);
+    assert_eq!(glyph('é'), glyph('?'));
+    assert_ne!(glyph('|'), glyph('?'));
+}
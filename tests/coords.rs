@@ -0,0 +1,21 @@
+//! Floating origin precision far from the world origin.
+
+use nalgebra::Vector3;
+
+use avenir::coords::{ChunkCoord, FloatingOrigin, Location};
+
+#[test]
+fn moving_the_origin_keeps_distant_locations_exact() {
+    let location = Location::new(
+        ChunkCoord::new(40_000_000, -3, 25_000_000),
+        Vector3::new(0.3, 17.7, 31.1),
+    );
+    let mut origin = FloatingOrigin::default();
+    // Through render space the offset is lost to f32 rounding.
+    assert_ne!(origin.to_render(&location), location.offset);
+
+    let shift = origin.move_to(location.chunk);
+    assert_eq!(origin.origin(), location.chunk);
+    assert_eq!(origin.to_render(&location), location.offset);
+    assert_eq!(shift, location.chunk.offset_to(&ChunkCoord::default()));
+}
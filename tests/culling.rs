@@ -0,0 +1,70 @@
+//! Frustum tests against hand-computed cases.
+//!
+//! The camera sits at the origin looking down -Z with a vertical field of
+//! view of 60 degrees, a square aspect and planes at 1 and 400, so at depth
+//! `z` the sides are at `±z * tan(30°)`, `±5.7735` at a depth of 10.
+
+use nalgebra::Point3;
+
+use avenir::camera::Camera;
+use avenir::culling::{CullingStats, Frustum};
+
+fn frustum() -> Frustum {
+    let camera = Camera::look_at(1.0, Point3::origin(), Point3::new(0.0, 0.0, -1.0), 1.0);
+    Frustum::from_camera(&camera)
+}
+
+#[test]
+fn objects_behind_the_camera_are_culled() {
+    let frustum = frustum();
+    assert!(frustum.contains_sphere(&Point3::new(0.0, 0.0, -10.0), 1.0));
+    assert!(!frustum.contains_sphere(&Point3::new(0.0, 0.0, 10.0), 1.0));
+    assert!(!frustum.contains_aabb(&Point3::new(-1.0, -1.0, 5.0), &Point3::new(1.0, 1.0, 7.0)));
+    // Beyond the far plane.
+    assert!(!frustum.contains_sphere(&Point3::new(0.0, 0.0, -402.0), 1.0));
+}
+
+#[test]
+fn spheres_at_the_edge_of_the_fov() {
+    let frustum = frustum();
+    // The center is 0.7265 above the top plane at depth 10, 0.6292 away
+    // from it along its normal, tilted by 30 degrees.
+    let center = Point3::new(0.0, 6.5, -10.0);
+    assert!(frustum.contains_sphere(&center, 0.7));
+    assert!(!frustum.contains_sphere(&center, 0.55));
+    // Same on the right side, the aspect is square.
+    let center = Point3::new(6.5, 0.0, -10.0);
+    assert!(frustum.contains_sphere(&center, 0.7));
+    assert!(!frustum.contains_sphere(&center, 0.55));
+}
+
+#[test]
+fn boxes_straddling_a_plane_are_kept() {
+    let frustum = frustum();
+    // Across the near plane.
+    assert!(frustum.contains_aabb(&Point3::new(-0.1, -0.1, -2.0), &Point3::new(0.1, 0.1, -0.5)));
+    // Across the right plane.
+    assert!(frustum.contains_aabb(&Point3::new(5.0, -1.0, -10.5), &Point3::new(7.0, 1.0, -9.5)));
+    // Just right of it, the side reaches 6.0622 at a depth of 10.5.
+    assert!(!frustum.contains_aabb(&Point3::new(6.1, -1.0, -10.5), &Point3::new(7.0, 1.0, -9.5)));
+}
+
+#[test]
+fn stats_of_separate_culling_add_up() {
+    let mut stats = CullingStats {
+        tested: 10,
+        frustum_culled: 4,
+        drawn: 6,
+        ..CullingStats::default()
+    };
+    stats.add(&CullingStats {
+        tested: 3,
+        frustum_culled: 1,
+        drawn: 2,
+        ..CullingStats::default()
+    });
+    assert_eq!(
+        (stats.tested, stats.frustum_culled, stats.drawn),
+        (13, 5, 8)
+    );
+}
@@ -0,0 +1,93 @@
+//! Checks of the debug palettes under simulated color blindness.
+
+use avenir::color::Color;
+use avenir::coords::ChunkCoord;
+use avenir::debug_palette::DebugPalette;
+
+/// Machado et al. 2009 simulation matrices at full severity, linear RGB.
+const PROTANOPIA: [[f32; 3]; 3] = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+const DEUTERANOPIA: [[f32; 3]; 3] = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+
+/// Color as seen with a deficiency, sRGB encoded.
+fn seen(color: Color, matrix: &[[f32; 3]; 3]) -> [f32; 3] {
+    let rgb = [color.r, color.g, color.b];
+    let row = |i: usize| {
+        let value: f32 = (0..3).map(|j| matrix[i][j] * rgb[j]).sum();
+        value.max(0.0).min(1.0)
+    };
+    let srgb = Color::rgb(row(0), row(1), row(2)).to_srgb();
+    [srgb[0], srgb[1], srgb[2]]
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>().sqrt()
+}
+
+/// Smallest distance between two of the first 8 categories as seen.
+fn closest_categories(palette: DebugPalette, matrix: &[[f32; 3]; 3]) -> f32 {
+    let colors: Vec<_> = (0..8).map(|i| seen(palette.category(i), matrix)).collect();
+    let mut closest = std::f32::INFINITY;
+    for i in 0..colors.len() {
+        for j in i + 1..colors.len() {
+            closest = closest.min(distance(colors[i], colors[j]));
+        }
+    }
+    closest
+}
+
+fn luminance(color: Color) -> f32 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+#[test]
+fn colorblind_categories_stay_apart() {
+    for matrix in &[PROTANOPIA, DEUTERANOPIA] {
+        let safe = closest_categories(DebugPalette::ColorblindSafe, matrix);
+        let rainbow = closest_categories(DebugPalette::Rainbow, matrix);
+        assert!(safe > 0.15, "{}", safe);
+        assert!(safe > rainbow * 2.0, "{} {}", safe, rainbow);
+    }
+}
+
+#[test]
+fn colorblind_ramp_gets_lighter() {
+    let palette = DebugPalette::ColorblindSafe;
+    for matrix in &[PROTANOPIA, DEUTERANOPIA] {
+        let lightness: Vec<_> = (0..=15)
+            .map(|level| {
+                let [r, g, b] = seen(palette.light(level), matrix);
+                luminance(Color::from_srgb(r, g, b, 1.0))
+            })
+            .collect();
+        assert!(lightness.windows(2).all(|pair| pair[1] > pair[0]));
+    }
+    assert_eq!(palette.ramp(-1.0), palette.light(0));
+    assert_eq!(palette.ramp(2.0), palette.light(15));
+}
+
+#[test]
+fn neighbor_chunks_have_different_colors() {
+    let palette = DebugPalette::ColorblindSafe;
+    let coord = ChunkCoord::new(-3, 7, 2);
+    let color = palette.chunk(&coord);
+    for &(x, y, z) in &[
+        (1, 0, 0),
+        (0, 1, 0),
+        (0, 0, 1),
+        (-1, 0, 0),
+        (0, -1, 0),
+        (0, 0, -1),
+    ] {
+        let neighbor = ChunkCoord::new(coord.x + x, coord.y + y, coord.z + z);
+        assert_ne!(palette.chunk(&neighbor), color);
+    }
+    assert_ne!(palette.lod(0), palette.lod(1));
+}
@@ -0,0 +1,119 @@
+//! Explosions carving solid blocks: radius, falloff and chunk borders.
+
+use nalgebra::Vector3;
+
+use avenir::chunk::AIR;
+use avenir::coords::{ChunkCoord, Location, WorldPos};
+use avenir::world::World;
+
+/// World solid from `min` to `max` excluded along each axis.
+fn solid(min: i64, max: i64) -> World {
+    let mut world = World::default();
+    for z in min..max {
+        for y in min..max {
+            for x in min..max {
+                world.set_voxel(&WorldPos::new(x, y, z), 1);
+            }
+        }
+    }
+    world
+}
+
+fn at(x: f32, y: f32, z: f32) -> Location {
+    Location::new(ChunkCoord::new(0, 0, 0), Vector3::new(x, y, z))
+}
+
+/// Distance from `center` to the center of the voxel at `pos`.
+fn distance(center: &Location, pos: &WorldPos) -> f32 {
+    let voxel = Vector3::new(pos.x as f32, pos.y as f32, pos.z as f32).add_scalar(0.5);
+    (voxel - center.offset).norm()
+}
+
+#[test]
+fn voxels_are_carved_within_the_radius() {
+    let mut world = solid(0, 24);
+    let center = at(12.5, 12.5, 12.5);
+    let explosion = world.explode(&center, 5.0, 1.0);
+    assert!(!explosion.is_empty());
+
+    // Destroyed voxels are touched by the blast and gone from the world.
+    let half_diagonal = 3.0f32.sqrt() / 2.0;
+    for (pos, id) in &explosion.destroyed {
+        assert_eq!(*id, 1);
+        assert!(distance(&center, pos) <= 5.0 + half_diagonal);
+        assert_eq!(world.get_voxel(pos), AIR);
+    }
+    // The blast is strongest at the center.
+    assert_eq!(world.get_voxel(&WorldPos::new(12, 12, 12)), AIR);
+    assert_eq!(world.get_voxel(&WorldPos::new(13, 12, 12)), AIR);
+    // Out of reach.
+    assert_eq!(world.get_voxel(&WorldPos::new(12, 12, 19)), 1);
+    assert_eq!(world.get_voxel(&WorldPos::new(6, 12, 12)), 1);
+}
+
+#[test]
+fn stronger_falloff_carves_less() {
+    let center = at(12.5, 12.5, 12.5);
+    let carved = |falloff: f32| solid(0, 24).explode(&center, 6.0, falloff).destroyed.len();
+    let soft = carved(0.5);
+    let linear = carved(1.0);
+    let sharp = carved(3.0);
+    assert!(soft > linear, "{} <= {}", soft, linear);
+    assert!(linear > sharp, "{} <= {}", linear, sharp);
+
+    // In the open nothing absorbs the blast, any falloff reaches the radius.
+    for &falloff in &[0.5, 1.0, 3.0] {
+        let mut world = World::default();
+        let target = WorldPos::new(16, 12, 12);
+        world.set_voxel(&target, 1);
+        let explosion = world.explode(&center, 5.0, falloff);
+        assert_eq!(explosion.destroyed, vec![(target, 1)]);
+    }
+}
+
+#[test]
+fn blasts_across_chunk_borders_update_both_chunks() {
+    let mut world = solid(-8, 8);
+    let explosion = world.explode(&at(0.0, 4.5, 4.5), 3.0, 1.0);
+
+    let left = ChunkCoord::new(-1, 0, 0);
+    let right = ChunkCoord::new(0, 0, 0);
+    let destroyed: Vec<_> = explosion.positions().collect();
+    assert!(destroyed.iter().any(|pos| pos.chunk() == left));
+    assert!(destroyed.iter().any(|pos| pos.chunk() == right));
+    assert!(explosion.chunks.contains(&left));
+    assert!(explosion.chunks.contains(&right));
+
+    // Each chunk is listed once, in order.
+    let mut sorted = explosion.chunks.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted, explosion.chunks);
+}
+
+#[test]
+fn destroyed_border_voxels_update_the_next_chunk() {
+    // Only the chunk at the origin holds voxels, the blast uncovers the
+    // faces of its neighbour along x.
+    let mut world = World::default();
+    let border = WorldPos::new(31, 4, 4);
+    world.set_voxel(&border, 1);
+    let explosion = world.explode(&at(30.5, 4.5, 4.5), 2.0, 1.0);
+    assert_eq!(explosion.destroyed, vec![(border, 1)]);
+    assert_eq!(
+        explosion.chunks,
+        vec![ChunkCoord::new(0, 0, 0), ChunkCoord::new(1, 0, 0)]
+    );
+}
+
+#[test]
+fn invalid_explosions_change_nothing() {
+    let mut world = solid(0, 4);
+    let center = at(2.0, 2.0, 2.0);
+    assert!(world.explode(&center, 0.0, 1.0).is_empty());
+    assert!(world.explode(&center, -2.0, 1.0).is_empty());
+    assert!(world.explode(&center, std::f32::NAN, 1.0).is_empty());
+    let nan = at(std::f32::NAN, 2.0, 2.0);
+    assert!(world.explode(&nan, 2.0, 1.0).is_empty());
+    assert_eq!(world.get_voxel(&WorldPos::new(2, 2, 2)), 1);
+}
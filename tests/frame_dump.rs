@@ -0,0 +1,90 @@
+//! Checks of the files written by the frame dumps.
+
+use avenir::frame_dump::{encode_png, to_rgba8};
+use rendy::hal::format::Format;
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Chunks of a PNG file as id and data.
+fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut chunks = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let len = be_u32(rest) as usize;
+        let mut id = [0; 4];
+        id.copy_from_slice(&rest[4..8]);
+        chunks.push((id, rest[8..8 + len].to_vec()));
+        rest = &rest[12 + len..];
+    }
+    chunks
+}
+
+/// Contents of a zlib stream of stored blocks.
+fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut rest = &zlib[2..];
+    loop {
+        let last = rest[0] & 1 == 1;
+        assert_eq!(rest[0] >> 1, 0, "only stored blocks are expected");
+        let len = u16::from_le_bytes([rest[1], rest[2]]) as usize;
+        let nlen = u16::from_le_bytes([rest[3], rest[4]]) as usize;
+        assert_eq!(len, !nlen & 0xffff);
+        data.extend_from_slice(&rest[5..5 + len]);
+        rest = &rest[5 + len..];
+        if last {
+            break;
+        }
+    }
+    assert_eq!(rest.len(), 4);
+    data
+}
+
+#[test]
+fn png_holds_the_rows_of_the_image() {
+    // Large enough for several deflate blocks.
+    let (width, height) = (200, 100);
+    let rgba: Vec<u8> = (0..width * height * 4).map(|i| (i % 251) as u8).collect();
+    let png = encode_png(width, height, &rgba);
+
+    let chunks = chunks(&png);
+    let ids: Vec<_> = chunks.iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, vec![b"IHDR", b"IDAT", b"IEND"]);
+    let header = &chunks[0].1;
+    assert_eq!(be_u32(&header[0..]), width);
+    assert_eq!(be_u32(&header[4..]), height);
+    assert_eq!(&header[8..], &[8, 6, 0, 0, 0]);
+
+    let raw = inflate_stored(&chunks[1].1);
+    let row = width as usize * 4;
+    assert_eq!(raw.len(), (row + 1) * height as usize);
+    for (y, line) in raw.chunks(row + 1).enumerate() {
+        assert_eq!(line[0], 0);
+        assert_eq!(&line[1..], &rgba[y * row..(y + 1) * row]);
+    }
+}
+
+#[test]
+fn png_chunks_have_valid_checksums() {
+    // Checksum of the IEND chunk, the same in every PNG file.
+    let png = encode_png(1, 1, &[255, 0, 0, 255]);
+    assert_eq!(&png[png.len() - 4..], &[0xae, 0x42, 0x60, 0x82]);
+}
+
+#[test]
+fn depth_is_stretched_to_grey_levels() {
+    let depth: Vec<u8> = [0.5f32, 0.75, 1.0, std::f32::NAN]
+        .iter()
+        .flat_map(|value| value.to_le_bytes().to_vec())
+        .collect();
+    let rgba = to_rgba8(Format::D32Sfloat, &depth).unwrap();
+    let levels: Vec<_> = rgba.chunks(4).map(|texel| texel[0]).collect();
+    assert_eq!(levels, vec![0, 128, 255, 0]);
+    assert!(rgba.chunks(4).all(|texel| texel[3] == 255));
+
+    let bgra = to_rgba8(Format::Bgra8Srgb, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(bgra, vec![3, 2, 1, 4]);
+    assert!(to_rgba8(Format::Rgba16Sfloat, &[0; 8]).is_none());
+}
@@ -0,0 +1,55 @@
+//! Checks of the shader include expansion.
+
+use avenir::glsl::{preprocess, snippet, IncludeError};
+
+fn resolve(name: &str) -> Option<&'static str> {
+    match name {
+        "a.glsl" => Some("float a() { return 1.0; }"),
+        "b.glsl" => Some("#include \"a.glsl\"\nfloat b() { return a(); }"),
+        "loop.glsl" => Some("#include \"loop.glsl\""),
+        _ => None,
+    }
+}
+
+#[test]
+fn includes_are_expanded_once() {
+    let source = "#version 450\n#include \"b.glsl\"\n#include \"a.glsl\"\nvoid main() {}\n";
+    let expanded = preprocess(source, resolve).unwrap();
+    assert_eq!(
+        expanded,
+        "#version 450\nfloat a() { return 1.0; }\n#line 2\nfloat b() { return a(); }\n\
+         #line 3\nvoid main() {}\n"
+    );
+}
+
+#[test]
+fn sources_without_includes_are_unchanged() {
+    let source = "#version 450\nvoid main() {}\n";
+    assert_eq!(preprocess(source, resolve).unwrap(), source);
+}
+
+#[test]
+fn include_errors() {
+    assert_eq!(
+        preprocess("#include \"missing.glsl\"", resolve),
+        Err(IncludeError::Unknown("missing.glsl".to_owned()))
+    );
+    assert_eq!(
+        preprocess("\n#include <a.glsl>", resolve),
+        Err(IncludeError::Malformed(2))
+    );
+    assert_eq!(
+        preprocess("#include \"loop.glsl\"", resolve),
+        Err(IncludeError::Cycle(vec![
+            "loop.glsl".to_owned(),
+            "loop.glsl".to_owned()
+        ]))
+    );
+}
+
+#[test]
+fn crate_snippets_resolve() {
+    assert!(snippet("fog.glsl").unwrap().contains("apply_fog"));
+    let expanded = preprocess(include_str!("../shader.frag"), snippet).unwrap();
+    assert!(!expanded.contains("#include"));
+}
@@ -0,0 +1,55 @@
+//! Checks of the reference image comparison itself.
+
+use avenir::golden::{check, compare, GoldenError, Image, Tolerance};
+
+/// White square on a grey background.
+fn square(offset: u32) -> Image {
+    let mut image = Image::new(32, 32, [128, 128, 128]);
+    for y in 8..24 {
+        for x in 8 + offset..24 + offset {
+            image.set(x, y, [255, 255, 255]);
+        }
+    }
+    image
+}
+
+#[test]
+fn identical_images_match() {
+    let comparison = compare(&square(0), &square(0), &Tolerance::default());
+    assert_eq!(comparison.differing, 0);
+}
+
+#[test]
+fn small_color_noise_is_tolerated() {
+    let mut noisy = square(0);
+    for pixel in &mut noisy.pixels {
+        pixel[0] = pixel[0].saturating_sub(2);
+    }
+    let tolerance = Tolerance::default();
+    assert!(compare(&square(0), &noisy, &tolerance).matches(&tolerance));
+}
+
+#[test]
+fn moved_shape_is_reported() {
+    let tolerance = Tolerance::default();
+    let comparison = compare(&square(0), &square(4), &tolerance);
+    // Two 4 pixel wide bands of the square's height.
+    assert_eq!(comparison.differing, 2 * 4 * 16);
+    assert!(!comparison.matches(&tolerance));
+    assert_eq!(comparison.diff.get(8, 8), [255, 0, 0]);
+}
+
+#[test]
+fn mismatch_writes_the_diff_image() {
+    let dir = std::env::temp_dir().join(format!("avenir-golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let reference = dir.join("square.ppm");
+    square(0).write_ppm(&reference).unwrap();
+    assert_eq!(Image::read_ppm(&reference).unwrap(), square(0));
+
+    match check(&reference, &square(4), &Tolerance::default()) {
+        Err(GoldenError::Mismatch { diff, .. }) => assert!(diff.exists()),
+        other => panic!("expected a mismatch, got {:?}", other),
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,58 @@
+//! Checks of the reduction plans and their CPU versions.
+
+use avenir::gpu::compute::{
+    exclusive_scan, reduce, reduce_passes, scan_levels, Reduced, GROUP_SIZE,
+};
+
+#[test]
+fn reduction_of_values() {
+    let reduced = reduce(&[2.0, -1.0, 4.0, 3.0]).unwrap();
+    assert_eq!(
+        reduced,
+        Reduced {
+            min: -1.0,
+            max: 4.0,
+            average: 2.0,
+        }
+    );
+    assert_eq!(reduce(&[]), None);
+}
+
+#[test]
+fn raw_results_are_read_back() {
+    let mut bytes = Vec::new();
+    for value in &[0.5f32, 8.0, 12.0, 4.0] {
+        bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+    let reduced = Reduced::from_bytes(&bytes).unwrap();
+    assert_eq!(reduced.average, 3.0);
+    assert_eq!(Reduced::from_bytes(&bytes[..8]), None);
+    assert_eq!(Reduced::from_raw([0.0, 0.0, 0.0, 0.0]), None);
+}
+
+#[test]
+fn reduction_passes_end_on_one_value() {
+    assert_eq!(reduce_passes(1), vec![1]);
+    assert_eq!(reduce_passes(GROUP_SIZE), vec![1]);
+    assert_eq!(reduce_passes(GROUP_SIZE + 1), vec![2, 1]);
+    // A 1080p image.
+    assert_eq!(reduce_passes(1920 * 1080), vec![8100, 32, 1]);
+}
+
+#[test]
+fn exclusive_prefix_sums() {
+    let mut values = vec![3, 0, 2, 5, 1];
+    assert_eq!(exclusive_scan(&mut values), 11);
+    assert_eq!(values, vec![0, 3, 3, 5, 10]);
+}
+
+#[test]
+fn scan_levels_end_on_the_total() {
+    assert_eq!(scan_levels(0), vec![1, 1]);
+    assert_eq!(scan_levels(GROUP_SIZE), vec![GROUP_SIZE, 1]);
+    assert_eq!(scan_levels(1000), vec![1000, 4, 1]);
+    assert_eq!(
+        scan_levels(GROUP_SIZE * GROUP_SIZE + 1),
+        vec![65537, 257, 2, 1]
+    );
+}
@@ -0,0 +1,59 @@
+//! Checks of declared layouts against the layouts of the shaders.
+
+use avenir::gpu::layout::{binding, mismatches, LayoutDesc, LayoutMismatch};
+use rendy::hal::pso::{DescriptorType, ShaderStageFlags};
+
+fn declared() -> LayoutDesc {
+    LayoutDesc::new()
+        .with_set(vec![
+            binding(0, DescriptorType::UniformBuffer, ShaderStageFlags::GRAPHICS),
+            binding(1, DescriptorType::StorageBuffer, ShaderStageFlags::FRAGMENT),
+        ])
+        .with_push_constants(ShaderStageFlags::VERTEX, 0..64)
+}
+
+#[test]
+fn matching_layouts() {
+    let reflected = LayoutDesc::new()
+        .with_set(vec![binding(
+            0,
+            DescriptorType::UniformBuffer,
+            ShaderStageFlags::VERTEX,
+        )])
+        .with_push_constants(ShaderStageFlags::VERTEX, 0..16)
+        .build();
+    assert!(mismatches(&declared().build(), &reflected).is_empty());
+}
+
+#[test]
+fn differences_are_reported() {
+    let reflected = LayoutDesc::new()
+        .with_set(vec![
+            binding(0, DescriptorType::UniformBuffer, ShaderStageFlags::VERTEX),
+            binding(1, DescriptorType::UniformBuffer, ShaderStageFlags::GRAPHICS),
+            binding(2, DescriptorType::StorageBuffer, ShaderStageFlags::FRAGMENT),
+        ])
+        .with_push_constants(ShaderStageFlags::FRAGMENT, 0..16)
+        .build();
+    assert_eq!(
+        mismatches(&declared().build(), &reflected),
+        vec![
+            LayoutMismatch::Type {
+                set: 0,
+                binding: 1,
+                declared: DescriptorType::StorageBuffer,
+                reflected: DescriptorType::UniformBuffer,
+            },
+            LayoutMismatch::Stages {
+                set: 0,
+                binding: 1,
+                missing: ShaderStageFlags::VERTEX,
+            },
+            LayoutMismatch::Missing { set: 0, binding: 2 },
+            LayoutMismatch::PushConstants {
+                stages: ShaderStageFlags::FRAGMENT,
+                range: 0..16,
+            },
+        ]
+    );
+}
@@ -0,0 +1,70 @@
+//! Generational handles: slot reuse, stale handles and released values.
+
+use avenir::handle::HandleMap;
+
+#[test]
+fn removed_slots_are_reused_with_a_new_generation() {
+    let mut map = HandleMap::new();
+    let first = map.insert("first");
+    assert_eq!(map.remove(first), Some("first"));
+
+    let second = map.insert("second");
+    assert_eq!(second.index(), first.index());
+    assert_ne!(second.generation(), first.generation());
+    assert_ne!(second, first);
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn stale_handles_resolve_to_nothing() {
+    let mut map = HandleMap::new();
+    let stale = map.insert(1);
+    map.remove(stale);
+    let fresh = map.insert(2);
+
+    assert_eq!(map.get(stale), None);
+    assert_eq!(map.get_mut(stale), None);
+    assert!(!map.contains(stale));
+    assert_eq!(map.remove(stale), None);
+    assert!(!map.release(stale, 0));
+    assert_eq!(map.get(fresh), Some(&2));
+}
+
+#[test]
+fn released_values_are_kept_until_their_frame_completes() {
+    let mut map = HandleMap::new();
+    let kept = map.insert("kept");
+    let released = map.insert("released");
+
+    assert!(map.release(released, 5));
+    assert!(!map.contains(released));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.released(), 1);
+
+    // The slot isn't reused while the frames in flight may use the value.
+    let other = map.insert("other");
+    assert_ne!(other.index(), released.index());
+    assert_eq!(map.collect(4), 0);
+    assert_eq!(map.released(), 1);
+
+    assert_eq!(map.collect(5), 1);
+    assert_eq!(map.released(), 0);
+    let reused = map.insert("reused");
+    assert_eq!(reused.index(), released.index());
+    assert!(!map.contains(released));
+    assert_eq!(map.get(kept), Some(&"kept"));
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn iteration_skips_removed_and_released_values() {
+    let mut map = HandleMap::new();
+    let a = map.insert('a');
+    let b = map.insert('b');
+    let c = map.insert('c');
+    map.remove(a);
+    map.release(c, 0);
+
+    let values: Vec<_> = map.iter().collect();
+    assert_eq!(values, vec![(b, &'b')]);
+}
@@ -0,0 +1,75 @@
+//! Key bindings of the action map and the camera movement they give.
+
+use avenir::camera::{Camera, CameraMovement};
+use avenir::input::{Action, ActionMap, InputState};
+use avenir::Inputs;
+use nalgebra::Point3;
+use rendy::init::winit::event::VirtualKeyCode;
+
+#[test]
+fn rebinding_moves_the_action_to_the_new_key() {
+    let mut map = ActionMap::default();
+    assert_eq!(map.action(VirtualKeyCode::W), Some(Action::MoveForward));
+    map.rebind(Action::MoveForward, VirtualKeyCode::Up);
+    assert_eq!(map.action(VirtualKeyCode::W), None);
+    assert_eq!(map.action(VirtualKeyCode::Up), Some(Action::MoveForward));
+    assert!(map.keys(Action::RotateUp).is_empty());
+
+    let mut inputs = InputState::new();
+    inputs.key(&map, VirtualKeyCode::W, true);
+    inputs.key(&map, VirtualKeyCode::Up, true);
+    assert_eq!(inputs.movement().forward, 1.0);
+    assert_eq!(inputs.movement().pitch, 0.0);
+}
+
+#[test]
+fn opposite_keys_cancel_out() {
+    let map = ActionMap::default();
+    let mut inputs = InputState::new();
+    inputs.key(&map, VirtualKeyCode::A, true);
+    assert_eq!(inputs.movement().right, -1.0);
+    inputs.key(&map, VirtualKeyCode::D, true);
+    assert_eq!(inputs.movement().right, 0.0);
+
+    // Key repeats don't hold the action twice.
+    inputs.key(&map, VirtualKeyCode::D, true);
+    inputs.key(&map, VirtualKeyCode::A, false);
+    assert_eq!(inputs.movement().right, 1.0);
+    inputs.key(&map, VirtualKeyCode::D, false);
+    assert_eq!(inputs.movement(), CameraMovement::default());
+}
+
+#[test]
+fn two_keys_hold_one_action() {
+    let mut map = ActionMap::default();
+    map.bind(VirtualKeyCode::E, Action::MoveForward);
+    let mut inputs = InputState::new();
+    inputs.key(&map, VirtualKeyCode::W, true);
+    inputs.key(&map, VirtualKeyCode::E, true);
+    inputs.key(&map, VirtualKeyCode::W, false);
+    assert!(inputs.is_held(Action::MoveForward));
+    inputs.key(&map, VirtualKeyCode::E, false);
+    assert!(!inputs.is_held(Action::MoveForward));
+}
+
+#[test]
+fn movement_moves_like_the_inputs() {
+    let camera = || Camera::look_at(10.0, Point3::origin(), Point3::new(1.0, 0.0, 0.0), 1.0);
+    let inputs = Inputs {
+        front: true,
+        back: true,
+        left: true,
+        mouse_x: 4.0,
+        ..Inputs::default()
+    };
+    let (mut by_inputs, mut by_movement) = (camera(), camera());
+    by_inputs.run(&inputs, 0.5);
+    let movement = CameraMovement {
+        right: -1.0,
+        mouse_x: 4.0,
+        ..CameraMovement::default()
+    };
+    assert_eq!(CameraMovement::from(&inputs), movement);
+    by_movement.apply_movement(&movement, 0.5);
+    assert_eq!(by_inputs.view, by_movement.view);
+}
@@ -0,0 +1,132 @@
+//! Checks of the chunks and entities sent to each viewer.
+
+use std::f32::consts::FRAC_PI_2;
+
+use nalgebra::{Perspective3, UnitQuaternion, Vector3};
+
+use avenir::chunk::Chunk;
+use avenir::coords::{ChunkCoord, Location};
+use avenir::interest::{
+    EntityBounds, InterestEvent, InterestManager, InterestSettings, ViewerCamera,
+};
+use avenir::world::World;
+
+fn camera(x: f32, yaw: f32) -> ViewerCamera {
+    ViewerCamera {
+        eye: Location::new(ChunkCoord::default(), Vector3::new(x, 16.0, 16.0)),
+        rotation: UnitQuaternion::from_euler_angles(0.0, yaw, 0.0),
+        proj: Perspective3::new(1.0, FRAC_PI_2, 0.1, 1000.0),
+    }
+}
+
+fn manager() -> InterestManager {
+    InterestManager::new(InterestSettings {
+        radius: 256.0,
+        near_radius: 40.0,
+        margin: 16.0,
+    })
+}
+
+#[test]
+fn far_chunks_need_the_frustum() {
+    let mut world = World::default();
+    for &x in &[-5, 1, 5] {
+        world.insert_chunk(ChunkCoord::new(x, 0, 0), Chunk::new());
+    }
+    let mut interest = manager();
+    // Looking along +X.
+    interest.add_viewer(7, camera(16.0, -FRAC_PI_2));
+    let events = interest.update(&world, &[]);
+    assert_eq!(
+        events,
+        vec![
+            InterestEvent::ChunkAdded {
+                viewer: 7,
+                coord: ChunkCoord::new(1, 0, 0),
+            },
+            InterestEvent::ChunkAdded {
+                viewer: 7,
+                coord: ChunkCoord::new(5, 0, 0),
+            },
+        ]
+    );
+    assert!(interest.update(&world, &[]).is_empty());
+
+    // Turning around adds the chunk behind and keeps the others.
+    interest.move_viewer(7, camera(16.0, FRAC_PI_2));
+    assert_eq!(
+        interest.update(&world, &[]),
+        vec![InterestEvent::ChunkAdded {
+            viewer: 7,
+            coord: ChunkCoord::new(-5, 0, 0),
+        }]
+    );
+    assert_eq!(
+        interest
+            .chunk_viewers(&ChunkCoord::new(5, 0, 0))
+            .collect::<Vec<_>>(),
+        vec![7]
+    );
+
+    // Unloaded chunks are removed.
+    world.remove_chunk(&ChunkCoord::new(1, 0, 0));
+    assert_eq!(
+        interest.update(&world, &[]),
+        vec![InterestEvent::ChunkRemoved {
+            viewer: 7,
+            coord: ChunkCoord::new(1, 0, 0),
+        }]
+    );
+}
+
+#[test]
+fn entities_follow_the_view_with_a_margin() {
+    let entity = |id, x| EntityBounds {
+        entity: id,
+        location: Location::new(ChunkCoord::default(), Vector3::new(x, 16.0, 16.0)),
+        radius: 1.0,
+    };
+    let entities = [entity(1, 116.0), entity(2, -84.0)];
+    let world = World::default();
+    let mut interest = manager();
+    interest.add_viewer(3, camera(16.0, -FRAC_PI_2));
+    assert_eq!(
+        interest.update(&world, &entities),
+        vec![InterestEvent::EntityAdded {
+            viewer: 3,
+            entity: 1,
+        }]
+    );
+
+    interest.move_viewer(3, camera(16.0, FRAC_PI_2));
+    assert_eq!(
+        interest.update(&world, &entities),
+        vec![
+            InterestEvent::EntityRemoved {
+                viewer: 3,
+                entity: 1,
+            },
+            InterestEvent::EntityAdded {
+                viewer: 3,
+                entity: 2,
+            },
+        ]
+    );
+
+    // Backing away past the radius, the margin keeps the entity a while.
+    interest.move_viewer(3, camera(182.0, FRAC_PI_2));
+    let events = interest.update(&world, &entities);
+    assert!(!events.contains(&InterestEvent::EntityRemoved {
+        viewer: 3,
+        entity: 2,
+    }));
+    assert!(interest.sees_entity(3, 2));
+    interest.move_viewer(3, camera(216.0, FRAC_PI_2));
+    assert_eq!(
+        interest.update(&world, &entities),
+        vec![InterestEvent::EntityRemoved {
+            viewer: 3,
+            entity: 2,
+        }]
+    );
+}
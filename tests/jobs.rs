@@ -0,0 +1,45 @@
+//! Scheduling of the jobs by category.
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use avenir::jobs::{JobCategory, JobConfig, JobSystem};
+
+#[test]
+fn panicking_job_leaves_the_system_usable() {
+    let jobs = JobSystem::new(&JobConfig::default().with_threads(1));
+    jobs.spawn(JobCategory::Meshing, || panic!("broken mesh"));
+    jobs.wait_idle();
+    assert_eq!(jobs.pending(JobCategory::Meshing), 0);
+
+    let (sender, receiver) = mpsc::channel();
+    jobs.spawn(JobCategory::Meshing, move || sender.send(()).unwrap());
+    jobs.wait_idle();
+    assert!(receiver.try_recv().is_ok());
+}
+
+#[test]
+fn categories_take_turns() {
+    let jobs = JobSystem::new(&JobConfig::default().with_threads(1));
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    // Hold the only worker until every job is queued.
+    let (release, gate) = mpsc::channel::<()>();
+    jobs.spawn(JobCategory::Meshing, move || gate.recv().unwrap());
+    for category in &[
+        JobCategory::Meshing,
+        JobCategory::Meshing,
+        JobCategory::Meshing,
+        JobCategory::Io,
+    ] {
+        let order = order.clone();
+        let category = *category;
+        jobs.spawn(category, move || order.lock().unwrap().push(category));
+    }
+    release.send(()).unwrap();
+    jobs.wait_idle();
+
+    let order = order.lock().unwrap();
+    assert_eq!(order.len(), 4);
+    assert_eq!(order[0], JobCategory::Io);
+}
@@ -0,0 +1,117 @@
+//! Incremental light updates against lighting the whole chunk again.
+
+use proptest::prelude::*;
+
+use avenir::chunk::{VoxelId, AIR};
+use avenir::coords::{ChunkCoord, WorldPos};
+use avenir::lighting::{LightEngine, LightMap};
+use avenir::world::World;
+
+const STONE: VoxelId = 1;
+const TORCH: VoxelId = 2;
+const LANTERN: VoxelId = 3;
+
+fn emission(id: VoxelId) -> u8 {
+    match id {
+        TORCH => 14,
+        LANTERN => 7,
+        _ => 0,
+    }
+}
+
+fn engine() -> LightEngine {
+    LightEngine::new(emission)
+}
+
+/// Apply an edit to the world and the engine.
+fn set(world: &mut World, engine: &mut LightEngine, pos: WorldPos, id: VoxelId) {
+    world.set_voxel(&pos, id);
+    engine.on_block_changed(world, &pos);
+}
+
+/// Incremental levels of the chunk at the origin match a full relight.
+fn assert_fresh(world: &World, engine: &LightEngine) {
+    let coord = ChunkCoord::new(0, 0, 0);
+    let full = LightMap::compute(world.chunk(&coord).unwrap(), emission);
+    assert!(engine.chunk(&coord).unwrap() == &full, "stale light");
+}
+
+#[test]
+fn removed_torch_leaves_no_light() {
+    let (mut world, mut engine) = (World::default(), engine());
+    let torch = WorldPos::new(8, 8, 8);
+    set(&mut world, &mut engine, torch, TORCH);
+    assert_eq!(engine.light(&WorldPos::new(10, 8, 8)), 12);
+    set(&mut world, &mut engine, torch, AIR);
+    assert_eq!(engine.light(&WorldPos::new(10, 8, 8)), 0);
+    assert_fresh(&world, &engine);
+}
+
+#[test]
+fn removed_torch_keeps_the_light_of_another() {
+    let (mut world, mut engine) = (World::default(), engine());
+    set(&mut world, &mut engine, WorldPos::new(4, 8, 8), TORCH);
+    set(&mut world, &mut engine, WorldPos::new(12, 8, 8), TORCH);
+    set(&mut world, &mut engine, WorldPos::new(4, 8, 8), AIR);
+    assert_eq!(engine.light(&WorldPos::new(8, 8, 8)), 10);
+    assert_eq!(engine.light(&WorldPos::new(4, 8, 8)), 6);
+    assert_fresh(&world, &engine);
+}
+
+#[test]
+fn wall_blocks_and_opening_lets_light_through() {
+    let (mut world, mut engine) = (World::default(), engine());
+    set(&mut world, &mut engine, WorldPos::new(2, 2, 2), STONE);
+    for y in 0..16 {
+        for z in 0..16 {
+            set(&mut world, &mut engine, WorldPos::new(10, y, z), STONE);
+        }
+    }
+    set(&mut world, &mut engine, WorldPos::new(8, 8, 8), TORCH);
+    assert_fresh(&world, &engine);
+
+    let behind = WorldPos::new(11, 8, 8);
+    let lit = engine.light(&behind);
+    set(&mut world, &mut engine, WorldPos::new(10, 8, 8), AIR);
+    assert!(engine.light(&behind) > lit);
+    assert_fresh(&world, &engine);
+
+    set(&mut world, &mut engine, WorldPos::new(10, 8, 8), STONE);
+    assert_eq!(engine.light(&behind), lit);
+    assert_fresh(&world, &engine);
+}
+
+#[test]
+fn unloaded_chunk_is_forgotten() {
+    let (mut world, mut engine) = (World::default(), engine());
+    let pos = WorldPos::new(8, 8, 8);
+    set(&mut world, &mut engine, pos, TORCH);
+    world.remove_chunk(&pos.chunk());
+    engine.on_block_changed(&world, &pos);
+    assert!(engine.chunk(&pos.chunk()).is_none());
+    assert_eq!(engine.light(&pos), 0);
+}
+
+/// Edits in a small corner of the chunk so they interact.
+fn edits() -> impl Strategy<Value = Vec<((i64, i64, i64), VoxelId)>> {
+    let id = prop_oneof![4 => Just(AIR), 4 => Just(STONE), 1 => Just(TORCH), 1 => Just(LANTERN)];
+    prop::collection::vec(((0..10i64, 0..10i64, 0..10i64), id), 1..80)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn incremental_updates_match_a_full_relight(edits in edits()) {
+        let (mut world, mut engine) = (World::default(), engine());
+        for ((x, y, z), id) in edits {
+            set(&mut world, &mut engine, WorldPos::new(x, y, z), id);
+            // Air set in a missing chunk doesn't create it.
+            let coord = ChunkCoord::new(0, 0, 0);
+            if let Some(chunk) = world.chunk(&coord) {
+                let full = LightMap::compute(chunk, emission);
+                prop_assert!(engine.chunk(&coord) == Some(&full), "stale light after {:?}", (x, y, z, id));
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Invariants of the greedy mesher on random chunks.
+//!
+//! Merged rectangles are split back into unit faces, keyed by their voxel
+//! and side, so meshes can be compared whatever the merge order.
+
+use std::collections::{BTreeSet, HashMap};
+
+use proptest::prelude::*;
+
+use avenir::chunk::{Chunk, MeshScratch, VoxelId, AIR};
+use avenir::color::Color;
+use avenir::coords::CHUNK_SIZE;
+use avenir::meshing::{mesh_chunk, MeshData, VoxelStyle};
+
+const SIZE: usize = CHUNK_SIZE as usize;
+
+/// Side of the random block of voxels placed in the chunk.
+const BLOCK: usize = 8;
+
+type Voxel = (usize, usize, usize);
+
+/// Unit face: its voxel and outward normal.
+type UnitFace = (Voxel, [i32; 3]);
+
+/// Block of random voxels, mostly air, placed anywhere in the chunk so the
+/// borders are covered too.
+fn chunks() -> impl Strategy<Value = Chunk> {
+    let ids = prop::collection::vec(
+        prop_oneof![3 => Just(AIR), 2 => 1..4 as VoxelId],
+        BLOCK.pow(3),
+    );
+    let offset = 0..=SIZE - BLOCK;
+    (ids, offset.clone(), offset.clone(), offset).prop_map(|(ids, ox, oy, oz)| {
+        let mut chunk = Chunk::new();
+        for (i, &id) in ids.iter().enumerate() {
+            let (x, y, z) = (i % BLOCK, i / BLOCK % BLOCK, i / (BLOCK * BLOCK));
+            chunk.set_voxel(ox + x, oy + y, oz + z, id);
+        }
+        chunk
+    })
+}
+
+/// Voxel colors encode their id in the red channel.
+fn style(id: VoxelId) -> VoxelStyle {
+    VoxelStyle {
+        color: Color::new(id as f32, 0.0, 0.0, 1.0),
+        flags: 0,
+        texture: None,
+    }
+}
+
+fn mesh(chunk: &Chunk) -> MeshData {
+    let mut out = MeshData::new();
+    mesh_chunk(chunk, &mut MeshScratch::default(), style, &mut out);
+    out
+}
+
+/// Unit faces covered by the quads of `mesh`, with the id of their color
+/// and how many quads cover them.
+fn unit_faces(mesh: &MeshData) -> HashMap<UnitFace, (VoxelId, usize)> {
+    assert_eq!(mesh.vertex_count() % 4, 0);
+    assert_eq!(mesh.indices.len(), mesh.vertex_count() / 4 * 6);
+    let mut faces = HashMap::new();
+    for quad in 0..mesh.vertex_count() / 4 {
+        let corners = &mesh.positions[quad * 4..quad * 4 + 4];
+        let normal = mesh.normals[quad * 4];
+        let axis = normal.iter().position(|&n| n != 0.0).unwrap();
+        let sign = normal[axis] as i32;
+        let mut min = [std::f32::MAX; 3];
+        let mut max = [std::f32::MIN; 3];
+        for corner in corners {
+            for k in 0..3 {
+                min[k] = min[k].min(corner[k]);
+                max[k] = max[k].max(corner[k]);
+            }
+        }
+        assert_eq!(min[axis], max[axis], "quad isn't flat");
+        let plane = min[axis] as i32;
+        let along = if sign > 0 { plane - 1 } else { plane } as usize;
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for a in min[u] as usize..max[u] as usize {
+            for b in min[v] as usize..max[v] as usize {
+                let mut voxel = [0; 3];
+                voxel[axis] = along;
+                voxel[u] = a;
+                voxel[v] = b;
+                let mut direction = [0; 3];
+                direction[axis] = sign;
+                let id = mesh.colors[quad * 4][0] as VoxelId;
+                let entry = faces
+                    .entry(((voxel[0], voxel[1], voxel[2]), direction))
+                    .or_insert((id, 0));
+                entry.1 += 1;
+            }
+        }
+    }
+    faces
+}
+
+/// Faces of the solid voxels next to air or to the chunk border.
+fn exposed_faces(chunk: &Chunk) -> BTreeSet<UnitFace> {
+    let mut faces = BTreeSet::new();
+    for z in 0..SIZE {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                if chunk.get_voxel(x, y, z) == AIR {
+                    continue;
+                }
+                for axis in 0..3 {
+                    for &sign in &[1, -1] {
+                        let mut direction = [0; 3];
+                        direction[axis] = sign;
+                        let neighbour = [
+                            x as i32 + direction[0],
+                            y as i32 + direction[1],
+                            z as i32 + direction[2],
+                        ];
+                        let open = neighbour.iter().any(|&n| n < 0 || n >= SIZE as i32)
+                            || chunk.get_voxel(
+                                neighbour[0] as usize,
+                                neighbour[1] as usize,
+                                neighbour[2] as usize,
+                            ) == AIR;
+                        if open {
+                            faces.insert(((x, y, z), direction));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    faces
+}
+
+/// Quarter turn around Y.
+fn rotate(chunk: &Chunk) -> Chunk {
+    let mut rotated = Chunk::new();
+    for z in 0..SIZE {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (rx, ry, rz) = rotate_voxel((x, y, z));
+                rotated.set_voxel(rx, ry, rz, chunk.get_voxel(x, y, z));
+            }
+        }
+    }
+    rotated
+}
+
+fn rotate_voxel((x, y, z): Voxel) -> Voxel {
+    (SIZE - 1 - z, y, x)
+}
+
+fn rotate_direction([x, y, z]: [i32; 3]) -> [i32; 3] {
+    [-z, y, x]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn faces_are_exactly_the_exposed_surface(chunk in chunks()) {
+        let faces = unit_faces(&mesh(&chunk));
+        let expected = exposed_faces(&chunk);
+        for (face, &(_, count)) in &faces {
+            prop_assert_eq!(count, 1, "face {:?} covered more than once", face);
+            prop_assert!(expected.contains(face), "interior face {:?}", face);
+        }
+        prop_assert_eq!(faces.len(), expected.len());
+    }
+
+    #[test]
+    fn merged_faces_keep_their_voxel_type(chunk in chunks()) {
+        for (((x, y, z), _), &(id, _)) in &unit_faces(&mesh(&chunk)) {
+            prop_assert_eq!(id, chunk.get_voxel(*x, *y, *z));
+        }
+    }
+
+    #[test]
+    fn mesh_is_watertight(chunk in chunks()) {
+        // Closed surface: every unit edge borders an even number of faces
+        // and the area weighted normals cancel out.
+        let faces = unit_faces(&mesh(&chunk));
+        let mut edges: HashMap<([usize; 3], [usize; 3]), usize> = HashMap::new();
+        let mut normals = [0i64; 3];
+        for (voxel, direction) in faces.keys() {
+            let axis = direction.iter().position(|&d| d != 0).unwrap();
+            normals[axis] += direction[axis] as i64;
+            let mut origin = [voxel.0, voxel.1, voxel.2];
+            if direction[axis] > 0 {
+                origin[axis] += 1;
+            }
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let offset = |du: usize, dv: usize| {
+                let mut point = origin;
+                point[u] += du;
+                point[v] += dv;
+                point
+            };
+            let corners = [offset(0, 0), offset(1, 0), offset(1, 1), offset(0, 1)];
+            for k in 0..4 {
+                let (a, b) = (corners[k], corners[(k + 1) % 4]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        prop_assert_eq!(normals, [0; 3]);
+        for (edge, count) in edges {
+            prop_assert!(count % 2 == 0, "open edge {:?}", edge);
+        }
+    }
+
+    #[test]
+    fn mesh_follows_rotations(chunk in chunks()) {
+        let rotated: BTreeSet<_> = unit_faces(&mesh(&chunk))
+            .into_iter()
+            .map(|((voxel, direction), (id, _))| {
+                ((rotate_voxel(voxel), rotate_direction(direction)), id)
+            })
+            .collect();
+        let expected: BTreeSet<_> = unit_faces(&mesh(&rotate(&chunk)))
+            .into_iter()
+            .map(|(face, (id, _))| (face, id))
+            .collect();
+        prop_assert_eq!(rotated, expected);
+    }
+}
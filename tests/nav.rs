@@ -0,0 +1,117 @@
+//! Paths across small floors, around walls and up steps.
+
+use avenir::coords::WorldPos;
+use avenir::nav::{find_path, is_walkable, NavSettings};
+use avenir::world::World;
+
+/// Floor of `size` by `size` voxels at y = 0, stood on at y = 1.
+fn floor(size: i64) -> World {
+    let mut world = World::default();
+    for z in 0..size {
+        for x in 0..size {
+            world.set_voxel(&WorldPos::new(x, 0, z), 1);
+        }
+    }
+    world
+}
+
+/// Column of solid voxels standing on the floor.
+fn pillar(world: &mut World, x: i64, z: i64, height: i64) {
+    for y in 1..=height {
+        world.set_voxel(&WorldPos::new(x, y, z), 1);
+    }
+}
+
+fn at(x: i64, z: i64) -> WorldPos {
+    WorldPos::new(x, 1, z)
+}
+
+#[test]
+fn open_floor_is_walked_straight() {
+    let world = floor(8);
+    let settings = NavSettings::default();
+    let path = find_path(&world, &at(0, 0), &at(6, 3), &settings).unwrap();
+    assert_eq!(path.cost, 9.0);
+    assert_eq!(path.waypoints, vec![at(0, 0), at(6, 3)]);
+
+    let path = find_path(&world, &at(2, 2), &at(2, 2), &settings).unwrap();
+    assert_eq!(path.cost, 0.0);
+    assert_eq!(path.waypoints, vec![at(2, 2)]);
+}
+
+#[test]
+fn walls_are_walked_around() {
+    let mut world = floor(8);
+    // Too high to jump, with a gap at z = 7.
+    for z in 0..7 {
+        pillar(&mut world, 3, z, 2);
+    }
+    let settings = NavSettings::default();
+    let path = find_path(&world, &at(1, 0), &at(5, 0), &settings).unwrap();
+    assert_eq!(path.cost, 4.0 + 2.0 * 7.0);
+    // Smoothing can't cut through the wall.
+    assert!(path.waypoints.len() > 2);
+    for waypoint in &path.waypoints {
+        assert!(is_walkable(&world, waypoint, &settings));
+    }
+}
+
+#[test]
+fn steps_are_climbed_with_a_jump() {
+    let mut world = floor(8);
+    for z in 0..8 {
+        pillar(&mut world, 4, z, 1);
+    }
+    let settings = NavSettings::default();
+    let path = find_path(&world, &at(2, 3), &at(6, 3), &settings).unwrap();
+    // One jump up the step, the drop down is free.
+    assert_eq!(path.cost, 4.0 + settings.jump_cost);
+    assert!(path.waypoints.contains(&WorldPos::new(4, 2, 3)));
+
+    let walk_up = NavSettings {
+        step_height: 1,
+        ..settings
+    };
+    let path = find_path(&world, &at(2, 3), &at(6, 3), &walk_up).unwrap();
+    assert_eq!(path.cost, 4.0);
+}
+
+#[test]
+fn enclosed_goals_are_unreachable() {
+    let mut world = floor(8);
+    for &(x, z) in &[(4, 3), (4, 5), (3, 4), (5, 4)] {
+        pillar(&mut world, x, z, 2);
+    }
+    let settings = NavSettings::default();
+    assert_eq!(find_path(&world, &at(0, 0), &at(4, 4), &settings), None);
+
+    // Floors not connected at all.
+    world.set_voxel(&WorldPos::new(20, 0, 20), 1);
+    assert_eq!(find_path(&world, &at(0, 0), &at(20, 20), &settings), None);
+}
+
+#[test]
+fn blocked_ends_have_no_path() {
+    let mut world = floor(8);
+    // Inside a solid voxel, and under a ceiling too low to stand.
+    pillar(&mut world, 2, 2, 1);
+    world.set_voxel(&WorldPos::new(5, 2, 5), 1);
+    let settings = NavSettings::default();
+    assert!(!is_walkable(&world, &at(2, 2), &settings));
+    assert!(!is_walkable(&world, &at(5, 5), &settings));
+    assert_eq!(find_path(&world, &at(2, 2), &at(0, 0), &settings), None);
+    assert_eq!(find_path(&world, &at(0, 0), &at(5, 5), &settings), None);
+    // Nothing to stand on.
+    assert_eq!(find_path(&world, &at(0, 0), &at(0, 12), &settings), None);
+}
+
+#[test]
+fn search_gives_up_past_max_nodes() {
+    let world = floor(16);
+    let settings = NavSettings {
+        max_nodes: 4,
+        ..NavSettings::default()
+    };
+    assert!(find_path(&world, &at(0, 0), &at(2, 0), &settings).is_some());
+    assert_eq!(find_path(&world, &at(0, 0), &at(15, 15), &settings), None);
+}
@@ -0,0 +1,117 @@
+//! Checks of the wire format and its framing.
+
+use nalgebra::{UnitQuaternion, Vector3};
+
+use avenir::chunk::{Chunk, Side};
+use avenir::color::Color;
+use avenir::coords::{ChunkCoord, Location};
+use avenir::net::{self, ChunkDiff, EntityTransform, FrameDecoder, Message};
+
+fn sample_chunk() -> Chunk {
+    let mut chunk = Chunk::new();
+    for x in 0..32 {
+        for z in 0..32 {
+            chunk.set_voxel(x, 0, z, 1);
+        }
+    }
+    chunk.set_voxel(5, 6, 7, 3);
+    chunk.paint_face(5, 6, 7, Side::ALL[2], Color::rgb(1.0, 0.5, 0.0));
+    chunk.set_damage(4, 0, 4, 2);
+    chunk
+}
+
+#[test]
+fn chunks_survive_a_round_trip() {
+    let chunk = sample_chunk();
+    let coord = ChunkCoord::new(-3, 1, 1 << 40);
+    let frame = net::encode(&Message::Chunk {
+        coord,
+        chunk: chunk.clone(),
+    });
+    // Runs of voxels keep mostly uniform chunks far below their raw size.
+    assert!(frame.len() < 1024);
+    match net::decode(&frame[4..]).unwrap() {
+        Message::Chunk {
+            coord: decoded_coord,
+            chunk: decoded,
+        } => {
+            assert_eq!(decoded_coord, coord);
+            assert_eq!(decoded.voxels(), chunk.voxels());
+            assert!(decoded.painted_faces().eq(chunk.painted_faces()));
+            assert!(decoded.damaged_voxels().eq(chunk.damaged_voxels()));
+        }
+        message => panic!("decoded {:?}", message),
+    }
+}
+
+#[test]
+fn diffs_rebuild_the_new_chunk() {
+    let old = sample_chunk();
+    let mut new = old.clone();
+    new.set_voxel(0, 0, 0, 0);
+    new.set_voxel(31, 31, 31, 9);
+    let diff = ChunkDiff::between(ChunkCoord::new(0, 0, 0), &old, &new);
+    assert_eq!(diff.voxels.len(), 2);
+
+    let frame = net::encode(&Message::ChunkDiff(diff.clone()));
+    let decoded = match net::decode(&frame[4..]).unwrap() {
+        Message::ChunkDiff(decoded) => decoded,
+        message => panic!("decoded {:?}", message),
+    };
+    assert_eq!(decoded, diff);
+    let mut patched = old;
+    decoded.apply(&mut patched);
+    assert_eq!(patched.voxels(), new.voxels());
+}
+
+#[test]
+fn decoder_waits_for_whole_frames() {
+    let transform = EntityTransform {
+        entity: 42,
+        location: Location::new(ChunkCoord::new(1, 2, 3), Vector3::new(0.5, 1.5, 2.5)),
+        rotation: UnitQuaternion::from_euler_angles(0.1, 0.2, 0.3),
+        scale: Vector3::repeat(2.0),
+    };
+    let mut stream = Vec::new();
+    net::write_message(&mut stream, &Message::EntityTransform(transform)).unwrap();
+    net::write_message(&mut stream, &Message::ChunkDiff(ChunkDiff::default())).unwrap();
+
+    let mut decoder = FrameDecoder::new();
+    let mut messages = Vec::new();
+    for byte in &stream {
+        decoder.push(&[*byte]);
+        while let Some(message) = decoder.next_message().unwrap() {
+            messages.push(message);
+        }
+    }
+    assert_eq!(decoder.pending(), 0);
+    assert_eq!(messages.len(), 2);
+    match messages[0] {
+        Message::EntityTransform(decoded) => assert_eq!(decoded, transform),
+        ref message => panic!("decoded {:?}", message),
+    }
+
+    let mut reader = &stream[..];
+    assert!(matches!(
+        net::read_message(&mut reader).unwrap(),
+        Message::EntityTransform(_)
+    ));
+    assert!(matches!(
+        net::read_message(&mut reader).unwrap(),
+        Message::ChunkDiff(_)
+    ));
+}
+
+#[test]
+fn malformed_frames_are_rejected() {
+    let frame = net::encode(&Message::Chunk {
+        coord: ChunkCoord::new(0, 0, 0),
+        chunk: sample_chunk(),
+    });
+    assert!(net::decode(&frame[4..frame.len() - 1]).is_err());
+    assert!(net::decode(&[0xff]).is_err());
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&u32::MAX.to_le_bytes());
+    assert!(decoder.next_message().is_err());
+}
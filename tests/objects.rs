@@ -0,0 +1,176 @@
+//! Checks of the scene objects and of the instances uploaded for them.
+
+use avenir::color::Color;
+use avenir::coords::{ChunkCoord, FloatingOrigin, Location};
+use avenir::handle::HandleMap;
+use avenir::objects::{
+    check_instance_count, check_new_object, object_draws, ObjectError, ObjectUploadKey,
+    SceneObject, MAX_OBJECT_INSTANCES, MAX_SCENE_OBJECTS,
+};
+use avenir::transform::Transform;
+use nalgebra::{Matrix4, Vector3};
+
+fn at(x: f32) -> Location {
+    let mut location = Location::default();
+    location.offset = Vector3::new(x, 0.0, 0.0);
+    location
+}
+
+fn object_with(instances: usize) -> SceneObject {
+    let mut object = SceneObject::cube(1.0, Color::WHITE);
+    for i in 0..instances {
+        object
+            .add_instance(at(i as f32 * 4.0), Transform::identity())
+            .unwrap();
+    }
+    object
+}
+
+#[test]
+fn removal_keeps_other_handles() {
+    let mut objects = HandleMap::new();
+    let cube = objects.insert(object_with(1));
+    let sphere = objects.insert(SceneObject::sphere(0.5, Color::WHITE));
+    let other = objects.insert(object_with(3));
+
+    assert!(objects.remove(sphere).is_some());
+    assert!(objects.get(sphere).is_none());
+    assert_eq!(objects.get(cube).unwrap().instances().len(), 1);
+    assert_eq!(objects.get(other).unwrap().instances().len(), 3);
+
+    // The slot is reused, the removed handle still resolves to nothing.
+    let added = objects.insert(object_with(2));
+    assert_ne!(added, sphere);
+    assert!(objects.get(sphere).is_none());
+    assert_eq!(objects.get(added).unwrap().instances().len(), 2);
+    assert_eq!(objects.len(), 3);
+}
+
+#[test]
+fn uploaded_instances_match_the_objects() {
+    let mut objects = HandleMap::new();
+    let first = objects.insert(object_with(3));
+    let empty = objects.insert(object_with(0));
+    let tinted = objects.insert(object_with(2).with_tint(Color::rgb(1.0, 0.0, 0.0)));
+    let origin = FloatingOrigin::default();
+
+    let mut instances = Vec::new();
+    let draws = object_draws(&objects, &origin, |_| true, &mut instances);
+    let summary: Vec<_> = draws
+        .iter()
+        .map(|draw| (draw.object, draw.first_instance, draw.instance_count))
+        .collect();
+    assert_eq!(summary, vec![(first, 0, 3), (empty, 3, 0), (tinted, 3, 2)]);
+    assert_eq!(instances.len(), 5);
+    assert_eq!(instances[3].tint, [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(instances[1].model[3][0], 4.0);
+
+    objects.remove(first);
+    let draws = object_draws(&objects, &origin, |_| true, &mut instances);
+    assert_eq!(draws.len(), 2);
+    assert_eq!(instances.len(), 2);
+
+    // Hidden instances are skipped, the draw of their object is kept.
+    let draws = object_draws(&objects, &origin, |aabb| aabb.min.x < 2.0, &mut instances);
+    assert_eq!((draws[1].instance_count, draws[1].culled), (1, 1));
+    assert_eq!(instances.len(), 1);
+}
+
+#[test]
+fn generated_meshes_have_the_requested_size() {
+    let cube = SceneObject::cube(2.0, Color::WHITE);
+    assert_eq!(cube.mesh().indices.len(), 36);
+    assert_eq!(cube.bounds().max.x, 1.0);
+    assert_eq!(cube.bounds().min.y, -1.0);
+
+    let sphere = SceneObject::sphere(0.5, Color::WHITE);
+    assert!((sphere.bounds().max.y - 0.5).abs() < 1e-4);
+    assert_eq!(sphere.mesh().indices.len() % 3, 0);
+}
+
+#[test]
+fn upload_key_changes_with_the_objects_and_the_view() {
+    let mut objects = HandleMap::new();
+    let cube = objects.insert(object_with(2));
+    let origin = FloatingOrigin::default();
+    let view_proj = Matrix4::identity();
+    let key = |objects: &HandleMap<SceneObject>, origin: &FloatingOrigin, view_proj| {
+        ObjectUploadKey::new(objects, origin, &view_proj)
+    };
+    let uploaded = key(&objects, &origin, view_proj);
+    assert_eq!(key(&objects, &origin, view_proj), uploaded);
+
+    // Reading the objects leaves them clean.
+    let _ = objects.get_mut(cube).unwrap().instances().len();
+    assert_eq!(key(&objects, &origin, view_proj), uploaded);
+
+    let object = objects.get_mut(cube).unwrap();
+    object
+        .set_instance(1, at(8.0), Transform::identity())
+        .unwrap();
+    let moved = key(&objects, &origin, view_proj);
+    assert_ne!(moved, uploaded);
+
+    let object = objects.get_mut(cube).unwrap();
+    object.set_tint(Some(Color::rgb(0.0, 1.0, 0.0)));
+    let tinted = key(&objects, &origin, view_proj);
+    assert_ne!(tinted, moved);
+
+    let turned = Matrix4::new_scaling(2.0);
+    assert_ne!(key(&objects, &origin, turned), tinted);
+    let mut rebased = origin;
+    assert!(rebased
+        .update(&Location::new(ChunkCoord::new(10, 0, 0), Vector3::zeros()))
+        .is_some());
+    assert_ne!(key(&objects, &rebased, view_proj), tinted);
+
+    // A new object in the slot of a removed one is still a change.
+    objects.remove(cube);
+    objects.insert(object_with(2));
+    assert_ne!(key(&objects, &origin, view_proj), tinted);
+}
+
+#[test]
+fn objects_past_the_buffers_are_refused() {
+    let mut object = object_with(MAX_OBJECT_INSTANCES);
+    assert_eq!(
+        object.add_instance(at(0.0), Transform::identity()),
+        Err(ObjectError::TooManyInstances(MAX_OBJECT_INSTANCES + 1))
+    );
+    assert_eq!(object.instances().len(), MAX_OBJECT_INSTANCES);
+    assert_eq!(
+        object.set_instance(MAX_OBJECT_INSTANCES, at(0.0), Transform::identity()),
+        Err(ObjectError::NotFound)
+    );
+
+    // The limit holds for the instances of all the objects.
+    let mut objects = HandleMap::new();
+    let first = objects.insert(object_with(MAX_OBJECT_INSTANCES - 4));
+    let second = object_with(5);
+    assert_eq!(
+        check_new_object(&objects, &second),
+        Err(ObjectError::TooManyInstances(MAX_OBJECT_INSTANCES + 1))
+    );
+    let second = objects.insert(object_with(4));
+    assert_eq!(check_instance_count(&objects, second, 4), Ok(()));
+    assert_eq!(
+        check_instance_count(&objects, second, 5),
+        Err(ObjectError::TooManyInstances(MAX_OBJECT_INSTANCES + 1))
+    );
+    assert_eq!(check_instance_count(&objects, first, 0), Ok(()));
+    objects.remove(second);
+    assert_eq!(
+        check_instance_count(&objects, second, 1),
+        Err(ObjectError::NotFound)
+    );
+
+    let mut objects = HandleMap::new();
+    for _ in 0..MAX_SCENE_OBJECTS {
+        check_new_object(&objects, &object_with(0)).unwrap();
+        objects.insert(object_with(0));
+    }
+    assert_eq!(
+        check_new_object(&objects, &object_with(0)),
+        Err(ObjectError::TooManyObjects)
+    );
+}
@@ -0,0 +1,64 @@
+//! Exports of the frames recorded by the profiler.
+
+use std::path::Path;
+use std::time::Duration;
+
+use avenir::profiler::{Format, Profiler};
+
+/// Two frames, the section timed in the first only.
+fn recorded() -> Profiler {
+    let profiler = Profiler::new();
+    profiler.record("before", Duration::from_millis(3));
+    profiler.end_frame(Duration::from_millis(20));
+    profiler.start_recording();
+
+    profiler.record("mesh.culling", Duration::from_micros(500));
+    profiler.record("mesh.culling", Duration::from_micros(250));
+    profiler.set_counter("chunks.visible", 12);
+    profiler.end_frame(Duration::from_millis(16));
+    profiler.set_counter("chunks.visible", 9);
+    profiler.end_frame(Duration::from_millis(17));
+    profiler
+}
+
+fn written(profiler: &Profiler, format: Format) -> String {
+    let mut out = Vec::new();
+    profiler.write(&mut out, format).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn csv_has_a_row_per_frame() {
+    let profiler = recorded();
+    assert_eq!(profiler.recorded_frames(), 2);
+    assert_eq!(
+        written(&profiler, Format::Csv),
+        "frame,frame_ms,mesh.culling_ms,chunks.visible\n\
+         0,16.000,0.750,12\n\
+         1,17.000,,9\n"
+    );
+}
+
+#[test]
+fn json_has_an_object_per_frame() {
+    assert_eq!(
+        written(&recorded(), Format::Json),
+        "[\n  \
+         {\"frame\": 0, \"frame_ms\": 16.000, \"timings_ms\": {\"mesh.culling\": 0.750}, \
+         \"counters\": {\"chunks.visible\": 12}},\n  \
+         {\"frame\": 1, \"frame_ms\": 17.000, \"timings_ms\": {}, \
+         \"counters\": {\"chunks.visible\": 9}}\n\
+         ]\n"
+    );
+}
+
+#[test]
+fn stopping_drops_the_frames() {
+    let profiler = recorded();
+    assert_eq!(profiler.stop_recording().len(), 2);
+    assert!(!profiler.is_recording());
+    profiler.end_frame(Duration::from_millis(16));
+    assert_eq!(written(&profiler, Format::Csv), "frame,frame_ms\n");
+    assert_eq!(Format::from_path(Path::new("run.JSON")), Format::Json);
+    assert_eq!(Format::from_path(Path::new("run")), Format::Csv);
+}
@@ -0,0 +1,160 @@
+//! Edge cases of the voxel raycaster.
+
+use nalgebra::Vector3;
+
+use avenir::coords::{ChunkCoord, Location, WorldPos};
+use avenir::raycast::Ray;
+use avenir::world::World;
+
+fn at(x: f32, y: f32, z: f32) -> Location {
+    Location::new(ChunkCoord::new(0, 0, 0), Vector3::new(x, y, z))
+}
+
+fn world_with(solid: &[(i64, i64, i64)]) -> World {
+    let mut world = World::default();
+    for &(x, y, z) in solid {
+        world.set_voxel(&WorldPos::new(x, y, z), 1);
+    }
+    world
+}
+
+#[test]
+fn ray_starting_inside_a_solid_hits_it() {
+    let world = world_with(&[(2, 2, 2)]);
+    let hit = world
+        .raycast(&at(2.5, 2.5, 2.5), &Vector3::new(1.0, 0.0, 0.0), 10.0)
+        .unwrap();
+    assert_eq!(hit.pos, WorldPos::new(2, 2, 2));
+    assert_eq!(hit.distance, 0.0);
+    assert_eq!(hit.normal, Vector3::zeros());
+}
+
+#[test]
+fn ray_crosses_into_negative_coordinates() {
+    let world = world_with(&[(-3, 0, 0)]);
+    let hit = world
+        .raycast(&at(0.5, 0.5, 0.5), &Vector3::new(-1.0, 0.0, 0.0), 10.0)
+        .unwrap();
+    assert_eq!(hit.pos, WorldPos::new(-3, 0, 0));
+    assert_eq!(hit.normal, Vector3::new(1, 0, 0));
+    assert!((hit.distance - 2.5).abs() < 1e-5);
+    assert_eq!(hit.adjacent(), Some(WorldPos::new(-2, 0, 0)));
+}
+
+#[test]
+fn ray_starting_on_a_face_hits_the_voxel_behind_it() {
+    let world = world_with(&[(4, 0, 0)]);
+    let hit = world
+        .raycast(&at(5.0, 0.5, 0.5), &Vector3::new(-1.0, 0.0, 0.0), 10.0)
+        .unwrap();
+    assert_eq!(hit.pos, WorldPos::new(4, 0, 0));
+    assert_eq!(hit.distance, 0.0);
+    assert_eq!(hit.normal, Vector3::new(1, 0, 0));
+}
+
+#[test]
+fn ray_along_a_boundary_tests_the_positive_side() {
+    let direction = Vector3::new(1.0, 0.0, 0.0);
+    let above = world_with(&[(3, 5, 0)]);
+    assert!(above
+        .raycast(&at(0.5, 5.0, 0.5), &direction, 10.0)
+        .is_some());
+    let below = world_with(&[(3, 4, 0)]);
+    assert!(below
+        .raycast(&at(0.5, 5.0, 0.5), &direction, 10.0)
+        .is_none());
+}
+
+#[test]
+fn invalid_rays_hit_nothing() {
+    let world = world_with(&[(1, 0, 0)]);
+    let origin = at(0.5, 0.5, 0.5);
+    let nan = Vector3::new(std::f32::NAN, 0.0, 0.0);
+    assert!(world.raycast(&origin, &nan, 10.0).is_none());
+    let infinite = Vector3::new(std::f32::INFINITY, 0.0, 0.0);
+    assert!(world.raycast(&origin, &infinite, 10.0).is_none());
+    let x = Vector3::new(1.0, 0.0, 0.0);
+    assert!(world.raycast(&origin, &x, std::f32::NAN).is_none());
+    assert!(world.raycast(&origin, &x, -1.0).is_none());
+}
+
+#[test]
+fn invalid_spherecasts_hit_nothing() {
+    let world = world_with(&[(2, 0, 0)]);
+    let origin = at(0.5, 0.5, 0.5);
+    let x = Vector3::new(1.0, 0.0, 0.0);
+    assert!(world.spherecast(&origin, &x, 0.25, 10.0).is_some());
+
+    let nan = std::f32::NAN;
+    let infinity = std::f32::INFINITY;
+    assert!(world.spherecast(&origin, &x, nan, 10.0).is_none());
+    assert!(world.spherecast(&origin, &x, infinity, 10.0).is_none());
+    assert!(world.spherecast(&origin, &x, -0.25, 10.0).is_none());
+    assert!(world.spherecast(&origin, &x, 0.25, nan).is_none());
+    assert!(world.spherecast(&origin, &x, 0.25, infinity).is_none());
+    assert!(world.spherecast(&origin, &x, 0.25, -1.0).is_none());
+    let zero = Vector3::zeros();
+    assert!(world.spherecast(&origin, &zero, 0.25, 10.0).is_none());
+    let nan_direction = Vector3::new(nan, 0.0, 0.0);
+    assert!(world
+        .spherecast(&origin, &nan_direction, 0.25, 10.0)
+        .is_none());
+}
+
+#[test]
+fn unbounded_ray_in_an_empty_world_ends() {
+    let world = World::default();
+    let direction = Vector3::new(0.3, -0.2, 1.0);
+    assert!(world
+        .raycast(&at(0.5, 0.5, 0.5), &direction, std::f32::INFINITY)
+        .is_none());
+}
+
+#[test]
+fn unnormalized_origin_hits_like_a_normalized_one() {
+    let world = world_with(&[(70, 1, 1)]);
+    let far = Location {
+        chunk: ChunkCoord::new(0, 0, 0),
+        offset: Vector3::new(64.5, 1.5, 1.5),
+    };
+    let hit = world
+        .raycast(&far, &Vector3::new(1.0, 0.0, 0.0), 10.0)
+        .unwrap();
+    assert_eq!(hit.pos, WorldPos::new(70, 1, 1));
+    assert!((hit.distance - 5.5).abs() < 1e-5);
+}
+
+#[test]
+fn batch_matches_single_raycasts() {
+    let world = world_with(&[(40, 3, 3), (-20, 3, -20), (3, -5, 3)]);
+    let origin = at(3.5, 3.5, 3.5);
+    let rays: Vec<_> = (0..64)
+        .map(|i| {
+            let angle = i as f32 / 64.0 * std::f32::consts::PI * 2.0;
+            let direction = Vector3::new(angle.cos(), (angle * 3.0).sin() * 0.5, angle.sin());
+            Ray::new(origin, direction, 50.0)
+        })
+        .chain(vec![Ray::new(origin, Vector3::new(1.0, 0.0, 0.0), 50.0)])
+        .collect();
+    let hits = world.raycast_batch(&rays);
+    assert_eq!(hits.len(), rays.len());
+    for (ray, hit) in rays.iter().zip(&hits) {
+        assert_eq!(
+            *hit,
+            world.raycast(&ray.origin, &ray.direction, ray.max_distance)
+        );
+    }
+    assert_eq!(hits.last().unwrap().unwrap().pos, WorldPos::new(40, 3, 3));
+}
+
+#[test]
+fn line_of_sight_between_locations() {
+    let world = world_with(&[(5, 0, 0)]);
+    let (eye, target) = (at(0.5, 0.5, 0.5), at(9.5, 0.5, 0.5));
+    let hits = world.raycast_batch(&[
+        Ray::between(&eye, &target),
+        Ray::between(&eye, &at(4.5, 0.5, 0.5)),
+    ]);
+    assert_eq!(hits[0].unwrap().pos, WorldPos::new(5, 0, 0));
+    assert!(hits[1].is_none());
+}
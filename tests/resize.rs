@@ -0,0 +1,77 @@
+//! Graph rebuilds on window resizes and the extents of the rebuilt targets.
+
+use rendy::hal::{image::Kind, window::Extent2D};
+
+use avenir::config::{QualityPreset, QualitySettings};
+use avenir::renderer::{resize_action, ResizeAction};
+
+fn quality(resolution_scale: f32) -> QualitySettings {
+    QualitySettings {
+        resolution_scale,
+        ..QualityPreset::High.settings().unwrap()
+    }
+}
+
+#[test]
+fn resizes_rebuild_at_new_sizes_only() {
+    let built = Some((800, 600));
+    // Winit sends the size of the window once created.
+    assert_eq!(
+        resize_action((800, 600), built, false),
+        ResizeAction::UpdateAspect
+    );
+    // Dragging the window corner.
+    assert_eq!(
+        resize_action((1024, 700), built, false),
+        ResizeAction::Rebuild
+    );
+    assert_eq!(
+        resize_action((800, 601), built, false),
+        ResizeAction::Rebuild
+    );
+}
+
+#[test]
+fn nothing_is_rebuilt_without_a_surface_to_draw_to() {
+    let built = Some((800, 600));
+    // Minimized.
+    assert_eq!(resize_action((0, 0), built, false), ResizeAction::Ignore);
+    assert_eq!(resize_action((800, 0), built, false), ResizeAction::Ignore);
+    // Suspended, `resume` builds the graph at the size of the window.
+    assert_eq!(resize_action((1024, 700), None, true), ResizeAction::Ignore);
+    // A graph which failed to build is built again.
+    assert_eq!(
+        resize_action((1024, 700), None, false),
+        ResizeAction::Rebuild
+    );
+}
+
+#[test]
+fn targets_follow_the_window_size() {
+    let quality = quality(1.0);
+    let extents = quality.target_extents(1024, 700);
+    assert_eq!(extents.render, Kind::D2(1024, 700, 1, 1));
+    assert_eq!(
+        extents.surface,
+        Extent2D {
+            width: 1024,
+            height: 700
+        }
+    );
+    assert!(!extents.is_scaled());
+    assert_ne!(quality.target_extents(800, 600), extents);
+}
+
+#[test]
+fn scaled_targets_are_stretched_to_the_window() {
+    let quality = quality(0.5);
+    let extents = quality.target_extents(1024, 700);
+    assert_eq!(extents.render, Kind::D2(512, 350, 1, 1));
+    assert_eq!(extents.surface.width, 1024);
+    assert!(extents.is_scaled());
+    // Never empty, even for a window a pixel high.
+    assert_eq!(
+        quality.target_extents(1024, 1).render,
+        Kind::D2(512, 1, 1, 1)
+    );
+}
@@ -0,0 +1,92 @@
+//! Checks of the rewind buffer against the edits of a tick loop.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use avenir::camera::Camera;
+use avenir::coords::{ChunkCoord, FloatingOrigin, WorldPos};
+use avenir::rewind::{CameraSnapshot, RewindBuffer};
+use avenir::timestep::{TickLoop, WorldEdit};
+use avenir::world::World;
+use nalgebra::{Point3, Vector3};
+
+/// Loop setting voxel `tick` along X to 1 on each tick.
+fn line_loop() -> TickLoop {
+    let mut ticks = TickLoop::new(0.1).with_rewind(1.0);
+    let next = Rc::new(Cell::new(0));
+    ticks.on_tick(move |context| {
+        context
+            .commands
+            .set_voxel(WorldPos::new(next.get(), 0, 0), 1);
+        next.set(next.get() + 1);
+    });
+    ticks
+}
+
+#[test]
+fn rewind_puts_the_voxels_back() {
+    let mut ticks = line_loop();
+    let mut world = World::default();
+    ticks.advance(0.45, &mut world);
+    assert_eq!(world.get_voxel(&WorldPos::new(3, 0, 0)), 1);
+
+    let rewind = ticks.rewind.as_mut().unwrap();
+    let rewound = rewind.rewind_seconds(0.2).unwrap();
+    assert_eq!(rewound.tick, 2);
+    assert_eq!(rewound.restore(&mut world), vec![ChunkCoord::new(0, 0, 0)]);
+    let line: Vec<_> = (0..4)
+        .map(|x| world.get_voxel(&WorldPos::new(x, 0, 0)))
+        .collect();
+    assert_eq!(line, vec![1, 1, 0, 0]);
+
+    // The chunk didn't exist before the first tick.
+    let rewound = rewind.rewind(10).unwrap();
+    assert_eq!(rewound.tick, 0);
+    rewound.restore(&mut world);
+    assert!(world.chunk(&ChunkCoord::new(0, 0, 0)).is_none());
+    assert!(rewind.rewind(1).is_none());
+}
+
+#[test]
+fn oldest_ticks_are_dropped() {
+    let mut rewind = RewindBuffer::new(0.5, 0.1);
+    let world = World::default();
+    let edit = WorldEdit::SetVoxel {
+        pos: WorldPos::new(0, 0, 0),
+        id: 1,
+    };
+    for tick in 0..8 {
+        rewind.record_edits(tick, &world, &[edit.clone(), edit.clone()]);
+    }
+    assert_eq!(rewind.len(), 5);
+    assert_eq!(rewind.rewind(10).unwrap().tick, 3);
+    assert!(rewind.is_empty());
+}
+
+#[test]
+fn camera_returns_to_the_recorded_state() {
+    let mut camera = Camera::look_at(1.0, Point3::new(1.0, 2.0, 3.0), Point3::origin(), 1.5);
+    let mut origin = FloatingOrigin::new(64.0);
+    let mut rewind = RewindBuffer::new(1.0, 0.1);
+    let snapshot = CameraSnapshot::of(&camera, &origin);
+    rewind.record_camera(0, snapshot);
+
+    camera.view.translation.vector += Vector3::new(100.0, 0.0, 0.0);
+    camera.set_fov(1.0).unwrap();
+    if let Some(shift) = origin.update(&CameraSnapshot::of(&camera, &origin).location) {
+        camera.view.translation.vector += shift;
+    }
+    rewind.record_camera(1, CameraSnapshot::of(&camera, &origin));
+
+    let rewound = rewind.rewind(2).unwrap();
+    rewound
+        .camera
+        .unwrap()
+        .restore(&mut camera, &origin)
+        .unwrap();
+    let restored = CameraSnapshot::of(&camera, &origin);
+    let moved = restored.location.relative_to(&snapshot.location.chunk) - snapshot.location.offset;
+    assert!(moved.norm() < 1e-4, "{:?}", moved);
+    assert_eq!(restored.rotation, snapshot.rotation);
+    assert_eq!(restored.fov, snapshot.fov);
+}
@@ -0,0 +1,251 @@
+//! Invariants of the distance field and of the smooth meshers on random
+//! spheres.
+//!
+//! Spheres are carved into an empty `SdfChunk` away from its borders, so
+//! every mesher must give a closed surface around the expected volume.
+
+use std::collections::HashMap;
+
+use nalgebra::Vector3;
+use proptest::prelude::*;
+
+use avenir::color::Color;
+use avenir::coords::{ChunkCoord, Location, CHUNK_SIZE};
+use avenir::dual_contouring::dual_contouring;
+use avenir::meshing::{MeshData, VoxelStyle};
+use avenir::sdf::{Brush, CsgEdit, SdfChunk, MAX_DISTANCE};
+use avenir::smooth::{marching_cubes, surface_nets, DensityGrid};
+
+const SIZE: f32 = CHUNK_SIZE as f32;
+
+fn style(_: usize, _: usize, _: usize) -> VoxelStyle {
+    VoxelStyle {
+        color: Color::WHITE,
+        flags: 0,
+        texture: None,
+    }
+}
+
+fn at(center: Vector3<f32>) -> Location {
+    Location::new(ChunkCoord::new(0, 0, 0), center)
+}
+
+/// Chunk at the origin holding a sphere.
+fn sphere_chunk(center: Vector3<f32>, radius: f32) -> SdfChunk {
+    let mut chunk = SdfChunk::new();
+    let brush = Brush::Sphere {
+        center: at(center),
+        radius,
+    };
+    assert!(chunk.apply(&ChunkCoord::new(0, 0, 0), &CsgEdit::union(brush)));
+    chunk
+}
+
+/// Spheres at least `MAX_DISTANCE` from the chunk borders.
+fn spheres() -> impl Strategy<Value = (Vector3<f32>, f32)> {
+    (3.0f32..8.0).prop_flat_map(|radius| {
+        let margin = radius + MAX_DISTANCE;
+        let center = margin..SIZE - margin;
+        (
+            (center.clone(), center.clone(), center).prop_map(|(x, y, z)| Vector3::new(x, y, z)),
+            Just(radius),
+        )
+    })
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Mesher {
+    SurfaceNets,
+    MarchingCubes,
+    DualContouring,
+}
+
+const MESHERS: [Mesher; 3] = [
+    Mesher::SurfaceNets,
+    Mesher::MarchingCubes,
+    Mesher::DualContouring,
+];
+
+fn mesh(mesher: Mesher, grid: &DensityGrid, center: Vector3<f32>) -> MeshData {
+    let mut out = MeshData::new();
+    match mesher {
+        Mesher::SurfaceNets => surface_nets(grid, style, &mut out),
+        Mesher::MarchingCubes => marching_cubes(grid, style, &mut out),
+        Mesher::DualContouring => {
+            // Exact normals of the sphere.
+            let normal = |p: [f32; 3]| {
+                let n = (Vector3::from(p) - center).normalize();
+                [n.x, n.y, n.z]
+            };
+            dual_contouring(grid, normal, style, &mut out)
+        }
+    }
+    out
+}
+
+fn triangles(mesh: &MeshData) -> impl Iterator<Item = [u32; 3]> + '_ {
+    mesh.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+}
+
+/// Volume enclosed by the triangles, positive when they wind counter
+/// clockwise seen from outside.
+fn volume(mesh: &MeshData) -> f32 {
+    triangles(mesh)
+        .map(|[a, b, c]| {
+            let position = |i: u32| Vector3::from(mesh.positions[i as usize]);
+            position(a).dot(&position(b).cross(&position(c))) / 6.0
+        })
+        .sum()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn vertices_lie_on_the_surface((center, radius) in spheres()) {
+        let grid = sphere_chunk(center, radius).density_grid();
+        for &mesher in &MESHERS {
+            let mesh = mesh(mesher, &grid, center);
+            prop_assert!(mesh.vertex_count() > 0);
+            for (position, normal) in mesh.positions.iter().zip(&mesh.normals) {
+                let offset = Vector3::from(*position) - center;
+                // Within a cell of the surface, the samples are linear
+                // across each cell only approximately.
+                prop_assert!(
+                    (offset.norm() - radius).abs() < 0.5,
+                    "{:?} vertex {:?} off the sphere", mesher, position
+                );
+                let normal = Vector3::from(*normal);
+                prop_assert!((normal.norm() - 1.0).abs() < 1e-3);
+                prop_assert!(normal.dot(&offset) > 0.0, "{:?} normal points in", mesher);
+            }
+        }
+    }
+
+    #[test]
+    fn meshes_are_closed((center, radius) in spheres()) {
+        let grid = sphere_chunk(center, radius).density_grid();
+        for &mesher in &MESHERS {
+            // Each directed edge is matched by the opposite one of the
+            // triangle next to it. Marching cubes doesn't share vertices
+            // between cells, they are matched by position.
+            let mesh = mesh(mesher, &grid, center);
+            let key = |i: u32| {
+                let p = mesh.positions[i as usize];
+                [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]
+            };
+            let mut edges: HashMap<([u32; 3], [u32; 3]), i32> = HashMap::new();
+            for [a, b, c] in triangles(&mesh) {
+                let (a, b, c) = (key(a), key(b), key(c));
+                for &(from, to) in &[(a, b), (b, c), (c, a)] {
+                    *edges.entry((from.min(to), from.max(to))).or_insert(0) +=
+                        if from < to { 1 } else { -1 };
+                }
+            }
+            for (edge, &balance) in &edges {
+                prop_assert_eq!(balance, 0, "{:?} open at {:?}", mesher, edge);
+            }
+        }
+    }
+
+    #[test]
+    fn meshes_enclose_the_sphere((center, radius) in spheres()) {
+        let grid = sphere_chunk(center, radius).density_grid();
+        let expected = 4.0 / 3.0 * std::f32::consts::PI * radius.powi(3);
+        for &mesher in &MESHERS {
+            let volume = volume(&mesh(mesher, &grid, center));
+            prop_assert!(
+                (volume - expected).abs() < expected * 0.15,
+                "{:?} encloses {} instead of {}", mesher, volume, expected
+            );
+        }
+    }
+
+    #[test]
+    fn subtracting_the_union_leaves_the_chunk_empty((center, radius) in spheres()) {
+        let coord = ChunkCoord::new(0, 0, 0);
+        let brush = Brush::Sphere {
+            center: at(center),
+            radius,
+        };
+        let mut chunk = sphere_chunk(center, radius);
+        prop_assert!(!chunk.is_empty());
+        prop_assert!(!chunk.apply(&coord, &CsgEdit::union(brush)));
+        prop_assert!(chunk.apply(&coord, &CsgEdit::subtract(brush)));
+        prop_assert!(chunk.is_empty());
+    }
+}
+
+#[test]
+fn distances_are_clamped() {
+    let mut chunk = SdfChunk::filled(-100.0);
+    assert_eq!(chunk.get(3, 4, 5), -MAX_DISTANCE);
+    chunk.set(3, 4, 5, 100.0);
+    assert_eq!(chunk.get(3, 4, 5), MAX_DISTANCE);
+    assert_eq!(SdfChunk::new(), SdfChunk::filled(MAX_DISTANCE));
+    assert!(SdfChunk::new().is_empty());
+}
+
+#[test]
+fn brushes_only_change_the_chunks_they_list() {
+    let brush = Brush::Sphere {
+        center: Location::new(ChunkCoord::new(0, 0, 0), Vector3::new(30.0, 2.0, 16.0)),
+        radius: 5.0,
+    };
+    let listed: Vec<_> = brush.chunks().collect();
+    for z in -2..=2 {
+        for y in -2..=2 {
+            for x in -2..=2 {
+                let coord = ChunkCoord::new(x, y, z);
+                let changed = SdfChunk::new().apply(&coord, &CsgEdit::union(brush));
+                if changed {
+                    assert!(listed.contains(&coord), "{:?} not listed", coord);
+                }
+            }
+        }
+    }
+    // The neighbours sharing the corners of the faces are listed.
+    assert!(listed.contains(&ChunkCoord::new(1, 0, 0)));
+    assert!(listed.contains(&ChunkCoord::new(0, -1, 0)));
+}
+
+#[test]
+fn dual_contouring_keeps_box_corners() {
+    let mut chunk = SdfChunk::new();
+    let half_extents = Vector3::new(4.3, 4.3, 4.3);
+    let center = Vector3::new(16.0, 16.0, 16.0);
+    let brush = Brush::Box {
+        center: at(center),
+        half_extents,
+    };
+    chunk.apply(&ChunkCoord::new(0, 0, 0), &CsgEdit::union(brush));
+    let grid = chunk.density_grid();
+
+    // Exact normals of the box, along the axis of the nearest face.
+    let normal = |p: [f32; 3]| {
+        let q = (Vector3::from(p) - center).abs() - half_extents;
+        let axis = q.imax();
+        let mut n = [0.0; 3];
+        n[axis] = (p[axis] - center[axis]).signum();
+        n
+    };
+    let mut sharp = MeshData::new();
+    dual_contouring(&grid, normal, style, &mut sharp);
+    let mut smooth = MeshData::new();
+    surface_nets(&grid, style, &mut smooth);
+
+    // Distance from the nearest vertex to a corner of the box.
+    let corner = center + half_extents;
+    let nearest = |mesh: &MeshData| {
+        mesh.positions
+            .iter()
+            .map(|p| (Vector3::from(*p) - corner).norm())
+            .fold(std::f32::MAX, f32::min)
+    };
+    assert!(
+        nearest(&sharp) < 0.1,
+        "corner rounded to {}",
+        nearest(&sharp)
+    );
+    assert!(nearest(&smooth) > nearest(&sharp));
+}
@@ -0,0 +1,100 @@
+//! Region files written with the bundled dictionary and by queued saves.
+
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+
+use avenir::chunk::Chunk;
+use avenir::coords::ChunkCoord;
+use avenir::jobs::{JobCategory, JobConfig, JobSystem};
+use avenir::storage::{
+    read_region, write_region, ChunkCodec, RegionCoord, RegionSaver, DEFAULT_LEVEL,
+};
+
+/// Empty directory of the test.
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("avenir-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Chunk filled up to `height` with `id`.
+fn layered(height: usize, id: u16) -> Chunk {
+    let mut chunk = Chunk::new();
+    for z in 0..32 {
+        for y in 0..height {
+            for x in 0..32 {
+                chunk.set_voxel(x, y, z, id);
+            }
+        }
+    }
+    chunk
+}
+
+#[test]
+fn default_codec_round_trips_and_records_its_dictionary() {
+    let dir = directory("storage-dictionary");
+    let path = dir.join("region.avr");
+    let codec = ChunkCodec::default();
+    assert_ne!(codec.dictionary_id(), 0);
+
+    let chunks = vec![(ChunkCoord::new(0, 1, 2), layered(7, 3))];
+    write_region(&path, &codec, &chunks).unwrap();
+    let loaded = read_region(&path, &codec).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].0, chunks[0].0);
+    assert_eq!(loaded[0].1.voxels(), chunks[0].1.voxels());
+
+    let err = read_region(&path, &ChunkCodec::new(DEFAULT_LEVEL)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn one_encoder_and_decoder_handle_a_whole_region() {
+    let codec = ChunkCodec::default();
+    let chunks: Vec<_> = (0..4).map(|height| layered(height * 5, 2)).collect();
+    let mut encoder = codec.encoder();
+    let blobs: Vec<_> = chunks
+        .iter()
+        .map(|chunk| encoder.encode(chunk).unwrap())
+        .collect();
+
+    let mut decoder = codec.decoder();
+    for (chunk, blob) in chunks.iter().zip(&blobs) {
+        assert_eq!(blob, &codec.encode(chunk).unwrap());
+        assert_eq!(decoder.decode(blob).unwrap().voxels(), chunk.voxels());
+    }
+    let err = decoder.decode(&blobs[0][..blobs[0].len() / 2]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    // The failure leaves the decoder usable.
+    assert_eq!(
+        decoder.decode(&blobs[1]).unwrap().voxels(),
+        chunks[1].voxels()
+    );
+}
+
+#[test]
+fn saves_of_a_region_write_the_newest_chunks() {
+    let dir = directory("storage-saves");
+    let jobs = Arc::new(JobSystem::new(&JobConfig::default().with_threads(1)));
+    let saver = RegionSaver::new(&dir, ChunkCodec::default(), jobs.clone());
+
+    // Hold the only worker so both saves are queued before either is written.
+    let (release, gate) = mpsc::channel::<()>();
+    jobs.spawn(JobCategory::Io, move || gate.recv().unwrap());
+    let coord = ChunkCoord::new(1, 0, 0);
+    saver.save(vec![(coord, layered(4, 1))]);
+    saver.save(vec![(coord, layered(9, 2))]);
+    release.send(()).unwrap();
+    assert!(saver.flush().is_empty());
+
+    let loaded = saver.load(RegionCoord::of(&coord)).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].1.voxels(), layered(9, 2).voxels());
+
+    // Only the region file is left, no temporary one.
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
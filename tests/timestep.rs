@@ -0,0 +1,50 @@
+//! Checks of the tick loop and its deferred world edits.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use avenir::coords::{ChunkCoord, WorldPos};
+use avenir::timestep::TickLoop;
+use avenir::world::World;
+
+#[test]
+fn callbacks_run_once_per_tick_in_order() {
+    let mut ticks = TickLoop::new(0.1);
+    let log = Rc::new(RefCell::new(Vec::new()));
+    for name in &["first", "second"] {
+        let log = log.clone();
+        ticks.on_tick(move |context| log.borrow_mut().push((context.tick, *name)));
+    }
+    let mut world = World::default();
+    ticks.advance(0.25, &mut world);
+    ticks.advance(0.1, &mut world);
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            (0, "first"),
+            (0, "second"),
+            (1, "first"),
+            (1, "second"),
+            (2, "first"),
+            (2, "second"),
+        ]
+    );
+}
+
+#[test]
+fn edits_apply_after_every_callback() {
+    let mut ticks = TickLoop::new(0.1);
+    let pos = WorldPos::new(1, 2, 3);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    ticks.on_tick(move |context| context.commands.set_voxel(pos, 4));
+    {
+        let seen = seen.clone();
+        ticks.on_tick(move |context| seen.borrow_mut().push(context.world.get_voxel(&pos)));
+    }
+    let mut world = World::default();
+    let changed = ticks.advance(0.2, &mut world);
+    // The second tick sees the edit of the first.
+    assert_eq!(*seen.borrow(), vec![0, 4]);
+    assert_eq!(changed, vec![ChunkCoord::new(0, 0, 0)]);
+    assert_eq!(world.get_voxel(&pos), 4);
+}
@@ -0,0 +1,81 @@
+//! Tonemap curves and the HDR settings of the renderer configuration.
+
+use rendy::hal;
+
+use avenir::color::Color;
+use avenir::config::{ConfigProblem, RendererConfig};
+use avenir::tonemap::{HdrSettings, TonemapCurve};
+
+const CURVES: [TonemapCurve; 2] = [TonemapCurve::Reinhard, TonemapCurve::Aces];
+
+#[test]
+fn curves_map_any_value_below_white() {
+    for &curve in &CURVES {
+        assert_eq!(curve.map(0.0), 0.0);
+        assert_eq!(curve.map(-4.0), 0.0);
+        let mut previous = 0.0;
+        for i in 1..200 {
+            let mapped = curve.map(i as f32 * 0.25);
+            assert!(mapped >= previous && mapped <= 1.0, "{:?} at {}", curve, i);
+            previous = mapped;
+        }
+    }
+    assert_eq!(TonemapCurve::Reinhard.map(1.0), 0.5);
+    assert!(TonemapCurve::Reinhard.map(1000.0) < 1.0);
+    assert_eq!(TonemapCurve::Aces.map(20.0), 1.0);
+}
+
+#[test]
+fn exposure_scales_the_colors_before_the_curve() {
+    let bright = Color::new(4.0, 1.0, 0.25, 0.5);
+    let settings = HdrSettings {
+        exposure: 0.5,
+        curve: TonemapCurve::Reinhard,
+    };
+    let mapped = settings.apply(bright);
+    assert_eq!(mapped, Color::new(2.0 / 3.0, 1.0 / 3.0, 0.125 / 1.125, 0.5));
+
+    // Values the surface would clamp stay apart.
+    let brighter = settings.apply(Color::rgb(8.0, 1.0, 0.25));
+    assert!(brighter.r > mapped.r && brighter.r < 1.0);
+}
+
+#[test]
+fn exposure_must_be_positive() {
+    let limits = hal::Limits {
+        max_image_2d_size: 4096,
+        framebuffer_color_sample_counts: 0b111,
+        ..hal::Limits::default()
+    };
+    let mut config = RendererConfig::default();
+    assert!(config.problems(&limits).is_empty());
+    config.hdr = Some(HdrSettings::default());
+    assert!(config.problems(&limits).is_empty());
+    for &exposure in &[0.0, -1.0, std::f32::INFINITY] {
+        config.hdr = Some(HdrSettings {
+            exposure,
+            ..HdrSettings::default()
+        });
+        assert_eq!(
+            config.problems(&limits),
+            vec![ConfigProblem::InvalidExposure(exposure)]
+        );
+    }
+}
+
+#[test]
+fn hdr_settings_rebuild_the_graph() {
+    let sdr = RendererConfig::default();
+    assert_eq!(sdr.hdr, None);
+    let mut hdr = sdr.clone();
+    hdr.hdr = Some(HdrSettings::default());
+    assert!(hdr.needs_rebuild(&sdr));
+    assert!(sdr.needs_rebuild(&hdr));
+
+    let mut exposed = hdr.clone();
+    exposed.hdr = Some(HdrSettings {
+        exposure: 2.0,
+        ..HdrSettings::default()
+    });
+    assert!(exposed.needs_rebuild(&hdr));
+}
@@ -0,0 +1,62 @@
+//! Environment of the validation layer and levels of its messages.
+
+use avenir::config::VALIDATION_LAYER;
+use avenir::validation::{message_level, with_layer, ValidationLayer};
+
+const LAYERS_VAR: &str = "VK_INSTANCE_LAYERS";
+
+#[cfg(windows)]
+const SEPARATOR: &str = ";";
+
+#[cfg(not(windows))]
+const SEPARATOR: &str = ":";
+
+#[test]
+fn the_layer_is_added_once() {
+    assert_eq!(with_layer("", VALIDATION_LAYER), VALIDATION_LAYER);
+    let other = format!("VK_LAYER_other{}{}", SEPARATOR, VALIDATION_LAYER);
+    assert_eq!(with_layer("VK_LAYER_other", VALIDATION_LAYER), other);
+    assert_eq!(with_layer(&other, VALIDATION_LAYER), other);
+}
+
+// The only test touching the environment, the tests run in parallel.
+#[test]
+fn the_environment_is_restored() {
+    std::env::remove_var(LAYERS_VAR);
+    let layer = ValidationLayer::enable();
+    assert_eq!(std::env::var(LAYERS_VAR).unwrap(), VALIDATION_LAYER);
+    drop(layer);
+    assert_eq!(std::env::var_os(LAYERS_VAR), None);
+
+    std::env::set_var(LAYERS_VAR, "VK_LAYER_other");
+    let layer = ValidationLayer::enable();
+    assert_eq!(
+        std::env::var(LAYERS_VAR).unwrap(),
+        format!("VK_LAYER_other{}{}", SEPARATOR, VALIDATION_LAYER)
+    );
+    // Without an instance the layer wrote nothing to forward.
+    assert!(layer.finish().is_none());
+    assert_eq!(std::env::var(LAYERS_VAR).unwrap(), "VK_LAYER_other");
+    std::env::remove_var(LAYERS_VAR);
+}
+
+#[test]
+fn messages_keep_their_severity() {
+    assert_eq!(
+        message_level("Validation Error: [ VUID-vkCmdDraw-None-02699 ] Object 0"),
+        Some(log::Level::Error)
+    );
+    assert_eq!(
+        message_level("Validation Performance Warning: [ UNASSIGNED-BestPractices ]"),
+        Some(log::Level::Warn)
+    );
+    assert_eq!(
+        message_level("Validation Information: [ Loader Message ]"),
+        Some(log::Level::Info)
+    );
+    assert_eq!(
+        message_level("ERROR : VALIDATION - Message Id Number: 0"),
+        Some(log::Level::Error)
+    );
+    assert_eq!(message_level("    Objects: 1"), None);
+}
@@ -0,0 +1,71 @@
+//! Reveals stamped into the fog of war mask.
+
+use nalgebra::Vector3;
+
+use avenir::coords::{ChunkCoord, Location};
+use avenir::vision::{VisionMask, MAX_VISION_CELLS, UNEXPLORED, VISIBLE};
+
+fn at(x: f32, z: f32) -> Location {
+    Location::new(ChunkCoord::new(0, 0, 0), Vector3::new(x, 0.0, z))
+}
+
+fn mask() -> VisionMask {
+    VisionMask::new(at(0.0, 0.0), 32, 16, 2.0).unwrap()
+}
+
+#[test]
+fn invalid_masks_are_refused() {
+    assert!(VisionMask::new(at(0.0, 0.0), 0, 16, 1.0).is_none());
+    assert!(VisionMask::new(at(0.0, 0.0), MAX_VISION_CELLS + 1, 1, 1.0).is_none());
+    assert!(VisionMask::new(at(0.0, 0.0), 4, 4, 0.0).is_none());
+    assert!(VisionMask::new(at(0.0, 0.0), 4, 4, std::f32::NAN).is_none());
+    assert!(VisionMask::new(at(0.0, 0.0), usize::max_value(), 2, 1.0).is_none());
+}
+
+#[test]
+fn reveal_opens_a_disc_of_cells() {
+    let mut mask = mask();
+    // Center of cell (10, 5), three cells of radius.
+    mask.reveal(&at(21.0, 11.0), 6.0);
+    assert_eq!(mask.get(10, 5), VISIBLE);
+    assert_eq!(mask.get(11, 6), VISIBLE);
+    assert_eq!(mask.get(12, 5), VISIBLE);
+    assert_eq!(mask.get(13, 5), UNEXPLORED);
+    assert_eq!(mask.get(10, 8), UNEXPLORED);
+    assert!(mask.get(12, 7) > UNEXPLORED && mask.get(12, 7) < VISIBLE);
+}
+
+#[test]
+fn reveal_past_the_border_is_clipped() {
+    let mut mask = mask();
+    mask.reveal(&at(1.0, 1.0), 2.0);
+    mask.reveal(&at(1000.0, 1000.0), 50.0);
+    mask.reveal(&at(-1000.0, 10.0), 50.0);
+    assert_eq!(mask.get(0, 0), VISIBLE);
+    assert_eq!(mask.cells().iter().filter(|&&cell| cell > 0).count(), 1);
+}
+
+#[test]
+fn reveal_follows_the_mask_origin() {
+    let origin = Location::new(ChunkCoord::new(-2, 0, 1), Vector3::new(4.0, 0.0, 0.0));
+    let mut mask = VisionMask::new(origin, 8, 8, 1.0).unwrap();
+    let center = Location::new(ChunkCoord::new(-2, 3, 1), Vector3::new(7.5, 0.0, 2.5));
+    mask.reveal(&center, 1.0);
+    assert_eq!(mask.get(3, 2), VISIBLE);
+    assert_eq!(mask.cells().iter().filter(|&&cell| cell == VISIBLE).count(), 1);
+}
+
+#[test]
+fn dim_keeps_the_explored_cells_apart() {
+    let mut mask = mask();
+    mask.reveal(&at(10.0, 10.0), 4.0);
+    mask.dim(100);
+    mask.reveal(&at(40.0, 10.0), 4.0);
+    assert_eq!(mask.get(5, 5), 100);
+    assert_eq!(mask.get(20, 5), VISIBLE);
+    assert_eq!(mask.get(0, 15), UNEXPLORED);
+    mask.set(0, 15, 7);
+    mask.set(99, 99, 7);
+    assert_eq!(mask.get(0, 15), 7);
+    assert_eq!(mask.get(99, 99), UNEXPLORED);
+}
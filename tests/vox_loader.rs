@@ -0,0 +1,67 @@
+//! Checks of the `.vox` loader on a small file with two models.
+
+use std::path::Path;
+
+use avenir::color::Color;
+use avenir::vox_loader::{load_vox, parse_vox, parse_vox_models, VoxError};
+
+fn fixture() -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/two_models.vox")
+}
+
+#[test]
+fn first_model_keeps_its_voxels_and_colors() {
+    let model = load_vox(&fixture()).unwrap();
+    // 4 by 3 by 2 Z up in the file.
+    assert_eq!(model.dimensions(), (4, 2, 3));
+    assert_eq!(model.len(), 5);
+
+    let red = Color::rgb(1.0, 0.0, 0.0);
+    let green = Color::rgb(0.0, 1.0, 0.0);
+    assert_eq!(model.color(1), red);
+    assert_eq!(model.color(2), green);
+    assert_eq!(model.color(3).a, 128.0 / 255.0);
+
+    let voxels: Vec<_> = model.voxels().collect();
+    assert_eq!(voxels.iter().filter(|v| v.3 == red.to_array()).count(), 2);
+    // File voxel (3, 2, 1), its Z up.
+    assert!(voxels.contains(&(3, 1, 0, green.to_array())));
+    assert!(voxels.contains(&(0, 0, 2, red.to_array())));
+}
+
+#[test]
+fn every_model_is_listed() {
+    let data = std::fs::read(fixture()).unwrap();
+    let models = parse_vox_models(&data).unwrap();
+    assert_eq!(models.len(), 2);
+    assert_eq!(models[1].dimensions(), (1, 1, 1));
+
+    // A lone voxel is a cube, 6 quads.
+    let mesh = models[1].mesh();
+    assert_eq!(mesh.vertex_count(), 24);
+    assert_eq!(mesh.indices.len(), 36);
+    assert!(mesh
+        .colors
+        .iter()
+        .all(|&color| color == models[1].color(3).to_array()));
+}
+
+#[test]
+fn malformed_files_are_errors() {
+    assert!(matches!(
+        parse_vox(b"not a vox file"),
+        Err(VoxError::NotVox)
+    ));
+
+    let data = std::fs::read(fixture()).unwrap();
+    assert!(matches!(
+        parse_vox(&data[..data.len() - 10]),
+        Err(VoxError::Truncated)
+    ));
+
+    let mut empty = b"VOX ".to_vec();
+    empty.extend_from_slice(&150u32.to_le_bytes());
+    empty.extend_from_slice(b"MAIN");
+    empty.extend_from_slice(&[0; 8]);
+    assert!(matches!(parse_vox(&empty), Err(VoxError::NoModel)));
+}
@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec4 color;
+
+// Linear colors of the scene, unclamped.
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+// Scale of the colors before the curve.
+layout(constant_id = 0) const float EXPOSURE = 1.0;
+// Curve, see `tonemap::TonemapCurve::id`.
+layout(constant_id = 1) const uint CURVE = 1;
+const uint CURVE_REINHARD = 0;
+
+#include "color.glsl"
+
+vec3 tonemap(vec3 c) {
+    c = max(c, vec3(0.0));
+    if (CURVE == CURVE_REINHARD) {
+        return c / (1.0 + c);
+    }
+    return clamp(c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec3 hdr = texelFetch(scene, ivec2(gl_FragCoord.xy), 0).rgb;
+    color = vec4(encode_output(tonemap(hdr * EXPOSURE)), 1.0);
+}